@@ -0,0 +1,50 @@
+extern crate compiler;
+extern crate test;
+
+use std::io::MemWriter;
+use test::Bencher;
+use compiler::ast::Program;
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::bytecode::compiler::compile;
+
+/*
+  Runs the same program with the tree-walking interpreter and with the
+  bytecode virtual machine. Compare the two with 'cargo bench'; the test
+  vm_is_several_times_faster_than_interpreter in tests/vm.rs checks that the
+  virtual machine takes at most a third of the time of the interpreter.
+*/
+
+static SOURCE: &'static str = "fn fib(n:int) : int {\n if (n < 2) { return n; }\n return fib(n - 1) + fib(n - 2); }\n\
+  fn main() {\n let sum:int = 0;\n for (let i:int = 0; i < 2000; i = i + 1) { sum = sum + i * 3 / 2; }\n\
+  print(sum);\n print(fib(15)); }";
+
+fn checked_program() -> Program {
+  let tokens = tokenize(SOURCE).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  program
+}
+
+#[bench]
+fn interpreter_runs_program(bencher: &mut Bencher) {
+  let program = checked_program();
+  bencher.iter(|| {
+    let mut output = MemWriter::new();
+    compiler::interpreter::run(&program, &mut output).unwrap();
+    output
+  });
+}
+
+#[bench]
+fn vm_runs_program(bencher: &mut Bencher) {
+  let module = compile(&checked_program()).unwrap();
+  bencher.iter(|| {
+    let mut output = MemWriter::new();
+    compiler::vm::execute(&module, &mut output).unwrap();
+    output
+  });
+}
@@ -0,0 +1,283 @@
+use std::fmt;
+use token::SyntaxToken;
+use token::TokenSubType;
+
+/*
+  Abstract syntax tree produced by the parser. Identifiers and text constants
+  are stored as indices to the text table, which is carried along with the
+  program so that later passes can produce readable diagnostics.
+*/
+
+#[derive(Show, Clone, Copy, PartialEq)]
+pub struct Position {
+  pub line: i32,
+  pub pos_at_line: i32,
+}
+
+impl Position {
+  pub fn new(line: i32, pos_at_line: i32) -> Position {
+    Position { line: line, pos_at_line: pos_at_line }
+  }
+
+  pub fn from_token(token: &SyntaxToken) -> Position {
+    Position::new(token.line, token.pos_at_line)
+  }
+}
+
+impl fmt::String for Position {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    write!(formatter, "{}:{}", self.line, self.pos_at_line)
+  }
+}
+
+#[derive(Show, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+  Integer,
+  Float,
+  Double,
+  Boolean,
+  String,
+  Void,
+}
+
+impl Type {
+  // converts VarType subtype into a type. Returns None for non-type subtypes
+  pub fn from_subtype(subtype: TokenSubType) -> Option<Type> {
+    match subtype {
+      TokenSubType::IntegerType => Some(Type::Integer),
+      TokenSubType::FloatType => Some(Type::Float),
+      TokenSubType::DoubleType => Some(Type::Double),
+      TokenSubType::BooleanType => Some(Type::Boolean),
+      TokenSubType::StringType => Some(Type::String),
+      TokenSubType::VoidType => Some(Type::Void),
+      _ => None,
+    }
+  }
+
+  pub fn is_numeric(&self) -> bool {
+    match *self {
+      Type::Integer | Type::Float | Type::Double => true,
+      _ => false,
+    }
+  }
+
+  // int -> float -> double
+  pub fn is_widening_conversion(&self, target: Type) -> bool {
+    match (*self, target) {
+      (Type::Integer, Type::Float) | (Type::Integer, Type::Double) |
+      (Type::Float, Type::Double) => true,
+      _ => false,
+    }
+  }
+}
+
+impl fmt::String for Type {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    fmt::String::fmt(
+      match *self {
+        Type::Integer => "int",
+        Type::Float => "float",
+        Type::Double => "double",
+        Type::Boolean => "bool",
+        Type::String => "string",
+        Type::Void => "void",
+      }, formatter)
+  }
+}
+
+pub struct Program {
+  pub globals: Vec<VariableDeclaration>,
+  pub functions: Vec<Function>,
+  pub text_table: Vec<String>,
+}
+
+impl Program {
+  pub fn get_text(&self, index: usize) -> &str {
+    self.text_table[index].as_slice()
+  }
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct Function {
+  pub name: usize, // index to text table
+  pub parameters: Vec<Parameter>,
+  pub return_type: Type,
+  pub body: Block,
+  pub pos: Position,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct Parameter {
+  pub name: usize, // index to text table
+  pub param_type: Type,
+  pub pos: Position,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct Block {
+  pub statements: Vec<Statement>,
+  pub pos: Position,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub enum Statement {
+  Block(Block),
+  VariableDeclaration(VariableDeclaration),
+  Assignment(Assignment),
+  FunctionCall(FunctionCall),
+  For(ForLoop),
+  If(IfStatement),
+  Return(ReturnStatement),
+  Empty(Position),
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct VariableDeclaration {
+  pub name: usize, // index to text table
+  pub var_type: Type,
+  pub initializer: Expression,
+  // only top level declarations may be constants
+  pub is_constant: bool,
+  pub pos: Position,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct Assignment {
+  pub name: usize, // index to text table
+  pub value: Expression,
+  pub pos: Position,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct FunctionCall {
+  pub name: usize, // index to text table
+  pub arguments: Vec<Expression>,
+  pub pos: Position,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct ForLoop {
+  // either variable declaration or assignment
+  pub init: Option<Box<Statement>>,
+  pub condition: Option<Expression>,
+  pub update: Option<Assignment>,
+  pub body: Block,
+  pub pos: Position,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct IfStatement {
+  pub condition: Expression,
+  pub block: Block,
+  pub else_ifs: Vec<ElseIf>,
+  pub else_block: Option<Block>,
+  pub pos: Position,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct ElseIf {
+  pub condition: Expression,
+  pub block: Block,
+  pub pos: Position,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct ReturnStatement {
+  pub value: Option<Expression>,
+  pub pos: Position,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct Expression {
+  pub kind: ExpressionKind,
+  // filled in by the type checker
+  pub expr_type: Option<Type>,
+  pub pos: Position,
+}
+
+impl Expression {
+  pub fn new(kind: ExpressionKind, pos: Position) -> Expression {
+    Expression { kind: kind, expr_type: None, pos: pos }
+  }
+
+  pub fn get_type(&self) -> Type {
+    match self.expr_type {
+      Some(expr_type) => expr_type,
+      None => panic!("Internal compiler error: expression type requested before type checking"),
+    }
+  }
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub enum ExpressionKind {
+  Literal(Literal),
+  Variable(usize), // index to text table
+  Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+  Call(FunctionCall),
+  // explicit 'as' cast or implicit widening conversion inserted by the type checker
+  Cast(Box<Expression>, Type),
+}
+
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Literal {
+  Integer(i32),
+  Float(f32),
+  Double(f64),
+  Boolean(bool),
+  Text(usize), // index to text table
+}
+
+#[derive(Show, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+  Plus,
+  Minus,
+  Multiply,
+  Divide,
+  Equals,
+  Lesser,
+  Greater,
+  LesserOrEq,
+  GreaterOrEq,
+}
+
+impl BinaryOperator {
+  pub fn from_subtype(subtype: TokenSubType) -> Option<BinaryOperator> {
+    match subtype {
+      TokenSubType::Plus => Some(BinaryOperator::Plus),
+      TokenSubType::Minus => Some(BinaryOperator::Minus),
+      TokenSubType::Multiply => Some(BinaryOperator::Multiply),
+      TokenSubType::Divide => Some(BinaryOperator::Divide),
+      TokenSubType::Equals => Some(BinaryOperator::Equals),
+      TokenSubType::Lesser => Some(BinaryOperator::Lesser),
+      TokenSubType::Greater => Some(BinaryOperator::Greater),
+      TokenSubType::LesserOrEq => Some(BinaryOperator::LesserOrEq),
+      TokenSubType::GreaterOrEq => Some(BinaryOperator::GreaterOrEq),
+      _ => None,
+    }
+  }
+
+  pub fn is_comparison(&self) -> bool {
+    match *self {
+      BinaryOperator::Equals | BinaryOperator::Lesser | BinaryOperator::Greater |
+      BinaryOperator::LesserOrEq | BinaryOperator::GreaterOrEq => true,
+      _ => false,
+    }
+  }
+}
+
+impl fmt::String for BinaryOperator {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    fmt::String::fmt(
+      match *self {
+        BinaryOperator::Plus => "+",
+        BinaryOperator::Minus => "-",
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::Divide => "/",
+        BinaryOperator::Equals => "==",
+        BinaryOperator::Lesser => "<",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::LesserOrEq => "<=",
+        BinaryOperator::GreaterOrEq => ">=",
+      }, formatter)
+  }
+}
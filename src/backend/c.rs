@@ -0,0 +1,436 @@
+use std::collections::HashSet;
+use ast::Program;
+use ast::Function;
+use ast::Block;
+use ast::Statement;
+use ast::Assignment;
+use ast::FunctionCall;
+use ast::Expression;
+use ast::ExpressionKind;
+use ast::Literal;
+use ast::BinaryOperator;
+use ast::Type;
+use symbol_table::SymbolTable;
+use builtins;
+use backend::string_literal;
+
+/*
+  C backend. Translates a resolved and type checked program into a single C99
+  translation unit, preceded by a small runtime for strings and printing.
+
+  Names are prefixed to avoid clashes with C keywords and the runtime: fn_ for
+  functions, g_ for globals and v_ for locals. Every local declaration gets a
+  unique name, as C makes a variable visible in its own initializer.
+
+  Integer arithmetic wraps around and integer division by zero terminates the
+  program with the same runtime error as the interpreter. Strings are never
+  freed.
+*/
+
+pub fn generate(program: &Program) -> String {
+  let mut generator = Generator::new(program);
+  generator.generate()
+}
+
+static RUNTIME: &'static str = "#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef const char *rt_string;
+
+static int32_t rt_iadd(int32_t a, int32_t b) { return (int32_t)((uint32_t)a + (uint32_t)b); }
+static int32_t rt_isub(int32_t a, int32_t b) { return (int32_t)((uint32_t)a - (uint32_t)b); }
+static int32_t rt_imul(int32_t a, int32_t b) { return (int32_t)((uint32_t)a * (uint32_t)b); }
+
+static int32_t rt_idiv(int32_t a, int32_t b, int line, int pos) {
+  if (b == 0) {
+    printf(\"Runtime error at %d:%d: Division by zero\\n\", line, pos);
+    exit(1);
+  }
+  if (a == INT32_MIN && b == -1) {
+    return INT32_MIN;
+  }
+  return a / b;
+}
+
+static rt_string rt_concat(rt_string a, rt_string b) {
+  size_t a_length = strlen(a);
+  size_t b_length = strlen(b);
+  char *result = malloc(a_length + b_length + 1);
+  if (result == NULL) {
+    printf(\"Runtime error: Out of memory\\n\");
+    exit(1);
+  }
+  memcpy(result, a, a_length);
+  memcpy(result + a_length, b, b_length + 1);
+  return result;
+}
+
+static bool rt_string_eq(rt_string a, rt_string b) { return strcmp(a, b) == 0; }
+
+static void rt_print_int(int32_t value) { printf(\"%d\\n\", (int)value); }
+static void rt_print_bool(bool value) { printf(\"%s\\n\", value ? \"true\" : \"false\"); }
+static void rt_print_string(rt_string value) { printf(\"%s\\n\", value); }
+
+/* shortest representation that reads back as the same value */
+static void rt_print_double(double value) {
+  char buffer[32];
+  int precision;
+  for (precision = 1; precision < 17; precision++) {
+    snprintf(buffer, sizeof buffer, \"%.*g\", precision, value);
+    if (strtod(buffer, NULL) == value) {
+      break;
+    }
+  }
+  snprintf(buffer, sizeof buffer, \"%.*g\", precision, value);
+  printf(\"%s\\n\", buffer);
+}
+
+static void rt_print_float(float value) {
+  char buffer[32];
+  int precision;
+  for (precision = 1; precision < 9; precision++) {
+    snprintf(buffer, sizeof buffer, \"%.*g\", precision, value);
+    if (strtof(buffer, NULL) == value) {
+      break;
+    }
+  }
+  snprintf(buffer, sizeof buffer, \"%.*g\", precision, value);
+  printf(\"%s\\n\", buffer);
+}
+";
+
+struct Generator<'a> {
+  program: &'a Program,
+  functions: HashSet<usize>,
+  // source name -> C name of the visible locals
+  locals: SymbolTable<String>,
+  local_count: usize,
+  output: String,
+  indent: usize,
+}
+
+impl<'a> Generator<'a> {
+  fn new(program: &'a Program) -> Generator<'a> {
+    Generator {
+      program: program,
+      functions: HashSet::new(),
+      locals: SymbolTable::new(),
+      local_count: 0,
+      output: String::new(),
+      indent: 0,
+    }
+  }
+
+  fn generate(&mut self) -> String {
+    let program = self.program;
+    self.output.push_str(RUNTIME);
+    self.output.push('\n');
+
+    let mut functions = vec![];
+    for function in program.functions.iter() {
+      if self.functions.insert(function.name) {
+        functions.push(function);
+      }
+    }
+
+    let mut declared_globals = HashSet::new();
+    for global in program.globals.iter() {
+      if declared_globals.insert(global.name) {
+        let line = format!("static {} g_{};", c_type(global.var_type), program.get_text(global.name));
+        self.line(line.as_slice());
+      }
+    }
+    self.output.push('\n');
+
+    for function in functions.iter() {
+      let line = format!("{};", self.prototype(*function));
+      self.line(line.as_slice());
+    }
+    self.output.push('\n');
+
+    self.line("static void rt_init_globals(void) {");
+    self.indent += 1;
+    for global in program.globals.iter() {
+      let line = format!("g_{} = {};", program.get_text(global.name),
+        self.expression(&global.initializer));
+      self.line(line.as_slice());
+    }
+    self.indent -= 1;
+    self.line("}");
+
+    for function in functions.iter() {
+      self.output.push('\n');
+      self.function(*function);
+    }
+
+    let has_main = functions.iter().any(|function| program.get_text(function.name) == "main");
+    if has_main {
+      self.output.push('\n');
+      self.line("int main(void) {");
+      self.indent += 1;
+      self.line("rt_init_globals();");
+      self.line("fn_main();");
+      self.line("return 0;");
+      self.indent -= 1;
+      self.line("}");
+    }
+
+    self.output.clone()
+  }
+
+  // declaration without parameter names, so functions can be called before their definition
+  fn prototype(&self, function: &Function) -> String {
+    let parameters: Vec<&str> = function.parameters.iter().map(|p| c_type(p.param_type)).collect();
+    let parameters = if parameters.is_empty() {
+      "void".to_string()
+    } else {
+      parameters.connect(", ")
+    };
+
+    format!("static {} fn_{}({})", c_type(function.return_type),
+      self.program.get_text(function.name), parameters)
+  }
+
+  // declares the parameters as locals
+  fn signature(&mut self, function: &Function) -> String {
+    let mut parameters = vec![];
+    for parameter in function.parameters.iter() {
+      let name = self.declare_local(parameter.name);
+      parameters.push(format!("{} {}", c_type(parameter.param_type), name));
+    }
+
+    let parameters = if parameters.is_empty() {
+      "void".to_string()
+    } else {
+      parameters.connect(", ")
+    };
+
+    format!("static {} fn_{}({})", c_type(function.return_type),
+      self.program.get_text(function.name), parameters)
+  }
+
+  fn function(&mut self, function: &Function) {
+    // parameters and the top level of the body share the scope
+    self.locals = SymbolTable::new();
+    self.local_count = 0;
+    self.locals.push_scope();
+
+    let line = format!("{} {{", self.signature(function));
+    self.line(line.as_slice());
+    self.indent += 1;
+    for statement in function.body.statements.iter() {
+      self.statement(statement);
+    }
+    self.indent -= 1;
+    self.line("}");
+    self.locals.pop_scope();
+  }
+
+  fn block(&mut self, block: &Block) {
+    self.locals.push_scope();
+    self.indent += 1;
+    for statement in block.statements.iter() {
+      self.statement(statement);
+    }
+    self.indent -= 1;
+    self.locals.pop_scope();
+  }
+
+  fn statement(&mut self, statement: &Statement) {
+    match *statement {
+      Statement::Block(ref block) => {
+        self.line("{");
+        self.block(block);
+        self.line("}");
+      },
+      Statement::VariableDeclaration(ref declaration) => {
+        // variable is not visible in its own initializer
+        let initializer = self.expression(&declaration.initializer);
+        let name = self.declare_local(declaration.name);
+        let line = format!("{} {} = {};", c_type(declaration.var_type), name, initializer);
+        self.line(line.as_slice());
+      },
+      Statement::Assignment(ref assignment) => {
+        let line = format!("{};", self.assignment(assignment));
+        self.line(line.as_slice());
+      },
+      Statement::FunctionCall(ref call) => {
+        let line = format!("{};", self.call(call));
+        self.line(line.as_slice());
+      },
+      Statement::For(ref for_loop) => {
+        // variable declared in the init clause is only visible inside the loop
+        self.line("{");
+        self.indent += 1;
+        self.locals.push_scope();
+        match for_loop.init {
+          Some(ref init) => self.statement(&**init),
+          None => { },
+        }
+
+        let condition = match for_loop.condition {
+          Some(ref condition) => self.expression(condition),
+          None => String::new(),
+        };
+        let update = match for_loop.update {
+          Some(ref update) => self.assignment(update),
+          None => String::new(),
+        };
+
+        let line = format!("for (; {}; {}) {{", condition, update);
+        self.line(line.as_slice());
+        self.block(&for_loop.body);
+        self.line("}");
+        self.locals.pop_scope();
+        self.indent -= 1;
+        self.line("}");
+      },
+      Statement::If(ref if_statement) => {
+        let line = format!("if ({}) {{", self.expression(&if_statement.condition));
+        self.line(line.as_slice());
+        self.block(&if_statement.block);
+
+        for else_if in if_statement.else_ifs.iter() {
+          let line = format!("}} else if ({}) {{", self.expression(&else_if.condition));
+          self.line(line.as_slice());
+          self.block(&else_if.block);
+        }
+
+        match if_statement.else_block {
+          Some(ref block) => {
+            self.line("} else {");
+            self.block(block);
+          },
+          None => { },
+        }
+        self.line("}");
+      },
+      Statement::Return(ref return_statement) => {
+        match return_statement.value {
+          Some(ref value) => {
+            let line = format!("return {};", self.expression(value));
+            self.line(line.as_slice());
+          },
+          None => self.line("return;"),
+        }
+      },
+      Statement::Empty(..) => self.line(";"),
+    }
+  }
+
+  fn assignment(&mut self, assignment: &Assignment) -> String {
+    let value = self.expression(&assignment.value);
+    format!("{} = {}", self.variable(assignment.name), value)
+  }
+
+  fn call(&mut self, call: &FunctionCall) -> String {
+    let mut arguments = vec![];
+    for argument in call.arguments.iter() {
+      arguments.push(self.expression(argument));
+    }
+
+    let program = self.program;
+    let name = program.get_text(call.name);
+    if self.functions.contains(&call.name) {
+      format!("fn_{}({})", name, arguments.connect(", "))
+    } else if name == builtins::PRINT {
+      let print = match call.arguments[0].get_type() {
+        Type::Integer => "rt_print_int",
+        Type::Float => "rt_print_float",
+        Type::Double => "rt_print_double",
+        Type::Boolean => "rt_print_bool",
+        Type::String => "rt_print_string",
+        Type::Void => panic!("Internal compiler error: void value printed"),
+      };
+      format!("{}({})", print, arguments[0])
+    } else {
+      panic!("Internal compiler error: call to unknown function '{}'", name);
+    }
+  }
+
+  fn expression(&mut self, expression: &Expression) -> String {
+    match expression.kind {
+      ExpressionKind::Literal(literal) => self.literal(literal),
+      ExpressionKind::Variable(name) => self.variable(name),
+      ExpressionKind::Binary(operator, ref left, ref right) => {
+        let operand_type = left.get_type();
+        let left = self.expression(&**left);
+        let right = self.expression(&**right);
+        match (operand_type, operator) {
+          (Type::Integer, BinaryOperator::Plus) => format!("rt_iadd({}, {})", left, right),
+          (Type::Integer, BinaryOperator::Minus) => format!("rt_isub({}, {})", left, right),
+          (Type::Integer, BinaryOperator::Multiply) => format!("rt_imul({}, {})", left, right),
+          (Type::Integer, BinaryOperator::Divide) => format!("rt_idiv({}, {}, {}, {})",
+            left, right, expression.pos.line, expression.pos.pos_at_line),
+          (Type::String, BinaryOperator::Plus) => format!("rt_concat({}, {})", left, right),
+          (Type::String, BinaryOperator::Equals) => format!("rt_string_eq({}, {})", left, right),
+          _ => format!("({} {} {})", left, operator, right),
+        }
+      },
+      ExpressionKind::Call(ref call) => self.call(call),
+      ExpressionKind::Cast(ref inner, target) => {
+        format!("(({}){})", c_type(target), self.expression(&**inner))
+      },
+    }
+  }
+
+  fn literal(&self, literal: Literal) -> String {
+    match literal {
+      Literal::Integer(value) => {
+        if value == -2147483647 - 1 {
+          "INT32_MIN".to_string()
+        } else {
+          format!("INT32_C({})", value)
+        }
+      },
+      Literal::Float(value) => format!("{}f", float_literal(format!("{}", value))),
+      Literal::Double(value) => float_literal(format!("{}", value)),
+      Literal::Boolean(value) => format!("{}", value),
+      Literal::Text(index) => string_literal(self.program.get_text(index)),
+    }
+  }
+
+  fn variable(&self, name: usize) -> String {
+    match self.locals.lookup(name) {
+      Some(local) => local,
+      None => format!("g_{}", self.program.get_text(name)),
+    }
+  }
+
+  fn declare_local(&mut self, name: usize) -> String {
+    let local = format!("v_{}_{}", self.program.get_text(name), self.local_count);
+    self.local_count += 1;
+    self.locals.declare(name, local.clone());
+    local
+  }
+
+  fn line(&mut self, text: &str) {
+    for _ in range(0, self.indent) {
+      self.output.push_str("  ");
+    }
+    self.output.push_str(text);
+    self.output.push('\n');
+  }
+}
+
+fn c_type(var_type: Type) -> &'static str {
+  match var_type {
+    Type::Integer => "int32_t",
+    Type::Float => "float",
+    Type::Double => "double",
+    Type::Boolean => "bool",
+    Type::String => "rt_string",
+    Type::Void => "void",
+  }
+}
+
+// C requires a decimal point or an exponent in floating point literals
+fn float_literal(mut text: String) -> String {
+  if !text.contains(".") && !text.contains("e") {
+    text.push_str(".0");
+  }
+  text
+}
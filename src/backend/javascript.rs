@@ -0,0 +1,647 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use ast::Program;
+use ast::Function;
+use ast::Block;
+use ast::Statement;
+use ast::Assignment;
+use ast::FunctionCall;
+use ast::Expression;
+use ast::ExpressionKind;
+use ast::Literal;
+use ast::BinaryOperator;
+use ast::ElseIf;
+use ast::Type;
+use symbol_table::SymbolTable;
+use builtins;
+
+/*
+  JavaScript backend. Translates a resolved and type checked program into a
+  JavaScript source file that runs in browsers and node.
+
+  The output keeps the line structure of the source: every construct is
+  written on the line it starts on in the source, with that line's
+  indentation, and the comments of the source are copied to the same lines.
+  As the parser drops comments and closing braces, these are recovered from
+  the source text. The runtime and the call to main follow the translated
+  program.
+
+  Names are kept, except for JavaScript keywords and the globals used by the
+  runtime, which get a '$' appended. A local declared while a variable or
+  function of the same name is visible is renamed to name$N, because a
+  JavaScript variable can not be read in its own initializer. Runtime names
+  start with '$', which is never part of a source identifier.
+
+  int arithmetic wraps around with '| 0' and Math.imul, integer division
+  truncates with Math.trunc and float arithmetic is rounded with Math.fround,
+  so results and printed values match the native backends. Runtime errors are
+  printed and end the program in node, and are thrown as exceptions
+  elsewhere.
+*/
+
+pub fn generate(program: &Program, source: &str) -> String {
+  let mut generator = Generator::new(program, Layout::new(source));
+  generator.generate()
+}
+
+static RUNTIME: &'static str = "// runtime
+
+function $print(value) {
+  console.log(String(value));
+}
+
+function $print_double(value) {
+  console.log($format(value, 17, function (value) { return value; }));
+}
+
+function $print_float(value) {
+  console.log($format(value, 9, Math.fround));
+}
+
+// printf(\"%.*g\") with the smallest precision that reads back as the same value
+function $format(value, max_precision, round) {
+  if (value !== value) {
+    var view = new DataView(new ArrayBuffer(8));
+    view.setFloat64(0, value);
+    return view.getUint8(0) & 0x80 ? \"-nan\" : \"nan\";
+  }
+  if (value === Infinity || value === -Infinity) {
+    return value < 0 ? \"-inf\" : \"inf\";
+  }
+  if (value === 0) {
+    return 1 / value < 0 ? \"-0\" : \"0\";
+  }
+
+  var precision = 1;
+  while (precision < max_precision && round(Number(value.toPrecision(precision))) !== value) {
+    precision++;
+  }
+
+  var parts = value.toExponential(precision - 1).split(\"e\");
+  var exponent = Number(parts[1]);
+  if (exponent < -4 || exponent >= precision) {
+    var digits = String(Math.abs(exponent));
+    return $strip_zeros(parts[0]) + \"e\" + (exponent < 0 ? \"-\" : \"+\") + (digits.length < 2 ? \"0\" : \"\") + digits;
+  }
+  return $strip_zeros(value.toFixed(precision - 1 - exponent));
+}
+
+function $strip_zeros(text) {
+  return text.indexOf(\".\") < 0 ? text : text.replace(/\\.?0+$/, \"\");
+}
+
+function $idiv(a, b, line, pos) {
+  if (b === 0) {
+    $runtime_error(\"Runtime error at \" + line + \":\" + pos + \": Division by zero\");
+  }
+  // -2147483648 / -1 wraps around to -2147483648
+  return Math.trunc(a / b) | 0;
+}
+
+// truncation towards zero, NaN and values out of range become -2147483648
+function $to_int(value) {
+  return value > -2147483649 && value < 2147483648 ? value | 0 : -2147483648;
+}
+
+function $runtime_error(message) {
+  console.log(message);
+  if (typeof process !== \"undefined\") {
+    process.exit(1);
+  }
+  throw new Error(message);
+}
+";
+
+// renamed when used as identifiers
+static RESERVED_NAMES: [&'static str; 53] = [
+  "arguments", "await", "break", "case", "catch", "class", "const", "continue", "debugger", "default",
+  "delete", "do", "else", "enum", "eval", "export", "extends", "false", "finally", "for", "function",
+  "if", "implements", "import", "in", "instanceof", "interface", "let", "new", "null", "package",
+  "private", "protected", "public", "return", "static", "super", "switch", "this", "throw", "true",
+  "try", "typeof", "var", "void", "while", "with", "yield",
+  // used by the runtime
+  "console", "Math", "Number", "String", "process",
+];
+
+// JavaScript operator precedences, atoms bind tightest
+static EQUALITY: u32 = 10;
+static RELATIONAL: u32 = 11;
+static ADDITIVE: u32 = 13;
+static MULTIPLICATIVE: u32 = 14;
+static ATOM: u32 = 20;
+
+// comments, indentation and closing braces of the source, indexed by line
+struct Layout {
+  line_count: i32,
+  indentation: Vec<String>,
+  comments: Vec<Option<String>>,
+  // position of an opening brace -> line of its closing brace
+  closing_braces: HashMap<(i32, i32), i32>,
+}
+
+impl Layout {
+  // positions are counted as in the lexer: lines and columns start at 1 and a
+  // tab advances the column by 4
+  fn new(source: &str) -> Layout {
+    let mut layout = Layout {
+      line_count: 1,
+      indentation: vec![],
+      comments: vec![],
+      closing_braces: HashMap::new(),
+    };
+
+    for line in source.split('\n') {
+      let indentation: String = line.chars().take_while(|ch| *ch == ' ' || *ch == '\t').collect();
+      layout.indentation.push(indentation);
+      layout.comments.push(None);
+    }
+    layout.line_count = layout.indentation.len() as i32;
+    // no line follows the final newline
+    if source.ends_with("\n") {
+      layout.line_count -= 1;
+    }
+
+    let mut open_braces = vec![];
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut comment: Option<String> = None;
+    let mut line = 1;
+    let mut pos = 1;
+    let mut chars = source.chars().peekable();
+    while let Some(ch) = chars.next() {
+      if ch == '\n' {
+        match comment.take() {
+          Some(text) => layout.comments[line as usize - 1] = Some(text.as_slice().trim_right().to_string()),
+          None => { },
+        }
+        line += 1;
+        pos = 1;
+        continue;
+      }
+
+      match comment {
+        Some(ref mut text) => text.push(ch),
+        None => {
+          if in_string {
+            if escaped {
+              escaped = false;
+            } else if ch == '\\' {
+              escaped = true;
+            } else if ch == '"' {
+              in_string = false;
+            }
+          } else if ch == '"' {
+            in_string = true;
+          } else if ch == '/' && chars.peek() == Some(&'/') {
+            comment = Some("/".to_string());
+          } else if ch == '{' {
+            open_braces.push((line, pos));
+          } else if ch == '}' {
+            match open_braces.pop() {
+              Some(open) => { layout.closing_braces.insert(open, line); },
+              None => { },
+            }
+          }
+        },
+      }
+      pos += if ch == '\t' { 4 } else { 1 };
+    }
+
+    match comment {
+      Some(text) => layout.comments[line as usize - 1] = Some(text.as_slice().trim_right().to_string()),
+      None => { },
+    }
+    layout
+  }
+}
+
+struct Generator<'a> {
+  program: &'a Program,
+  layout: Layout,
+  functions: HashMap<usize, String>,
+  globals: HashMap<usize, String>,
+  declared_globals: HashSet<usize>,
+  // source name -> JavaScript name of the visible locals
+  locals: SymbolTable<String>,
+  // names declared in the current function
+  used_names: HashSet<String>,
+  output: String,
+  // source line the output is on
+  line: i32,
+  line_started: bool,
+}
+
+impl<'a> Generator<'a> {
+  fn new(program: &'a Program, layout: Layout) -> Generator<'a> {
+    Generator {
+      program: program,
+      layout: layout,
+      functions: HashMap::new(),
+      globals: HashMap::new(),
+      declared_globals: HashSet::new(),
+      locals: SymbolTable::new(),
+      used_names: HashSet::new(),
+      output: String::new(),
+      line: 1,
+      line_started: false,
+    }
+  }
+
+  fn generate(&mut self) -> String {
+    let program = self.program;
+    for function in program.functions.iter() {
+      if !self.functions.contains_key(&function.name) {
+        self.functions.insert(function.name, identifier(program.get_text(function.name)));
+      }
+    }
+
+    // a global sharing its name with a function must not replace the function
+    for global in program.globals.iter() {
+      let mut name = identifier(program.get_text(global.name));
+      if self.functions.contains_key(&global.name) {
+        name.push_str("$global");
+      }
+      self.globals.insert(global.name, name);
+    }
+
+    // globals and functions are written in source order
+    let mut declared_functions = HashSet::new();
+    let mut global_index = 0;
+    for function in program.functions.iter() {
+      while global_index < program.globals.len() && program.globals[global_index].pos.line <= function.pos.line {
+        self.global(global_index);
+        global_index += 1;
+      }
+      if declared_functions.insert(function.name) {
+        self.function(function);
+      }
+    }
+    while global_index < program.globals.len() {
+      self.global(global_index);
+      global_index += 1;
+    }
+
+    let last_line = self.layout.line_count;
+    self.advance_to(last_line);
+    self.end_line();
+
+    self.output.push('\n');
+    self.output.push_str(RUNTIME);
+    match program.functions.iter().find(|function| program.get_text(function.name) == "main") {
+      Some(main) => {
+        let call = format!("\n{}();\n", self.function_name(main.name));
+        self.output.push_str(call.as_slice());
+      },
+      None => { },
+    }
+    self.output.clone()
+  }
+
+  fn global(&mut self, index: usize) {
+    let program = self.program;
+    let global = &program.globals[index];
+    if !self.declared_globals.insert(global.name) {
+      return;
+    }
+    let (initializer, _) = self.expression(&global.initializer);
+    let keyword = if global.is_constant { "const" } else { "let" };
+    let text = format!("{} {} = {};", keyword, self.variable(global.name), initializer);
+    self.write(global.pos.line, text.as_slice());
+  }
+
+  fn function(&mut self, function: &Function) {
+    // parameters and the top level of the body share the scope
+    self.locals = SymbolTable::new();
+    self.used_names = HashSet::new();
+    self.locals.push_scope();
+
+    let mut parameters = vec![];
+    for parameter in function.parameters.iter() {
+      parameters.push(self.declare_local(parameter.name));
+    }
+
+    let header = format!("function {}({})", self.function_name(function.name), parameters.connect(", "));
+    self.write(function.pos.line, header.as_slice());
+    self.write(function.body.pos.line, "{");
+    for statement in function.body.statements.iter() {
+      self.statement(statement);
+    }
+    self.close_block(&function.body);
+    self.locals.pop_scope();
+  }
+
+  // writes the braces at the lines they have in the source
+  fn block(&mut self, block: &Block) {
+    self.write(block.pos.line, "{");
+    self.locals.push_scope();
+    for statement in block.statements.iter() {
+      self.statement(statement);
+    }
+    self.locals.pop_scope();
+    self.close_block(block);
+  }
+
+  fn close_block(&mut self, block: &Block) {
+    let line = match self.layout.closing_braces.get(&(block.pos.line, block.pos.pos_at_line)) {
+      Some(line) => *line,
+      None => self.line,
+    };
+    self.write(line, "}");
+  }
+
+  fn statement(&mut self, statement: &Statement) {
+    match *statement {
+      Statement::Block(ref block) => self.block(block),
+      Statement::VariableDeclaration(ref declaration) => {
+        // variable is not visible in its own initializer
+        let (initializer, _) = self.expression(&declaration.initializer);
+        let name = self.declare_local(declaration.name);
+        let text = format!("let {} = {};", name, initializer);
+        self.write(declaration.pos.line, text.as_slice());
+      },
+      Statement::Assignment(ref assignment) => {
+        let text = format!("{};", self.assignment(assignment));
+        self.write(assignment.pos.line, text.as_slice());
+      },
+      Statement::FunctionCall(ref call) => {
+        let text = format!("{};", self.call(call));
+        self.write(call.pos.line, text.as_slice());
+      },
+      Statement::For(ref for_loop) => {
+        // variable declared in the init clause is only visible inside the loop
+        self.locals.push_scope();
+        let init = match for_loop.init {
+          Some(ref init) => match **init {
+            Statement::VariableDeclaration(ref declaration) => {
+              let (initializer, _) = self.expression(&declaration.initializer);
+              format!("let {} = {}", self.declare_local(declaration.name), initializer)
+            },
+            Statement::Assignment(ref assignment) => self.assignment(assignment),
+            _ => panic!("Internal compiler error: invalid for loop initialization"),
+          },
+          None => String::new(),
+        };
+        let condition = match for_loop.condition {
+          Some(ref condition) => format!(" {}", self.expression_text(condition)),
+          None => String::new(),
+        };
+        let update = match for_loop.update {
+          Some(ref update) => format!(" {}", self.assignment(update)),
+          None => String::new(),
+        };
+
+        let text = format!("for ({};{};{})", init, condition, update);
+        self.write(for_loop.pos.line, text.as_slice());
+        self.block(&for_loop.body);
+        self.locals.pop_scope();
+      },
+      Statement::If(ref if_statement) => {
+        let text = format!("if ({})", self.expression_text(&if_statement.condition));
+        self.write(if_statement.pos.line, text.as_slice());
+        self.block(&if_statement.block);
+        for else_if in if_statement.else_ifs.iter() {
+          self.else_if(else_if);
+        }
+
+        match if_statement.else_block {
+          Some(ref block) => {
+            // the else keyword has no position of its own
+            self.write(block.pos.line, "else");
+            self.block(block);
+          },
+          None => { },
+        }
+      },
+      Statement::Return(ref return_statement) => {
+        let text = match return_statement.value {
+          Some(ref value) => format!("return {};", self.expression_text(value)),
+          None => "return;".to_string(),
+        };
+        self.write(return_statement.pos.line, text.as_slice());
+      },
+      Statement::Empty(pos) => self.write(pos.line, ";"),
+    }
+  }
+
+  fn else_if(&mut self, else_if: &ElseIf) {
+    let text = format!("else if ({})", self.expression_text(&else_if.condition));
+    self.write(else_if.pos.line, text.as_slice());
+    self.block(&else_if.block);
+  }
+
+  fn assignment(&mut self, assignment: &Assignment) -> String {
+    let value = self.expression_text(&assignment.value);
+    format!("{} = {}", self.variable(assignment.name), value)
+  }
+
+  fn call(&mut self, call: &FunctionCall) -> String {
+    let mut arguments = vec![];
+    for argument in call.arguments.iter() {
+      arguments.push(self.expression_text(argument));
+    }
+
+    let program = self.program;
+    let name = program.get_text(call.name);
+    if self.functions.contains_key(&call.name) {
+      format!("{}({})", self.function_name(call.name), arguments.connect(", "))
+    } else if name == builtins::PRINT {
+      let print = match call.arguments[0].get_type() {
+        Type::Integer | Type::Boolean | Type::String => "$print",
+        Type::Float => "$print_float",
+        Type::Double => "$print_double",
+        Type::Void => panic!("Internal compiler error: void value printed"),
+      };
+      format!("{}({})", print, arguments[0])
+    } else {
+      panic!("Internal compiler error: call to unknown function '{}'", name);
+    }
+  }
+
+  fn expression_text(&mut self, expression: &Expression) -> String {
+    let (text, _) = self.expression(expression);
+    text
+  }
+
+  // returns the JavaScript expression and its precedence
+  fn expression(&mut self, expression: &Expression) -> (String, u32) {
+    match expression.kind {
+      ExpressionKind::Literal(literal) => (self.literal(literal), ATOM),
+      ExpressionKind::Variable(name) => (self.variable(name), ATOM),
+      ExpressionKind::Binary(operator, ref left, ref right) => {
+        let operand_type = left.get_type();
+        let precedence = match operator {
+          BinaryOperator::Plus | BinaryOperator::Minus => ADDITIVE,
+          BinaryOperator::Multiply | BinaryOperator::Divide => MULTIPLICATIVE,
+          BinaryOperator::Equals => EQUALITY,
+          _ => RELATIONAL,
+        };
+        let (left, left_precedence) = self.expression(&**left);
+        let (right, right_precedence) = self.expression(&**right);
+        // operators are left associative
+        let left = parenthesize(left, left_precedence < precedence);
+        let right = parenthesize(right, right_precedence <= precedence);
+        let operator_text = match operator {
+          BinaryOperator::Equals => "===".to_string(),
+          _ => format!("{}", operator),
+        };
+        let text = format!("{} {} {}", left, operator_text, right);
+
+        match (operand_type, operator) {
+          (Type::Integer, BinaryOperator::Plus) | (Type::Integer, BinaryOperator::Minus) => {
+            (format!("({} | 0)", text), ATOM)
+          },
+          (Type::Integer, BinaryOperator::Multiply) => {
+            (format!("Math.imul({}, {})", left, right), ATOM)
+          },
+          (Type::Integer, BinaryOperator::Divide) => {
+            (format!("$idiv({}, {}, {}, {})", left, right, expression.pos.line, expression.pos.pos_at_line), ATOM)
+          },
+          (Type::Float, _) if !operator.is_comparison() => (format!("Math.fround({})", text), ATOM),
+          _ => (text, precedence),
+        }
+      },
+      ExpressionKind::Call(ref call) => (self.call(call), ATOM),
+      ExpressionKind::Cast(ref inner, target) => {
+        let (value, precedence) = self.expression(&**inner);
+        match (inner.get_type(), target) {
+          (Type::Integer, Type::Float) | (Type::Double, Type::Float) => (format!("Math.fround({})", value), ATOM),
+          (Type::Float, Type::Integer) | (Type::Double, Type::Integer) => (format!("$to_int({})", value), ATOM),
+          // every int and float is exactly a double
+          (Type::Integer, Type::Double) | (Type::Float, Type::Double) => (value, precedence),
+          (from, to) => {
+            if from != to {
+              panic!("Internal compiler error: cast from {} to {}", from, to);
+            }
+            (value, precedence)
+          },
+        }
+      },
+    }
+  }
+
+  fn literal(&self, literal: Literal) -> String {
+    match literal {
+      Literal::Integer(value) => format!("{}", value),
+      Literal::Float(value) => {
+        // the shortest text of a float is usually not exactly a double
+        let text = format!("{}", value);
+        if text == format!("{}", value as f64) {
+          text
+        } else {
+          format!("Math.fround({})", text)
+        }
+      },
+      Literal::Double(value) => format!("{}", value),
+      Literal::Boolean(value) => format!("{}", value),
+      Literal::Text(index) => string_literal(self.program.get_text(index)),
+    }
+  }
+
+  fn function_name(&self, name: usize) -> String {
+    match self.functions.get(&name) {
+      Some(function) => function.clone(),
+      None => panic!("Internal compiler error: unknown function '{}'", self.program.get_text(name)),
+    }
+  }
+
+  fn variable(&self, name: usize) -> String {
+    match self.locals.lookup(name) {
+      Some(local) => local,
+      None => match self.globals.get(&name) {
+        Some(global) => global.clone(),
+        None => panic!("Internal compiler error: unknown variable '{}'", self.program.get_text(name)),
+      },
+    }
+  }
+
+  fn declare_local(&mut self, name: usize) -> String {
+    let base = identifier(self.program.get_text(name));
+    let visible = self.locals.lookup(name).is_some() || self.globals.contains_key(&name) ||
+      self.functions.contains_key(&name);
+
+    let mut local = base.clone();
+    let mut number = 1;
+    while visible && (local == base || self.used_names.contains(&local)) {
+      local = format!("{}${}", base, number);
+      number += 1;
+    }
+    self.used_names.insert(local.clone());
+    self.locals.declare(name, local.clone());
+    local
+  }
+
+  // writes the text on the given source line, or on the current line if the
+  // output has already passed it
+  fn write(&mut self, line: i32, text: &str) {
+    self.advance_to(line);
+    if self.line_started {
+      self.output.push(' ');
+    } else {
+      let indentation = self.layout.indentation[self.line as usize - 1].clone();
+      self.output.push_str(indentation.as_slice());
+      self.line_started = true;
+    }
+    self.output.push_str(text);
+  }
+
+  fn advance_to(&mut self, line: i32) {
+    while self.line < line {
+      self.end_line();
+    }
+  }
+
+  // ends the current line with the comment of its source line
+  fn end_line(&mut self) {
+    let index = self.line as usize - 1;
+    match self.layout.comments[index] {
+      Some(ref comment) => {
+        if self.line_started {
+          self.output.push(' ');
+        } else {
+          self.output.push_str(self.layout.indentation[index].as_slice());
+        }
+        self.output.push_str(comment.as_slice());
+      },
+      None => { },
+    }
+    self.output.push('\n');
+    self.line += 1;
+    self.line_started = false;
+  }
+}
+
+fn parenthesize(text: String, needed: bool) -> String {
+  if needed {
+    format!("({})", text)
+  } else {
+    text
+  }
+}
+
+fn identifier(name: &str) -> String {
+  if RESERVED_NAMES.contains(&name) {
+    format!("{}$", name)
+  } else {
+    name.to_string()
+  }
+}
+
+fn string_literal(text: &str) -> String {
+  let mut literal = "\"".to_string();
+  for ch in text.chars() {
+    match ch {
+      '"' => literal.push_str("\\\""),
+      '\\' => literal.push_str("\\\\"),
+      '\n' => literal.push_str("\\n"),
+      '\t' => literal.push_str("\\t"),
+      '\r' => literal.push_str("\\r"),
+      // other control characters and the line and paragraph separators
+      _ if ch < ' ' || ch == '\u{2028}' || ch == '\u{2029}' => {
+        literal.push_str(format!("\\u{:04x}", ch as u32).as_slice());
+      },
+      _ => literal.push(ch),
+    }
+  }
+  literal.push('"');
+  literal
+}
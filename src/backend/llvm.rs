@@ -0,0 +1,664 @@
+use std::collections::HashSet;
+use std::mem;
+use ast::Program;
+use ast::Function;
+use ast::Block;
+use ast::Statement;
+use ast::Assignment;
+use ast::FunctionCall;
+use ast::Expression;
+use ast::ExpressionKind;
+use ast::Literal;
+use ast::BinaryOperator;
+use ast::ElseIf;
+use ast::Position;
+use ast::Type;
+use symbol_table::SymbolTable;
+use builtins;
+
+/*
+  LLVM backend. Translates a resolved and type checked program into a textual
+  LLVM IR module (.ll) with opaque pointers, preceded by a small runtime for
+  strings and printing written in IR on top of the C library.
+
+  Every function becomes one 'define'. Parameters and locals live in stack
+  slots created with 'alloca' in the entry block and are accessed with
+  'load'/'store', leaving register promotion to LLVM. Values are named %tN,
+  slots %v_<name>_N and parameters %p_<name>. int maps to i32, float and
+  double to float and double, bool to i1 and string to ptr.
+
+  Integer arithmetic wraps around and integer division by zero terminates the
+  program with the same runtime error as the interpreter. Strings are never
+  freed.
+*/
+
+pub fn generate(program: &Program) -> String {
+  let mut generator = Generator::new(program);
+  generator.generate()
+}
+
+static RUNTIME: &'static str = "@.rt_int_format = private unnamed_addr constant [4 x i8] c\"%d\\0A\\00\"
+@.rt_string_format = private unnamed_addr constant [4 x i8] c\"%s\\0A\\00\"
+@.rt_float_format = private unnamed_addr constant [5 x i8] c\"%.*g\\00\"
+@.rt_division_format = private unnamed_addr constant [42 x i8] c\"Runtime error at %d:%d: Division by zero\\0A\\00\"
+@.rt_memory_message = private unnamed_addr constant [30 x i8] c\"Runtime error: Out of memory\\0A\\00\"
+@.rt_true = private unnamed_addr constant [5 x i8] c\"true\\00\"
+@.rt_false = private unnamed_addr constant [6 x i8] c\"false\\00\"
+
+declare i32 @printf(ptr, ...)
+declare i32 @snprintf(ptr, i64, ptr, ...)
+declare double @strtod(ptr, ptr)
+declare float @strtof(ptr, ptr)
+declare i64 @strlen(ptr)
+declare i32 @strcmp(ptr, ptr)
+declare ptr @malloc(i64)
+declare ptr @memcpy(ptr, ptr, i64)
+declare void @exit(i32)
+
+define internal i32 @rt_idiv(i32 %a, i32 %b, i32 %line, i32 %pos) {
+entry:
+  %zero = icmp eq i32 %b, 0
+  br i1 %zero, label %division_by_zero, label %check_overflow
+division_by_zero:
+  call i32 (ptr, ...) @printf(ptr @.rt_division_format, i32 %line, i32 %pos)
+  call void @exit(i32 1)
+  unreachable
+check_overflow:
+  %minimum = icmp eq i32 %a, -2147483648
+  %minus_one = icmp eq i32 %b, -1
+  %overflow = and i1 %minimum, %minus_one
+  br i1 %overflow, label %wrap, label %divide
+wrap:
+  ret i32 -2147483648
+divide:
+  %result = sdiv i32 %a, %b
+  ret i32 %result
+}
+
+define internal ptr @rt_concat(ptr %a, ptr %b) {
+entry:
+  %a_length = call i64 @strlen(ptr %a)
+  %b_length = call i64 @strlen(ptr %b)
+  %length = add i64 %a_length, %b_length
+  %size = add i64 %length, 1
+  %result = call ptr @malloc(i64 %size)
+  %failed = icmp eq ptr %result, null
+  br i1 %failed, label %out_of_memory, label %copy
+out_of_memory:
+  call i32 (ptr, ...) @printf(ptr @.rt_memory_message)
+  call void @exit(i32 1)
+  unreachable
+copy:
+  call ptr @memcpy(ptr %result, ptr %a, i64 %a_length)
+  %tail = getelementptr i8, ptr %result, i64 %a_length
+  %b_size = add i64 %b_length, 1
+  call ptr @memcpy(ptr %tail, ptr %b, i64 %b_size)
+  ret ptr %result
+}
+
+define internal i1 @rt_string_eq(ptr %a, ptr %b) {
+entry:
+  %order = call i32 @strcmp(ptr %a, ptr %b)
+  %equal = icmp eq i32 %order, 0
+  ret i1 %equal
+}
+
+define internal void @rt_print_int(i32 %value) {
+entry:
+  call i32 (ptr, ...) @printf(ptr @.rt_int_format, i32 %value)
+  ret void
+}
+
+define internal void @rt_print_bool(i1 %value) {
+entry:
+  %text = select i1 %value, ptr @.rt_true, ptr @.rt_false
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %text)
+  ret void
+}
+
+define internal void @rt_print_string(ptr %value) {
+entry:
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %value)
+  ret void
+}
+
+; shortest representation that reads back as the same value
+define internal void @rt_print_double(double %value) {
+entry:
+  %buffer = alloca [32 x i8]
+  br label %format
+format:
+  %precision = phi i32 [ 1, %entry ], [ %next, %retry ]
+  call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buffer, i64 32, ptr @.rt_float_format, i32 %precision, double %value)
+  %parsed = call double @strtod(ptr %buffer, ptr null)
+  %same = fcmp oeq double %parsed, %value
+  %exhausted = icmp sge i32 %precision, 17
+  %done = or i1 %same, %exhausted
+  br i1 %done, label %print, label %retry
+retry:
+  %next = add i32 %precision, 1
+  br label %format
+print:
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %buffer)
+  ret void
+}
+
+define internal void @rt_print_float(float %value) {
+entry:
+  %buffer = alloca [32 x i8]
+  %extended = fpext float %value to double
+  br label %format
+format:
+  %precision = phi i32 [ 1, %entry ], [ %next, %retry ]
+  call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buffer, i64 32, ptr @.rt_float_format, i32 %precision, double %extended)
+  %parsed = call float @strtof(ptr %buffer, ptr null)
+  %same = fcmp oeq float %parsed, %value
+  %exhausted = icmp sge i32 %precision, 9
+  %done = or i1 %same, %exhausted
+  br i1 %done, label %print, label %retry
+retry:
+  %next = add i32 %precision, 1
+  br label %format
+print:
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %buffer)
+  ret void
+}
+";
+
+struct Generator<'a> {
+  program: &'a Program,
+  functions: HashSet<usize>,
+  // source name -> stack slot of the visible locals
+  locals: SymbolTable<String>,
+  local_count: usize,
+  value_count: usize,
+  label_count: usize,
+  // string constants, emitted before the functions
+  constants: String,
+  constant_count: usize,
+  // allocas of the current function, placed at the start of its entry block
+  allocas: String,
+  body: String,
+  // the current basic block ends with a terminator
+  terminated: bool,
+  output: String,
+}
+
+impl<'a> Generator<'a> {
+  fn new(program: &'a Program) -> Generator<'a> {
+    Generator {
+      program: program,
+      functions: HashSet::new(),
+      locals: SymbolTable::new(),
+      local_count: 0,
+      value_count: 0,
+      label_count: 0,
+      constants: String::new(),
+      constant_count: 0,
+      allocas: String::new(),
+      body: String::new(),
+      terminated: false,
+      output: String::new(),
+    }
+  }
+
+  fn generate(&mut self) -> String {
+    let program = self.program;
+    let mut functions = vec![];
+    for function in program.functions.iter() {
+      if self.functions.insert(function.name) {
+        functions.push(function);
+      }
+    }
+
+    let mut declared_globals = HashSet::new();
+    let mut globals = String::new();
+    for global in program.globals.iter() {
+      if declared_globals.insert(global.name) {
+        globals.push_str(format!("@g_{} = internal global {} zeroinitializer\n",
+          program.get_text(global.name), llvm_type(global.var_type)).as_slice());
+      }
+    }
+
+    self.begin_function();
+    for global in program.globals.iter() {
+      let value = self.expression(&global.initializer);
+      let line = format!("store {} {}, ptr @g_{}", llvm_type(global.var_type), value,
+        program.get_text(global.name));
+      self.instruction(line.as_slice());
+    }
+    self.end_function("define internal void @rt_init_globals()", Type::Void);
+
+    for function in functions.iter() {
+      self.function(*function);
+    }
+
+    let has_main = functions.iter().any(|function| program.get_text(function.name) == "main");
+    if has_main {
+      self.begin_function();
+      self.instruction("call void @rt_init_globals()");
+      self.instruction("call void @fn_main()");
+      self.terminator("ret i32 0");
+      self.end_function("define i32 @main()", Type::Integer);
+    }
+
+    let mut output = RUNTIME.to_string();
+    if !globals.is_empty() {
+      output.push('\n');
+      output.push_str(globals.as_slice());
+    }
+    if !self.constants.is_empty() {
+      output.push('\n');
+      output.push_str(self.constants.as_slice());
+    }
+    output.push_str(self.output.as_slice());
+    output
+  }
+
+  fn function(&mut self, function: &Function) {
+    // parameters and the top level of the body share the scope
+    let program = self.program;
+    self.begin_function();
+    self.locals.push_scope();
+
+    let mut parameters = vec![];
+    for parameter in function.parameters.iter() {
+      let parameter_type = llvm_type(parameter.param_type);
+      let value = format!("%p_{}", program.get_text(parameter.name));
+      parameters.push(format!("{} {}", parameter_type, value));
+      let slot = self.declare_local(parameter.name, parameter.param_type);
+      let line = format!("store {} {}, ptr {}", parameter_type, value, slot);
+      self.instruction(line.as_slice());
+    }
+
+    for statement in function.body.statements.iter() {
+      self.statement(statement);
+    }
+    self.locals.pop_scope();
+
+    let header = format!("define internal {} @fn_{}({})", llvm_type(function.return_type),
+      program.get_text(function.name), parameters.connect(", "));
+    self.end_function(header.as_slice(), function.return_type);
+  }
+
+  fn begin_function(&mut self) {
+    self.locals = SymbolTable::new();
+    self.local_count = 0;
+    self.value_count = 0;
+    self.label_count = 0;
+    self.allocas = String::new();
+    self.body = String::new();
+    self.terminated = false;
+  }
+
+  // the end of a function returning a value can only be reached if the type
+  // checker let a missing return through
+  fn end_function(&mut self, header: &str, return_type: Type) {
+    if !self.terminated {
+      match return_type {
+        Type::Void => self.terminator("ret void"),
+        _ => self.terminator("unreachable"),
+      }
+    }
+
+    self.output.push('\n');
+    self.output.push_str(header);
+    self.output.push_str(" {\nentry:\n");
+    self.output.push_str(self.allocas.as_slice());
+    self.output.push_str(self.body.as_slice());
+    self.output.push_str("}\n");
+  }
+
+  fn block(&mut self, block: &Block) {
+    self.locals.push_scope();
+    for statement in block.statements.iter() {
+      self.statement(statement);
+    }
+    self.locals.pop_scope();
+  }
+
+  fn statement(&mut self, statement: &Statement) {
+    match *statement {
+      Statement::Block(ref block) => self.block(block),
+      Statement::VariableDeclaration(ref declaration) => {
+        // variable is not visible in its own initializer
+        let value = self.expression(&declaration.initializer);
+        let slot = self.declare_local(declaration.name, declaration.var_type);
+        let line = format!("store {} {}, ptr {}", llvm_type(declaration.var_type), value, slot);
+        self.instruction(line.as_slice());
+      },
+      Statement::Assignment(ref assignment) => self.assignment(assignment),
+      Statement::FunctionCall(ref call) => {
+        self.call(call);
+      },
+      Statement::For(ref for_loop) => {
+        // variable declared in the init clause is only visible inside the loop
+        self.locals.push_scope();
+        match for_loop.init {
+          Some(ref init) => self.statement(&**init),
+          None => { },
+        }
+
+        let number = self.new_label_number();
+        let condition_label = format!("for.cond.{}", number);
+        let body_label = format!("for.body.{}", number);
+        let end_label = format!("for.end.{}", number);
+
+        self.label(condition_label.as_slice());
+        match for_loop.condition {
+          Some(ref condition) => {
+            let value = self.expression(condition);
+            let line = format!("br i1 {}, label %{}, label %{}", value, body_label, end_label);
+            self.terminator(line.as_slice());
+          },
+          None => { },
+        }
+
+        self.label(body_label.as_slice());
+        self.block(&for_loop.body);
+        match for_loop.update {
+          Some(ref update) => self.assignment(update),
+          None => { },
+        }
+        let line = format!("br label %{}", condition_label);
+        self.terminator(line.as_slice());
+
+        self.label(end_label.as_slice());
+        self.locals.pop_scope();
+      },
+      Statement::If(ref if_statement) => {
+        let number = self.new_label_number();
+        let end_label = format!("if.end.{}", number);
+        self.if_chain(&if_statement.condition, &if_statement.block, if_statement.else_ifs.as_slice(),
+          &if_statement.else_block, number, end_label.as_slice());
+        self.label(end_label.as_slice());
+      },
+      Statement::Return(ref return_statement) => {
+        match return_statement.value {
+          Some(ref value) => {
+            let result = self.expression(value);
+            let line = format!("ret {} {}", llvm_type(value.get_type()), result);
+            self.terminator(line.as_slice());
+          },
+          None => self.terminator("ret void"),
+        }
+      },
+      Statement::Empty(..) => { },
+    }
+  }
+
+  // else ifs become conditions nested in the else block, all branching to
+  // the same end block. Each condition numbers its then and else blocks
+  fn if_chain(&mut self, condition: &Expression, block: &Block, else_ifs: &[ElseIf],
+              else_block: &Option<Block>, number: usize, end_label: &str) {
+    let then_label = format!("if.then.{}", number);
+    let else_label = format!("if.else.{}", number);
+    let has_else = !else_ifs.is_empty() || else_block.is_some();
+
+    let value = self.expression(condition);
+    let false_label = if has_else { else_label.as_slice() } else { end_label };
+    let line = format!("br i1 {}, label %{}, label %{}", value, then_label, false_label);
+    self.terminator(line.as_slice());
+
+    self.label(then_label.as_slice());
+    self.block(block);
+    let line = format!("br label %{}", end_label);
+    self.terminator(line.as_slice());
+
+    if has_else {
+      self.label(else_label.as_slice());
+      if !else_ifs.is_empty() {
+        let number = self.new_label_number();
+        self.if_chain(&else_ifs[0].condition, &else_ifs[0].block, else_ifs.slice_from(1), else_block,
+          number, end_label);
+      } else {
+        match *else_block {
+          Some(ref else_block) => self.block(else_block),
+          None => { },
+        }
+      }
+    }
+  }
+
+  fn assignment(&mut self, assignment: &Assignment) {
+    let value = self.expression(&assignment.value);
+    let line = format!("store {} {}, ptr {}", llvm_type(assignment.value.get_type()), value,
+      self.variable(assignment.name));
+    self.instruction(line.as_slice());
+  }
+
+  // returns the result, or an empty string for void functions
+  fn call(&mut self, call: &FunctionCall) -> String {
+    let mut arguments = vec![];
+    for argument in call.arguments.iter() {
+      let value = self.expression(argument);
+      arguments.push(format!("{} {}", llvm_type(argument.get_type()), value));
+    }
+
+    let program = self.program;
+    let name = program.get_text(call.name);
+    if self.functions.contains(&call.name) {
+      let return_type = self.return_type(call.name);
+      let text = format!("call {} @fn_{}({})", llvm_type(return_type), name, arguments.connect(", "));
+      match return_type {
+        Type::Void => {
+          self.instruction(text.as_slice());
+          String::new()
+        },
+        _ => self.value(text.as_slice()),
+      }
+    } else if name == builtins::PRINT {
+      let print = match call.arguments[0].get_type() {
+        Type::Integer => "rt_print_int",
+        Type::Float => "rt_print_float",
+        Type::Double => "rt_print_double",
+        Type::Boolean => "rt_print_bool",
+        Type::String => "rt_print_string",
+        Type::Void => panic!("Internal compiler error: void value printed"),
+      };
+      let line = format!("call void @{}({})", print, arguments[0]);
+      self.instruction(line.as_slice());
+      String::new()
+    } else {
+      panic!("Internal compiler error: call to unknown function '{}'", name);
+    }
+  }
+
+  fn return_type(&self, name: usize) -> Type {
+    match self.program.functions.iter().find(|function| function.name == name) {
+      Some(function) => function.return_type,
+      None => panic!("Internal compiler error: unknown function '{}'", self.program.get_text(name)),
+    }
+  }
+
+  // returns a constant or the name of the value holding the result
+  fn expression(&mut self, expression: &Expression) -> String {
+    match expression.kind {
+      ExpressionKind::Literal(literal) => self.literal(literal),
+      ExpressionKind::Variable(name) => {
+        let text = format!("load {}, ptr {}", llvm_type(expression.get_type()), self.variable(name));
+        self.value(text.as_slice())
+      },
+      ExpressionKind::Binary(operator, ref left, ref right) => {
+        let operand_type = left.get_type();
+        let left = self.expression(&**left);
+        let right = self.expression(&**right);
+        self.binary(operator, operand_type, left, right, expression.pos)
+      },
+      ExpressionKind::Call(ref call) => self.call(call),
+      ExpressionKind::Cast(ref inner, target) => {
+        let value = self.expression(&**inner);
+        let conversion = match (inner.get_type(), target) {
+          (Type::Integer, Type::Float) | (Type::Integer, Type::Double) => "sitofp",
+          (Type::Float, Type::Integer) | (Type::Double, Type::Integer) => "fptosi",
+          (Type::Float, Type::Double) => "fpext",
+          (Type::Double, Type::Float) => "fptrunc",
+          (from, to) => {
+            if from != to {
+              panic!("Internal compiler error: cast from {} to {}", from, to);
+            }
+            return value;
+          },
+        };
+        let text = format!("{} {} {} to {}", conversion, llvm_type(inner.get_type()), value,
+          llvm_type(target));
+        self.value(text.as_slice())
+      },
+    }
+  }
+
+  fn binary(&mut self, operator: BinaryOperator, operand_type: Type, left: String, right: String,
+            pos: Position) -> String {
+    let operand_llvm_type = llvm_type(operand_type);
+    let text = match operand_type {
+      Type::Integer | Type::Boolean => {
+        let instruction = match operator {
+          BinaryOperator::Plus => "add",
+          BinaryOperator::Minus => "sub",
+          BinaryOperator::Multiply => "mul",
+          BinaryOperator::Divide => {
+            return self.value(format!("call i32 @rt_idiv(i32 {}, i32 {}, i32 {}, i32 {})",
+              left, right, pos.line, pos.pos_at_line).as_slice());
+          },
+          BinaryOperator::Equals => "icmp eq",
+          BinaryOperator::Lesser => "icmp slt",
+          BinaryOperator::Greater => "icmp sgt",
+          BinaryOperator::LesserOrEq => "icmp sle",
+          BinaryOperator::GreaterOrEq => "icmp sge",
+        };
+        format!("{} {} {}, {}", instruction, operand_llvm_type, left, right)
+      },
+      Type::Float | Type::Double => {
+        // ordered comparisons are false if either operand is NaN
+        let instruction = match operator {
+          BinaryOperator::Plus => "fadd",
+          BinaryOperator::Minus => "fsub",
+          BinaryOperator::Multiply => "fmul",
+          BinaryOperator::Divide => "fdiv",
+          BinaryOperator::Equals => "fcmp oeq",
+          BinaryOperator::Lesser => "fcmp olt",
+          BinaryOperator::Greater => "fcmp ogt",
+          BinaryOperator::LesserOrEq => "fcmp ole",
+          BinaryOperator::GreaterOrEq => "fcmp oge",
+        };
+        format!("{} {} {}, {}", instruction, operand_llvm_type, left, right)
+      },
+      Type::String => match operator {
+        BinaryOperator::Plus => format!("call ptr @rt_concat(ptr {}, ptr {})", left, right),
+        BinaryOperator::Equals => format!("call i1 @rt_string_eq(ptr {}, ptr {})", left, right),
+        _ => panic!("Internal compiler error: operator {} applied to strings", operator),
+      },
+      Type::Void => panic!("Internal compiler error: operator {} applied to void", operator),
+    };
+    self.value(text.as_slice())
+  }
+
+  fn literal(&mut self, literal: Literal) -> String {
+    match literal {
+      Literal::Integer(value) => format!("{}", value),
+      // LLVM writes floats of both sizes as the bits of the equivalent double
+      Literal::Float(value) => float_constant(value as f64),
+      Literal::Double(value) => float_constant(value),
+      Literal::Boolean(value) => format!("{}", value),
+      Literal::Text(index) => {
+        let text = self.program.get_text(index);
+        let name = format!("@.str.{}", self.constant_count);
+        self.constant_count += 1;
+        self.constants.push_str(format!("{} = private unnamed_addr constant [{} x i8] {}\n",
+          name, text.len() + 1, string_constant(text)).as_slice());
+        name
+      },
+    }
+  }
+
+  // pointer to the variable's storage
+  fn variable(&self, name: usize) -> String {
+    match self.locals.lookup(name) {
+      Some(slot) => slot,
+      None => format!("@g_{}", self.program.get_text(name)),
+    }
+  }
+
+  fn declare_local(&mut self, name: usize, var_type: Type) -> String {
+    let slot = format!("%v_{}_{}", self.program.get_text(name), self.local_count);
+    self.local_count += 1;
+    self.allocas.push_str(format!("  {} = alloca {}\n", slot, llvm_type(var_type)).as_slice());
+    self.locals.declare(name, slot.clone());
+    slot
+  }
+
+  // emits an instruction producing a value and returns the value's name
+  fn value(&mut self, text: &str) -> String {
+    let name = format!("%t{}", self.value_count);
+    self.value_count += 1;
+    let line = format!("{} = {}", name, text);
+    self.instruction(line.as_slice());
+    name
+  }
+
+  fn new_label_number(&mut self) -> usize {
+    self.label_count += 1;
+    self.label_count - 1
+  }
+
+  // starts a basic block, falling through from the current one
+  fn label(&mut self, label: &str) {
+    if !self.terminated {
+      let line = format!("br label %{}", label);
+      self.instruction(line.as_slice());
+    }
+    self.body.push_str(label);
+    self.body.push_str(":\n");
+    self.terminated = false;
+  }
+
+  // instructions following a terminator, e.g. after a return, are placed in
+  // a block of their own that is never branched to
+  fn instruction(&mut self, text: &str) {
+    if self.terminated {
+      let label = format!("dead.{}", self.new_label_number());
+      self.body.push_str(label.as_slice());
+      self.body.push_str(":\n");
+      self.terminated = false;
+    }
+    self.body.push_str("  ");
+    self.body.push_str(text);
+    self.body.push('\n');
+  }
+
+  fn terminator(&mut self, text: &str) {
+    self.instruction(text);
+    self.terminated = true;
+  }
+}
+
+fn llvm_type(var_type: Type) -> &'static str {
+  match var_type {
+    Type::Integer => "i32",
+    Type::Float => "float",
+    Type::Double => "double",
+    Type::Boolean => "i1",
+    Type::String => "ptr",
+    Type::Void => "void",
+  }
+}
+
+// hexadecimal constants are exact, decimal ones have to be exactly representable
+fn float_constant(value: f64) -> String {
+  let bits = unsafe { mem::transmute::<f64, u64>(value) };
+  format!("0x{:016X}", bits)
+}
+
+// printable ascii is kept, everything else is written as \XX
+fn string_constant(text: &str) -> String {
+  let mut constant = "c\"".to_string();
+  for byte in text.bytes() {
+    match byte {
+      b'"' | b'\\' => constant.push_str(format!("\\{:02X}", byte).as_slice()),
+      0x20 ... 0x7E => constant.push(byte as char),
+      _ => constant.push_str(format!("\\{:02X}", byte).as_slice()),
+    }
+  }
+  constant.push_str("\\00\"");
+  constant
+}
@@ -0,0 +1,30 @@
+/*
+  Code generators translating a resolved and type checked program into other
+  languages. The C, JavaScript, LLVM IR and x86-64 backends return the
+  generated source as a string, the WebAssembly backend builds a module that
+  is printed as text or encoded in the binary format.
+*/
+
+pub mod c;
+pub mod javascript;
+pub mod llvm;
+pub mod wasm;
+pub mod x86_64;
+
+// double quoted literal understood by both C compilers and the GNU assembler
+pub fn string_literal(text: &str) -> String {
+  let mut literal = "\"".to_string();
+  for byte in text.bytes() {
+    match byte {
+      b'"' => literal.push_str("\\\""),
+      b'\\' => literal.push_str("\\\\"),
+      b'\n' => literal.push_str("\\n"),
+      b'\t' => literal.push_str("\\t"),
+      0x20 ... 0x7E => literal.push(byte as char),
+      // octal escapes always use three digits, so a following digit is not consumed
+      _ => literal.push_str(format!("\\{:03o}", byte).as_slice()),
+    }
+  }
+  literal.push('"');
+  literal
+}
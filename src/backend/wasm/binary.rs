@@ -0,0 +1,248 @@
+use std::mem;
+use backend::wasm::*;
+
+/*
+  Encodes a module in the WebAssembly binary format. Integers are LEB128
+  encoded, floats are stored as little endian IEEE 754 bits. Sections are
+  written in the order required by the format; empty sections are left out.
+*/
+
+static TYPE_SECTION: u8 = 1;
+static IMPORT_SECTION: u8 = 2;
+static FUNCTION_SECTION: u8 = 3;
+static MEMORY_SECTION: u8 = 5;
+static GLOBAL_SECTION: u8 = 6;
+static EXPORT_SECTION: u8 = 7;
+static CODE_SECTION: u8 = 10;
+static DATA_SECTION: u8 = 11;
+
+static FUNCTION_KIND: u8 = 0x00;
+static MEMORY_KIND: u8 = 0x02;
+
+pub fn encode(module: &Module) -> Vec<u8> {
+  let mut bytes = vec![];
+  bytes.push_all(MAGIC.as_slice());
+  bytes.push_all(VERSION.as_slice());
+
+  let mut section = vec![];
+  write_u32(&mut section, module.types.len() as u32);
+  for function_type in module.types.iter() {
+    section.push(FUNCTION_TYPE);
+    write_value_types(&mut section, function_type.parameters.as_slice());
+    write_value_types(&mut section, function_type.results.as_slice());
+  }
+  write_section(&mut bytes, TYPE_SECTION, section);
+
+  let mut section = vec![];
+  write_u32(&mut section, module.imports.len() as u32);
+  for import in module.imports.iter() {
+    write_name(&mut section, import.module.as_slice());
+    write_name(&mut section, import.name.as_slice());
+    section.push(FUNCTION_KIND);
+    write_u32(&mut section, import.type_index);
+  }
+  write_section(&mut bytes, IMPORT_SECTION, section);
+
+  let mut section = vec![];
+  write_u32(&mut section, module.functions.len() as u32);
+  for function in module.functions.iter() {
+    write_u32(&mut section, function.type_index);
+  }
+  write_section(&mut bytes, FUNCTION_SECTION, section);
+
+  // one memory with a minimum size and no maximum
+  let mut section = vec![];
+  write_u32(&mut section, 1);
+  section.push(0x00);
+  write_u32(&mut section, module.memory_pages);
+  write_section(&mut bytes, MEMORY_SECTION, section);
+
+  let mut section = vec![];
+  write_u32(&mut section, module.globals.len() as u32);
+  for global in module.globals.iter() {
+    section.push(global.value_type.code());
+    section.push(if global.mutable { 0x01 } else { 0x00 });
+    write_instruction(&mut section, &global.init);
+    section.push(END);
+  }
+  write_section(&mut bytes, GLOBAL_SECTION, section);
+
+  let mut section = vec![];
+  write_u32(&mut section, module.exports.len() as u32);
+  for export in module.exports.iter() {
+    write_name(&mut section, export.name.as_slice());
+    match export.kind {
+      ExportKind::Function(index) => {
+        section.push(FUNCTION_KIND);
+        write_u32(&mut section, index);
+      },
+      ExportKind::Memory(index) => {
+        section.push(MEMORY_KIND);
+        write_u32(&mut section, index);
+      },
+    }
+  }
+  write_section(&mut bytes, EXPORT_SECTION, section);
+
+  let mut section = vec![];
+  write_u32(&mut section, module.functions.len() as u32);
+  for function in module.functions.iter() {
+    let body = encode_body(function);
+    write_u32(&mut section, body.len() as u32);
+    section.push_all(body.as_slice());
+  }
+  write_section(&mut bytes, CODE_SECTION, section);
+
+  let mut section = vec![];
+  write_u32(&mut section, module.data.len() as u32);
+  for data in module.data.iter() {
+    // memory index and offset expression
+    write_u32(&mut section, 0);
+    write_instruction(&mut section, &Instruction::I32Const(data.offset as i32));
+    section.push(END);
+    write_u32(&mut section, data.bytes.len() as u32);
+    section.push_all(data.bytes.as_slice());
+  }
+  write_section(&mut bytes, DATA_SECTION, section);
+
+  bytes
+}
+
+fn encode_body(function: &Function) -> Vec<u8> {
+  // locals are declared as runs of the same type
+  let mut runs: Vec<(u32, ValueType)> = vec![];
+  for local in function.locals.iter() {
+    let same_type = match runs.last() {
+      Some(&(_, run_type)) => run_type == *local,
+      None => false,
+    };
+    if same_type {
+      let last = runs.len() - 1;
+      let (count, value_type) = runs[last];
+      runs[last] = (count + 1, value_type);
+    } else {
+      runs.push((1, *local));
+    }
+  }
+
+  let mut body = vec![];
+  write_u32(&mut body, runs.len() as u32);
+  for &(count, value_type) in runs.iter() {
+    write_u32(&mut body, count);
+    body.push(value_type.code());
+  }
+
+  for instruction in function.body.iter() {
+    write_instruction(&mut body, instruction);
+  }
+  body.push(END);
+  body
+}
+
+fn write_instruction(bytes: &mut Vec<u8>, instruction: &Instruction) {
+  match *instruction {
+    Instruction::Unreachable => bytes.push(UNREACHABLE),
+    Instruction::Block => bytes.push_all(&[BLOCK, EMPTY_BLOCK]),
+    Instruction::Loop => bytes.push_all(&[LOOP, EMPTY_BLOCK]),
+    Instruction::If => bytes.push_all(&[IF, EMPTY_BLOCK]),
+    Instruction::Else => bytes.push(ELSE),
+    Instruction::End => bytes.push(END),
+    Instruction::Br(depth) => write_with_index(bytes, BR, depth),
+    Instruction::BrIf(depth) => write_with_index(bytes, BR_IF, depth),
+    Instruction::Return => bytes.push(RETURN),
+    Instruction::Call(index) => write_with_index(bytes, CALL, index),
+    Instruction::Drop => bytes.push(DROP),
+    Instruction::LocalGet(index) => write_with_index(bytes, LOCAL_GET, index),
+    Instruction::LocalSet(index) => write_with_index(bytes, LOCAL_SET, index),
+    Instruction::GlobalGet(index) => write_with_index(bytes, GLOBAL_GET, index),
+    Instruction::GlobalSet(index) => write_with_index(bytes, GLOBAL_SET, index),
+    // memory accesses use their natural alignment, given as a power of two
+    Instruction::I32Load(offset) => write_memory_access(bytes, I32_LOAD, 2, offset),
+    Instruction::I32Load8U(offset) => write_memory_access(bytes, I32_LOAD8_U, 0, offset),
+    Instruction::I32Store(offset) => write_memory_access(bytes, I32_STORE, 2, offset),
+    Instruction::I32Store8(offset) => write_memory_access(bytes, I32_STORE8, 0, offset),
+    // the memory index is reserved and must be zero
+    Instruction::MemorySize => bytes.push_all(&[MEMORY_SIZE, 0x00]),
+    Instruction::MemoryGrow => bytes.push_all(&[MEMORY_GROW, 0x00]),
+    Instruction::I32Const(value) => {
+      bytes.push(I32_CONST);
+      write_i32(bytes, value);
+    },
+    Instruction::F32Const(value) => {
+      bytes.push(F32_CONST);
+      let bits = unsafe { mem::transmute::<f32, u32>(value) };
+      for shift in range(0, 4) {
+        bytes.push((bits >> (8 * shift)) as u8);
+      }
+    },
+    Instruction::F64Const(value) => {
+      bytes.push(F64_CONST);
+      let bits = unsafe { mem::transmute::<f64, u64>(value) };
+      for shift in range(0, 8) {
+        bytes.push((bits >> (8 * shift)) as u8);
+      }
+    },
+    Instruction::Numeric(opcode) => bytes.push(opcode),
+  }
+}
+
+fn write_with_index(bytes: &mut Vec<u8>, opcode: u8, index: u32) {
+  bytes.push(opcode);
+  write_u32(bytes, index);
+}
+
+fn write_memory_access(bytes: &mut Vec<u8>, opcode: u8, alignment: u32, offset: u32) {
+  bytes.push(opcode);
+  write_u32(bytes, alignment);
+  write_u32(bytes, offset);
+}
+
+fn write_section(bytes: &mut Vec<u8>, id: u8, section: Vec<u8>) {
+  // sections holding nothing but a zero count are left out
+  if section == vec![0] {
+    return;
+  }
+  bytes.push(id);
+  write_u32(bytes, section.len() as u32);
+  bytes.push_all(section.as_slice());
+}
+
+fn write_value_types(bytes: &mut Vec<u8>, value_types: &[ValueType]) {
+  write_u32(bytes, value_types.len() as u32);
+  for value_type in value_types.iter() {
+    bytes.push(value_type.code());
+  }
+}
+
+fn write_name(bytes: &mut Vec<u8>, name: &str) {
+  write_u32(bytes, name.len() as u32);
+  bytes.push_all(name.as_bytes());
+}
+
+// unsigned LEB128
+fn write_u32(bytes: &mut Vec<u8>, mut value: u32) {
+  loop {
+    let byte = (value & 0x7F) as u8;
+    value >>= 7;
+    if value == 0 {
+      bytes.push(byte);
+      return;
+    }
+    bytes.push(byte | 0x80);
+  }
+}
+
+// signed LEB128
+fn write_i32(bytes: &mut Vec<u8>, mut value: i32) {
+  loop {
+    let byte = (value & 0x7F) as u8;
+    // arithmetic shift keeps the sign
+    value >>= 7;
+    let sign_bit_clear = byte & 0x40 == 0;
+    if (value == 0 && sign_bit_clear) || (value == -1 && !sign_bit_clear) {
+      bytes.push(byte);
+      return;
+    }
+    bytes.push(byte | 0x80);
+  }
+}
@@ -0,0 +1,691 @@
+use std::collections::HashMap;
+use std::mem;
+use ast;
+use ast::Program;
+use ast::Block;
+use ast::Statement;
+use ast::Assignment;
+use ast::FunctionCall;
+use ast::ElseIf;
+use ast::Expression;
+use ast::ExpressionKind;
+use ast::Literal;
+use ast::BinaryOperator;
+use ast::Type;
+use backend::wasm;
+use backend::wasm::*;
+use signatures::SignatureTable;
+use symbol_table::SymbolTable;
+use builtins;
+
+/*
+  Lowers a resolved and type checked program into a WebAssembly module.
+
+  int and bool values are i32, float is f32 and double is f64. A string is the
+  i32 address of its u32 little endian byte length followed by the utf-8
+  bytes. String literals are placed in a data segment; concatenation results
+  are allocated from a heap growing upwards from the end of the data and are
+  never freed.
+
+  Every local declaration gets its own wasm local. 'if'/'elif'/'else' becomes
+  nested if/else blocks, a 'for' loop becomes a loop inside a block, which is
+  left when the condition is false.
+
+  The module imports from "env":
+    print_int(i32), print_float(f32), print_double(f64), print_bool(i32),
+    print_string(i32 address)     print the value followed by a newline
+    division_by_zero(i32 line, i32 position at line)
+                                  reports the runtime error, the module
+                                  traps afterwards if the import returns
+  and exports its "memory" and, if the program has a 'main' function, a
+  "main" function that initializes the globals and calls it.
+*/
+
+pub fn compile(program: &Program) -> Module {
+  let mut compiler = Compiler::new(program);
+  compiler.compile()
+}
+
+// function indices of the imports
+const PRINT_INT: u32 = 0;
+const PRINT_FLOAT: u32 = 1;
+const PRINT_DOUBLE: u32 = 2;
+const PRINT_BOOL: u32 = 3;
+const PRINT_STRING: u32 = 4;
+const DIVISION_BY_ZERO: u32 = 5;
+// function indices of the runtime functions defined in the module
+const RT_ALLOC: u32 = 6;
+const RT_COPY: u32 = 7;
+const RT_CONCAT: u32 = 8;
+const RT_STRING_EQ: u32 = 9;
+const RT_IDIV: u32 = 10;
+const FIRST_PROGRAM_FUNCTION: u32 = 11;
+
+// global holding the next free heap address
+const HEAP: u32 = 0;
+// address of the first string literal, so no string is at address 0
+const DATA_START: u32 = 8;
+
+struct Compiler<'a> {
+  program: &'a Program,
+  signatures: SignatureTable,
+  module: Module,
+  // name -> function index
+  functions: HashMap<usize, u32>,
+  // name -> global index
+  globals: HashMap<usize, u32>,
+  // text table index -> address of the string literal
+  strings: HashMap<usize, u32>,
+  // contents of the memory from DATA_START on
+  data: Vec<u8>,
+  // state of the function being compiled
+  locals: SymbolTable<u32>,
+  parameter_count: u32,
+  local_types: Vec<ValueType>,
+  body: Vec<Instruction>,
+}
+
+impl<'a> Compiler<'a> {
+  fn new(program: &'a Program) -> Compiler<'a> {
+    Compiler {
+      program: program,
+      signatures: SignatureTable::from_program(program),
+      module: Module {
+        types: vec![],
+        imports: vec![],
+        functions: vec![],
+        globals: vec![],
+        memory_pages: 1,
+        exports: vec![],
+        data: vec![],
+      },
+      functions: HashMap::new(),
+      globals: HashMap::new(),
+      strings: HashMap::new(),
+      data: vec![],
+      locals: SymbolTable::new(),
+      parameter_count: 0,
+      local_types: vec![],
+      body: vec![],
+    }
+  }
+
+  fn compile(&mut self) -> Module {
+    let program = self.program;
+    self.add_imports();
+    self.module.globals.push(Global {
+      name: "heap".to_string(),
+      value_type: ValueType::I32,
+      mutable: true,
+      init: Instruction::I32Const(0),
+    });
+    self.add_runtime();
+
+    let mut declared = vec![];
+    for function in program.functions.iter() {
+      if !self.functions.contains_key(&function.name) {
+        let index = FIRST_PROGRAM_FUNCTION + declared.len() as u32;
+        self.functions.insert(function.name, index);
+        declared.push(function);
+      }
+    }
+
+    for global in program.globals.iter() {
+      if !self.globals.contains_key(&global.name) {
+        let index = self.module.globals.len() as u32;
+        self.globals.insert(global.name, index);
+        self.module.globals.push(Global {
+          name: format!("g_{}", program.get_text(global.name)),
+          value_type: value_type(global.var_type),
+          mutable: true,
+          init: zero(value_type(global.var_type)),
+        });
+      }
+    }
+
+    for function in declared.iter() {
+      let compiled = self.compile_function(*function);
+      self.module.functions.push(compiled);
+    }
+
+    let init = FIRST_PROGRAM_FUNCTION + declared.len() as u32;
+    let initializers = self.compile_global_initializers();
+    self.module.functions.push(initializers);
+
+    let main = declared.iter()
+      .find(|function| program.get_text(function.name) == "main" && function.parameters.is_empty());
+    match main {
+      Some(main) => {
+        let main_index = self.function_index(main.name);
+        let type_index = self.type_index(vec![], vec![]);
+        self.module.functions.push(wasm::Function {
+          name: "main".to_string(),
+          type_index: type_index,
+          locals: vec![],
+          body: vec![Instruction::Call(init), Instruction::Call(main_index)],
+        });
+        let index = FIRST_PROGRAM_FUNCTION + declared.len() as u32 + 1;
+        self.module.exports.push(Export { name: "main".to_string(), kind: ExportKind::Function(index) });
+      },
+      None => { },
+    }
+    self.module.exports.push(Export { name: "memory".to_string(), kind: ExportKind::Memory(0) });
+
+    // the heap starts after the string literals
+    let heap_start = align(DATA_START + self.data.len() as u32, 4);
+    self.module.globals[HEAP as usize].init = Instruction::I32Const(heap_start as i32);
+    self.module.memory_pages = (heap_start + PAGE_SIZE - 1) / PAGE_SIZE;
+    if !self.data.is_empty() {
+      let bytes = mem::replace(&mut self.data, vec![]);
+      self.module.data.push(Data { offset: DATA_START, bytes: bytes });
+    }
+
+    mem::replace(&mut self.module, Module {
+      types: vec![],
+      imports: vec![],
+      functions: vec![],
+      globals: vec![],
+      memory_pages: 0,
+      exports: vec![],
+      data: vec![],
+    })
+  }
+
+  fn add_imports(&mut self) {
+    let imports = [
+      ("print_int", vec![ValueType::I32]),
+      ("print_float", vec![ValueType::F32]),
+      ("print_double", vec![ValueType::F64]),
+      ("print_bool", vec![ValueType::I32]),
+      ("print_string", vec![ValueType::I32]),
+      ("division_by_zero", vec![ValueType::I32, ValueType::I32]),
+    ];
+
+    for &(name, ref parameters) in imports.iter() {
+      let type_index = self.type_index(parameters.clone(), vec![]);
+      self.module.imports.push(Import {
+        module: "env".to_string(),
+        name: name.to_string(),
+        type_index: type_index,
+      });
+    }
+  }
+
+  // functions for memory management, strings and integer division. Their
+  // indices must match the RT_ constants
+  fn add_runtime(&mut self) {
+    let i32_type = ValueType::I32;
+
+    // rt_alloc(size) -> address, aligned to 4 bytes. Grows the memory when
+    // the heap does not fit
+    let body = vec![
+      Instruction::GlobalGet(HEAP), Instruction::LocalSet(1),
+      Instruction::GlobalGet(HEAP), Instruction::LocalGet(0), Instruction::Numeric(I32_ADD),
+      Instruction::I32Const(3), Instruction::Numeric(I32_ADD),
+      Instruction::I32Const(-4), Instruction::Numeric(I32_AND), Instruction::GlobalSet(HEAP),
+      Instruction::GlobalGet(HEAP), Instruction::MemorySize, Instruction::I32Const(16),
+      Instruction::Numeric(I32_SHL), Instruction::Numeric(I32_GT_U),
+      Instruction::If,
+        Instruction::GlobalGet(HEAP), Instruction::MemorySize, Instruction::I32Const(16),
+        Instruction::Numeric(I32_SHL), Instruction::Numeric(I32_SUB),
+        Instruction::I32Const(65535), Instruction::Numeric(I32_ADD),
+        Instruction::I32Const(16), Instruction::Numeric(I32_SHR_U), Instruction::MemoryGrow,
+        Instruction::I32Const(-1), Instruction::Numeric(I32_EQ),
+        Instruction::If, Instruction::Unreachable, Instruction::End,
+      Instruction::End,
+      Instruction::LocalGet(1),
+    ];
+    self.add_runtime_function("rt_alloc", vec![i32_type], vec![i32_type], vec![i32_type], body);
+
+    // rt_copy(destination, source, length)
+    let body = vec![
+      Instruction::Block, Instruction::Loop,
+        Instruction::LocalGet(2), Instruction::Numeric(I32_EQZ), Instruction::BrIf(1),
+        Instruction::LocalGet(0), Instruction::LocalGet(1), Instruction::I32Load8U(0),
+        Instruction::I32Store8(0),
+        Instruction::LocalGet(0), Instruction::I32Const(1), Instruction::Numeric(I32_ADD),
+        Instruction::LocalSet(0),
+        Instruction::LocalGet(1), Instruction::I32Const(1), Instruction::Numeric(I32_ADD),
+        Instruction::LocalSet(1),
+        Instruction::LocalGet(2), Instruction::I32Const(1), Instruction::Numeric(I32_SUB),
+        Instruction::LocalSet(2),
+        Instruction::Br(0),
+      Instruction::End, Instruction::End,
+    ];
+    self.add_runtime_function("rt_copy", vec![i32_type, i32_type, i32_type], vec![], vec![], body);
+
+    // rt_concat(a, b) -> address of the new string a + b
+    let body = vec![
+      Instruction::LocalGet(0), Instruction::I32Load(0), Instruction::LocalSet(2),
+      Instruction::LocalGet(1), Instruction::I32Load(0), Instruction::LocalSet(3),
+      Instruction::LocalGet(2), Instruction::LocalGet(3), Instruction::Numeric(I32_ADD),
+      Instruction::I32Const(4), Instruction::Numeric(I32_ADD),
+      Instruction::Call(RT_ALLOC), Instruction::LocalSet(4),
+      Instruction::LocalGet(4), Instruction::LocalGet(2), Instruction::LocalGet(3),
+      Instruction::Numeric(I32_ADD), Instruction::I32Store(0),
+      Instruction::LocalGet(4), Instruction::I32Const(4), Instruction::Numeric(I32_ADD),
+      Instruction::LocalGet(0), Instruction::I32Const(4), Instruction::Numeric(I32_ADD),
+      Instruction::LocalGet(2), Instruction::Call(RT_COPY),
+      Instruction::LocalGet(4), Instruction::I32Const(4), Instruction::Numeric(I32_ADD),
+      Instruction::LocalGet(2), Instruction::Numeric(I32_ADD),
+      Instruction::LocalGet(1), Instruction::I32Const(4), Instruction::Numeric(I32_ADD),
+      Instruction::LocalGet(3), Instruction::Call(RT_COPY),
+      Instruction::LocalGet(4),
+    ];
+    self.add_runtime_function("rt_concat", vec![i32_type, i32_type], vec![i32_type],
+      vec![i32_type, i32_type, i32_type], body);
+
+    // rt_string_eq(a, b) -> 1 if the strings have the same bytes, else 0
+    let body = vec![
+      Instruction::LocalGet(0), Instruction::I32Load(0), Instruction::LocalSet(2),
+      Instruction::LocalGet(2), Instruction::LocalGet(1), Instruction::I32Load(0),
+      Instruction::Numeric(I32_NE),
+      Instruction::If, Instruction::I32Const(0), Instruction::Return, Instruction::End,
+      Instruction::Block, Instruction::Loop,
+        Instruction::LocalGet(2), Instruction::Numeric(I32_EQZ), Instruction::BrIf(1),
+        Instruction::LocalGet(0), Instruction::I32Load8U(4),
+        Instruction::LocalGet(1), Instruction::I32Load8U(4), Instruction::Numeric(I32_NE),
+        Instruction::If, Instruction::I32Const(0), Instruction::Return, Instruction::End,
+        Instruction::LocalGet(0), Instruction::I32Const(1), Instruction::Numeric(I32_ADD),
+        Instruction::LocalSet(0),
+        Instruction::LocalGet(1), Instruction::I32Const(1), Instruction::Numeric(I32_ADD),
+        Instruction::LocalSet(1),
+        Instruction::LocalGet(2), Instruction::I32Const(1), Instruction::Numeric(I32_SUB),
+        Instruction::LocalSet(2),
+        Instruction::Br(0),
+      Instruction::End, Instruction::End,
+      Instruction::I32Const(1),
+    ];
+    self.add_runtime_function("rt_string_eq", vec![i32_type, i32_type], vec![i32_type], vec![i32_type], body);
+
+    // rt_idiv(a, b, line, position at line) -> a / b
+    let body = vec![
+      Instruction::LocalGet(1), Instruction::Numeric(I32_EQZ),
+      Instruction::If,
+        Instruction::LocalGet(2), Instruction::LocalGet(3), Instruction::Call(DIVISION_BY_ZERO),
+        Instruction::Unreachable,
+      Instruction::End,
+      // i32.div_s traps on i32::MIN / -1, negation wraps around instead
+      Instruction::LocalGet(1), Instruction::I32Const(-1), Instruction::Numeric(I32_EQ),
+      Instruction::If,
+        Instruction::I32Const(0), Instruction::LocalGet(0), Instruction::Numeric(I32_SUB),
+        Instruction::Return,
+      Instruction::End,
+      Instruction::LocalGet(0), Instruction::LocalGet(1), Instruction::Numeric(I32_DIV_S),
+    ];
+    self.add_runtime_function("rt_idiv", vec![i32_type, i32_type, i32_type, i32_type], vec![i32_type], vec![], body);
+  }
+
+  fn add_runtime_function(&mut self, name: &str, parameters: Vec<ValueType>, results: Vec<ValueType>,
+                          locals: Vec<ValueType>, body: Vec<Instruction>) {
+    let type_index = self.type_index(parameters, results);
+    self.module.functions.push(wasm::Function {
+      name: name.to_string(),
+      type_index: type_index,
+      locals: locals,
+      body: body,
+    });
+  }
+
+  fn compile_function(&mut self, function: &ast::Function) -> wasm::Function {
+    let parameters = function.parameters.iter().map(|p| value_type(p.param_type)).collect();
+    let type_index = self.type_index(parameters, result_types(function.return_type));
+    self.locals = SymbolTable::new();
+    self.local_types = vec![];
+    self.parameter_count = function.parameters.len() as u32;
+
+    // parameters and the top level of the body share the scope
+    self.locals.push_scope();
+    for (index, parameter) in function.parameters.iter().enumerate() {
+      self.locals.declare(parameter.name, index as u32);
+    }
+
+    for statement in function.body.statements.iter() {
+      self.compile_statement(statement);
+    }
+    self.locals.pop_scope();
+
+    // functions returning a value always end in a return statement
+    if function.return_type != Type::Void {
+      self.body.push(Instruction::Unreachable);
+    }
+
+    wasm::Function {
+      name: format!("fn_{}", self.program.get_text(function.name)),
+      type_index: type_index,
+      locals: mem::replace(&mut self.local_types, vec![]),
+      body: mem::replace(&mut self.body, vec![]),
+    }
+  }
+
+  fn compile_global_initializers(&mut self) -> wasm::Function {
+    let program = self.program;
+    let type_index = self.type_index(vec![], vec![]);
+    self.locals = SymbolTable::new();
+    self.local_types = vec![];
+    self.parameter_count = 0;
+
+    for global in program.globals.iter() {
+      self.compile_expression(&global.initializer);
+      let index = self.global_index(global.name);
+      self.body.push(Instruction::GlobalSet(index));
+    }
+
+    wasm::Function {
+      name: "rt_init_globals".to_string(),
+      type_index: type_index,
+      locals: mem::replace(&mut self.local_types, vec![]),
+      body: mem::replace(&mut self.body, vec![]),
+    }
+  }
+
+  fn compile_block(&mut self, block: &Block) {
+    self.locals.push_scope();
+    for statement in block.statements.iter() {
+      self.compile_statement(statement);
+    }
+    self.locals.pop_scope();
+  }
+
+  fn compile_statement(&mut self, statement: &Statement) {
+    match *statement {
+      Statement::Block(ref block) => self.compile_block(block),
+      Statement::VariableDeclaration(ref declaration) => {
+        // variable is not visible in its own initializer
+        self.compile_expression(&declaration.initializer);
+        let index = self.parameter_count + self.local_types.len() as u32;
+        self.local_types.push(value_type(declaration.var_type));
+        self.locals.declare(declaration.name, index);
+        self.body.push(Instruction::LocalSet(index));
+      },
+      Statement::Assignment(ref assignment) => self.compile_assignment(assignment),
+      Statement::FunctionCall(ref call) => {
+        self.compile_call(call);
+        let returns_value = match self.signatures.get(call.name) {
+          Some(signature) => signature.return_type != Type::Void,
+          None => false,
+        };
+        if returns_value {
+          self.body.push(Instruction::Drop);
+        }
+      },
+      Statement::For(ref for_loop) => {
+        // variable declared in the init clause is only visible inside the loop
+        self.locals.push_scope();
+        match for_loop.init {
+          Some(ref init) => self.compile_statement(&**init),
+          None => { },
+        }
+
+        self.body.push(Instruction::Block);
+        self.body.push(Instruction::Loop);
+        match for_loop.condition {
+          Some(ref condition) => {
+            self.compile_expression(condition);
+            self.body.push(Instruction::Numeric(I32_EQZ));
+            self.body.push(Instruction::BrIf(1));
+          },
+          None => { },
+        }
+
+        self.compile_block(&for_loop.body);
+        match for_loop.update {
+          Some(ref update) => self.compile_assignment(update),
+          None => { },
+        }
+        self.body.push(Instruction::Br(0));
+        self.body.push(Instruction::End);
+        self.body.push(Instruction::End);
+        self.locals.pop_scope();
+      },
+      Statement::If(ref if_statement) => {
+        self.compile_if(&if_statement.condition, &if_statement.block,
+          if_statement.else_ifs.as_slice(), &if_statement.else_block);
+      },
+      Statement::Return(ref return_statement) => {
+        match return_statement.value {
+          Some(ref value) => self.compile_expression(value),
+          None => { },
+        }
+        self.body.push(Instruction::Return);
+      },
+      Statement::Empty(..) => { },
+    }
+  }
+
+  // every 'elif' is an if/else nested in the else branch of the previous condition
+  fn compile_if(&mut self, condition: &Expression, block: &Block, else_ifs: &[ElseIf],
+                else_block: &Option<Block>) {
+    self.compile_expression(condition);
+    self.body.push(Instruction::If);
+    self.compile_block(block);
+
+    if !else_ifs.is_empty() {
+      self.body.push(Instruction::Else);
+      self.compile_if(&else_ifs[0].condition, &else_ifs[0].block, else_ifs.slice_from(1), else_block);
+    } else {
+      match *else_block {
+        Some(ref block) => {
+          self.body.push(Instruction::Else);
+          self.compile_block(block);
+        },
+        None => { },
+      }
+    }
+    self.body.push(Instruction::End);
+  }
+
+  fn compile_assignment(&mut self, assignment: &Assignment) {
+    self.compile_expression(&assignment.value);
+    match self.locals.lookup(assignment.name) {
+      Some(index) => self.body.push(Instruction::LocalSet(index)),
+      None => {
+        let index = self.global_index(assignment.name);
+        self.body.push(Instruction::GlobalSet(index));
+      },
+    }
+  }
+
+  fn compile_call(&mut self, call: &FunctionCall) {
+    for argument in call.arguments.iter() {
+      self.compile_expression(argument);
+    }
+
+    let function = match self.functions.get(&call.name) {
+      Some(index) => Some(*index),
+      None => None,
+    };
+
+    match function {
+      Some(index) => self.body.push(Instruction::Call(index)),
+      None => {
+        let program = self.program;
+        let name = program.get_text(call.name);
+        if name != builtins::PRINT {
+          panic!("Internal compiler error: call to unknown function '{}'", name);
+        }
+
+        let print = match call.arguments[0].get_type() {
+          Type::Integer => PRINT_INT,
+          Type::Float => PRINT_FLOAT,
+          Type::Double => PRINT_DOUBLE,
+          Type::Boolean => PRINT_BOOL,
+          Type::String => PRINT_STRING,
+          Type::Void => panic!("Internal compiler error: void value printed"),
+        };
+        self.body.push(Instruction::Call(print));
+      },
+    }
+  }
+
+  fn compile_expression(&mut self, expression: &Expression) {
+    match expression.kind {
+      ExpressionKind::Literal(literal) => {
+        let instruction = match literal {
+          Literal::Integer(value) => Instruction::I32Const(value),
+          Literal::Float(value) => Instruction::F32Const(value),
+          Literal::Double(value) => Instruction::F64Const(value),
+          Literal::Boolean(value) => Instruction::I32Const(if value { 1 } else { 0 }),
+          Literal::Text(index) => Instruction::I32Const(self.string_address(index) as i32),
+        };
+        self.body.push(instruction);
+      },
+      ExpressionKind::Variable(name) => {
+        match self.locals.lookup(name) {
+          Some(index) => self.body.push(Instruction::LocalGet(index)),
+          None => {
+            let index = self.global_index(name);
+            self.body.push(Instruction::GlobalGet(index));
+          },
+        }
+      },
+      ExpressionKind::Binary(operator, ref left, ref right) => {
+        self.compile_expression(&**left);
+        self.compile_expression(&**right);
+        let instruction = match (left.get_type(), operator) {
+          (Type::Integer, BinaryOperator::Divide) => {
+            self.body.push(Instruction::I32Const(expression.pos.line));
+            self.body.push(Instruction::I32Const(expression.pos.pos_at_line));
+            Instruction::Call(RT_IDIV)
+          },
+          (Type::String, BinaryOperator::Plus) => Instruction::Call(RT_CONCAT),
+          (Type::String, BinaryOperator::Equals) => Instruction::Call(RT_STRING_EQ),
+          (operand_type, operator) => Instruction::Numeric(binary_opcode(operator, operand_type)),
+        };
+        self.body.push(instruction);
+      },
+      ExpressionKind::Call(ref call) => self.compile_call(call),
+      ExpressionKind::Cast(ref inner, target) => {
+        self.compile_expression(&**inner);
+        match conversion_opcode(inner.get_type(), target) {
+          Some(opcode) => self.body.push(Instruction::Numeric(opcode)),
+          None => { },
+        }
+      },
+    }
+  }
+
+  // places the literal in the data segment the first time it is used
+  fn string_address(&mut self, index: usize) -> u32 {
+    match self.strings.get(&index) {
+      Some(address) => return *address,
+      None => { },
+    }
+
+    while self.data.len() % 4 != 0 {
+      self.data.push(0);
+    }
+    let program = self.program;
+    let address = DATA_START + self.data.len() as u32;
+    let bytes = program.get_text(index).as_bytes();
+    let length = bytes.len() as u32;
+    for shift in [0, 8, 16, 24].iter() {
+      self.data.push((length >> *shift) as u8);
+    }
+    self.data.push_all(bytes);
+    self.strings.insert(index, address);
+    address
+  }
+
+  fn function_index(&self, name: usize) -> u32 {
+    match self.functions.get(&name) {
+      Some(index) => *index,
+      None => panic!("Internal compiler error: undeclared function '{}'",
+        self.program.get_text(name)),
+    }
+  }
+
+  fn global_index(&self, name: usize) -> u32 {
+    match self.globals.get(&name) {
+      Some(index) => *index,
+      None => panic!("Internal compiler error: undeclared variable '{}'",
+        self.program.get_text(name)),
+    }
+  }
+
+  fn type_index(&mut self, parameters: Vec<ValueType>, results: Vec<ValueType>) -> u32 {
+    let function_type = FunctionType { parameters: parameters, results: results };
+    match self.module.types.iter().position(|existing| *existing == function_type) {
+      Some(index) => index as u32,
+      None => {
+        self.module.types.push(function_type);
+        (self.module.types.len() - 1) as u32
+      },
+    }
+  }
+}
+
+fn value_type(var_type: Type) -> ValueType {
+  match var_type {
+    Type::Integer | Type::Boolean | Type::String => ValueType::I32,
+    Type::Float => ValueType::F32,
+    Type::Double => ValueType::F64,
+    Type::Void => panic!("Internal compiler error: void has no WebAssembly type"),
+  }
+}
+
+fn result_types(return_type: Type) -> Vec<ValueType> {
+  match return_type {
+    Type::Void => vec![],
+    return_type => vec![value_type(return_type)],
+  }
+}
+
+fn zero(value_type: ValueType) -> Instruction {
+  match value_type {
+    ValueType::I32 => Instruction::I32Const(0),
+    ValueType::F32 => Instruction::F32Const(0.0),
+    ValueType::F64 => Instruction::F64Const(0.0),
+  }
+}
+
+fn align(value: u32, alignment: u32) -> u32 {
+  (value + alignment - 1) / alignment * alignment
+}
+
+// the type checker makes both operands the same type
+fn binary_opcode(operator: BinaryOperator, operand_type: Type) -> u8 {
+  match (operand_type, operator) {
+    (Type::Integer, BinaryOperator::Plus) => I32_ADD,
+    (Type::Integer, BinaryOperator::Minus) => I32_SUB,
+    (Type::Integer, BinaryOperator::Multiply) => I32_MUL,
+    (Type::Integer, BinaryOperator::Equals) => I32_EQ,
+    (Type::Integer, BinaryOperator::Lesser) => I32_LT_S,
+    (Type::Integer, BinaryOperator::Greater) => I32_GT_S,
+    (Type::Integer, BinaryOperator::LesserOrEq) => I32_LE_S,
+    (Type::Integer, BinaryOperator::GreaterOrEq) => I32_GE_S,
+    (Type::Float, BinaryOperator::Plus) => F32_ADD,
+    (Type::Float, BinaryOperator::Minus) => F32_SUB,
+    (Type::Float, BinaryOperator::Multiply) => F32_MUL,
+    (Type::Float, BinaryOperator::Divide) => F32_DIV,
+    (Type::Float, BinaryOperator::Equals) => F32_EQ,
+    (Type::Float, BinaryOperator::Lesser) => F32_LT,
+    (Type::Float, BinaryOperator::Greater) => F32_GT,
+    (Type::Float, BinaryOperator::LesserOrEq) => F32_LE,
+    (Type::Float, BinaryOperator::GreaterOrEq) => F32_GE,
+    (Type::Double, BinaryOperator::Plus) => F64_ADD,
+    (Type::Double, BinaryOperator::Minus) => F64_SUB,
+    (Type::Double, BinaryOperator::Multiply) => F64_MUL,
+    (Type::Double, BinaryOperator::Divide) => F64_DIV,
+    (Type::Double, BinaryOperator::Equals) => F64_EQ,
+    (Type::Double, BinaryOperator::Lesser) => F64_LT,
+    (Type::Double, BinaryOperator::Greater) => F64_GT,
+    (Type::Double, BinaryOperator::LesserOrEq) => F64_LE,
+    (Type::Double, BinaryOperator::GreaterOrEq) => F64_GE,
+    (Type::Boolean, BinaryOperator::Equals) => I32_EQ,
+    (operand_type, operator) => panic!(
+      "Internal compiler error: operator {} applied to {}", operator, operand_type),
+  }
+}
+
+fn conversion_opcode(from: Type, to: Type) -> Option<u8> {
+  match (from, to) {
+    (Type::Integer, Type::Float) => Some(F32_CONVERT_I32_S),
+    (Type::Integer, Type::Double) => Some(F64_CONVERT_I32_S),
+    (Type::Float, Type::Integer) => Some(I32_TRUNC_F32_S),
+    (Type::Float, Type::Double) => Some(F64_PROMOTE_F32),
+    (Type::Double, Type::Integer) => Some(I32_TRUNC_F64_S),
+    (Type::Double, Type::Float) => Some(F32_DEMOTE_F64),
+    _ => None,
+  }
+}
@@ -0,0 +1,295 @@
+use std::fmt;
+
+pub mod compiler;
+pub mod text;
+pub mod binary;
+pub mod validator;
+
+/*
+  WebAssembly backend. The compiler lowers a type checked program into an
+  in-memory module, which is printed in the text format (.wat) or encoded in
+  the binary format (.wasm). The validator checks the structure and the
+  instruction typing of encoded modules, so the output can be tested without
+  a WebAssembly runtime.
+
+  Only the subset of WebAssembly 1.0 the compiler needs is modelled: function
+  imports, one linear memory, globals, exports, data segments and blocks
+  without results.
+
+  Function indices count the imports first, followed by the functions defined
+  in the module. The body of a function does not contain its final 'end'.
+*/
+
+pub static MAGIC: [u8; 4] = [0x00, 0x61, 0x73, 0x6D];
+pub static VERSION: [u8; 4] = [0x01, 0x00, 0x00, 0x00];
+pub static PAGE_SIZE: u32 = 65536;
+
+// block type of blocks without parameters and results
+pub const EMPTY_BLOCK: u8 = 0x40;
+pub const FUNCTION_TYPE: u8 = 0x60;
+
+pub const UNREACHABLE: u8 = 0x00;
+pub const BLOCK: u8 = 0x02;
+pub const LOOP: u8 = 0x03;
+pub const IF: u8 = 0x04;
+pub const ELSE: u8 = 0x05;
+pub const END: u8 = 0x0B;
+pub const BR: u8 = 0x0C;
+pub const BR_IF: u8 = 0x0D;
+pub const RETURN: u8 = 0x0F;
+pub const CALL: u8 = 0x10;
+pub const DROP: u8 = 0x1A;
+pub const LOCAL_GET: u8 = 0x20;
+pub const LOCAL_SET: u8 = 0x21;
+pub const GLOBAL_GET: u8 = 0x23;
+pub const GLOBAL_SET: u8 = 0x24;
+pub const I32_LOAD: u8 = 0x28;
+pub const I32_LOAD8_U: u8 = 0x2D;
+pub const I32_STORE: u8 = 0x36;
+pub const I32_STORE8: u8 = 0x3A;
+pub const MEMORY_SIZE: u8 = 0x3F;
+pub const MEMORY_GROW: u8 = 0x40;
+pub const I32_CONST: u8 = 0x41;
+pub const F32_CONST: u8 = 0x43;
+pub const F64_CONST: u8 = 0x44;
+
+pub const I32_EQZ: u8 = 0x45;
+pub const I32_EQ: u8 = 0x46;
+pub const I32_NE: u8 = 0x47;
+pub const I32_LT_S: u8 = 0x48;
+pub const I32_GT_S: u8 = 0x4A;
+pub const I32_GT_U: u8 = 0x4B;
+pub const I32_LE_S: u8 = 0x4C;
+pub const I32_GE_S: u8 = 0x4E;
+pub const F32_EQ: u8 = 0x5B;
+pub const F32_LT: u8 = 0x5D;
+pub const F32_GT: u8 = 0x5E;
+pub const F32_LE: u8 = 0x5F;
+pub const F32_GE: u8 = 0x60;
+pub const F64_EQ: u8 = 0x61;
+pub const F64_LT: u8 = 0x63;
+pub const F64_GT: u8 = 0x64;
+pub const F64_LE: u8 = 0x65;
+pub const F64_GE: u8 = 0x66;
+pub const I32_ADD: u8 = 0x6A;
+pub const I32_SUB: u8 = 0x6B;
+pub const I32_MUL: u8 = 0x6C;
+pub const I32_DIV_S: u8 = 0x6D;
+pub const I32_AND: u8 = 0x71;
+pub const I32_SHL: u8 = 0x74;
+pub const I32_SHR_U: u8 = 0x76;
+pub const F32_ADD: u8 = 0x92;
+pub const F32_SUB: u8 = 0x93;
+pub const F32_MUL: u8 = 0x94;
+pub const F32_DIV: u8 = 0x95;
+pub const F64_ADD: u8 = 0xA0;
+pub const F64_SUB: u8 = 0xA1;
+pub const F64_MUL: u8 = 0xA2;
+pub const F64_DIV: u8 = 0xA3;
+pub const I32_TRUNC_F32_S: u8 = 0xA8;
+pub const I32_TRUNC_F64_S: u8 = 0xAA;
+pub const F32_CONVERT_I32_S: u8 = 0xB2;
+pub const F32_DEMOTE_F64: u8 = 0xB6;
+pub const F64_CONVERT_I32_S: u8 = 0xB7;
+pub const F64_PROMOTE_F32: u8 = 0xBB;
+
+// name, operand types and result type of an instruction without immediates.
+// None for other opcodes
+pub fn numeric_info(opcode: u8) -> Option<(&'static str, &'static [ValueType], ValueType)> {
+  static UNARY_I32: [ValueType; 1] = [ValueType::I32];
+  static UNARY_F32: [ValueType; 1] = [ValueType::F32];
+  static UNARY_F64: [ValueType; 1] = [ValueType::F64];
+  static BINARY_I32: [ValueType; 2] = [ValueType::I32, ValueType::I32];
+  static BINARY_F32: [ValueType; 2] = [ValueType::F32, ValueType::F32];
+  static BINARY_F64: [ValueType; 2] = [ValueType::F64, ValueType::F64];
+
+  let info = match opcode {
+    I32_EQZ => ("i32.eqz", UNARY_I32.as_slice(), ValueType::I32),
+    I32_EQ => ("i32.eq", BINARY_I32.as_slice(), ValueType::I32),
+    I32_NE => ("i32.ne", BINARY_I32.as_slice(), ValueType::I32),
+    I32_LT_S => ("i32.lt_s", BINARY_I32.as_slice(), ValueType::I32),
+    I32_GT_S => ("i32.gt_s", BINARY_I32.as_slice(), ValueType::I32),
+    I32_GT_U => ("i32.gt_u", BINARY_I32.as_slice(), ValueType::I32),
+    I32_LE_S => ("i32.le_s", BINARY_I32.as_slice(), ValueType::I32),
+    I32_GE_S => ("i32.ge_s", BINARY_I32.as_slice(), ValueType::I32),
+    F32_EQ => ("f32.eq", BINARY_F32.as_slice(), ValueType::I32),
+    F32_LT => ("f32.lt", BINARY_F32.as_slice(), ValueType::I32),
+    F32_GT => ("f32.gt", BINARY_F32.as_slice(), ValueType::I32),
+    F32_LE => ("f32.le", BINARY_F32.as_slice(), ValueType::I32),
+    F32_GE => ("f32.ge", BINARY_F32.as_slice(), ValueType::I32),
+    F64_EQ => ("f64.eq", BINARY_F64.as_slice(), ValueType::I32),
+    F64_LT => ("f64.lt", BINARY_F64.as_slice(), ValueType::I32),
+    F64_GT => ("f64.gt", BINARY_F64.as_slice(), ValueType::I32),
+    F64_LE => ("f64.le", BINARY_F64.as_slice(), ValueType::I32),
+    F64_GE => ("f64.ge", BINARY_F64.as_slice(), ValueType::I32),
+    I32_ADD => ("i32.add", BINARY_I32.as_slice(), ValueType::I32),
+    I32_SUB => ("i32.sub", BINARY_I32.as_slice(), ValueType::I32),
+    I32_MUL => ("i32.mul", BINARY_I32.as_slice(), ValueType::I32),
+    I32_DIV_S => ("i32.div_s", BINARY_I32.as_slice(), ValueType::I32),
+    I32_AND => ("i32.and", BINARY_I32.as_slice(), ValueType::I32),
+    I32_SHL => ("i32.shl", BINARY_I32.as_slice(), ValueType::I32),
+    I32_SHR_U => ("i32.shr_u", BINARY_I32.as_slice(), ValueType::I32),
+    F32_ADD => ("f32.add", BINARY_F32.as_slice(), ValueType::F32),
+    F32_SUB => ("f32.sub", BINARY_F32.as_slice(), ValueType::F32),
+    F32_MUL => ("f32.mul", BINARY_F32.as_slice(), ValueType::F32),
+    F32_DIV => ("f32.div", BINARY_F32.as_slice(), ValueType::F32),
+    F64_ADD => ("f64.add", BINARY_F64.as_slice(), ValueType::F64),
+    F64_SUB => ("f64.sub", BINARY_F64.as_slice(), ValueType::F64),
+    F64_MUL => ("f64.mul", BINARY_F64.as_slice(), ValueType::F64),
+    F64_DIV => ("f64.div", BINARY_F64.as_slice(), ValueType::F64),
+    I32_TRUNC_F32_S => ("i32.trunc_f32_s", UNARY_F32.as_slice(), ValueType::I32),
+    I32_TRUNC_F64_S => ("i32.trunc_f64_s", UNARY_F64.as_slice(), ValueType::I32),
+    F32_CONVERT_I32_S => ("f32.convert_i32_s", UNARY_I32.as_slice(), ValueType::F32),
+    F32_DEMOTE_F64 => ("f32.demote_f64", UNARY_F64.as_slice(), ValueType::F32),
+    F64_CONVERT_I32_S => ("f64.convert_i32_s", UNARY_I32.as_slice(), ValueType::F64),
+    F64_PROMOTE_F32 => ("f64.promote_f32", UNARY_F32.as_slice(), ValueType::F64),
+    _ => return None,
+  };
+  Some(info)
+}
+
+#[derive(Show, Clone, Copy, PartialEq, Eq)]
+pub enum ValueType {
+  I32,
+  F32,
+  F64,
+}
+
+impl ValueType {
+  pub fn code(&self) -> u8 {
+    match *self {
+      ValueType::I32 => 0x7F,
+      ValueType::F32 => 0x7D,
+      ValueType::F64 => 0x7C,
+    }
+  }
+
+  pub fn from_code(code: u8) -> Option<ValueType> {
+    match code {
+      0x7F => Some(ValueType::I32),
+      0x7D => Some(ValueType::F32),
+      0x7C => Some(ValueType::F64),
+      _ => None,
+    }
+  }
+}
+
+impl fmt::String for ValueType {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    fmt::String::fmt(
+      match *self {
+        ValueType::I32 => "i32",
+        ValueType::F32 => "f32",
+        ValueType::F64 => "f64",
+      }, formatter)
+  }
+}
+
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Instruction {
+  Unreachable,
+  Block,
+  Loop,
+  If,
+  Else,
+  End,
+  // operand: relative depth of the target block
+  Br(u32),
+  BrIf(u32),
+  Return,
+  Call(u32),
+  Drop,
+  LocalGet(u32),
+  LocalSet(u32),
+  GlobalGet(u32),
+  GlobalSet(u32),
+  // operand: static address offset
+  I32Load(u32),
+  I32Load8U(u32),
+  I32Store(u32),
+  I32Store8(u32),
+  MemorySize,
+  MemoryGrow,
+  I32Const(i32),
+  F32Const(f32),
+  F64Const(f64),
+  // instruction without immediates, see numeric_info
+  Numeric(u8),
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct FunctionType {
+  pub parameters: Vec<ValueType>,
+  pub results: Vec<ValueType>,
+}
+
+// imported function
+#[derive(Show, Clone)]
+pub struct Import {
+  pub module: String,
+  pub name: String,
+  pub type_index: u32,
+}
+
+#[derive(Show, Clone)]
+pub struct Function {
+  // only used in the text format
+  pub name: String,
+  pub type_index: u32,
+  // locals following the parameters
+  pub locals: Vec<ValueType>,
+  pub body: Vec<Instruction>,
+}
+
+#[derive(Show, Clone)]
+pub struct Global {
+  // only used in the text format
+  pub name: String,
+  pub value_type: ValueType,
+  pub mutable: bool,
+  // constant instruction
+  pub init: Instruction,
+}
+
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum ExportKind {
+  Function(u32),
+  Memory(u32),
+}
+
+#[derive(Show, Clone)]
+pub struct Export {
+  pub name: String,
+  pub kind: ExportKind,
+}
+
+// bytes copied into the memory at instantiation
+#[derive(Show, Clone)]
+pub struct Data {
+  pub offset: u32,
+  pub bytes: Vec<u8>,
+}
+
+#[derive(Show, Clone)]
+pub struct Module {
+  pub types: Vec<FunctionType>,
+  pub imports: Vec<Import>,
+  pub functions: Vec<Function>,
+  pub globals: Vec<Global>,
+  // initial size of the memory
+  pub memory_pages: u32,
+  pub exports: Vec<Export>,
+  pub data: Vec<Data>,
+}
+
+impl Module {
+  // name of the function with the given index in the function index space
+  pub fn function_name(&self, index: u32) -> &str {
+    let index = index as usize;
+    if index < self.imports.len() {
+      self.imports[index].name.as_slice()
+    } else {
+      self.functions[index - self.imports.len()].name.as_slice()
+    }
+  }
+}
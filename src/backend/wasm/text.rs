@@ -0,0 +1,214 @@
+use std::mem;
+use backend::wasm::*;
+
+/*
+  Prints a module in the WebAssembly text format. Instructions are written
+  in the flat (unfolded) syntax, indented by block nesting. Functions and
+  globals are referred to by name, locals by index. Float constants are
+  written as exact hexadecimal floats, followed by their decimal value in a
+  comment.
+*/
+
+pub fn print(module: &Module) -> String {
+  let mut output = "(module\n".to_string();
+
+  for (index, function_type) in module.types.iter().enumerate() {
+    output.push_str(format!("  (type (;{};) (func{}))\n", index, signature(function_type)).as_slice());
+  }
+
+  for import in module.imports.iter() {
+    output.push_str(format!("  (import {} {} (func ${} (type {})))\n",
+      string(import.module.as_bytes()), string(import.name.as_bytes()),
+      import.name, import.type_index).as_slice());
+  }
+
+  output.push_str(format!("  (memory (;0;) {})\n", module.memory_pages).as_slice());
+
+  for global in module.globals.iter() {
+    let global_type = if global.mutable {
+      format!("(mut {})", global.value_type)
+    } else {
+      format!("{}", global.value_type)
+    };
+    output.push_str(format!("  (global ${} {} ({}))\n", global.name, global_type,
+      instruction_text(module, &global.init)).as_slice());
+  }
+
+  for function in module.functions.iter() {
+    print_function(module, function, &mut output);
+  }
+
+  for export in module.exports.iter() {
+    let target = match export.kind {
+      ExportKind::Function(index) => format!("func ${}", module.function_name(index)),
+      ExportKind::Memory(index) => format!("memory {}", index),
+    };
+    output.push_str(format!("  (export {} ({}))\n", string(export.name.as_bytes()), target).as_slice());
+  }
+
+  for data in module.data.iter() {
+    output.push_str(format!("  (data (i32.const {}) {})\n", data.offset,
+      string(data.bytes.as_slice())).as_slice());
+  }
+
+  output.push_str(")\n");
+  output
+}
+
+fn print_function(module: &Module, function: &Function, output: &mut String) {
+  let function_type = &module.types[function.type_index as usize];
+  output.push_str(format!("  (func ${} (type {}){}", function.name, function.type_index,
+    signature(function_type)).as_slice());
+  if !function.locals.is_empty() {
+    let locals: Vec<String> = function.locals.iter().map(|local| format!("{}", local)).collect();
+    output.push_str(format!(" (local {})", locals.connect(" ")).as_slice());
+  }
+  output.push('\n');
+
+  let mut depth = 2;
+  for instruction in function.body.iter() {
+    match *instruction {
+      Instruction::Else | Instruction::End => depth -= 1,
+      _ => { },
+    }
+    for _ in range(0, depth) {
+      output.push_str("  ");
+    }
+    output.push_str(instruction_text(module, instruction).as_slice());
+    output.push('\n');
+    match *instruction {
+      Instruction::Block | Instruction::Loop | Instruction::If | Instruction::Else => depth += 1,
+      _ => { },
+    }
+  }
+  output.push_str("  )\n");
+}
+
+fn instruction_text(module: &Module, instruction: &Instruction) -> String {
+  match *instruction {
+    Instruction::Unreachable => "unreachable".to_string(),
+    Instruction::Block => "block".to_string(),
+    Instruction::Loop => "loop".to_string(),
+    Instruction::If => "if".to_string(),
+    Instruction::Else => "else".to_string(),
+    Instruction::End => "end".to_string(),
+    Instruction::Br(depth) => format!("br {}", depth),
+    Instruction::BrIf(depth) => format!("br_if {}", depth),
+    Instruction::Return => "return".to_string(),
+    Instruction::Call(index) => format!("call ${}", module.function_name(index)),
+    Instruction::Drop => "drop".to_string(),
+    Instruction::LocalGet(index) => format!("local.get {}", index),
+    Instruction::LocalSet(index) => format!("local.set {}", index),
+    Instruction::GlobalGet(index) => format!("global.get ${}", module.globals[index as usize].name),
+    Instruction::GlobalSet(index) => format!("global.set ${}", module.globals[index as usize].name),
+    Instruction::I32Load(offset) => memory_access("i32.load", offset),
+    Instruction::I32Load8U(offset) => memory_access("i32.load8_u", offset),
+    Instruction::I32Store(offset) => memory_access("i32.store", offset),
+    Instruction::I32Store8(offset) => memory_access("i32.store8", offset),
+    Instruction::MemorySize => "memory.size".to_string(),
+    Instruction::MemoryGrow => "memory.grow".to_string(),
+    Instruction::I32Const(value) => format!("i32.const {}", value),
+    Instruction::F32Const(value) => {
+      let bits = unsafe { mem::transmute::<f32, u32>(value) } as u64;
+      format!("f32.const {} (;{};)", hex_float(bits, 23, 8), value)
+    },
+    Instruction::F64Const(value) => {
+      let bits = unsafe { mem::transmute::<f64, u64>(value) };
+      format!("f64.const {} (;{};)", hex_float(bits, 52, 11), value)
+    },
+    Instruction::Numeric(opcode) => {
+      match numeric_info(opcode) {
+        Some((name, _, _)) => name.to_string(),
+        None => format!("(;invalid opcode 0x{:02x};)", opcode),
+      }
+    },
+  }
+}
+
+fn memory_access(name: &str, offset: u32) -> String {
+  if offset == 0 {
+    name.to_string()
+  } else {
+    format!("{} offset={}", name, offset)
+  }
+}
+
+// " (param ...) (result ...)", parts without types are left out
+fn signature(function_type: &FunctionType) -> String {
+  let mut text = String::new();
+  if !function_type.parameters.is_empty() {
+    let parameters: Vec<String> = function_type.parameters.iter().map(|p| format!("{}", p)).collect();
+    text.push_str(format!(" (param {})", parameters.connect(" ")).as_slice());
+  }
+  if !function_type.results.is_empty() {
+    let results: Vec<String> = function_type.results.iter().map(|r| format!("{}", r)).collect();
+    text.push_str(format!(" (result {})", results.connect(" ")).as_slice());
+  }
+  text
+}
+
+// printable ascii is kept, everything else is written as \hh
+fn string(bytes: &[u8]) -> String {
+  let mut text = "\"".to_string();
+  for byte in bytes.iter() {
+    match *byte {
+      b'"' | b'\\' => text.push_str(format!("\\{:02x}", *byte).as_slice()),
+      0x20 ... 0x7E => text.push(*byte as char),
+      _ => text.push_str(format!("\\{:02x}", *byte).as_slice()),
+    }
+  }
+  text.push('"');
+  text
+}
+
+// exact text of an IEEE 754 value given as bits, e.g. 0x1.4p+1 for 2.5
+fn hex_float(bits: u64, mantissa_bits: usize, exponent_bits: usize) -> String {
+  let negative = (bits >> (mantissa_bits + exponent_bits)) & 1 == 1;
+  let exponent = ((bits >> mantissa_bits) & ((1 << exponent_bits) - 1)) as i64;
+  let mantissa = bits & ((1 << mantissa_bits) - 1);
+  let max_exponent = (1 << exponent_bits) - 1;
+  let bias = (1 << (exponent_bits - 1)) - 1;
+
+  let magnitude = if exponent == max_exponent {
+    if mantissa == 0 {
+      "inf".to_string()
+    } else {
+      format!("nan:0x{:x}", mantissa)
+    }
+  } else if exponent == 0 && mantissa == 0 {
+    "0x0p+0".to_string()
+  } else {
+    // subnormals have no implicit leading one
+    let (leading, exponent) = if exponent == 0 { (0, 1 - bias) } else { (1, exponent - bias) };
+    let sign = if exponent < 0 { "-" } else { "+" };
+    let fraction = hex_fraction(mantissa, mantissa_bits);
+    if fraction.is_empty() {
+      format!("0x{}p{}{}", leading, sign, exponent.abs())
+    } else {
+      format!("0x{}.{}p{}{}", leading, fraction, sign, exponent.abs())
+    }
+  };
+
+  if negative {
+    format!("-{}", magnitude)
+  } else {
+    magnitude
+  }
+}
+
+// hexadecimal digits of the mantissa without trailing zeros
+fn hex_fraction(mantissa: u64, mantissa_bits: usize) -> String {
+  // pad the mantissa to whole hexadecimal digits
+  let digit_count = (mantissa_bits + 3) / 4;
+  let mut value = mantissa << (digit_count * 4 - mantissa_bits);
+  let mut digits = vec![];
+  for _ in range(0, digit_count) {
+    digits.push(value & 0xF);
+    value >>= 4;
+  }
+  digits.reverse();
+  while digits.last() == Some(&0) {
+    digits.pop();
+  }
+  digits.iter().map(|digit| format!("{:x}", *digit)).collect::<Vec<String>>().connect("")
+}
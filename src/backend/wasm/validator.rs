@@ -0,0 +1,885 @@
+use std::collections::HashSet;
+use std::i32;
+use std::str;
+use std::u32;
+use backend::wasm::*;
+
+/*
+  Structural validator for binary WebAssembly modules, so generated code can
+  be checked without a WebAssembly runtime. Checks:
+    -the header, and the order and sizes of the sections
+    -type, function, global and memory indices refer to existing entities
+    -export names are unique, initializers and data offsets are constants of
+     the right type and data segments fit into the initial memory
+    -every function body is well typed: each instruction finds operands of
+     the right types on the stack, blocks are properly nested and end with
+     their results on the stack, branches target enclosing blocks and
+     execution can not fall off the end of a function without its results
+
+  Only the subset of WebAssembly 1.0 produced by the compiler is supported;
+  tables, imports other than functions, blocks with results and all other
+  instructions are reported as unsupported.
+
+  Errors inside a function body are reported per function and validation
+  continues with the next function. Any other error ends the validation.
+*/
+
+pub fn validate(bytes: &[u8]) -> Result<(), Vec<String>> {
+  let mut validator = Validator {
+    reader: Reader { bytes: bytes, offset: 0 },
+    types: vec![],
+    functions: vec![],
+    imported_functions: 0,
+    globals: vec![],
+    memory_pages: None,
+    errors: vec![],
+  };
+
+  match validator.validate_module() {
+    Ok(()) => { },
+    Err(error) => validator.errors.push(error),
+  }
+
+  if validator.errors.is_empty() {
+    Ok(())
+  } else {
+    Err(validator.errors)
+  }
+}
+
+static CUSTOM_SECTION: u8 = 0;
+static TYPE_SECTION: u8 = 1;
+static IMPORT_SECTION: u8 = 2;
+static FUNCTION_SECTION: u8 = 3;
+static TABLE_SECTION: u8 = 4;
+static MEMORY_SECTION: u8 = 5;
+static GLOBAL_SECTION: u8 = 6;
+static EXPORT_SECTION: u8 = 7;
+static START_SECTION: u8 = 8;
+static ELEMENT_SECTION: u8 = 9;
+static CODE_SECTION: u8 = 10;
+static DATA_SECTION: u8 = 11;
+
+static MAX_PAGES: u32 = 65536;
+// guards against allocating huge local tables for corrupt input
+static MAX_LOCALS: u32 = 50000;
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn at_end(&self) -> bool {
+    self.offset >= self.bytes.len()
+  }
+
+  fn read_byte(&mut self, what: &str) -> Result<u8, String> {
+    let bytes = try!(self.read_bytes(1, what));
+    Ok(bytes[0])
+  }
+
+  fn read_bytes(&mut self, count: usize, what: &str) -> Result<&'a [u8], String> {
+    if count > self.bytes.len() - self.offset {
+      return Err(format!(
+        "Truncated WebAssembly module: expected {} byte(s) of {} at byte {}, but only {} remain",
+        count, what, self.offset, self.bytes.len() - self.offset));
+    }
+
+    let bytes: &'a [u8] = self.bytes;
+    let result = bytes.slice(self.offset, self.offset + count);
+    self.offset += count;
+    Ok(result)
+  }
+
+  // unsigned LEB128
+  fn read_u32(&mut self, what: &str) -> Result<u32, String> {
+    let start = self.offset;
+    let mut result: u64 = 0;
+    let mut shift = 0;
+    loop {
+      let byte = try!(self.read_byte(what));
+      result |= ((byte & 0x7F) as u64) << shift;
+      if byte & 0x80 == 0 {
+        break;
+      }
+      shift += 7;
+      if shift >= 35 {
+        return Err(error_at(start, format!("{} is longer than 5 bytes", what).as_slice()));
+      }
+    }
+
+    if result > u32::MAX as u64 {
+      return Err(error_at(start, format!("{} does not fit into 32 bits", what).as_slice()));
+    }
+    Ok(result as u32)
+  }
+
+  // signed LEB128
+  fn read_i32(&mut self, what: &str) -> Result<i32, String> {
+    let start = self.offset;
+    let mut result: i64 = 0;
+    let mut shift = 0;
+    loop {
+      let byte = try!(self.read_byte(what));
+      result |= ((byte & 0x7F) as i64) << shift;
+      shift += 7;
+      if byte & 0x80 == 0 {
+        if byte & 0x40 != 0 {
+          result |= -1i64 << shift;
+        }
+        break;
+      }
+      if shift >= 35 {
+        return Err(error_at(start, format!("{} is longer than 5 bytes", what).as_slice()));
+      }
+    }
+
+    if result < i32::MIN as i64 || result > i32::MAX as i64 {
+      return Err(error_at(start, format!("{} does not fit into 32 bits", what).as_slice()));
+    }
+    Ok(result as i32)
+  }
+
+  fn read_name(&mut self, what: &str) -> Result<String, String> {
+    let length = try!(self.read_u32(what)) as usize;
+    let start = self.offset;
+    let bytes = try!(self.read_bytes(length, what));
+    match str::from_utf8(bytes) {
+      Ok(name) => Ok(name.to_string()),
+      Err(..) => Err(error_at(start, format!("{} is not valid utf-8", what).as_slice())),
+    }
+  }
+
+  fn read_value_type(&mut self, what: &str) -> Result<ValueType, String> {
+    let start = self.offset;
+    let code = try!(self.read_byte(what));
+    match ValueType::from_code(code) {
+      Some(value_type) => Ok(value_type),
+      None => Err(error_at(start, format!("0x{:02x} is not a supported value type", code).as_slice())),
+    }
+  }
+}
+
+fn error_at(offset: usize, msg: &str) -> String {
+  format!("Invalid WebAssembly module at byte {}: {}", offset, msg)
+}
+
+struct Validator<'a> {
+  reader: Reader<'a>,
+  types: Vec<FunctionType>,
+  // type index of every function, imports first
+  functions: Vec<u32>,
+  imported_functions: usize,
+  // type and mutability
+  globals: Vec<(ValueType, bool)>,
+  memory_pages: Option<u32>,
+  errors: Vec<String>,
+}
+
+impl<'a> Validator<'a> {
+  fn validate_module(&mut self) -> Result<(), String> {
+    let magic = try!(self.reader.read_bytes(MAGIC.len(), "magic number"));
+    if magic != MAGIC.as_slice() {
+      return Err(error_at(0, "bad magic number"));
+    }
+    let version = try!(self.reader.read_bytes(VERSION.len(), "version"));
+    if version != VERSION.as_slice() {
+      return Err(error_at(MAGIC.len(), "unsupported version"));
+    }
+
+    let mut last_id = 0;
+    let mut code_seen = false;
+    while !self.reader.at_end() {
+      let section_offset = self.reader.offset;
+      let id = try!(self.reader.read_byte("section id"));
+      let size = try!(self.reader.read_u32("section size")) as usize;
+      let start = self.reader.offset;
+      if size > self.reader.bytes.len() - start {
+        return Err(error_at(section_offset, format!(
+          "section {} has size {}, but only {} bytes remain", id, size, self.reader.bytes.len() - start).as_slice()));
+      }
+
+      if id != CUSTOM_SECTION {
+        if id <= last_id {
+          return Err(error_at(section_offset, format!("section {} is duplicated or out of order", id).as_slice()));
+        }
+        last_id = id;
+      }
+
+      if id == CUSTOM_SECTION {
+        try!(self.reader.read_name("custom section name"));
+        self.reader.offset = start + size;
+      } else if id == TYPE_SECTION {
+        try!(self.validate_types());
+      } else if id == IMPORT_SECTION {
+        try!(self.validate_imports());
+      } else if id == FUNCTION_SECTION {
+        try!(self.validate_function_declarations());
+      } else if id == MEMORY_SECTION {
+        try!(self.validate_memories());
+      } else if id == GLOBAL_SECTION {
+        try!(self.validate_globals());
+      } else if id == EXPORT_SECTION {
+        try!(self.validate_exports());
+      } else if id == START_SECTION {
+        try!(self.validate_start());
+      } else if id == CODE_SECTION {
+        code_seen = true;
+        try!(self.validate_code());
+      } else if id == DATA_SECTION {
+        try!(self.validate_data());
+      } else if id == TABLE_SECTION || id == ELEMENT_SECTION {
+        return Err(error_at(section_offset, "tables are not supported"));
+      } else {
+        return Err(error_at(section_offset, format!("unknown section id {}", id).as_slice()));
+      }
+
+      if self.reader.offset != start + size {
+        return Err(error_at(start, format!("section {} has size {}, but its contents take {} bytes",
+          id, size, self.reader.offset - start).as_slice()));
+      }
+    }
+
+    if !code_seen && self.functions.len() > self.imported_functions {
+      return Err(error_at(self.reader.offset, "functions are declared, but there is no code section"));
+    }
+    Ok(())
+  }
+
+  fn validate_types(&mut self) -> Result<(), String> {
+    let count = try!(self.reader.read_u32("type count"));
+    for _ in range(0, count) {
+      let offset = self.reader.offset;
+      if try!(self.reader.read_byte("function type")) != FUNCTION_TYPE {
+        return Err(error_at(offset, "expected a function type"));
+      }
+      let parameters = try!(self.read_value_types("parameter types"));
+      let results = try!(self.read_value_types("result types"));
+      if results.len() > 1 {
+        return Err(error_at(offset, "functions can not have more than one result"));
+      }
+      self.types.push(FunctionType { parameters: parameters, results: results });
+    }
+    Ok(())
+  }
+
+  fn validate_imports(&mut self) -> Result<(), String> {
+    let count = try!(self.reader.read_u32("import count"));
+    for _ in range(0, count) {
+      try!(self.reader.read_name("import module name"));
+      try!(self.reader.read_name("import name"));
+      let offset = self.reader.offset;
+      if try!(self.reader.read_byte("import kind")) != 0x00 {
+        return Err(error_at(offset, "only function imports are supported"));
+      }
+      let type_index = try!(self.read_type_index());
+      self.functions.push(type_index);
+      self.imported_functions += 1;
+    }
+    Ok(())
+  }
+
+  fn validate_function_declarations(&mut self) -> Result<(), String> {
+    let count = try!(self.reader.read_u32("function count"));
+    for _ in range(0, count) {
+      let type_index = try!(self.read_type_index());
+      self.functions.push(type_index);
+    }
+    Ok(())
+  }
+
+  fn validate_memories(&mut self) -> Result<(), String> {
+    let offset = self.reader.offset;
+    let count = try!(self.reader.read_u32("memory count"));
+    if count > 1 {
+      return Err(error_at(offset, "there can be at most one memory"));
+    }
+    for _ in range(0, count) {
+      let limits_offset = self.reader.offset;
+      let flags = try!(self.reader.read_byte("memory limits"));
+      let minimum = try!(self.reader.read_u32("minimum memory size"));
+      if flags == 0x01 {
+        let maximum = try!(self.reader.read_u32("maximum memory size"));
+        if maximum < minimum || maximum > MAX_PAGES {
+          return Err(error_at(limits_offset, "invalid maximum memory size"));
+        }
+      } else if flags != 0x00 {
+        return Err(error_at(limits_offset, "invalid memory limits"));
+      }
+      if minimum > MAX_PAGES {
+        return Err(error_at(limits_offset, "minimum memory size exceeds 4 GiB"));
+      }
+      self.memory_pages = Some(minimum);
+    }
+    Ok(())
+  }
+
+  fn validate_globals(&mut self) -> Result<(), String> {
+    let count = try!(self.reader.read_u32("global count"));
+    for _ in range(0, count) {
+      let value_type = try!(self.reader.read_value_type("global type"));
+      let offset = self.reader.offset;
+      let mutable = match try!(self.reader.read_byte("global mutability")) {
+        0x00 => false,
+        0x01 => true,
+        _ => return Err(error_at(offset, "invalid global mutability")),
+      };
+      try!(self.validate_constant(value_type, "global initializer"));
+      self.globals.push((value_type, mutable));
+    }
+    Ok(())
+  }
+
+  // a constant instruction of the expected type followed by end
+  fn validate_constant(&mut self, expected: ValueType, what: &str) -> Result<(), String> {
+    let offset = self.reader.offset;
+    let opcode = try!(self.reader.read_byte(what));
+    let value_type = if opcode == I32_CONST {
+      try!(self.reader.read_i32(what));
+      ValueType::I32
+    } else if opcode == F32_CONST {
+      try!(self.reader.read_bytes(4, what));
+      ValueType::F32
+    } else if opcode == F64_CONST {
+      try!(self.reader.read_bytes(8, what));
+      ValueType::F64
+    } else {
+      return Err(error_at(offset, format!("{} is not a constant", what).as_slice()));
+    };
+
+    if value_type != expected {
+      return Err(error_at(offset, format!("{} has type {}, expected {}", what, value_type, expected).as_slice()));
+    }
+
+    let end_offset = self.reader.offset;
+    if try!(self.reader.read_byte(what)) != END {
+      return Err(error_at(end_offset, format!("{} must end after the constant", what).as_slice()));
+    }
+    Ok(())
+  }
+
+  fn validate_exports(&mut self) -> Result<(), String> {
+    let count = try!(self.reader.read_u32("export count"));
+    let mut names = HashSet::new();
+    for _ in range(0, count) {
+      let offset = self.reader.offset;
+      let name = try!(self.reader.read_name("export name"));
+      if !names.insert(name.clone()) {
+        return Err(error_at(offset, format!("duplicate export '{}'", name).as_slice()));
+      }
+
+      let kind_offset = self.reader.offset;
+      let kind = try!(self.reader.read_byte("export kind"));
+      let index = try!(self.reader.read_u32("export index")) as usize;
+      let valid = match kind {
+        0x00 => index < self.functions.len(),
+        0x02 => index == 0 && self.memory_pages.is_some(),
+        0x03 => index < self.globals.len(),
+        _ => return Err(error_at(kind_offset, "only functions, memories and globals can be exported")),
+      };
+      if !valid {
+        return Err(error_at(kind_offset, format!("export '{}' refers to index {} which does not exist",
+          name, index).as_slice()));
+      }
+    }
+    Ok(())
+  }
+
+  fn validate_start(&mut self) -> Result<(), String> {
+    let offset = self.reader.offset;
+    let index = try!(self.reader.read_u32("start function")) as usize;
+    if index >= self.functions.len() {
+      return Err(error_at(offset, format!("start function {} does not exist", index).as_slice()));
+    }
+    let function_type = &self.types[self.functions[index] as usize];
+    if !function_type.parameters.is_empty() || !function_type.results.is_empty() {
+      return Err(error_at(offset, "start function must not take parameters or return results"));
+    }
+    Ok(())
+  }
+
+  fn validate_code(&mut self) -> Result<(), String> {
+    let offset = self.reader.offset;
+    let count = try!(self.reader.read_u32("function body count")) as usize;
+    if count != self.functions.len() - self.imported_functions {
+      return Err(error_at(offset, format!("{} function bodies for {} declared functions",
+        count, self.functions.len() - self.imported_functions).as_slice()));
+    }
+
+    for body in range(0, count) {
+      let index = self.imported_functions + body;
+      let size = try!(self.reader.read_u32("function body size")) as usize;
+      let start = self.reader.offset;
+      if size > self.reader.bytes.len() - start {
+        return Err(error_at(start, format!("body of function {} exceeds the module", index).as_slice()));
+      }
+
+      let end = start + size;
+      let result = {
+        let mut validator = FunctionValidator {
+          module: self,
+          offset: start,
+          end: end,
+          locals: vec![],
+          operands: vec![],
+          controls: vec![],
+        };
+        validator.validate(index)
+      };
+
+      match result {
+        Ok(()) => { },
+        Err((offset, msg)) => self.errors.push(format!(
+          "Validation error in function {} at byte {}: {}", index, offset, msg)),
+      }
+      self.reader.offset = end;
+    }
+    Ok(())
+  }
+
+  fn validate_data(&mut self) -> Result<(), String> {
+    let count = try!(self.reader.read_u32("data segment count"));
+    for _ in range(0, count) {
+      let offset = self.reader.offset;
+      let memory = try!(self.reader.read_u32("data memory index"));
+      let pages = match self.memory_pages {
+        Some(pages) if memory == 0 => pages,
+        _ => return Err(error_at(offset, format!("data segment refers to memory {} which does not exist", memory).as_slice())),
+      };
+
+      let address_offset = self.reader.offset;
+      try!(self.validate_constant(ValueType::I32, "data offset"));
+      // the constant was just read, decode its value again
+      let mut address_reader = Reader { bytes: self.reader.bytes, offset: address_offset + 1 };
+      let address = try!(address_reader.read_i32("data offset")) as u32 as u64;
+
+      let length = try!(self.reader.read_u32("data length")) as usize;
+      try!(self.reader.read_bytes(length, "data"));
+      if address + length as u64 > pages as u64 * PAGE_SIZE as u64 {
+        return Err(error_at(offset, "data segment does not fit into the initial memory"));
+      }
+    }
+    Ok(())
+  }
+
+  fn read_type_index(&mut self) -> Result<u32, String> {
+    let offset = self.reader.offset;
+    let index = try!(self.reader.read_u32("type index"));
+    if index as usize >= self.types.len() {
+      return Err(error_at(offset, format!("type {} does not exist", index).as_slice()));
+    }
+    Ok(index)
+  }
+
+  fn read_value_types(&mut self, what: &str) -> Result<Vec<ValueType>, String> {
+    let count = try!(self.reader.read_u32(what));
+    let mut value_types = vec![];
+    for _ in range(0, count) {
+      value_types.push(try!(self.reader.read_value_type(what)));
+    }
+    Ok(value_types)
+  }
+}
+
+// block, loop, if or the body of the function itself
+struct Control {
+  opcode: u8,
+  results: Vec<ValueType>,
+  // operand stack height at the start of the block
+  height: usize,
+  // rest of the block can not be reached, so operands are not checked
+  unreachable: bool,
+  has_else: bool,
+}
+
+// opcode of the control frame of the function body
+static FUNCTION_BODY: u8 = 0xFF;
+
+// errors are the byte offset of the instruction and the message
+type FunctionResult<T> = Result<T, (usize, String)>;
+
+struct FunctionValidator<'a, 'b: 'a> {
+  module: &'a Validator<'b>,
+  offset: usize,
+  end: usize,
+  locals: Vec<ValueType>,
+  // None is an operand of unknown type in unreachable code
+  operands: Vec<Option<ValueType>>,
+  controls: Vec<Control>,
+}
+
+impl<'a, 'b> FunctionValidator<'a, 'b> {
+  fn validate(&mut self, index: usize) -> FunctionResult<()> {
+    let function_type = self.module.types[self.module.functions[index] as usize].clone();
+    self.locals.push_all(function_type.parameters.as_slice());
+
+    let run_count = try!(self.read_u32("local declaration count"));
+    let mut local_count = 0u32;
+    for _ in range(0, run_count) {
+      let offset = self.offset;
+      let count = try!(self.read_u32("local count"));
+      let value_type = match ValueType::from_code(try!(self.read_byte("local type"))) {
+        Some(value_type) => value_type,
+        None => return Err((offset, "unsupported local type".to_string())),
+      };
+      local_count += count;
+      if count > MAX_LOCALS || local_count > MAX_LOCALS {
+        return Err((offset, format!("more than {} locals", MAX_LOCALS)));
+      }
+      for _ in range(0, count) {
+        self.locals.push(value_type);
+      }
+    }
+
+    self.controls.push(Control {
+      opcode: FUNCTION_BODY,
+      results: function_type.results.clone(),
+      height: 0,
+      unreachable: false,
+      has_else: false,
+    });
+
+    while !self.controls.is_empty() {
+      if self.offset >= self.end {
+        return Err((self.offset, "function body ends before its final end".to_string()));
+      }
+      try!(self.validate_instruction());
+    }
+
+    if self.offset != self.end {
+      return Err((self.offset, "unexpected bytes after the final end of the function".to_string()));
+    }
+    Ok(())
+  }
+
+  fn validate_instruction(&mut self) -> FunctionResult<()> {
+    let offset = self.offset;
+    let opcode = try!(self.read_byte("opcode"));
+    let result = self.apply(opcode);
+    match result {
+      Ok(()) => Ok(()),
+      Err((_, msg)) => Err((offset, format!("{}: {}", instruction_name(opcode), msg))),
+    }
+  }
+
+  fn apply(&mut self, opcode: u8) -> FunctionResult<()> {
+    let module = self.module;
+    match opcode {
+      UNREACHABLE => self.set_unreachable(),
+      BLOCK | LOOP => {
+        try!(self.read_block_type());
+        self.push_control(opcode);
+      },
+      IF => {
+        try!(self.read_block_type());
+        try!(self.pop_expect(ValueType::I32));
+        self.push_control(opcode);
+      },
+      ELSE => {
+        let is_if = match self.controls.last() {
+          Some(control) => control.opcode == IF && !control.has_else,
+          None => false,
+        };
+        if !is_if {
+          return self.error("else without matching if");
+        }
+        try!(self.check_block_end());
+        let height = self.controls.last().unwrap().height;
+        self.operands.truncate(height);
+        let control = self.controls.last_mut().unwrap();
+        control.has_else = true;
+        control.unreachable = false;
+      },
+      END => {
+        try!(self.check_block_end());
+        let control = self.controls.pop().unwrap();
+        self.operands.truncate(control.height);
+        for result in control.results.iter() {
+          self.operands.push(Some(*result));
+        }
+      },
+      BR => {
+        let depth = try!(self.read_u32("branch depth"));
+        let label_types = try!(self.label_types(depth));
+        try!(self.pop_all(label_types.as_slice()));
+        self.set_unreachable();
+      },
+      BR_IF => {
+        let depth = try!(self.read_u32("branch depth"));
+        try!(self.pop_expect(ValueType::I32));
+        let label_types = try!(self.label_types(depth));
+        try!(self.pop_all(label_types.as_slice()));
+        self.push_all(label_types.as_slice());
+      },
+      RETURN => {
+        let results = self.controls[0].results.clone();
+        try!(self.pop_all(results.as_slice()));
+        self.set_unreachable();
+      },
+      CALL => {
+        let index = try!(self.read_u32("function index")) as usize;
+        if index >= module.functions.len() {
+          return self.error(format!("function {} does not exist", index).as_slice());
+        }
+        let function_type = &module.types[module.functions[index] as usize];
+        try!(self.pop_all(function_type.parameters.as_slice()));
+        self.push_all(function_type.results.as_slice());
+      },
+      DROP => {
+        try!(self.pop());
+      },
+      LOCAL_GET | LOCAL_SET => {
+        let index = try!(self.read_u32("local index")) as usize;
+        if index >= self.locals.len() {
+          return self.error(format!("local {} does not exist", index).as_slice());
+        }
+        let local_type = self.locals[index];
+        if opcode == LOCAL_GET {
+          self.operands.push(Some(local_type));
+        } else {
+          try!(self.pop_expect(local_type));
+        }
+      },
+      GLOBAL_GET | GLOBAL_SET => {
+        let index = try!(self.read_u32("global index")) as usize;
+        if index >= module.globals.len() {
+          return self.error(format!("global {} does not exist", index).as_slice());
+        }
+        let (global_type, mutable) = module.globals[index];
+        if opcode == GLOBAL_GET {
+          self.operands.push(Some(global_type));
+        } else if !mutable {
+          return self.error(format!("global {} is immutable", index).as_slice());
+        } else {
+          try!(self.pop_expect(global_type));
+        }
+      },
+      I32_LOAD | I32_LOAD8_U | I32_STORE | I32_STORE8 => {
+        try!(self.require_memory());
+        let alignment = try!(self.read_u32("alignment"));
+        try!(self.read_u32("address offset"));
+        let natural_alignment = if opcode == I32_LOAD || opcode == I32_STORE { 2 } else { 0 };
+        if alignment > natural_alignment {
+          return self.error("alignment is larger than the natural alignment");
+        }
+        if opcode == I32_STORE || opcode == I32_STORE8 {
+          try!(self.pop_expect(ValueType::I32));
+          try!(self.pop_expect(ValueType::I32));
+        } else {
+          try!(self.pop_expect(ValueType::I32));
+          self.operands.push(Some(ValueType::I32));
+        }
+      },
+      MEMORY_SIZE | MEMORY_GROW => {
+        try!(self.require_memory());
+        if try!(self.read_byte("memory index")) != 0x00 {
+          return self.error("memory index must be zero");
+        }
+        if opcode == MEMORY_GROW {
+          try!(self.pop_expect(ValueType::I32));
+        }
+        self.operands.push(Some(ValueType::I32));
+      },
+      I32_CONST => {
+        try!(self.read_i32("i32 constant"));
+        self.operands.push(Some(ValueType::I32));
+      },
+      F32_CONST => {
+        try!(self.read_bytes(4, "f32 constant"));
+        self.operands.push(Some(ValueType::F32));
+      },
+      F64_CONST => {
+        try!(self.read_bytes(8, "f64 constant"));
+        self.operands.push(Some(ValueType::F64));
+      },
+      _ => {
+        match numeric_info(opcode) {
+          Some((_, operand_types, result_type)) => {
+            try!(self.pop_all(operand_types));
+            self.operands.push(Some(result_type));
+          },
+          None => return self.error(format!("opcode 0x{:02x} is unknown or not supported", opcode).as_slice()),
+        }
+      },
+    }
+    Ok(())
+  }
+
+  fn read_block_type(&mut self) -> FunctionResult<()> {
+    let block_type = try!(self.read_byte("block type"));
+    if block_type == EMPTY_BLOCK {
+      Ok(())
+    } else if ValueType::from_code(block_type).is_some() {
+      self.error("blocks with results are not supported")
+    } else {
+      self.error(format!("invalid block type 0x{:02x}", block_type).as_slice())
+    }
+  }
+
+  fn push_control(&mut self, opcode: u8) {
+    let height = self.operands.len();
+    self.controls.push(Control {
+      opcode: opcode,
+      results: vec![],
+      height: height,
+      unreachable: false,
+      has_else: false,
+    });
+  }
+
+  // the results of the innermost block must be exactly what is left on its stack
+  fn check_block_end(&mut self) -> FunctionResult<()> {
+    let results = self.controls.last().unwrap().results.clone();
+    try!(self.pop_all(results.as_slice()));
+    let height = self.controls.last().unwrap().height;
+    if self.operands.len() != height {
+      return self.error(format!("block leaves {} extra value(s) on the stack",
+        self.operands.len() - height).as_slice());
+    }
+    Ok(())
+  }
+
+  // types a branch to the block at the given depth must provide
+  fn label_types(&self, depth: u32) -> FunctionResult<Vec<ValueType>> {
+    let depth = depth as usize;
+    if depth >= self.controls.len() {
+      return self.error(format!("branch depth {} exceeds the {} enclosing block(s)",
+        depth, self.controls.len()).as_slice());
+    }
+    let control = &self.controls[self.controls.len() - 1 - depth];
+    if control.opcode == LOOP {
+      Ok(vec![])
+    } else {
+      Ok(control.results.clone())
+    }
+  }
+
+  fn set_unreachable(&mut self) {
+    let control = self.controls.last_mut().unwrap();
+    self.operands.truncate(control.height);
+    control.unreachable = true;
+  }
+
+  fn require_memory(&self) -> FunctionResult<()> {
+    match self.module.memory_pages {
+      Some(..) => Ok(()),
+      None => self.error("module has no memory"),
+    }
+  }
+
+  fn pop(&mut self) -> FunctionResult<Option<ValueType>> {
+    let control = self.controls.last().unwrap();
+    if self.operands.len() == control.height {
+      if control.unreachable {
+        return Ok(None);
+      }
+      return self.error("operand stack is empty");
+    }
+    Ok(self.operands.pop().unwrap())
+  }
+
+  fn pop_expect(&mut self, expected: ValueType) -> FunctionResult<()> {
+    match try!(self.pop()) {
+      Some(actual) => {
+        if actual != expected {
+          return self.error(format!("expected operand of type {}, found {}", expected, actual).as_slice());
+        }
+        Ok(())
+      },
+      None => Ok(()),
+    }
+  }
+
+  // pops operands of the given types, the last type is on top of the stack
+  fn pop_all(&mut self, value_types: &[ValueType]) -> FunctionResult<()> {
+    for value_type in value_types.iter().rev() {
+      try!(self.pop_expect(*value_type));
+    }
+    Ok(())
+  }
+
+  fn push_all(&mut self, value_types: &[ValueType]) {
+    for value_type in value_types.iter() {
+      self.operands.push(Some(*value_type));
+    }
+  }
+
+  // the offset of the error is filled in by validate_instruction
+  fn error<T>(&self, msg: &str) -> FunctionResult<T> {
+    Err((self.offset, msg.to_string()))
+  }
+
+  fn read_byte(&mut self, what: &str) -> FunctionResult<u8> {
+    let bytes = try!(self.read_bytes(1, what));
+    Ok(bytes[0])
+  }
+
+  fn read_bytes(&mut self, count: usize, what: &str) -> FunctionResult<&'b [u8]> {
+    let mut reader = self.body_reader();
+    let result = reader.read_bytes(count, what);
+    self.finish_read(reader, result)
+  }
+
+  fn read_u32(&mut self, what: &str) -> FunctionResult<u32> {
+    let mut reader = self.body_reader();
+    let result = reader.read_u32(what);
+    self.finish_read(reader, result)
+  }
+
+  fn read_i32(&mut self, what: &str) -> FunctionResult<i32> {
+    let mut reader = self.body_reader();
+    let result = reader.read_i32(what);
+    self.finish_read(reader, result)
+  }
+
+  // reader limited to the function body
+  fn body_reader(&self) -> Reader<'b> {
+    let bytes: &'b [u8] = self.module.reader.bytes;
+    Reader { bytes: bytes.slice_to(self.end), offset: self.offset }
+  }
+
+  fn finish_read<T>(&mut self, reader: Reader<'b>, result: Result<T, String>) -> FunctionResult<T> {
+    match result {
+      Ok(value) => {
+        self.offset = reader.offset;
+        Ok(value)
+      },
+      Err(..) => Err((self.offset, "function body is truncated".to_string())),
+    }
+  }
+}
+
+fn instruction_name(opcode: u8) -> String {
+  let name = match opcode {
+    UNREACHABLE => "unreachable",
+    BLOCK => "block",
+    LOOP => "loop",
+    IF => "if",
+    ELSE => "else",
+    END => "end",
+    BR => "br",
+    BR_IF => "br_if",
+    RETURN => "return",
+    CALL => "call",
+    DROP => "drop",
+    LOCAL_GET => "local.get",
+    LOCAL_SET => "local.set",
+    GLOBAL_GET => "global.get",
+    GLOBAL_SET => "global.set",
+    I32_LOAD => "i32.load",
+    I32_LOAD8_U => "i32.load8_u",
+    I32_STORE => "i32.store",
+    I32_STORE8 => "i32.store8",
+    MEMORY_SIZE => "memory.size",
+    MEMORY_GROW => "memory.grow",
+    I32_CONST => "i32.const",
+    F32_CONST => "f32.const",
+    F64_CONST => "f64.const",
+    _ => match numeric_info(opcode) {
+      Some((name, _, _)) => name,
+      None => return format!("opcode 0x{:02x}", opcode),
+    },
+  };
+  name.to_string()
+}
@@ -0,0 +1,987 @@
+use std::mem;
+use ast::Program;
+use ast::BinaryOperator;
+use ast::Type;
+use ir::Module;
+use ir::Function;
+use ir::Instruction;
+use ir::Terminator;
+use ir::Operand;
+use ir::Register;
+use ir::BlockId;
+use ir::lowering;
+use backend::string_literal;
+use self::register_allocator::Allocation;
+use self::register_allocator::Class;
+use self::register_allocator::Location;
+use self::register_allocator::Target;
+
+pub mod register_allocator;
+
+/*
+  Native backend. Translates a resolved and type checked program into x86-64
+  assembly for the GNU assembler (Intel syntax), to be linked with the C
+  library on Linux.
+
+  The program is lowered into the intermediate representation and every
+  function is translated from the locations register_allocator.rs assigns to
+  its virtual registers: machine registers, or stack slots below rbp and the
+  saved callee-saved registers where too many values are live. Instructions
+  compute their result in rax, rdx or xmm14, which the allocator leaves to
+  the code generator, and copy it to the location of the destination.
+
+  Where a value changes location, the copies before the instruction or on
+  the control flow edge are ordered so that no location is overwritten
+  before it is read; r11 or xmm15 holds one value to break a cycle. Call
+  arguments are moved into place the same way.
+
+  Functions follow the System V calling convention: the first six int, bool
+  and string arguments are passed in rdi, rsi, rdx, rcx, r8 and r9, the first
+  eight float and double arguments in xmm0-xmm7 and the rest on the stack.
+  Results are returned in eax/rax or xmm0. The frame holds an even number of
+  8 byte slots, so the stack is 16 byte aligned at every call.
+
+  Integer arithmetic wraps around and integer division by zero terminates the
+  program with the same runtime error as the interpreter. Strings are never
+  freed.
+
+  generate lowers the program itself, generate_module translates a module
+  in normal form, for example one the optimizer has run on.
+*/
+
+pub fn generate(program: &Program) -> String {
+  generate_module(&lowering::lower(program))
+}
+
+pub fn generate_module(module: &Module) -> String {
+  let mut generator = Generator::new(module);
+  generator.generate()
+}
+
+static INTEGER_REGISTERS: [&'static str; 6] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+static SSE_REGISTERS: [&'static str; 8] = ["xmm0", "xmm1", "xmm2", "xmm3", "xmm4", "xmm5", "xmm6", "xmm7"];
+
+static RUNTIME: &'static str = "  .section .rodata
+.Lrt_int_format:
+  .asciz \"%d\\n\"
+.Lrt_float_format:
+  .asciz \"%.*g\"
+.Lrt_division_format:
+  .asciz \"Runtime error at %d:%d: Division by zero\\n\"
+.Lrt_true:
+  .asciz \"true\"
+.Lrt_false:
+  .asciz \"false\"
+
+  .text
+# rt_concat(rdi: a, rsi: b) -> rax: newly allocated a + b
+rt_concat:
+  push rbp
+  mov rbp, rsp
+  push rbx
+  push r12
+  push r13
+  push r14
+  mov r12, rdi
+  mov r13, rsi
+  call strlen@PLT
+  mov rbx, rax
+  mov rdi, r13
+  call strlen@PLT
+  mov r14, rax
+  lea rdi, [rbx + r14 + 1]
+  call malloc@PLT
+  mov rdi, rax
+  mov rsi, r12
+  mov rdx, rbx
+  mov r12, rax
+  call memcpy@PLT
+  lea rdi, [r12 + rbx]
+  mov rsi, r13
+  lea rdx, [r14 + 1]
+  call memcpy@PLT
+  mov rax, r12
+  pop r14
+  pop r13
+  pop r12
+  pop rbx
+  pop rbp
+  ret
+
+# rt_string_eq(rdi: a, rsi: b) -> eax: 1 if equal
+rt_string_eq:
+  push rbp
+  mov rbp, rsp
+  call strcmp@PLT
+  test eax, eax
+  sete al
+  movzx eax, al
+  pop rbp
+  ret
+
+# rt_print_int(edi: value)
+rt_print_int:
+  push rbp
+  mov rbp, rsp
+  mov esi, edi
+  lea rdi, [rip + .Lrt_int_format]
+  xor eax, eax
+  call printf@PLT
+  pop rbp
+  ret
+
+# rt_print_bool(edi: value)
+rt_print_bool:
+  push rbp
+  mov rbp, rsp
+  lea rax, [rip + .Lrt_true]
+  lea rcx, [rip + .Lrt_false]
+  test edi, edi
+  cmovz rax, rcx
+  mov rdi, rax
+  call puts@PLT
+  pop rbp
+  ret
+
+# rt_print_string(rdi: value)
+rt_print_string:
+  push rbp
+  mov rbp, rsp
+  call puts@PLT
+  pop rbp
+  ret
+
+# rt_print_double(xmm0: value). Prints the shortest representation that
+# reads back as the same value
+rt_print_double:
+  push rbp
+  mov rbp, rsp
+  push rbx
+  sub rsp, 56
+  movsd qword ptr [rbp - 16], xmm0
+  mov ebx, 1
+.Lrt_print_double_loop:
+  lea rdi, [rbp - 48]
+  mov esi, 32
+  lea rdx, [rip + .Lrt_float_format]
+  mov ecx, ebx
+  movsd xmm0, qword ptr [rbp - 16]
+  mov eax, 1
+  call snprintf@PLT
+  cmp ebx, 17
+  jge .Lrt_print_double_done
+  lea rdi, [rbp - 48]
+  xor esi, esi
+  call strtod@PLT
+  ucomisd xmm0, qword ptr [rbp - 16]
+  jne .Lrt_print_double_next
+  jnp .Lrt_print_double_done
+.Lrt_print_double_next:
+  inc ebx
+  jmp .Lrt_print_double_loop
+.Lrt_print_double_done:
+  lea rdi, [rbp - 48]
+  call puts@PLT
+  add rsp, 56
+  pop rbx
+  pop rbp
+  ret
+
+# rt_print_float(xmm0: value)
+rt_print_float:
+  push rbp
+  mov rbp, rsp
+  push rbx
+  sub rsp, 56
+  movss dword ptr [rbp - 16], xmm0
+  mov ebx, 1
+.Lrt_print_float_loop:
+  lea rdi, [rbp - 48]
+  mov esi, 32
+  lea rdx, [rip + .Lrt_float_format]
+  mov ecx, ebx
+  cvtss2sd xmm0, dword ptr [rbp - 16]
+  mov eax, 1
+  call snprintf@PLT
+  cmp ebx, 9
+  jge .Lrt_print_float_done
+  lea rdi, [rbp - 48]
+  xor esi, esi
+  call strtof@PLT
+  ucomiss xmm0, dword ptr [rbp - 16]
+  jne .Lrt_print_float_next
+  jnp .Lrt_print_float_done
+.Lrt_print_float_next:
+  inc ebx
+  jmp .Lrt_print_float_loop
+.Lrt_print_float_done:
+  lea rdi, [rbp - 48]
+  call puts@PLT
+  add rsp, 56
+  pop rbx
+  pop rbp
+  ret
+
+# rt_division_by_zero(edi: line, esi: position at line). Does not return
+rt_division_by_zero:
+  and rsp, -16
+  mov edx, esi
+  mov esi, edi
+  lea rdi, [rip + .Lrt_division_format]
+  xor eax, eax
+  call printf@PLT
+  mov edi, 1
+  call exit@PLT
+
+";
+
+// where the caller passes an argument
+enum Argument {
+  Register(&'static str),
+  // index among the arguments passed on the stack
+  Stack(usize),
+}
+
+fn calling_convention(types: &[Type]) -> Vec<Argument> {
+  let mut integers = 0;
+  let mut sses = 0;
+  let mut stack = 0;
+  let mut result = vec![];
+  for argument_type in types.iter() {
+    let class = Class::of(*argument_type);
+    if class == Class::Float && sses < SSE_REGISTERS.len() {
+      result.push(Argument::Register(SSE_REGISTERS[sses]));
+      sses += 1;
+    } else if class == Class::General && integers < INTEGER_REGISTERS.len() {
+      result.push(Argument::Register(INTEGER_REGISTERS[integers]));
+      integers += 1;
+    } else {
+      result.push(Argument::Stack(stack));
+      stack += 1;
+    }
+  }
+  result
+}
+
+// a register or the address of an 8 byte stack slot, like [rbp - 16]
+#[derive(Clone, PartialEq)]
+enum Place {
+  Register(&'static str),
+  Memory(String),
+}
+
+// register the code generator computes values of the class in
+fn scratch(class: Class) -> &'static str {
+  match class {
+    Class::General => "rax",
+    Class::Float => "xmm14",
+  }
+}
+
+// the function being translated and the locations of its values
+struct Frame<'a> {
+  function: &'a Function,
+  allocation: Allocation,
+  // reachable blocks in the order of their positions
+  layout: Vec<BlockId>,
+  labels: Vec<String>,
+  return_label: String,
+}
+
+impl<'a> Frame<'a> {
+  // spill slots are below the saved rbp and the saved callee-saved registers
+  fn place(&self, location: Location) -> Place {
+    match location {
+      Location::Register(name) => Place::Register(name),
+      Location::Stack(slot) => {
+        Place::Memory(format!("[rbp - {}]", 8 * (self.allocation.callee_saved.len() + slot + 1)))
+      },
+    }
+  }
+
+  fn read(&self, register: Register, position: usize) -> Place {
+    match self.allocation.location(register, position) {
+      Some(location) => self.place(location),
+      None => panic!("Internal compiler error: %{} read at position {} without a location", register, position),
+    }
+  }
+
+  fn class(&self, register: Register) -> Class {
+    Class::of(self.function.registers[register])
+  }
+}
+
+struct Generator<'a> {
+  module: &'a Module,
+  target: Target,
+  label_count: usize,
+  // instructions of the current function
+  body: String,
+  text: String,
+  data: String,
+}
+
+impl<'a> Generator<'a> {
+  fn new(module: &'a Module) -> Generator<'a> {
+    Generator {
+      module: module,
+      target: Target::x86_64(),
+      label_count: 0,
+      body: String::new(),
+      text: String::new(),
+      data: String::new(),
+    }
+  }
+
+  fn generate(&mut self) -> String {
+    let module = self.module;
+    for (index, function) in module.functions.iter().enumerate() {
+      let name = self.function_label(index);
+      self.function(function, name.as_slice());
+    }
+
+    if module.main.is_some() {
+      self.text.push_str("\n  .globl main\nmain:\n  push rbp\n  mov rbp, rsp\n");
+      self.text.push_str("  call rt_init_globals\n  call fn_main\n  xor eax, eax\n  pop rbp\n  ret\n");
+    }
+
+    let mut output = "  .intel_syntax noprefix\n\n".to_string();
+    output.push_str(RUNTIME);
+    output.push_str(self.text.as_slice());
+
+    if !self.data.is_empty() {
+      output.push_str("\n  .section .rodata\n");
+      output.push_str(self.data.as_slice());
+    }
+
+    if !module.globals.is_empty() {
+      output.push_str("\n  .bss\n  .p2align 3\n");
+      for global in module.globals.iter() {
+        output.push_str(format!("g_{}:\n  .zero 8\n", global.name).as_slice());
+      }
+    }
+
+    output.push_str("\n  .section .note.GNU-stack,\"\",@progbits\n");
+    output
+  }
+
+  fn function_label(&self, index: usize) -> String {
+    if index == self.module.init {
+      "rt_init_globals".to_string()
+    } else {
+      format!("fn_{}", self.module.functions[index].name)
+    }
+  }
+
+  fn function(&mut self, function: &Function, name: &str) {
+    let allocation = register_allocator::allocate(function, &self.target);
+    let mut order: Vec<(usize, BlockId)> = allocation.blocks.iter().enumerate()
+      .filter_map(|(block, range)| range.map(|(from, _)| (from, block)))
+      .collect();
+    order.sort();
+    let mut labels = vec![];
+    for _ in function.blocks.iter() {
+      labels.push(self.new_label());
+    }
+    let frame = Frame {
+      function: function,
+      allocation: allocation,
+      layout: order.iter().map(|&(_, block)| block).collect(),
+      labels: labels,
+      return_label: self.new_label(),
+    };
+
+    // parameters are moved from where the caller passes them
+    let types: Vec<Type> = function.parameters.iter().map(|parameter| function.registers[*parameter]).collect();
+    let mut moves = vec![];
+    for (parameter, argument) in function.parameters.iter().zip(calling_convention(types.as_slice()).iter()) {
+      let to = match frame.allocation.location(*parameter, 0) {
+        Some(location) => frame.place(location),
+        None => continue,
+      };
+      let from = match *argument {
+        Argument::Register(name) => Place::Register(name),
+        // above the saved rbp and the return address
+        Argument::Stack(index) => Place::Memory(format!("[rbp + {}]", 16 + 8 * index)),
+      };
+      moves.push((from, to, frame.class(*parameter)));
+    }
+    self.parallel_copy(moves);
+
+    for (index, block) in frame.layout.iter().enumerate() {
+      let next = frame.layout.get(index + 1).map(|next| *next);
+      self.block(&frame, *block, next);
+    }
+
+    // the prologue and the epilogue are known once the body is translated
+    let body = mem::replace(&mut self.body, String::new());
+    let saved = &frame.allocation.callee_saved;
+    let slots = frame.allocation.spill_slots + (saved.len() + frame.allocation.spill_slots) % 2;
+    self.text.push_str(format!("\n{}:\n  push rbp\n  mov rbp, rsp\n", name).as_slice());
+    for register in saved.iter() {
+      self.text.push_str(format!("  push {}\n", register).as_slice());
+    }
+    if slots > 0 {
+      self.text.push_str(format!("  sub rsp, {}\n", 8 * slots).as_slice());
+    }
+    self.text.push_str(body.as_slice());
+    self.text.push_str(format!("{}:\n", frame.return_label).as_slice());
+    if saved.is_empty() {
+      if slots > 0 {
+        self.text.push_str("  mov rsp, rbp\n");
+      }
+    } else {
+      self.text.push_str(format!("  lea rsp, [rbp - {}]\n", 8 * saved.len()).as_slice());
+      for register in saved.iter().rev() {
+        self.text.push_str(format!("  pop {}\n", register).as_slice());
+      }
+    }
+    self.text.push_str("  pop rbp\n  ret\n");
+  }
+
+  fn block(&mut self, frame: &Frame, block: BlockId, next: Option<BlockId>) {
+    let (start, end) = match frame.allocation.blocks[block] {
+      Some(range) => range,
+      None => panic!("Internal compiler error: unreachable block {} laid out", block),
+    };
+    self.label(frame.labels[block].as_slice());
+    let mut position = start;
+    // a call with a result makes the copies before the next instruction itself
+    let mut copied = false;
+    for instruction in frame.function.blocks[block].instructions.iter() {
+      if !copied {
+        self.copies_at(frame, position);
+      }
+      copied = self.translate(frame, position, instruction);
+      position += 2;
+    }
+    if !copied {
+      self.copies_at(frame, position);
+    }
+    self.terminator(frame, block, end - 2, next);
+  }
+
+  // returns whether the copies before the next position are made
+  fn translate(&mut self, frame: &Frame, position: usize, instruction: &Instruction) -> bool {
+    let module = self.module;
+    let function = frame.function;
+    match *instruction {
+      Instruction::Copy(destination, ref operand) => {
+        match frame.allocation.location(destination, position + 1) {
+          Some(Location::Register(name)) => self.load(frame, operand, position, name),
+          _ => {
+            let register = scratch(frame.class(destination));
+            self.load(frame, operand, position, register);
+            self.store(frame, destination, position + 1, register);
+          },
+        }
+      },
+      Instruction::Binary(destination, operator, ref left, ref right) => {
+        let operand_type = left.get_type(function);
+        match operand_type {
+          Type::Integer | Type::Boolean => {
+            self.load(frame, left, position, "rax");
+            let source = self.source(frame, right, position, operand_type);
+            match operator {
+              BinaryOperator::Plus => self.instruction(format!("add eax, {}", source).as_slice()),
+              BinaryOperator::Minus => self.instruction(format!("sub eax, {}", source).as_slice()),
+              BinaryOperator::Multiply => {
+                if right.is_constant() {
+                  self.instruction(format!("imul eax, eax, {}", source).as_slice());
+                } else {
+                  self.instruction(format!("imul eax, {}", source).as_slice());
+                }
+              },
+              BinaryOperator::Divide => panic!("Internal compiler error: integer division as a binary operator"),
+              _ => {
+                self.instruction(format!("cmp eax, {}", source).as_slice());
+                self.set_flag(match operator {
+                  BinaryOperator::Equals => "sete",
+                  BinaryOperator::Lesser => "setl",
+                  BinaryOperator::Greater => "setg",
+                  BinaryOperator::LesserOrEq => "setle",
+                  _ => "setge",
+                });
+              },
+            }
+            self.store(frame, destination, position + 1, "rax");
+          },
+          Type::Float | Type::Double => {
+            let suffix = if operand_type == Type::Float { "ss" } else { "sd" };
+            match operator {
+              BinaryOperator::Plus | BinaryOperator::Minus | BinaryOperator::Multiply | BinaryOperator::Divide => {
+                let mnemonic = match operator {
+                  BinaryOperator::Plus => "add",
+                  BinaryOperator::Minus => "sub",
+                  BinaryOperator::Multiply => "mul",
+                  _ => "div",
+                };
+                self.load(frame, left, position, "xmm14");
+                let source = self.source(frame, right, position, operand_type);
+                self.instruction(format!("{}{} xmm14, {}", mnemonic, suffix, source).as_slice());
+                self.store(frame, destination, position + 1, "xmm14");
+              },
+              // the parity flag is set for unordered operands, which compare false
+              BinaryOperator::Equals => {
+                self.load(frame, left, position, "xmm14");
+                let source = self.source(frame, right, position, operand_type);
+                self.instruction(format!("ucomi{} xmm14, {}", suffix, source).as_slice());
+                self.instruction("sete al");
+                self.instruction("setnp dl");
+                self.instruction("and al, dl");
+                self.instruction("movzx eax, al");
+                self.store(frame, destination, position + 1, "rax");
+              },
+              // 'above' conditions are false for unordered operands
+              _ => {
+                let (first, second) = match operator {
+                  BinaryOperator::Lesser | BinaryOperator::LesserOrEq => (right, left),
+                  _ => (left, right),
+                };
+                self.load(frame, first, position, "xmm14");
+                let source = self.source(frame, second, position, operand_type);
+                self.instruction(format!("ucomi{} xmm14, {}", suffix, source).as_slice());
+                self.set_flag(match operator {
+                  BinaryOperator::Lesser | BinaryOperator::Greater => "seta",
+                  _ => "setae",
+                });
+                self.store(frame, destination, position + 1, "rax");
+              },
+            }
+          },
+          Type::String => {
+            let runtime = if operator == BinaryOperator::Plus { "rt_concat" } else { "rt_string_eq" };
+            let arguments = vec![left.clone(), right.clone()];
+            return self.call(frame, position, runtime, arguments.as_slice(), Some(destination));
+          },
+          Type::Void => panic!("Internal compiler error: operator {} applied to void", operator),
+        }
+      },
+      Instruction::Divide(destination, ref left, ref right, source_position) => {
+        let checked_label = self.new_label();
+        let divide_label = self.new_label();
+        let end_label = self.new_label();
+        self.load(frame, left, position, "rax");
+        self.load(frame, right, position, "r11");
+        self.instruction("test r11d, r11d");
+        self.instruction(format!("jnz {}", checked_label).as_slice());
+        self.instruction(format!("mov edi, {}", source_position.line).as_slice());
+        self.instruction(format!("mov esi, {}", source_position.pos_at_line).as_slice());
+        self.instruction("call rt_division_by_zero");
+        self.label(checked_label.as_slice());
+        // idiv traps on i32::MIN / -1, negation wraps around instead
+        self.instruction("cmp r11d, -1");
+        self.instruction(format!("jne {}", divide_label).as_slice());
+        self.instruction("neg eax");
+        self.instruction(format!("jmp {}", end_label).as_slice());
+        self.label(divide_label.as_slice());
+        self.instruction("cdq");
+        self.instruction("idiv r11d");
+        self.label(end_label.as_slice());
+        self.store(frame, destination, position + 1, "rax");
+      },
+      Instruction::Convert(destination, ref operand) => {
+        let from = operand.get_type(function);
+        let to = function.registers[destination];
+        let conversion = match (from, to) {
+          (Type::Integer, Type::Float) => Some("cvtsi2ss xmm14, eax"),
+          (Type::Integer, Type::Double) => Some("cvtsi2sd xmm14, eax"),
+          (Type::Float, Type::Integer) => Some("cvttss2si eax, xmm14"),
+          (Type::Float, Type::Double) => Some("cvtss2sd xmm14, xmm14"),
+          (Type::Double, Type::Integer) => Some("cvttsd2si eax, xmm14"),
+          (Type::Double, Type::Float) => Some("cvtsd2ss xmm14, xmm14"),
+          (from, to) => {
+            if from != to {
+              panic!("Internal compiler error: conversion from {} to {}", from, to);
+            }
+            None
+          },
+        };
+        self.load(frame, operand, position, scratch(Class::of(from)));
+        match conversion {
+          Some(conversion) => self.instruction(conversion),
+          None => { },
+        }
+        self.store(frame, destination, position + 1, scratch(Class::of(to)));
+      },
+      Instruction::Call(destination, index, ref arguments) => {
+        let name = self.function_label(index);
+        return self.call(frame, position, name.as_slice(), arguments.as_slice(), destination);
+      },
+      Instruction::Print(ref operand) => {
+        let runtime = match operand.get_type(function) {
+          Type::Integer => "rt_print_int",
+          Type::Boolean => "rt_print_bool",
+          Type::String => "rt_print_string",
+          Type::Float => "rt_print_float",
+          Type::Double => "rt_print_double",
+          Type::Void => panic!("Internal compiler error: void value printed"),
+        };
+        let arguments = vec![operand.clone()];
+        return self.call(frame, position, runtime, arguments.as_slice(), None);
+      },
+      Instruction::LoadGlobal(destination, index) => {
+        let global = &module.globals[index];
+        let line = match global.global_type {
+          Type::Integer | Type::Boolean => format!("mov eax, dword ptr [rip + g_{}]", global.name),
+          Type::String => format!("mov rax, qword ptr [rip + g_{}]", global.name),
+          Type::Float => format!("movss xmm14, dword ptr [rip + g_{}]", global.name),
+          Type::Double => format!("movsd xmm14, qword ptr [rip + g_{}]", global.name),
+          Type::Void => panic!("Internal compiler error: void global loaded"),
+        };
+        self.instruction(line.as_slice());
+        self.store(frame, destination, position + 1, scratch(Class::of(global.global_type)));
+      },
+      Instruction::StoreGlobal(index, ref operand) => {
+        let global = &module.globals[index];
+        self.load(frame, operand, position, scratch(Class::of(global.global_type)));
+        let line = match global.global_type {
+          Type::Integer | Type::Boolean => format!("mov dword ptr [rip + g_{}], eax", global.name),
+          Type::String => format!("mov qword ptr [rip + g_{}], rax", global.name),
+          Type::Float => format!("movss dword ptr [rip + g_{}], xmm14", global.name),
+          Type::Double => format!("movsd qword ptr [rip + g_{}], xmm14", global.name),
+          Type::Void => panic!("Internal compiler error: void global stored"),
+        };
+        self.instruction(line.as_slice());
+      },
+      Instruction::Phi(..) => panic!("Internal compiler error: phi in the native backend"),
+    }
+    false
+  }
+
+  // calls a function of the program or of the runtime. The result is
+  // written after the copies before the next position, as those may still
+  // read the register the result is assigned to
+  fn call(&mut self, frame: &Frame, position: usize, name: &str, arguments: &[Operand],
+    destination: Option<Register>) -> bool {
+
+    let types: Vec<Type> = arguments.iter().map(|argument| argument.get_type(frame.function)).collect();
+    let convention = calling_convention(types.as_slice());
+    let mut stack_arguments = vec![];
+    for (index, argument) in convention.iter().enumerate() {
+      match *argument {
+        Argument::Stack(..) => stack_arguments.push(index),
+        Argument::Register(..) => { },
+      }
+    }
+
+    let padding = stack_arguments.len() % 2;
+    if padding == 1 {
+      self.instruction("sub rsp, 8");
+    }
+    // the first stack argument ends up at the lowest address
+    for index in stack_arguments.iter().rev() {
+      self.push(frame, &arguments[*index], position);
+    }
+
+    // constants are loaded once the registers they go to have been read
+    let mut moves = vec![];
+    let mut constants = vec![];
+    for (operand, argument) in arguments.iter().zip(convention.iter()) {
+      match (operand, argument) {
+        (&Operand::Register(register), &Argument::Register(name)) => {
+          moves.push((frame.read(register, position), Place::Register(name), frame.class(register)));
+        },
+        (_, &Argument::Register(name)) => constants.push((operand, name)),
+        (_, &Argument::Stack(..)) => { },
+      }
+    }
+    self.parallel_copy(moves);
+    for &(operand, name) in constants.iter() {
+      self.load(frame, operand, position, name);
+    }
+
+    self.instruction(format!("call {}", name).as_slice());
+    let pushed = stack_arguments.len() + padding;
+    if pushed > 0 {
+      self.instruction(format!("add rsp, {}", 8 * pushed).as_slice());
+    }
+
+    match destination {
+      Some(destination) => {
+        let result = match frame.class(destination) {
+          Class::General => "rax",
+          Class::Float => {
+            self.instruction("movaps xmm14, xmm0");
+            "xmm14"
+          },
+        };
+        self.copies_at(frame, position + 2);
+        self.store(frame, destination, position + 2, result);
+        true
+      },
+      None => false,
+    }
+  }
+
+  // pushes an argument passed on the stack
+  fn push(&mut self, frame: &Frame, operand: &Operand, position: usize) {
+    match *operand {
+      Operand::Register(register) => match frame.read(register, position) {
+        Place::Register(name) => {
+          if frame.class(register) == Class::General {
+            self.instruction(format!("push {}", name).as_slice());
+          } else {
+            self.instruction("sub rsp, 8");
+            self.instruction(format!("movsd qword ptr [rsp], {}", name).as_slice());
+          }
+        },
+        Place::Memory(address) => self.instruction(format!("push qword ptr {}", address).as_slice()),
+      },
+      Operand::Integer(value) => self.instruction(format!("push {}", value).as_slice()),
+      Operand::Boolean(value) => self.instruction(if value { "push 1" } else { "push 0" }),
+      Operand::Text(..) => {
+        self.load(frame, operand, position, "rax");
+        self.instruction("push rax");
+      },
+      Operand::Float(..) | Operand::Double(..) => {
+        self.load(frame, operand, position, "xmm14");
+        self.instruction("sub rsp, 8");
+        self.instruction("movsd qword ptr [rsp], xmm14");
+      },
+    }
+  }
+
+  fn terminator(&mut self, frame: &Frame, block: BlockId, position: usize, next: Option<BlockId>) {
+    match frame.function.blocks[block].terminator {
+      Terminator::Jump(target) => self.jump(frame, block, target, next),
+      Terminator::Branch(ref condition, if_true, if_false) => {
+        let register = match *condition {
+          Operand::Register(register) => register,
+          Operand::Boolean(value) => {
+            self.jump(frame, block, if value { if_true } else { if_false }, next);
+            return;
+          },
+          _ => panic!("Internal compiler error: branch on a value that is not a bool"),
+        };
+        match frame.read(register, position) {
+          Place::Register(name) => {
+            let low = low_half(name);
+            self.instruction(format!("test {}, {}", low, low).as_slice());
+          },
+          Place::Memory(address) => self.instruction(format!("cmp dword ptr {}, 0", address).as_slice()),
+        }
+        // the copies for the false edge go after those of the true edge,
+        // which must not fall through into them
+        if frame.allocation.edge_moves(block, if_false).is_empty() {
+          self.instruction(format!("jz {}", frame.labels[if_false]).as_slice());
+          self.jump(frame, block, if_true, next);
+        } else {
+          let false_label = self.new_label();
+          self.instruction(format!("jz {}", false_label).as_slice());
+          self.jump(frame, block, if_true, None);
+          self.label(false_label.as_slice());
+          self.jump(frame, block, if_false, next);
+        }
+      },
+      Terminator::Return(ref value) => {
+        match *value {
+          Some(ref value) => {
+            let register = match Class::of(value.get_type(frame.function)) {
+              Class::General => "rax",
+              Class::Float => "xmm0",
+            };
+            self.load(frame, value, position, register);
+          },
+          None => { },
+        }
+        // the epilogue follows the last block
+        if next.is_some() {
+          self.instruction(format!("jmp {}", frame.return_label).as_slice());
+        }
+      },
+      Terminator::Unreachable => self.instruction("ud2"),
+    }
+  }
+
+  fn jump(&mut self, frame: &Frame, from: BlockId, to: BlockId, next: Option<BlockId>) {
+    let moves = frame.allocation.edge_moves(from, to).into_iter()
+      .map(|(register, from, to)| (frame.place(from), frame.place(to), frame.class(register)))
+      .collect();
+    self.parallel_copy(moves);
+    if next != Some(to) {
+      self.instruction(format!("jmp {}", frame.labels[to]).as_slice());
+    }
+  }
+
+  // copies of the values that change location before the position
+  fn copies_at(&mut self, frame: &Frame, position: usize) {
+    let moves = frame.allocation.moves_at(position).into_iter()
+      .map(|(register, from, to)| (frame.place(from), frame.place(to), frame.class(register)))
+      .collect();
+    self.parallel_copy(moves);
+  }
+
+  // makes copies from one place to another as if all were made at once
+  fn parallel_copy(&mut self, moves: Vec<(Place, Place, Class)>) {
+    let mut pending: Vec<(Place, Place, Class)> = moves.into_iter()
+      .filter(|&(ref from, ref to, _)| from != to)
+      .collect();
+    while !pending.is_empty() {
+      // a copy can go next once no other pending copy reads its destination
+      let ready = pending.iter()
+        .position(|&(_, ref to, _)| !pending.iter().any(|&(ref from, _, _)| from == to));
+      match ready {
+        Some(index) => {
+          let (from, to, class) = pending.remove(index);
+          self.copy(&from, &to, class);
+        },
+        None => {
+          // only cycles are left, one source moves to r11 or xmm15
+          let (source, class) = (pending[0].0.clone(), pending[0].2);
+          let saved = Place::Register(match class {
+            Class::General => "r11",
+            Class::Float => "xmm15",
+          });
+          self.copy(&source, &saved, class);
+          pending = pending.into_iter()
+            .map(|(from, to, class)| (if from == source { saved.clone() } else { from }, to, class))
+            .collect();
+        },
+      }
+    }
+  }
+
+  // copies all 8 bytes of a value
+  fn copy(&mut self, from: &Place, to: &Place, class: Class) {
+    let line = match (from, to, class) {
+      (&Place::Register(from), &Place::Register(to), Class::General) => format!("mov {}, {}", to, from),
+      (&Place::Register(from), &Place::Register(to), Class::Float) => format!("movaps {}, {}", to, from),
+      (&Place::Memory(ref from), &Place::Register(to), Class::General) => format!("mov {}, qword ptr {}", to, from),
+      (&Place::Memory(ref from), &Place::Register(to), Class::Float) => format!("movsd {}, qword ptr {}", to, from),
+      (&Place::Register(from), &Place::Memory(ref to), Class::General) => format!("mov qword ptr {}, {}", to, from),
+      (&Place::Register(from), &Place::Memory(ref to), Class::Float) => format!("movsd qword ptr {}, {}", to, from),
+      // no register is free for copies between stack slots
+      (&Place::Memory(ref from), &Place::Memory(ref to), _) => {
+        self.instruction(format!("push qword ptr {}", from).as_slice());
+        format!("pop qword ptr {}", to)
+      },
+    };
+    self.instruction(line.as_slice());
+  }
+
+  // loads the operand read at the position into a register
+  fn load(&mut self, frame: &Frame, operand: &Operand, position: usize, register: &'static str) {
+    let line = match *operand {
+      Operand::Register(source) => {
+        let place = frame.read(source, position);
+        let target = Place::Register(register);
+        if place != target {
+          self.copy(&place, &target, frame.class(source));
+        }
+        return;
+      },
+      Operand::Integer(value) => format!("mov {}, {}", low_half(register), value),
+      Operand::Boolean(value) => format!("mov {}, {}", low_half(register), if value { 1 } else { 0 }),
+      Operand::Float(value) => {
+        let label = self.float_constant(value);
+        format!("movss {}, dword ptr [rip + {}]", register, label)
+      },
+      Operand::Double(value) => {
+        let label = self.double_constant(value);
+        format!("movsd {}, qword ptr [rip + {}]", register, label)
+      },
+      Operand::Text(ref text) => {
+        let label = self.constant(0, format!(".asciz {}", string_literal(text.as_slice())));
+        format!("lea {}, [rip + {}]", register, label)
+      },
+    };
+    self.instruction(line.as_slice());
+  }
+
+  // copies a result to the location of the destination
+  fn store(&mut self, frame: &Frame, destination: Register, position: usize, register: &'static str) {
+    match frame.allocation.location(destination, position) {
+      Some(location) => {
+        let place = frame.place(location);
+        let source = Place::Register(register);
+        if place != source {
+          self.copy(&source, &place, frame.class(destination));
+        }
+      },
+      None => { },
+    }
+  }
+
+  // the operand read at the position as the second operand of an
+  // instruction on int, bool, float or double values
+  fn source(&mut self, frame: &Frame, operand: &Operand, position: usize, operand_type: Type) -> String {
+    match *operand {
+      Operand::Register(register) => match frame.read(register, position) {
+        Place::Register(name) => {
+          if Class::of(operand_type) == Class::General { low_half(name).to_string() } else { name.to_string() }
+        },
+        Place::Memory(address) => {
+          format!("{} ptr {}", if operand_type == Type::Double { "qword" } else { "dword" }, address)
+        },
+      },
+      Operand::Integer(value) => format!("{}", value),
+      Operand::Boolean(value) => (if value { "1" } else { "0" }).to_string(),
+      Operand::Float(value) => format!("dword ptr [rip + {}]", self.float_constant(value)),
+      Operand::Double(value) => format!("qword ptr [rip + {}]", self.double_constant(value)),
+      Operand::Text(..) => panic!("Internal compiler error: string operand of an arithmetic instruction"),
+    }
+  }
+
+  fn set_flag(&mut self, set: &str) {
+    self.instruction(format!("{} al", set).as_slice());
+    self.instruction("movzx eax, al");
+  }
+
+  fn float_constant(&mut self, value: f32) -> String {
+    let bits = unsafe { mem::transmute::<f32, u32>(value) };
+    self.constant(2, format!(".long 0x{:08x}", bits))
+  }
+
+  fn double_constant(&mut self, value: f64) -> String {
+    let bits = unsafe { mem::transmute::<f64, u64>(value) };
+    self.constant(3, format!(".quad 0x{:016x}", bits))
+  }
+
+  // adds a read-only constant aligned to 2^alignment bytes and returns its label
+  fn constant(&mut self, alignment: usize, directive: String) -> String {
+    let label = self.new_label();
+    if alignment > 0 {
+      self.data.push_str(format!("  .p2align {}\n", alignment).as_slice());
+    }
+    self.data.push_str(format!("{}:\n  {}\n", label, directive).as_slice());
+    label
+  }
+
+  fn new_label(&mut self) -> String {
+    self.label_count += 1;
+    format!(".L{}", self.label_count)
+  }
+
+  fn label(&mut self, label: &str) {
+    self.body.push_str(label);
+    self.body.push_str(":\n");
+  }
+
+  fn instruction(&mut self, text: &str) {
+    self.body.push_str("  ");
+    self.body.push_str(text);
+    self.body.push('\n');
+  }
+}
+
+// 32 bit register holding the int and bool values of a general purpose register
+fn low_half(register: &str) -> &'static str {
+  match register {
+    "rax" => "eax",
+    "rbx" => "ebx",
+    "rcx" => "ecx",
+    "rdx" => "edx",
+    "rsi" => "esi",
+    "rdi" => "edi",
+    "r8" => "r8d",
+    "r9" => "r9d",
+    "r10" => "r10d",
+    "r11" => "r11d",
+    "r12" => "r12d",
+    "r13" => "r13d",
+    "r14" => "r14d",
+    "r15" => "r15d",
+    _ => panic!("Internal compiler error: no 32 bit half of {}", register),
+  }
+}
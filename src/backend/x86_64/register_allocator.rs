@@ -0,0 +1,758 @@
+use std::cmp;
+use std::fmt;
+use std::iter;
+use std::mem;
+use std::usize;
+use ast::Type;
+use ir::*;
+use ir::liveness;
+use ir::liveness::Liveness;
+use ir::printer::print_instruction;
+
+/*
+  Linear scan register allocation over the intermediate representation, for
+  functions in normal form. Instead of giving every value a stack slot, the
+  virtual registers are kept in machine registers and only moved to the
+  stack where too many values are live at the same time.
+
+  The reachable blocks are laid out in reverse postorder and every
+  instruction gets an even position; the terminator follows the last
+  instruction. An instruction reads its operands at its position p and
+  writes its destination at p + 1, so a value read for the last time can
+  share its register with the result. Calls, print and the string
+  operations, which call the runtime, clobber the caller-saved registers at
+  p + 1 and write their result at p + 2. A value is live across a call if it
+  is live at the clobber, so it gets a callee-saved register or is spilled
+  around the call.
+
+  Liveness gives the live interval of every virtual register as a list of
+  ranges with holes where its value is dead. The intervals are allocated in
+  order of their start:
+    -the register of the class that stays free the longest is taken. If it
+     becomes busy before the interval ends, the interval is split and the
+     rest is allocated later
+    -if no register is free, the interval whose next use is furthest away
+     is spilled: either the new interval until its first use, or the
+     intervals holding the register until their next use
+  Splits are always at even positions, so the copies between the locations
+  of a value go before an instruction. Uses by calls and print do not need a
+  register, as the arguments are moved into their places anyway. Values
+  that are spilled get one stack slot each.
+
+  Where a value changes location inside a block, moves_at lists the copies
+  before the instruction at that position. Where its location differs
+  between a block and its successor, edge_moves lists the copies on the
+  edge. dump writes the numbered instructions and the assignments for
+  debugging.
+*/
+
+// general purpose registers for ints, bools and strings, xmm registers for
+// floats and doubles
+#[derive(Show, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+  General,
+  Float,
+}
+
+impl Class {
+  pub fn of(register_type: Type) -> Class {
+    match register_type {
+      Type::Float | Type::Double => Class::Float,
+      _ => Class::General,
+    }
+  }
+}
+
+#[derive(Show, Clone, Copy, PartialEq)]
+pub struct PhysicalRegister {
+  pub name: &'static str,
+  pub class: Class,
+  // preserved across calls by the called function
+  pub callee_saved: bool,
+}
+
+// registers available for allocation, in order of preference
+pub struct Target {
+  pub registers: Vec<PhysicalRegister>,
+}
+
+impl Target {
+  // rax, rdx, r11, xmm14 and xmm15 are left to the code generator for
+  // results, division and values loaded from the stack
+  pub fn x86_64() -> Target {
+    let mut registers = vec![];
+    for name in ["rdi", "rsi", "rcx", "r8", "r9", "r10"].iter() {
+      registers.push(PhysicalRegister { name: *name, class: Class::General, callee_saved: false });
+    }
+    for name in ["rbx", "r12", "r13", "r14", "r15"].iter() {
+      registers.push(PhysicalRegister { name: *name, class: Class::General, callee_saved: true });
+    }
+    for name in ["xmm0", "xmm1", "xmm2", "xmm3", "xmm4", "xmm5", "xmm6", "xmm7", "xmm8", "xmm9",
+      "xmm10", "xmm11", "xmm12", "xmm13"].iter() {
+      registers.push(PhysicalRegister { name: *name, class: Class::Float, callee_saved: false });
+    }
+    Target { registers: registers }
+  }
+}
+
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Location {
+  Register(&'static str),
+  // index of the spill slot
+  Stack(usize),
+}
+
+impl fmt::String for Location {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Location::Register(name) => write!(formatter, "{}", name),
+      Location::Stack(slot) => write!(formatter, "stack {}", slot),
+    }
+  }
+}
+
+// location of a value from one position up to but not including another
+#[derive(Show, Clone, Copy, PartialEq)]
+pub struct Segment {
+  pub from: usize,
+  pub to: usize,
+  pub location: Location,
+}
+
+pub struct Allocation {
+  // virtual register -> locations of its live ranges, in order of position
+  pub segments: Vec<Vec<Segment>>,
+  // block -> position of its first instruction and the position after its
+  // terminator. None for blocks that can not be reached
+  pub blocks: Vec<Option<(usize, usize)>>,
+  pub spill_slots: usize,
+  // callee-saved registers the function uses, to be saved in its prologue
+  pub callee_saved: Vec<&'static str>,
+  pub liveness: Liveness,
+}
+
+impl Allocation {
+  pub fn location(&self, register: Register, position: usize) -> Option<Location> {
+    self.segments[register].iter()
+      .find(|segment| segment.from <= position && position < segment.to)
+      .map(|segment| segment.location)
+  }
+
+  // copies of register, from location, to location needed before the
+  // instruction at the position, where a value changes location inside a block
+  pub fn moves_at(&self, position: usize) -> Vec<(Register, Location, Location)> {
+    let mut moves = vec![];
+    if self.blocks.iter().any(|range| match *range {
+      Some((from, _)) => from == position,
+      None => false,
+    }) {
+      return moves;
+    }
+    for (register, segments) in self.segments.iter().enumerate() {
+      for pair in segments.windows(2) {
+        if pair[0].to == position && pair[1].from == position && pair[0].location != pair[1].location {
+          moves.push((register, pair[0].location, pair[1].location));
+        }
+      }
+    }
+    moves
+  }
+
+  // copies needed on the edge from a block to its successor for the values
+  // that are not in the same location at the end of one and the start of the other
+  pub fn edge_moves(&self, predecessor: BlockId, successor: BlockId) -> Vec<(Register, Location, Location)> {
+    let (end, start) = match (self.blocks[predecessor], self.blocks[successor]) {
+      (Some((_, end)), Some((start, _))) => (end, start),
+      _ => return vec![],
+    };
+    let mut moves = vec![];
+    for register in self.liveness.live_in_registers(successor).into_iter() {
+      match (self.location(register, end - 1), self.location(register, start)) {
+        (Some(from), Some(to)) if from != to => moves.push((register, from, to)),
+        _ => { },
+      }
+    }
+    moves
+  }
+}
+
+pub fn allocate(function: &Function, target: &Target) -> Allocation {
+  for block in function.blocks.iter() {
+    if block.instructions.iter().any(|instruction| instruction.is_phi()) {
+      panic!("Internal compiler error: register allocation expects a function without phis");
+    }
+  }
+
+  let liveness = liveness::analyze(function);
+  let blocks = number_blocks(function);
+  let (intervals, clobbers) = build_intervals(function, &liveness, &blocks);
+
+  let mut allocator = Allocator {
+    target: target,
+    clobbers: clobbers,
+    unhandled: vec![],
+    active: vec![],
+    inactive: vec![],
+    handled: vec![],
+    slots: iter::repeat(None).take(function.registers.len()).collect(),
+    slot_count: 0,
+  };
+  for interval in intervals.into_iter() {
+    if !interval.ranges.is_empty() {
+      allocator.insert_unhandled(interval);
+    }
+  }
+  allocator.run();
+
+  let mut segments: Vec<Vec<Segment>> = iter::repeat(vec![]).take(function.registers.len()).collect();
+  let intervals = allocator.handled.into_iter()
+    .chain(allocator.active.into_iter())
+    .chain(allocator.inactive.into_iter());
+  for interval in intervals {
+    let location = match interval.location {
+      Some(location) => location,
+      None => panic!("Internal compiler error: interval of %{} left without a location", interval.register),
+    };
+    for &(from, to) in interval.ranges.iter() {
+      segments[interval.register].push(Segment { from: from, to: to, location: location });
+    }
+  }
+  for register_segments in segments.iter_mut() {
+    register_segments.sort_by(|a, b| a.from.cmp(&b.from));
+    // parts of a split interval that ended up in the same place are joined
+    let mut joined: Vec<Segment> = vec![];
+    for segment in register_segments.iter() {
+      let last = joined.len();
+      if last > 0 && joined[last - 1].to == segment.from && joined[last - 1].location == segment.location {
+        joined[last - 1].to = segment.to;
+      } else {
+        joined.push(*segment);
+      }
+    }
+    *register_segments = joined;
+  }
+
+  let callee_saved = target.registers.iter()
+    .filter(|register| register.callee_saved && segments.iter().any(|register_segments| {
+      register_segments.iter().any(|segment| segment.location == Location::Register(register.name))
+    }))
+    .map(|register| register.name)
+    .collect();
+
+  Allocation {
+    segments: segments,
+    blocks: blocks,
+    spill_slots: allocator.slot_count,
+    callee_saved: callee_saved,
+    liveness: liveness,
+  }
+}
+
+// numbered instructions followed by the locations of the virtual registers
+pub fn dump(module: &Module, function: &Function, allocation: &Allocation) -> String {
+  let parameters: Vec<String> = function.parameters.iter()
+    .map(|parameter| format!("%{}: {}", parameter, function.registers[*parameter]))
+    .collect();
+  let mut output = format!("fn @{}({}) : {} {{\n", function.name, parameters.connect(", "),
+    function.return_type);
+
+  let mut order: Vec<(usize, BlockId)> = allocation.blocks.iter().enumerate()
+    .filter_map(|(block, range)| range.map(|(from, _)| (from, block)))
+    .collect();
+  order.sort();
+  for &(from, block) in order.iter() {
+    output.push_str(format!("bb{}:\n", block).as_slice());
+    let mut position = from;
+    for instruction in function.blocks[block].instructions.iter() {
+      output.push_str(format!("{:4}  {}\n", position,
+        print_instruction(module, function, instruction)).as_slice());
+      position += 2;
+    }
+    output.push_str(format!("{:4}  {}\n", position, function.blocks[block].terminator).as_slice());
+  }
+  output.push_str("}\n");
+
+  for (register, segments) in allocation.segments.iter().enumerate() {
+    let locations: Vec<String> = segments.iter()
+      .map(|segment| format!("{} [{}, {})", segment.location, segment.from, segment.to))
+      .collect();
+    output.push_str(format!("%{}: {} -> {}\n", register, function.registers[register],
+      if locations.is_empty() { "unused".to_string() } else { locations.connect(", ") }).as_slice());
+  }
+  output.push_str(format!("spill slots: {}\n", allocation.spill_slots).as_slice());
+  let callee_saved: Vec<String> = allocation.callee_saved.iter().map(|name| name.to_string()).collect();
+  output.push_str(format!("callee-saved: {}\n",
+    if callee_saved.is_empty() { "none".to_string() } else { callee_saved.connect(", ") }).as_slice());
+  output
+}
+
+// positions of the reachable blocks laid out in reverse postorder
+fn number_blocks(function: &Function) -> Vec<Option<(usize, usize)>> {
+  let mut blocks: Vec<Option<(usize, usize)>> = iter::repeat(None).take(function.blocks.len()).collect();
+  let mut position = 0;
+  for block in function.reverse_postorder().into_iter() {
+    // instructions and the terminator
+    let end = position + 2 * (function.blocks[block].instructions.len() + 1);
+    blocks[block] = Some((position, end));
+    position = end;
+  }
+  blocks
+}
+
+// calls into the runtime clobber the caller-saved registers like calls do
+fn is_call(function: &Function, instruction: &Instruction) -> bool {
+  match *instruction {
+    Instruction::Call(..) | Instruction::Print(..) => true,
+    Instruction::Binary(_, _, ref left, _) => left.get_type(function) == Type::String,
+    _ => false,
+  }
+}
+
+// the live interval of every virtual register and the positions where the
+// caller-saved registers are clobbered
+fn build_intervals(function: &Function, liveness: &Liveness,
+  blocks: &Vec<Option<(usize, usize)>>) -> (Vec<Interval>, Vec<usize>) {
+
+  let mut intervals: Vec<Interval> = function.registers.iter().enumerate()
+    .map(|(register, register_type)| Interval {
+      register: register,
+      class: Class::of(*register_type),
+      ranges: vec![],
+      uses: vec![],
+      location: None,
+    })
+    .collect();
+  let mut clobbers = vec![];
+
+  // backwards, so that the ranges of a block are added before the ranges
+  // ending in it
+  let mut order: Vec<(usize, usize, BlockId)> = blocks.iter().enumerate()
+    .filter_map(|(block, range)| range.map(|(from, to)| (from, to, block)))
+    .collect();
+  order.sort();
+  order.reverse();
+  for &(from, to, block) in order.iter() {
+    for register in liveness.live_out_registers(block).into_iter() {
+      intervals[register].add_range(from, to);
+    }
+
+    let terminator_position = to - 2;
+    for operand in function.blocks[block].terminator.operands().iter() {
+      match **operand {
+        Operand::Register(register) => {
+          intervals[register].add_range(from, terminator_position + 1);
+          intervals[register].uses.push(terminator_position);
+        },
+        _ => { },
+      }
+    }
+
+    for (index, instruction) in function.blocks[block].instructions.iter().enumerate().rev() {
+      let position = from + 2 * index;
+      let call = is_call(function, instruction);
+      if call {
+        clobbers.push(position + 1);
+      }
+      match instruction.destination() {
+        Some(register) => intervals[register].define(if call { position + 2 } else { position + 1 }),
+        None => { },
+      }
+      for operand in instruction.operands().iter() {
+        match **operand {
+          Operand::Register(register) => {
+            intervals[register].add_range(from, position + 1);
+            if !call {
+              intervals[register].uses.push(position);
+            }
+          },
+          _ => { },
+        }
+      }
+    }
+  }
+
+  for interval in intervals.iter_mut() {
+    interval.uses.sort();
+    interval.uses.dedup();
+  }
+  clobbers.sort();
+  (intervals, clobbers)
+}
+
+#[derive(Show, Clone)]
+struct Interval {
+  register: Register,
+  class: Class,
+  // sorted and disjoint ranges from a position up to but not including another
+  ranges: Vec<(usize, usize)>,
+  // sorted positions where the value is read from a register
+  uses: Vec<usize>,
+  location: Option<Location>,
+}
+
+impl Interval {
+  fn start(&self) -> usize {
+    self.ranges[0].0
+  }
+
+  fn end(&self) -> usize {
+    self.ranges[self.ranges.len() - 1].1
+  }
+
+  fn covers(&self, position: usize) -> bool {
+    self.ranges.iter().any(|&(from, to)| from <= position && position < to)
+  }
+
+  fn add_range(&mut self, from: usize, to: usize) {
+    let mut from = from;
+    let mut to = to;
+    // ranges overlapping or touching the new one are merged into it
+    let mut kept = vec![];
+    for &(other_from, other_to) in self.ranges.iter() {
+      if other_to < from || to < other_from {
+        kept.push((other_from, other_to));
+      } else {
+        from = cmp::min(from, other_from);
+        to = cmp::max(to, other_to);
+      }
+    }
+    kept.push((from, to));
+    kept.sort();
+    self.ranges = kept;
+  }
+
+  // the value is assigned at the position: the range covering it starts
+  // there. A value that is never read lives for one position
+  fn define(&mut self, position: usize) {
+    if !self.ranges.is_empty() && self.ranges[0].0 <= position && position < self.ranges[0].1 {
+      self.ranges[0].0 = position;
+    } else {
+      self.ranges.insert(0, (position, position + 1));
+    }
+  }
+
+  fn next_use(&self, position: usize) -> Option<usize> {
+    self.uses.iter().find(|use_position| **use_position >= position).map(|use_position| *use_position)
+  }
+
+  // first position covered by both intervals
+  fn intersection(&self, other: &Interval) -> Option<usize> {
+    let mut first = None;
+    for &(from, to) in self.ranges.iter() {
+      for &(other_from, other_to) in other.ranges.iter() {
+        let start = cmp::max(from, other_from);
+        if start < cmp::min(to, other_to) {
+          first = Some(match first {
+            Some(position) => cmp::min(position, start),
+            None => start,
+          });
+        }
+      }
+    }
+    first
+  }
+
+  // the part before the position and the part from it on, both without a location
+  fn split(self, position: usize) -> (Interval, Interval) {
+    let mut head = Interval {
+      register: self.register,
+      class: self.class,
+      ranges: vec![],
+      uses: vec![],
+      location: None,
+    };
+    let mut tail = head.clone();
+    for &(from, to) in self.ranges.iter() {
+      if to <= position {
+        head.ranges.push((from, to));
+      } else if from >= position {
+        tail.ranges.push((from, to));
+      } else {
+        head.ranges.push((from, position));
+        tail.ranges.push((position, to));
+      }
+    }
+    for use_position in self.uses.iter() {
+      if *use_position < position {
+        head.uses.push(*use_position);
+      } else {
+        tail.uses.push(*use_position);
+      }
+    }
+    if head.ranges.is_empty() || tail.ranges.is_empty() {
+      panic!("Internal compiler error: interval of %{} split at {} outside of it", self.register, position);
+    }
+    (head, tail)
+  }
+}
+
+struct Allocator<'a> {
+  target: &'a Target,
+  // positions where calls clobber the caller-saved registers
+  clobbers: Vec<usize>,
+  // sorted by decreasing start, so the next interval is the last one
+  unhandled: Vec<Interval>,
+  // intervals with a register that cover the current position
+  active: Vec<Interval>,
+  // intervals with a register that are in a lifetime hole at the current position
+  inactive: Vec<Interval>,
+  handled: Vec<Interval>,
+  // virtual register -> its spill slot
+  slots: Vec<Option<usize>>,
+  slot_count: usize,
+}
+
+impl<'a> Allocator<'a> {
+  fn run(&mut self) {
+    while let Some(current) = self.unhandled.pop() {
+      let position = current.start();
+
+      let active = mem::replace(&mut self.active, vec![]);
+      for interval in active.into_iter() {
+        if interval.end() <= position {
+          self.handled.push(interval);
+        } else if interval.covers(position) {
+          self.active.push(interval);
+        } else {
+          self.inactive.push(interval);
+        }
+      }
+      let inactive = mem::replace(&mut self.inactive, vec![]);
+      for interval in inactive.into_iter() {
+        if interval.end() <= position {
+          self.handled.push(interval);
+        } else if interval.covers(position) {
+          self.active.push(interval);
+        } else {
+          self.inactive.push(interval);
+        }
+      }
+
+      match self.free_register(&current) {
+        Some((register, free_until)) if free_until >= current.end() => {
+          let mut current = current;
+          current.location = Some(Location::Register(register));
+          self.active.push(current);
+        },
+        Some((register, free_until)) if even(free_until) > position => {
+          let (mut head, tail) = current.split(even(free_until));
+          head.location = Some(Location::Register(register));
+          self.active.push(head);
+          self.insert_unhandled(tail);
+        },
+        _ => self.allocate_blocked_register(current),
+      }
+    }
+  }
+
+  // the register of the class that stays free the longest, and the position
+  // where it stops being free
+  fn free_register(&self, current: &Interval) -> Option<(&'static str, usize)> {
+    let clobber = self.clobber(current);
+    let mut free_until: Vec<usize> = self.target.registers.iter().map(|register| {
+      if register.callee_saved {
+        usize::MAX
+      } else {
+        clobber.unwrap_or(usize::MAX)
+      }
+    }).collect();
+
+    for interval in self.active.iter() {
+      if interval.class == current.class {
+        free_until[self.index(interval)] = 0;
+      }
+    }
+    for interval in self.inactive.iter() {
+      if interval.class == current.class {
+        match interval.intersection(current) {
+          Some(position) => {
+            let index = self.index(interval);
+            free_until[index] = cmp::min(free_until[index], position);
+          },
+          None => { },
+        }
+      }
+    }
+
+    self.best(current.class, &free_until)
+  }
+
+  fn allocate_blocked_register(&mut self, current: Interval) {
+    let position = current.start();
+    let clobber = self.clobber(&current);
+    // the next position each register is needed by another interval or the
+    // position it is clobbered
+    let mut blocked: Vec<usize> = self.target.registers.iter().map(|register| {
+      if register.callee_saved {
+        usize::MAX
+      } else {
+        clobber.unwrap_or(usize::MAX)
+      }
+    }).collect();
+    let mut next_use = blocked.clone();
+
+    for interval in self.active.iter() {
+      if interval.class == current.class {
+        let index = self.index(interval);
+        next_use[index] = cmp::min(next_use[index], interval.next_use(position).unwrap_or(usize::MAX));
+      }
+    }
+    for interval in self.inactive.iter() {
+      if interval.class == current.class && interval.intersection(&current).is_some() {
+        let index = self.index(interval);
+        next_use[index] = cmp::min(next_use[index], interval.next_use(position).unwrap_or(usize::MAX));
+      }
+    }
+
+    let (register, register_next_use) = match self.best(current.class, &next_use) {
+      Some(best) => best,
+      // the target has no registers of the class
+      None => return self.spill(current),
+    };
+    let index = self.index_of(register);
+    match current.next_use(position) {
+      None => self.spill(current),
+      // the other values are needed earlier, so the current one is spilled up
+      // to its first use
+      Some(first_use) if first_use > register_next_use => {
+        let (head, tail) = current.split(first_use);
+        self.spill(head);
+        self.insert_unhandled(tail);
+      },
+      Some(..) => {
+        let split = if blocked[index] < current.end() { Some(even(blocked[index])) } else { None };
+        match split {
+          // clobbered before it could be used
+          Some(split) if split <= position => return self.spill_until_use(current, position + 1),
+          _ => { },
+        }
+        self.evict(register, &current);
+        match split {
+          Some(split) => {
+            let (mut head, tail) = current.split(split);
+            head.location = Some(Location::Register(register));
+            self.active.push(head);
+            self.insert_unhandled(tail);
+          },
+          None => {
+            let mut current = current;
+            current.location = Some(Location::Register(register));
+            self.active.push(current);
+          },
+        }
+      },
+    }
+  }
+
+  // spills the parts of the intervals in the register that overlap the current one
+  fn evict(&mut self, register: &'static str, current: &Interval) {
+    let position = current.start();
+    let split = even(position);
+    let location = Some(Location::Register(register));
+
+    let active = mem::replace(&mut self.active, vec![]);
+    let mut evicted = vec![];
+    for interval in active.into_iter() {
+      if interval.location == location {
+        evicted.push(interval);
+      } else {
+        self.active.push(interval);
+      }
+    }
+    let inactive = mem::replace(&mut self.inactive, vec![]);
+    for interval in inactive.into_iter() {
+      if interval.location == location && interval.intersection(current).is_some() {
+        evicted.push(interval);
+      } else {
+        self.inactive.push(interval);
+      }
+    }
+
+    for interval in evicted.into_iter() {
+      let rest = if interval.start() < split && interval.end() > split {
+        let (mut head, tail) = interval.split(split);
+        head.location = location;
+        self.handled.push(head);
+        tail
+      } else {
+        let mut interval = interval;
+        interval.location = None;
+        interval
+      };
+      self.spill_until_use(rest, position + 1);
+    }
+  }
+
+  // moves the interval to the stack up to its next use from the position on
+  fn spill_until_use(&mut self, interval: Interval, position: usize) {
+    match interval.next_use(position) {
+      None => self.spill(interval),
+      Some(use_position) if use_position <= interval.start() => self.insert_unhandled(interval),
+      Some(use_position) => {
+        let (head, tail) = interval.split(use_position);
+        self.spill(head);
+        self.insert_unhandled(tail);
+      },
+    }
+  }
+
+  fn spill(&mut self, interval: Interval) {
+    let slot = match self.slots[interval.register] {
+      Some(slot) => slot,
+      None => {
+        self.slots[interval.register] = Some(self.slot_count);
+        self.slot_count += 1;
+        self.slot_count - 1
+      },
+    };
+    let mut interval = interval;
+    interval.location = Some(Location::Stack(slot));
+    self.handled.push(interval);
+  }
+
+  fn insert_unhandled(&mut self, interval: Interval) {
+    let start = interval.start();
+    let index = self.unhandled.iter().position(|other| other.start() < start).unwrap_or(self.unhandled.len());
+    self.unhandled.insert(index, interval);
+  }
+
+  // the first position where the interval is live and the caller-saved
+  // registers are clobbered
+  fn clobber(&self, interval: &Interval) -> Option<usize> {
+    self.clobbers.iter().find(|position| interval.covers(**position)).map(|position| *position)
+  }
+
+  // the register of the class with the highest value, the first one on ties
+  fn best(&self, class: Class, values: &Vec<usize>) -> Option<(&'static str, usize)> {
+    let mut best: Option<(&'static str, usize)> = None;
+    for (index, register) in self.target.registers.iter().enumerate() {
+      if register.class != class {
+        continue;
+      }
+      best = match best {
+        Some((_, value)) if value >= values[index] => best,
+        _ => Some((register.name, values[index])),
+      };
+    }
+    best
+  }
+
+  fn index(&self, interval: &Interval) -> usize {
+    match interval.location {
+      Some(Location::Register(name)) => self.index_of(name),
+      _ => panic!("Internal compiler error: interval of %{} has no register", interval.register),
+    }
+  }
+
+  fn index_of(&self, name: &'static str) -> usize {
+    match self.target.registers.iter().position(|register| register.name == name) {
+      Some(index) => index,
+      None => panic!("Internal compiler error: unknown register {}", name),
+    }
+  }
+}
+
+// positions between instructions are even
+fn even(position: usize) -> usize {
+  position & !1
+}
@@ -0,0 +1,12 @@
+/*
+  Functions provided by the language itself. A builtin is used only when the
+  program does not declare a function with the same name.
+
+  print(value) - writes any non-void value followed by a newline
+*/
+
+pub static PRINT: &'static str = "print";
+
+pub fn is_builtin(name: &str) -> bool {
+  name == PRINT
+}
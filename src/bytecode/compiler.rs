@@ -0,0 +1,476 @@
+use std::collections::HashMap;
+use std::mem;
+use ast::Program;
+use ast::Function;
+use ast::Block;
+use ast::Statement;
+use ast::Assignment;
+use ast::FunctionCall;
+use ast::Expression;
+use ast::ExpressionKind;
+use ast::Literal;
+use ast::BinaryOperator;
+use ast::Position;
+use ast::Type;
+use bytecode::*;
+use symbol_table::SymbolTable;
+use builtins;
+
+/*
+  Compiles a resolved and type checked program into bytecode. Every local
+  variable gets its own slot in the frame of its function; slots of variables
+  that went out of scope are not reused. Globals are initialized by a synthetic
+  function that is placed after the program's functions.
+
+  Operands are 16 bits wide. A function with more constants, locals or
+  bytecode than they can address, or a program with more functions or
+  globals, can not be compiled; the errors are returned instead.
+*/
+
+pub fn compile(program: &Program) -> Result<Module, Vec<String>> {
+  let mut compiler = Compiler::new(program);
+  let module = compiler.compile();
+  if compiler.errors.is_empty() {
+    Ok(module)
+  } else {
+    Err(compiler.errors)
+  }
+}
+
+static INIT_FUNCTION_NAME: &'static str = "<globals>";
+
+struct Compiler<'a> {
+  program: &'a Program,
+  // name -> function index
+  functions: HashMap<usize, usize>,
+  // name -> global index
+  globals: HashMap<usize, usize>,
+  // state of the function being compiled
+  chunk: Chunk,
+  // exact value of a constant of the chunk -> constant index
+  constants: HashMap<(u8, u64), usize>,
+  locals: SymbolTable<usize>,
+  errors: Vec<String>,
+}
+
+impl<'a> Compiler<'a> {
+  fn new(program: &'a Program) -> Compiler<'a> {
+    Compiler {
+      program: program,
+      functions: HashMap::new(),
+      globals: HashMap::new(),
+      chunk: Chunk::new(String::new(), 0, Position::new(0, 0)),
+      constants: HashMap::new(),
+      locals: SymbolTable::new(),
+      errors: vec![],
+    }
+  }
+
+  fn compile(&mut self) -> Module {
+    let program = self.program;
+    let mut main = None;
+    let mut declared = vec![];
+    for function in program.functions.iter() {
+      if !self.functions.contains_key(&function.name) {
+        let index = declared.len();
+        self.functions.insert(function.name, index);
+        if program.get_text(function.name) == "main" {
+          main = Some(index);
+        }
+        declared.push(function);
+      }
+    }
+
+    for global in program.globals.iter() {
+      if !self.globals.contains_key(&global.name) {
+        let index = self.globals.len();
+        self.globals.insert(global.name, index);
+      }
+    }
+
+    let mut chunks = vec![];
+    for function in declared.iter() {
+      chunks.push(self.compile_function(*function));
+    }
+
+    let init = chunks.len();
+    chunks.push(self.compile_global_initializers());
+
+    Module {
+      functions: chunks,
+      global_count: self.globals.len(),
+      init: init,
+      main: main,
+      text_table: program.text_table.clone(),
+    }
+  }
+
+  fn compile_function(&mut self, function: &Function) -> Chunk {
+    let name = self.program.get_text(function.name).to_string();
+    self.chunk = Chunk::new(name, function.parameters.len(), function.pos);
+    self.constants = HashMap::new();
+    self.locals = SymbolTable::new();
+
+    // parameters and the top level of the body share the scope
+    self.locals.push_scope();
+    let mut slot = 0;
+    for parameter in function.parameters.iter() {
+      self.locals.declare(parameter.name, slot);
+      slot += 1;
+    }
+
+    for statement in function.body.statements.iter() {
+      self.compile_statement(statement);
+    }
+    self.locals.pop_scope();
+
+    // functions returning a value always end in a return statement
+    self.emit(RETURN_VOID, &function.body.pos);
+    mem::replace(&mut self.chunk, Chunk::new(String::new(), 0, Position::new(0, 0)))
+  }
+
+  fn compile_global_initializers(&mut self) -> Chunk {
+    let program = self.program;
+    self.chunk = Chunk::new(INIT_FUNCTION_NAME.to_string(), 0, Position::new(1, 1));
+    self.constants = HashMap::new();
+    self.locals = SymbolTable::new();
+
+    for global in program.globals.iter() {
+      self.compile_expression(&global.initializer);
+      let index = self.global_index(global.name);
+      self.emit_with_operand(STORE_GLOBAL, index, &global.pos);
+    }
+
+    let pos = self.chunk.pos;
+    self.emit(RETURN_VOID, &pos);
+    mem::replace(&mut self.chunk, Chunk::new(String::new(), 0, Position::new(0, 0)))
+  }
+
+  fn compile_block(&mut self, block: &Block) {
+    self.locals.push_scope();
+    for statement in block.statements.iter() {
+      self.compile_statement(statement);
+    }
+    self.locals.pop_scope();
+  }
+
+  fn compile_statement(&mut self, statement: &Statement) {
+    match *statement {
+      Statement::Block(ref block) => self.compile_block(block),
+      Statement::VariableDeclaration(ref declaration) => {
+        // variable is not visible in its own initializer
+        self.compile_expression(&declaration.initializer);
+        let slot = self.chunk.local_count;
+        self.chunk.local_count += 1;
+        self.locals.declare(declaration.name, slot);
+        self.emit_with_operand(STORE_LOCAL, slot, &declaration.pos);
+      },
+      Statement::Assignment(ref assignment) => self.compile_assignment(assignment),
+      Statement::FunctionCall(ref call) => {
+        self.compile_call(call);
+        self.emit(POP, &call.pos);
+      },
+      Statement::For(ref for_loop) => {
+        // variable declared in the init clause is only visible inside the loop
+        self.locals.push_scope();
+        match for_loop.init {
+          Some(ref init) => self.compile_statement(&**init),
+          None => { },
+        }
+
+        let loop_start = self.chunk.code.len();
+        let exit_jump = match for_loop.condition {
+          Some(ref condition) => {
+            self.compile_expression(condition);
+            Some(self.emit_jump(JUMP_IF_FALSE, &condition.pos))
+          },
+          None => None,
+        };
+
+        self.compile_block(&for_loop.body);
+        match for_loop.update {
+          Some(ref update) => self.compile_assignment(update),
+          None => { },
+        }
+        self.emit_with_operand(JUMP, loop_start, &for_loop.pos);
+
+        match exit_jump {
+          Some(jump) => self.patch_jump(jump),
+          None => { },
+        }
+        self.locals.pop_scope();
+      },
+      Statement::If(ref if_statement) => {
+        let mut end_jumps = vec![];
+
+        self.compile_expression(&if_statement.condition);
+        let mut next_jump = self.emit_jump(JUMP_IF_FALSE, &if_statement.condition.pos);
+        self.compile_block(&if_statement.block);
+        end_jumps.push(self.emit_jump(JUMP, &if_statement.pos));
+
+        for else_if in if_statement.else_ifs.iter() {
+          self.patch_jump(next_jump);
+          self.compile_expression(&else_if.condition);
+          next_jump = self.emit_jump(JUMP_IF_FALSE, &else_if.condition.pos);
+          self.compile_block(&else_if.block);
+          end_jumps.push(self.emit_jump(JUMP, &else_if.pos));
+        }
+
+        self.patch_jump(next_jump);
+        match if_statement.else_block {
+          Some(ref block) => self.compile_block(block),
+          None => { },
+        }
+
+        for jump in end_jumps.into_iter() {
+          self.patch_jump(jump);
+        }
+      },
+      Statement::Return(ref return_statement) => {
+        match return_statement.value {
+          Some(ref value) => {
+            // the frame of a function returning the result of a call is
+            // reused for the callee
+            let callee = match value.kind {
+              ExpressionKind::Call(ref call) => self.functions.get(&call.name).map(|index| (*index, call)),
+              _ => None,
+            };
+            match callee {
+              Some((index, call)) => {
+                for argument in call.arguments.iter() {
+                  self.compile_expression(argument);
+                }
+                self.emit_with_operand(TAIL_CALL, index, &call.pos);
+              },
+              None => {
+                self.compile_expression(value);
+                self.emit(RETURN, &return_statement.pos);
+              },
+            }
+          },
+          None => self.emit(RETURN_VOID, &return_statement.pos),
+        }
+      },
+      Statement::Empty(..) => { },
+    }
+  }
+
+  fn compile_assignment(&mut self, assignment: &Assignment) {
+    self.compile_expression(&assignment.value);
+    match self.locals.lookup(assignment.name) {
+      Some(slot) => self.emit_with_operand(STORE_LOCAL, slot, &assignment.pos),
+      None => {
+        let index = self.global_index(assignment.name);
+        self.emit_with_operand(STORE_GLOBAL, index, &assignment.pos);
+      },
+    }
+  }
+
+  fn compile_call(&mut self, call: &FunctionCall) {
+    for argument in call.arguments.iter() {
+      self.compile_expression(argument);
+    }
+
+    let function = match self.functions.get(&call.name) {
+      Some(index) => Some(*index),
+      None => None,
+    };
+
+    match function {
+      Some(index) => self.emit_with_operand(CALL, index, &call.pos),
+      None => {
+        let program = self.program;
+        let name = program.get_text(call.name);
+        if name == builtins::PRINT {
+          self.emit(PRINT, &call.pos);
+        } else {
+          panic!("Internal compiler error: call to unknown function '{}'", name);
+        }
+      },
+    }
+  }
+
+  fn compile_expression(&mut self, expression: &Expression) {
+    let pos = expression.pos;
+    match expression.kind {
+      ExpressionKind::Literal(literal) => {
+        match literal {
+          Literal::Integer(value) => self.emit_constant(Constant::Integer(value), &pos),
+          Literal::Float(value) => self.emit_constant(Constant::Float(value), &pos),
+          Literal::Double(value) => self.emit_constant(Constant::Double(value), &pos),
+          Literal::Boolean(true) => self.emit(TRUE, &pos),
+          Literal::Boolean(false) => self.emit(FALSE, &pos),
+          Literal::Text(index) => self.emit_constant(Constant::Text(index), &pos),
+        }
+      },
+      ExpressionKind::Variable(name) => {
+        match self.locals.lookup(name) {
+          Some(slot) => self.emit_with_operand(LOAD_LOCAL, slot, &pos),
+          None => {
+            let index = self.global_index(name);
+            self.emit_with_operand(LOAD_GLOBAL, index, &pos);
+          },
+        }
+      },
+      ExpressionKind::Binary(operator, ref left, ref right) => {
+        self.compile_expression(&**left);
+        self.compile_expression(&**right);
+        self.emit(binary_opcode(operator, left.get_type()), &pos);
+      },
+      ExpressionKind::Call(ref call) => self.compile_call(call),
+      ExpressionKind::Cast(ref inner, target) => {
+        self.compile_expression(&**inner);
+        match conversion_opcode(inner.get_type(), target) {
+          Some(opcode) => self.emit(opcode, &pos),
+          None => { },
+        }
+      },
+    }
+  }
+
+  fn global_index(&self, name: usize) -> usize {
+    match self.globals.get(&name) {
+      Some(index) => *index,
+      None => panic!("Internal compiler error: undeclared variable '{}'",
+        self.program.get_text(name)),
+    }
+  }
+
+  fn emit_constant(&mut self, constant: Constant, pos: &Position) {
+    let key = constant_key(constant);
+    let existing = self.constants.get(&key).map(|index| *index);
+    let index = match existing {
+      Some(index) => index,
+      None => {
+        let index = self.chunk.constants.len();
+        self.chunk.constants.push(constant);
+        self.constants.insert(key, index);
+        index
+      },
+    };
+    self.emit_with_operand(CONST, index, pos);
+  }
+
+  fn emit(&mut self, opcode: u8, pos: &Position) {
+    let offset = self.chunk.code.len();
+    let position_changed = match self.chunk.lines.last() {
+      Some(entry) => entry.pos != *pos,
+      None => true,
+    };
+
+    if position_changed {
+      self.chunk.lines.push(LineEntry { offset: offset, pos: *pos });
+    }
+    self.chunk.code.push(opcode);
+  }
+
+  fn emit_with_operand(&mut self, opcode: u8, operand: usize, pos: &Position) {
+    if operand > 0xFFFF {
+      let limit = match opcode {
+        CONST => "constants",
+        LOAD_LOCAL | STORE_LOCAL => "local variables",
+        LOAD_GLOBAL | STORE_GLOBAL => "global variables",
+        CALL | TAIL_CALL => "called functions",
+        _ => "operands",
+      };
+      let msg = format!("Too many {} in function '{}', at most 65536 are supported", limit, self.chunk.name);
+      self.register_error(msg);
+    }
+    self.emit(opcode, pos);
+    self.chunk.code.push((operand >> 8) as u8);
+    self.chunk.code.push((operand & 0xFF) as u8);
+  }
+
+  // emits jump with a placeholder target. Returns the offset of the operand
+  fn emit_jump(&mut self, opcode: u8, pos: &Position) -> usize {
+    self.emit_with_operand(opcode, 0xFFFF, pos);
+    self.chunk.code.len() - 2
+  }
+
+  // makes the jump at the offset target the next instruction
+  fn patch_jump(&mut self, operand_offset: usize) {
+    let target = self.chunk.code.len();
+    if target > 0xFFFF {
+      let msg = format!("Function '{}' is too large, jumps can not reach past 65535 bytes of bytecode",
+        self.chunk.name);
+      self.register_error(msg);
+    }
+    self.chunk.code[operand_offset] = (target >> 8) as u8;
+    self.chunk.code[operand_offset + 1] = (target & 0xFF) as u8;
+  }
+
+  // reports the error at the function being compiled, once
+  fn register_error(&mut self, msg: String) {
+    let error = format!("Error at {}: {}", self.chunk.pos, msg);
+    if !self.errors.contains(&error) {
+      self.errors.push(error);
+    }
+  }
+}
+
+fn binary_opcode(operator: BinaryOperator, operand_type: Type) -> u8 {
+  match (operand_type, operator) {
+    (Type::Integer, BinaryOperator::Plus) => IADD,
+    (Type::Integer, BinaryOperator::Minus) => ISUB,
+    (Type::Integer, BinaryOperator::Multiply) => IMUL,
+    (Type::Integer, BinaryOperator::Divide) => IDIV,
+    (Type::Integer, BinaryOperator::Equals) => IEQ,
+    (Type::Integer, BinaryOperator::Lesser) => ILT,
+    (Type::Integer, BinaryOperator::Greater) => IGT,
+    (Type::Integer, BinaryOperator::LesserOrEq) => ILE,
+    (Type::Integer, BinaryOperator::GreaterOrEq) => IGE,
+    (Type::Float, BinaryOperator::Plus) => FADD,
+    (Type::Float, BinaryOperator::Minus) => FSUB,
+    (Type::Float, BinaryOperator::Multiply) => FMUL,
+    (Type::Float, BinaryOperator::Divide) => FDIV,
+    (Type::Float, BinaryOperator::Equals) => FEQ,
+    (Type::Float, BinaryOperator::Lesser) => FLT,
+    (Type::Float, BinaryOperator::Greater) => FGT,
+    (Type::Float, BinaryOperator::LesserOrEq) => FLE,
+    (Type::Float, BinaryOperator::GreaterOrEq) => FGE,
+    (Type::Double, BinaryOperator::Plus) => DADD,
+    (Type::Double, BinaryOperator::Minus) => DSUB,
+    (Type::Double, BinaryOperator::Multiply) => DMUL,
+    (Type::Double, BinaryOperator::Divide) => DDIV,
+    (Type::Double, BinaryOperator::Equals) => DEQ,
+    (Type::Double, BinaryOperator::Lesser) => DLT,
+    (Type::Double, BinaryOperator::Greater) => DGT,
+    (Type::Double, BinaryOperator::LesserOrEq) => DLE,
+    (Type::Double, BinaryOperator::GreaterOrEq) => DGE,
+    (Type::Boolean, BinaryOperator::Equals) => BEQ,
+    (Type::String, BinaryOperator::Plus) => SCONCAT,
+    (Type::String, BinaryOperator::Equals) => SEQ,
+    _ => panic!("Internal compiler error: operator {} can not be applied to {}",
+      operator, operand_type),
+  }
+}
+
+// constants are only shared if they have the same bits, 0.0 and -0.0 are
+// different constants
+fn constant_key(constant: Constant) -> (u8, u64) {
+  match constant {
+    Constant::Integer(value) => (0, value as u32 as u64),
+    Constant::Float(value) => (1, unsafe { mem::transmute::<f32, u32>(value) } as u64),
+    Constant::Double(value) => (2, unsafe { mem::transmute::<f64, u64>(value) }),
+    Constant::Text(index) => (3, index as u64),
+  }
+}
+
+// None if no conversion is needed
+fn conversion_opcode(source: Type, target: Type) -> Option<u8> {
+  match (source, target) {
+    (Type::Integer, Type::Float) => Some(I2F),
+    (Type::Integer, Type::Double) => Some(I2D),
+    (Type::Float, Type::Integer) => Some(F2I),
+    (Type::Float, Type::Double) => Some(F2D),
+    (Type::Double, Type::Integer) => Some(D2I),
+    (Type::Double, Type::Float) => Some(D2F),
+    _ => {
+      if source == target {
+        None
+      } else {
+        panic!("Internal compiler error: can not convert {} to {}", source, target);
+      }
+    }
+  }
+}
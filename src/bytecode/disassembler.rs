@@ -0,0 +1,112 @@
+use bytecode::*;
+
+/*
+  Human readable listing of a bytecode module. Every instruction is printed on
+  its own line with its code offset, the source position it was generated
+  from, the mnemonic and the decoded operand. For
+  "fn add(a:int, b:int) : int { return a + b; }" the listing is
+
+    == fn add (arity 2, locals 2) at 1:1 ==
+    0000     1:37  LOAD_LOCAL     0
+    0003     1:41  LOAD_LOCAL     1
+    0006     1:39  IADD
+    0007     1:30  RETURN
+    0008     1:28  RETURN_VOID
+*/
+
+pub fn disassemble(module: &Module) -> String {
+  let mut listing = String::new();
+  let mut first = true;
+  for chunk in module.functions.iter() {
+    if !first {
+      listing.push('\n');
+    }
+    first = false;
+    listing.push_str(disassemble_chunk(module, chunk).as_slice());
+  }
+  listing
+}
+
+pub fn disassemble_chunk(module: &Module, chunk: &Chunk) -> String {
+  let mut listing = format!("== fn {} (arity {}, locals {}) at {} ==\n",
+    chunk.name, chunk.arity, chunk.local_count, chunk.pos);
+
+  let mut offset = 0;
+  while offset < chunk.code.len() {
+    let opcode = chunk.code[offset];
+    let pos = format!("{}", chunk.position_at(offset));
+    match opcode_info(opcode) {
+      Some((name, operand_size)) => {
+        if offset + 1 + operand_size > chunk.code.len() {
+          listing.push_str(format!("{:04} {:>8}  {} <truncated>\n", offset, pos, name).as_slice());
+          break;
+        }
+
+        if operand_size == 0 {
+          listing.push_str(format!("{:04} {:>8}  {}\n", offset, pos, name).as_slice());
+        } else {
+          let operand = chunk.read_u16(offset + 1);
+          listing.push_str(format!("{:04} {:>8}  {:<14} {}\n", offset, pos, name,
+            describe_operand(module, chunk, opcode, operand)).as_slice());
+        }
+        offset += 1 + operand_size;
+      },
+      None => {
+        listing.push_str(format!("{:04} {:>8}  <invalid opcode {}>\n", offset, pos, opcode).as_slice());
+        offset += 1;
+      }
+    }
+  }
+  listing
+}
+
+fn describe_operand(module: &Module, chunk: &Chunk, opcode: u8, operand: usize) -> String {
+  match opcode {
+    CONST => {
+      let value = if operand < chunk.constants.len() {
+        describe_constant(module, &chunk.constants[operand])
+      } else {
+        "<invalid constant>".to_string()
+      };
+      format!("{} ({})", operand, value)
+    },
+    JUMP | JUMP_IF_FALSE => format!("-> {:04}", operand),
+    CALL | TAIL_CALL => {
+      if operand < module.functions.len() {
+        format!("{} ({})", operand, module.functions[operand].name)
+      } else {
+        format!("{} (<invalid function>)", operand)
+      }
+    },
+    _ => format!("{}", operand),
+  }
+}
+
+fn describe_constant(module: &Module, constant: &Constant) -> String {
+  match *constant {
+    Constant::Integer(value) => format!("int {}", value),
+    Constant::Float(value) => format!("float {}", value),
+    Constant::Double(value) => format!("double {}", value),
+    Constant::Text(index) => {
+      if index < module.text_table.len() {
+        format!("string \"{}\"", escape(module.text_table[index].as_slice()))
+      } else {
+        "<invalid text index>".to_string()
+      }
+    },
+  }
+}
+
+fn escape(text: &str) -> String {
+  let mut escaped = String::new();
+  for ch in text.chars() {
+    match ch {
+      '\n' => escaped.push_str("\\n"),
+      '\t' => escaped.push_str("\\t"),
+      '\\' => escaped.push_str("\\\\"),
+      '"' => escaped.push_str("\\\""),
+      _ => escaped.push(ch),
+    }
+  }
+  escaped
+}
@@ -0,0 +1,326 @@
+use std::mem;
+use std::str;
+use ast::Position;
+use bytecode::*;
+
+/*
+  On-disk container for compiled modules. All integers are big endian.
+
+    header      magic "\x7fCBC", u16 format version
+    module      u32 global count, u32 init function, u32 main function
+                (NO_MAIN if the module has no main)
+    strings     u32 count, every string as u32 byte length + utf-8 bytes
+    functions   u32 count, for every function:
+                  name (u32 length + utf-8 bytes), u32 arity, u32 local count,
+                  i32 line, i32 position at line,
+                  u32 code length + code bytes,
+                  u32 constant count + constants (u8 tag, value),
+                  u32 line table length + entries (u32 offset, i32 line,
+                  i32 position at line)
+
+  Constant tags: 0 int (i32), 1 float (f32 bits), 2 double (f64 bits),
+  3 string (u32 index to string table).
+
+  The loader validates the structure of the file: every read is bounds
+  checked, indices must refer to existing strings and functions, and line
+  tables must be ordered and inside the code. Global and local counts must
+  be addressable by the 16 bit operands of the instructions, so a corrupted
+  count can not make the virtual machine allocate huge frames. Validity of
+  the instructions themselves is checked by the verifier.
+*/
+
+pub static MAGIC: [u8; 4] = [0x7F, 0x43, 0x42, 0x43];
+// version 2 added the TAIL_CALL instruction
+pub static VERSION: u16 = 2;
+static NO_MAIN: u32 = 0xFFFFFFFF;
+// number of globals or locals the operands of the instructions can address
+static MAX_SLOTS: usize = 0x10000;
+
+static TAG_INTEGER: u8 = 0;
+static TAG_FLOAT: u8 = 1;
+static TAG_DOUBLE: u8 = 2;
+static TAG_TEXT: u8 = 3;
+
+pub fn is_bytecode_file(bytes: &[u8]) -> bool {
+  bytes.len() >= MAGIC.len() && bytes.slice_to(MAGIC.len()) == MAGIC.as_slice()
+}
+
+pub fn write(module: &Module) -> Vec<u8> {
+  let mut bytes = vec![];
+  bytes.push_all(MAGIC.as_slice());
+  write_u16(&mut bytes, VERSION);
+
+  write_u32(&mut bytes, module.global_count as u32);
+  write_u32(&mut bytes, module.init as u32);
+  write_u32(&mut bytes, match module.main {
+    Some(main) => main as u32,
+    None => NO_MAIN,
+  });
+
+  write_u32(&mut bytes, module.text_table.len() as u32);
+  for text in module.text_table.iter() {
+    write_string(&mut bytes, text.as_slice());
+  }
+
+  write_u32(&mut bytes, module.functions.len() as u32);
+  for chunk in module.functions.iter() {
+    write_chunk(&mut bytes, chunk);
+  }
+  bytes
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, chunk: &Chunk) {
+  write_string(bytes, chunk.name.as_slice());
+  write_u32(bytes, chunk.arity as u32);
+  write_u32(bytes, chunk.local_count as u32);
+  write_position(bytes, &chunk.pos);
+
+  write_u32(bytes, chunk.code.len() as u32);
+  bytes.push_all(chunk.code.as_slice());
+
+  write_u32(bytes, chunk.constants.len() as u32);
+  for constant in chunk.constants.iter() {
+    match *constant {
+      Constant::Integer(value) => {
+        bytes.push(TAG_INTEGER);
+        write_u32(bytes, value as u32);
+      },
+      Constant::Float(value) => {
+        bytes.push(TAG_FLOAT);
+        write_u32(bytes, unsafe { mem::transmute::<f32, u32>(value) });
+      },
+      Constant::Double(value) => {
+        bytes.push(TAG_DOUBLE);
+        write_u64(bytes, unsafe { mem::transmute::<f64, u64>(value) });
+      },
+      Constant::Text(index) => {
+        bytes.push(TAG_TEXT);
+        write_u32(bytes, index as u32);
+      },
+    }
+  }
+
+  write_u32(bytes, chunk.lines.len() as u32);
+  for entry in chunk.lines.iter() {
+    write_u32(bytes, entry.offset as u32);
+    write_position(bytes, &entry.pos);
+  }
+}
+
+fn write_u16(bytes: &mut Vec<u8>, value: u16) {
+  bytes.push((value >> 8) as u8);
+  bytes.push(value as u8);
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: u32) {
+  write_u16(bytes, (value >> 16) as u16);
+  write_u16(bytes, value as u16);
+}
+
+fn write_u64(bytes: &mut Vec<u8>, value: u64) {
+  write_u32(bytes, (value >> 32) as u32);
+  write_u32(bytes, value as u32);
+}
+
+fn write_position(bytes: &mut Vec<u8>, pos: &Position) {
+  write_u32(bytes, pos.line as u32);
+  write_u32(bytes, pos.pos_at_line as u32);
+}
+
+fn write_string(bytes: &mut Vec<u8>, text: &str) {
+  write_u32(bytes, text.len() as u32);
+  bytes.push_all(text.as_bytes());
+}
+
+pub fn read(bytes: &[u8]) -> Result<Module, String> {
+  let mut loader = Loader { bytes: bytes, offset: 0 };
+  loader.read_module()
+}
+
+struct Loader<'a> {
+  bytes: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> Loader<'a> {
+  fn read_module(&mut self) -> Result<Module, String> {
+    let magic = try!(self.read_bytes(MAGIC.len(), "magic number"));
+    if magic != MAGIC.as_slice() {
+      return Err("Invalid bytecode file: bad magic number".to_string());
+    }
+
+    let version = try!(self.read_u16("format version"));
+    if version != VERSION {
+      return Err(format!("Unsupported bytecode format version {}, expected {}", version, VERSION));
+    }
+
+    let global_count = try!(self.read_u32("global count")) as usize;
+    if global_count > MAX_SLOTS {
+      return Err(self.error(format!("{} globals, but at most {} can be addressed", global_count, MAX_SLOTS)
+        .as_slice()));
+    }
+    let init_offset = self.offset;
+    let init = try!(self.read_u32("init function index")) as usize;
+    let main_offset = self.offset;
+    let main = try!(self.read_u32("main function index"));
+
+    let string_count = try!(self.read_u32("string count")) as usize;
+    let mut text_table = vec![];
+    for index in range(0, string_count) {
+      text_table.push(try!(self.read_string(format!("string {}", index).as_slice())));
+    }
+
+    let function_count = try!(self.read_u32("function count")) as usize;
+    let mut functions = vec![];
+    for index in range(0, function_count) {
+      functions.push(try!(self.read_chunk(index, text_table.len())));
+    }
+
+    if self.offset != self.bytes.len() {
+      return Err(self.error("unexpected data after the last function"));
+    }
+
+    if init >= functions.len() {
+      return Err(format!("Invalid bytecode file at byte {}: init function index {} is out of range",
+        init_offset, init));
+    }
+
+    let main = if main == NO_MAIN {
+      None
+    } else if (main as usize) < functions.len() {
+      Some(main as usize)
+    } else {
+      return Err(format!("Invalid bytecode file at byte {}: main function index {} is out of range",
+        main_offset, main));
+    };
+
+    Ok(Module {
+      functions: functions,
+      global_count: global_count,
+      init: init,
+      main: main,
+      text_table: text_table,
+    })
+  }
+
+  fn read_chunk(&mut self, index: usize, string_count: usize) -> Result<Chunk, String> {
+    let name = try!(self.read_string(format!("name of function {}", index).as_slice()));
+    let arity = try!(self.read_u32("function arity")) as usize;
+    let local_count = try!(self.read_u32("function local count")) as usize;
+    if local_count > MAX_SLOTS {
+      return Err(self.error(format!(
+        "function '{}' has {} locals, but at most {} can be addressed", name, local_count, MAX_SLOTS).as_slice()));
+    }
+    if local_count < arity {
+      return Err(self.error(format!(
+        "function '{}' has {} locals, but {} parameters", name, local_count, arity).as_slice()));
+    }
+    let pos = try!(self.read_position("function position"));
+
+    let code_length = try!(self.read_u32("code length")) as usize;
+    let code = try!(self.read_bytes(code_length, "code")).to_vec();
+
+    let constant_count = try!(self.read_u32("constant count")) as usize;
+    let mut constants = vec![];
+    for _ in range(0, constant_count) {
+      constants.push(try!(self.read_constant(string_count)));
+    }
+
+    let line_count = try!(self.read_u32("line table length")) as usize;
+    let mut lines: Vec<LineEntry> = vec![];
+    for _ in range(0, line_count) {
+      let offset = try!(self.read_u32("line table offset")) as usize;
+      let ordered = match lines.last() {
+        Some(previous) => previous.offset < offset,
+        None => true,
+      };
+
+      if offset >= code.len() || !ordered {
+        return Err(self.error(format!(
+          "line table offset {} of function '{}' is out of order or outside the code", offset, name).as_slice()));
+      }
+
+      let pos = try!(self.read_position("line table position"));
+      lines.push(LineEntry { offset: offset, pos: pos });
+    }
+
+    Ok(Chunk {
+      name: name,
+      arity: arity,
+      local_count: local_count,
+      code: code,
+      constants: constants,
+      lines: lines,
+      pos: pos,
+    })
+  }
+
+  fn read_constant(&mut self, string_count: usize) -> Result<Constant, String> {
+    let tag = try!(self.read_bytes(1, "constant tag"))[0];
+    if tag == TAG_INTEGER {
+      Ok(Constant::Integer(try!(self.read_u32("int constant")) as i32))
+    } else if tag == TAG_FLOAT {
+      let bits = try!(self.read_u32("float constant"));
+      Ok(Constant::Float(unsafe { mem::transmute::<u32, f32>(bits) }))
+    } else if tag == TAG_DOUBLE {
+      let high = try!(self.read_u32("double constant")) as u64;
+      let low = try!(self.read_u32("double constant")) as u64;
+      Ok(Constant::Double(unsafe { mem::transmute::<u64, f64>((high << 32) | low) }))
+    } else if tag == TAG_TEXT {
+      let index = try!(self.read_u32("string constant")) as usize;
+      if index >= string_count {
+        return Err(self.error(format!(
+          "string constant refers to string {}, but there are only {}", index, string_count).as_slice()));
+      }
+      Ok(Constant::Text(index))
+    } else {
+      self.offset -= 1;
+      Err(self.error(format!("unknown constant tag {}", tag).as_slice()))
+    }
+  }
+
+  fn read_position(&mut self, what: &str) -> Result<Position, String> {
+    let line = try!(self.read_u32(what)) as i32;
+    let pos_at_line = try!(self.read_u32(what)) as i32;
+    Ok(Position::new(line, pos_at_line))
+  }
+
+  fn read_string(&mut self, what: &str) -> Result<String, String> {
+    let length = try!(self.read_u32(what)) as usize;
+    let start = self.offset;
+    let bytes = try!(self.read_bytes(length, what));
+    match str::from_utf8(bytes) {
+      Ok(text) => Ok(text.to_string()),
+      Err(..) => Err(format!("Invalid bytecode file at byte {}: {} is not valid utf-8", start, what)),
+    }
+  }
+
+  fn read_u16(&mut self, what: &str) -> Result<u16, String> {
+    let bytes = try!(self.read_bytes(2, what));
+    Ok(((bytes[0] as u16) << 8) | (bytes[1] as u16))
+  }
+
+  fn read_u32(&mut self, what: &str) -> Result<u32, String> {
+    let bytes = try!(self.read_bytes(4, what));
+    Ok(((bytes[0] as u32) << 24) | ((bytes[1] as u32) << 16) |
+       ((bytes[2] as u32) << 8) | (bytes[3] as u32))
+  }
+
+  fn read_bytes(&mut self, count: usize, what: &str) -> Result<&'a [u8], String> {
+    if count > self.bytes.len() - self.offset {
+      return Err(format!(
+        "Truncated bytecode file: expected {} byte(s) of {} at byte {}, but only {} remain",
+        count, what, self.offset, self.bytes.len() - self.offset));
+    }
+
+    let bytes: &'a [u8] = self.bytes;
+    let result = bytes.slice(self.offset, self.offset + count);
+    self.offset += count;
+    Ok(result)
+  }
+
+  // error about the data just read
+  fn error(&self, msg: &str) -> String {
+    format!("Invalid bytecode file at byte {}: {}", self.offset, msg)
+  }
+}
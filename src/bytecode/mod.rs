@@ -0,0 +1,210 @@
+use ast::Position;
+
+pub mod compiler;
+pub mod disassembler;
+pub mod file;
+pub mod verifier;
+
+/*
+  Bytecode for the stack based virtual machine. Every function is compiled
+  into its own chunk holding the instructions, the constant pool and a line
+  table that maps instruction offsets back to source positions.
+
+  Instructions are a single opcode byte followed by the operands; u16 operands
+  are stored big endian. Arithmetic and comparison instructions are typed, the
+  compiler relies on the type checker having made both operands the same type.
+  Every call leaves exactly one value on the stack (void for void functions).
+  A tail call returns the result of the callee right away, so the caller's
+  frame can be reused for the callee.
+
+  String constants are indices to the text table produced by the lexer, which
+  is carried along in the module.
+*/
+
+// operand: u16 constant index
+pub const CONST: u8 = 0;
+pub const TRUE: u8 = 1;
+pub const FALSE: u8 = 2;
+// operand: u16 local slot
+pub const LOAD_LOCAL: u8 = 3;
+pub const STORE_LOCAL: u8 = 4;
+// operand: u16 global index
+pub const LOAD_GLOBAL: u8 = 5;
+pub const STORE_GLOBAL: u8 = 6;
+
+pub const IADD: u8 = 10;
+pub const ISUB: u8 = 11;
+pub const IMUL: u8 = 12;
+pub const IDIV: u8 = 13;
+pub const FADD: u8 = 14;
+pub const FSUB: u8 = 15;
+pub const FMUL: u8 = 16;
+pub const FDIV: u8 = 17;
+pub const DADD: u8 = 18;
+pub const DSUB: u8 = 19;
+pub const DMUL: u8 = 20;
+pub const DDIV: u8 = 21;
+pub const SCONCAT: u8 = 22;
+
+pub const IEQ: u8 = 30;
+pub const ILT: u8 = 31;
+pub const IGT: u8 = 32;
+pub const ILE: u8 = 33;
+pub const IGE: u8 = 34;
+pub const FEQ: u8 = 35;
+pub const FLT: u8 = 36;
+pub const FGT: u8 = 37;
+pub const FLE: u8 = 38;
+pub const FGE: u8 = 39;
+pub const DEQ: u8 = 40;
+pub const DLT: u8 = 41;
+pub const DGT: u8 = 42;
+pub const DLE: u8 = 43;
+pub const DGE: u8 = 44;
+pub const BEQ: u8 = 45;
+pub const SEQ: u8 = 46;
+
+pub const I2F: u8 = 50;
+pub const I2D: u8 = 51;
+pub const F2I: u8 = 52;
+pub const F2D: u8 = 53;
+pub const D2I: u8 = 54;
+pub const D2F: u8 = 55;
+
+// operand: u16 absolute code offset
+pub const JUMP: u8 = 60;
+pub const JUMP_IF_FALSE: u8 = 61;
+// operand: u16 function index
+pub const CALL: u8 = 62;
+pub const RETURN: u8 = 63;
+pub const RETURN_VOID: u8 = 64;
+pub const POP: u8 = 65;
+pub const PRINT: u8 = 66;
+// operand: u16 function index
+pub const TAIL_CALL: u8 = 67;
+
+// mnemonic and operand byte count of the opcode. None for invalid opcodes
+pub fn opcode_info(opcode: u8) -> Option<(&'static str, usize)> {
+  let info = match opcode {
+    CONST => ("CONST", 2),
+    TRUE => ("TRUE", 0),
+    FALSE => ("FALSE", 0),
+    LOAD_LOCAL => ("LOAD_LOCAL", 2),
+    STORE_LOCAL => ("STORE_LOCAL", 2),
+    LOAD_GLOBAL => ("LOAD_GLOBAL", 2),
+    STORE_GLOBAL => ("STORE_GLOBAL", 2),
+    IADD => ("IADD", 0),
+    ISUB => ("ISUB", 0),
+    IMUL => ("IMUL", 0),
+    IDIV => ("IDIV", 0),
+    FADD => ("FADD", 0),
+    FSUB => ("FSUB", 0),
+    FMUL => ("FMUL", 0),
+    FDIV => ("FDIV", 0),
+    DADD => ("DADD", 0),
+    DSUB => ("DSUB", 0),
+    DMUL => ("DMUL", 0),
+    DDIV => ("DDIV", 0),
+    SCONCAT => ("SCONCAT", 0),
+    IEQ => ("IEQ", 0),
+    ILT => ("ILT", 0),
+    IGT => ("IGT", 0),
+    ILE => ("ILE", 0),
+    IGE => ("IGE", 0),
+    FEQ => ("FEQ", 0),
+    FLT => ("FLT", 0),
+    FGT => ("FGT", 0),
+    FLE => ("FLE", 0),
+    FGE => ("FGE", 0),
+    DEQ => ("DEQ", 0),
+    DLT => ("DLT", 0),
+    DGT => ("DGT", 0),
+    DLE => ("DLE", 0),
+    DGE => ("DGE", 0),
+    BEQ => ("BEQ", 0),
+    SEQ => ("SEQ", 0),
+    I2F => ("I2F", 0),
+    I2D => ("I2D", 0),
+    F2I => ("F2I", 0),
+    F2D => ("F2D", 0),
+    D2I => ("D2I", 0),
+    D2F => ("D2F", 0),
+    JUMP => ("JUMP", 2),
+    JUMP_IF_FALSE => ("JUMP_IF_FALSE", 2),
+    CALL => ("CALL", 2),
+    RETURN => ("RETURN", 0),
+    RETURN_VOID => ("RETURN_VOID", 0),
+    POP => ("POP", 0),
+    PRINT => ("PRINT", 0),
+    TAIL_CALL => ("TAIL_CALL", 2),
+    _ => return None,
+  };
+  Some(info)
+}
+
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Constant {
+  Integer(i32),
+  Float(f32),
+  Double(f64),
+  Text(usize), // index to text table
+}
+
+#[derive(Show, Clone, Copy, PartialEq)]
+pub struct LineEntry {
+  // first code offset generated from the position
+  pub offset: usize,
+  pub pos: Position,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct Chunk {
+  pub name: String,
+  pub arity: usize,
+  // parameters included
+  pub local_count: usize,
+  pub code: Vec<u8>,
+  pub constants: Vec<Constant>,
+  pub lines: Vec<LineEntry>,
+  pub pos: Position,
+}
+
+impl Chunk {
+  pub fn new(name: String, arity: usize, pos: Position) -> Chunk {
+    Chunk {
+      name: name,
+      arity: arity,
+      local_count: arity,
+      code: vec![],
+      constants: vec![],
+      lines: vec![],
+      pos: pos,
+    }
+  }
+
+  // source position of the instruction at the offset
+  pub fn position_at(&self, offset: usize) -> Position {
+    let mut pos = self.pos;
+    for entry in self.lines.iter() {
+      if entry.offset > offset {
+        break;
+      }
+      pos = entry.pos;
+    }
+    pos
+  }
+
+  pub fn read_u16(&self, offset: usize) -> usize {
+    ((self.code[offset] as usize) << 8) | (self.code[offset + 1] as usize)
+  }
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct Module {
+  pub functions: Vec<Chunk>,
+  pub global_count: usize,
+  // synthetic function that initializes the globals
+  pub init: usize,
+  pub main: Option<usize>,
+  pub text_table: Vec<String>,
+}
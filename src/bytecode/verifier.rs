@@ -0,0 +1,655 @@
+use std::fmt;
+use std::iter;
+use bytecode::*;
+
+/*
+  Checks a module before it is executed, so the virtual machine can trust the
+  bytecode. For every function:
+    -every opcode is valid and its operands are inside the code
+    -constant, local, global and function indices are within range
+    -jumps target the start of an instruction inside the function
+    -the value stack has the same height on every path reaching an
+     instruction, never underflows, and holds the arguments of every call
+    -every instruction finds values of the kinds it expects on the stack
+    -locals other than the parameters are stored before they are read
+    -execution can not run past the end of the code
+
+  The init function must exist and take no parameters; main, if present,
+  must exist and take no parameters. Returns all found errors.
+
+  The flow of values is only checked once every instruction can be decoded.
+  Bytecode does not declare the types of parameters, return values and
+  globals, so they are inferred from the module: a parameter has the kind of
+  the arguments passed to it, a function returns the kind of its return
+  instructions and of the functions it tail calls, and a global holds the
+  kind stored into it, all of which must agree. The inference is repeated
+  until no more kinds become known; values whose kind stays unknown, like
+  the parameters of a function that is never called, are accepted
+  everywhere.
+
+  Globals hold no value before the init function stores one. Init may only
+  read a global, directly or through the functions it calls, once it stored
+  it on every path; the other functions run after init and may read every
+  global it stores.
+*/
+
+pub fn verify(module: &Module) -> Result<(), Vec<String>> {
+  let mut errors = vec![];
+
+  if module.init >= module.functions.len() {
+    errors.push(format!("Verification error: init function index {} is out of range", module.init));
+  } else if module.functions[module.init].arity != 0 {
+    errors.push("Verification error: init function must not take parameters".to_string());
+  }
+
+  match module.main {
+    Some(main) => {
+      if main >= module.functions.len() {
+        errors.push(format!("Verification error: main function index {} is out of range", main));
+      } else if module.functions[main].arity != 0 {
+        errors.push("Verification error: main function must not take parameters".to_string());
+      }
+    },
+    None => { },
+  }
+
+  let mut decoded = vec![];
+  for chunk in module.functions.iter() {
+    let mut verifier = ChunkVerifier { module: module, chunk: chunk, errors: vec![] };
+    decoded.push(verifier.verify());
+    errors.push_all(verifier.errors.as_slice());
+  }
+
+  if !errors.is_empty() {
+    return Err(errors);
+  }
+
+  let code: Vec<Code> = decoded.into_iter().map(|code| code.unwrap()).collect();
+  let global_reads = global_reads(module, &code);
+  // init runs first and finds out which globals are initialized
+  let mut order = vec![module.init];
+  order.extend(range(0, module.functions.len()).filter(|function| *function != module.init));
+
+  let mut signatures = Signatures {
+    parameters: module.functions.iter().map(|chunk| iter::repeat(None).take(chunk.arity).collect()).collect(),
+    returns: iter::repeat(None).take(module.functions.len()).collect(),
+    globals: iter::repeat(None).take(module.global_count).collect(),
+    initialized: None,
+  };
+  loop {
+    let mut changed = false;
+    for function in order.iter() {
+      let mut flow = Flow::new(module, *function, &code[*function], &global_reads, false);
+      flow.run(&mut signatures);
+      changed = changed || flow.changed;
+    }
+    if !changed {
+      break;
+    }
+  }
+
+  for function in order.iter() {
+    let mut flow = Flow::new(module, *function, &code[*function], &global_reads, true);
+    flow.run(&mut signatures);
+    errors.push_all(flow.errors.as_slice());
+  }
+
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(errors)
+  }
+}
+
+struct Instruction {
+  offset: usize,
+  opcode: u8,
+  operand: usize,
+  // offset of the following instruction
+  next: usize,
+}
+
+// decoded instructions of a chunk
+struct Code {
+  instructions: Vec<Instruction>,
+  // index of the instruction starting at each code offset
+  starts: Vec<Option<usize>>,
+}
+
+// kind of a value on the stack or in a local
+#[derive(Show, Clone, Copy, PartialEq)]
+enum Kind {
+  Integer,
+  Float,
+  Double,
+  Boolean,
+  Text,
+  Void,
+  // the kind can not be inferred, the value is accepted everywhere
+  Unknown,
+}
+
+impl fmt::String for Kind {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    fmt::String::fmt(
+      match *self {
+        Kind::Integer => "int",
+        Kind::Float => "float",
+        Kind::Double => "double",
+        Kind::Boolean => "bool",
+        Kind::Text => "string",
+        Kind::Void => "void",
+        Kind::Unknown => "unknown",
+      }, formatter)
+  }
+}
+
+// kinds inferred for the module, None while unknown
+struct Signatures {
+  parameters: Vec<Vec<Option<Kind>>>,
+  returns: Vec<Option<Kind>>,
+  globals: Vec<Option<Kind>>,
+  // globals stored on every path through init, None if init never returns
+  initialized: Option<Vec<bool>>,
+}
+
+#[derive(Clone, PartialEq)]
+struct State {
+  stack: Vec<Kind>,
+  // None if the local may not have been stored
+  locals: Vec<Option<Kind>>,
+  // globals stored on every path, only tracked in the init function
+  stored: Vec<bool>,
+}
+
+struct ChunkVerifier<'a> {
+  module: &'a Module,
+  chunk: &'a Chunk,
+  errors: Vec<String>,
+}
+
+impl<'a> ChunkVerifier<'a> {
+  fn verify(&mut self) -> Option<Code> {
+    if self.chunk.local_count < self.chunk.arity {
+      let msg = format!("function has {} locals, but {} parameters",
+        self.chunk.local_count, self.chunk.arity);
+      self.register_error(msg, None);
+    }
+
+    if self.chunk.code.is_empty() {
+      self.register_error("function has no code".to_string(), None);
+      return None;
+    }
+
+    let instructions = self.decode();
+    if !self.errors.is_empty() {
+      return None;
+    }
+
+    let mut starts = vec![];
+    for _ in range(0, self.chunk.code.len()) {
+      starts.push(None);
+    }
+    for (index, instruction) in instructions.iter().enumerate() {
+      starts[instruction.offset] = Some(index);
+    }
+
+    for instruction in instructions.iter() {
+      self.check_operand(instruction, &starts);
+    }
+
+    if self.errors.is_empty() {
+      Some(Code { instructions: instructions, starts: starts })
+    } else {
+      None
+    }
+  }
+
+  fn decode(&mut self) -> Vec<Instruction> {
+    let chunk = self.chunk;
+    let code = &chunk.code;
+    let mut instructions = vec![];
+    let mut offset = 0;
+    while offset < code.len() {
+      let opcode = code[offset];
+      match opcode_info(opcode) {
+        Some((name, operand_size)) => {
+          let next = offset + 1 + operand_size;
+          if next > code.len() {
+            self.register_error(format!("operand of {} is truncated", name), Some(offset));
+            break;
+          }
+
+          let operand = if operand_size == 0 { 0 } else { chunk.read_u16(offset + 1) };
+          instructions.push(Instruction { offset: offset, opcode: opcode, operand: operand, next: next });
+          offset = next;
+        },
+        None => {
+          self.register_error(format!("invalid opcode {}", opcode), Some(offset));
+          break;
+        }
+      }
+    }
+    instructions
+  }
+
+  fn check_operand(&mut self, instruction: &Instruction, starts: &Vec<Option<usize>>) {
+    let chunk = self.chunk;
+    let module = self.module;
+    let operand = instruction.operand;
+    let offset = Some(instruction.offset);
+    match instruction.opcode {
+      CONST => {
+        if operand >= chunk.constants.len() {
+          self.register_error(format!("constant index {} is out of range", operand), offset);
+        } else {
+          match chunk.constants[operand] {
+            Constant::Text(index) => {
+              if index >= module.text_table.len() {
+                self.register_error(format!("string constant refers to text {} which does not exist", index), offset);
+              }
+            },
+            _ => { },
+          }
+        }
+      },
+      LOAD_LOCAL | STORE_LOCAL => {
+        if operand >= chunk.local_count {
+          self.register_error(format!("local slot {} is out of range", operand), offset);
+        }
+      },
+      LOAD_GLOBAL | STORE_GLOBAL => {
+        if operand >= module.global_count {
+          self.register_error(format!("global index {} is out of range", operand), offset);
+        }
+      },
+      JUMP | JUMP_IF_FALSE => {
+        if operand >= starts.len() || starts[operand].is_none() {
+          self.register_error(format!("jump target {:04} is not the start of an instruction", operand), offset);
+        }
+      },
+      CALL | TAIL_CALL => {
+        if operand >= module.functions.len() {
+          self.register_error(format!("function index {} is out of range", operand), offset);
+        }
+      },
+      _ => { },
+    }
+  }
+
+  fn register_error(&mut self, msg: String, offset: Option<usize>) {
+    self.errors.push(error_message(self.chunk, msg, offset));
+  }
+}
+
+// follows the values through a chunk from its first instruction, merging
+// the states where paths join until none changes
+struct Flow<'a> {
+  module: &'a Module,
+  function: usize,
+  chunk: &'a Chunk,
+  code: &'a Code,
+  global_reads: &'a Vec<Vec<bool>>,
+  // errors are only reported once the inferred kinds are final
+  report: bool,
+  // whether a kind of the signatures became known
+  changed: bool,
+  errors: Vec<String>,
+}
+
+impl<'a> Flow<'a> {
+  fn new(module: &'a Module, function: usize, code: &'a Code, global_reads: &'a Vec<Vec<bool>>,
+    report: bool) -> Flow<'a> {
+
+    Flow {
+      module: module,
+      function: function,
+      chunk: &module.functions[function],
+      code: code,
+      global_reads: global_reads,
+      report: report,
+      changed: false,
+      errors: vec![],
+    }
+  }
+
+  fn run(&mut self, signatures: &mut Signatures) {
+    let module = self.module;
+    let chunk = self.chunk;
+    let code = self.code;
+    let is_init = self.function == module.init;
+
+    let mut locals: Vec<Option<Kind>> = iter::repeat(None).take(chunk.local_count).collect();
+    for slot in range(0, chunk.arity) {
+      locals[slot] = Some(signatures.parameters[self.function][slot].unwrap_or(Kind::Unknown));
+    }
+    let stored = if is_init { iter::repeat(false).take(module.global_count).collect() } else { vec![] };
+
+    let mut states: Vec<Option<State>> = code.instructions.iter().map(|_| None).collect();
+    states[0] = Some(State { stack: vec![], locals: locals, stored: stored });
+    let mut worklist = vec![0];
+    let mut initialized: Option<Vec<bool>> = None;
+
+    while let Some(index) = worklist.pop() {
+      let instruction = &code.instructions[index];
+      let mut state = states[index].clone().unwrap();
+      if !self.execute(instruction, &mut state, signatures) {
+        continue;
+      }
+
+      let mut successors = vec![];
+      match instruction.opcode {
+        JUMP => successors.push(instruction.operand),
+        JUMP_IF_FALSE => {
+          successors.push(instruction.next);
+          successors.push(instruction.operand);
+        },
+        RETURN | RETURN_VOID | TAIL_CALL => {
+          if is_init {
+            initialized = Some(match initialized {
+              Some(initialized) => initialized.iter().zip(state.stored.iter()).map(|(a, b)| *a && *b).collect(),
+              None => state.stored.clone(),
+            });
+          }
+        },
+        _ => successors.push(instruction.next),
+      }
+
+      for successor in successors.into_iter() {
+        if successor >= code.starts.len() {
+          self.register_error("execution runs past the end of the code".to_string(), instruction.offset);
+          continue;
+        }
+
+        let successor = code.starts[successor].unwrap();
+        let merged = match states[successor] {
+          Some(ref existing) => self.merge(existing, &state, code.instructions[successor].offset),
+          None => Some(state.clone()),
+        };
+        match merged {
+          Some(merged) => {
+            if states[successor].as_ref() != Some(&merged) {
+              states[successor] = Some(merged);
+              worklist.push(successor);
+            }
+          },
+          None => { },
+        }
+      }
+    }
+
+    if is_init {
+      signatures.initialized = initialized;
+    }
+  }
+
+  // applies the instruction to the state. False if the stack does not hold
+  // enough values to continue
+  fn execute(&mut self, instruction: &Instruction, state: &mut State, signatures: &mut Signatures) -> bool {
+    let module = self.module;
+    let chunk = self.chunk;
+    let operand = instruction.operand;
+    let offset = instruction.offset;
+    let pops = match instruction.opcode {
+      STORE_LOCAL | STORE_GLOBAL | JUMP_IF_FALSE | RETURN | POP | PRINT => 1,
+      I2F | I2D | F2I | F2D | D2I | D2F => 1,
+      CONST | TRUE | FALSE | LOAD_LOCAL | LOAD_GLOBAL | JUMP | RETURN_VOID => 0,
+      CALL | TAIL_CALL => module.functions[operand].arity,
+      _ => 2, // binary operators
+    };
+    if state.stack.len() < pops {
+      let msg = format!("instruction needs {} value(s), but the stack holds {}", pops, state.stack.len());
+      self.register_error(msg, offset);
+      return false;
+    }
+
+    match instruction.opcode {
+      CONST => {
+        let kind = match chunk.constants[operand] {
+          Constant::Integer(..) => Kind::Integer,
+          Constant::Float(..) => Kind::Float,
+          Constant::Double(..) => Kind::Double,
+          Constant::Text(..) => Kind::Text,
+        };
+        state.stack.push(kind);
+      },
+      TRUE | FALSE => state.stack.push(Kind::Boolean),
+      LOAD_LOCAL => {
+        match state.locals[operand] {
+          Some(kind) => state.stack.push(kind),
+          None => {
+            self.register_error(format!("local slot {} may be read before it is stored", operand), offset);
+            state.stack.push(Kind::Unknown);
+          },
+        }
+      },
+      STORE_LOCAL => {
+        let kind = state.stack.pop().unwrap();
+        state.locals[operand] = Some(kind);
+      },
+      LOAD_GLOBAL => {
+        let initialized = if self.function == module.init {
+          state.stored[operand]
+        } else {
+          match signatures.initialized {
+            Some(ref initialized) => initialized[operand],
+            None => true,
+          }
+        };
+        if !initialized {
+          self.register_error(format!("global {} is read before it is initialized", operand), offset);
+        }
+        state.stack.push(signatures.globals[operand].unwrap_or(Kind::Unknown));
+      },
+      STORE_GLOBAL => {
+        let kind = state.stack.pop().unwrap();
+        match self.infer(&mut signatures.globals[operand], kind) {
+          Some(existing) => {
+            let msg = format!("stores {} into global {}, which holds {} elsewhere", kind, operand, existing);
+            self.register_error(msg, offset);
+          },
+          None => { },
+        }
+        if self.function == module.init {
+          state.stored[operand] = true;
+        }
+      },
+
+      IADD | ISUB | IMUL | IDIV => self.binary(instruction, state, Kind::Integer, Kind::Integer),
+      FADD | FSUB | FMUL | FDIV => self.binary(instruction, state, Kind::Float, Kind::Float),
+      DADD | DSUB | DMUL | DDIV => self.binary(instruction, state, Kind::Double, Kind::Double),
+      SCONCAT => self.binary(instruction, state, Kind::Text, Kind::Text),
+      IEQ | ILT | IGT | ILE | IGE => self.binary(instruction, state, Kind::Integer, Kind::Boolean),
+      FEQ | FLT | FGT | FLE | FGE => self.binary(instruction, state, Kind::Float, Kind::Boolean),
+      DEQ | DLT | DGT | DLE | DGE => self.binary(instruction, state, Kind::Double, Kind::Boolean),
+      BEQ => self.binary(instruction, state, Kind::Boolean, Kind::Boolean),
+      SEQ => self.binary(instruction, state, Kind::Text, Kind::Boolean),
+
+      I2F => self.unary(instruction, state, Kind::Integer, Kind::Float),
+      I2D => self.unary(instruction, state, Kind::Integer, Kind::Double),
+      F2I => self.unary(instruction, state, Kind::Float, Kind::Integer),
+      F2D => self.unary(instruction, state, Kind::Float, Kind::Double),
+      D2I => self.unary(instruction, state, Kind::Double, Kind::Integer),
+      D2F => self.unary(instruction, state, Kind::Double, Kind::Float),
+
+      JUMP => { },
+      JUMP_IF_FALSE => self.expect(instruction, state, Kind::Boolean),
+      CALL | TAIL_CALL => {
+        let callee = &module.functions[operand];
+        let start = state.stack.len() - callee.arity;
+        let arguments = state.stack.slice_from(start).to_vec();
+        state.stack.truncate(start);
+        for (index, kind) in arguments.iter().enumerate() {
+          match self.infer(&mut signatures.parameters[operand][index], *kind) {
+            Some(existing) => {
+              let msg = format!("argument {} of the call of '{}' is {}, but {} elsewhere",
+                index + 1, callee.name, kind, existing);
+              self.register_error(msg, offset);
+            },
+            None => { },
+          }
+        }
+        if self.function == module.init {
+          for global in range(0, module.global_count) {
+            if self.global_reads[operand][global] && !state.stored[global] {
+              let msg = format!("call of '{}' reads global {} before it is initialized", callee.name, global);
+              self.register_error(msg, offset);
+            }
+          }
+        }
+        let returned = signatures.returns[operand];
+        if instruction.opcode == CALL {
+          state.stack.push(returned.unwrap_or(Kind::Unknown));
+        } else {
+          // the function returns what the callee returns
+          match returned {
+            Some(kind) => self.check_return(kind, offset, signatures),
+            None => { },
+          }
+        }
+      },
+      RETURN | RETURN_VOID => {
+        let kind = if instruction.opcode == RETURN { state.stack.pop().unwrap() } else { Kind::Void };
+        self.check_return(kind, offset, signatures);
+      },
+      POP => { state.stack.pop(); },
+      PRINT => {
+        state.stack.pop();
+        state.stack.push(Kind::Void);
+      },
+      _ => panic!("Internal compiler error: opcode {} passed decoding", instruction.opcode),
+    }
+    true
+  }
+
+  fn check_return(&mut self, kind: Kind, offset: usize, signatures: &mut Signatures) {
+    match self.infer(&mut signatures.returns[self.function], kind) {
+      Some(existing) => {
+        let msg = format!("function returns {}, but {} elsewhere", kind, existing);
+        self.register_error(msg, offset);
+      },
+      None => { },
+    }
+  }
+
+  fn unary(&mut self, instruction: &Instruction, state: &mut State, operand: Kind, result: Kind) {
+    self.expect(instruction, state, operand);
+    state.stack.push(result);
+  }
+
+  fn binary(&mut self, instruction: &Instruction, state: &mut State, operands: Kind, result: Kind) {
+    self.expect(instruction, state, operands);
+    self.expect(instruction, state, operands);
+    state.stack.push(result);
+  }
+
+  // pops a value of the expected kind
+  fn expect(&mut self, instruction: &Instruction, state: &mut State, expected: Kind) {
+    let kind = state.stack.pop().unwrap();
+    if kind != expected && kind != Kind::Unknown {
+      let (name, _) = opcode_info(instruction.opcode).unwrap();
+      let msg = format!("{} expects {}, but the stack holds {}", name, expected, kind);
+      self.register_error(msg, instruction.offset);
+    }
+  }
+
+  // remembers the kind of a parameter, return value or global if it is not
+  // known yet. Returns the known kind if it differs
+  fn infer(&mut self, known: &mut Option<Kind>, kind: Kind) -> Option<Kind> {
+    if kind == Kind::Unknown {
+      return None;
+    }
+    match *known {
+      Some(existing) if existing != kind => Some(existing),
+      Some(..) => None,
+      None => {
+        *known = Some(kind);
+        self.changed = true;
+        None
+      },
+    }
+  }
+
+  // the state where two paths join, None if they do not fit together
+  fn merge(&mut self, existing: &State, incoming: &State, offset: usize) -> Option<State> {
+    if existing.stack.len() != incoming.stack.len() {
+      let msg = format!("stack height {} does not match height {} of another path",
+        incoming.stack.len(), existing.stack.len());
+      self.register_error(msg, offset);
+      return None;
+    }
+
+    let mut stack = vec![];
+    for (position, (first, second)) in existing.stack.iter().zip(incoming.stack.iter()).enumerate() {
+      if first == second {
+        stack.push(*first);
+      } else if *first == Kind::Unknown || *second == Kind::Unknown {
+        stack.push(Kind::Unknown);
+      } else {
+        let msg = format!("value {} of the stack is {} on one path and {} on another", position, second, first);
+        self.register_error(msg, offset);
+        return None;
+      }
+    }
+
+    let locals = existing.locals.iter().zip(incoming.locals.iter()).map(|(first, second)| {
+      match (*first, *second) {
+        (Some(first), Some(second)) if first == second => Some(first),
+        (Some(Kind::Unknown), Some(..)) | (Some(..), Some(Kind::Unknown)) => Some(Kind::Unknown),
+        _ => None,
+      }
+    }).collect();
+    let stored = existing.stored.iter().zip(incoming.stored.iter()).map(|(a, b)| *a && *b).collect();
+    Some(State { stack: stack, locals: locals, stored: stored })
+  }
+
+  fn register_error(&mut self, msg: String, offset: usize) {
+    let error = error_message(self.chunk, msg, Some(offset));
+    if self.report && !self.errors.contains(&error) {
+      self.errors.push(error);
+    }
+  }
+}
+
+// function -> globals it reads, directly or through the functions it calls
+fn global_reads(module: &Module, code: &Vec<Code>) -> Vec<Vec<bool>> {
+  let mut reads: Vec<Vec<bool>> = vec![];
+  let mut callees: Vec<Vec<usize>> = vec![];
+  for function in code.iter() {
+    let mut read: Vec<bool> = iter::repeat(false).take(module.global_count).collect();
+    let mut called = vec![];
+    for instruction in function.instructions.iter() {
+      match instruction.opcode {
+        LOAD_GLOBAL => read[instruction.operand] = true,
+        CALL | TAIL_CALL => called.push(instruction.operand),
+        _ => { },
+      }
+    }
+    reads.push(read);
+    callees.push(called);
+  }
+
+  let mut changed = true;
+  while changed {
+    changed = false;
+    for function in range(0, reads.len()) {
+      for callee in callees[function].iter() {
+        for global in range(0, module.global_count) {
+          let read = reads[*callee][global];
+          if read && !reads[function][global] {
+            reads[function][global] = true;
+            changed = true;
+          }
+        }
+      }
+    }
+  }
+  reads
+}
+
+fn error_message(chunk: &Chunk, msg: String, offset: Option<usize>) -> String {
+  match offset {
+    Some(offset) => format!("Verification error in function '{}' at offset {:04}: {}", chunk.name, offset, msg),
+    None => format!("Verification error in function '{}': {}", chunk.name, msg),
+  }
+}
@@ -0,0 +1,219 @@
+use std::iter;
+use ast::Program;
+use ast::Block;
+use ast::Statement;
+use ast::Expression;
+use ast::ExpressionKind;
+use ast::FunctionCall;
+use ir;
+
+/*
+  Call graph of a program, built from every function call in the bodies of
+  its functions. Calls of built-in functions such as print are not part of
+  the graph. Functions are identified by their index in the program.
+
+  A function is reachable if main calls it, directly or through other
+  functions. Functions that are not reachable are reported as warnings, as
+  nothing can ever run them; a program without main has no reachable
+  functions and no warnings. Recursion is found with the strongly connected
+  components of the call graph of the intermediate representation: a
+  function is directly recursive if it calls itself, and mutually recursive
+  with the other functions of its component.
+
+  The graph can be written in the DOT language of Graphviz. Unreachable
+  functions are drawn dashed and recursive functions with a double border.
+*/
+
+pub struct CallGraph {
+  names: Vec<String>,
+  graph: ir::call_graph::CallGraph,
+  reachable: Vec<bool>,
+}
+
+impl CallGraph {
+  pub fn new(program: &Program) -> CallGraph {
+    let names: Vec<String> = program.functions.iter()
+      .map(|function| program.get_text(function.name).to_string())
+      .collect();
+    let mut calls = vec![];
+    for (caller, function) in program.functions.iter().enumerate() {
+      let mut callees = vec![];
+      block_calls(&function.body, &mut callees);
+      for call in callees.iter() {
+        match program.functions.iter().position(|function| function.name == call.name) {
+          Some(callee) => calls.push((caller, callee)),
+          None => { },
+        }
+      }
+    }
+    let graph = ir::call_graph::CallGraph::from_calls(names.len(), calls.as_slice());
+
+    let mut reachable = iter::repeat(false).take(names.len()).collect::<Vec<bool>>();
+    let mut stack: Vec<usize> = names.iter().position(|name| name.as_slice() == "main").into_iter().collect();
+    for function in stack.iter() {
+      reachable[*function] = true;
+    }
+    while !stack.is_empty() {
+      let function = stack.pop().unwrap();
+      for callee in graph.callees(function).iter() {
+        if !reachable[*callee] {
+          reachable[*callee] = true;
+          stack.push(*callee);
+        }
+      }
+    }
+
+    CallGraph {
+      names: names,
+      graph: graph,
+      reachable: reachable,
+    }
+  }
+
+  pub fn function_index(&self, name: &str) -> Option<usize> {
+    self.names.iter().position(|function| function.as_slice() == name)
+  }
+
+  pub fn callees(&self, function: usize) -> &[usize] {
+    self.graph.callees(function)
+  }
+
+  pub fn call_sites(&self, function: usize) -> usize {
+    self.graph.call_sites(function)
+  }
+
+  pub fn is_reachable(&self, function: usize) -> bool {
+    self.reachable[function]
+  }
+
+  pub fn unreachable_functions(&self) -> Vec<usize> {
+    range(0, self.names.len()).filter(|function| !self.reachable[*function]).collect()
+  }
+
+  pub fn is_recursive(&self, function: usize) -> bool {
+    self.graph.is_recursive(function)
+  }
+
+  pub fn is_directly_recursive(&self, function: usize) -> bool {
+    self.graph.callees(function).contains(&function)
+  }
+
+  // the other functions the function calls through a cycle of calls
+  pub fn mutually_recursive(&self, function: usize) -> Vec<usize> {
+    range(0, self.names.len())
+      .filter(|other| *other != function && self.graph.in_same_cycle(function, *other))
+      .collect()
+  }
+
+  // all functions, callees before their callers
+  pub fn bottom_up_order(&self) -> &[usize] {
+    self.graph.bottom_up_order()
+  }
+
+  pub fn to_dot(&self) -> String {
+    let mut dot = "digraph calls {\n".to_string();
+    for (function, name) in self.names.iter().enumerate() {
+      let mut attributes = vec![];
+      if !self.reachable[function] {
+        attributes.push("style=dashed");
+      }
+      if self.is_recursive(function) {
+        attributes.push("peripheries=2");
+      }
+      if attributes.is_empty() {
+        dot.push_str(format!("  \"{}\";\n", name).as_slice());
+      } else {
+        dot.push_str(format!("  \"{}\" [{}];\n", name, attributes.connect(", ")).as_slice());
+      }
+    }
+    for (caller, name) in self.names.iter().enumerate() {
+      for callee in self.graph.callees(caller).iter() {
+        dot.push_str(format!("  \"{}\" -> \"{}\";\n", name, self.names[*callee]).as_slice());
+      }
+    }
+    dot.push_str("}\n");
+    dot
+  }
+}
+
+// warnings for the functions main can never call
+pub fn check(program: &Program) -> Vec<String> {
+  let graph = CallGraph::new(program);
+  if graph.function_index("main").is_none() {
+    return vec![];
+  }
+  graph.unreachable_functions().iter().map(|function| {
+    let function = &program.functions[*function];
+    format!("Warning at {}: Function '{}' is never called from 'main'", function.pos,
+      program.get_text(function.name))
+  }).collect()
+}
+
+fn block_calls<'a>(block: &'a Block, calls: &mut Vec<&'a FunctionCall>) {
+  for statement in block.statements.iter() {
+    statement_calls(statement, calls);
+  }
+}
+
+fn statement_calls<'a>(statement: &'a Statement, calls: &mut Vec<&'a FunctionCall>) {
+  match *statement {
+    Statement::Block(ref block) => block_calls(block, calls),
+    Statement::VariableDeclaration(ref declaration) => expression_calls(&declaration.initializer, calls),
+    Statement::Assignment(ref assignment) => expression_calls(&assignment.value, calls),
+    Statement::FunctionCall(ref call) => function_call_calls(call, calls),
+    Statement::For(ref for_loop) => {
+      match for_loop.init {
+        Some(ref init) => statement_calls(&**init, calls),
+        None => { },
+      }
+      match for_loop.condition {
+        Some(ref condition) => expression_calls(condition, calls),
+        None => { },
+      }
+      match for_loop.update {
+        Some(ref update) => expression_calls(&update.value, calls),
+        None => { },
+      }
+      block_calls(&for_loop.body, calls);
+    },
+    Statement::If(ref if_statement) => {
+      expression_calls(&if_statement.condition, calls);
+      block_calls(&if_statement.block, calls);
+      for else_if in if_statement.else_ifs.iter() {
+        expression_calls(&else_if.condition, calls);
+        block_calls(&else_if.block, calls);
+      }
+      match if_statement.else_block {
+        Some(ref block) => block_calls(block, calls),
+        None => { },
+      }
+    },
+    Statement::Return(ref return_statement) => {
+      match return_statement.value {
+        Some(ref value) => expression_calls(value, calls),
+        None => { },
+      }
+    },
+    Statement::Empty(..) => { },
+  }
+}
+
+// the call itself and the calls in its arguments
+fn function_call_calls<'a>(call: &'a FunctionCall, calls: &mut Vec<&'a FunctionCall>) {
+  calls.push(call);
+  for argument in call.arguments.iter() {
+    expression_calls(argument, calls);
+  }
+}
+
+fn expression_calls<'a>(expression: &'a Expression, calls: &mut Vec<&'a FunctionCall>) {
+  match expression.kind {
+    ExpressionKind::Literal(..) | ExpressionKind::Variable(..) => { },
+    ExpressionKind::Binary(_, ref left, ref right) => {
+      expression_calls(&**left, calls);
+      expression_calls(&**right, calls);
+    },
+    ExpressionKind::Call(ref call) => function_call_calls(call, calls),
+    ExpressionKind::Cast(ref inner, _) => expression_calls(&**inner, calls),
+  }
+}
@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+use std::f32;
+use std::i32;
+use std::num::Float;
+use ast::Program;
+use ast::Function;
+use ast::Block;
+use ast::Statement;
+use ast::VariableDeclaration;
+use ast::Expression;
+use ast::ExpressionKind;
+use ast::Literal;
+use ast::BinaryOperator;
+use ast::Position;
+use ast::Type;
+use symbol_table::SymbolTable;
+
+/*
+  Compile-time evaluation of constant expressions. An expression is constant
+  if it only consists of literals, global constants and casts and binary
+  operators applied to constants. Expects a type checked program, so that
+  operands of binary operators have the same type.
+
+  evaluate computes the value of a single expression, for example a global
+  constant initializer. fold replaces every constant subtree of the program
+  with a literal, evaluating the global constants in declaration order so
+  that later declarations and the functions can use their values. Strings
+  built by concatenation are added to the text table.
+
+  Integer arithmetic does not wrap around at compile time: an overflowing
+  operation and an integer division by zero are errors at the position of
+  the operator. In a branch that is never executed, because its condition
+  is the constant false or an earlier condition of the if statement is the
+  constant true, they are warnings instead and the operation is left
+  unfolded. Float and double results that are not finite are left to be
+  computed at run time, as the backends can not write them as literals.
+
+  fold returns the warnings on success. On failure, the errors are returned,
+  followed by any warnings that were generated.
+*/
+
+#[derive(Show, Clone, PartialEq)]
+pub enum Constant {
+  Integer(i32),
+  Float(f32),
+  Double(f64),
+  Boolean(bool),
+  Text(String),
+}
+
+// the value of the expression, or None if it is not constant. Names in the
+// constants map are global constants
+pub fn evaluate(expression: &Expression, text_table: &Vec<String>,
+  constants: &HashMap<usize, Constant>) -> Result<Option<Constant>, String> {
+
+  match expression.kind {
+    ExpressionKind::Literal(literal) => Ok(Some(from_literal(literal, text_table))),
+    ExpressionKind::Variable(name) => Ok(constants.get(&name).map(|constant| constant.clone())),
+    ExpressionKind::Binary(operator, ref left, ref right) => {
+      let left = try!(evaluate(&**left, text_table, constants));
+      let right = try!(evaluate(&**right, text_table, constants));
+      match (left, right) {
+        (Some(left), Some(right)) => binary_operation(operator, left, right)
+          .map_err(|msg| compile_error(msg.as_slice(), &expression.pos)),
+        _ => Ok(None),
+      }
+    },
+    ExpressionKind::Cast(ref inner, target) => {
+      match try!(evaluate(&**inner, text_table, constants)) {
+        Some(constant) => convert(constant, target)
+          .map_err(|msg| compile_error(msg.as_slice(), &expression.pos)),
+        None => Ok(None),
+      }
+    },
+    ExpressionKind::Call(..) => Ok(None),
+  }
+}
+
+pub fn fold(program: &mut Program) -> Result<Vec<String>, Vec<String>> {
+  let Program { ref mut globals, ref mut functions, ref mut text_table } = *program;
+  let mut folder = Folder {
+    text_table: text_table,
+    constants: HashMap::new(),
+    locals: SymbolTable::new(),
+    dead: false,
+    errors: vec![],
+    warnings: vec![],
+  };
+
+  for global in globals.iter_mut() {
+    folder.fold_global(global);
+  }
+
+  for function in functions.iter_mut() {
+    folder.fold_function(function);
+  }
+
+  if folder.errors.is_empty() {
+    Ok(folder.warnings)
+  } else {
+    let mut messages = folder.errors;
+    messages.push_all(folder.warnings.as_slice());
+    Err(messages)
+  }
+}
+
+struct Folder<'a> {
+  text_table: &'a mut Vec<String>,
+  constants: HashMap<usize, Constant>,
+  // locals shadow the global constants
+  locals: SymbolTable<()>,
+  // inside a branch that is never executed
+  dead: bool,
+  errors: Vec<String>,
+  warnings: Vec<String>,
+}
+
+impl<'a> Folder<'a> {
+  fn fold_global(&mut self, global: &mut VariableDeclaration) {
+    match self.fold_expression(&mut global.initializer) {
+      Some(constant) => {
+        if global.is_constant {
+          self.constants.insert(global.name, constant);
+        }
+      },
+      None => { },
+    }
+  }
+
+  fn fold_function(&mut self, function: &mut Function) {
+    self.locals.push_scope();
+    for parameter in function.parameters.iter() {
+      self.locals.declare(parameter.name, ());
+    }
+
+    // parameters and the top level of the body share the scope
+    for statement in function.body.statements.iter_mut() {
+      self.fold_statement(statement);
+    }
+    self.locals.pop_scope();
+  }
+
+  fn fold_block(&mut self, block: &mut Block) {
+    self.locals.push_scope();
+    for statement in block.statements.iter_mut() {
+      self.fold_statement(statement);
+    }
+    self.locals.pop_scope();
+  }
+
+  // folds the block of a branch, which is dead if its condition is never true
+  fn fold_branch(&mut self, block: &mut Block, dead: bool) {
+    let outer = self.dead;
+    self.dead = outer || dead;
+    self.fold_block(block);
+    self.dead = outer;
+  }
+
+  fn fold_statement(&mut self, statement: &mut Statement) {
+    match *statement {
+      Statement::Block(ref mut block) => self.fold_block(block),
+      Statement::VariableDeclaration(ref mut declaration) => {
+        self.fold_expression(&mut declaration.initializer);
+        self.locals.declare(declaration.name, ());
+      },
+      Statement::Assignment(ref mut assignment) => { self.fold_expression(&mut assignment.value); },
+      Statement::FunctionCall(ref mut call) => {
+        for argument in call.arguments.iter_mut() {
+          self.fold_expression(argument);
+        }
+      },
+      Statement::For(ref mut for_loop) => {
+        self.locals.push_scope();
+        match for_loop.init {
+          Some(ref mut init) => self.fold_statement(&mut **init),
+          None => { },
+        }
+
+        let dead = match for_loop.condition {
+          Some(ref mut condition) => is_false(&self.fold_expression(condition)),
+          None => false,
+        };
+
+        // the update only runs after the body
+        let outer = self.dead;
+        self.dead = outer || dead;
+        match for_loop.update {
+          Some(ref mut update) => { self.fold_expression(&mut update.value); },
+          None => { },
+        }
+        self.dead = outer;
+
+        self.fold_branch(&mut for_loop.body, dead);
+        self.locals.pop_scope();
+      },
+      Statement::If(ref mut if_statement) => {
+        // once a condition is the constant true, the later branches are dead
+        let condition = self.fold_expression(&mut if_statement.condition);
+        self.fold_branch(&mut if_statement.block, is_false(&condition));
+        let mut taken = is_true(&condition);
+
+        for else_if in if_statement.else_ifs.iter_mut() {
+          let outer = self.dead;
+          self.dead = outer || taken;
+          let condition = self.fold_expression(&mut else_if.condition);
+          self.dead = outer;
+          self.fold_branch(&mut else_if.block, taken || is_false(&condition));
+          taken = taken || is_true(&condition);
+        }
+
+        match if_statement.else_block {
+          Some(ref mut block) => self.fold_branch(block, taken),
+          None => { },
+        }
+      },
+      Statement::Return(ref mut return_statement) => {
+        match return_statement.value {
+          Some(ref mut value) => { self.fold_expression(value); },
+          None => { },
+        }
+      },
+      Statement::Empty(..) => { },
+    }
+  }
+
+  // folds the constant subtrees of the expression and returns its value if
+  // the whole expression is constant
+  fn fold_expression(&mut self, expression: &mut Expression) -> Option<Constant> {
+    let pos = expression.pos;
+    let result = match expression.kind {
+      ExpressionKind::Literal(literal) => return Some(from_literal(literal, &*self.text_table)),
+      ExpressionKind::Variable(name) => {
+        match self.locals.lookup(name) {
+          Some(..) => None,
+          None => self.constants.get(&name).map(|constant| constant.clone()),
+        }
+      },
+      ExpressionKind::Binary(operator, ref mut left, ref mut right) => {
+        let left = self.fold_expression(&mut **left);
+        let right = self.fold_expression(&mut **right);
+        match (left, right) {
+          (Some(left), Some(right)) => self.report(binary_operation(operator, left, right), &pos),
+          _ => None,
+        }
+      },
+      ExpressionKind::Cast(ref mut inner, target) => {
+        match self.fold_expression(&mut **inner) {
+          Some(constant) => self.report(convert(constant, target), &pos),
+          None => None,
+        }
+      },
+      ExpressionKind::Call(ref mut call) => {
+        for argument in call.arguments.iter_mut() {
+          self.fold_expression(argument);
+        }
+        None
+      },
+    };
+
+    match result {
+      Some(ref constant) => expression.kind = ExpressionKind::Literal(self.to_literal(constant)),
+      None => { },
+    }
+    result
+  }
+
+  // a failed operation is an error, or a warning in a dead branch, where it
+  // is left for the runtime check
+  fn report(&mut self, result: Result<Option<Constant>, String>, pos: &Position) -> Option<Constant> {
+    match result {
+      Ok(constant) => constant,
+      Err(msg) => {
+        if self.dead {
+          self.warnings.push(format!("Warning at {}: {} in code that is never executed", pos, msg));
+        } else {
+          self.errors.push(compile_error(msg.as_slice(), pos));
+        }
+        None
+      },
+    }
+  }
+
+  fn to_literal(&mut self, constant: &Constant) -> Literal {
+    match *constant {
+      Constant::Integer(value) => Literal::Integer(value),
+      Constant::Float(value) => Literal::Float(value),
+      Constant::Double(value) => Literal::Double(value),
+      Constant::Boolean(value) => Literal::Boolean(value),
+      Constant::Text(ref value) => {
+        match self.text_table.iter().position(|text| text == value) {
+          Some(index) => Literal::Text(index),
+          None => {
+            self.text_table.push(value.clone());
+            Literal::Text(self.text_table.len() - 1)
+          },
+        }
+      },
+    }
+  }
+}
+
+fn is_true(constant: &Option<Constant>) -> bool {
+  match *constant {
+    Some(Constant::Boolean(value)) => value,
+    _ => false,
+  }
+}
+
+fn is_false(constant: &Option<Constant>) -> bool {
+  match *constant {
+    Some(Constant::Boolean(value)) => !value,
+    _ => false,
+  }
+}
+
+fn from_literal(literal: Literal, text_table: &Vec<String>) -> Constant {
+  match literal {
+    Literal::Integer(value) => Constant::Integer(value),
+    Literal::Float(value) => Constant::Float(value),
+    Literal::Double(value) => Constant::Double(value),
+    Literal::Boolean(value) => Constant::Boolean(value),
+    Literal::Text(index) => Constant::Text(text_table[index].clone()),
+  }
+}
+
+// errors are returned without the position, which the caller adds
+fn binary_operation(operator: BinaryOperator, left: Constant, right: Constant)
+  -> Result<Option<Constant>, String> {
+
+  // type checker guarantees that both operands have the same type
+  let result = match (left, right) {
+    (Constant::Integer(a), Constant::Integer(b)) => {
+      if operator.is_comparison() {
+        Constant::Boolean(compare(operator, a, b))
+      } else {
+        let result = match operator {
+          BinaryOperator::Plus => a.checked_add(b),
+          BinaryOperator::Minus => a.checked_sub(b),
+          BinaryOperator::Multiply => a.checked_mul(b),
+          BinaryOperator::Divide => {
+            if b == 0 {
+              return Err("Division by zero in constant expression".to_string());
+            }
+            a.checked_div(b)
+          },
+          _ => unreachable!(),
+        };
+        match result {
+          Some(value) => Constant::Integer(value),
+          None => return Err(format!(
+            "Integer overflow in constant expression {} {} {}", a, operator, b)),
+        }
+      }
+    },
+    (Constant::Float(a), Constant::Float(b)) => {
+      if operator.is_comparison() {
+        Constant::Boolean(compare(operator, a, b))
+      } else {
+        let value = arithmetic(operator, a, b);
+        if !value.is_finite() {
+          return Ok(None);
+        }
+        Constant::Float(value)
+      }
+    },
+    (Constant::Double(a), Constant::Double(b)) => {
+      if operator.is_comparison() {
+        Constant::Boolean(compare(operator, a, b))
+      } else {
+        let value = arithmetic(operator, a, b);
+        if !value.is_finite() {
+          return Ok(None);
+        }
+        Constant::Double(value)
+      }
+    },
+    (Constant::Boolean(a), Constant::Boolean(b)) => Constant::Boolean(compare(operator, a, b)),
+    (Constant::Text(a), Constant::Text(b)) => {
+      match operator {
+        BinaryOperator::Plus => Constant::Text(a + b.as_slice()),
+        _ => Constant::Boolean(compare(operator, a, b)),
+      }
+    },
+    (left, right) => panic!(
+      "Internal compiler error: invalid operands {:?} and {:?} for operator {}", left, right, operator),
+  };
+
+  Ok(Some(result))
+}
+
+fn arithmetic<T: Float>(operator: BinaryOperator, a: T, b: T) -> T {
+  match operator {
+    BinaryOperator::Plus => a + b,
+    BinaryOperator::Minus => a - b,
+    BinaryOperator::Multiply => a * b,
+    BinaryOperator::Divide => a / b,
+    _ => panic!("Internal compiler error: {} is not an arithmetic operator", operator),
+  }
+}
+
+fn compare<T: PartialOrd>(operator: BinaryOperator, a: T, b: T) -> bool {
+  match operator {
+    BinaryOperator::Equals => a == b,
+    BinaryOperator::Lesser => a < b,
+    BinaryOperator::Greater => a > b,
+    BinaryOperator::LesserOrEq => a <= b,
+    BinaryOperator::GreaterOrEq => a >= b,
+    _ => panic!("Internal compiler error: {} is not a comparison operator", operator),
+  }
+}
+
+fn convert(constant: Constant, target: Type) -> Result<Option<Constant>, String> {
+  let result = match (constant, target) {
+    (Constant::Integer(value), Type::Integer) => Constant::Integer(value),
+    (Constant::Integer(value), Type::Float) => Constant::Float(value as f32),
+    (Constant::Integer(value), Type::Double) => Constant::Double(value as f64),
+    (Constant::Float(value), Type::Integer) => try!(truncate(value as f64)),
+    (Constant::Float(value), Type::Float) => Constant::Float(value),
+    (Constant::Float(value), Type::Double) => Constant::Double(value as f64),
+    (Constant::Double(value), Type::Integer) => try!(truncate(value)),
+    (Constant::Double(value), Type::Float) => {
+      if value.abs() > f32::MAX as f64 {
+        return Ok(None);
+      }
+      Constant::Float(value as f32)
+    },
+    (Constant::Double(value), Type::Double) => Constant::Double(value),
+    (constant, target) => panic!(
+      "Internal compiler error: can not convert {:?} to {}", constant, target),
+  };
+  Ok(Some(result))
+}
+
+// the integer part of the value, which has to fit in an int
+fn truncate(value: f64) -> Result<Constant, String> {
+  let truncated = value.trunc();
+  if truncated >= i32::MIN as f64 && truncated <= i32::MAX as f64 {
+    Ok(Constant::Integer(truncated as i32))
+  } else {
+    Err(format!(
+      "Integer overflow in constant expression: {} does not fit in {}", value, Type::Integer))
+  }
+}
+
+fn compile_error(msg: &str, pos: &Position) -> String {
+  format!("Error at {}: {}", pos, msg)
+}
@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::i32;
+use std::io::Writer;
+use ast::Program;
+use ast::Function;
+use ast::Block;
+use ast::Statement;
+use ast::Assignment;
+use ast::FunctionCall;
+use ast::Expression;
+use ast::ExpressionKind;
+use ast::Literal;
+use ast::BinaryOperator;
+use ast::Position;
+use ast::Type;
+use symbol_table::SymbolTable;
+use builtins;
+
+/*
+  Tree-walking interpreter. Executes a resolved and type checked program,
+  starting from its 'main' function. Globals are initialized in declaration
+  order before main is called. Output of the print builtin is written to the
+  given writer.
+
+  Integer arithmetic wraps around on overflow. Integer division by zero and too
+  deep recursion are runtime errors that report the position of the failing
+  expression; float and double arithmetic follow IEEE semantics. A function
+  returning the result of a call to another function hands its frame over to
+  the callee, so tail calls do not count towards the call depth.
+*/
+
+pub static MAX_CALL_DEPTH: usize = 1000;
+
+pub fn run(program: &Program, output: &mut Writer) -> Result<(), String> {
+  let mut interpreter = Interpreter::new(program, output);
+  interpreter.run()
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub enum Value {
+  Integer(i32),
+  Float(f32),
+  Double(f64),
+  Boolean(bool),
+  Text(String),
+  Void,
+}
+
+impl fmt::String for Value {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Value::Integer(value) => write!(formatter, "{}", value),
+      Value::Float(value) => write!(formatter, "{}", value),
+      Value::Double(value) => write!(formatter, "{}", value),
+      Value::Boolean(value) => write!(formatter, "{}", value),
+      Value::Text(ref value) => write!(formatter, "{}", value),
+      Value::Void => write!(formatter, "void"),
+    }
+  }
+}
+
+// result of executing a statement
+enum Flow<'a> {
+  Next,
+  Return(Value),
+  // returns the result of calling the function with the arguments
+  TailCall(&'a Function, Vec<Value>),
+}
+
+struct Interpreter<'a> {
+  program: &'a Program,
+  output: &'a mut (Writer + 'a),
+  functions: HashMap<usize, &'a Function>,
+  globals: HashMap<usize, Value>,
+  // local variables of each active call
+  frames: Vec<SymbolTable<Value>>,
+}
+
+impl<'a> Interpreter<'a> {
+  fn new(program: &'a Program, output: &'a mut (Writer + 'a)) -> Interpreter<'a> {
+    Interpreter {
+      program: program,
+      output: output,
+      functions: HashMap::new(),
+      globals: HashMap::new(),
+      frames: vec![],
+    }
+  }
+
+  fn run(&mut self) -> Result<(), String> {
+    let program = self.program;
+    let mut main = None;
+    for function in program.functions.iter() {
+      if !self.functions.contains_key(&function.name) {
+        self.functions.insert(function.name, function);
+        if program.get_text(function.name) == "main" {
+          main = Some(function);
+        }
+      }
+    }
+
+    for global in program.globals.iter() {
+      let value = try!(self.evaluate(&global.initializer));
+      self.globals.insert(global.name, value);
+    }
+
+    match main {
+      Some(function) => {
+        if !function.parameters.is_empty() {
+          return Err(runtime_error("Function 'main' must not take parameters", &function.pos));
+        }
+        try!(self.call_function(function, vec![], &function.pos));
+        Ok(())
+      },
+      None => Err("Runtime error: No 'main' function found".to_string()),
+    }
+  }
+
+  fn call_function(&mut self, function: &'a Function, arguments: Vec<Value>,
+    pos: &Position) -> Result<Value, String> {
+
+    if self.frames.len() >= MAX_CALL_DEPTH {
+      let msg = format!("Maximum call depth of {} exceeded", MAX_CALL_DEPTH);
+      return Err(runtime_error(msg.as_slice(), pos));
+    }
+
+    let mut function = function;
+    let mut arguments = arguments;
+    loop {
+      // parameters and the top level of the body share the scope
+      let mut locals = SymbolTable::new();
+      locals.push_scope();
+      for (parameter, argument) in function.parameters.iter().zip(arguments.into_iter()) {
+        locals.declare(parameter.name, argument);
+      }
+
+      self.frames.push(locals);
+      let result = self.execute_statements(&function.body.statements);
+      self.frames.pop();
+
+      match try!(result) {
+        Flow::Return(value) => return Ok(value),
+        Flow::Next => return Ok(Value::Void),
+        // the callee takes the place of the finished frame
+        Flow::TailCall(callee, callee_arguments) => {
+          function = callee;
+          arguments = callee_arguments;
+        },
+      }
+    }
+  }
+
+  fn execute_block(&mut self, block: &Block) -> Result<Flow<'a>, String> {
+    self.current_frame().push_scope();
+    let result = self.execute_statements(&block.statements);
+    self.current_frame().pop_scope();
+    result
+  }
+
+  fn execute_statements(&mut self, statements: &Vec<Statement>) -> Result<Flow<'a>, String> {
+    for statement in statements.iter() {
+      match try!(self.execute_statement(statement)) {
+        Flow::Next => { },
+        flow => return Ok(flow),
+      }
+    }
+    Ok(Flow::Next)
+  }
+
+  fn execute_statement(&mut self, statement: &Statement) -> Result<Flow<'a>, String> {
+    match *statement {
+      Statement::Block(ref block) => self.execute_block(block),
+      Statement::VariableDeclaration(ref declaration) => {
+        let value = try!(self.evaluate(&declaration.initializer));
+        self.current_frame().declare(declaration.name, value);
+        Ok(Flow::Next)
+      },
+      Statement::Assignment(ref assignment) => {
+        try!(self.execute_assignment(assignment));
+        Ok(Flow::Next)
+      },
+      Statement::FunctionCall(ref call) => {
+        try!(self.call(call));
+        Ok(Flow::Next)
+      },
+      Statement::For(ref for_loop) => {
+        // variable declared in the init clause is only visible inside the loop
+        self.current_frame().push_scope();
+        let result = self.execute_for_loop(&for_loop.init, &for_loop.condition,
+          &for_loop.update, &for_loop.body);
+        self.current_frame().pop_scope();
+        result
+      },
+      Statement::If(ref if_statement) => {
+        if try!(self.evaluate_condition(&if_statement.condition)) {
+          return self.execute_block(&if_statement.block);
+        }
+
+        for else_if in if_statement.else_ifs.iter() {
+          if try!(self.evaluate_condition(&else_if.condition)) {
+            return self.execute_block(&else_if.block);
+          }
+        }
+
+        match if_statement.else_block {
+          Some(ref block) => self.execute_block(block),
+          None => Ok(Flow::Next),
+        }
+      },
+      Statement::Return(ref return_statement) => {
+        match return_statement.value {
+          Some(ref value) => {
+            let callee = match value.kind {
+              ExpressionKind::Call(ref call) => self.program_function(call.name).map(|function| (function, call)),
+              _ => None,
+            };
+            match callee {
+              Some((function, call)) => {
+                let arguments = try!(self.evaluate_arguments(call));
+                Ok(Flow::TailCall(function, arguments))
+              },
+              None => Ok(Flow::Return(try!(self.evaluate(value)))),
+            }
+          },
+          None => Ok(Flow::Return(Value::Void)),
+        }
+      },
+      Statement::Empty(..) => Ok(Flow::Next),
+    }
+  }
+
+  fn execute_for_loop(&mut self, init: &Option<Box<Statement>>, condition: &Option<Expression>,
+    update: &Option<Assignment>, body: &Block) -> Result<Flow<'a>, String> {
+
+    match *init {
+      Some(ref init) => { try!(self.execute_statement(&**init)); },
+      None => { },
+    }
+
+    loop {
+      match *condition {
+        Some(ref condition) => {
+          if !try!(self.evaluate_condition(condition)) {
+            return Ok(Flow::Next);
+          }
+        },
+        None => { },
+      }
+
+      match try!(self.execute_block(body)) {
+        Flow::Next => { },
+        flow => return Ok(flow),
+      }
+
+      match *update {
+        Some(ref update) => try!(self.execute_assignment(update)),
+        None => { },
+      }
+    }
+  }
+
+  fn execute_assignment(&mut self, assignment: &Assignment) -> Result<(), String> {
+    let value = try!(self.evaluate(&assignment.value));
+    let assigned_local = match self.frames.last_mut() {
+      Some(frame) => frame.assign(assignment.name, value.clone()),
+      None => false,
+    };
+
+    if !assigned_local {
+      self.globals.insert(assignment.name, value);
+    }
+    Ok(())
+  }
+
+  fn call(&mut self, call: &FunctionCall) -> Result<Value, String> {
+    let arguments = try!(self.evaluate_arguments(call));
+    match self.program_function(call.name) {
+      Some(function) => self.call_function(function, arguments, &call.pos),
+      None => self.call_builtin(call, arguments),
+    }
+  }
+
+  fn evaluate_arguments(&mut self, call: &FunctionCall) -> Result<Vec<Value>, String> {
+    let mut arguments = vec![];
+    for argument in call.arguments.iter() {
+      arguments.push(try!(self.evaluate(argument)));
+    }
+    Ok(arguments)
+  }
+
+  // the function declared in the program under the name, None for builtins
+  fn program_function(&self, name: usize) -> Option<&'a Function> {
+    match self.functions.get(&name) {
+      Some(function) => Some(*function),
+      None => None,
+    }
+  }
+
+  fn call_builtin(&mut self, call: &FunctionCall, arguments: Vec<Value>) -> Result<Value, String> {
+    let name = self.program.get_text(call.name);
+    if name == builtins::PRINT {
+      match writeln!(self.output, "{}", arguments[0]) {
+        Ok(..) => Ok(Value::Void),
+        Err(err) => {
+          let msg = format!("Failed to write output: {}", err);
+          Err(runtime_error(msg.as_slice(), &call.pos))
+        },
+      }
+    } else {
+      panic!("Internal compiler error: call to unknown function '{}'", name);
+    }
+  }
+
+  fn evaluate_condition(&mut self, condition: &Expression) -> Result<bool, String> {
+    match try!(self.evaluate(condition)) {
+      Value::Boolean(value) => Ok(value),
+      _ => panic!("Internal compiler error: condition is not a boolean value"),
+    }
+  }
+
+  fn evaluate(&mut self, expression: &Expression) -> Result<Value, String> {
+    match expression.kind {
+      ExpressionKind::Literal(literal) => Ok(self.literal_value(literal)),
+      ExpressionKind::Variable(name) => Ok(self.lookup(name)),
+      ExpressionKind::Binary(operator, ref left, ref right) => {
+        let left = try!(self.evaluate(&**left));
+        let right = try!(self.evaluate(&**right));
+        binary_operation(operator, left, right, &expression.pos)
+      },
+      ExpressionKind::Call(ref call) => self.call(call),
+      ExpressionKind::Cast(ref inner, target) => {
+        let value = try!(self.evaluate(&**inner));
+        Ok(convert(value, target))
+      },
+    }
+  }
+
+  fn literal_value(&self, literal: Literal) -> Value {
+    match literal {
+      Literal::Integer(value) => Value::Integer(value),
+      Literal::Float(value) => Value::Float(value),
+      Literal::Double(value) => Value::Double(value),
+      Literal::Boolean(value) => Value::Boolean(value),
+      Literal::Text(index) => Value::Text(self.program.get_text(index).to_string()),
+    }
+  }
+
+  fn lookup(&self, name: usize) -> Value {
+    match self.frames.last() {
+      Some(frame) => match frame.lookup(name) {
+        Some(value) => return value,
+        None => { },
+      },
+      None => { },
+    }
+
+    match self.globals.get(&name) {
+      Some(value) => value.clone(),
+      None => panic!("Internal compiler error: undeclared variable '{}'",
+        self.program.get_text(name)),
+    }
+  }
+
+  fn current_frame(&mut self) -> &mut SymbolTable<Value> {
+    match self.frames.last_mut() {
+      Some(frame) => frame,
+      None => panic!("Internal compiler error: statement executed outside of a function"),
+    }
+  }
+}
+
+pub fn binary_operation(operator: BinaryOperator, left: Value, right: Value,
+  pos: &Position) -> Result<Value, String> {
+
+  // type checker guarantees that both operands have the same type
+  let result = match (left, right) {
+    (Value::Integer(a), Value::Integer(b)) => {
+      if operator.is_comparison() {
+        Value::Boolean(compare(operator, a, b))
+      } else {
+        match operator {
+          BinaryOperator::Plus => Value::Integer(a + b),
+          BinaryOperator::Minus => Value::Integer(a - b),
+          BinaryOperator::Multiply => Value::Integer(a * b),
+          BinaryOperator::Divide => {
+            if b == 0 {
+              return Err(runtime_error("Division by zero", pos));
+            } else if a == i32::MIN && b == -1 {
+              Value::Integer(i32::MIN)
+            } else {
+              Value::Integer(a / b)
+            }
+          },
+          _ => unreachable!(),
+        }
+      }
+    },
+    (Value::Float(a), Value::Float(b)) => {
+      if operator.is_comparison() {
+        Value::Boolean(compare(operator, a, b))
+      } else {
+        match operator {
+          BinaryOperator::Plus => Value::Float(a + b),
+          BinaryOperator::Minus => Value::Float(a - b),
+          BinaryOperator::Multiply => Value::Float(a * b),
+          BinaryOperator::Divide => Value::Float(a / b),
+          _ => unreachable!(),
+        }
+      }
+    },
+    (Value::Double(a), Value::Double(b)) => {
+      if operator.is_comparison() {
+        Value::Boolean(compare(operator, a, b))
+      } else {
+        match operator {
+          BinaryOperator::Plus => Value::Double(a + b),
+          BinaryOperator::Minus => Value::Double(a - b),
+          BinaryOperator::Multiply => Value::Double(a * b),
+          BinaryOperator::Divide => Value::Double(a / b),
+          _ => unreachable!(),
+        }
+      }
+    },
+    (Value::Boolean(a), Value::Boolean(b)) => Value::Boolean(compare(operator, a, b)),
+    (Value::Text(a), Value::Text(b)) => {
+      match operator {
+        BinaryOperator::Plus => Value::Text(a + b.as_slice()),
+        _ => Value::Boolean(compare(operator, a, b)),
+      }
+    },
+    (left, right) => panic!(
+      "Internal compiler error: invalid operands {:?} and {:?} for operator {}", left, right, operator),
+  };
+
+  Ok(result)
+}
+
+fn compare<T: PartialOrd>(operator: BinaryOperator, a: T, b: T) -> bool {
+  match operator {
+    BinaryOperator::Equals => a == b,
+    BinaryOperator::Lesser => a < b,
+    BinaryOperator::Greater => a > b,
+    BinaryOperator::LesserOrEq => a <= b,
+    BinaryOperator::GreaterOrEq => a >= b,
+    _ => panic!("Internal compiler error: {} is not a comparison operator", operator),
+  }
+}
+
+pub fn convert(value: Value, target: Type) -> Value {
+  match (value, target) {
+    (Value::Integer(value), Type::Integer) => Value::Integer(value),
+    (Value::Integer(value), Type::Float) => Value::Float(value as f32),
+    (Value::Integer(value), Type::Double) => Value::Double(value as f64),
+    (Value::Float(value), Type::Integer) => Value::Integer(value as i32),
+    (Value::Float(value), Type::Float) => Value::Float(value),
+    (Value::Float(value), Type::Double) => Value::Double(value as f64),
+    (Value::Double(value), Type::Integer) => Value::Integer(value as i32),
+    (Value::Double(value), Type::Float) => Value::Float(value as f32),
+    (Value::Double(value), Type::Double) => Value::Double(value),
+    (value, target) => panic!(
+      "Internal compiler error: can not convert {:?} to {}", value, target),
+  }
+}
+
+pub fn runtime_error(msg: &str, pos: &Position) -> String {
+  format!("Runtime error at {}: {}", pos, msg)
+}
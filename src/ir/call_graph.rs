@@ -0,0 +1,161 @@
+use std::cmp;
+use std::iter;
+use ir::Instruction;
+use ir::Module;
+
+/*
+  Call graph of a module: which functions every function calls directly,
+  and how many call sites every function has. Functions are identified by
+  their index in the module. The graph can also be built from a list of
+  call sites, which is how the call graph of the syntax tree reuses it.
+
+  The strongly connected components are found with Tarjan's algorithm. A
+  function is recursive when it calls itself or shares its component with
+  other functions. Tarjan's algorithm completes a component only after all
+  components it calls, so listing the components in that order gives a
+  bottom-up order in which callees come before their callers, apart from
+  the calls within a component.
+*/
+
+pub struct CallGraph {
+  // function -> called functions, in order of their first call
+  callees: Vec<Vec<usize>>,
+  // function -> number of calls to it in the module
+  call_sites: Vec<usize>,
+  // function -> index of its strongly connected component
+  components: Vec<usize>,
+  component_sizes: Vec<usize>,
+  bottom_up: Vec<usize>,
+}
+
+impl CallGraph {
+  pub fn new(module: &Module) -> CallGraph {
+    let mut calls = vec![];
+    for (caller, function) in module.functions.iter().enumerate() {
+      for block in function.blocks.iter() {
+        for instruction in block.instructions.iter() {
+          match *instruction {
+            Instruction::Call(_, callee, _) => calls.push((caller, callee)),
+            _ => { },
+          }
+        }
+      }
+    }
+    CallGraph::from_calls(module.functions.len(), calls.as_slice())
+  }
+
+  // graph of the given call sites, each a caller and the function it calls
+  pub fn from_calls(function_count: usize, calls: &[(usize, usize)]) -> CallGraph {
+    let mut callees: Vec<Vec<usize>> = iter::repeat(vec![]).take(function_count).collect();
+    let mut call_sites = iter::repeat(0).take(function_count).collect::<Vec<usize>>();
+    for &(caller, callee) in calls.iter() {
+      call_sites[callee] += 1;
+      if !callees[caller].contains(&callee) {
+        callees[caller].push(callee);
+      }
+    }
+
+    let mut search = ComponentSearch {
+      callees: &callees,
+      index: iter::repeat(None).take(function_count).collect(),
+      lowlink: iter::repeat(0).take(function_count).collect(),
+      on_stack: iter::repeat(false).take(function_count).collect(),
+      stack: vec![],
+      next_index: 0,
+      components: iter::repeat(0).take(function_count).collect(),
+      component_sizes: vec![],
+      order: vec![],
+    };
+    for function in range(0, function_count) {
+      if search.index[function].is_none() {
+        search.visit(function);
+      }
+    }
+
+    let components = search.components;
+    let component_sizes = search.component_sizes;
+    let bottom_up = search.order;
+    CallGraph {
+      callees: callees,
+      call_sites: call_sites,
+      components: components,
+      component_sizes: component_sizes,
+      bottom_up: bottom_up,
+    }
+  }
+
+  pub fn callees(&self, function: usize) -> &[usize] {
+    self.callees[function].as_slice()
+  }
+
+  pub fn call_sites(&self, function: usize) -> usize {
+    self.call_sites[function]
+  }
+
+  pub fn is_recursive(&self, function: usize) -> bool {
+    self.component_sizes[self.components[function]] > 1 || self.callees[function].contains(&function)
+  }
+
+  // whether the functions call each other, directly or through others
+  pub fn in_same_cycle(&self, first: usize, second: usize) -> bool {
+    self.components[first] == self.components[second] &&
+      (first != second || self.is_recursive(first))
+  }
+
+  // all functions, callees before their callers
+  pub fn bottom_up_order(&self) -> &[usize] {
+    self.bottom_up.as_slice()
+  }
+}
+
+struct ComponentSearch<'a> {
+  callees: &'a Vec<Vec<usize>>,
+  index: Vec<Option<usize>>,
+  lowlink: Vec<usize>,
+  on_stack: Vec<bool>,
+  stack: Vec<usize>,
+  next_index: usize,
+  components: Vec<usize>,
+  component_sizes: Vec<usize>,
+  order: Vec<usize>,
+}
+
+impl<'a> ComponentSearch<'a> {
+  fn visit(&mut self, function: usize) {
+    self.index[function] = Some(self.next_index);
+    self.lowlink[function] = self.next_index;
+    self.next_index += 1;
+    self.stack.push(function);
+    self.on_stack[function] = true;
+
+    let callees = self.callees;
+    for callee in callees[function].iter() {
+      match self.index[*callee] {
+        None => {
+          self.visit(*callee);
+          self.lowlink[function] = cmp::min(self.lowlink[function], self.lowlink[*callee]);
+        },
+        Some(index) if self.on_stack[*callee] => {
+          self.lowlink[function] = cmp::min(self.lowlink[function], index);
+        },
+        Some(..) => { },
+      }
+    }
+
+    if Some(self.lowlink[function]) == self.index[function] {
+      let component = self.component_sizes.len();
+      let mut size = 0;
+      loop {
+        let member = self.stack.pop().unwrap();
+        self.on_stack[member] = false;
+        self.components[member] = component;
+        self.order.push(member);
+        size += 1;
+        if member == function {
+          break;
+        }
+      }
+      self.component_sizes.push(size);
+    }
+  }
+}
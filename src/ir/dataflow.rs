@@ -0,0 +1,179 @@
+use std::iter;
+use ir::*;
+
+/*
+  Worklist solver for dataflow analyses over the control-flow graph of a
+  function. An analysis chooses the direction facts flow in, the fact at the
+  boundary of the function, the fact other blocks start with, how the facts
+  of several neighbours are combined and how instructions and terminators
+  change a fact. The solver computes the fact at the start and at the end
+  of every block as the fixed point of these equations:
+    forward    start(b) = meet of end(p) over the predecessors p of b,
+                          and the boundary for the entry block
+               end(b) = transfer of the instructions of b applied to start(b)
+    backward   end(b) = meet of start(s) over the successors s of b,
+                        and the boundary for blocks without successors
+               start(b) = transfer of b applied backwards to end(b)
+
+  A fact can be changed on its way along an edge, which is how analyses see
+  phis: their operands belong to the edge from the predecessor they come
+  from. Meeting starts from the initial fact, so it should be the top of the
+  lattice, and the transfer functions must be monotone for the solver to
+  terminate. Blocks that can not be reached from the entry block are never
+  visited and keep the initial fact at both ends.
+*/
+
+#[derive(Show, Copy, PartialEq)]
+pub enum Direction {
+  Forward,
+  Backward,
+}
+
+pub trait Analysis<T: Clone + PartialEq> {
+  fn direction(&self) -> Direction;
+  // fact at the start of the entry block, or at the end of a block without
+  // successors for a backward analysis
+  fn boundary(&self) -> T;
+  // fact every other block starts with
+  fn initial(&self) -> T;
+  // combines the fact of another neighbour into a fact
+  fn meet(&self, fact: &mut T, other: &T);
+  // applies an instruction to the fact before it, or after it for a
+  // backward analysis
+  fn transfer(&self, block: BlockId, index: usize, instruction: &Instruction, fact: &mut T);
+
+  fn transfer_terminator(&self, _block: BlockId, _terminator: &Terminator, _fact: &mut T) {
+  }
+
+  // the fact leaving `from` as it arrives in `to`, where `from` is the
+  // predecessor for a forward analysis and the successor for a backward one
+  fn edge(&self, _from: BlockId, _to: BlockId, fact: &T) -> T {
+    fact.clone()
+  }
+}
+
+pub struct Solution<T> {
+  // block -> fact at the start of the block
+  pub start: Vec<T>,
+  // block -> fact at the end of the block
+  pub end: Vec<T>,
+}
+
+pub fn solve<T: Clone + PartialEq, A: Analysis<T>>(function: &Function, analysis: &A) -> Solution<T> {
+  let block_count = function.blocks.len();
+  let predecessors = function.predecessors();
+  let forward = analysis.direction() == Direction::Forward;
+  let mut order = function.reverse_postorder();
+  if !forward {
+    order.reverse();
+  }
+  let mut reachable = iter::repeat(false).take(block_count).collect::<Vec<bool>>();
+  for block in order.iter() {
+    reachable[*block] = true;
+  }
+
+  let mut solution = Solution {
+    start: iter::repeat(analysis.initial()).take(block_count).collect(),
+    end: iter::repeat(analysis.initial()).take(block_count).collect(),
+  };
+
+  // the worklist is a stack, so the first block of the order is on top
+  let mut worklist: Vec<BlockId> = order.iter().rev().map(|block| *block).collect();
+  let mut queued = reachable.clone();
+  while !worklist.is_empty() {
+    let block = worklist.pop().unwrap();
+    queued[block] = false;
+    let successors = function.blocks[block].terminator.successors();
+    // blocks whose facts flow into this block, and blocks this block flows into
+    let (sources, dependents) = if forward {
+      (predecessors[block].clone(), successors)
+    } else {
+      (successors, predecessors[block].clone())
+    };
+
+    let at_boundary = if forward { block == 0 } else { sources.is_empty() };
+    let mut fact = if at_boundary { analysis.boundary() } else { analysis.initial() };
+    for source in sources.iter().filter(|source| reachable[**source]) {
+      let incoming = if forward { &solution.end[*source] } else { &solution.start[*source] };
+      analysis.meet(&mut fact, &analysis.edge(*source, block, incoming));
+    }
+    let result = transfer_block(function, analysis, block, &fact);
+
+    let changed = if forward {
+      solution.start[block] = fact;
+      result != solution.end[block]
+    } else {
+      solution.end[block] = fact;
+      result != solution.start[block]
+    };
+    if !changed {
+      continue;
+    }
+    if forward {
+      solution.end[block] = result;
+    } else {
+      solution.start[block] = result;
+    }
+    for dependent in dependents.into_iter() {
+      if reachable[dependent] && !queued[dependent] {
+        queued[dependent] = true;
+        worklist.push(dependent);
+      }
+    }
+  }
+  solution
+}
+
+// applies the transfer functions of a block to the fact at its start, or to
+// the fact at its end for a backward analysis
+pub fn transfer_block<T: Clone + PartialEq, A: Analysis<T>>(function: &Function, analysis: &A, block: BlockId,
+    fact: &T) -> T {
+  let mut fact = fact.clone();
+  let instructions = &function.blocks[block].instructions;
+  match analysis.direction() {
+    Direction::Forward => {
+      for (index, instruction) in instructions.iter().enumerate() {
+        analysis.transfer(block, index, instruction, &mut fact);
+      }
+      analysis.transfer_terminator(block, &function.blocks[block].terminator, &mut fact);
+    },
+    Direction::Backward => {
+      analysis.transfer_terminator(block, &function.blocks[block].terminator, &mut fact);
+      for (index, instruction) in instructions.iter().enumerate().rev() {
+        analysis.transfer(block, index, instruction, &mut fact);
+      }
+    },
+  }
+  fact
+}
+
+// the facts inside a block, one for every instruction and a last one for
+// the terminator: the fact before each of them, or after each of them for a
+// backward analysis
+pub fn block_facts<T: Clone + PartialEq, A: Analysis<T>>(function: &Function, analysis: &A,
+    solution: &Solution<T>, block: BlockId) -> Vec<T> {
+  let instructions = &function.blocks[block].instructions;
+  match analysis.direction() {
+    Direction::Forward => {
+      let mut fact = solution.start[block].clone();
+      let mut facts = vec![];
+      for (index, instruction) in instructions.iter().enumerate() {
+        facts.push(fact.clone());
+        analysis.transfer(block, index, instruction, &mut fact);
+      }
+      facts.push(fact);
+      facts
+    },
+    Direction::Backward => {
+      let mut fact = solution.end[block].clone();
+      let mut facts = vec![fact.clone()];
+      analysis.transfer_terminator(block, &function.blocks[block].terminator, &mut fact);
+      for (index, instruction) in instructions.iter().enumerate().rev() {
+        facts.push(fact.clone());
+        analysis.transfer(block, index, instruction, &mut fact);
+      }
+      facts.reverse();
+      facts
+    },
+  }
+}
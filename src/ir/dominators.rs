@@ -0,0 +1,162 @@
+use std::iter;
+use ir::BlockId;
+use ir::Function;
+
+/*
+  Dominator tree of a function's control-flow graph, computed with the
+  iterative algorithm of Cooper, Harvey and Kennedy over the reverse
+  postorder. Blocks that can not be reached from the entry block have no
+  immediate dominator and neither dominate nor are dominated by any block.
+
+  Dominance queries compare the entry and exit numbers of a depth-first walk
+  over the tree, so they take constant time.
+*/
+
+pub struct DominatorTree {
+  idom: Vec<Option<BlockId>>,
+  children: Vec<Vec<BlockId>>,
+  // entry and exit numbers of the depth-first walk over the tree
+  entry: Vec<usize>,
+  exit: Vec<usize>,
+}
+
+impl DominatorTree {
+  pub fn new(function: &Function) -> DominatorTree {
+    let block_count = function.blocks.len();
+    let predecessors = function.predecessors();
+    let order = function.reverse_postorder();
+    let mut order_index: Vec<Option<usize>> = iter::repeat(None).take(block_count).collect();
+    for (index, block) in order.iter().enumerate() {
+      order_index[*block] = Some(index);
+    }
+
+    let mut idom: Vec<Option<BlockId>> = iter::repeat(None).take(block_count).collect();
+    idom[0] = Some(0);
+    let mut changed = true;
+    while changed {
+      changed = false;
+      for block in order.iter().skip(1) {
+        let mut new_idom = None;
+        for predecessor in predecessors[*block].iter() {
+          if idom[*predecessor].is_none() {
+            continue;
+          }
+          new_idom = match new_idom {
+            None => Some(*predecessor),
+            Some(current) => Some(intersect(&idom, &order_index, current, *predecessor)),
+          };
+        }
+        if new_idom != idom[*block] {
+          idom[*block] = new_idom;
+          changed = true;
+        }
+      }
+    }
+    // the entry block has no immediate dominator
+    idom[0] = None;
+
+    let mut children: Vec<Vec<BlockId>> = iter::repeat(vec![]).take(block_count).collect();
+    for block in range(0, block_count) {
+      match idom[block] {
+        Some(parent) => children[parent].push(block),
+        None => { },
+      }
+    }
+
+    let mut tree = DominatorTree {
+      idom: idom,
+      children: children,
+      entry: iter::repeat(0).take(block_count).collect(),
+      exit: iter::repeat(0).take(block_count).collect(),
+    };
+    tree.number(block_count);
+    tree
+  }
+
+  // numbers the blocks in a depth-first walk from the entry block
+  fn number(&mut self, block_count: usize) {
+    if block_count == 0 {
+      return;
+    }
+    let mut counter = 1;
+    // block and the number of its children already visited
+    let mut stack = vec![(0, 0)];
+    self.entry[0] = counter;
+    while !stack.is_empty() {
+      let last = stack.len() - 1;
+      let (block, next) = stack[last];
+      if next < self.children[block].len() {
+        stack[last] = (block, next + 1);
+        let child = self.children[block][next];
+        counter += 1;
+        self.entry[child] = counter;
+        stack.push((child, 0));
+      } else {
+        counter += 1;
+        self.exit[block] = counter;
+        stack.pop();
+      }
+    }
+  }
+
+  pub fn immediate_dominator(&self, block: BlockId) -> Option<BlockId> {
+    self.idom[block]
+  }
+
+  // blocks immediately dominated by the block, in block order
+  pub fn children(&self, block: BlockId) -> &[BlockId] {
+    self.children[block].as_slice()
+  }
+
+  pub fn is_reachable(&self, block: BlockId) -> bool {
+    self.entry[block] != 0
+  }
+
+  // every reachable block dominates itself
+  pub fn dominates(&self, dominator: BlockId, block: BlockId) -> bool {
+    self.is_reachable(dominator) && self.is_reachable(block) &&
+      self.entry[dominator] <= self.entry[block] && self.exit[block] <= self.exit[dominator]
+  }
+
+  pub fn strictly_dominates(&self, dominator: BlockId, block: BlockId) -> bool {
+    dominator != block && self.dominates(dominator, block)
+  }
+
+  // dominance frontier of every block: the blocks where its dominance ends
+  pub fn frontiers(&self, function: &Function) -> Vec<Vec<BlockId>> {
+    let predecessors = function.predecessors();
+    let mut frontiers: Vec<Vec<BlockId>> = iter::repeat(vec![]).take(function.blocks.len()).collect();
+    for (block, block_predecessors) in predecessors.iter().enumerate() {
+      if block_predecessors.len() < 2 || !self.is_reachable(block) {
+        continue;
+      }
+      for predecessor in block_predecessors.iter() {
+        let mut runner = *predecessor;
+        while self.is_reachable(runner) && Some(runner) != self.idom[block] {
+          if !frontiers[runner].contains(&block) {
+            frontiers[runner].push(block);
+          }
+          runner = match self.idom[runner] {
+            Some(parent) => parent,
+            None => break,
+          };
+        }
+      }
+    }
+    frontiers
+  }
+}
+
+fn intersect(idom: &Vec<Option<BlockId>>, order_index: &Vec<Option<usize>>, a: BlockId, b: BlockId) -> BlockId {
+  let mut a = a;
+  let mut b = b;
+  while a != b {
+    while order_index[a] > order_index[b] {
+      a = idom[a].unwrap();
+    }
+    while order_index[b] > order_index[a] {
+      b = idom[b].unwrap();
+    }
+  }
+  a
+}
@@ -0,0 +1,170 @@
+use std::iter;
+use std::io::Writer;
+use ast::BinaryOperator;
+use ast::Position;
+use interpreter;
+use interpreter::Value;
+use interpreter::MAX_CALL_DEPTH;
+use ir::*;
+
+/*
+  Interpreter for the intermediate representation, in normal as well as in
+  SSA form. Runs the function initializing the globals and then 'main',
+  writing the output of print to the given writer like the tree-walking
+  interpreter does. The phis at the start of a block all read the values
+  of the block control came from before any of them is assigned.
+
+  Arithmetic is shared with the tree-walking interpreter, so a division by
+  zero is reported at the position the division instruction carries. Calls
+  carry no position, too deep recursion names the called function instead.
+
+  It is not meant to be fast: tests use it to check that optimizations do
+  not change what a program prints.
+*/
+
+pub fn run(module: &Module, output: &mut Writer) -> Result<(), String> {
+  let mut machine = Machine {
+    module: module,
+    output: output,
+    globals: iter::repeat(Value::Void).take(module.globals.len()).collect(),
+    depth: 0,
+  };
+  try!(machine.call(module.init, vec![]));
+  match module.main {
+    Some(main) => {
+      try!(machine.call(main, vec![]));
+      Ok(())
+    },
+    None => Err("Runtime error: No 'main' function found".to_string()),
+  }
+}
+
+struct Machine<'a> {
+  module: &'a Module,
+  output: &'a mut (Writer + 'a),
+  globals: Vec<Value>,
+  // number of active calls
+  depth: usize,
+}
+
+impl<'a> Machine<'a> {
+  fn call(&mut self, index: usize, arguments: Vec<Value>) -> Result<Value, String> {
+    let module = self.module;
+    let function = &module.functions[index];
+    if self.depth >= MAX_CALL_DEPTH {
+      return Err(format!("Runtime error in function '@{}': Maximum call depth of {} exceeded",
+        function.name, MAX_CALL_DEPTH));
+    }
+
+    let mut registers: Vec<Value> = iter::repeat(Value::Void).take(function.registers.len()).collect();
+    for (parameter, argument) in function.parameters.iter().zip(arguments.into_iter()) {
+      registers[*parameter] = argument;
+    }
+
+    self.depth += 1;
+    let result = self.execute(function, &mut registers);
+    self.depth -= 1;
+    result
+  }
+
+  fn execute(&mut self, function: &Function, registers: &mut Vec<Value>) -> Result<Value, String> {
+    let mut current = 0;
+    let mut previous = None;
+    loop {
+      let block = &function.blocks[current];
+      let mut merged = vec![];
+      for instruction in block.instructions.iter() {
+        match *instruction {
+          Instruction::Phi(destination, ref incoming) => {
+            let operand = match incoming.iter().find(|&&(predecessor, _)| Some(predecessor) == previous) {
+              Some(&(_, ref operand)) => operand,
+              None => panic!("Internal compiler error: phi has no operand for the predecessor"),
+            };
+            merged.push((destination, value(registers, operand)));
+          },
+          _ => break,
+        }
+      }
+      for (destination, merged_value) in merged.into_iter() {
+        registers[destination] = merged_value;
+      }
+
+      for instruction in block.instructions.iter() {
+        try!(self.execute_instruction(function, instruction, registers));
+      }
+
+      previous = Some(current);
+      current = match block.terminator {
+        Terminator::Jump(target) => target,
+        Terminator::Branch(ref condition, if_true, if_false) => {
+          match value(registers, condition) {
+            Value::Boolean(true) => if_true,
+            Value::Boolean(false) => if_false,
+            other => panic!("Internal compiler error: branch on {:?}", other),
+          }
+        },
+        Terminator::Return(Some(ref operand)) => return Ok(value(registers, operand)),
+        Terminator::Return(None) => return Ok(Value::Void),
+        Terminator::Unreachable => panic!("Internal compiler error: unreachable end of '@{}' reached",
+          function.name),
+      };
+    }
+  }
+
+  fn execute_instruction(&mut self, function: &Function, instruction: &Instruction,
+    registers: &mut Vec<Value>) -> Result<(), String> {
+
+    match *instruction {
+      Instruction::Copy(destination, ref operand) => {
+        registers[destination] = value(registers, operand);
+      },
+      Instruction::Binary(destination, operator, ref left, ref right) => {
+        // only integer division reports a position, and it has its own instruction
+        let position = Position::new(0, 0);
+        registers[destination] = try!(interpreter::binary_operation(operator, value(registers, left),
+          value(registers, right), &position));
+      },
+      Instruction::Divide(destination, ref left, ref right, ref position) => {
+        registers[destination] = try!(interpreter::binary_operation(BinaryOperator::Divide,
+          value(registers, left), value(registers, right), position));
+      },
+      Instruction::Convert(destination, ref operand) => {
+        registers[destination] = interpreter::convert(value(registers, operand), function.registers[destination]);
+      },
+      Instruction::Call(destination, callee, ref arguments) => {
+        let arguments = arguments.iter().map(|argument| value(registers, argument)).collect();
+        let result = try!(self.call(callee, arguments));
+        match destination {
+          Some(destination) => registers[destination] = result,
+          None => { },
+        }
+      },
+      Instruction::Print(ref operand) => {
+        match writeln!(self.output, "{}", value(registers, operand)) {
+          Ok(..) => { },
+          Err(err) => return Err(format!("Runtime error: Failed to write output: {}", err)),
+        }
+      },
+      Instruction::LoadGlobal(destination, global) => {
+        registers[destination] = self.globals[global].clone();
+      },
+      Instruction::StoreGlobal(global, ref operand) => {
+        self.globals[global] = value(registers, operand);
+      },
+      // assigned at the start of the block
+      Instruction::Phi(..) => { },
+    }
+    Ok(())
+  }
+}
+
+fn value(registers: &Vec<Value>, operand: &Operand) -> Value {
+  match *operand {
+    Operand::Register(register) => registers[register].clone(),
+    Operand::Integer(value) => Value::Integer(value),
+    Operand::Float(value) => Value::Float(value),
+    Operand::Double(value) => Value::Double(value),
+    Operand::Boolean(value) => Value::Boolean(value),
+    Operand::Text(ref value) => Value::Text(value.clone()),
+  }
+}
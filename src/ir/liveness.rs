@@ -0,0 +1,111 @@
+use std::iter;
+use ir::*;
+use ir::dataflow;
+use ir::dataflow::Analysis;
+use ir::dataflow::Direction;
+
+/*
+  Liveness of virtual registers. A register is live at a point of the
+  function if its value may still be read without being assigned first.
+  This is the backward dataflow analysis over sets of registers where a
+  block's uses are added and its assignments removed.
+
+  Phis are handled like SSA destruction places their copies: the operands
+  are read at the end of the predecessor they come from and the destination
+  is assigned at the start of the block. Blocks that can not be reached have
+  nothing live.
+*/
+
+pub struct Liveness {
+  // block -> register -> whether it is live at the start of the block
+  pub live_in: Vec<Vec<bool>>,
+  // block -> register -> whether it is live at the end of the block
+  pub live_out: Vec<Vec<bool>>,
+}
+
+impl Liveness {
+  pub fn live_in_registers(&self, block: BlockId) -> Vec<Register> {
+    registers(&self.live_in[block])
+  }
+
+  pub fn live_out_registers(&self, block: BlockId) -> Vec<Register> {
+    registers(&self.live_out[block])
+  }
+}
+
+pub struct LiveRegisters<'a> {
+  pub function: &'a Function,
+}
+
+impl<'a> Analysis<Vec<bool>> for LiveRegisters<'a> {
+  fn direction(&self) -> Direction {
+    Direction::Backward
+  }
+
+  fn boundary(&self) -> Vec<bool> {
+    self.initial()
+  }
+
+  fn initial(&self) -> Vec<bool> {
+    iter::repeat(false).take(self.function.registers.len()).collect()
+  }
+
+  fn meet(&self, fact: &mut Vec<bool>, other: &Vec<bool>) {
+    for (register, live) in other.iter().enumerate() {
+      if *live {
+        fact[register] = true;
+      }
+    }
+  }
+
+  fn transfer(&self, _block: BlockId, _index: usize, instruction: &Instruction, fact: &mut Vec<bool>) {
+    match instruction.destination() {
+      Some(register) => fact[register] = false,
+      None => { },
+    }
+    // the operands of phis are read on the edges
+    if !instruction.is_phi() {
+      use_operands(instruction.operands(), fact);
+    }
+  }
+
+  fn transfer_terminator(&self, _block: BlockId, terminator: &Terminator, fact: &mut Vec<bool>) {
+    use_operands(terminator.operands(), fact);
+  }
+
+  fn edge(&self, from: BlockId, to: BlockId, fact: &Vec<bool>) -> Vec<bool> {
+    let mut fact = fact.clone();
+    for instruction in self.function.blocks[from].instructions.iter() {
+      match *instruction {
+        Instruction::Phi(_, ref incoming) => {
+          for &(predecessor, ref operand) in incoming.iter() {
+            match *operand {
+              Operand::Register(register) if predecessor == to => fact[register] = true,
+              _ => { },
+            }
+          }
+        },
+        _ => { },
+      }
+    }
+    fact
+  }
+}
+
+pub fn analyze(function: &Function) -> Liveness {
+  let solution = dataflow::solve(function, &LiveRegisters { function: function });
+  Liveness { live_in: solution.start, live_out: solution.end }
+}
+
+fn use_operands(operands: Vec<&Operand>, fact: &mut Vec<bool>) {
+  for operand in operands.iter() {
+    match **operand {
+      Operand::Register(register) => fact[register] = true,
+      _ => { },
+    }
+  }
+}
+
+fn registers(live: &Vec<bool>) -> Vec<Register> {
+  live.iter().enumerate().filter(|&(_, live)| *live).map(|(register, _)| register).collect()
+}
@@ -0,0 +1,170 @@
+use std::iter;
+use ir::*;
+use ir::dominators::DominatorTree;
+
+/*
+  Natural loops of a function. An edge from a block to a block dominating it
+  is a back edge; its target is the loop header, and the loop is the header
+  together with every block that reaches the back edge without passing the
+  header. Back edges to the same header form a single loop. Control flow
+  that enters a cycle somewhere else than at a dominating block does not
+  form a natural loop and is not found, the lowering never produces it.
+
+  Loops are listed innermost first: a loop nested in another one has fewer
+  blocks, so ordering by size puts it before the loops around it.
+
+  The loop optimizations place code in the preheader, the single block
+  outside the loop that jumps to the header. insert_preheader creates it
+  when the header has several predecessors outside the loop, merging the
+  values the header's phis take from them in phis of the new block.
+*/
+
+#[derive(Show, Clone, PartialEq)]
+pub struct Loop {
+  pub header: BlockId,
+  // blocks of the loop including the header, in block order
+  pub blocks: Vec<BlockId>,
+  // blocks of the loop jumping back to the header
+  pub latches: Vec<BlockId>,
+  // blocks outside the loop jumped to from inside, in block order
+  pub exits: Vec<BlockId>,
+  // index of the innermost loop around this one
+  pub parent: Option<usize>,
+}
+
+impl Loop {
+  pub fn contains(&self, block: BlockId) -> bool {
+    self.blocks.contains(&block)
+  }
+}
+
+pub fn find_loops(function: &Function) -> Vec<Loop> {
+  let tree = DominatorTree::new(function);
+  let predecessors = function.predecessors();
+  let mut loops: Vec<Loop> = vec![];
+
+  for (id, block) in function.blocks.iter().enumerate() {
+    if !tree.is_reachable(id) {
+      continue;
+    }
+    for successor in block.terminator.successors().iter() {
+      if !tree.dominates(*successor, id) {
+        continue;
+      }
+      match loops.iter().position(|other| other.header == *successor) {
+        Some(index) => loops[index].latches.push(id),
+        None => loops.push(Loop {
+          header: *successor,
+          blocks: vec![],
+          latches: vec![id],
+          exits: vec![],
+          parent: None,
+        }),
+      }
+    }
+  }
+
+  for natural_loop in loops.iter_mut() {
+    let mut in_loop = iter::repeat(false).take(function.blocks.len()).collect::<Vec<bool>>();
+    in_loop[natural_loop.header] = true;
+    let mut worklist = natural_loop.latches.clone();
+    while let Some(block) = worklist.pop() {
+      if in_loop[block] {
+        continue;
+      }
+      in_loop[block] = true;
+      for predecessor in predecessors[block].iter() {
+        if tree.is_reachable(*predecessor) {
+          worklist.push(*predecessor);
+        }
+      }
+    }
+
+    natural_loop.blocks = range(0, function.blocks.len()).filter(|block| in_loop[*block]).collect();
+    let mut exits = vec![];
+    for block in natural_loop.blocks.iter() {
+      for successor in function.blocks[*block].terminator.successors().iter() {
+        if !in_loop[*successor] && !exits.contains(successor) {
+          exits.push(*successor);
+        }
+      }
+    }
+    exits.sort();
+    natural_loop.exits = exits;
+  }
+
+  loops.sort_by(|a, b| a.blocks.len().cmp(&b.blocks.len()));
+  for index in range(0, loops.len()) {
+    let header = loops[index].header;
+    loops[index].parent = range(index + 1, loops.len())
+      .find(|other| loops[*other].header != header && loops[*other].contains(header));
+  }
+  loops
+}
+
+// the single block outside the loop jumping to the header, if it jumps nowhere
+// else
+pub fn preheader(function: &Function, natural_loop: &Loop) -> Option<BlockId> {
+  let outside = outside_predecessors(function, natural_loop);
+  if outside.len() == 1 && function.blocks[outside[0]].terminator == Terminator::Jump(natural_loop.header) {
+    Some(outside[0])
+  } else {
+    None
+  }
+}
+
+// creates the preheader of a loop in SSA form unless it has one, and returns
+// it. None if the header is the entry block. The loops of the function have
+// to be found again afterwards
+pub fn insert_preheader(function: &mut Function, natural_loop: &Loop) -> Option<BlockId> {
+  match preheader(function, natural_loop) {
+    Some(block) => return Some(block),
+    None => { },
+  }
+  let outside = outside_predecessors(function, natural_loop);
+  if outside.is_empty() {
+    return None;
+  }
+
+  let header = natural_loop.header;
+  let preheader = function.blocks.len();
+  function.blocks.push(BasicBlock { instructions: vec![], terminator: Terminator::Jump(header) });
+  for predecessor in outside.iter() {
+    function.blocks[*predecessor].terminator.replace_successor(header, preheader);
+  }
+
+  let mut merges = vec![];
+  for index in range(0, function.blocks[header].instructions.len()) {
+    let (destination, incoming) = match function.blocks[header].instructions[index] {
+      Instruction::Phi(destination, ref incoming) => (destination, incoming.clone()),
+      _ => break,
+    };
+    let (entering, mut kept): (Vec<(BlockId, Operand)>, Vec<(BlockId, Operand)>) = incoming.into_iter()
+      .partition(|&(predecessor, _)| outside.contains(&predecessor));
+    let first = match entering[0] {
+      (_, ref operand) => operand.clone(),
+    };
+    let operand = if entering.iter().all(|&(_, ref operand)| operand.is_identical(&first)) {
+      first
+    } else {
+      let merged = function.new_register(function.registers[destination]);
+      merges.push(Instruction::Phi(merged, entering));
+      Operand::Register(merged)
+    };
+    kept.push((preheader, operand));
+    kept.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+    function.blocks[header].instructions[index] = Instruction::Phi(destination, kept);
+  }
+  function.blocks[preheader].instructions = merges;
+  Some(preheader)
+}
+
+fn outside_predecessors(function: &Function, natural_loop: &Loop) -> Vec<BlockId> {
+  let mut outside = vec![];
+  for (id, block) in function.blocks.iter().enumerate() {
+    if !natural_loop.contains(id) && block.terminator.successors().contains(&natural_loop.header) {
+      outside.push(id);
+    }
+  }
+  outside
+}
@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+use std::mem;
+use ast;
+use ast::Program;
+use ast::Block;
+use ast::Statement;
+use ast::Assignment;
+use ast::FunctionCall;
+use ast::Expression;
+use ast::ExpressionKind;
+use ast::Literal;
+use ast::BinaryOperator;
+use ast::Type;
+use ir::*;
+use symbol_table::SymbolTable;
+use builtins;
+
+/*
+  Lowers a resolved and type checked program into the intermediate
+  representation. Every parameter and local variable gets its own register,
+  expressions are evaluated into fresh temporary registers. Loops test their
+  condition in a header block that the end of the body jumps back to.
+
+  Statements following a return are lowered into a block without
+  predecessors; such blocks are removed once the function is complete.
+*/
+
+pub fn lower(program: &Program) -> Module {
+  let mut lowering = Lowering::new(program);
+  lowering.lower()
+}
+
+// block under construction, the terminator is set when the block is finished
+struct PartialBlock {
+  instructions: Vec<Instruction>,
+  terminator: Option<Terminator>,
+}
+
+struct Lowering<'a> {
+  program: &'a Program,
+  // name -> function index
+  functions: HashMap<usize, usize>,
+  // name -> global index
+  globals: HashMap<usize, usize>,
+  // state of the function being lowered
+  registers: Vec<Type>,
+  blocks: Vec<PartialBlock>,
+  current: BlockId,
+  locals: SymbolTable<Register>,
+}
+
+impl<'a> Lowering<'a> {
+  fn new(program: &'a Program) -> Lowering<'a> {
+    Lowering {
+      program: program,
+      functions: HashMap::new(),
+      globals: HashMap::new(),
+      registers: vec![],
+      blocks: vec![],
+      current: 0,
+      locals: SymbolTable::new(),
+    }
+  }
+
+  fn lower(&mut self) -> Module {
+    let program = self.program;
+    let mut main = None;
+    let mut declared = vec![];
+    for function in program.functions.iter() {
+      if !self.functions.contains_key(&function.name) {
+        let index = declared.len();
+        self.functions.insert(function.name, index);
+        if program.get_text(function.name) == "main" {
+          main = Some(index);
+        }
+        declared.push(function);
+      }
+    }
+
+    let mut globals = vec![];
+    for global in program.globals.iter() {
+      if !self.globals.contains_key(&global.name) {
+        self.globals.insert(global.name, globals.len());
+        globals.push(Global {
+          name: program.get_text(global.name).to_string(),
+          global_type: global.var_type,
+        });
+      }
+    }
+
+    let mut functions = vec![];
+    for function in declared.iter() {
+      functions.push(self.lower_function(*function));
+    }
+
+    let init = functions.len();
+    functions.push(self.lower_global_initializers());
+
+    Module {
+      globals: globals,
+      functions: functions,
+      init: init,
+      main: main,
+    }
+  }
+
+  fn lower_function(&mut self, function: &ast::Function) -> Function {
+    self.start_function();
+
+    // parameters and the top level of the body share the scope
+    self.locals.push_scope();
+    let mut parameters = vec![];
+    for parameter in function.parameters.iter() {
+      let register = self.new_register(parameter.param_type);
+      self.locals.declare(parameter.name, register);
+      parameters.push(register);
+    }
+
+    for statement in function.body.statements.iter() {
+      self.lower_statement(statement);
+    }
+    self.locals.pop_scope();
+
+    // only void functions may reach their end
+    let terminator = if function.return_type == Type::Void {
+      Terminator::Return(None)
+    } else {
+      Terminator::Unreachable
+    };
+    self.terminate(terminator);
+
+    let name = self.program.get_text(function.name).to_string();
+    self.finish_function(name, parameters, function.return_type)
+  }
+
+  fn lower_global_initializers(&mut self) -> Function {
+    let program = self.program;
+    self.start_function();
+    for global in program.globals.iter() {
+      let value = self.lower_expression(&global.initializer);
+      let index = self.global_index(global.name);
+      self.emit(Instruction::StoreGlobal(index, value));
+    }
+    self.terminate(Terminator::Return(None));
+    self.finish_function(INIT_FUNCTION_NAME.to_string(), vec![], Type::Void)
+  }
+
+  fn start_function(&mut self) {
+    self.registers = vec![];
+    self.blocks = vec![];
+    self.locals = SymbolTable::new();
+    let entry = self.new_block();
+    self.current = entry;
+  }
+
+  fn finish_function(&mut self, name: String, parameters: Vec<Register>, return_type: Type) -> Function {
+    let mut blocks = vec![];
+    for block in mem::replace(&mut self.blocks, vec![]).into_iter() {
+      match block.terminator {
+        Some(terminator) => blocks.push(BasicBlock {
+          instructions: block.instructions,
+          terminator: terminator,
+        }),
+        None => panic!("Internal compiler error: unterminated block in function '{}'", name),
+      }
+    }
+
+    let mut function = Function {
+      name: name,
+      parameters: parameters,
+      return_type: return_type,
+      registers: mem::replace(&mut self.registers, vec![]),
+      blocks: blocks,
+    };
+    function.remove_unreachable_blocks();
+    function
+  }
+
+  fn lower_block(&mut self, block: &Block) {
+    self.locals.push_scope();
+    for statement in block.statements.iter() {
+      self.lower_statement(statement);
+    }
+    self.locals.pop_scope();
+  }
+
+  fn lower_statement(&mut self, statement: &Statement) {
+    match *statement {
+      Statement::Block(ref block) => self.lower_block(block),
+      Statement::VariableDeclaration(ref declaration) => {
+        // variable is not visible in its own initializer
+        let value = self.lower_expression(&declaration.initializer);
+        let register = self.new_register(declaration.var_type);
+        self.locals.declare(declaration.name, register);
+        self.emit(Instruction::Copy(register, value));
+      },
+      Statement::Assignment(ref assignment) => self.lower_assignment(assignment),
+      Statement::FunctionCall(ref call) => {
+        self.lower_call(call);
+      },
+      Statement::For(ref for_loop) => {
+        // variable declared in the init clause is only visible inside the loop
+        self.locals.push_scope();
+        match for_loop.init {
+          Some(ref init) => self.lower_statement(&**init),
+          None => { },
+        }
+
+        let header = self.new_block();
+        self.terminate(Terminator::Jump(header));
+        self.current = header;
+        let condition = match for_loop.condition {
+          Some(ref condition) => Some(self.lower_expression(condition)),
+          None => None,
+        };
+
+        let body = self.new_block();
+        self.current = body;
+        self.lower_block(&for_loop.body);
+        match for_loop.update {
+          Some(ref update) => self.lower_assignment(update),
+          None => { },
+        }
+        self.terminate(Terminator::Jump(header));
+
+        // the end block follows the blocks of the body
+        let end = self.new_block();
+        self.current = header;
+        match condition {
+          Some(value) => self.terminate(Terminator::Branch(value, body, end)),
+          None => self.terminate(Terminator::Jump(body)),
+        }
+
+        self.current = end;
+        self.locals.pop_scope();
+      },
+      Statement::If(ref if_statement) => {
+        // last blocks of the branches, they jump to the end block once it exists
+        let mut exits = vec![];
+
+        let value = self.lower_expression(&if_statement.condition);
+        let then_block = self.new_block();
+        let mut next = self.new_block();
+        self.terminate(Terminator::Branch(value, then_block, next));
+        self.current = then_block;
+        self.lower_block(&if_statement.block);
+        exits.push(self.current);
+
+        for else_if in if_statement.else_ifs.iter() {
+          self.current = next;
+          let value = self.lower_expression(&else_if.condition);
+          let then_block = self.new_block();
+          next = self.new_block();
+          self.terminate(Terminator::Branch(value, then_block, next));
+          self.current = then_block;
+          self.lower_block(&else_if.block);
+          exits.push(self.current);
+        }
+
+        self.current = next;
+        match if_statement.else_block {
+          Some(ref block) => self.lower_block(block),
+          None => { },
+        }
+        exits.push(self.current);
+
+        let end = self.new_block();
+        for exit in exits.into_iter() {
+          self.current = exit;
+          self.terminate(Terminator::Jump(end));
+        }
+        self.current = end;
+      },
+      Statement::Return(ref return_statement) => {
+        let value = match return_statement.value {
+          Some(ref value) => Some(self.lower_expression(value)),
+          None => None,
+        };
+        self.terminate(Terminator::Return(value));
+        // statements after the return go into an unreachable block
+        let block = self.new_block();
+        self.current = block;
+      },
+      Statement::Empty(..) => { },
+    }
+  }
+
+  fn lower_assignment(&mut self, assignment: &Assignment) {
+    let value = self.lower_expression(&assignment.value);
+    match self.locals.lookup(assignment.name) {
+      Some(register) => self.emit(Instruction::Copy(register, value)),
+      None => {
+        let index = self.global_index(assignment.name);
+        self.emit(Instruction::StoreGlobal(index, value));
+      },
+    }
+  }
+
+  // returns the register holding the result, None for void calls
+  fn lower_call(&mut self, call: &FunctionCall) -> Option<Register> {
+    let mut arguments = vec![];
+    for argument in call.arguments.iter() {
+      arguments.push(self.lower_expression(argument));
+    }
+
+    let function = match self.functions.get(&call.name) {
+      Some(index) => Some(*index),
+      None => None,
+    };
+
+    match function {
+      Some(index) => {
+        let program = self.program;
+        let return_type = program.functions.iter()
+          .find(|function| function.name == call.name).unwrap().return_type;
+        let destination = if return_type == Type::Void {
+          None
+        } else {
+          Some(self.new_register(return_type))
+        };
+        self.emit(Instruction::Call(destination, index, arguments));
+        destination
+      },
+      None => {
+        let program = self.program;
+        let name = program.get_text(call.name);
+        if name == builtins::PRINT {
+          self.emit(Instruction::Print(arguments.pop().unwrap()));
+          None
+        } else {
+          panic!("Internal compiler error: call to unknown function '{}'", name);
+        }
+      },
+    }
+  }
+
+  fn lower_expression(&mut self, expression: &Expression) -> Operand {
+    match expression.kind {
+      ExpressionKind::Literal(literal) => {
+        match literal {
+          Literal::Integer(value) => Operand::Integer(value),
+          Literal::Float(value) => Operand::Float(value),
+          Literal::Double(value) => Operand::Double(value),
+          Literal::Boolean(value) => Operand::Boolean(value),
+          Literal::Text(index) => Operand::Text(self.program.get_text(index).to_string()),
+        }
+      },
+      ExpressionKind::Variable(name) => {
+        match self.locals.lookup(name) {
+          Some(register) => Operand::Register(register),
+          None => {
+            let index = self.global_index(name);
+            let register = self.new_register(expression.get_type());
+            self.emit(Instruction::LoadGlobal(register, index));
+            Operand::Register(register)
+          },
+        }
+      },
+      ExpressionKind::Binary(operator, ref left, ref right) => {
+        let operand_type = left.get_type();
+        let left = self.lower_expression(&**left);
+        let right = self.lower_expression(&**right);
+        let register = self.new_register(expression.get_type());
+        if operator == BinaryOperator::Divide && operand_type == Type::Integer {
+          self.emit(Instruction::Divide(register, left, right, expression.pos));
+        } else {
+          self.emit(Instruction::Binary(register, operator, left, right));
+        }
+        Operand::Register(register)
+      },
+      ExpressionKind::Call(ref call) => {
+        match self.lower_call(call) {
+          Some(register) => Operand::Register(register),
+          None => panic!("Internal compiler error: void call used as a value"),
+        }
+      },
+      ExpressionKind::Cast(ref inner, target) => {
+        let value = self.lower_expression(&**inner);
+        if inner.get_type() == target {
+          value
+        } else {
+          let register = self.new_register(target);
+          self.emit(Instruction::Convert(register, value));
+          Operand::Register(register)
+        }
+      },
+    }
+  }
+
+  fn global_index(&self, name: usize) -> usize {
+    match self.globals.get(&name) {
+      Some(index) => *index,
+      None => panic!("Internal compiler error: undeclared variable '{}'",
+        self.program.get_text(name)),
+    }
+  }
+
+  fn new_register(&mut self, register_type: Type) -> Register {
+    self.registers.push(register_type);
+    self.registers.len() - 1
+  }
+
+  fn new_block(&mut self) -> BlockId {
+    self.blocks.push(PartialBlock { instructions: vec![], terminator: None });
+    self.blocks.len() - 1
+  }
+
+  fn emit(&mut self, instruction: Instruction) {
+    self.blocks[self.current].instructions.push(instruction);
+  }
+
+  // ends the current block unless it already has a terminator
+  fn terminate(&mut self, terminator: Terminator) {
+    let block = &mut self.blocks[self.current];
+    if block.terminator.is_none() {
+      block.terminator = Some(terminator);
+    }
+  }
+}
@@ -0,0 +1,420 @@
+use std::iter;
+use std::mem;
+use ast::BinaryOperator;
+use ast::Position;
+use ast::Type;
+
+pub mod lowering;
+pub mod printer;
+pub mod parser;
+pub mod dominators;
+pub mod ssa;
+pub mod verifier;
+pub mod loops;
+pub mod dataflow;
+pub mod liveness;
+pub mod reaching_definitions;
+pub mod call_graph;
+pub mod interpreter;
+pub mod optimizer;
+
+/*
+  Mid-level intermediate representation. Every function is a control-flow
+  graph of basic blocks; a block is a list of three-address instructions
+  ended by a single terminator that names the successor blocks. Block 0 is
+  the entry block.
+
+  Values live in virtual registers, numbered per function. Every register
+  has one type for its whole lifetime, stored in the function's register
+  table. Registers may be assigned more than once: the lowering gives every
+  source variable its own register and assigns it like the source does.
+  Parameters are the first registers of the function. ssa.rs converts
+  functions into static single assignment form, where every register is
+  assigned once and phi instructions at the start of a block merge the
+  values flowing in from its predecessors, and back out of it.
+
+  Operators are typed by their operands, so the type checker must have made
+  both operands the same type. Integer division is a separate instruction
+  carrying its source position, as it is the only operation that fails at
+  run time. Globals are read and written with explicit instructions and are
+  initialized by a synthetic function placed after the program's functions.
+
+  The textual form written by the printer is read back by the parser, see
+  printer.rs for the syntax.
+*/
+
+pub type Register = usize;
+pub type BlockId = usize;
+
+#[derive(Show, Clone, PartialEq)]
+pub enum Operand {
+  Register(Register),
+  Integer(i32),
+  Float(f32),
+  Double(f64),
+  Boolean(bool),
+  Text(String),
+}
+
+impl Operand {
+  pub fn get_type(&self, function: &Function) -> Type {
+    match *self {
+      Operand::Register(register) => function.registers[register],
+      Operand::Integer(..) => Type::Integer,
+      Operand::Float(..) => Type::Float,
+      Operand::Double(..) => Type::Double,
+      Operand::Boolean(..) => Type::Boolean,
+      Operand::Text(..) => Type::String,
+    }
+  }
+
+  pub fn is_constant(&self) -> bool {
+    match *self {
+      Operand::Register(..) => false,
+      _ => true,
+    }
+  }
+
+  // same register or constant with the same bits: 0.0 and -0.0 differ, NaN
+  // is identical to itself
+  pub fn is_identical(&self, other: &Operand) -> bool {
+    match (self, other) {
+      (&Operand::Float(a), &Operand::Float(b)) => {
+        unsafe { mem::transmute::<f32, u32>(a) == mem::transmute::<f32, u32>(b) }
+      },
+      (&Operand::Double(a), &Operand::Double(b)) => {
+        unsafe { mem::transmute::<f64, u64>(a) == mem::transmute::<f64, u64>(b) }
+      },
+      _ => self == other,
+    }
+  }
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub enum Instruction {
+  // dest = operand
+  Copy(Register, Operand),
+  // dest = left op right; comparisons produce bool, + on strings concatenates
+  Binary(Register, BinaryOperator, Operand, Operand),
+  // dest = left / right on ints. Division by zero is reported at the position
+  Divide(Register, Operand, Operand, Position),
+  // dest = operand converted to the type of dest
+  Convert(Register, Operand),
+  // function index, arguments. No destination for void functions
+  Call(Option<Register>, usize, Vec<Operand>),
+  Print(Operand),
+  // global index
+  LoadGlobal(Register, usize),
+  StoreGlobal(usize, Operand),
+  // dest = the operand of the predecessor control came from. Only in SSA
+  // form, phis come before the other instructions of their block
+  Phi(Register, Vec<(BlockId, Operand)>),
+}
+
+impl Instruction {
+  // register written by the instruction
+  pub fn destination(&self) -> Option<Register> {
+    match *self {
+      Instruction::Copy(dest, _) | Instruction::Binary(dest, _, _, _) |
+      Instruction::Divide(dest, _, _, _) | Instruction::Convert(dest, _) |
+      Instruction::LoadGlobal(dest, _) | Instruction::Phi(dest, _) => Some(dest),
+      Instruction::Call(dest, _, _) => dest,
+      Instruction::Print(..) | Instruction::StoreGlobal(..) => None,
+    }
+  }
+
+  pub fn destination_mut(&mut self) -> Option<&mut Register> {
+    match *self {
+      Instruction::Copy(ref mut dest, _) | Instruction::Binary(ref mut dest, _, _, _) |
+      Instruction::Divide(ref mut dest, _, _, _) | Instruction::Convert(ref mut dest, _) |
+      Instruction::LoadGlobal(ref mut dest, _) | Instruction::Phi(ref mut dest, _) |
+      Instruction::Call(Some(ref mut dest), _, _) => Some(dest),
+      Instruction::Call(None, _, _) | Instruction::Print(..) | Instruction::StoreGlobal(..) => None,
+    }
+  }
+
+  pub fn operands(&self) -> Vec<&Operand> {
+    match *self {
+      Instruction::Copy(_, ref operand) | Instruction::Convert(_, ref operand) |
+      Instruction::Print(ref operand) | Instruction::StoreGlobal(_, ref operand) => vec![operand],
+      Instruction::Binary(_, _, ref left, ref right) |
+      Instruction::Divide(_, ref left, ref right, _) => vec![left, right],
+      Instruction::Call(_, _, ref arguments) => arguments.iter().collect(),
+      Instruction::LoadGlobal(..) => vec![],
+      Instruction::Phi(_, ref incoming) => incoming.iter().map(|&(_, ref operand)| operand).collect(),
+    }
+  }
+
+  pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+    match *self {
+      Instruction::Copy(_, ref mut operand) | Instruction::Convert(_, ref mut operand) |
+      Instruction::Print(ref mut operand) | Instruction::StoreGlobal(_, ref mut operand) => vec![operand],
+      Instruction::Binary(_, _, ref mut left, ref mut right) |
+      Instruction::Divide(_, ref mut left, ref mut right, _) => vec![left, right],
+      Instruction::Call(_, _, ref mut arguments) => arguments.iter_mut().collect(),
+      Instruction::LoadGlobal(..) => vec![],
+      Instruction::Phi(_, ref mut incoming) => {
+        incoming.iter_mut().map(|&mut (_, ref mut operand)| operand).collect()
+      },
+    }
+  }
+
+  pub fn is_phi(&self) -> bool {
+    match *self {
+      Instruction::Phi(..) => true,
+      _ => false,
+    }
+  }
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub enum Terminator {
+  Jump(BlockId),
+  // condition, block taken if true, block taken if false
+  Branch(Operand, BlockId, BlockId),
+  Return(Option<Operand>),
+  // end of a non-void function that is never reached by a well typed program
+  Unreachable,
+}
+
+impl Terminator {
+  pub fn successors(&self) -> Vec<BlockId> {
+    match *self {
+      Terminator::Jump(target) => vec![target],
+      Terminator::Branch(_, if_true, if_false) => {
+        if if_true == if_false { vec![if_true] } else { vec![if_true, if_false] }
+      },
+      Terminator::Return(..) | Terminator::Unreachable => vec![],
+    }
+  }
+
+  // redirects the edges to a block to another block
+  pub fn replace_successor(&mut self, old: BlockId, new: BlockId) {
+    match *self {
+      Terminator::Jump(ref mut target) => {
+        if *target == old {
+          *target = new;
+        }
+      },
+      Terminator::Branch(_, ref mut if_true, ref mut if_false) => {
+        if *if_true == old {
+          *if_true = new;
+        }
+        if *if_false == old {
+          *if_false = new;
+        }
+      },
+      Terminator::Return(..) | Terminator::Unreachable => { },
+    }
+  }
+
+  pub fn operands(&self) -> Vec<&Operand> {
+    match *self {
+      Terminator::Branch(ref condition, _, _) => vec![condition],
+      Terminator::Return(Some(ref value)) => vec![value],
+      _ => vec![],
+    }
+  }
+
+  pub fn operands_mut(&mut self) -> Vec<&mut Operand> {
+    match *self {
+      Terminator::Branch(ref mut condition, _, _) => vec![condition],
+      Terminator::Return(Some(ref mut value)) => vec![value],
+      _ => vec![],
+    }
+  }
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct BasicBlock {
+  pub instructions: Vec<Instruction>,
+  pub terminator: Terminator,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct Function {
+  pub name: String,
+  pub parameters: Vec<Register>,
+  pub return_type: Type,
+  // register -> type
+  pub registers: Vec<Type>,
+  pub blocks: Vec<BasicBlock>,
+}
+
+impl Function {
+  pub fn new_register(&mut self, register_type: Type) -> Register {
+    self.registers.push(register_type);
+    self.registers.len() - 1
+  }
+
+  // replaces the uses of registers by their replacement, following chains of
+  // replacements
+  pub fn replace_uses(&mut self, replacements: &Vec<Option<Operand>>) {
+    let limit = replacements.len();
+    let replace = |operand: &mut Operand| {
+      let mut steps = 0;
+      loop {
+        let replacement = match *operand {
+          Operand::Register(register) => match replacements[register] {
+            Some(ref replacement) => replacement.clone(),
+            None => return,
+          },
+          _ => return,
+        };
+        *operand = replacement;
+        steps += 1;
+        if steps > limit {
+          panic!("Internal compiler error: cyclic register replacement");
+        }
+      }
+    };
+
+    for block in self.blocks.iter_mut() {
+      for instruction in block.instructions.iter_mut() {
+        for operand in instruction.operands_mut().into_iter() {
+          replace(operand);
+        }
+      }
+      for operand in block.terminator.operands_mut().into_iter() {
+        replace(operand);
+      }
+    }
+  }
+
+  // removes the phi operands for a predecessor that no longer jumps to the block
+  pub fn remove_phi_operands(&mut self, block: BlockId, predecessor: BlockId) {
+    for instruction in self.blocks[block].instructions.iter_mut() {
+      match *instruction {
+        Instruction::Phi(_, ref mut incoming) => incoming.retain(|&(from, _)| from != predecessor),
+        _ => break,
+      }
+    }
+  }
+
+  // renames a predecessor in the phis of a block, after the edge moved to
+  // another block
+  pub fn replace_phi_predecessor(&mut self, block: BlockId, old: BlockId, new: BlockId) {
+    for instruction in self.blocks[block].instructions.iter_mut() {
+      match *instruction {
+        Instruction::Phi(_, ref mut incoming) => {
+          for &mut (ref mut from, _) in incoming.iter_mut() {
+            if *from == old {
+              *from = new;
+            }
+          }
+        },
+        _ => break,
+      }
+    }
+  }
+
+  // predecessors of every block, in block order
+  pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+    let mut predecessors: Vec<Vec<BlockId>> = iter::repeat(vec![]).take(self.blocks.len()).collect();
+    for (id, block) in self.blocks.iter().enumerate() {
+      for successor in block.terminator.successors().iter() {
+        predecessors[*successor].push(id);
+      }
+    }
+    predecessors
+  }
+
+  // blocks reachable from the entry block in reverse postorder
+  pub fn reverse_postorder(&self) -> Vec<BlockId> {
+    let mut visited = iter::repeat(false).take(self.blocks.len()).collect::<Vec<bool>>();
+    let mut postorder = vec![];
+    // block and the number of its successors already visited
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+    while !stack.is_empty() {
+      let (block, next) = stack[stack.len() - 1];
+      let successors = self.blocks[block].terminator.successors();
+      if next < successors.len() {
+        let last = stack.len() - 1;
+        stack[last] = (block, next + 1);
+        let successor = successors[next];
+        if !visited[successor] {
+          visited[successor] = true;
+          stack.push((successor, 0));
+        }
+      } else {
+        postorder.push(block);
+        stack.pop();
+      }
+    }
+    postorder.reverse();
+    postorder
+  }
+
+  // removes the blocks that can not be reached from the entry block and
+  // renumbers the others, keeping their order
+  pub fn remove_unreachable_blocks(&mut self) {
+    let mut reachable = iter::repeat(false).take(self.blocks.len()).collect::<Vec<bool>>();
+    for block in self.reverse_postorder().iter() {
+      reachable[*block] = true;
+    }
+
+    let mut new_ids = vec![];
+    let mut next_id = 0;
+    for is_reachable in reachable.iter() {
+      new_ids.push(next_id);
+      if *is_reachable {
+        next_id += 1;
+      }
+    }
+
+    let blocks = mem::replace(&mut self.blocks, vec![]);
+    for (id, mut block) in blocks.into_iter().enumerate() {
+      if reachable[id] {
+        block.terminator = match block.terminator {
+          Terminator::Jump(target) => Terminator::Jump(new_ids[target]),
+          Terminator::Branch(condition, if_true, if_false) =>
+            Terminator::Branch(condition, new_ids[if_true], new_ids[if_false]),
+          terminator => terminator,
+        };
+        // phis drop the values coming from removed predecessors
+        for instruction in block.instructions.iter_mut() {
+          match *instruction {
+            Instruction::Phi(_, ref mut incoming) => {
+              let kept = mem::replace(incoming, vec![]).into_iter()
+                .filter(|&(predecessor, _)| reachable[predecessor])
+                .map(|(predecessor, operand)| (new_ids[predecessor], operand))
+                .collect();
+              *incoming = kept;
+            },
+            _ => { },
+          }
+        }
+        self.blocks.push(block);
+      }
+    }
+  }
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct Global {
+  pub name: String,
+  pub global_type: Type,
+}
+
+#[derive(Show, Clone, PartialEq)]
+pub struct Module {
+  pub globals: Vec<Global>,
+  pub functions: Vec<Function>,
+  // synthetic function that initializes the globals
+  pub init: usize,
+  pub main: Option<usize>,
+}
+
+impl Module {
+  pub fn function_index(&self, name: &str) -> Option<usize> {
+    self.functions.iter().position(|function| function.name.as_slice() == name)
+  }
+
+  pub fn global_index(&self, name: &str) -> Option<usize> {
+    self.globals.iter().position(|global| global.name.as_slice() == name)
+  }
+}
+
+// name of the synthetic function initializing the globals
+pub static INIT_FUNCTION_NAME: &'static str = "<globals>";
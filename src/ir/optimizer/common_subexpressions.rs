@@ -0,0 +1,121 @@
+use ast::BinaryOperator;
+use ast::Type;
+use ir::*;
+use ir::dominators::DominatorTree;
+use ir::optimizer::Pass;
+
+/*
+  Common subexpression elimination over the dominator tree. An operation
+  whose operands are identical to those of an operation in a dominating
+  block, or earlier in the same block, computes the same value in SSA form,
+  so it becomes a copy of the earlier result that copy propagation removes.
+  Operands of commutative operators are matched in either order; string
+  concatenation is not commutative.
+
+  Binary operations, integer divisions and conversions take part. A second
+  division by the same zero would never run, as the first one stops the
+  program. Loads and calls may see different values and are left alone.
+*/
+
+pub struct CommonSubexpressionElimination;
+
+impl Pass for CommonSubexpressionElimination {
+  fn name(&self) -> &'static str {
+    "cse"
+  }
+
+  fn run(&self, function: &mut Function) -> bool {
+    run(function)
+  }
+}
+
+#[derive(Show, Clone)]
+enum Expression {
+  // operator, operands, whether the operands may be swapped
+  Binary(BinaryOperator, Operand, Operand, bool),
+  Divide(Operand, Operand),
+  // target type, operand
+  Convert(Type, Operand),
+}
+
+impl Expression {
+  fn matches(&self, other: &Expression) -> bool {
+    match (self, other) {
+      (&Expression::Binary(operator, ref a, ref b, commutative), &Expression::Binary(other_operator, ref c, ref d, _)) => {
+        operator == other_operator && ((a.is_identical(c) && b.is_identical(d)) ||
+          (commutative && a.is_identical(d) && b.is_identical(c)))
+      },
+      (&Expression::Divide(ref a, ref b), &Expression::Divide(ref c, ref d)) => {
+        a.is_identical(c) && b.is_identical(d)
+      },
+      (&Expression::Convert(target, ref a), &Expression::Convert(other_target, ref b)) => {
+        target == other_target && a.is_identical(b)
+      },
+      _ => false,
+    }
+  }
+}
+
+fn is_commutative(operator: BinaryOperator, result: Type) -> bool {
+  match operator {
+    BinaryOperator::Plus => result != Type::String,
+    BinaryOperator::Multiply | BinaryOperator::Equals => true,
+    _ => false,
+  }
+}
+
+pub fn run(function: &mut Function) -> bool {
+  let tree = DominatorTree::new(function);
+  let mut elimination = Elimination {
+    tree: &tree,
+    available: vec![],
+    changed: false,
+  };
+  elimination.visit(function, 0);
+  elimination.changed
+}
+
+struct Elimination<'a> {
+  tree: &'a DominatorTree,
+  // expressions computed in the dominating blocks and the registers holding them
+  available: Vec<(Expression, Register)>,
+  changed: bool,
+}
+
+impl<'a> Elimination<'a> {
+  fn visit(&mut self, function: &mut Function, block: BlockId) {
+    let scope = self.available.len();
+    for index in range(0, function.blocks[block].instructions.len()) {
+      let (destination, expression) = match function.blocks[block].instructions[index] {
+        Instruction::Binary(destination, operator, ref left, ref right) => {
+          let commutative = is_commutative(operator, function.registers[destination]);
+          (destination, Expression::Binary(operator, left.clone(), right.clone(), commutative))
+        },
+        Instruction::Divide(destination, ref left, ref right, _) => {
+          (destination, Expression::Divide(left.clone(), right.clone()))
+        },
+        Instruction::Convert(destination, ref operand) => {
+          (destination, Expression::Convert(function.registers[destination], operand.clone()))
+        },
+        _ => continue,
+      };
+
+      let previous = self.available.iter()
+        .find(|&&(ref available, _)| available.matches(&expression))
+        .map(|&(_, register)| register);
+      match previous {
+        Some(register) => {
+          function.blocks[block].instructions[index] = Instruction::Copy(destination, Operand::Register(register));
+          self.changed = true;
+        },
+        None => self.available.push((expression, destination)),
+      }
+    }
+
+    let tree = self.tree;
+    for child in tree.children(block).iter() {
+      self.visit(function, *child);
+    }
+    self.available.truncate(scope);
+  }
+}
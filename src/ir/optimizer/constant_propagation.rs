@@ -0,0 +1,383 @@
+use std::i32;
+use std::iter;
+use std::mem;
+use ast::BinaryOperator;
+use ast::Type;
+use ir::*;
+use ir::optimizer::Pass;
+use ir::optimizer::unreachable_blocks;
+
+/*
+  Sparse conditional constant propagation (Wegman and Zadeck). Every
+  register starts out unknown and only moves down the lattice
+
+    unknown -> constant -> varying
+
+  as the blocks reached so far are evaluated. A branch on a constant only
+  makes the edge it takes executable, and phis only merge the values coming
+  in over executable edges, so constants flowing around a loop or past an
+  if whose condition is known are found as well.
+
+  Afterwards uses of constant registers are replaced by the constants, the
+  instructions computing them are removed, branches on constants become
+  jumps and blocks that were never reached are removed. Folding follows the
+  run time semantics: integers wrap around, i32::MIN / -1 is i32::MIN.
+  Integer division by zero and conversions of floating point values outside
+  the int range are left for run time.
+*/
+
+pub struct ConstantPropagation;
+
+impl Pass for ConstantPropagation {
+  fn name(&self) -> &'static str {
+    "constant-propagation"
+  }
+
+  fn run(&self, function: &mut Function) -> bool {
+    run(function)
+  }
+}
+
+#[derive(Show, Clone, PartialEq)]
+enum Value {
+  // no executed instruction assigned the register yet
+  Unknown,
+  Constant(Operand),
+  // may hold more than one value
+  Varying,
+}
+
+fn meet(a: &Value, b: &Value) -> Value {
+  match (a, b) {
+    (&Value::Unknown, other) | (other, &Value::Unknown) => other.clone(),
+    (&Value::Constant(ref x), &Value::Constant(ref y)) if x.is_identical(y) => a.clone(),
+    _ => Value::Varying,
+  }
+}
+
+pub fn run(function: &mut Function) -> bool {
+  let (values, executable) = {
+    let mut propagation = Propagation::new(function);
+    propagation.solve();
+    (propagation.values, propagation.executable)
+  };
+
+  let mut changed = false;
+  let replacements: Vec<Option<Operand>> = values.into_iter().map(|value| match value {
+    Value::Constant(constant) => Some(constant),
+    _ => None,
+  }).collect();
+
+  for (id, block) in function.blocks.iter_mut().enumerate() {
+    if executable[id].is_empty() && id != 0 {
+      continue;
+    }
+    // constant registers are no longer assigned. Calls never are constant
+    let count = block.instructions.len();
+    block.instructions.retain(|instruction| match instruction.destination() {
+      Some(register) => replacements[register].is_none(),
+      None => true,
+    });
+    changed = changed || block.instructions.len() != count;
+
+    // phis lose the operands of edges that are never taken
+    for instruction in block.instructions.iter_mut() {
+      match *instruction {
+        Instruction::Phi(_, ref mut incoming) => {
+          let count = incoming.len();
+          incoming.retain(|&(predecessor, _)| executable[id].contains(&predecessor));
+          changed = changed || incoming.len() != count;
+        },
+        _ => break,
+      }
+    }
+  }
+
+  if replacements.iter().any(|replacement| replacement.is_some()) {
+    function.replace_uses(&replacements);
+    changed = true;
+  }
+
+  // branches on constants become jumps
+  unreachable_blocks::run(function) || changed
+}
+
+struct Propagation<'a> {
+  function: &'a Function,
+  values: Vec<Value>,
+  // block -> predecessors whose edge to the block was taken
+  executable: Vec<Vec<BlockId>>,
+  visited: Vec<bool>,
+  // edges that became executable
+  flow_worklist: Vec<(BlockId, BlockId)>,
+  // registers whose value changed
+  ssa_worklist: Vec<Register>,
+  // register -> block and instruction using it, None for the terminator
+  uses: Vec<Vec<(BlockId, Option<usize>)>>,
+}
+
+impl<'a> Propagation<'a> {
+  fn new(function: &'a Function) -> Propagation<'a> {
+    let register_count = function.registers.len();
+    let block_count = function.blocks.len();
+    let mut uses: Vec<Vec<(BlockId, Option<usize>)>> = iter::repeat(vec![]).take(register_count).collect();
+    for (id, block) in function.blocks.iter().enumerate() {
+      for (index, instruction) in block.instructions.iter().enumerate() {
+        for operand in instruction.operands().iter() {
+          match **operand {
+            Operand::Register(register) => uses[register].push((id, Some(index))),
+            _ => { },
+          }
+        }
+      }
+      for operand in block.terminator.operands().iter() {
+        match **operand {
+          Operand::Register(register) => uses[register].push((id, None)),
+          _ => { },
+        }
+      }
+    }
+
+    let mut values: Vec<Value> = iter::repeat(Value::Unknown).take(register_count).collect();
+    for parameter in function.parameters.iter() {
+      values[*parameter] = Value::Varying;
+    }
+
+    Propagation {
+      function: function,
+      values: values,
+      executable: iter::repeat(vec![]).take(block_count).collect(),
+      visited: iter::repeat(false).take(block_count).collect(),
+      flow_worklist: vec![],
+      ssa_worklist: vec![],
+      uses: uses,
+    }
+  }
+
+  fn solve(&mut self) {
+    self.visit_block(0);
+    while !self.flow_worklist.is_empty() || !self.ssa_worklist.is_empty() {
+      while let Some((from, to)) = self.flow_worklist.pop() {
+        if self.executable[to].contains(&from) {
+          continue;
+        }
+        self.executable[to].push(from);
+        if self.visited[to] {
+          // only the phis see the new edge
+          let phi_count = self.function.blocks[to].instructions.iter()
+            .take_while(|instruction| instruction.is_phi()).count();
+          for index in range(0, phi_count) {
+            self.evaluate_instruction(to, index);
+          }
+        } else {
+          self.visit_block(to);
+        }
+      }
+
+      while let Some(register) = self.ssa_worklist.pop() {
+        let uses = mem::replace(&mut self.uses[register], vec![]);
+        for &(block, index) in uses.iter() {
+          if !self.visited[block] {
+            continue;
+          }
+          match index {
+            Some(index) => self.evaluate_instruction(block, index),
+            None => self.evaluate_terminator(block),
+          }
+        }
+        self.uses[register] = uses;
+      }
+    }
+  }
+
+  fn visit_block(&mut self, block: BlockId) {
+    self.visited[block] = true;
+    for index in range(0, self.function.blocks[block].instructions.len()) {
+      self.evaluate_instruction(block, index);
+    }
+    self.evaluate_terminator(block);
+  }
+
+  fn operand_value(&self, operand: &Operand) -> Value {
+    match *operand {
+      Operand::Register(register) => self.values[register].clone(),
+      ref constant => Value::Constant(constant.clone()),
+    }
+  }
+
+  fn evaluate_instruction(&mut self, block: BlockId, index: usize) {
+    let function = self.function;
+    let instruction = &function.blocks[block].instructions[index];
+    let value = match *instruction {
+      Instruction::Phi(_, ref incoming) => {
+        let mut value = Value::Unknown;
+        for &(predecessor, ref operand) in incoming.iter() {
+          if self.executable[block].contains(&predecessor) {
+            value = meet(&value, &self.operand_value(operand));
+          }
+        }
+        value
+      },
+      Instruction::Copy(_, ref operand) => self.operand_value(operand),
+      Instruction::Binary(_, operator, ref left, ref right) => {
+        self.fold(left, right, |left, right| fold_binary(operator, left, right))
+      },
+      Instruction::Divide(_, ref left, ref right, _) => self.fold(left, right, fold_divide),
+      Instruction::Convert(destination, ref operand) => {
+        let target = function.registers[destination];
+        match self.operand_value(operand) {
+          Value::Constant(constant) => match fold_convert(&constant, target) {
+            Some(result) => Value::Constant(result),
+            None => Value::Varying,
+          },
+          value => value,
+        }
+      },
+      Instruction::Call(..) | Instruction::LoadGlobal(..) => Value::Varying,
+      Instruction::Print(..) | Instruction::StoreGlobal(..) => return,
+    };
+
+    let register = match instruction.destination() {
+      Some(register) => register,
+      None => return,
+    };
+    let new_value = meet(&self.values[register], &value);
+    if new_value != self.values[register] {
+      self.values[register] = new_value;
+      self.ssa_worklist.push(register);
+    }
+  }
+
+  fn fold<F>(&self, left: &Operand, right: &Operand, fold: F) -> Value
+    where F: Fn(&Operand, &Operand) -> Option<Operand> {
+    match (self.operand_value(left), self.operand_value(right)) {
+      (Value::Constant(left), Value::Constant(right)) => match fold(&left, &right) {
+        Some(result) => Value::Constant(result),
+        None => Value::Varying,
+      },
+      (Value::Varying, _) | (_, Value::Varying) => Value::Varying,
+      _ => Value::Unknown,
+    }
+  }
+
+  fn evaluate_terminator(&mut self, block: BlockId) {
+    match self.function.blocks[block].terminator {
+      Terminator::Jump(target) => self.flow_worklist.push((block, target)),
+      Terminator::Branch(ref condition, if_true, if_false) => {
+        match self.operand_value(condition) {
+          Value::Constant(Operand::Boolean(true)) => self.flow_worklist.push((block, if_true)),
+          Value::Constant(Operand::Boolean(false)) => self.flow_worklist.push((block, if_false)),
+          Value::Unknown => { },
+          _ => {
+            self.flow_worklist.push((block, if_true));
+            self.flow_worklist.push((block, if_false));
+          },
+        }
+      },
+      Terminator::Return(..) | Terminator::Unreachable => { },
+    }
+  }
+}
+
+// result of a binary operation on constants, None if it is left for run time
+pub fn fold_binary(operator: BinaryOperator, left: &Operand, right: &Operand) -> Option<Operand> {
+  let result = match (left, right) {
+    (&Operand::Integer(a), &Operand::Integer(b)) => {
+      if operator.is_comparison() {
+        Operand::Boolean(compare(operator, a, b))
+      } else {
+        match operator {
+          BinaryOperator::Plus => Operand::Integer(a + b),
+          BinaryOperator::Minus => Operand::Integer(a - b),
+          BinaryOperator::Multiply => Operand::Integer(a * b),
+          // integer division is a separate instruction
+          _ => return fold_divide(left, right),
+        }
+      }
+    },
+    (&Operand::Float(a), &Operand::Float(b)) => {
+      if operator.is_comparison() {
+        Operand::Boolean(compare(operator, a, b))
+      } else {
+        match operator {
+          BinaryOperator::Plus => Operand::Float(a + b),
+          BinaryOperator::Minus => Operand::Float(a - b),
+          BinaryOperator::Multiply => Operand::Float(a * b),
+          _ => Operand::Float(a / b),
+        }
+      }
+    },
+    (&Operand::Double(a), &Operand::Double(b)) => {
+      if operator.is_comparison() {
+        Operand::Boolean(compare(operator, a, b))
+      } else {
+        match operator {
+          BinaryOperator::Plus => Operand::Double(a + b),
+          BinaryOperator::Minus => Operand::Double(a - b),
+          BinaryOperator::Multiply => Operand::Double(a * b),
+          _ => Operand::Double(a / b),
+        }
+      }
+    },
+    (&Operand::Boolean(a), &Operand::Boolean(b)) if operator.is_comparison() => {
+      Operand::Boolean(compare(operator, a, b))
+    },
+    (&Operand::Text(ref a), &Operand::Text(ref b)) => {
+      match operator {
+        BinaryOperator::Plus => Operand::Text(a.clone() + b.as_slice()),
+        _ if operator.is_comparison() => Operand::Boolean(compare(operator, a, b)),
+        _ => return None,
+      }
+    },
+    _ => return None,
+  };
+  Some(result)
+}
+
+// integer division, None for division by zero
+pub fn fold_divide(left: &Operand, right: &Operand) -> Option<Operand> {
+  match (left, right) {
+    (_, &Operand::Integer(0)) => None,
+    (&Operand::Integer(i32::MIN), &Operand::Integer(-1)) => Some(Operand::Integer(i32::MIN)),
+    (&Operand::Integer(a), &Operand::Integer(b)) => Some(Operand::Integer(a / b)),
+    _ => None,
+  }
+}
+
+// None for floating point values outside the int range
+pub fn fold_convert(operand: &Operand, target: Type) -> Option<Operand> {
+  let value = match *operand {
+    Operand::Integer(value) => value as f64,
+    Operand::Float(value) => value as f64,
+    Operand::Double(value) => value,
+    _ => return None,
+  };
+
+  match (operand, target) {
+    (&Operand::Integer(value), Type::Integer) => Some(Operand::Integer(value)),
+    (&Operand::Integer(value), Type::Float) => Some(Operand::Float(value as f32)),
+    (&Operand::Float(value), Type::Float) => Some(Operand::Float(value)),
+    (&Operand::Double(value), Type::Float) => Some(Operand::Float(value as f32)),
+    (_, Type::Integer) => {
+      // NaN fails both comparisons
+      if value >= i32::MIN as f64 && value < -(i32::MIN as f64) {
+        Some(Operand::Integer(value as i32))
+      } else {
+        None
+      }
+    },
+    (_, Type::Double) => Some(Operand::Double(value)),
+    _ => None,
+  }
+}
+
+fn compare<T: PartialOrd>(operator: BinaryOperator, a: T, b: T) -> bool {
+  match operator {
+    BinaryOperator::Equals => a == b,
+    BinaryOperator::Lesser => a < b,
+    BinaryOperator::Greater => a > b,
+    BinaryOperator::LesserOrEq => a <= b,
+    BinaryOperator::GreaterOrEq => a >= b,
+    _ => panic!("Internal compiler error: {} is not a comparison operator", operator),
+  }
+}
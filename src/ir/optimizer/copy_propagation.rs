@@ -0,0 +1,95 @@
+use std::iter;
+use ir::*;
+use ir::optimizer::Pass;
+
+/*
+  Copy propagation. In SSA form the source of a copy dominates every use of
+  its destination, so the uses can read the source directly and the copy
+  goes away. A phi whose operands are all the same value, apart from the phi
+  itself coming around a loop, is such a copy as well. Removing one phi can
+  make another trivial, so the pass repeats until it finds no more copies.
+*/
+
+pub struct CopyPropagation;
+
+impl Pass for CopyPropagation {
+  fn name(&self) -> &'static str {
+    "copy-propagation"
+  }
+
+  fn run(&self, function: &mut Function) -> bool {
+    run(function)
+  }
+}
+
+pub fn run(function: &mut Function) -> bool {
+  let mut changed = false;
+  loop {
+    let mut replacements: Vec<Option<Operand>> = iter::repeat(None).take(function.registers.len()).collect();
+    let mut found = false;
+    for block in function.blocks.iter() {
+      for instruction in block.instructions.iter() {
+        let (destination, value) = match *instruction {
+          Instruction::Copy(destination, ref source) => (destination, resolve(&replacements, source)),
+          Instruction::Phi(destination, ref incoming) => {
+            match single_value(&replacements, destination, incoming) {
+              Some(value) => (destination, value),
+              None => continue,
+            }
+          },
+          _ => continue,
+        };
+        // a copy of itself through earlier replacements stays
+        if value != Operand::Register(destination) {
+          replacements[destination] = Some(value);
+          found = true;
+        }
+      }
+    }
+
+    if !found {
+      return changed;
+    }
+    for block in function.blocks.iter_mut() {
+      block.instructions.retain(|instruction| match instruction.destination() {
+        Some(register) => replacements[register].is_none(),
+        None => true,
+      });
+    }
+    function.replace_uses(&replacements);
+    changed = true;
+  }
+}
+
+// the operand after the replacements found so far
+fn resolve(replacements: &Vec<Option<Operand>>, operand: &Operand) -> Operand {
+  let mut operand = operand.clone();
+  loop {
+    let replacement = match operand {
+      Operand::Register(register) => match replacements[register] {
+        Some(ref replacement) => replacement.clone(),
+        None => return operand,
+      },
+      _ => return operand,
+    };
+    operand = replacement;
+  }
+}
+
+// the value of a phi whose operands other than the phi itself are all the same
+fn single_value(replacements: &Vec<Option<Operand>>, destination: Register,
+  incoming: &Vec<(BlockId, Operand)>) -> Option<Operand> {
+  let mut value: Option<Operand> = None;
+  for &(_, ref operand) in incoming.iter() {
+    let operand = resolve(replacements, operand);
+    if operand == Operand::Register(destination) {
+      continue;
+    }
+    match value {
+      Some(ref value) if !value.is_identical(&operand) => return None,
+      _ => { },
+    }
+    value = Some(operand);
+  }
+  value
+}
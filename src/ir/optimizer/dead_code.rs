@@ -0,0 +1,93 @@
+use std::iter;
+use ir::*;
+use ir::optimizer::Pass;
+
+/*
+  Dead code elimination. Instructions with effects beyond their destination
+  are live: prints, global stores, calls, and integer divisions that may
+  divide by zero. So are the operands of terminators. Everything a live
+  instruction reads is live in turn; the remaining instructions compute
+  values nobody needs and are removed. Starting from the effects instead of
+  counting uses also removes cycles of phis that only feed each other.
+*/
+
+pub struct DeadCodeElimination;
+
+impl Pass for DeadCodeElimination {
+  fn name(&self) -> &'static str {
+    "dead-code"
+  }
+
+  fn run(&self, function: &mut Function) -> bool {
+    run(function)
+  }
+}
+
+pub fn run(function: &mut Function) -> bool {
+  let register_count = function.registers.len();
+  let mut live = iter::repeat(false).take(register_count).collect::<Vec<bool>>();
+  let mut worklist = vec![];
+  // register -> block and index of the instruction assigning it
+  let mut definitions: Vec<Option<(BlockId, usize)>> = iter::repeat(None).take(register_count).collect();
+
+  for (id, block) in function.blocks.iter().enumerate() {
+    for (index, instruction) in block.instructions.iter().enumerate() {
+      match instruction.destination() {
+        Some(register) => definitions[register] = Some((id, index)),
+        None => { },
+      }
+      if has_effects(instruction) {
+        mark_operands(instruction.operands(), &mut live, &mut worklist);
+      }
+    }
+    mark_operands(block.terminator.operands(), &mut live, &mut worklist);
+  }
+
+  while let Some(register) = worklist.pop() {
+    match definitions[register] {
+      Some((block, index)) => {
+        let instruction = &function.blocks[block].instructions[index];
+        mark_operands(instruction.operands(), &mut live, &mut worklist);
+      },
+      // parameters
+      None => { },
+    }
+  }
+
+  let mut changed = false;
+  for block in function.blocks.iter_mut() {
+    let count = block.instructions.len();
+    block.instructions.retain(|instruction| {
+      has_effects(instruction) || match instruction.destination() {
+        Some(register) => live[register],
+        None => true,
+      }
+    });
+    changed = changed || block.instructions.len() != count;
+  }
+  changed
+}
+
+pub fn has_effects(instruction: &Instruction) -> bool {
+  match *instruction {
+    Instruction::Print(..) | Instruction::StoreGlobal(..) | Instruction::Call(..) => true,
+    Instruction::Divide(_, _, ref divisor, _) => match *divisor {
+      Operand::Integer(value) => value == 0,
+      _ => true,
+    },
+    Instruction::Copy(..) | Instruction::Binary(..) | Instruction::Convert(..) |
+    Instruction::LoadGlobal(..) | Instruction::Phi(..) => false,
+  }
+}
+
+fn mark_operands(operands: Vec<&Operand>, live: &mut Vec<bool>, worklist: &mut Vec<Register>) {
+  for operand in operands.iter() {
+    match **operand {
+      Operand::Register(register) if !live[register] => {
+        live[register] = true;
+        worklist.push(register);
+      },
+      _ => { },
+    }
+  }
+}
@@ -0,0 +1,183 @@
+use std::iter;
+use std::mem;
+use ir::*;
+use ir::call_graph::CallGraph;
+use ir::optimizer::ModulePass;
+
+/*
+  Inlining. A call is replaced by a copy of the called function's blocks
+  when the callee is cheap, so small helpers called in loops no longer pay
+  for the call and the optimizations after it see through them.
+
+  The cost of a function is the number of its instructions other than phis
+  and copies plus the number of its blocks. A callee is inlined when its
+  cost is at most INLINE_COST, or at most SINGLE_CALL_COST if the call is
+  its only call site. Recursive functions are never inlined, tail calls
+  handles their self calls, and no caller grows beyond MAX_CALLER_COST.
+  Callers are visited bottom-up in the call graph, so a callee has its own
+  calls inlined before it is inlined itself.
+
+  The block of the call is split after the call. The copied blocks read the
+  arguments in place of the parameters, their returns jump to the second
+  half of the split block, where a phi merges the returned values into the
+  destination of the call. The copied instructions keep their source
+  positions, so a division by zero in an inlined function is reported at
+  the division inside that function, as it is without inlining.
+*/
+
+static INLINE_COST: usize = 12;
+static SINGLE_CALL_COST: usize = 60;
+static MAX_CALLER_COST: usize = 400;
+
+pub struct Inlining;
+
+impl ModulePass for Inlining {
+  fn name(&self) -> &'static str {
+    "inlining"
+  }
+
+  fn run(&self, module: &mut Module) -> bool {
+    run(module)
+  }
+}
+
+pub fn run(module: &mut Module) -> bool {
+  let mut changed = false;
+  let order = CallGraph::new(module).bottom_up_order().to_vec();
+  for caller in order.into_iter() {
+    loop {
+      // the graph changes with every call inlined
+      let graph = CallGraph::new(module);
+      let call = match find_call(module, &graph, caller) {
+        Some(call) => call,
+        None => break,
+      };
+      let (block, index) = call;
+      inline_call(module, caller, block, index);
+      changed = true;
+    }
+  }
+  changed
+}
+
+pub fn cost(function: &Function) -> usize {
+  function.blocks.iter().map(block_cost).fold(0, |total, cost| total + cost)
+}
+
+pub fn block_cost(block: &BasicBlock) -> usize {
+  1 + block.instructions.iter().filter(|instruction| match **instruction {
+    Instruction::Phi(..) | Instruction::Copy(..) => false,
+    _ => true,
+  }).count()
+}
+
+// the first call in the caller worth inlining, as block and instruction index
+fn find_call(module: &Module, graph: &CallGraph, caller: usize) -> Option<(BlockId, usize)> {
+  let function = &module.functions[caller];
+  let caller_cost = cost(function);
+  for (id, block) in function.blocks.iter().enumerate() {
+    for (index, instruction) in block.instructions.iter().enumerate() {
+      let callee = match *instruction {
+        Instruction::Call(_, callee, _) => callee,
+        _ => continue,
+      };
+      if callee == caller || graph.is_recursive(callee) {
+        continue;
+      }
+      let callee_cost = cost(&module.functions[callee]);
+      let cheap = callee_cost <= INLINE_COST ||
+        (graph.call_sites(callee) == 1 && callee_cost <= SINGLE_CALL_COST);
+      if cheap && caller_cost + callee_cost <= MAX_CALLER_COST {
+        return Some((id, index));
+      }
+    }
+  }
+  None
+}
+
+// replaces the call at the index of the block by the body of the callee
+pub fn inline_call(module: &mut Module, caller: usize, block: BlockId, index: usize) {
+  let (destination, callee, arguments) = match module.functions[caller].blocks[block].instructions[index] {
+    Instruction::Call(destination, callee, ref arguments) => (destination, callee, arguments.clone()),
+    _ => panic!("Internal compiler error: call expected"),
+  };
+  let callee = module.functions[callee].clone();
+  let function = &mut module.functions[caller];
+
+  // the instructions after the call continue in a new block
+  let continuation = function.blocks.len();
+  let base = continuation + 1;
+  let rest: Vec<Instruction> = function.blocks[block].instructions.iter()
+    .skip(index + 1)
+    .map(|instruction| instruction.clone())
+    .collect();
+  function.blocks[block].instructions.truncate(index);
+  let terminator = mem::replace(&mut function.blocks[block].terminator, Terminator::Jump(base));
+  for successor in terminator.successors().iter() {
+    function.replace_phi_predecessor(*successor, block, continuation);
+  }
+  function.blocks.push(BasicBlock { instructions: rest, terminator: terminator });
+
+  // callee register -> operand in the caller
+  let mut registers: Vec<Operand> = iter::repeat(Operand::Integer(0)).take(callee.registers.len()).collect();
+  for (register, register_type) in callee.registers.iter().enumerate() {
+    registers[register] = match callee.parameters.iter().position(|parameter| *parameter == register) {
+      Some(position) => arguments[position].clone(),
+      None => Operand::Register(function.new_register(*register_type)),
+    };
+  }
+  let map = |operand: &mut Operand| {
+    let mapped = match *operand {
+      Operand::Register(register) => registers[register].clone(),
+      _ => return,
+    };
+    *operand = mapped;
+  };
+
+  let mut returns = vec![];
+  for (id, mut callee_block) in callee.blocks.into_iter().enumerate() {
+    for instruction in callee_block.instructions.iter_mut() {
+      for operand in instruction.operands_mut().into_iter() {
+        map(operand);
+      }
+      match instruction.destination_mut() {
+        Some(register) => *register = match registers[*register] {
+          Operand::Register(mapped) => mapped,
+          _ => panic!("Internal compiler error: assignment to a parameter in SSA form"),
+        },
+        None => { },
+      }
+      match *instruction {
+        Instruction::Phi(_, ref mut incoming) => {
+          for &mut (ref mut predecessor, _) in incoming.iter_mut() {
+            *predecessor = *predecessor + base;
+          }
+        },
+        _ => { },
+      }
+    }
+    for operand in callee_block.terminator.operands_mut().into_iter() {
+      map(operand);
+    }
+    callee_block.terminator = match callee_block.terminator {
+      Terminator::Jump(target) => Terminator::Jump(target + base),
+      Terminator::Branch(condition, if_true, if_false) => Terminator::Branch(condition, if_true + base, if_false + base),
+      Terminator::Return(value) => {
+        match value {
+          Some(value) => returns.push((id + base, value)),
+          None => { },
+        }
+        Terminator::Jump(continuation)
+      },
+      Terminator::Unreachable => Terminator::Unreachable,
+    };
+    function.blocks.push(callee_block);
+  }
+
+  match destination {
+    Some(destination) => function.blocks[continuation].instructions.insert(0, Instruction::Phi(destination, returns)),
+    None => { },
+  }
+  // a callee that never returns leaves the continuation unreachable
+  function.remove_unreachable_blocks();
+}
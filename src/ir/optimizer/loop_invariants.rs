@@ -0,0 +1,126 @@
+use std::iter;
+use ir::*;
+use ir::loops;
+use ir::loops::Loop;
+use ir::optimizer::Pass;
+
+/*
+  Loop-invariant code motion. An operation inside a loop whose operands are
+  all constants or registers assigned outside the loop computes the same
+  value in every iteration, so it moves to the preheader and runs once.
+  Once moved, its destination is assigned outside the loop as well, which
+  can make further operations invariant.
+
+  Only operations that can not fail are moved, as the preheader also runs
+  when the loop body does not: copies, binary operations, conversions and
+  integer divisions by a non-zero constant. Loads of a global move when the
+  loop neither stores the global nor calls any function. Inner loops are
+  handled first, so an operation can move out of several loops in turn.
+*/
+
+pub struct LoopInvariantCodeMotion;
+
+impl Pass for LoopInvariantCodeMotion {
+  fn name(&self) -> &'static str {
+    "licm"
+  }
+
+  fn run(&self, function: &mut Function) -> bool {
+    run(function)
+  }
+}
+
+pub fn run(function: &mut Function) -> bool {
+  let mut changed = false;
+  // creating a preheader changes the blocks of the loops around it
+  loop {
+    let mut inserted = false;
+    for natural_loop in loops::find_loops(function).iter() {
+      if loops::preheader(function, natural_loop).is_none() &&
+        loops::insert_preheader(function, natural_loop).is_some() {
+        inserted = true;
+        break;
+      }
+    }
+    if !inserted {
+      break;
+    }
+    changed = true;
+  }
+
+  for natural_loop in loops::find_loops(function).iter() {
+    match loops::preheader(function, natural_loop) {
+      Some(preheader) => {
+        if hoist(function, natural_loop, preheader) {
+          changed = true;
+        }
+      },
+      None => { },
+    }
+  }
+  changed
+}
+
+fn hoist(function: &mut Function, natural_loop: &Loop, preheader: BlockId) -> bool {
+  let mut in_loop = iter::repeat(false).take(function.registers.len()).collect::<Vec<bool>>();
+  let mut calls = false;
+  let mut stored = vec![];
+  for block in natural_loop.blocks.iter() {
+    for instruction in function.blocks[*block].instructions.iter() {
+      match instruction.destination() {
+        Some(register) => in_loop[register] = true,
+        None => { },
+      }
+      match *instruction {
+        Instruction::Call(..) => calls = true,
+        Instruction::StoreGlobal(global, _) => stored.push(global),
+        _ => { },
+      }
+    }
+  }
+
+  // blocks in reverse postorder see the assignments before their uses
+  let order: Vec<BlockId> = function.reverse_postorder().into_iter()
+    .filter(|block| natural_loop.contains(*block))
+    .collect();
+  let mut changed = false;
+  let mut hoisted = true;
+  while hoisted {
+    hoisted = false;
+    for block in order.iter() {
+      let mut index = 0;
+      while index < function.blocks[*block].instructions.len() {
+        let movable = {
+          let instruction = &function.blocks[*block].instructions[index];
+          can_move(instruction, calls, &stored) && instruction.operands().iter().all(|operand| match **operand {
+            Operand::Register(register) => !in_loop[register],
+            _ => true,
+          })
+        };
+        if movable {
+          let instruction = function.blocks[*block].instructions.remove(index);
+          match instruction.destination() {
+            Some(register) => in_loop[register] = false,
+            None => { },
+          }
+          function.blocks[preheader].instructions.push(instruction);
+          hoisted = true;
+          changed = true;
+        } else {
+          index += 1;
+        }
+      }
+    }
+  }
+  changed
+}
+
+fn can_move(instruction: &Instruction, calls: bool, stored: &Vec<usize>) -> bool {
+  match *instruction {
+    Instruction::Copy(..) | Instruction::Binary(..) | Instruction::Convert(..) => true,
+    Instruction::Divide(_, _, Operand::Integer(divisor), _) => divisor != 0,
+    Instruction::LoadGlobal(_, global) => !calls && !stored.contains(&global),
+    Instruction::Divide(..) | Instruction::Call(..) | Instruction::Print(..) |
+    Instruction::StoreGlobal(..) | Instruction::Phi(..) => false,
+  }
+}
@@ -0,0 +1,168 @@
+use ir::Module;
+use ir::Function;
+use ir::ssa;
+use ir::verifier;
+use ir::verifier::Form;
+
+pub mod constant_propagation;
+pub mod copy_propagation;
+pub mod dead_code;
+pub mod unreachable_blocks;
+pub mod common_subexpressions;
+pub mod inlining;
+pub mod tail_calls;
+pub mod loop_invariants;
+pub mod strength_reduction;
+pub mod unrolling;
+
+/*
+  Optimizations over the intermediate representation. Most passes transform
+  one function in SSA form at a time and report whether they changed it;
+  module passes such as inlining see all functions at once. The pass manager
+  runs its passes in order over all functions of a module and verifies the
+  module after each pass, so a broken pass is named right away.
+
+  Levels, selected with -O0, -O1 and -O2:
+    O0   no optimization
+    O1   tail call elimination, sparse conditional constant propagation,
+         copy propagation, dead code elimination and unreachable block
+         elimination, run once
+    O2   inlining before the passes of O1, with common subexpression
+         elimination after constant propagation and the loop optimizations
+         after copy propagation: full unrolling, loop-invariant code motion
+         and strength reduction. Repeated until they no longer change the
+         module
+
+  Tests can build a pass manager with any passes to check them one by one.
+*/
+
+pub trait Pass {
+  fn name(&self) -> &'static str;
+  // returns whether the function changed
+  fn run(&self, function: &mut Function) -> bool;
+}
+
+pub trait ModulePass {
+  fn name(&self) -> &'static str;
+  // returns whether the module changed
+  fn run(&self, module: &mut Module) -> bool;
+}
+
+enum Stage {
+  Function(Box<Pass>),
+  Module(Box<ModulePass>),
+}
+
+#[derive(Show, Copy, PartialEq)]
+pub enum Level {
+  O0,
+  O1,
+  O2,
+}
+
+// upper bound of the rounds of a repeated pipeline, in case two passes keep
+// undoing each other
+static MAX_ROUNDS: usize = 10;
+
+pub struct PassManager {
+  stages: Vec<Stage>,
+  // run the passes again until none of them changes the module
+  repeat: bool,
+}
+
+impl PassManager {
+  pub fn new() -> PassManager {
+    PassManager { stages: vec![], repeat: false }
+  }
+
+  pub fn for_level(level: Level) -> PassManager {
+    let mut manager = PassManager::new();
+    match level {
+      Level::O0 => { },
+      Level::O1 => {
+        manager.add_module_pass(Box::new(tail_calls::TailCallElimination));
+        manager.add(Box::new(constant_propagation::ConstantPropagation));
+        manager.add(Box::new(copy_propagation::CopyPropagation));
+        manager.add(Box::new(dead_code::DeadCodeElimination));
+        manager.add(Box::new(unreachable_blocks::UnreachableBlockElimination));
+      },
+      Level::O2 => {
+        manager.add_module_pass(Box::new(inlining::Inlining));
+        manager.add_module_pass(Box::new(tail_calls::TailCallElimination));
+        manager.add(Box::new(constant_propagation::ConstantPropagation));
+        manager.add(Box::new(common_subexpressions::CommonSubexpressionElimination));
+        manager.add(Box::new(copy_propagation::CopyPropagation));
+        manager.add(Box::new(unrolling::LoopUnrolling));
+        manager.add(Box::new(loop_invariants::LoopInvariantCodeMotion));
+        manager.add(Box::new(strength_reduction::StrengthReduction));
+        manager.add(Box::new(dead_code::DeadCodeElimination));
+        manager.add(Box::new(unreachable_blocks::UnreachableBlockElimination));
+        manager.repeat = true;
+      },
+    }
+    manager
+  }
+
+  pub fn add(&mut self, pass: Box<Pass>) {
+    self.stages.push(Stage::Function(pass));
+  }
+
+  pub fn add_module_pass(&mut self, pass: Box<ModulePass>) {
+    self.stages.push(Stage::Module(pass));
+  }
+
+  pub fn repeat_until_unchanged(&mut self) {
+    self.repeat = true;
+  }
+
+  pub fn pass_names(&self) -> Vec<&'static str> {
+    self.stages.iter().map(|stage| match *stage {
+      Stage::Function(ref pass) => pass.name(),
+      Stage::Module(ref pass) => pass.name(),
+    }).collect()
+  }
+
+  // runs the passes over a module in SSA form, returns whether it changed
+  pub fn run(&self, module: &mut Module) -> bool {
+    let rounds = if self.repeat { MAX_ROUNDS } else { 1 };
+    let mut changed = false;
+    for _ in range(0, rounds) {
+      let mut round_changed = false;
+      for stage in self.stages.iter() {
+        let name = match *stage {
+          Stage::Function(ref pass) => {
+            for function in module.functions.iter_mut() {
+              if pass.run(function) {
+                round_changed = true;
+              }
+            }
+            pass.name()
+          },
+          Stage::Module(ref pass) => {
+            if pass.run(module) {
+              round_changed = true;
+            }
+            pass.name()
+          },
+        };
+        verifier::assert_valid(module, Form::Ssa, name);
+      }
+
+      changed = changed || round_changed;
+      if !round_changed {
+        break;
+      }
+    }
+    changed
+  }
+}
+
+// optimizes a module in normal form, it is in normal form afterwards
+pub fn optimize(module: &mut Module, level: Level) {
+  if level == Level::O0 {
+    return;
+  }
+  ssa::to_ssa(module);
+  PassManager::for_level(level).run(module);
+  ssa::from_ssa(module);
+}
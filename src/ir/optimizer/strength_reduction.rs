@@ -0,0 +1,149 @@
+use std::mem;
+use ast::BinaryOperator;
+use ast::Type;
+use ir::*;
+use ir::loops;
+use ir::loops::Loop;
+use ir::optimizer::Pass;
+
+/*
+  Strength reduction of induction variables. A basic induction variable is
+  an int phi in a loop header that starts from a value coming in from the
+  preheader and grows by a constant step on the back edge:
+
+    %i = phi [pre: a], [latch: %next]      %next = add %i, c
+
+  A multiplication of %i by a constant k then is a second induction
+  variable, starting from a * k and growing by c * k, so it is replaced by
+  a new phi and an addition at the end of the latch. Integers wrap around,
+  so the sums stay equal to the products even when they overflow.
+
+  Loops need a preheader and a single latch; the loop-invariant code motion
+  pass creates missing preheaders.
+*/
+
+pub struct StrengthReduction;
+
+impl Pass for StrengthReduction {
+  fn name(&self) -> &'static str {
+    "strength-reduction"
+  }
+
+  fn run(&self, function: &mut Function) -> bool {
+    run(function)
+  }
+}
+
+// the start value and step of an induction variable
+struct Induction {
+  register: Register,
+  start: Operand,
+  step: i32,
+}
+
+pub fn run(function: &mut Function) -> bool {
+  let mut changed = false;
+  for natural_loop in loops::find_loops(function).iter() {
+    let preheader = match loops::preheader(function, natural_loop) {
+      Some(preheader) if natural_loop.latches.len() == 1 => preheader,
+      _ => continue,
+    };
+    let latch = natural_loop.latches[0];
+    let inductions = induction_variables(function, natural_loop, preheader, latch);
+
+    // block, index and destination of the multiplication, induction variable and factor
+    let mut products = vec![];
+    for block in natural_loop.blocks.iter() {
+      for (index, instruction) in function.blocks[*block].instructions.iter().enumerate() {
+        match *instruction {
+          Instruction::Binary(destination, BinaryOperator::Multiply, Operand::Register(register), Operand::Integer(factor)) |
+          Instruction::Binary(destination, BinaryOperator::Multiply, Operand::Integer(factor), Operand::Register(register)) => {
+            match inductions.iter().position(|induction| induction.register == register) {
+              Some(induction) => products.push((*block, index, destination, induction, factor)),
+              None => { },
+            }
+          },
+          _ => { },
+        }
+      }
+    }
+
+    let mut phis = vec![];
+    for &(block, index, destination, induction, factor) in products.iter() {
+      let induction = &inductions[induction];
+      let start = match induction.start {
+        Operand::Integer(value) => Operand::Integer(value * factor),
+        ref start => {
+          let product = function.new_register(Type::Integer);
+          function.blocks[preheader].instructions.push(
+            Instruction::Binary(product, BinaryOperator::Multiply, start.clone(), Operand::Integer(factor)));
+          Operand::Register(product)
+        },
+      };
+      let reduced = function.new_register(Type::Integer);
+      let next = function.new_register(Type::Integer);
+      let mut incoming = vec![(preheader, start), (latch, Operand::Register(next))];
+      incoming.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+      phis.push(Instruction::Phi(reduced, incoming));
+      function.blocks[latch].instructions.push(Instruction::Binary(next, BinaryOperator::Plus,
+        Operand::Register(reduced), Operand::Integer(induction.step * factor)));
+      function.blocks[block].instructions[index] = Instruction::Copy(destination, Operand::Register(reduced));
+      changed = true;
+    }
+
+    let header = natural_loop.header;
+    let instructions = mem::replace(&mut function.blocks[header].instructions, phis);
+    function.blocks[header].instructions.extend(instructions.into_iter());
+  }
+  changed
+}
+
+fn induction_variables(function: &Function, natural_loop: &Loop, preheader: BlockId,
+  latch: BlockId) -> Vec<Induction> {
+
+  let mut inductions = vec![];
+  for instruction in function.blocks[natural_loop.header].instructions.iter() {
+    let (register, incoming) = match *instruction {
+      Instruction::Phi(register, ref incoming) => (register, incoming),
+      _ => break,
+    };
+    if function.registers[register] != Type::Integer || incoming.len() != 2 {
+      continue;
+    }
+    let mut start = None;
+    let mut next = None;
+    for &(predecessor, ref operand) in incoming.iter() {
+      if predecessor == preheader {
+        start = Some(operand.clone());
+      } else if predecessor == latch {
+        next = Some(operand.clone());
+      }
+    }
+    let (start, next) = match (start, next) {
+      (Some(start), Some(Operand::Register(next))) => (start, next),
+      _ => continue,
+    };
+    match step(function, natural_loop, register, next) {
+      Some(step) => inductions.push(Induction { register: register, start: start, step: step }),
+      None => { },
+    }
+  }
+  inductions
+}
+
+// the constant the register is incremented by to give next, in the loop
+fn step(function: &Function, natural_loop: &Loop, register: Register, next: Register) -> Option<i32> {
+  for block in natural_loop.blocks.iter() {
+    for instruction in function.blocks[*block].instructions.iter() {
+      match *instruction {
+        Instruction::Binary(destination, BinaryOperator::Plus, Operand::Register(source), Operand::Integer(step)) |
+        Instruction::Binary(destination, BinaryOperator::Plus, Operand::Integer(step), Operand::Register(source))
+          if destination == next && source == register => return Some(step),
+        Instruction::Binary(destination, BinaryOperator::Minus, Operand::Register(source), Operand::Integer(step))
+          if destination == next && source == register => return Some(-step),
+        _ => { },
+      }
+    }
+  }
+  None
+}
@@ -0,0 +1,111 @@
+use std::iter;
+use std::mem;
+use ir::*;
+use ir::optimizer::ModulePass;
+
+/*
+  Tail call elimination. A function that returns the result of calling
+  itself, or calls itself right before returning from a void function, does
+  not need a new frame: the call becomes a jump back to the start of the
+  function with the arguments as the new parameter values. Deep recursion
+  then runs in constant stack space.
+
+  The body of the entry block moves to a new loop header, and the entry
+  block jumps to it. Every parameter gets a phi in the header that takes the
+  parameter on entry and the arguments of the call on the jumps back, and
+  the uses of the parameter read the phi instead. The instructions keep
+  their source positions, so a division by zero is reported where it is in
+  the source. Functions whose entry block already is a loop header are left
+  alone, which the lowering never produces.
+*/
+
+pub struct TailCallElimination;
+
+impl ModulePass for TailCallElimination {
+  fn name(&self) -> &'static str {
+    "tail-calls"
+  }
+
+  fn run(&self, module: &mut Module) -> bool {
+    let mut changed = false;
+    for (index, function) in module.functions.iter_mut().enumerate() {
+      if run(function, index) {
+        changed = true;
+      }
+    }
+    changed
+  }
+}
+
+// eliminates the tail calls of the function with the index to itself
+pub fn run(function: &mut Function, index: usize) -> bool {
+  let tail_blocks: Vec<BlockId> = range(0, function.blocks.len())
+    .filter(|block| is_tail_call(&function.blocks[*block], index))
+    .collect();
+  if tail_blocks.is_empty() || !function.predecessors()[0].is_empty() {
+    return false;
+  }
+
+  // the entry block becomes the loop header
+  let header = function.blocks.len();
+  let entry = mem::replace(&mut function.blocks[0], BasicBlock {
+    instructions: vec![],
+    terminator: Terminator::Jump(header),
+  });
+  for successor in entry.terminator.successors().iter() {
+    function.replace_phi_predecessor(*successor, 0, header);
+  }
+  function.blocks.push(entry);
+  let tail_blocks: Vec<BlockId> = tail_blocks.into_iter()
+    .map(|block| if block == 0 { header } else { block })
+    .collect();
+
+  let mut replacements: Vec<Option<Operand>> = iter::repeat(None).take(function.registers.len()).collect();
+  let mut parameters = vec![];
+  for parameter in function.parameters.clone().into_iter() {
+    let parameter_type = function.registers[parameter];
+    let value = function.new_register(parameter_type);
+    replacements.push(None);
+    replacements[parameter] = Some(Operand::Register(value));
+    parameters.push((parameter, value));
+  }
+  function.replace_uses(&replacements);
+
+  // parameter -> operands of the phi
+  let mut incoming: Vec<Vec<(BlockId, Operand)>> = parameters.iter()
+    .map(|&(parameter, _)| vec![(0, Operand::Register(parameter))])
+    .collect();
+  for block in tail_blocks.iter() {
+    let arguments = match function.blocks[*block].instructions.pop() {
+      Some(Instruction::Call(_, _, arguments)) => arguments,
+      _ => panic!("Internal compiler error: tail call expected"),
+    };
+    for (operands, argument) in incoming.iter_mut().zip(arguments.into_iter()) {
+      operands.push((*block, argument));
+    }
+    function.blocks[*block].terminator = Terminator::Jump(header);
+  }
+
+  let phis: Vec<Instruction> = parameters.iter().zip(incoming.into_iter())
+    .map(|(&(_, value), mut operands)| {
+      operands.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+      Instruction::Phi(value, operands)
+    })
+    .collect();
+  let instructions = mem::replace(&mut function.blocks[header].instructions, phis);
+  function.blocks[header].instructions.extend(instructions.into_iter());
+  true
+}
+
+// whether the block ends by returning what a call of the function returns
+fn is_tail_call(block: &BasicBlock, index: usize) -> bool {
+  let call = match block.instructions.last() {
+    Some(&Instruction::Call(destination, callee, _)) if callee == index => destination,
+    _ => return false,
+  };
+  match (call, &block.terminator) {
+    (Some(destination), &Terminator::Return(Some(Operand::Register(value)))) => destination == value,
+    (None, &Terminator::Return(None)) => true,
+    _ => false,
+  }
+}
@@ -0,0 +1,44 @@
+use ir::*;
+use ir::optimizer::Pass;
+
+/*
+  Unreachable block elimination. Branches on a constant condition become
+  jumps to the block they always take, and the phis of the other block lose
+  the operand for the branch. Branches with the same block on both sides
+  become jumps as well. Blocks no longer reachable from the entry block are
+  then removed.
+*/
+
+pub struct UnreachableBlockElimination;
+
+impl Pass for UnreachableBlockElimination {
+  fn name(&self) -> &'static str {
+    "unreachable-blocks"
+  }
+
+  fn run(&self, function: &mut Function) -> bool {
+    run(function)
+  }
+}
+
+pub fn run(function: &mut Function) -> bool {
+  let mut changed = false;
+  for id in range(0, function.blocks.len()) {
+    let (taken, skipped) = match function.blocks[id].terminator {
+      Terminator::Branch(Operand::Boolean(true), if_true, if_false) => (if_true, Some(if_false)),
+      Terminator::Branch(Operand::Boolean(false), if_true, if_false) => (if_false, Some(if_true)),
+      Terminator::Branch(_, if_true, if_false) if if_true == if_false => (if_true, None),
+      _ => continue,
+    };
+    function.blocks[id].terminator = Terminator::Jump(taken);
+    match skipped {
+      Some(skipped) if skipped != taken => function.remove_phi_operands(skipped, id),
+      _ => { },
+    }
+    changed = true;
+  }
+
+  let block_count = function.blocks.len();
+  function.remove_unreachable_blocks();
+  changed || function.blocks.len() != block_count
+}
@@ -0,0 +1,342 @@
+use std::iter;
+use ast::BinaryOperator;
+use ir::*;
+use ir::loops;
+use ir::loops::Loop;
+use ir::optimizer::Pass;
+use ir::optimizer::constant_propagation::fold_binary;
+use ir::optimizer::inlining;
+
+/*
+  Full unrolling of loops with a small constant trip count. The loop has to
+  have the shape of a lowered for loop: the header compares an induction
+  variable with a constant and branches into the loop or out of it, and no
+  other block leaves the loop except by returning. The induction variable
+  is a phi in the header that starts from a constant and is incremented or
+  decremented by a constant on the single back edge, so the number of
+  iterations is found by stepping it through the comparison.
+
+  A loop running at most MAX_TRIP_COUNT times whose unrolled body costs at
+  most MAX_UNROLLED_COST is replaced by one copy of its blocks per
+  iteration. In the copies the phis of the header become copies of the
+  values of the previous iteration, the branch of the header becomes a
+  jump into the body, and the back edge a jump to the next copy. The last
+  copy jumps to the original header, which now only decides to leave the
+  loop; constant propagation folds the rest.
+*/
+
+static MAX_TRIP_COUNT: usize = 8;
+static MAX_UNROLLED_COST: usize = 120;
+
+pub struct LoopUnrolling;
+
+impl Pass for LoopUnrolling {
+  fn name(&self) -> &'static str {
+    "unrolling"
+  }
+
+  fn run(&self, function: &mut Function) -> bool {
+    run(function)
+  }
+}
+
+pub fn run(function: &mut Function) -> bool {
+  let mut changed = false;
+  // unrolling a loop changes the blocks of the loops around it
+  loop {
+    let mut unrolled = false;
+    for natural_loop in loops::find_loops(function).iter() {
+      let preheader = match loops::preheader(function, natural_loop) {
+        Some(preheader) if natural_loop.latches.len() == 1 => preheader,
+        _ => continue,
+      };
+      let (body, trips) = match trip_count(function, natural_loop, preheader) {
+        Some(found) => found,
+        None => continue,
+      };
+      if trips == 0 || trips * loop_cost(function, natural_loop) > MAX_UNROLLED_COST {
+        continue;
+      }
+      unroll(function, natural_loop, preheader, body, trips);
+      unrolled = true;
+      break;
+    }
+    if !unrolled {
+      return changed;
+    }
+    changed = true;
+  }
+}
+
+fn loop_cost(function: &Function, natural_loop: &Loop) -> usize {
+  natural_loop.blocks.iter()
+    .map(|block| inlining::block_cost(&function.blocks[*block]))
+    .fold(0, |total, cost| total + cost)
+}
+
+// the block the header branches to inside the loop and the number of times
+// the loop runs
+fn trip_count(function: &Function, natural_loop: &Loop, preheader: BlockId) -> Option<(BlockId, usize)> {
+  let header = &function.blocks[natural_loop.header];
+  let (condition, if_true, if_false) = match header.terminator {
+    Terminator::Branch(Operand::Register(condition), if_true, if_false) => (condition, if_true, if_false),
+    _ => return None,
+  };
+  let (body, stays_if) = match (natural_loop.contains(if_true), natural_loop.contains(if_false)) {
+    (true, false) => (if_true, true),
+    (false, true) => (if_false, false),
+    _ => return None,
+  };
+  for block in natural_loop.blocks.iter() {
+    if *block != natural_loop.header &&
+      function.blocks[*block].terminator.successors().iter().any(|successor| !natural_loop.contains(*successor)) {
+      return None;
+    }
+  }
+
+  // condition = variable op bound or bound op variable
+  let comparison = header.instructions.iter().filter_map(|instruction| match *instruction {
+    Instruction::Binary(destination, operator, ref left, ref right) if destination == condition => {
+      Some((operator, left.clone(), right.clone()))
+    },
+    _ => None,
+  }).next();
+  let (operator, left, right) = match comparison {
+    Some(comparison) => comparison,
+    None => return None,
+  };
+  let variable = match (&left, &right) {
+    (&Operand::Register(register), &Operand::Integer(..)) |
+    (&Operand::Integer(..), &Operand::Register(register)) => register,
+    _ => return None,
+  };
+
+  let latch = natural_loop.latches[0];
+  let (start, next) = match phi_operands(header, variable, preheader, latch) {
+    Some((Operand::Integer(start), Operand::Register(next))) => (start, next),
+    _ => return None,
+  };
+  let (step_operator, step) = match increment(function, natural_loop, variable, next) {
+    Some(increment) => increment,
+    None => return None,
+  };
+
+  let mut value = Operand::Integer(start);
+  let mut trips = 0;
+  loop {
+    let (a, b) = if left == Operand::Register(variable) { (&value, &right) } else { (&left, &value) };
+    match fold_binary(operator, a, b) {
+      Some(Operand::Boolean(result)) if result == stays_if => { },
+      Some(Operand::Boolean(..)) => return Some((body, trips)),
+      _ => return None,
+    }
+    trips += 1;
+    if trips > MAX_TRIP_COUNT {
+      return None;
+    }
+    value = match fold_binary(step_operator, &value, &Operand::Integer(step)) {
+      Some(next_value) => next_value,
+      None => return None,
+    };
+  }
+}
+
+// the operands of a phi of the header for the preheader and the latch
+fn phi_operands(header: &BasicBlock, register: Register, preheader: BlockId,
+  latch: BlockId) -> Option<(Operand, Operand)> {
+
+  for instruction in header.instructions.iter() {
+    match *instruction {
+      Instruction::Phi(destination, ref incoming) if destination == register && incoming.len() == 2 => {
+        let entering = incoming.iter().find(|&&(predecessor, _)| predecessor == preheader);
+        let repeating = incoming.iter().find(|&&(predecessor, _)| predecessor == latch);
+        return match (entering, repeating) {
+          (Some(&(_, ref entering)), Some(&(_, ref repeating))) => Some((entering.clone(), repeating.clone())),
+          _ => None,
+        };
+      },
+      Instruction::Phi(..) => { },
+      _ => return None,
+    }
+  }
+  None
+}
+
+// how next is computed from the register in the loop
+fn increment(function: &Function, natural_loop: &Loop, register: Register,
+  next: Register) -> Option<(BinaryOperator, i32)> {
+
+  for block in natural_loop.blocks.iter() {
+    for instruction in function.blocks[*block].instructions.iter() {
+      match *instruction {
+        Instruction::Binary(destination, operator, Operand::Register(source), Operand::Integer(step))
+          if destination == next && source == register &&
+            (operator == BinaryOperator::Plus || operator == BinaryOperator::Minus) => {
+          return Some((operator, step));
+        },
+        Instruction::Binary(destination, BinaryOperator::Plus, Operand::Integer(step), Operand::Register(source))
+          if destination == next && source == register => return Some((BinaryOperator::Plus, step)),
+        _ => { },
+      }
+    }
+  }
+  None
+}
+
+fn unroll(function: &mut Function, natural_loop: &Loop, preheader: BlockId, body: BlockId, trips: usize) {
+  let header = natural_loop.header;
+  let latch = natural_loop.latches[0];
+  // header phi -> operand from the latch, in the registers of the previous copy
+  let mut carried: Vec<(Register, Operand)> = vec![];
+  for instruction in function.blocks[header].instructions.iter() {
+    match *instruction {
+      Instruction::Phi(destination, ref incoming) => {
+        let entering = match incoming.iter().find(|&&(predecessor, _)| predecessor == preheader) {
+          Some(&(_, ref operand)) => operand.clone(),
+          None => panic!("Internal compiler error: phi has no operand for the preheader"),
+        };
+        carried.push((destination, entering));
+      },
+      _ => break,
+    }
+  }
+
+  let mut in_loop = iter::repeat(false).take(function.registers.len()).collect::<Vec<bool>>();
+  for block in natural_loop.blocks.iter() {
+    for instruction in function.blocks[*block].instructions.iter() {
+      match instruction.destination() {
+        Some(register) => in_loop[register] = true,
+        None => { },
+      }
+    }
+  }
+
+  let header_position = natural_loop.blocks.iter().position(|block| *block == header).unwrap();
+  let latch_position = natural_loop.blocks.iter().position(|block| *block == latch).unwrap();
+  let first_copy = function.blocks.len() + header_position;
+  function.blocks[preheader].terminator.replace_successor(header, first_copy);
+  let mut last_latch = latch;
+  for iteration in range(0, trips) {
+    // loop block -> its copy, loop register -> its copy
+    let base = function.blocks.len();
+    let copies: Vec<Option<BlockId>> = range(0, base).map(|block| {
+      natural_loop.blocks.iter().position(|other| *other == block).map(|position| base + position)
+    }).collect();
+    let mut registers: Vec<Operand> = vec![];
+    for register in range(0, in_loop.len()) {
+      registers.push(if in_loop[register] {
+        let register_type = function.registers[register];
+        Operand::Register(function.new_register(register_type))
+      } else {
+        Operand::Register(register)
+      });
+    }
+    // registers of earlier copies keep their numbers
+    let map = |operand: &mut Operand| {
+      let mapped = match *operand {
+        Operand::Register(register) if register < registers.len() => registers[register].clone(),
+        _ => return,
+      };
+      *operand = mapped;
+    };
+    // the back edge leads to the next copy, from the last one to the header
+    let next = if iteration + 1 == trips { header } else { base + natural_loop.blocks.len() + header_position };
+
+    for block in natural_loop.blocks.iter() {
+      let mut copy = function.blocks[*block].clone();
+      let mut instructions = vec![];
+      for instruction in copy.instructions.into_iter() {
+        let mut instruction = match instruction {
+          // the header is entered from one place in every copy
+          Instruction::Phi(destination, _) if *block == header => {
+            let value = match carried.iter().find(|&&(register, _)| register == destination) {
+              Some(&(_, ref value)) => value.clone(),
+              None => panic!("Internal compiler error: phi of the header not carried"),
+            };
+            Instruction::Copy(destination, value)
+          },
+          Instruction::Phi(destination, incoming) => {
+            let incoming = incoming.into_iter()
+              .map(|(predecessor, operand)| (copies[predecessor].unwrap(), operand))
+              .collect();
+            Instruction::Phi(destination, incoming)
+          },
+          instruction => instruction,
+        };
+        for operand in instruction.operands_mut().into_iter() {
+          map(operand);
+        }
+        match instruction.destination_mut() {
+          Some(register) => *register = match registers[*register] {
+            Operand::Register(mapped) => mapped,
+            _ => panic!("Internal compiler error: loop register mapped to a constant"),
+          },
+          None => { },
+        }
+        instructions.push(instruction);
+      }
+      copy.instructions = instructions;
+      for operand in copy.terminator.operands_mut().into_iter() {
+        map(operand);
+      }
+      copy.terminator = if *block == header {
+        Terminator::Jump(copies[body].unwrap())
+      } else {
+        match copy.terminator {
+          Terminator::Jump(successor) => Terminator::Jump(follow(&copies, header, successor, next)),
+          Terminator::Branch(condition, if_true, if_false) => Terminator::Branch(condition,
+            follow(&copies, header, if_true, next), follow(&copies, header, if_false, next)),
+          terminator => terminator,
+        }
+      };
+      function.blocks.push(copy);
+    }
+
+    // the next iteration starts from the values the latch passes back
+    for &mut (destination, ref mut value) in carried.iter_mut() {
+      let mut from_latch = match function.blocks[header].instructions.iter()
+        .find(|instruction| instruction.destination() == Some(destination)) {
+        Some(&Instruction::Phi(_, ref incoming)) => {
+          match incoming.iter().find(|&&(predecessor, _)| predecessor == latch) {
+            Some(&(_, ref operand)) => operand.clone(),
+            None => panic!("Internal compiler error: phi has no operand for the latch"),
+          }
+        },
+        _ => panic!("Internal compiler error: phi of the header expected"),
+      };
+      map(&mut from_latch);
+      *value = from_latch;
+    }
+    last_latch = base + latch_position;
+  }
+
+  // the original header is entered once more from the last copy of the latch,
+  // and leaves the loop
+  for instruction in function.blocks[header].instructions.iter_mut() {
+    match *instruction {
+      Instruction::Phi(destination, ref mut incoming) => {
+        let value = match carried.iter().find(|&&(register, _)| register == destination) {
+          Some(&(_, ref value)) => value.clone(),
+          None => panic!("Internal compiler error: phi of the header not carried"),
+        };
+        *incoming = vec![(last_latch, value)];
+      },
+      _ => break,
+    }
+  }
+  let exit = match function.blocks[header].terminator {
+    Terminator::Branch(_, if_true, if_false) => if if_true == body { if_false } else { if_true },
+    _ => panic!("Internal compiler error: branch expected at the end of the loop header"),
+  };
+  function.blocks[header].terminator = Terminator::Jump(exit);
+  function.remove_unreachable_blocks();
+}
+
+// the copy of a successor, or where the back edge leads
+fn follow(copies: &Vec<Option<BlockId>>, header: BlockId, successor: BlockId, next: BlockId) -> BlockId {
+  if successor == header {
+    next
+  } else {
+    copies[successor].unwrap()
+  }
+}
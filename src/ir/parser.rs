@@ -0,0 +1,804 @@
+use std::collections::HashMap;
+use std::mem;
+use std::num;
+use std::char;
+use std::f32;
+use std::f64;
+use ast::BinaryOperator;
+use ast::Position;
+use ast::Type;
+use ir::*;
+
+/*
+  Parses the textual form written by printer.rs, so that tests can be
+  written directly in the intermediate representation. Every line holds one
+  global, function header, block label, instruction or closing brace; '//'
+  starts a comment.
+
+  Besides the syntax the parser checks that blocks are numbered bb0, bb1...
+  without gaps and end in a terminator, that branch targets, called
+  functions, globals and the predecessors named by phis exist and that
+  every register is defined with a single type. Register numbers that are
+  never defined get the type void. A module without a <globals> function
+  gets an empty one.
+*/
+
+pub fn parse(text: &str) -> Result<Module, Vec<String>> {
+  let mut parser = Parser::new(text);
+  parser.parse()
+}
+
+#[derive(Show, Clone, PartialEq)]
+enum Token {
+  Register(Register),
+  // @name of a function or global
+  Name(String),
+  Text(String),
+  // any other sequence of characters: keywords, types, numbers and labels
+  Word(String),
+  Symbol(char),
+}
+
+struct Lexeme {
+  token: Token,
+  column: i32,
+  // column following the token
+  end: i32,
+}
+
+struct Line {
+  number: i32,
+  tokens: Vec<Lexeme>,
+}
+
+// state of the function being parsed
+struct FunctionState {
+  function: Function,
+  register_types: HashMap<Register, Type>,
+  // register -> position of the first use
+  used_registers: HashMap<Register, Position>,
+  blocks: HashMap<BlockId, BasicBlock>,
+  // block whose instructions are being read
+  block: Option<(BlockId, Vec<Instruction>)>,
+  // referenced block -> position of the first reference
+  targets: HashMap<BlockId, Position>,
+}
+
+struct Parser {
+  lines: Vec<Line>,
+  errors: Vec<String>,
+  // name -> index, parameter count
+  functions: HashMap<String, (usize, usize)>,
+  globals: HashMap<String, usize>,
+  // line being parsed
+  line: i32,
+  tokens: Vec<Lexeme>,
+  next: usize,
+}
+
+impl Parser {
+  fn new(text: &str) -> Parser {
+    let mut parser = Parser {
+      lines: vec![],
+      errors: vec![],
+      functions: HashMap::new(),
+      globals: HashMap::new(),
+      line: 0,
+      tokens: vec![],
+      next: 0,
+    };
+
+    for (index, line) in text.lines().enumerate() {
+      let number = index as i32 + 1;
+      match tokenize_line(line) {
+        Ok(tokens) => {
+          if !tokens.is_empty() {
+            parser.lines.push(Line { number: number, tokens: tokens });
+          }
+        },
+        Err((column, message)) => parser.errors.push(format!("Error at {}:{}: {}", number, column, message)),
+      }
+    }
+    parser
+  }
+
+  fn parse(&mut self) -> Result<Module, Vec<String>> {
+    let lines = mem::replace(&mut self.lines, vec![]);
+    self.declare_names(&lines);
+
+    let mut module = Module { globals: vec![], functions: vec![], init: 0, main: None };
+    let mut state: Option<FunctionState> = None;
+    for line in lines.into_iter() {
+      self.line = line.number;
+      self.tokens = line.tokens;
+      self.next = 0;
+
+      let result = match state.take() {
+        Some(mut current) => {
+          if self.peek() == Some(Token::Symbol('}')) {
+            self.next += 1;
+            let function = self.finish_function(current);
+            module.functions.push(function);
+            Ok(())
+          } else {
+            let result = self.parse_function_line(&mut current);
+            state = Some(current);
+            result
+          }
+        },
+        None => {
+          if self.peek() == Some(Token::Word("global".to_string())) {
+            self.parse_global().map(|global| module.globals.push(global))
+          } else if self.peek() == Some(Token::Word("fn".to_string())) {
+            match self.parse_function_header() {
+              Ok(current) => {
+                state = Some(current);
+                Ok(())
+              },
+              Err(message) => Err(message),
+            }
+          } else {
+            Err(self.error("Expected global or function"))
+          }
+        },
+      };
+
+      match result.and_then(|()| self.expect_end()) {
+        Ok(()) => { },
+        Err(message) => self.errors.push(message),
+      }
+    }
+
+    match state {
+      Some(current) => {
+        let message = format!("Error at {}:1: Function '@{}' is not closed with '}}'", self.line + 1,
+          current.function.name);
+        self.errors.push(message);
+      },
+      None => { },
+    }
+
+    if !self.errors.is_empty() {
+      return Err(self.errors.clone());
+    }
+
+    module.init = match module.function_index(INIT_FUNCTION_NAME) {
+      Some(index) => index,
+      None => {
+        module.functions.push(Function {
+          name: INIT_FUNCTION_NAME.to_string(),
+          parameters: vec![],
+          return_type: Type::Void,
+          registers: vec![],
+          blocks: vec![BasicBlock { instructions: vec![], terminator: Terminator::Return(None) }],
+        });
+        module.functions.len() - 1
+      },
+    };
+    module.main = module.function_index("main");
+    Ok(module)
+  }
+
+  // names of all globals and functions, so they can be used before they are defined
+  fn declare_names(&mut self, lines: &Vec<Line>) {
+    let mut function_count = 0;
+    for line in lines.iter() {
+      let keyword = line.tokens[0].token.clone();
+      let (name, column) = match line.tokens.get(1) {
+        Some(&Lexeme { token: Token::Name(ref name), column, .. }) => (name.clone(), column),
+        _ => continue,
+      };
+
+      if keyword == Token::Word("global".to_string()) {
+        if self.globals.contains_key(&name) {
+          self.errors.push(format!("Error at {}:{}: Global '@{}' is already defined", line.number,
+            column, name));
+        } else {
+          let index = self.globals.len();
+          self.globals.insert(name, index);
+        }
+      } else if keyword == Token::Word("fn".to_string()) {
+        if self.functions.contains_key(&name) {
+          self.errors.push(format!("Error at {}:{}: Function '@{}' is already defined", line.number,
+            column, name));
+        } else {
+          let parameter_count = line.tokens.iter()
+            .filter(|lexeme| match lexeme.token { Token::Register(..) => true, _ => false })
+            .count();
+          self.functions.insert(name, (function_count, parameter_count));
+          function_count += 1;
+        }
+      }
+    }
+  }
+
+  fn parse_global(&mut self) -> Result<Global, String> {
+    try!(self.expect_word("global"));
+    let name = try!(self.expect_name());
+    try!(self.expect_symbol(':'));
+    let global_type = try!(self.expect_type());
+    Ok(Global { name: name, global_type: global_type })
+  }
+
+  fn parse_function_header(&mut self) -> Result<FunctionState, String> {
+    try!(self.expect_word("fn"));
+    let name = try!(self.expect_name());
+    let mut state = FunctionState {
+      function: Function {
+        name: name,
+        parameters: vec![],
+        return_type: Type::Void,
+        registers: vec![],
+        blocks: vec![],
+      },
+      register_types: HashMap::new(),
+      used_registers: HashMap::new(),
+      blocks: HashMap::new(),
+      block: None,
+      targets: HashMap::new(),
+    };
+
+    try!(self.expect_symbol('('));
+    if self.peek() != Some(Token::Symbol(')')) {
+      loop {
+        let register = try!(self.parse_definition(&mut state));
+        state.function.parameters.push(register);
+        if self.peek() != Some(Token::Symbol(',')) {
+          break;
+        }
+        self.next += 1;
+      }
+    }
+    try!(self.expect_symbol(')'));
+    try!(self.expect_symbol(':'));
+    state.function.return_type = try!(self.expect_type());
+    try!(self.expect_symbol('{'));
+    Ok(state)
+  }
+
+  fn parse_function_line(&mut self, state: &mut FunctionState) -> Result<(), String> {
+    // block label
+    match self.peek() {
+      Some(Token::Word(ref word)) if word.as_slice().starts_with("bb") && self.is_label_line() => {
+        let pos = self.position();
+        let id = try!(self.expect_label());
+        try!(self.expect_symbol(':'));
+        match state.block {
+          Some((previous, _)) => {
+            return Err(format!("Error at {}: Block bb{} has no terminator", pos, previous));
+          },
+          None => { },
+        }
+        if state.blocks.contains_key(&id) {
+          return Err(format!("Error at {}: Block bb{} is already defined", pos, id));
+        }
+        state.block = Some((id, vec![]));
+        return Ok(());
+      },
+      _ => { },
+    }
+
+    if state.block.is_none() {
+      return Err(self.error("Expected block label"));
+    }
+
+    match self.peek() {
+      Some(Token::Word(ref word)) if is_terminator(word.as_slice()) => {
+        let terminator = try!(self.parse_terminator(state));
+        let (id, instructions) = state.block.take().unwrap();
+        state.blocks.insert(id, BasicBlock { instructions: instructions, terminator: terminator });
+      },
+      _ => {
+        let instruction = try!(self.parse_instruction(state));
+        match state.block {
+          Some((_, ref mut instructions)) => instructions.push(instruction),
+          None => { },
+        }
+      },
+    }
+    Ok(())
+  }
+
+  fn parse_instruction(&mut self, state: &mut FunctionState) -> Result<Instruction, String> {
+    let destination = match self.peek() {
+      Some(Token::Register(..)) => {
+        let register = try!(self.parse_definition(state));
+        try!(self.expect_symbol('='));
+        Some(register)
+      },
+      _ => None,
+    };
+
+    let pos = self.position();
+    let mnemonic = match self.advance() {
+      Some(Token::Word(word)) => word,
+      _ => return Err(format!("Error at {}: Expected instruction", pos)),
+    };
+
+    let instruction = match (mnemonic.as_slice(), destination) {
+      ("copy", Some(dest)) => Instruction::Copy(dest, try!(self.parse_operand(state))),
+      ("convert", Some(dest)) => Instruction::Convert(dest, try!(self.parse_operand(state))),
+      ("load", Some(dest)) => Instruction::LoadGlobal(dest, try!(self.expect_global())),
+      ("call", _) => {
+        let (index, parameter_count) = try!(self.expect_function());
+        try!(self.expect_symbol('('));
+        let mut arguments = vec![];
+        if self.peek() != Some(Token::Symbol(')')) {
+          loop {
+            arguments.push(try!(self.parse_operand(state)));
+            if self.peek() != Some(Token::Symbol(',')) {
+              break;
+            }
+            self.next += 1;
+          }
+        }
+        try!(self.expect_symbol(')'));
+        if arguments.len() != parameter_count {
+          return Err(format!("Error at {}: Call passes {} argument(s), but the function has {} parameter(s)",
+            pos, arguments.len(), parameter_count));
+        }
+        Instruction::Call(destination, index, arguments)
+      },
+      ("phi", Some(dest)) => {
+        let mut incoming = vec![];
+        loop {
+          try!(self.expect_symbol('['));
+          let predecessor = try!(self.parse_target(state));
+          try!(self.expect_symbol(':'));
+          incoming.push((predecessor, try!(self.parse_operand(state))));
+          try!(self.expect_symbol(']'));
+          if self.peek() != Some(Token::Symbol(',')) {
+            break;
+          }
+          self.next += 1;
+        }
+        Instruction::Phi(dest, incoming)
+      },
+      ("print", None) => Instruction::Print(try!(self.parse_operand(state))),
+      ("store", None) => {
+        let index = try!(self.expect_global());
+        try!(self.expect_symbol(','));
+        Instruction::StoreGlobal(index, try!(self.parse_operand(state)))
+      },
+      (mnemonic, Some(dest)) => {
+        let operator = match operator_from_mnemonic(mnemonic) {
+          Some(operator) => operator,
+          None => return Err(format!("Error at {}: Unknown instruction '{}'", pos, mnemonic)),
+        };
+        let left = try!(self.parse_operand(state));
+        try!(self.expect_symbol(','));
+        let right = try!(self.parse_operand(state));
+        if self.peek() == Some(Token::Word("at".to_string())) && operator == BinaryOperator::Divide {
+          self.next += 1;
+          let line = try!(self.expect_number());
+          try!(self.expect_symbol(':'));
+          let column = try!(self.expect_number());
+          Instruction::Divide(dest, left, right, Position::new(line, column))
+        } else {
+          Instruction::Binary(dest, operator, left, right)
+        }
+      },
+      ("copy", None) | ("convert", None) | ("load", None) | ("phi", None) | ("add", None) |
+      ("sub", None) | ("mul", None) | ("div", None) | ("eq", None) | ("lt", None) | ("gt", None) |
+      ("le", None) | ("ge", None) => {
+        return Err(format!("Error at {}: Instruction '{}' needs a destination register", pos, mnemonic));
+      },
+      (mnemonic, None) => {
+        return Err(format!("Error at {}: Unknown instruction '{}'", pos, mnemonic));
+      },
+    };
+    Ok(instruction)
+  }
+
+  fn parse_terminator(&mut self, state: &mut FunctionState) -> Result<Terminator, String> {
+    let pos = self.position();
+    let mnemonic = match self.advance() {
+      Some(Token::Word(word)) => word,
+      _ => return Err(format!("Error at {}: Expected terminator", pos)),
+    };
+
+    let terminator = match mnemonic.as_slice() {
+      "jmp" => Terminator::Jump(try!(self.parse_target(state))),
+      "br" => {
+        let condition = try!(self.parse_operand(state));
+        try!(self.expect_symbol(','));
+        let if_true = try!(self.parse_target(state));
+        try!(self.expect_symbol(','));
+        let if_false = try!(self.parse_target(state));
+        Terminator::Branch(condition, if_true, if_false)
+      },
+      "ret" => {
+        if self.next < self.tokens.len() {
+          Terminator::Return(Some(try!(self.parse_operand(state))))
+        } else {
+          Terminator::Return(None)
+        }
+      },
+      "unreachable" => Terminator::Unreachable,
+      _ => return Err(format!("Error at {}: Unknown terminator '{}'", pos, mnemonic)),
+    };
+    Ok(terminator)
+  }
+
+  fn parse_target(&mut self, state: &mut FunctionState) -> Result<BlockId, String> {
+    let pos = self.position();
+    let id = try!(self.expect_label());
+    if !state.targets.contains_key(&id) {
+      state.targets.insert(id, pos);
+    }
+    Ok(id)
+  }
+
+  // %N: type
+  fn parse_definition(&mut self, state: &mut FunctionState) -> Result<Register, String> {
+    let pos = self.position();
+    let register = match self.advance() {
+      Some(Token::Register(register)) => register,
+      _ => return Err(format!("Error at {}: Expected register", pos)),
+    };
+    try!(self.expect_symbol(':'));
+    let register_type = try!(self.expect_type());
+
+    match state.register_types.get(&register) {
+      Some(previous) => {
+        if *previous != register_type {
+          return Err(format!("Error at {}: Register %{} is defined as {}, but was defined as {} before",
+            pos, register, register_type, previous));
+        }
+      },
+      None => { },
+    }
+    state.register_types.insert(register, register_type);
+    Ok(register)
+  }
+
+  fn parse_operand(&mut self, state: &mut FunctionState) -> Result<Operand, String> {
+    let pos = self.position();
+    match self.advance() {
+      Some(Token::Register(register)) => {
+        if !state.used_registers.contains_key(&register) {
+          state.used_registers.insert(register, pos);
+        }
+        Ok(Operand::Register(register))
+      },
+      Some(Token::Text(text)) => Ok(Operand::Text(text)),
+      Some(Token::Word(word)) => match parse_constant(word.as_slice()) {
+        Some(operand) => Ok(operand),
+        None => Err(format!("Error at {}: Invalid operand '{}'", pos, word)),
+      },
+      _ => Err(format!("Error at {}: Expected operand", pos)),
+    }
+  }
+
+  fn finish_function(&mut self, state: FunctionState) -> Function {
+    let mut function = state.function;
+    let pos = Position::new(self.line, 1);
+    match state.block {
+      Some((id, _)) => self.errors.push(format!("Error at {}: Block bb{} has no terminator", pos, id)),
+      None => { },
+    }
+
+    let block_count = state.blocks.len();
+    if block_count == 0 {
+      self.errors.push(format!("Error at {}: Function '@{}' has no blocks", pos, function.name));
+    }
+    for id in range(0, block_count) {
+      match state.blocks.get(&id) {
+        Some(block) => function.blocks.push(block.clone()),
+        None => self.errors.push(format!("Error at {}: Blocks of function '@{}' are not numbered bb0 to bb{}",
+          pos, function.name, block_count - 1)),
+      }
+    }
+
+    let mut targets: Vec<(BlockId, Position)> = state.targets.iter().map(|(id, pos)| (*id, *pos)).collect();
+    targets.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+    for &(target, target_pos) in targets.iter() {
+      if target >= block_count {
+        self.errors.push(format!("Error at {}: Block bb{} does not exist", target_pos, target));
+      }
+    }
+
+    let mut uses: Vec<(Register, Position)> = state.used_registers.iter().map(|(register, pos)| (*register, *pos)).collect();
+    uses.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
+    for &(register, use_pos) in uses.iter() {
+      if !state.register_types.contains_key(&register) {
+        self.errors.push(format!("Error at {}: Register %{} is never defined", use_pos, register));
+      }
+    }
+
+    let register_count = match state.register_types.keys().max() {
+      Some(max) => *max + 1,
+      None => 0,
+    };
+    for register in range(0, register_count) {
+      function.registers.push(match state.register_types.get(&register) {
+        Some(register_type) => *register_type,
+        None => Type::Void,
+      });
+    }
+    function
+  }
+
+  fn peek(&self) -> Option<Token> {
+    self.tokens.get(self.next).map(|lexeme| lexeme.token.clone())
+  }
+
+  fn advance(&mut self) -> Option<Token> {
+    let token = self.peek();
+    if token.is_some() {
+      self.next += 1;
+    }
+    token
+  }
+
+  // position of the next token, or of the end of the line
+  fn position(&self) -> Position {
+    match self.tokens.get(self.next) {
+      Some(lexeme) => Position::new(self.line, lexeme.column),
+      None => {
+        let column = match self.tokens.last() {
+          Some(lexeme) => lexeme.end,
+          None => 1,
+        };
+        Position::new(self.line, column)
+      },
+    }
+  }
+
+  // the line starts with 'bbN:'
+  fn is_label_line(&self) -> bool {
+    self.next == 0 && self.tokens.len() >= 2 && self.tokens[1].token == Token::Symbol(':')
+  }
+
+  fn error(&self, message: &str) -> String {
+    format!("Error at {}: {}", self.position(), message)
+  }
+
+  fn expect_end(&self) -> Result<(), String> {
+    if self.next < self.tokens.len() {
+      Err(self.error("Unexpected input at end of line"))
+    } else {
+      Ok(())
+    }
+  }
+
+  fn expect_word(&mut self, expected: &str) -> Result<(), String> {
+    match self.peek() {
+      Some(Token::Word(ref word)) if word.as_slice() == expected => {
+        self.next += 1;
+        Ok(())
+      },
+      _ => Err(self.error(format!("Expected '{}'", expected).as_slice())),
+    }
+  }
+
+  fn expect_symbol(&mut self, expected: char) -> Result<(), String> {
+    if self.peek() == Some(Token::Symbol(expected)) {
+      self.next += 1;
+      Ok(())
+    } else {
+      Err(self.error(format!("Expected '{}'", expected).as_slice()))
+    }
+  }
+
+  fn expect_name(&mut self) -> Result<String, String> {
+    match self.peek() {
+      Some(Token::Name(name)) => {
+        self.next += 1;
+        Ok(name)
+      },
+      _ => Err(self.error("Expected '@' name")),
+    }
+  }
+
+  fn expect_global(&mut self) -> Result<usize, String> {
+    let pos = self.position();
+    let name = try!(self.expect_name());
+    match self.globals.get(&name) {
+      Some(index) => Ok(*index),
+      None => Err(format!("Error at {}: Global '@{}' is not defined", pos, name)),
+    }
+  }
+
+  // index and parameter count
+  fn expect_function(&mut self) -> Result<(usize, usize), String> {
+    let pos = self.position();
+    let name = try!(self.expect_name());
+    match self.functions.get(&name) {
+      Some(function) => Ok(*function),
+      None => Err(format!("Error at {}: Function '@{}' is not defined", pos, name)),
+    }
+  }
+
+  fn expect_type(&mut self) -> Result<Type, String> {
+    let register_type = match self.peek() {
+      Some(Token::Word(ref word)) => match word.as_slice() {
+        "int" => Some(Type::Integer),
+        "float" => Some(Type::Float),
+        "double" => Some(Type::Double),
+        "bool" => Some(Type::Boolean),
+        "string" => Some(Type::String),
+        "void" => Some(Type::Void),
+        _ => None,
+      },
+      _ => None,
+    };
+
+    match register_type {
+      Some(register_type) => {
+        self.next += 1;
+        Ok(register_type)
+      },
+      None => Err(self.error("Expected type")),
+    }
+  }
+
+  fn expect_label(&mut self) -> Result<BlockId, String> {
+    let id = match self.peek() {
+      Some(Token::Word(ref word)) if word.as_slice().starts_with("bb") => word.as_slice().slice_from(2).parse(),
+      _ => None,
+    };
+
+    match id {
+      Some(id) => {
+        self.next += 1;
+        Ok(id)
+      },
+      None => Err(self.error("Expected block label")),
+    }
+  }
+
+  fn expect_number(&mut self) -> Result<i32, String> {
+    let number = match self.peek() {
+      Some(Token::Word(ref word)) => word.as_slice().parse(),
+      _ => None,
+    };
+
+    match number {
+      Some(number) => {
+        self.next += 1;
+        Ok(number)
+      },
+      None => Err(self.error("Expected number")),
+    }
+  }
+}
+
+fn is_terminator(word: &str) -> bool {
+  word == "jmp" || word == "br" || word == "ret" || word == "unreachable"
+}
+
+fn operator_from_mnemonic(mnemonic: &str) -> Option<BinaryOperator> {
+  match mnemonic {
+    "add" => Some(BinaryOperator::Plus),
+    "sub" => Some(BinaryOperator::Minus),
+    "mul" => Some(BinaryOperator::Multiply),
+    "div" => Some(BinaryOperator::Divide),
+    "eq" => Some(BinaryOperator::Equals),
+    "lt" => Some(BinaryOperator::Lesser),
+    "gt" => Some(BinaryOperator::Greater),
+    "le" => Some(BinaryOperator::LesserOrEq),
+    "ge" => Some(BinaryOperator::GreaterOrEq),
+    _ => None,
+  }
+}
+
+fn parse_constant(word: &str) -> Option<Operand> {
+  match word {
+    "true" => return Some(Operand::Boolean(true)),
+    "false" => return Some(Operand::Boolean(false)),
+    _ => { },
+  }
+
+  // 'inf' is a double, 'inff' a float
+  if word.ends_with("f") {
+    let text = word.slice_to(word.len() - 1);
+    let value = match text {
+      "inf" => Some(f32::INFINITY),
+      "-inf" => Some(f32::NEG_INFINITY),
+      "NaN" => Some(f32::NAN),
+      _ => if is_number(text) { text.parse() } else { None },
+    };
+    match value {
+      Some(value) => return Some(Operand::Float(value)),
+      None => { },
+    }
+  }
+
+  let value = match word {
+    "inf" => Some(f64::INFINITY),
+    "-inf" => Some(f64::NEG_INFINITY),
+    "NaN" => Some(f64::NAN),
+    _ if word.contains(".") || word.contains("e") => if is_number(word) { word.parse() } else { None },
+    _ => return word.parse().map(|value| Operand::Integer(value)),
+  };
+  value.map(|value| Operand::Double(value))
+}
+
+fn is_number(text: &str) -> bool {
+  text.chars().all(|ch| ch.is_digit(10) || ch == '-' || ch == '+' || ch == '.' || ch == 'e') &&
+    text.chars().any(|ch| ch.is_digit(10))
+}
+
+// splits a line into tokens with their columns. Returns the column and
+// message of the first error
+fn tokenize_line(line: &str) -> Result<Vec<Lexeme>, (i32, String)> {
+  let chars: Vec<char> = line.chars().collect();
+  let mut tokens = vec![];
+  let mut index = 0;
+  while index < chars.len() {
+    let ch = chars[index];
+    let column = index as i32 + 1;
+    if ch == ' ' || ch == '\t' || ch == '\r' {
+      index += 1;
+    } else if ch == '/' && index + 1 < chars.len() && chars[index + 1] == '/' {
+      break;
+    } else if is_symbol(ch) {
+      tokens.push(Lexeme { token: Token::Symbol(ch), column: column, end: column + 1 });
+      index += 1;
+    } else if ch == '"' {
+      let mut text = String::new();
+      index += 1;
+      loop {
+        if index >= chars.len() {
+          return Err((column, "Unterminated string".to_string()));
+        }
+        let ch = chars[index];
+        index += 1;
+        if ch == '"' {
+          break;
+        } else if ch != '\\' {
+          text.push(ch);
+          continue;
+        }
+
+        let escape_column = index as i32;
+        let escaped = match chars.get(index) {
+          Some(&'n') => Some('\n'),
+          Some(&'t') => Some('\t'),
+          Some(&'\\') => Some('\\'),
+          Some(&'"') => Some('"'),
+          Some(&'x') if index + 2 < chars.len() => {
+            let digits: String = chars.slice(index + 1, index + 3).iter().map(|ch| *ch).collect();
+            index += 2;
+            num::from_str_radix::<u32>(digits.as_slice(), 16).and_then(|code| char::from_u32(code))
+          },
+          _ => None,
+        };
+        match escaped {
+          Some(escaped) => text.push(escaped),
+          None => return Err((escape_column, "Invalid escape sequence".to_string())),
+        }
+        index += 1;
+      }
+      tokens.push(Lexeme { token: Token::Text(text), column: column, end: index as i32 + 1 });
+    } else {
+      let start = index;
+      while index < chars.len() && !is_symbol(chars[index]) && chars[index] != ' ' &&
+        chars[index] != '\t' && chars[index] != '"' && chars[index] != '\r' {
+        index += 1;
+      }
+      let word: String = chars.slice(start, index).iter().map(|ch| *ch).collect();
+      let token = if ch == '%' {
+        match word.as_slice().slice_from(1).parse() {
+          Some(register) => Token::Register(register),
+          None => return Err((column, format!("Invalid register '{}'", word))),
+        }
+      } else if ch == '@' {
+        if word.len() == 1 {
+          return Err((column, "Expected name after '@'".to_string()));
+        }
+        Token::Name(word.as_slice().slice_from(1).to_string())
+      } else {
+        Token::Word(word)
+      };
+      tokens.push(Lexeme { token: token, column: column, end: index as i32 + 1 });
+    }
+  }
+  Ok(tokens)
+}
+
+fn is_symbol(ch: char) -> bool {
+  ch == '(' || ch == ')' || ch == ',' || ch == ':' || ch == '=' || ch == '{' || ch == '}' ||
+    ch == '[' || ch == ']'
+}
@@ -0,0 +1,167 @@
+use std::fmt;
+use ast::BinaryOperator;
+use ir::*;
+
+/*
+  Textual form of the intermediate representation, read back by parser.rs.
+
+    global @calls: int
+
+    fn @fib(%0: int) : int {
+    bb0:
+      %1: bool = lt %0, 2
+      br %1, bb1, bb2
+    bb1:
+      ret %0
+    bb2:
+      %2: int = sub %0, 1
+      %3: int = call @fib(%2)
+      ...
+    }
+
+  The definition of a register names its type. Operands are registers or
+  constants: ints, floats with an 'f' suffix, doubles that always contain a
+  '.' or an exponent, true, false and double quoted strings with the escapes
+  \n, \t, \\, \" and \xHH.
+
+  Instructions:
+    %d: T = copy a          %d: T = convert a
+    %d: T = add a, b        (sub, mul, div, eq, lt, gt, le, ge)
+    %d: int = div a, b at L:P           integer division reporting division
+                                        by zero at line L, column P
+    %d: T = call @f(a, ...)             call @f(a, ...) for void functions
+    print a
+    %d: T = load @g         store @g, a
+    %d: T = phi [bbP: a], [bbQ: b], ...   value a when coming from bbP...
+  Terminators:
+    jmp bbN   br c, bbT, bbF   ret a   ret   unreachable
+*/
+
+pub fn print(module: &Module) -> String {
+  let mut output = String::new();
+  for global in module.globals.iter() {
+    output.push_str(format!("global @{}: {}\n", global.name, global.global_type).as_slice());
+  }
+
+  for function in module.functions.iter() {
+    if !output.is_empty() {
+      output.push('\n');
+    }
+    output.push_str(print_function(module, function).as_slice());
+  }
+  output
+}
+
+pub fn print_function(module: &Module, function: &Function) -> String {
+  let parameters: Vec<String> = function.parameters.iter()
+    .map(|parameter| format!("%{}: {}", parameter, function.registers[*parameter]))
+    .collect();
+  let mut output = format!("fn @{}({}) : {} {{\n", function.name, parameters.connect(", "),
+    function.return_type);
+
+  for (id, block) in function.blocks.iter().enumerate() {
+    output.push_str(format!("bb{}:\n", id).as_slice());
+    for instruction in block.instructions.iter() {
+      output.push_str(format!("  {}\n", print_instruction(module, function, instruction)).as_slice());
+    }
+    output.push_str(format!("  {}\n", block.terminator).as_slice());
+  }
+  output.push_str("}\n");
+  output
+}
+
+pub fn print_instruction(module: &Module, function: &Function, instruction: &Instruction) -> String {
+  let definition = match instruction.destination() {
+    Some(register) => format!("%{}: {} = ", register, function.registers[register]),
+    None => String::new(),
+  };
+
+  let operation = match *instruction {
+    Instruction::Copy(_, ref operand) => format!("copy {}", operand),
+    Instruction::Binary(_, operator, ref left, ref right) => {
+      format!("{} {}, {}", operator_mnemonic(operator), left, right)
+    },
+    Instruction::Divide(_, ref left, ref right, pos) => format!("div {}, {} at {}", left, right, pos),
+    Instruction::Convert(_, ref operand) => format!("convert {}", operand),
+    Instruction::Call(_, index, ref arguments) => {
+      let arguments: Vec<String> = arguments.iter().map(|argument| format!("{}", argument)).collect();
+      format!("call @{}({})", module.functions[index].name, arguments.connect(", "))
+    },
+    Instruction::Print(ref operand) => format!("print {}", operand),
+    Instruction::LoadGlobal(_, index) => format!("load @{}", module.globals[index].name),
+    Instruction::StoreGlobal(index, ref operand) => format!("store @{}, {}", module.globals[index].name, operand),
+    Instruction::Phi(_, ref incoming) => {
+      let incoming: Vec<String> = incoming.iter()
+        .map(|&(predecessor, ref operand)| format!("[bb{}: {}]", predecessor, operand))
+        .collect();
+      format!("phi {}", incoming.connect(", "))
+    },
+  };
+  format!("{}{}", definition, operation)
+}
+
+pub fn operator_mnemonic(operator: BinaryOperator) -> &'static str {
+  match operator {
+    BinaryOperator::Plus => "add",
+    BinaryOperator::Minus => "sub",
+    BinaryOperator::Multiply => "mul",
+    BinaryOperator::Divide => "div",
+    BinaryOperator::Equals => "eq",
+    BinaryOperator::Lesser => "lt",
+    BinaryOperator::Greater => "gt",
+    BinaryOperator::LesserOrEq => "le",
+    BinaryOperator::GreaterOrEq => "ge",
+  }
+}
+
+impl fmt::String for Operand {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Operand::Register(register) => write!(formatter, "%{}", register),
+      Operand::Integer(value) => write!(formatter, "{}", value),
+      Operand::Float(value) => write!(formatter, "{}f", float_text(format!("{}", value))),
+      Operand::Double(value) => write!(formatter, "{}", float_text(format!("{}", value))),
+      Operand::Boolean(value) => write!(formatter, "{}", value),
+      Operand::Text(ref text) => write!(formatter, "{}", string_literal(text.as_slice())),
+    }
+  }
+}
+
+impl fmt::String for Terminator {
+  fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Terminator::Jump(target) => write!(formatter, "jmp bb{}", target),
+      Terminator::Branch(ref condition, if_true, if_false) => {
+        write!(formatter, "br {}, bb{}, bb{}", condition, if_true, if_false)
+      },
+      Terminator::Return(Some(ref value)) => write!(formatter, "ret {}", value),
+      Terminator::Return(None) => write!(formatter, "ret"),
+      Terminator::Unreachable => write!(formatter, "unreachable"),
+    }
+  }
+}
+
+// doubles are told apart from ints by a decimal point or an exponent
+fn float_text(mut text: String) -> String {
+  let is_finite = text.as_slice().chars().all(|ch| ch.is_digit(10) || ch == '-' || ch == '.' || ch == 'e');
+  if is_finite && !text.contains(".") && !text.contains("e") {
+    text.push_str(".0");
+  }
+  text
+}
+
+fn string_literal(text: &str) -> String {
+  let mut literal = "\"".to_string();
+  for ch in text.chars() {
+    match ch {
+      '"' => literal.push_str("\\\""),
+      '\\' => literal.push_str("\\\\"),
+      '\n' => literal.push_str("\\n"),
+      '\t' => literal.push_str("\\t"),
+      _ if (ch as u32) < 0x20 => literal.push_str(format!("\\x{:02X}", ch as u32).as_slice()),
+      _ => literal.push(ch),
+    }
+  }
+  literal.push('"');
+  literal
+}
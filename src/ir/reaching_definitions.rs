@@ -0,0 +1,141 @@
+use std::iter;
+use ir::*;
+use ir::dataflow;
+use ir::dataflow::Analysis;
+use ir::dataflow::Direction;
+use ir::dataflow::Solution;
+
+/*
+  Reaching definitions. A definition of a register reaches a point of the
+  function if some path leads from it to the point without assigning the
+  register again. Parameters are defined at the start of the entry block,
+  instructions and phis where they are. This is the forward dataflow
+  analysis over sets of definitions where an assignment removes the other
+  definitions of its register and adds its own.
+*/
+
+#[derive(Show, Copy, PartialEq)]
+pub enum Definition {
+  Parameter(Register),
+  // block and index of the assigning instruction
+  Instruction(BlockId, usize),
+}
+
+pub struct ReachingDefinitions<'a> {
+  function: &'a Function,
+  // definition -> the register it assigns and where
+  definitions: Vec<(Register, Definition)>,
+  // register -> its definitions
+  register_definitions: Vec<Vec<usize>>,
+  // block -> instruction -> its definition
+  instruction_definitions: Vec<Vec<Option<usize>>>,
+  solution: Solution<Vec<bool>>,
+}
+
+impl<'a> ReachingDefinitions<'a> {
+  pub fn new(function: &'a Function) -> ReachingDefinitions<'a> {
+    let mut definitions = vec![];
+    let mut register_definitions: Vec<Vec<usize>> = iter::repeat(vec![]).take(function.registers.len()).collect();
+    for parameter in function.parameters.iter() {
+      register_definitions[*parameter].push(definitions.len());
+      definitions.push((*parameter, Definition::Parameter(*parameter)));
+    }
+    let mut instruction_definitions = vec![];
+    for (id, block) in function.blocks.iter().enumerate() {
+      let mut block_definitions = vec![];
+      for (index, instruction) in block.instructions.iter().enumerate() {
+        block_definitions.push(match instruction.destination() {
+          Some(register) => {
+            register_definitions[register].push(definitions.len());
+            definitions.push((register, Definition::Instruction(id, index)));
+            Some(definitions.len() - 1)
+          },
+          None => None,
+        });
+      }
+      instruction_definitions.push(block_definitions);
+    }
+
+    let mut reaching = ReachingDefinitions {
+      function: function,
+      definitions: definitions,
+      register_definitions: register_definitions,
+      instruction_definitions: instruction_definitions,
+      solution: Solution { start: vec![], end: vec![] },
+    };
+    reaching.solution = dataflow::solve(function, &reaching);
+    reaching
+  }
+
+  // definitions reaching the start of a block
+  pub fn at_start(&self, block: BlockId) -> Vec<Definition> {
+    self.collect(&self.solution.start[block])
+  }
+
+  // definitions reaching the end of a block
+  pub fn at_end(&self, block: BlockId) -> Vec<Definition> {
+    self.collect(&self.solution.end[block])
+  }
+
+  // definitions of a register reaching an instruction of a block, or its
+  // terminator if the index is the number of instructions
+  pub fn reaching(&self, block: BlockId, index: usize, register: Register) -> Vec<Definition> {
+    let facts = dataflow::block_facts(self.function, self, &self.solution, block);
+    let mut fact = facts[index].clone();
+    for (definition, &(assigned, _)) in self.definitions.iter().enumerate() {
+      if assigned != register {
+        fact[definition] = false;
+      }
+    }
+    self.collect(&fact)
+  }
+
+  fn collect(&self, fact: &Vec<bool>) -> Vec<Definition> {
+    fact.iter().enumerate()
+      .filter(|&(_, reaches)| *reaches)
+      .map(|(definition, _)| self.definitions[definition].1)
+      .collect()
+  }
+}
+
+impl<'a> Analysis<Vec<bool>> for ReachingDefinitions<'a> {
+  fn direction(&self) -> Direction {
+    Direction::Forward
+  }
+
+  fn boundary(&self) -> Vec<bool> {
+    let mut fact = self.initial();
+    for (definition, &(_, place)) in self.definitions.iter().enumerate() {
+      match place {
+        Definition::Parameter(..) => fact[definition] = true,
+        Definition::Instruction(..) => { },
+      }
+    }
+    fact
+  }
+
+  fn initial(&self) -> Vec<bool> {
+    iter::repeat(false).take(self.definitions.len()).collect()
+  }
+
+  fn meet(&self, fact: &mut Vec<bool>, other: &Vec<bool>) {
+    for (definition, reaches) in other.iter().enumerate() {
+      if *reaches {
+        fact[definition] = true;
+      }
+    }
+  }
+
+  fn transfer(&self, block: BlockId, index: usize, _instruction: &Instruction, fact: &mut Vec<bool>) {
+    match self.instruction_definitions[block][index] {
+      Some(definition) => {
+        let register = self.definitions[definition].0;
+        for other in self.register_definitions[register].iter() {
+          fact[*other] = false;
+        }
+        fact[definition] = true;
+      },
+      None => { },
+    }
+  }
+}
@@ -0,0 +1,317 @@
+use std::iter;
+use std::mem;
+use ast::Type;
+use ir::*;
+use ir::dominators::DominatorTree;
+use ir::liveness;
+use ir::verifier;
+use ir::verifier::Form;
+
+/*
+  Conversion into and out of static single assignment form.
+
+  Construction follows Cytron et al. The lowering assigns the register of a
+  source variable wherever the source does, so a variable assigned in if and
+  elif arms or in a loop body ends up with several assignments. Such
+  registers get a phi in every block of the iterated dominance frontier of
+  their assigning blocks where they are live. A walk over the dominator tree
+  then gives every assignment its own register and rewrites each use to the
+  register of the assignment reaching it. The first assignment keeps the
+  original register, as do registers assigned only once. A path on which a
+  register was never assigned passes the zero value of its type to a phi.
+
+  Destruction replaces the phis of a block by copies at the end of its
+  predecessors. Edges leaving a block that ends in a branch are split first,
+  so that the copies only run on their own edge and can not overwrite the
+  branch condition. The copies on one edge form a parallel copy: every
+  source is read before any destination is written. They are ordered so
+  that no source is overwritten before it is read; cycles such as swaps go
+  through a temporary register.
+*/
+
+// converts every function of the module into SSA form
+pub fn to_ssa(module: &mut Module) {
+  for function in module.functions.iter_mut() {
+    construct(function);
+  }
+  verifier::assert_valid(module, Form::Ssa, "SSA construction");
+}
+
+// converts every function of the module out of SSA form
+pub fn from_ssa(module: &mut Module) {
+  for function in module.functions.iter_mut() {
+    destruct(function);
+  }
+  verifier::assert_valid(module, Form::Normal, "SSA destruction");
+}
+
+pub fn construct(function: &mut Function) {
+  function.remove_unreachable_blocks();
+  let tree = DominatorTree::new(function);
+  let frontiers = tree.frontiers(function);
+  let live = liveness::analyze(function).live_in;
+
+  // blocks assigning each register, parameters are assigned in the entry block
+  let register_count = function.registers.len();
+  let mut assigning: Vec<Vec<BlockId>> = iter::repeat(vec![]).take(register_count).collect();
+  let mut assignment_count: Vec<usize> = iter::repeat(0).take(register_count).collect();
+  for parameter in function.parameters.iter() {
+    assigning[*parameter].push(0);
+    assignment_count[*parameter] += 1;
+  }
+  for (id, block) in function.blocks.iter().enumerate() {
+    for instruction in block.instructions.iter() {
+      match instruction.destination() {
+        Some(register) => {
+          if assigning[register].last() != Some(&id) {
+            assigning[register].push(id);
+          }
+          assignment_count[register] += 1;
+        },
+        None => { },
+      }
+    }
+  }
+  let renamed: Vec<bool> = assignment_count.iter().map(|count| *count > 1).collect();
+
+  // block -> original registers of its phis, in order
+  let mut phi_registers: Vec<Vec<Register>> = iter::repeat(vec![]).take(function.blocks.len()).collect();
+  for register in range(0, register_count) {
+    if !renamed[register] {
+      continue;
+    }
+    let mut has_phi = iter::repeat(false).take(function.blocks.len()).collect::<Vec<bool>>();
+    let mut queued = has_phi.clone();
+    let mut worklist = assigning[register].clone();
+    for block in worklist.iter() {
+      queued[*block] = true;
+    }
+    while let Some(block) = worklist.pop() {
+      for frontier in frontiers[block].iter() {
+        if has_phi[*frontier] || !live[*frontier][register] {
+          continue;
+        }
+        has_phi[*frontier] = true;
+        phi_registers[*frontier].push(register);
+        if !queued[*frontier] {
+          queued[*frontier] = true;
+          worklist.push(*frontier);
+        }
+      }
+    }
+  }
+
+  for (id, block) in function.blocks.iter_mut().enumerate() {
+    let mut instructions: Vec<Instruction> = phi_registers[id].iter()
+      .map(|register| Instruction::Phi(*register, vec![]))
+      .collect();
+    instructions.extend(mem::replace(&mut block.instructions, vec![]).into_iter());
+    block.instructions = instructions;
+  }
+
+  let mut renamer = Renamer {
+    tree: &tree,
+    phi_registers: phi_registers,
+    renamed: renamed,
+    reused: iter::repeat(false).take(register_count).collect(),
+    stacks: iter::repeat(vec![]).take(register_count).collect(),
+  };
+  for parameter in function.parameters.iter() {
+    renamer.reused[*parameter] = true;
+    renamer.stacks[*parameter].push(*parameter);
+  }
+  renamer.rename_block(function, 0);
+
+  // phi operands in the order of the predecessors
+  for block in function.blocks.iter_mut() {
+    for instruction in block.instructions.iter_mut() {
+      match *instruction {
+        Instruction::Phi(_, ref mut incoming) => incoming.sort_by(|&(a, _), &(b, _)| a.cmp(&b)),
+        _ => break,
+      }
+    }
+  }
+}
+
+struct Renamer<'a> {
+  tree: &'a DominatorTree,
+  phi_registers: Vec<Vec<Register>>,
+  // registers assigned more than once
+  renamed: Vec<bool>,
+  // the original register already names one of the assignments
+  reused: Vec<bool>,
+  // original register -> registers of the assignments reaching the current block
+  stacks: Vec<Vec<Register>>,
+}
+
+impl<'a> Renamer<'a> {
+  fn rename_block(&mut self, function: &mut Function, block: BlockId) {
+    // original registers whose stacks got a new entry in this block
+    let mut assigned = vec![];
+
+    let mut instructions = mem::replace(&mut function.blocks[block].instructions, vec![]);
+    for instruction in instructions.iter_mut() {
+      // phi operands are renamed at the end of the predecessors
+      if !instruction.is_phi() {
+        for operand in instruction.operands_mut().into_iter() {
+          self.rename_use(function, operand);
+        }
+      }
+
+      let original = match instruction.destination() {
+        Some(register) if self.renamed[register] => register,
+        _ => continue,
+      };
+      let register = if self.reused[original] {
+        let register_type = function.registers[original];
+        function.new_register(register_type)
+      } else {
+        self.reused[original] = true;
+        original
+      };
+      match instruction.destination_mut() {
+        Some(destination) => *destination = register,
+        None => { },
+      }
+      self.stacks[original].push(register);
+      assigned.push(original);
+    }
+    function.blocks[block].instructions = instructions;
+
+    let mut terminator = mem::replace(&mut function.blocks[block].terminator, Terminator::Unreachable);
+    for operand in terminator.operands_mut().into_iter() {
+      self.rename_use(function, operand);
+    }
+    let successors = terminator.successors();
+    function.blocks[block].terminator = terminator;
+
+    for successor in successors.iter() {
+      for (index, original) in self.phi_registers[*successor].iter().enumerate() {
+        let value = match self.stacks[*original].last() {
+          Some(register) => Operand::Register(*register),
+          None => zero_value(function.registers[*original]),
+        };
+        match function.blocks[*successor].instructions[index] {
+          Instruction::Phi(_, ref mut incoming) => incoming.push((block, value)),
+          _ => panic!("Internal compiler error: phi expected at the start of bb{}", successor),
+        }
+      }
+    }
+
+    let tree = self.tree;
+    for child in tree.children(block).iter() {
+      self.rename_block(function, *child);
+    }
+
+    for original in assigned.iter() {
+      self.stacks[*original].pop();
+    }
+  }
+
+  fn rename_use(&self, function: &Function, operand: &mut Operand) {
+    let original = match *operand {
+      Operand::Register(register) if self.renamed[register] => register,
+      _ => return,
+    };
+    *operand = match self.stacks[original].last() {
+      Some(register) => Operand::Register(*register),
+      None => zero_value(function.registers[original]),
+    };
+  }
+}
+
+// value of registers read on a path that never assigned them
+fn zero_value(value_type: Type) -> Operand {
+  match value_type {
+    Type::Integer => Operand::Integer(0),
+    Type::Float => Operand::Float(0.0),
+    Type::Double => Operand::Double(0.0),
+    Type::Boolean => Operand::Boolean(false),
+    Type::String => Operand::Text(String::new()),
+    Type::Void => panic!("Internal compiler error: void register"),
+  }
+}
+
+pub fn destruct(function: &mut Function) {
+  let predecessors = function.predecessors();
+  for block in range(0, function.blocks.len()) {
+    let phi_count = function.blocks[block].instructions.iter().take_while(|instruction| instruction.is_phi()).count();
+    if phi_count == 0 {
+      continue;
+    }
+    let instructions = mem::replace(&mut function.blocks[block].instructions, vec![]);
+    let (phis, others): (Vec<Instruction>, Vec<Instruction>) =
+      instructions.into_iter().partition(|instruction| instruction.is_phi());
+    function.blocks[block].instructions = others;
+
+    for predecessor in predecessors[block].iter() {
+      let copies: Vec<(Register, Operand)> = phis.iter().map(|phi| match *phi {
+        Instruction::Phi(destination, ref incoming) => (destination, incoming_value(incoming, *predecessor)),
+        _ => panic!("Internal compiler error: phi expected"),
+      }).collect();
+
+      let copy_block = match function.blocks[*predecessor].terminator {
+        Terminator::Branch(..) => {
+          let split = function.blocks.len();
+          function.blocks.push(BasicBlock { instructions: vec![], terminator: Terminator::Jump(block) });
+          function.blocks[*predecessor].terminator.replace_successor(block, split);
+          split
+        },
+        _ => *predecessor,
+      };
+      let sequence = sequentialize(function, copies);
+      function.blocks[copy_block].instructions.extend(sequence.into_iter());
+    }
+  }
+}
+
+fn incoming_value(incoming: &Vec<(BlockId, Operand)>, predecessor: BlockId) -> Operand {
+  for &(block, ref value) in incoming.iter() {
+    if block == predecessor {
+      return value.clone();
+    }
+  }
+  panic!("Internal compiler error: phi has no operand for bb{}", predecessor);
+}
+
+// orders the copies of a parallel copy so that no source is overwritten
+// before it is read
+fn sequentialize(function: &mut Function, copies: Vec<(Register, Operand)>) -> Vec<Instruction> {
+  let mut sequence = vec![];
+  // constants are copied last, they never have to be saved
+  let mut constants = vec![];
+  // destination, source
+  let mut pending: Vec<(Register, Register)> = vec![];
+  for (destination, source) in copies.into_iter() {
+    match source {
+      Operand::Register(source) if source == destination => { },
+      Operand::Register(source) => pending.push((destination, source)),
+      constant => constants.push(Instruction::Copy(destination, constant)),
+    }
+  }
+
+  while !pending.is_empty() {
+    // a copy can go next once no other pending copy reads its destination
+    let ready = pending.iter()
+      .position(|&(destination, _)| !pending.iter().any(|&(_, source)| source == destination));
+    match ready {
+      Some(index) => {
+        let (destination, source) = pending.remove(index);
+        sequence.push(Instruction::Copy(destination, Operand::Register(source)));
+      },
+      None => {
+        // only cycles are left, one source moves to a temporary
+        let (_, source) = pending[0];
+        let register_type = function.registers[source];
+        let temporary = function.new_register(register_type);
+        sequence.push(Instruction::Copy(temporary, Operand::Register(source)));
+        pending = pending.into_iter()
+          .map(|(destination, read)| (destination, if read == source { temporary } else { read }))
+          .collect();
+      },
+    }
+  }
+
+  sequence.extend(constants.into_iter());
+  sequence
+}
@@ -0,0 +1,477 @@
+use std::iter;
+use ast::BinaryOperator;
+use ast::Type;
+use ir::*;
+use ir::dataflow;
+use ir::dataflow::Analysis;
+use ir::dataflow::Direction;
+use ir::dominators::DominatorTree;
+use ir::reaching_definitions::ReachingDefinitions;
+
+/*
+  Checks the invariants that the passes over the intermediate representation
+  rely on, so that a pass breaking them is caught right after it ran instead
+  of silently miscompiling the program. In both forms:
+    -registers, blocks, functions and globals exist
+    -operands have the types their instructions expect and calls match the
+     signature of the callee
+  In Form::Normal:
+    -there are no phis
+    -every register is assigned on every path from the entry block to each
+     of its uses. Reaching definitions tell a register that is never
+     assigned before a use from one that is only assigned on some paths
+  In Form::Ssa:
+    -every register is assigned exactly once, by being a parameter or by an
+     instruction, and the assignment dominates all its uses. The operand of
+     a phi is used at the end of the predecessor it belongs to
+    -phis come before the other instructions of their block and have
+     exactly one operand per predecessor
+
+  Blocks that can not be reached from the entry block are only type checked.
+  Returns all found errors.
+*/
+
+#[derive(Show, Copy, PartialEq)]
+pub enum Form {
+  Normal,
+  Ssa,
+}
+
+pub fn verify(module: &Module, form: Form) -> Result<(), Vec<String>> {
+  let mut errors = vec![];
+  for function in module.functions.iter() {
+    match verify_function(module, function, form) {
+      Ok(()) => { },
+      Err(function_errors) => errors.extend(function_errors.into_iter()),
+    }
+  }
+
+  if errors.is_empty() {
+    Ok(())
+  } else {
+    Err(errors)
+  }
+}
+
+// a pass leaving the module invalid is a bug in the compiler
+pub fn assert_valid(module: &Module, form: Form, pass: &str) {
+  match verify(module, form) {
+    Ok(()) => { },
+    Err(errors) => panic!("Internal compiler error: invalid intermediate representation after {}:\n{}",
+      pass, errors.connect("\n")),
+  }
+}
+
+pub fn verify_function(module: &Module, function: &Function, form: Form) -> Result<(), Vec<String>> {
+  let mut verifier = Verifier {
+    module: module,
+    function: function,
+    errors: vec![],
+  };
+  verifier.check_types();
+  // dominance and assignment checks assume the registers and blocks exist
+  if verifier.errors.is_empty() {
+    match form {
+      Form::Normal => verifier.check_assignments(),
+      Form::Ssa => verifier.check_dominance(),
+    }
+  }
+
+  if verifier.errors.is_empty() {
+    Ok(())
+  } else {
+    Err(verifier.errors)
+  }
+}
+
+// where an instruction or terminator is
+#[derive(Copy)]
+struct Location {
+  block: BlockId,
+  // None for the terminator
+  instruction: Option<usize>,
+}
+
+struct Verifier<'a> {
+  module: &'a Module,
+  function: &'a Function,
+  errors: Vec<String>,
+}
+
+impl<'a> Verifier<'a> {
+  fn error(&mut self, location: Location, message: String) {
+    let place = match location.instruction {
+      Some(index) => format!("instruction {}", index),
+      None => "terminator".to_string(),
+    };
+    self.errors.push(format!("Verification error in function '@{}' at bb{}, {}: {}", self.function.name,
+      location.block, place, message));
+  }
+
+  fn check_types(&mut self) {
+    let function = self.function;
+    if function.blocks.is_empty() {
+      self.errors.push(format!("Verification error in function '@{}': function has no blocks", function.name));
+      return;
+    }
+    for parameter in function.parameters.iter() {
+      if *parameter >= function.registers.len() {
+        self.errors.push(format!("Verification error in function '@{}': parameter %{} does not exist",
+          function.name, parameter));
+      }
+    }
+
+    for (id, block) in function.blocks.iter().enumerate() {
+      for (index, instruction) in block.instructions.iter().enumerate() {
+        self.check_instruction(Location { block: id, instruction: Some(index) }, instruction);
+      }
+      self.check_terminator(Location { block: id, instruction: None }, &block.terminator);
+    }
+  }
+
+  // type of an operand, None if the register does not exist
+  fn operand_type(&mut self, location: Location, operand: &Operand) -> Option<Type> {
+    match *operand {
+      Operand::Register(register) if register >= self.function.registers.len() => {
+        self.error(location, format!("register %{} does not exist", register));
+        None
+      },
+      _ => Some(operand.get_type(self.function)),
+    }
+  }
+
+  fn expect_type(&mut self, location: Location, operand: &Operand, expected: Type) {
+    match self.operand_type(location, operand) {
+      Some(actual) if actual != expected => {
+        self.error(location, format!("operand {} has type {}, expected {}", operand, actual, expected));
+      },
+      _ => { },
+    }
+  }
+
+  fn check_instruction(&mut self, location: Location, instruction: &Instruction) {
+    let destination_type = match instruction.destination() {
+      Some(register) if register >= self.function.registers.len() => {
+        self.error(location, format!("register %{} does not exist", register));
+        return;
+      },
+      Some(register) => self.function.registers[register],
+      None => Type::Void,
+    };
+
+    match *instruction {
+      Instruction::Copy(_, ref operand) => self.expect_type(location, operand, destination_type),
+      Instruction::Binary(_, operator, ref left, ref right) => {
+        let operand_type = match self.operand_type(location, left) {
+          Some(operand_type) => operand_type,
+          None => return,
+        };
+        self.expect_type(location, right, operand_type);
+        let result_type = match operator {
+          BinaryOperator::Plus | BinaryOperator::Minus | BinaryOperator::Multiply |
+          BinaryOperator::Divide => operand_type,
+          _ => Type::Boolean,
+        };
+        if result_type != destination_type {
+          self.error(location, format!("result has type {}, but the destination has type {}", result_type,
+            destination_type));
+        }
+      },
+      Instruction::Divide(_, ref left, ref right, _) => {
+        self.expect_type(location, left, Type::Integer);
+        self.expect_type(location, right, Type::Integer);
+        if destination_type != Type::Integer {
+          self.error(location, format!("result has type int, but the destination has type {}", destination_type));
+        }
+      },
+      Instruction::Convert(_, ref operand) => {
+        match self.operand_type(location, operand) {
+          Some(operand_type) if !is_numeric(operand_type) => {
+            self.error(location, format!("can not convert {} values", operand_type));
+          },
+          _ => { },
+        }
+        if !is_numeric(destination_type) {
+          self.error(location, format!("can not convert to {}", destination_type));
+        }
+      },
+      Instruction::Call(destination, index, ref arguments) => {
+        let callee = match self.module.functions.get(index) {
+          Some(callee) => callee,
+          None => {
+            self.error(location, format!("function {} does not exist", index));
+            return;
+          },
+        };
+        if arguments.len() != callee.parameters.len() {
+          self.error(location, format!("call passes {} argument(s), but @{} has {} parameter(s)",
+            arguments.len(), callee.name, callee.parameters.len()));
+          return;
+        }
+        for (argument, parameter) in arguments.iter().zip(callee.parameters.iter()) {
+          match callee.registers.get(*parameter) {
+            Some(parameter_type) => self.expect_type(location, argument, *parameter_type),
+            None => { },
+          }
+        }
+        if destination.is_some() != (callee.return_type != Type::Void) ||
+          (destination.is_some() && destination_type != callee.return_type) {
+          self.error(location, format!("result of @{} has type {}, but the destination has type {}",
+            callee.name, callee.return_type, destination_type));
+        }
+      },
+      Instruction::Print(ref operand) => {
+        if self.operand_type(location, operand) == Some(Type::Void) {
+          self.error(location, "can not print void values".to_string());
+        }
+      },
+      Instruction::LoadGlobal(_, index) => {
+        match self.module.globals.get(index) {
+          Some(global) if global.global_type != destination_type => {
+            self.error(location, format!("global @{} has type {}, but the destination has type {}",
+              global.name, global.global_type, destination_type));
+          },
+          Some(..) => { },
+          None => self.error(location, format!("global {} does not exist", index)),
+        }
+      },
+      Instruction::StoreGlobal(index, ref operand) => {
+        match self.module.globals.get(index) {
+          Some(global) => self.expect_type(location, operand, global.global_type),
+          None => self.error(location, format!("global {} does not exist", index)),
+        }
+      },
+      Instruction::Phi(_, ref incoming) => {
+        for &(predecessor, ref operand) in incoming.iter() {
+          if predecessor >= self.function.blocks.len() {
+            self.error(location, format!("block bb{} does not exist", predecessor));
+          }
+          self.expect_type(location, operand, destination_type);
+        }
+      },
+    }
+  }
+
+  fn check_terminator(&mut self, location: Location, terminator: &Terminator) {
+    for successor in terminator.successors().iter() {
+      if *successor >= self.function.blocks.len() {
+        self.error(location, format!("block bb{} does not exist", successor));
+      }
+    }
+
+    match *terminator {
+      Terminator::Branch(ref condition, _, _) => self.expect_type(location, condition, Type::Boolean),
+      Terminator::Return(Some(ref value)) => {
+        let return_type = self.function.return_type;
+        if return_type == Type::Void {
+          self.error(location, "void function returns a value".to_string());
+        } else {
+          self.expect_type(location, value, return_type);
+        }
+      },
+      Terminator::Return(None) => {
+        if self.function.return_type != Type::Void {
+          self.error(location, format!("function returns no value, expected {}", self.function.return_type));
+        }
+      },
+      Terminator::Jump(..) | Terminator::Unreachable => { },
+    }
+  }
+
+  // every use is preceded by an assignment on every path from the entry
+  fn check_assignments(&mut self) {
+    let function = self.function;
+    let analysis = DefiniteAssignment { function: function };
+    let solution = dataflow::solve(function, &analysis);
+    let reaching = ReachingDefinitions::new(function);
+    for block in function.reverse_postorder().into_iter() {
+      let assigned = dataflow::block_facts(function, &analysis, &solution, block);
+      let instructions = &function.blocks[block].instructions;
+      for (index, instruction) in instructions.iter().enumerate() {
+        let location = Location { block: block, instruction: Some(index) };
+        if instruction.is_phi() {
+          self.error(location, "phi outside of SSA form".to_string());
+        }
+        for operand in instruction.operands().iter() {
+          self.check_assigned(location, *operand, &assigned[index], &reaching);
+        }
+      }
+      let location = Location { block: block, instruction: None };
+      for operand in function.blocks[block].terminator.operands().iter() {
+        self.check_assigned(location, *operand, &assigned[instructions.len()], &reaching);
+      }
+    }
+  }
+
+  fn check_assigned(&mut self, location: Location, operand: &Operand, assigned: &Vec<bool>,
+    reaching: &ReachingDefinitions) {
+    match *operand {
+      Operand::Register(register) if !assigned[register] => {
+        let index = match location.instruction {
+          Some(index) => index,
+          None => self.function.blocks[location.block].instructions.len(),
+        };
+        if reaching.reaching(location.block, index, register).is_empty() {
+          self.error(location, format!("register %{} is used before it is assigned", register));
+        } else {
+          self.error(location, format!("register %{} may be used before it is assigned", register));
+        }
+      },
+      _ => { },
+    }
+  }
+
+  // every register has one definition that dominates its uses
+  fn check_dominance(&mut self) {
+    let function = self.function;
+    let tree = DominatorTree::new(function);
+    let predecessors = function.predecessors();
+
+    // register -> block and instruction index of the definition. Parameters
+    // are defined before the first instruction of the entry block
+    let mut definitions: Vec<Option<(BlockId, Option<usize>)>> =
+      iter::repeat(None).take(function.registers.len()).collect();
+    for parameter in function.parameters.iter() {
+      if definitions[*parameter].is_some() {
+        self.errors.push(format!("Verification error in function '@{}': parameter %{} is listed twice",
+          function.name, parameter));
+      }
+      definitions[*parameter] = Some((0, None));
+    }
+    for (id, block) in function.blocks.iter().enumerate() {
+      for (index, instruction) in block.instructions.iter().enumerate() {
+        match instruction.destination() {
+          Some(register) => {
+            if definitions[register].is_some() {
+              let location = Location { block: id, instruction: Some(index) };
+              self.error(location, format!("register %{} is assigned more than once", register));
+            }
+            definitions[register] = Some((id, Some(index)));
+          },
+          None => { },
+        }
+      }
+    }
+
+    for (id, block) in function.blocks.iter().enumerate() {
+      if !tree.is_reachable(id) {
+        continue;
+      }
+      let mut phis_ended = false;
+      for (index, instruction) in block.instructions.iter().enumerate() {
+        let location = Location { block: id, instruction: Some(index) };
+        match *instruction {
+          Instruction::Phi(_, ref incoming) => {
+            if phis_ended {
+              self.error(location, "phi follows other instructions".to_string());
+            }
+            self.check_phi_predecessors(location, incoming, &predecessors[id]);
+            for &(predecessor, ref operand) in incoming.iter() {
+              // the operand is used where control leaves the predecessor
+              let end = Location { block: predecessor, instruction: None };
+              self.check_dominated(location, operand, end, &definitions, &tree);
+            }
+          },
+          _ => {
+            phis_ended = true;
+            for operand in instruction.operands().iter() {
+              self.check_dominated(location, *operand, location, &definitions, &tree);
+            }
+          },
+        }
+      }
+      let location = Location { block: id, instruction: None };
+      for operand in block.terminator.operands().iter() {
+        self.check_dominated(location, *operand, location, &definitions, &tree);
+      }
+    }
+  }
+
+  fn check_phi_predecessors(&mut self, location: Location, incoming: &Vec<(BlockId, Operand)>,
+    predecessors: &Vec<BlockId>) {
+    let mut seen = vec![];
+    for &(predecessor, _) in incoming.iter() {
+      if seen.contains(&predecessor) {
+        self.error(location, format!("phi has more than one operand for bb{}", predecessor));
+      } else if !predecessors.contains(&predecessor) {
+        self.error(location, format!("phi has an operand for bb{}, which is not a predecessor", predecessor));
+      }
+      seen.push(predecessor);
+    }
+    for predecessor in predecessors.iter() {
+      if !seen.contains(predecessor) {
+        self.error(location, format!("phi has no operand for predecessor bb{}", predecessor));
+      }
+    }
+  }
+
+  // the definition of a register operand dominates the place it is used at
+  fn check_dominated(&mut self, location: Location, operand: &Operand, used_at: Location,
+    definitions: &Vec<Option<(BlockId, Option<usize>)>>, tree: &DominatorTree) {
+    let register = match *operand {
+      Operand::Register(register) => register,
+      _ => return,
+    };
+
+    let dominated = match definitions[register] {
+      None => {
+        self.error(location, format!("register %{} is never assigned", register));
+        return;
+      },
+      Some((block, index)) if block == used_at.block => {
+        // parameters come before, the terminator after every instruction
+        match (index, used_at.instruction) {
+          (None, _) | (Some(_), None) => true,
+          (Some(defined), Some(used)) => defined < used,
+        }
+      },
+      Some((block, _)) => tree.dominates(block, used_at.block),
+    };
+    if !dominated {
+      self.error(location, format!("definition of register %{} does not dominate its use", register));
+    }
+  }
+}
+
+// registers assigned on every path from the entry block, the initial fact
+// of the blocks assumes all registers are assigned
+struct DefiniteAssignment<'a> {
+  function: &'a Function,
+}
+
+impl<'a> Analysis<Vec<bool>> for DefiniteAssignment<'a> {
+  fn direction(&self) -> Direction {
+    Direction::Forward
+  }
+
+  fn boundary(&self) -> Vec<bool> {
+    let mut assigned: Vec<bool> = iter::repeat(false).take(self.function.registers.len()).collect();
+    for parameter in self.function.parameters.iter() {
+      assigned[*parameter] = true;
+    }
+    assigned
+  }
+
+  fn initial(&self) -> Vec<bool> {
+    iter::repeat(true).take(self.function.registers.len()).collect()
+  }
+
+  fn meet(&self, fact: &mut Vec<bool>, other: &Vec<bool>) {
+    for (register, assigned) in other.iter().enumerate() {
+      if !*assigned {
+        fact[register] = false;
+      }
+    }
+  }
+
+  fn transfer(&self, _block: BlockId, _index: usize, instruction: &Instruction, fact: &mut Vec<bool>) {
+    match instruction.destination() {
+      Some(register) => fact[register] = true,
+      None => { },
+    }
+  }
+}
+
+fn is_numeric(value_type: Type) -> bool {
+  value_type == Type::Integer || value_type == Type::Float || value_type == Type::Double
+}
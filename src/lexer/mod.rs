@@ -226,7 +226,7 @@ impl<'a> Lexer<'a> {
       }
     }
   }
-  /*if, else, while, for, let, fn, return, new, class,
+  /*if, else, while, for, let, const, as, fn, return, new, class,
   public, protected, private, true, false, int, float, double, bool, void*/
   fn handle_keywords(&self, identifier: &str) -> Option<SyntaxToken> {
     match identifier {
@@ -236,6 +236,8 @@ impl<'a> Lexer<'a> {
       "while" => Some(self.create_token(TokenType::While, TokenSubType::NoSubType)),
       "for" => Some(self.create_token(TokenType::For, TokenSubType::NoSubType)),
       "let" => Some(self.create_token(TokenType::Let, TokenSubType::NoSubType)),
+      "const" => Some(self.create_token(TokenType::Const, TokenSubType::NoSubType)),
+      "as" => Some(self.create_token(TokenType::As, TokenSubType::NoSubType)),
       "fn" => Some(self.create_token(TokenType::Fn, TokenSubType::NoSubType)),
       "return" => Some(self.create_token(TokenType::Return, TokenSubType::NoSubType)),
       "new" => Some(self.create_token(TokenType::New, TokenSubType::NoSubType)),
@@ -1,3 +1,16 @@
 pub mod lexer;
 pub mod token;
+pub mod ast;
 pub mod parser;
+pub mod symbol_table;
+pub mod builtins;
+pub mod resolver;
+pub mod signatures;
+pub mod type_checker;
+pub mod constant_evaluator;
+pub mod call_graph;
+pub mod interpreter;
+pub mod bytecode;
+pub mod vm;
+pub mod ir;
+pub mod backend;
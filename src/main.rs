@@ -4,29 +4,276 @@ extern crate compiler;
 #[cfg(not(test))]
 use std::io::File;
 #[cfg(not(test))]
+use std::io::stdio;
+#[cfg(not(test))]
+use std::os;
+#[cfg(not(test))]
 use std::str::from_utf8;
 
+/*
+  Usage:
+    compiler [options] [file]          checks the file (default: 'file')
+    compiler run [options] <file>      checks and executes the file. The file
+                                       may also be a compiled bytecode file
+    compiler build <file> <output>     compiles the file into a bytecode file
+
+  Options:
+    --emit=bytecode   print the bytecode listing of the checked file
+    --emit=c          print the checked file translated to C
+    --emit=callgraph  print the call graph of the checked file in the DOT
+                      language of Graphviz
+    --emit=ir         print the intermediate representation of the checked file
+    --emit=js         print the checked file translated to JavaScript
+    --emit=llvm-ir    print the checked file translated to textual LLVM IR
+    --emit=regalloc   print the registers the native backend's allocator
+                      assigns to the values of the intermediate representation
+    --emit=x86_64     print the checked file translated to x86-64 assembly
+    --emit=wat        print the checked file translated to WebAssembly text
+    --emit=wasm       write the checked file as a binary WebAssembly module to
+                      standard output
+    -O0, -O1, -O2     optimization level of the intermediate representation
+                      printed by --emit=ir and --emit=regalloc and translated
+                      by --emit=x86_64 (default: -O0)
+
+  Options of run:
+    --interpret       execute with the tree-walking interpreter instead of the
+                      bytecode virtual machine
+    --jit             compile hot functions of the executed bytecode into
+                      machine code
+
+  Options that do not apply to the selected mode are rejected, and so is
+  more than one --emit or -O option.
+*/
 #[cfg(not(test))]
 fn main() {
-  let tokens = tokenize_file("file");
-  parse_tokens(tokens);
+  let args = os::args();
+  let mut options = vec![];
+  let mut arguments = vec![];
+  for arg in args.iter().skip(1) {
+    if arg.as_slice().starts_with("-") {
+      options.push(arg.as_slice());
+    } else {
+      arguments.push(arg.as_slice());
+    }
+  }
+
+  let known_options = ["--emit=bytecode", "--emit=c", "--emit=callgraph", "--emit=ir", "--emit=js", "--emit=llvm-ir",
+    "--emit=regalloc", "--emit=x86_64", "--emit=wat", "--emit=wasm", "--interpret", "--jit", "-O0", "-O1", "-O2"];
+  let valid_options = options.iter().all(|option| known_options.contains(option));
 
+  if valid_options && arguments.len() == 2 && arguments[0] == "run" {
+    let interpret = options.contains(&"--interpret");
+    let jit = options.contains(&"--jit");
+    let output_option = options.iter().find(|option| option.starts_with("--emit=") || option.starts_with("-O"));
+    if output_option.is_some() {
+      println!("Option '{}' can not be used with run", output_option.unwrap());
+      os::set_exit_status(1);
+    } else if interpret && jit {
+      println!("Options --interpret and --jit can not be combined");
+      os::set_exit_status(1);
+    } else if compiler::bytecode::file::is_bytecode_file(read_bytes(arguments[1]).as_slice()) {
+      if interpret {
+        println!("Compiled bytecode files can not be interpreted");
+        os::set_exit_status(1);
+      } else {
+        run_module(&load_bytecode_file(arguments[1]), jit);
+      }
+    } else {
+      let program = compile_file(arguments[1], false);
+      if interpret {
+        interpret_program(&program);
+      } else {
+        run_module(&compile_bytecode(&program), jit);
+      }
+    }
+  } else if options.is_empty() && arguments.len() == 3 && arguments[0] == "build" {
+    let program = compile_file(arguments[1], false);
+    let module = compile_bytecode(&program);
+    let bytes = compiler::bytecode::file::write(&module);
+    match File::create(&Path::new(arguments[2])).write(bytes.as_slice()) {
+      Ok(..) => { },
+      Err(err) => panic!("Io Error: {}", err),
+    }
+  } else if valid_options && arguments.len() <= 1 {
+    let name = if arguments.is_empty() { "file" } else { arguments[0] };
+    let emit_format = options.iter()
+      .filter(|option| option.starts_with("--emit="))
+      .map(|option| option.slice_from("--emit=".len()))
+      .last();
+    let level = options.iter()
+      .filter_map(|option| match *option {
+        "-O0" => Some(compiler::ir::optimizer::Level::O0),
+        "-O1" => Some(compiler::ir::optimizer::Level::O1),
+        "-O2" => Some(compiler::ir::optimizer::Level::O2),
+        _ => None,
+      })
+      .last()
+      .unwrap_or(compiler::ir::optimizer::Level::O0);
+    let run_option = options.iter().find(|option| **option == "--interpret" || **option == "--jit");
+    let level_option = options.iter().find(|option| option.starts_with("-O"));
+    let emit_count = options.iter().filter(|option| option.starts_with("--emit=")).count();
+    let level_count = options.iter().filter(|option| option.starts_with("-O")).count();
+    let optimized_format = match emit_format {
+      Some("ir") | Some("regalloc") | Some("x86_64") => true,
+      _ => false,
+    };
+    if emit_count > 1 {
+      println!("Only one --emit option can be given");
+      os::set_exit_status(1);
+    } else if level_count > 1 {
+      println!("Only one -O option can be given");
+      os::set_exit_status(1);
+    } else if run_option.is_some() {
+      println!("Option '{}' can only be used with run", run_option.unwrap());
+      os::set_exit_status(1);
+    } else if level_option.is_some() && !optimized_format {
+      println!("Option '{}' only applies to --emit=ir, --emit=regalloc and --emit=x86_64", level_option.unwrap());
+      os::set_exit_status(1);
+    } else {
+      let program = compile_file(name, emit_format.is_none());
+      match emit_format {
+        Some(format) => emit(&program, format, name, level),
+        None => { },
+      }
+    }
+  } else {
+    println!("Usage: {} [run] [--emit=bytecode|c|callgraph|ir|js|llvm-ir|regalloc|x86_64|wat|wasm] \
+      [--interpret|--jit] [-O0|-O1|-O2] <file> | build <file> <output>", args[0]);
+    os::set_exit_status(1);
+  }
 }
 
 #[cfg(not(test))]
-fn read_file(name: &str) -> String {
-  let contents = File::open(&Path::new(name)).read_to_end();
-  match contents {
-    Ok(res) => {
-      match from_utf8(res.as_slice()) {
-        Ok(utf_res) => return utf_res.to_string(),
-        Err(err) => panic!("Conversion error: {}", err),
-      }
+fn emit(program: &compiler::ast::Program, format: &str, name: &str, level: compiler::ir::optimizer::Level) {
+  match format {
+    "bytecode" => {
+      let module = compile_bytecode(program);
+      print!("{}", compiler::bytecode::disassembler::disassemble(&module));
+    },
+    "c" => print!("{}", compiler::backend::c::generate(program)),
+    "callgraph" => print!("{}", compiler::call_graph::CallGraph::new(program).to_dot()),
+    "ir" => {
+      let mut module = compiler::ir::lowering::lower(program);
+      compiler::ir::optimizer::optimize(&mut module, level);
+      print!("{}", compiler::ir::printer::print(&module));
+    },
+    // the comments and line structure are taken from the source
+    "js" => print!("{}", compiler::backend::javascript::generate(program, read_file(name).as_slice())),
+    "llvm-ir" => print!("{}", compiler::backend::llvm::generate(program)),
+    "regalloc" => {
+      let mut module = compiler::ir::lowering::lower(program);
+      compiler::ir::optimizer::optimize(&mut module, level);
+      let target = compiler::backend::x86_64::register_allocator::Target::x86_64();
+      let dumps: Vec<String> = module.functions.iter().map(|function| {
+        let allocation = compiler::backend::x86_64::register_allocator::allocate(function, &target);
+        compiler::backend::x86_64::register_allocator::dump(&module, function, &allocation)
+      }).collect();
+      print!("{}", dumps.connect("\n"));
+    },
+    "x86_64" => {
+      let mut module = compiler::ir::lowering::lower(program);
+      compiler::ir::optimizer::optimize(&mut module, level);
+      print!("{}", compiler::backend::x86_64::generate_module(&module));
+    },
+    "wat" => {
+      let module = compiler::backend::wasm::compiler::compile(program);
+      print!("{}", compiler::backend::wasm::text::print(&module));
+    },
+    "wasm" => {
+      let module = compiler::backend::wasm::compiler::compile(program);
+      let bytes = compiler::backend::wasm::binary::encode(&module);
+      stdio::stdout().write(bytes.as_slice()).unwrap();
+    },
+    _ => panic!("Unknown output format '{}'", format),
+  }
+}
+
+#[cfg(not(test))]
+fn compile_file(name: &str, verbose: bool) -> compiler::ast::Program {
+  let tokens = tokenize_file(name);
+  let mut program = parse_tokens(tokens);
+  if verbose {
+    println!("Parsing succeeded");
+  }
+  check_program(&mut program);
+  program
+}
+
+#[cfg(not(test))]
+fn compile_bytecode(program: &compiler::ast::Program) -> compiler::bytecode::Module {
+  match compiler::bytecode::compiler::compile(program) {
+    Ok(module) => module,
+    Err(errors) => {
+      print_errors(errors);
+      panic!("Terminating process due to previous error(s)");
+    }
+  }
+}
+
+#[cfg(not(test))]
+fn load_bytecode_file(name: &str) -> compiler::bytecode::Module {
+  let module = match compiler::bytecode::file::read(read_bytes(name).as_slice()) {
+    Ok(module) => module,
+    Err(error) => {
+      print_errors(vec![error]);
+      panic!("Terminating process due to previous error(s)");
+    }
+  };
+
+  match compiler::bytecode::verifier::verify(&module) {
+    Ok(..) => module,
+    Err(errors) => {
+      print_errors(errors);
+      panic!("Terminating process due to previous error(s)");
     }
+  }
+}
+
+#[cfg(not(test))]
+fn run_module(module: &compiler::bytecode::Module, jit: bool) {
+  let result = if jit {
+    let mut jit = compiler::vm::jit::Jit::new(compiler::vm::jit::DEFAULT_THRESHOLD);
+    compiler::vm::execute_with_jit(module, &mut stdio::stdout(), &mut jit)
+  } else {
+    compiler::vm::execute(module, &mut stdio::stdout())
+  };
+  match result {
+    Ok(..) => { },
+    Err(error) => {
+      println!("{}", error);
+      os::set_exit_status(1);
+    }
+  }
+}
+
+#[cfg(not(test))]
+fn interpret_program(program: &compiler::ast::Program) {
+  match compiler::interpreter::run(program, &mut stdio::stdout()) {
+    Ok(..) => { },
+    Err(error) => {
+      println!("{}", error);
+      os::set_exit_status(1);
+    }
+  }
+}
+
+#[cfg(not(test))]
+fn read_bytes(name: &str) -> Vec<u8> {
+  match File::open(&Path::new(name)).read_to_end() {
+    Ok(res) => res,
     Err(err) => panic!("Io Error: {}", err),
   }
 }
 
+#[cfg(not(test))]
+fn read_file(name: &str) -> String {
+  let contents = read_bytes(name);
+  match from_utf8(contents.as_slice()) {
+    Ok(utf_res) => return utf_res.to_string(),
+    Err(err) => panic!("Conversion error: {}", err),
+  }
+}
+
 #[cfg(not(test))]
 fn tokenize_file(name: &str) -> compiler::token::Tokens {
 
@@ -43,9 +290,9 @@ fn tokenize_file(name: &str) -> compiler::token::Tokens {
 }
 
 #[cfg(not(test))]
-fn parse_tokens(tokens: compiler::token::Tokens) {
+fn parse_tokens(tokens: compiler::token::Tokens) -> compiler::ast::Program {
   match compiler::parser::parse(tokens) {
-    Ok(..) => println!("Parsing succeeded"),
+    Ok(program) => program,
     Err(errors) => {
       print_errors(errors);
       panic!("Terminating process due to previous error(s)");
@@ -53,6 +300,42 @@ fn parse_tokens(tokens: compiler::token::Tokens) {
   }
 }
 
+#[cfg(not(test))]
+fn check_program(program: &mut compiler::ast::Program) {
+  match compiler::resolver::resolve(program) {
+    Ok(warnings) => print_warnings(warnings),
+    Err(errors) => {
+      print_errors(errors);
+      panic!("Terminating process due to previous error(s)");
+    }
+  }
+
+  match compiler::type_checker::check(program) {
+    Ok(warnings) => print_warnings(warnings),
+    Err(errors) => {
+      print_errors(errors);
+      panic!("Terminating process due to previous error(s)");
+    }
+  }
+
+  match compiler::constant_evaluator::fold(program) {
+    Ok(warnings) => print_warnings(warnings),
+    Err(errors) => {
+      print_errors(errors);
+      panic!("Terminating process due to previous error(s)");
+    }
+  }
+
+  print_warnings(compiler::call_graph::check(program));
+}
+
+#[cfg(not(test))]
+fn print_warnings(warnings: Vec<String>) {
+  for warning in warnings.iter() {
+    println!("{}", warning)
+  }
+}
+
 #[cfg(not(test))]
 fn print_errors(errors: Vec<String>) {
   println!("Error(s) were found:");
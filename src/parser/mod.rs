@@ -2,16 +2,38 @@ use token::Tokens;
 use token::SyntaxToken;
 use token::TokenType;
 use token::TokenSubType;
+use ast::Program;
+use ast::Function;
+use ast::Parameter;
+use ast::Block;
+use ast::Statement;
+use ast::VariableDeclaration;
+use ast::Assignment;
+use ast::FunctionCall;
+use ast::ForLoop;
+use ast::IfStatement;
+use ast::ElseIf;
+use ast::ReturnStatement;
+use ast::Expression;
+use ast::ExpressionKind;
+use ast::Literal;
+use ast::BinaryOperator;
+use ast::Position;
+use ast::Type;
 /*
-  Recursive descent parser that for now merely checks if input conforms to
-  grammar. No syntax tree is built.
+  Recursive descent parser that checks if input conforms to grammar and
+  builds the abstract syntax tree.
+
+  Functions return either bool or Option; on failure an error has been
+  registered and the parser has possibly skipped tokens in order to recover.
+  The syntax tree is only returned if no errors were found, so partial nodes
+  created during error recovery are never visible outside the parser.
 
   Check documentation for grammar.
 */
 
 
-// first string param serves as a placeholder for abstract syntax tree
-pub fn parse(tokens: Tokens) -> Result<String, Vec<String>> {
+pub fn parse(tokens: Tokens) -> Result<Program, Vec<String>> {
   let mut parser = Parser::new(tokens);
   parser.parse()
 }
@@ -21,15 +43,24 @@ pub fn parse(tokens: Tokens) -> Result<String, Vec<String>> {
 struct Parser {
   tokens: Tokens,
   errors: Vec<String>,
+  globals: Vec<VariableDeclaration>,
+  functions: Vec<Function>,
+}
+
+// function name, parameters and return type; filled while parsing the declaration
+struct FunctionHeader {
+  name: Option<usize>,
+  parameters: Vec<Parameter>,
+  return_type: Type,
 }
 
 
 impl Parser {
   fn new(tokens: Tokens) -> Parser {
-    Parser { tokens: tokens, errors: Vec::new() }
+    Parser { tokens: tokens, errors: Vec::new(), globals: Vec::new(), functions: Vec::new() }
   }
 
-  fn parse(&mut self) -> Result<String, Vec<String>> {
+  fn parse(&mut self) -> Result<Program, Vec<String>> {
 
     loop {
       let mut next_token:Option<SyntaxToken>;
@@ -44,7 +75,11 @@ impl Parser {
     }
 
     if self.errors.is_empty() {
-      Ok("Placeholder".to_string())
+      Ok(Program {
+        globals: self.globals.clone(),
+        functions: self.functions.clone(),
+        text_table: self.tokens.get_text_table(),
+      })
     } else {
       Err(self.errors.clone())
     }
@@ -52,23 +87,50 @@ impl Parser {
 
   fn parse_start_token(&mut self, token: SyntaxToken) {
     match token.t_type {
-      TokenType::Fn => { self.parse_function(); },
+      TokenType::Fn => { self.parse_function(&token); },
+      TokenType::Let => { self.parse_global_declaration(&token, false); },
+      TokenType::Const => { self.parse_global_declaration(&token, true); },
       _ => {
         let token_str = self.tokens.to_string(&token);
 
         self.register_error_and_skip_to(
           format!(
-              "Invalid token {}. Expected token {}", token_str, TokenType::Fn),
+              "Invalid token {}. Expected token {}, {} or {}", token_str, TokenType::Fn,
+              TokenType::Let, TokenType::Const),
             &token,
-            vec![TokenType::Fn]);
+            vec![TokenType::Fn, TokenType::Let, TokenType::Const]);
       },
     }
   }
 
+  // top level let\const. Start token has already been consumed
+  fn parse_global_declaration(&mut self, start_token: &SyntaxToken, is_constant: bool) -> bool {
+    match self.parse_variable_declaration_body(start_token, is_constant) {
+      Some(declaration) => {
+        if self.expect(TokenType::SemiColon) {
+          self.globals.push(declaration);
+          return true;
+        }
+      },
+      None => { },
+    }
 
-  fn parse_function(&mut self) -> bool {
+    // skip the rest of the declaration, so that the semicolon is not reported
+    // as an invalid start token
+    self.skip_to_first_of(vec![TokenType::SemiColon, TokenType::Fn, TokenType::Let,
+      TokenType::Const]);
+    if self.next_token_is(TokenType::SemiColon) {
+      self.tokens.next();
+    }
+    false
+  }
+
+
+  fn parse_function(&mut self, fn_token: &SyntaxToken) -> bool {
     let mut success = true;
-    if !self.parse_function_declaration() {
+    let mut header = FunctionHeader { name: None, parameters: vec![], return_type: Type::Void };
+
+    if !self.parse_function_declaration(&mut header) {
       self.skip_to_first_of(vec![TokenType::LBrace,
         TokenType::Fn]);
       success = false;
@@ -80,28 +142,44 @@ impl Parser {
       }
     }
 
-    if !self.parse_block() {
-      self.skip_to_first_of(vec![TokenType::Fn]);
-
-      success = false;
+    match self.parse_block() {
+      Some(block) => {
+        match header.name {
+          Some(name) => self.functions.push(Function {
+            name: name,
+            parameters: header.parameters,
+            return_type: header.return_type,
+            body: block,
+            pos: Position::from_token(fn_token),
+          }),
+          None => { /* error already reported */ },
+        }
+      },
+      None => {
+        self.skip_to_first_of(vec![TokenType::Fn]);
+        success = false;
+      }
     }
 
     success
   }
 
-  fn parse_function_declaration(&mut self) -> bool {
+  fn parse_function_declaration(&mut self, header: &mut FunctionHeader) -> bool {
     let mut success = true;
 
-    if !self.expect(TokenType::Identifier) {
-      // skip to start of block or start of parameter list
-      self.skip_to_first_of(vec![TokenType::Fn, TokenType::LBrace,
-        TokenType::LParen]);
-      success = false;
-      // if next token is not start of parameter list, bail out.
-      // Otherwise, continue parsing in order to see if there are any
-      // additional syntax issues
-      if !self.next_token_is(TokenType::LParen) {
-        return false;
+    match self.expect_token(TokenType::Identifier) {
+      Some(token) => header.name = Some(identifier_index(&token)),
+      None => {
+        // skip to start of block or start of parameter list
+        self.skip_to_first_of(vec![TokenType::Fn, TokenType::LBrace,
+          TokenType::LParen]);
+        success = false;
+        // if next token is not start of parameter list, bail out.
+        // Otherwise, continue parsing in order to see if there are any
+        // additional syntax issues
+        if !self.next_token_is(TokenType::LParen) {
+          return false;
+        }
       }
     }
 
@@ -119,7 +197,7 @@ impl Parser {
       }
     }
 
-    if !self.parse_function_parameters() {
+    if !self.parse_function_parameters(&mut header.parameters) {
       self.skip_to_first_of(vec![TokenType::Fn, TokenType::LBrace,
         TokenType::RParen]);
       success = false;
@@ -139,38 +217,41 @@ impl Parser {
       }
     }
 
-    self.parse_optional_return_type() && success
+    self.parse_optional_return_type(header) && success
   }
 
-  fn parse_function_parameters(&mut self) -> bool {
+  fn parse_function_parameters(&mut self, parameters: &mut Vec<Parameter>) -> bool {
     if self.next_token_is(TokenType::RParen) {
       true
     } else {
-      self.parse_function_parameter_list()
+      self.parse_function_parameter_list(parameters)
     }
   }
 
-  fn parse_function_parameter_list(&mut self) -> bool {
+  fn parse_function_parameter_list(&mut self, parameters: &mut Vec<Parameter>) -> bool {
 
     let mut success = true;
 
-    if !self.parse_function_parameter() {
-      success = false;
-      self.skip_to_first_of(vec![TokenType::Fn, TokenType::Comma,
-        TokenType::RParen, TokenType::LBrace]);
-
-      // skipped whole list and either reached start of next block
-      // or start of function -> bail out
-      if self.next_token_is(TokenType::LBrace) ||
-         self.next_token_is(TokenType::Fn) {
-        return false;
+    match self.parse_function_parameter() {
+      Some(parameter) => parameters.push(parameter),
+      None => {
+        success = false;
+        self.skip_to_first_of(vec![TokenType::Fn, TokenType::Comma,
+          TokenType::RParen, TokenType::LBrace]);
+
+        // skipped whole list and either reached start of next block
+        // or start of function -> bail out
+        if self.next_token_is(TokenType::LBrace) ||
+           self.next_token_is(TokenType::Fn) {
+          return false;
+        }
       }
     }
 
-    self.parse_additional_parameters() && success
+    self.parse_additional_parameters(parameters) && success
   }
 
-  fn parse_additional_parameters(&mut self) -> bool {
+  fn parse_additional_parameters(&mut self, parameters: &mut Vec<Parameter>) -> bool {
     if self.next_token_is(TokenType::RParen) {
       return true;
     }
@@ -180,157 +261,248 @@ impl Parser {
         TokenType::RParen, TokenType::LBrace]);
 
       if self.next_token_is(TokenType::Comma) {
-        self.parse_additional_parameters();
+        self.parse_additional_parameters(parameters);
       }
 
       return false;
     }
 
     let mut success = true;
-    if !self.parse_function_parameter() {
-      success = false;
-      self.skip_to_first_of(vec![TokenType::Fn, TokenType::Comma,
-        TokenType::RParen, TokenType::LBrace]);
-      if !self.next_token_is(TokenType::Comma) {
-          return false;
+    match self.parse_function_parameter() {
+      Some(parameter) => parameters.push(parameter),
+      None => {
+        success = false;
+        self.skip_to_first_of(vec![TokenType::Fn, TokenType::Comma,
+          TokenType::RParen, TokenType::LBrace]);
+        if !self.next_token_is(TokenType::Comma) {
+            return false;
+        }
       }
     }
 
-    self.parse_additional_parameters() && success
+    self.parse_additional_parameters(parameters) && success
   }
 
 
-  fn parse_function_parameter(&mut self) -> bool {
+  fn parse_function_parameter(&mut self) -> Option<Parameter> {
 
-    if !self.expect(TokenType::Identifier) {
-      return false;
-    }
+    let name_token = match self.expect_token(TokenType::Identifier) {
+      Some(token) => token,
+      None => return None,
+    };
 
     if !self.expect(TokenType::Colon) {
-      return false;
+      return None;
     }
 
-    self.expect(TokenType::VarType)
+    match self.expect_token(TokenType::VarType) {
+      Some(type_token) => Some(Parameter {
+        name: identifier_index(&name_token),
+        param_type: var_type(&type_token),
+        pos: Position::from_token(&name_token),
+      }),
+      None => None,
+    }
   }
 
 
-  fn parse_optional_return_type(&mut self) -> bool {
+  fn parse_optional_return_type(&mut self, header: &mut FunctionHeader) -> bool {
 
     if self.next_token_is(TokenType::Colon) {
       self.tokens.next();
 
-      if !self.expect(TokenType::VarType) {
-        return false;
+      match self.expect_token(TokenType::VarType) {
+        Some(type_token) => header.return_type = var_type(&type_token),
+        None => return false,
       }
     }
 
     true
   }
 
-  fn parse_block(&mut self) -> bool {
+  fn parse_block(&mut self) -> Option<Block> {
 
-    if !self.expect(TokenType::LBrace) {
-      return false;
-    }
-    let success = self.parse_statements();
+    let pos = match self.expect_token(TokenType::LBrace) {
+      Some(token) => Position::from_token(&token),
+      None => return None,
+    };
+
+    let mut statements = vec![];
+    let success = self.parse_statements(&mut statements);
 
-    self.expect(TokenType::RBrace) && success
+    if self.expect(TokenType::RBrace) && success {
+      Some(Block { statements: statements, pos: pos })
+    } else {
+      None
+    }
   }
 
-  fn parse_statements(&mut self) -> bool {
+  fn parse_statements(&mut self, statements: &mut Vec<Statement>) -> bool {
     if self.next_token_is(TokenType::RBrace) {
       return true;
     }
     let mut success = true;
-    if !self.parse_statement() {
-      success = false;
-      self.skip_to_first_of(vec![TokenType::LBrace, TokenType::RBrace, TokenType::SemiColon, TokenType::Fn]);
+    match self.parse_statement() {
+      Some(statement) => statements.push(statement),
+      None => {
+        success = false;
+        self.skip_to_first_of(vec![TokenType::LBrace, TokenType::RBrace, TokenType::SemiColon, TokenType::Fn]);
 
-      // next token is not rbrace or semicolon (so either fn or end-of-file -> bail out)
-      if !self.next_token_is(TokenType::LBrace) &&
-         !self.next_token_is(TokenType::RBrace) &&
-         !self.next_token_is(TokenType::SemiColon) {
-        return false;
+        // next token is not rbrace or semicolon (so either fn or end-of-file -> bail out)
+        if !self.next_token_is(TokenType::LBrace) &&
+           !self.next_token_is(TokenType::RBrace) &&
+           !self.next_token_is(TokenType::SemiColon) {
+          return false;
+        }
       }
     }
 
-    self.parse_statements() && success
+    self.parse_statements(statements) && success
   }
 
 
-  fn parse_statement(&mut self) -> bool {
+  fn parse_statement(&mut self) -> Option<Statement> {
     match self.tokens.peek() {
       Some(token) => {
         match (token.t_type) {
-        TokenType::SemiColon => { self.tokens.next(); true } // empty statement
-        TokenType::Let => self.parse_variable_declaration() && self.expect(TokenType::SemiColon),
-        TokenType::LBrace => self.parse_block(),
-        TokenType::Identifier =>
-            self.parse_variable_assignment_or_function_call() && self.expect(TokenType::SemiColon),
+        TokenType::SemiColon => { // empty statement
+          self.tokens.next();
+          Some(Statement::Empty(Position::from_token(&token)))
+        }
+        TokenType::Let => match self.parse_variable_declaration() {
+          Some(declaration) => self.expect_semicolon_after(Statement::VariableDeclaration(declaration)),
+          None => None,
+        },
+        TokenType::LBrace => match self.parse_block() {
+          Some(block) => Some(Statement::Block(block)),
+          None => None,
+        },
+        TokenType::Identifier => match self.parse_variable_assignment_or_function_call() {
+          Some(statement) => self.expect_semicolon_after(statement),
+          None => None,
+        },
         TokenType::For => self.parse_for_loop(),
         TokenType::If => self.parse_if_statement(),
+        TokenType::Return => match self.parse_return_statement() {
+          Some(statement) => self.expect_semicolon_after(statement),
+          None => None,
+        },
         _ => {
           let token_str = self.tokens.to_string(&token);
           self.register_error(
             format!("Unexpected token {} when expecting start of statement",
               token_str),
             &token);
-            false
+            None
           }
         }
         },
-      None => { false /* empty statement list, end. Let the above level handle it*/ }
+      None => { None /* empty statement list, end. Let the above level handle it*/ }
     }
   }
 
+  fn expect_semicolon_after(&mut self, statement: Statement) -> Option<Statement> {
+    if self.expect(TokenType::SemiColon) {
+      Some(statement)
+    } else {
+      None
+    }
+  }
 
-  fn parse_variable_declaration(&mut self) -> bool {
-    if !self.expect(TokenType::Let) {
-      self.skip_to_first_of(vec![TokenType::RBrace, TokenType::SemiColon]);
-      return false;
+
+  fn parse_return_statement(&mut self) -> Option<Statement> {
+    let return_token = match self.expect_token(TokenType::Return) {
+      Some(token) => token,
+      None => return None,
+    };
+
+    let pos = Position::from_token(&return_token);
+    if self.next_token_is(TokenType::SemiColon) {
+      return Some(Statement::Return(ReturnStatement { value: None, pos: pos }));
     }
-    if !self.expect(TokenType::Identifier) {
-      self.skip_to_first_of(vec![TokenType::RBrace, TokenType::SemiColon]);
-      return false;
+
+    match self.parse_expression() {
+      Some(expression) => Some(Statement::Return(ReturnStatement { value: Some(expression), pos: pos })),
+      None => None,
     }
-    if !self.expect(TokenType::Colon) {
-      self.skip_to_first_of(vec![TokenType::RBrace, TokenType::SemiColon]);
-      return false;
+  }
+
+  fn parse_variable_declaration(&mut self) -> Option<VariableDeclaration> {
+    match self.expect_token(TokenType::Let) {
+      Some(token) => self.parse_variable_declaration_body(&token, false),
+      None => {
+        self.skip_to_first_of(vec![TokenType::RBrace, TokenType::SemiColon]);
+        None
+      }
     }
+  }
+
+  // parses the declaration after the let\const token
+  fn parse_variable_declaration_body(&mut self, start_token: &SyntaxToken,
+    is_constant: bool) -> Option<VariableDeclaration> {
 
-    if !self.expect(TokenType::VarType) {
+    let name_token = match self.expect_token(TokenType::Identifier) {
+      Some(token) => token,
+      None => {
+        self.skip_to_first_of(vec![TokenType::RBrace, TokenType::SemiColon]);
+        return None;
+      }
+    };
+
+    if !self.expect(TokenType::Colon) {
       self.skip_to_first_of(vec![TokenType::RBrace, TokenType::SemiColon]);
-      return false;
+      return None;
     }
 
+    let type_token = match self.expect_token(TokenType::VarType) {
+      Some(token) => token,
+      None => {
+        self.skip_to_first_of(vec![TokenType::RBrace, TokenType::SemiColon]);
+        return None;
+      }
+    };
+
     if !self.expect(TokenType::Assign) {
       self.skip_to_first_of(vec![TokenType::RBrace, TokenType::SemiColon]);
-      return false;
+      return None;
     }
 
-    self.parse_expression();
+    // errors in expression do not affect the declaration structure itself
+    let pos = Position::from_token(start_token);
+    let initializer = match self.parse_expression() {
+      Some(expression) => expression,
+      None => error_expression(pos),
+    };
 
-    true
+    Some(VariableDeclaration {
+      name: identifier_index(&name_token),
+      var_type: var_type(&type_token),
+      initializer: initializer,
+      is_constant: is_constant,
+      pos: pos,
+    })
   }
 
-  fn parse_variable_assignment_or_function_call(&mut self) -> bool {
+  fn parse_variable_assignment_or_function_call(&mut self) -> Option<Statement> {
     match self.tokens.peek_2() {
       Some(token) => match token.t_type {
         TokenType::LParen => {
-          if !self.parse_function_call() {
-            self.skip_to_first_of(vec![TokenType::SemiColon]);
-            false
-          } else {
-            true
+          match self.parse_function_call() {
+            Some(call) => Some(Statement::FunctionCall(call)),
+            None => {
+              self.skip_to_first_of(vec![TokenType::SemiColon]);
+              None
+            }
+          }
+        },
+        TokenType::Assign => match self.parse_variable_assignment() {
+          Some(assignment) => Some(Statement::Assignment(assignment)),
+          None => {
+            self.skip_to_first_of(vec![TokenType::SemiColon, TokenType::LBrace, TokenType::RBrace,
+              TokenType::Fn]);
+            None
           }
         },
-        TokenType::Assign => if !self.parse_variable_assignment() {
-          self.skip_to_first_of(vec![TokenType::SemiColon, TokenType::LBrace, TokenType::RBrace,
-            TokenType::Fn]);
-          false
-          } else {
-            true
-          },
         _ => {
           let token_str = self.tokens.to_string(&token);
 
@@ -340,61 +512,70 @@ impl Parser {
               token_str, TokenType::Assign, TokenType::LParen),
             &token,
             vec![TokenType::RBrace, TokenType::SemiColon]);
-            false
+            None
         }
       },
-      _ => { self.errors.push("Unexpected end-of-line".to_string()); false },
+      _ => { self.errors.push("Unexpected end-of-line".to_string()); None },
     }
   }
 
-  fn parse_function_call(&mut self) -> bool {
+  fn parse_function_call(&mut self) -> Option<FunctionCall> {
 
-    if !self.expect(TokenType::Identifier) {
-      return false;
-    }
+    let name_token = match self.expect_token(TokenType::Identifier) {
+      Some(token) => token,
+      None => return None,
+    };
 
     if !self.expect(TokenType::LParen) {
-      return false;
+      return None;
     }
 
-    if !self.parse_optional_function_call_argument_list() {
-        return false;
+    let mut arguments = vec![];
+    if !self.parse_optional_function_call_argument_list(&mut arguments) {
+        return None;
     }
 
     if !self.expect(TokenType::RParen) {
       self.skip_to_first_of(vec![TokenType::SemiColon]);
-      return false;
+      return None;
     }
 
-    true
+    Some(FunctionCall {
+      name: identifier_index(&name_token),
+      arguments: arguments,
+      pos: Position::from_token(&name_token),
+    })
   }
 
-  fn parse_optional_function_call_argument_list(&mut self) -> bool {
+  fn parse_optional_function_call_argument_list(&mut self, arguments: &mut Vec<Expression>) -> bool {
     if self.next_token_is(TokenType::RParen) {
       return true;
     }
 
-    self.parse_function_call_argument_list()
+    self.parse_function_call_argument_list(arguments)
   }
 
-  fn parse_function_call_argument_list(&mut self) -> bool {
+  fn parse_function_call_argument_list(&mut self, arguments: &mut Vec<Expression>) -> bool {
     let mut success = true;
 
-    if !self.parse_expression() {
-      success = false;
-      self.skip_to_first_of(vec![TokenType::SemiColon, TokenType::Comma,
-         TokenType::RBrace, TokenType::LBrace, TokenType::Fn]);
+    match self.parse_expression() {
+      Some(expression) => arguments.push(expression),
+      None => {
+        success = false;
+        self.skip_to_first_of(vec![TokenType::SemiColon, TokenType::Comma,
+           TokenType::RBrace, TokenType::LBrace, TokenType::Fn]);
 
-      // if we reached comma, continue parse, otherwise bail out
-      if !self.next_token_is(TokenType::Comma) {
-        return false;
+        // if we reached comma, continue parse, otherwise bail out
+        if !self.next_token_is(TokenType::Comma) {
+          return false;
+        }
       }
     }
 
-    self.parse_additional_function_call_arguments() && success
+    self.parse_additional_function_call_arguments(arguments) && success
   }
 
-  fn parse_additional_function_call_arguments(&mut self) -> bool {
+  fn parse_additional_function_call_arguments(&mut self, arguments: &mut Vec<Expression>) -> bool {
     if self.next_token_is(TokenType::RParen) {
       return true;
     }
@@ -406,117 +587,150 @@ impl Parser {
         TokenType::RBrace, TokenType::LBrace, TokenType::Fn]);
 
       if self.next_token_is(TokenType::Comma) {
-        self.parse_additional_function_call_arguments();
+        self.parse_additional_function_call_arguments(arguments);
       }
 
       return false;
     }
 
-    if !self.parse_expression() {
-      success = false;
-      self.skip_to_first_of(vec![TokenType::SemiColon, TokenType::Comma,
-        TokenType::RBrace, TokenType::LBrace, TokenType::Fn]);
-      if !self.next_token_is(TokenType::Comma) {
-        return false;
+    match self.parse_expression() {
+      Some(expression) => arguments.push(expression),
+      None => {
+        success = false;
+        self.skip_to_first_of(vec![TokenType::SemiColon, TokenType::Comma,
+          TokenType::RBrace, TokenType::LBrace, TokenType::Fn]);
+        if !self.next_token_is(TokenType::Comma) {
+          return false;
+        }
       }
     }
 
-    self.parse_additional_function_call_arguments() && success
+    self.parse_additional_function_call_arguments(arguments) && success
   }
 
-  fn parse_variable_assignment(&mut self) -> bool {
+  fn parse_variable_assignment(&mut self) -> Option<Assignment> {
 
 
-    if !self.expect(TokenType::Identifier) {
-      return false;
-    }
+    let name_token = match self.expect_token(TokenType::Identifier) {
+      Some(token) => token,
+      None => return None,
+    };
 
     if !self.expect(TokenType::Assign) {
-      return false;
+      return None;
     }
 
-    if !self.parse_expression() {
-      return false;
+    match self.parse_expression() {
+      Some(expression) => Some(Assignment {
+        name: identifier_index(&name_token),
+        value: expression,
+        pos: Position::from_token(&name_token),
+      }),
+      None => None,
     }
-
-    true
   }
 
-  fn parse_for_loop(&mut self) -> bool {
-    if !self.expect(TokenType::For) {
-      return false;
-    }
+  fn parse_for_loop(&mut self) -> Option<Statement> {
+    let for_token = match self.expect_token(TokenType::For) {
+      Some(token) => token,
+      None => return None,
+    };
 
     if !self.expect(TokenType::LParen) {
 
       self.skip_to_first_of(vec![TokenType::LBrace, TokenType::SemiColon, TokenType::Fn]);
-      return false;
+      return None;
     }
 
-
+    let mut init = None;
     if !self.next_token_is(TokenType::SemiColon) {
-      self.parse_optional_variable_declaration_or_assignment();
+      init = self.parse_optional_variable_declaration_or_assignment();
     }
 
     self.expect(TokenType::SemiColon);
 
+    let mut condition = None;
     if !self.next_token_is(TokenType::SemiColon) {
-      self.parse_expression();
+      condition = self.parse_expression();
     }
 
     self.expect(TokenType::SemiColon);
 
+    let mut update = None;
     if !self.next_token_is(TokenType::RParen) {
-      self.parse_optional_variable_assignment();
+      update = self.parse_optional_variable_assignment();
     }
 
     if !self.expect(TokenType::RParen) {
       self.skip_to_first_of(vec![TokenType::LBrace, TokenType::SemiColon, TokenType::Fn]);
-      return false;
+      return None;
     }
 
-    self.parse_block()
+    match self.parse_block() {
+      Some(block) => Some(Statement::For(ForLoop {
+        init: init,
+        condition: condition,
+        update: update,
+        body: block,
+        pos: Position::from_token(&for_token),
+      })),
+      None => None,
+    }
   }
 
-  fn parse_optional_variable_declaration_or_assignment(&mut self) -> bool {
+  fn parse_optional_variable_declaration_or_assignment(&mut self) -> Option<Box<Statement>> {
     if self.next_token_is(TokenType::Let) {
-      self.parse_variable_declaration()
+      match self.parse_variable_declaration() {
+        Some(declaration) => Some(Box::new(Statement::VariableDeclaration(declaration))),
+        None => None,
+      }
       } else {
-        self.parse_optional_variable_assignment()
+        match self.parse_optional_variable_assignment() {
+          Some(assignment) => Some(Box::new(Statement::Assignment(assignment))),
+          None => None,
+        }
       }
     }
 
-  fn parse_optional_variable_assignment(&mut self) -> bool {
+  fn parse_optional_variable_assignment(&mut self) -> Option<Assignment> {
     if self.next_token_is(TokenType::Identifier) {
       self.parse_variable_assignment()
     } else {
-      true
+      None
     }
   }
 
 
 
-  fn parse_if_statement(&mut self) -> bool {
+  fn parse_if_statement(&mut self) -> Option<Statement> {
 
-    if !self.expect(TokenType::If) {
-      return false;
-    }
+    let if_token = match self.expect_token(TokenType::If) {
+      Some(token) => token,
+      None => return None,
+    };
 
     let mut success = true;
+    let mut else_ifs = vec![];
+    let mut else_block = None;
 
-    if !self.expect(TokenType::LParen) || !self.parse_expression() {
+    let mut condition = None;
+    if self.expect(TokenType::LParen) {
+      condition = self.parse_expression();
+    }
+
+    if condition.is_none() {
       self.skip_to_first_of(vec![TokenType::RParen, TokenType::LBrace, TokenType::RBrace, TokenType::SemiColon,
         TokenType::Fn, TokenType::Else, TokenType::ElseIf]);
 
       // if we found else if\else, parse them and bail out as structure is preeeetty broken
       if self.next_token_is(TokenType::ElseIf) || self.next_token_is(TokenType::Else) {
-        self.parse_optional_else_if_blocks();
-        self.parse_optional_else_block();
-        return false;
+        self.parse_optional_else_if_blocks(&mut else_ifs);
+        self.parse_optional_else_block(&mut else_block);
+        return None;
       } else if self.next_token_is(TokenType::SemiColon) || self.next_token_is(TokenType::Fn) {
         // if we found semicolon or function start, structure is pretty broken. Bail out and see
         // if higher level can make any sense of this
-        return false;
+        return None;
       }
 
       success = false;
@@ -525,78 +739,127 @@ impl Parser {
     if !self.expect(TokenType::RParen) {
       self.skip_to_first_of(vec![TokenType::LBrace, TokenType::RBrace, TokenType::SemiColon, TokenType::Fn]);
       if !self.next_token_is(TokenType::LBrace) {
-        return false;
+        return None;
       }
       success = false;
     }
 
-    if !self.parse_block() {
+    let block = self.parse_block();
+    if block.is_none() {
       self.skip_to_first_of(vec![TokenType::LBrace, TokenType::RBrace, TokenType::ElseIf,
           TokenType::Else, TokenType::SemiColon, TokenType::Fn]);
 
       if !self.next_token_is(TokenType::Else) && !self.next_token_is(TokenType::ElseIf) {
-        return false;
+        return None;
       }
       success = false;
     }
 
-    if !self.parse_optional_else_if_blocks() {
+    if !self.parse_optional_else_if_blocks(&mut else_ifs) {
       self.skip_to_first_of(vec![TokenType::LBrace, TokenType::RBrace, TokenType::Else,
          TokenType::SemiColon, TokenType::Fn, TokenType::ElseIf]);
     }
 
-    self.parse_optional_else_block() && success
+    if !self.parse_optional_else_block(&mut else_block) || !success {
+      return None;
+    }
+
+    match (condition, block) {
+      (Some(condition), Some(block)) => Some(Statement::If(IfStatement {
+        condition: condition,
+        block: block,
+        else_ifs: else_ifs,
+        else_block: else_block,
+        pos: Position::from_token(&if_token),
+      })),
+      _ => None,
+    }
   }
 
-  fn parse_optional_else_if_blocks(&mut self) -> bool {
+  fn parse_optional_else_if_blocks(&mut self, else_ifs: &mut Vec<ElseIf>) -> bool {
     if !self.next_token_is(TokenType::ElseIf) {
       return true;
     }
-    self.tokens.next();
 
-    self.expect(TokenType::LParen) && self.parse_expression() && self.expect(TokenType::RParen) &&
-      self.parse_block() && self.parse_optional_else_if_blocks()
+    let pos = match self.tokens.next() {
+      Some(token) => Position::from_token(&token),
+      None => return false,
+    };
+
+    if !self.expect(TokenType::LParen) {
+      return false;
+    }
+
+    let condition = match self.parse_expression() {
+      Some(expression) => expression,
+      None => return false,
+    };
+
+    if !self.expect(TokenType::RParen) {
+      return false;
+    }
+
+    match self.parse_block() {
+      Some(block) => else_ifs.push(ElseIf { condition: condition, block: block, pos: pos }),
+      None => return false,
+    }
+
+    self.parse_optional_else_if_blocks(else_ifs)
   }
 
-  fn parse_optional_else_block(&mut self) -> bool {
+  fn parse_optional_else_block(&mut self, else_block: &mut Option<Block>) -> bool {
     if !self.next_token_is(TokenType::Else) {
       return true;
     }
 
     self.tokens.next();
 
-    if !self.parse_block() {
-      return false;
+    match self.parse_block() {
+      Some(block) => {
+        *else_block = Some(block);
+        true
+      },
+      None => false,
     }
-
-    true
   }
 
-  fn parse_expression(&mut self) -> bool {
-    self.parse_expression_2() && self.parse_equality_expression()
+  fn parse_expression(&mut self) -> Option<Expression> {
+    match self.parse_expression_2() {
+      Some(left) => self.parse_equality_expression(left),
+      None => None,
+    }
   }
 
-  fn parse_equality_expression(&mut self) -> bool {
+  fn parse_equality_expression(&mut self, left: Expression) -> Option<Expression> {
     match self.tokens.peek() {
       Some(token) => {
         if token.t_type == TokenType::CompOp && token.t_subtype == TokenSubType::Equals {
           self.tokens.next();
-          self.parse_expression_2() && self.parse_equality_expression()
+          match self.parse_expression_2() {
+            Some(right) => {
+              let expression = binary_expression(&token, left, right);
+              self.parse_equality_expression(expression)
+            },
+            None => None,
+          }
         } else {
-          true
+          Some(left)
         }
       },
-      None => { true },
+      None => { Some(left) },
     }
   }
 
 
 
-  fn parse_expression_2(&mut self) -> bool {
-    self.parse_expression_3() && self.parse_less_more_expression()
+  fn parse_expression_2(&mut self) -> Option<Expression> {
+    match self.parse_expression_3() {
+      Some(left) => self.parse_less_more_expression(left),
+      None => None,
+    }
   }
 
-  fn parse_less_more_expression(&mut self) -> bool {
+  fn parse_less_more_expression(&mut self, left: Expression) -> Option<Expression> {
     match self.tokens.peek() {
       Some(token) => {
         if token.t_type == TokenType::CompOp {
@@ -604,113 +867,200 @@ impl Parser {
             TokenSubType::Lesser | TokenSubType::Greater | TokenSubType::GreaterOrEq |
             TokenSubType::LesserOrEq => {
               self.tokens.next();
-              self.parse_expression_3() && self.parse_less_more_expression()
+              match self.parse_expression_3() {
+                Some(right) => {
+                  let expression = binary_expression(&token, left, right);
+                  self.parse_less_more_expression(expression)
+                },
+                None => None,
+              }
             },
-            _ => true
+            _ => Some(left)
           }
         } else {
-          true
+          Some(left)
         }
       }
-      None => { true },
+      None => { Some(left) },
     }
   }
 
   // see grammar for better description. I need to figure out better naming
-  fn parse_expression_3(&mut self) -> bool {
-    self.parse_term() && self.parse_plus_minus_expression()
+  fn parse_expression_3(&mut self) -> Option<Expression> {
+    match self.parse_term() {
+      Some(left) => self.parse_plus_minus_expression(left),
+      None => None,
+    }
   }
 
-  fn parse_plus_minus_expression(&mut self) -> bool {
+  fn parse_plus_minus_expression(&mut self, left: Expression) -> Option<Expression> {
     match self.tokens.peek() {
       Some(token) => match token.t_subtype {
         TokenSubType::Plus | TokenSubType::Minus => {
           self.tokens.next();
-          self.parse_term() && self.parse_plus_minus_expression()
+          match self.parse_term() {
+            Some(right) => {
+              let expression = binary_expression(&token, left, right);
+              self.parse_plus_minus_expression(expression)
+            },
+            None => None,
+          }
         },
-        _ => { true }
+        _ => { Some(left) }
       },
-      None => { true },
+      None => { Some(left) },
     }
   }
 
-  fn parse_term(&mut self) -> bool {
-    self.parse_factor() && self.parse_mult_div_term()
+  fn parse_term(&mut self) -> Option<Expression> {
+    match self.parse_cast_expression() {
+      Some(left) => self.parse_mult_div_term(left),
+      None => None,
+    }
   }
 
-  fn parse_mult_div_term(&mut self) -> bool {
+  fn parse_mult_div_term(&mut self, left: Expression) -> Option<Expression> {
     match self.tokens.peek() {
       Some(token) => match token.t_subtype {
         TokenSubType::Multiply | TokenSubType::Divide => {
           self.tokens.next();
-          self.parse_factor() && self.parse_mult_div_term()
+          match self.parse_cast_expression() {
+            Some(right) => {
+              let expression = binary_expression(&token, left, right);
+              self.parse_mult_div_term(expression)
+            },
+            None => None,
+          }
           },
-        _ => { true }
+        _ => { Some(left) }
         },
-        None => { true },
+        None => { Some(left) },
       }
   }
 
-  fn parse_factor(&mut self) -> bool {
-    // helper function
-    let factor_err =  |&: parser:&mut Parser, token:&SyntaxToken| -> bool {
-
-      let token_str = parser.tokens.to_string(token);
+  fn parse_cast_expression(&mut self) -> Option<Expression> {
+    match self.parse_factor() {
+      Some(expression) => self.parse_optional_cast(expression),
+      None => None,
+    }
+  }
 
-      parser.register_error(
-        format!("Invalid token {}. Expected an expression",
-                 token_str),
-      token);
-      false
+  fn parse_optional_cast(&mut self, expression: Expression) -> Option<Expression> {
+    let as_token = match self.tokens.peek() {
+      Some(token) => {
+        if token.t_type != TokenType::As {
+          return Some(expression);
+        }
+        token
+      },
+      None => return Some(expression),
     };
+    self.tokens.next();
 
+    match self.expect_token(TokenType::VarType) {
+      Some(type_token) => {
+        let cast = Expression::new(
+          ExpressionKind::Cast(Box::new(expression), var_type(&type_token)),
+          Position::from_token(&as_token));
+        self.parse_optional_cast(cast)
+      },
+      None => None,
+    }
+  }
+
+  fn parse_factor(&mut self) -> Option<Expression> {
     match self.tokens.peek() {
       Some(token) => match token.t_type {
         // check if op is + or -, and if it is followed by a number. If so, accept.
         TokenType::ArithOp => {
           self.tokens.next();
-          self.parse_plus_minus_number(&token, factor_err)
+          self.parse_plus_minus_number(&token)
+        },
+        TokenType::Identifier => {
+          if self.next_token_after_peeked_is(TokenType::LParen) {
+            match self.parse_function_call() {
+              Some(call) => Some(Expression::new(ExpressionKind::Call(call),
+                Position::from_token(&token))),
+              None => None,
+            }
+          } else {
+            self.tokens.next();
+            Some(Expression::new(
+              ExpressionKind::Variable(identifier_index(&token)),
+              Position::from_token(&token)))
+          }
+        },
+        TokenType::Number | TokenType::Text | TokenType::Boolean => {
+          self.tokens.next();
+          Some(Expression::new(
+            ExpressionKind::Literal(literal(&token)),
+            Position::from_token(&token)))
         },
-        TokenType::Identifier => { self.tokens.next(); true },
-        TokenType::Number | TokenType::Text | TokenType::Boolean => { self.tokens.next(); true },
         TokenType::LParen => {
           self.tokens.next();
-          self.parse_expression();
-          self.expect(TokenType::RParen)
+          let expression = self.parse_expression();
+          if self.expect(TokenType::RParen) {
+            // errors inside the parenthesis have already been reported
+            Some(expression.unwrap_or(error_expression(Position::from_token(&token))))
+          } else {
+            None
+          }
         }
-        _ => factor_err(self, &token),
+        _ => self.factor_error(&token),
       },
       None => {
         self.errors.push(
           "Unexpected end of file when parsing expression".to_string());
-          false
+          None
         },
     }
   }
 
-  fn parse_plus_minus_number<F: Fn(&mut Parser, &SyntaxToken) -> bool>
-        (&mut self, token:&SyntaxToken, factor_err: F) -> bool {
+  fn parse_plus_minus_number(&mut self, token:&SyntaxToken) -> Option<Expression> {
 
     match token.t_subtype {
       TokenSubType::Plus | TokenSubType::Minus => {
         match self.tokens.peek() {
           Some(peek_token) => match peek_token.t_type {
-            TokenType::Number => { self.tokens.next(); true },
-            _ => factor_err(self, token),
+            TokenType::Number => {
+              self.tokens.next();
+              let value = literal(&peek_token);
+              let value = if token.t_subtype == TokenSubType::Minus {
+                negate_literal(value)
+              } else {
+                value
+              };
+              Some(Expression::new(ExpressionKind::Literal(value), Position::from_token(token)))
             },
-            None => factor_err(self, token),
+            _ => self.factor_error(token),
+            },
+            None => self.factor_error(token),
           }
         }
-        _ => factor_err(self, token),
+        _ => self.factor_error(token),
       }
   }
 
+  fn factor_error(&mut self, token: &SyntaxToken) -> Option<Expression> {
+    let token_str = self.tokens.to_string(token);
+
+    self.register_error(
+      format!("Invalid token {}. Expected an expression",
+               token_str),
+    token);
+    None
+  }
+
   fn expect(&mut self, expected_type: TokenType) -> bool {
+    self.expect_token(expected_type).is_some()
+  }
+
+  fn expect_token(&mut self, expected_type: TokenType) -> Option<SyntaxToken> {
     match self.tokens.peek() {
       Some(token) => {
         if expected_type == token.t_type {
           self.tokens.next();
-          true
+          Some(token)
         } else {
           let token_str = self.tokens.to_string(&token);
           self.register_error(
@@ -718,7 +1068,7 @@ impl Parser {
               expected_type, token_str),
             &token);
 
-          false
+          None
         }
       },
       None => {
@@ -726,7 +1076,7 @@ impl Parser {
           format!("Expected token of type {}. Instead found end-of-file",
             expected_type));
 
-        false
+        None
       },
     }
   }
@@ -740,6 +1090,15 @@ impl Parser {
     }
   }
 
+  fn next_token_after_peeked_is(&mut self, token_type: TokenType) -> bool {
+    match self.tokens.peek_2() {
+      Some(token) => {
+        token.t_type == token_type
+      }
+      None => false,
+    }
+  }
+
   fn register_error_and_skip_to(&mut self, msg: String, err_token: &SyntaxToken,
      skip_tokens: Vec<TokenType>) {
 
@@ -767,3 +1126,54 @@ impl Parser {
     }
   }
 }
+
+fn identifier_index(token: &SyntaxToken) -> usize {
+  match token.t_subtype {
+    TokenSubType::Identifier(index) => index,
+    _ => panic!("Internal compiler error: identifier token without text index"),
+  }
+}
+
+fn var_type(token: &SyntaxToken) -> Type {
+  match Type::from_subtype(token.t_subtype) {
+    Some(var_type) => var_type,
+    None => panic!("Internal compiler error: type token with invalid subtype"),
+  }
+}
+
+fn literal(token: &SyntaxToken) -> Literal {
+  match token.t_subtype {
+    TokenSubType::IntegerNumber(value) => Literal::Integer(value),
+    TokenSubType::FloatNumber(value) => Literal::Float(value),
+    TokenSubType::DoubleNumber(value) => Literal::Double(value),
+    TokenSubType::BooleanValue(value) => Literal::Boolean(value),
+    TokenSubType::Text(index) => Literal::Text(index),
+    _ => panic!("Internal compiler error: constant token with invalid subtype"),
+  }
+}
+
+fn negate_literal(value: Literal) -> Literal {
+  match value {
+    Literal::Integer(value) => Literal::Integer(-value),
+    Literal::Float(value) => Literal::Float(-value),
+    Literal::Double(value) => Literal::Double(-value),
+    _ => value,
+  }
+}
+
+fn binary_expression(operator_token: &SyntaxToken, left: Expression, right: Expression) -> Expression {
+  let operator = match BinaryOperator::from_subtype(operator_token.t_subtype) {
+    Some(operator) => operator,
+    None => panic!("Internal compiler error: invalid binary operator"),
+  };
+
+  Expression::new(
+    ExpressionKind::Binary(operator, Box::new(left), Box::new(right)),
+    Position::from_token(operator_token))
+}
+
+// placeholder for expressions that failed to parse. Never visible outside the parser,
+// as syntax tree is discarded if errors were encountered
+fn error_expression(pos: Position) -> Expression {
+  Expression::new(ExpressionKind::Literal(Literal::Integer(0)), pos)
+}
@@ -0,0 +1,326 @@
+use std::collections::HashMap;
+use ast::Program;
+use ast::Function;
+use ast::Block;
+use ast::Statement;
+use ast::VariableDeclaration;
+use ast::Assignment;
+use ast::FunctionCall;
+use ast::Expression;
+use ast::ExpressionKind;
+use ast::Position;
+use symbol_table::SymbolTable;
+use builtins;
+
+/*
+  Name resolution. Collects the global declarations and function names, checks
+  that global initializers are compile-time constant expressions and walks
+  every function while keeping track of the names visible in the current scope.
+
+  Scoping rules:
+    -globals and functions are visible everywhere. Functions and variables
+     live in separate namespaces
+    -function parameters and the top level statements of the function body
+     share a scope
+    -every block opens a new scope
+    -variable declared in for loop initialization is only visible inside the loop
+    -a declaration may shadow a declaration of an enclosing scope, but not one in
+     the same scope. Shadowing a global produces a warning
+
+  Returns the warnings on success. On failure, the errors are returned,
+  followed by any warnings that were generated.
+*/
+
+pub fn resolve(program: &Program) -> Result<Vec<String>, Vec<String>> {
+  let mut resolver = Resolver::new(program);
+  resolver.resolve()
+}
+
+#[derive(Clone, Copy)]
+struct Symbol {
+  pos: Position,
+  is_constant: bool,
+}
+
+struct Resolver<'a> {
+  program: &'a Program,
+  globals: HashMap<usize, Symbol>,
+  functions: HashMap<usize, Position>,
+  locals: SymbolTable<Symbol>,
+  errors: Vec<String>,
+  warnings: Vec<String>,
+}
+
+impl<'a> Resolver<'a> {
+  fn new(program: &'a Program) -> Resolver<'a> {
+    Resolver {
+      program: program,
+      globals: HashMap::new(),
+      functions: HashMap::new(),
+      locals: SymbolTable::new(),
+      errors: vec![],
+      warnings: vec![],
+    }
+  }
+
+  fn resolve(&mut self) -> Result<Vec<String>, Vec<String>> {
+    let program = self.program;
+    for global in program.globals.iter() {
+      self.resolve_global(global);
+    }
+
+    // functions may be called before they are declared, so collect them first
+    for function in program.functions.iter() {
+      self.declare_function(function);
+    }
+
+    for function in program.functions.iter() {
+      self.resolve_function(function);
+    }
+
+    if self.errors.is_empty() {
+      Ok(self.warnings.clone())
+    } else {
+      let mut messages = self.errors.clone();
+      messages.push_all(self.warnings.as_slice());
+      Err(messages)
+    }
+  }
+
+  fn resolve_global(&mut self, global: &VariableDeclaration) {
+    // initializer may only refer to constants declared before this declaration,
+    // so check it before the global itself becomes visible
+    self.check_constant_expression(&global.initializer);
+
+    let previous = match self.globals.get(&global.name) {
+      Some(symbol) => Some(symbol.pos),
+      None => None,
+    };
+
+    match previous {
+      Some(previous_pos) => {
+        let msg = format!("Global '{}' is already declared at {}",
+          self.program.get_text(global.name), previous_pos);
+        self.register_error(msg, &global.pos);
+      },
+      None => {
+        self.globals.insert(global.name, Symbol { pos: global.pos, is_constant: global.is_constant });
+      }
+    }
+  }
+
+  fn declare_function(&mut self, function: &Function) {
+    let previous = match self.functions.get(&function.name) {
+      Some(pos) => Some(*pos),
+      None => None,
+    };
+
+    match previous {
+      Some(previous_pos) => {
+        let msg = format!("Function '{}' is already declared at {}",
+          self.program.get_text(function.name), previous_pos);
+        self.register_error(msg, &function.pos);
+      },
+      None => { self.functions.insert(function.name, function.pos); },
+    }
+  }
+
+  fn check_constant_expression(&mut self, expression: &Expression) {
+    match expression.kind {
+      ExpressionKind::Literal(..) => { },
+      ExpressionKind::Variable(name) => {
+        let is_constant = match self.globals.get(&name) {
+          Some(symbol) => symbol.is_constant,
+          None => false,
+        };
+
+        if !is_constant {
+          let msg = format!(
+            "Global initializer must be a compile-time constant expression, but '{}' is not a constant declared before this declaration",
+            self.program.get_text(name));
+          self.register_error(msg, &expression.pos);
+        }
+      },
+      ExpressionKind::Binary(_, ref left, ref right) => {
+        self.check_constant_expression(&**left);
+        self.check_constant_expression(&**right);
+      },
+      ExpressionKind::Cast(ref inner, _) => self.check_constant_expression(&**inner),
+      ExpressionKind::Call(..) => {
+        self.register_error(
+          "Global initializer must be a compile-time constant expression, but contains a function call".to_string(),
+          &expression.pos);
+      },
+    }
+  }
+
+  fn resolve_function(&mut self, function: &Function) {
+    self.locals.push_scope();
+
+    for parameter in function.parameters.iter() {
+      match self.locals.lookup_in_current_scope(parameter.name) {
+        Some(previous) => {
+          let msg = format!("Parameter '{}' is already declared at {}",
+            self.program.get_text(parameter.name), previous.pos);
+          self.register_error(msg, &parameter.pos);
+        },
+        None => self.declare_local(parameter.name, &parameter.pos),
+      }
+    }
+
+    // parameters and the top level of the body share the scope
+    for statement in function.body.statements.iter() {
+      self.resolve_statement(statement);
+    }
+
+    self.locals.pop_scope();
+  }
+
+  fn resolve_block(&mut self, block: &Block) {
+    self.locals.push_scope();
+    for statement in block.statements.iter() {
+      self.resolve_statement(statement);
+    }
+    self.locals.pop_scope();
+  }
+
+  fn resolve_statement(&mut self, statement: &Statement) {
+    match *statement {
+      Statement::Block(ref block) => self.resolve_block(block),
+      Statement::VariableDeclaration(ref declaration) => {
+        // variable is not visible in its own initializer
+        self.resolve_expression(&declaration.initializer);
+        self.declare_local(declaration.name, &declaration.pos);
+      },
+      Statement::Assignment(ref assignment) => self.resolve_assignment(assignment),
+      Statement::FunctionCall(ref call) => self.resolve_function_call(call),
+      Statement::For(ref for_loop) => {
+        // variable declared in the init clause is only visible inside the loop
+        self.locals.push_scope();
+        match for_loop.init {
+          Some(ref init) => self.resolve_statement(&**init),
+          None => { },
+        }
+
+        match for_loop.condition {
+          Some(ref condition) => self.resolve_expression(condition),
+          None => { },
+        }
+
+        match for_loop.update {
+          Some(ref update) => self.resolve_assignment(update),
+          None => { },
+        }
+
+        self.resolve_block(&for_loop.body);
+        self.locals.pop_scope();
+      },
+      Statement::If(ref if_statement) => {
+        self.resolve_expression(&if_statement.condition);
+        self.resolve_block(&if_statement.block);
+        for else_if in if_statement.else_ifs.iter() {
+          self.resolve_expression(&else_if.condition);
+          self.resolve_block(&else_if.block);
+        }
+
+        match if_statement.else_block {
+          Some(ref block) => self.resolve_block(block),
+          None => { },
+        }
+      },
+      Statement::Return(ref return_statement) => {
+        match return_statement.value {
+          Some(ref value) => self.resolve_expression(value),
+          None => { },
+        }
+      },
+      Statement::Empty(..) => { },
+    }
+  }
+
+  fn resolve_assignment(&mut self, assignment: &Assignment) {
+    self.resolve_expression(&assignment.value);
+    match self.lookup(assignment.name) {
+      Some(symbol) => {
+        if symbol.is_constant {
+          let msg = format!("Cannot assign to constant '{}' declared at {}",
+            self.program.get_text(assignment.name), symbol.pos);
+          self.register_error(msg, &assignment.pos);
+        }
+      },
+      None => self.undeclared_variable(assignment.name, &assignment.pos),
+    }
+  }
+
+  fn resolve_function_call(&mut self, call: &FunctionCall) {
+    if !self.functions.contains_key(&call.name) &&
+       !builtins::is_builtin(self.program.get_text(call.name)) {
+      let msg = format!("Undeclared function '{}'", self.program.get_text(call.name));
+      self.register_error(msg, &call.pos);
+    }
+
+    for argument in call.arguments.iter() {
+      self.resolve_expression(argument);
+    }
+  }
+
+  fn resolve_expression(&mut self, expression: &Expression) {
+    match expression.kind {
+      ExpressionKind::Literal(..) => { },
+      ExpressionKind::Variable(name) => {
+        if self.lookup(name).is_none() {
+          self.undeclared_variable(name, &expression.pos);
+        }
+      },
+      ExpressionKind::Binary(_, ref left, ref right) => {
+        self.resolve_expression(&**left);
+        self.resolve_expression(&**right);
+      },
+      ExpressionKind::Call(ref call) => self.resolve_function_call(call),
+      ExpressionKind::Cast(ref inner, _) => self.resolve_expression(&**inner),
+    }
+  }
+
+  fn declare_local(&mut self, name: usize, pos: &Position) {
+    match self.globals.get(&name) {
+      Some(global) => {
+        let msg = format!("Local variable '{}' shadows global {} declared at {}",
+          self.program.get_text(name),
+          if global.is_constant { "constant" } else { "variable" },
+          global.pos);
+        self.warnings.push(format!("Warning at {}: {}", pos, msg));
+      },
+      None => { },
+    }
+
+    match self.locals.declare(name, Symbol { pos: *pos, is_constant: false }) {
+      Some(previous) => {
+        let msg = format!("Variable '{}' is already declared in this scope at {}",
+          self.program.get_text(name), previous.pos);
+        self.register_error(msg, pos);
+      },
+      None => { },
+    }
+  }
+
+  fn undeclared_variable(&mut self, name: usize, pos: &Position) {
+    let msg = format!("Undeclared variable '{}'", self.program.get_text(name));
+    self.register_error(msg, pos);
+  }
+
+  fn lookup(&self, name: usize) -> Option<Symbol> {
+    match self.locals.lookup(name) {
+      Some(symbol) => return Some(symbol),
+      None => { },
+    }
+
+    match self.globals.get(&name) {
+      Some(symbol) => Some(*symbol),
+      None => None,
+    }
+  }
+
+  fn register_error(&mut self, msg: String, pos: &Position) {
+    self.errors.push(format!("Error at {}: {}", pos, msg));
+  }
+}
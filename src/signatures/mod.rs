@@ -0,0 +1,59 @@
+use std::collections::HashMap;
+use ast::Program;
+use ast::Parameter;
+use ast::Position;
+use ast::Type;
+
+/*
+  Function signatures of a program. Collected up front, so calls to functions
+  declared later in the file can be checked. When a function is declared more
+  than once, the first declaration is kept; duplicates are reported by name
+  resolution.
+*/
+
+#[derive(Clone)]
+pub struct Signature {
+  pub name: usize, // index to text table
+  pub parameters: Vec<Parameter>,
+  pub return_type: Type,
+  pub pos: Position,
+}
+
+impl Signature {
+  pub fn parameter_types(&self) -> Vec<Type> {
+    self.parameters.iter().map(|p| p.param_type).collect()
+  }
+}
+
+pub struct SignatureTable {
+  signatures: HashMap<usize, Signature>,
+}
+
+impl SignatureTable {
+  pub fn new() -> SignatureTable {
+    SignatureTable { signatures: HashMap::new() }
+  }
+
+  pub fn from_program(program: &Program) -> SignatureTable {
+    let mut table = SignatureTable::new();
+    for function in program.functions.iter() {
+      if !table.signatures.contains_key(&function.name) {
+        table.signatures.insert(function.name, Signature {
+          name: function.name,
+          parameters: function.parameters.clone(),
+          return_type: function.return_type,
+          pos: function.pos,
+        });
+      }
+    }
+    table
+  }
+
+  pub fn get(&self, name: usize) -> Option<&Signature> {
+    self.signatures.get(&name)
+  }
+
+  pub fn len(&self) -> usize {
+    self.signatures.len()
+  }
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+/*
+  Scoped symbol table. Names are text table indices; each scope maps a name to
+  arbitrary per-pass information (declaration position, type, storage slot...).
+  Lookups search from the innermost scope outwards.
+*/
+
+pub struct SymbolTable<T> {
+  scopes: Vec<HashMap<usize, T>>,
+}
+
+impl<T: Clone> SymbolTable<T> {
+  pub fn new() -> SymbolTable<T> {
+    SymbolTable { scopes: vec![] }
+  }
+
+  pub fn push_scope(&mut self) {
+    self.scopes.push(HashMap::new());
+  }
+
+  pub fn pop_scope(&mut self) {
+    self.scopes.pop();
+  }
+
+  pub fn depth(&self) -> usize {
+    self.scopes.len()
+  }
+
+  // declares symbol in the innermost scope. Returns the previous symbol if the
+  // name was already declared in the same scope; in that case the old symbol is kept
+  pub fn declare(&mut self, name: usize, symbol: T) -> Option<T> {
+    match self.scopes.last_mut() {
+      Some(scope) => {
+        match scope.get(&name) {
+          Some(previous) => return Some(previous.clone()),
+          None => { },
+        }
+        scope.insert(name, symbol);
+        None
+      },
+      None => panic!("Internal compiler error: symbol declared outside of any scope"),
+    }
+  }
+
+  // replaces the symbol in the innermost scope that declares the name.
+  // Returns false if the name is not declared in any scope
+  pub fn assign(&mut self, name: usize, symbol: T) -> bool {
+    for scope in self.scopes.iter_mut().rev() {
+      if scope.contains_key(&name) {
+        scope.insert(name, symbol);
+        return true;
+      }
+    }
+    false
+  }
+
+  pub fn lookup(&self, name: usize) -> Option<T> {
+    for scope in self.scopes.iter().rev() {
+      match scope.get(&name) {
+        Some(symbol) => return Some(symbol.clone()),
+        None => { },
+      }
+    }
+    None
+  }
+
+  pub fn lookup_in_current_scope(&self, name: usize) -> Option<T> {
+    match self.scopes.last() {
+      Some(scope) => match scope.get(&name) {
+        Some(symbol) => Some(symbol.clone()),
+        None => None,
+      },
+      None => None,
+    }
+  }
+}
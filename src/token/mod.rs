@@ -23,6 +23,8 @@ pub enum TokenType {
   While,
   For,
   Let,
+  Const,
+  As,
   Fn,
   Return,
   Boolean,
@@ -59,6 +61,8 @@ impl fmt::String for TokenType {
         TokenType::While => "While",
         TokenType::For => "For",
         TokenType::Let => "Let",
+        TokenType::Const => "Const",
+        TokenType::As => "As",
         TokenType::Fn => "Fn",
         TokenType::Return => "Return",
         TokenType::Boolean => "Boolean",
@@ -200,6 +204,10 @@ impl Tokens {
     self.text_table[index].as_slice()
   }
 
+  pub fn get_text_table(&self) -> Vec<String> {
+    self.text_table.clone()
+  }
+
   pub fn push(&mut self, token: SyntaxToken) {
     self.tokens.push(token);
   }
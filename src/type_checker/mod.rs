@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::mem;
+use ast::Program;
+use ast::Function;
+use ast::Block;
+use ast::Statement;
+use ast::VariableDeclaration;
+use ast::Assignment;
+use ast::FunctionCall;
+use ast::Expression;
+use ast::ExpressionKind;
+use ast::Literal;
+use ast::BinaryOperator;
+use ast::Position;
+use ast::Type;
+use symbol_table::SymbolTable;
+use signatures::SignatureTable;
+use builtins;
+
+/*
+  Static type checker. Computes the type of every expression and stores it in
+  the expression node, so later passes can rely on the types being present.
+
+  Checks variable initializers, assignments, call arguments, return values,
+  conditions and operand compatibility. Assumes that name resolution has
+  succeeded; unknown names are silently given no type, so that errors are
+  not reported twice.
+
+  Numeric values are widened (int -> float -> double) where needed by wrapping
+  the expression into an implicit cast node; after type checking, operands of
+  binary operators always have the same type. Implicit narrowing is an error.
+  Explicit casts to the type of the expression, or widening casts where the
+  value would be widened anyway, are reported as redundant.
+
+  Returns the warnings on success. On failure, the errors are returned,
+  followed by any warnings that were generated.
+*/
+
+pub fn check(program: &mut Program) -> Result<Vec<String>, Vec<String>> {
+  let signatures = SignatureTable::from_program(program);
+  let mut checker = TypeChecker::new(&program.text_table, signatures);
+  checker.collect_globals(&program.globals);
+
+  for global in program.globals.iter_mut() {
+    checker.check_global(global);
+  }
+
+  for function in program.functions.iter_mut() {
+    checker.check_function(function);
+  }
+
+  checker.result()
+}
+
+struct TypeChecker<'a> {
+  text_table: &'a Vec<String>,
+  functions: SignatureTable,
+  globals: HashMap<usize, Type>,
+  locals: SymbolTable<Type>,
+  // name and return type of the function being checked
+  current_function: usize,
+  return_type: Type,
+  errors: Vec<String>,
+  warnings: Vec<String>,
+}
+
+impl<'a> TypeChecker<'a> {
+  fn new(text_table: &'a Vec<String>, functions: SignatureTable) -> TypeChecker<'a> {
+    TypeChecker {
+      text_table: text_table,
+      functions: functions,
+      globals: HashMap::new(),
+      locals: SymbolTable::new(),
+      current_function: 0,
+      return_type: Type::Void,
+      errors: vec![],
+      warnings: vec![],
+    }
+  }
+
+  fn result(&self) -> Result<Vec<String>, Vec<String>> {
+    if self.errors.is_empty() {
+      Ok(self.warnings.clone())
+    } else {
+      let mut messages = self.errors.clone();
+      messages.push_all(self.warnings.as_slice());
+      Err(messages)
+    }
+  }
+
+  fn collect_globals(&mut self, globals: &Vec<VariableDeclaration>) {
+    for global in globals.iter() {
+      if !self.globals.contains_key(&global.name) {
+        self.globals.insert(global.name, global.var_type);
+      }
+    }
+  }
+
+  fn check_global(&mut self, global: &mut VariableDeclaration) {
+    self.check_declaration(global);
+  }
+
+  fn check_function(&mut self, function: &mut Function) {
+    self.current_function = function.name;
+    self.return_type = function.return_type;
+    self.locals.push_scope();
+
+    for parameter in function.parameters.iter() {
+      if parameter.param_type == Type::Void {
+        let msg = format!("Parameter '{}' can not have type {}",
+          self.get_text(parameter.name), Type::Void);
+        self.register_error(msg, &parameter.pos);
+      }
+      self.locals.declare(parameter.name, parameter.param_type);
+    }
+
+    // parameters and the top level of the body share the scope
+    for statement in function.body.statements.iter_mut() {
+      self.check_statement(statement);
+    }
+
+    self.locals.pop_scope();
+
+    if function.return_type != Type::Void && !always_returns(&function.body) {
+      let msg = format!("Function '{}' with return type {} does not return a value on every path",
+        self.get_text(function.name), function.return_type);
+      self.register_error(msg, &function.pos);
+    }
+  }
+
+  fn check_block(&mut self, block: &mut Block) {
+    self.locals.push_scope();
+    for statement in block.statements.iter_mut() {
+      self.check_statement(statement);
+    }
+    self.locals.pop_scope();
+  }
+
+  fn check_statement(&mut self, statement: &mut Statement) {
+    match *statement {
+      Statement::Block(ref mut block) => self.check_block(block),
+      Statement::VariableDeclaration(ref mut declaration) => {
+        self.check_declaration(declaration);
+        self.locals.declare(declaration.name, declaration.var_type);
+      },
+      Statement::Assignment(ref mut assignment) => self.check_assignment(assignment),
+      Statement::FunctionCall(ref mut call) => { self.check_function_call(call); },
+      Statement::For(ref mut for_loop) => {
+        self.locals.push_scope();
+        match for_loop.init {
+          Some(ref mut init) => self.check_statement(&mut **init),
+          None => { },
+        }
+
+        match for_loop.condition {
+          Some(ref mut condition) => self.check_condition(condition, "for loop condition"),
+          None => { },
+        }
+
+        match for_loop.update {
+          Some(ref mut update) => self.check_assignment(update),
+          None => { },
+        }
+
+        self.check_block(&mut for_loop.body);
+        self.locals.pop_scope();
+      },
+      Statement::If(ref mut if_statement) => {
+        self.check_condition(&mut if_statement.condition, "if condition");
+        self.check_block(&mut if_statement.block);
+        for else_if in if_statement.else_ifs.iter_mut() {
+          self.check_condition(&mut else_if.condition, "elif condition");
+          self.check_block(&mut else_if.block);
+        }
+
+        match if_statement.else_block {
+          Some(ref mut block) => self.check_block(block),
+          None => { },
+        }
+      },
+      Statement::Return(ref mut return_statement) => {
+        let expected = self.return_type;
+        match return_statement.value {
+          Some(ref mut value) => {
+            if expected == Type::Void {
+              let msg = format!("Function '{}' has return type {}, but a value is returned",
+                self.get_text(self.current_function), Type::Void);
+              self.register_error(msg, &value.pos);
+              self.check_expression(value);
+            } else {
+              let context = "return value".to_string();
+              self.check_value(value, expected, context);
+            }
+          },
+          None => {
+            if expected != Type::Void {
+              let msg = format!("Function '{}' must return a value of type {}",
+                self.get_text(self.current_function), expected);
+              self.register_error(msg, &return_statement.pos);
+            }
+          },
+        }
+      },
+      Statement::Empty(..) => { },
+    }
+  }
+
+  fn check_declaration(&mut self, declaration: &mut VariableDeclaration) {
+    if declaration.var_type == Type::Void {
+      let msg = format!("Variable '{}' can not have type {}",
+        self.get_text(declaration.name), Type::Void);
+      self.register_error(msg, &declaration.pos);
+      self.check_expression(&mut declaration.initializer);
+      return;
+    }
+
+    let context = format!("declaration of '{}'", self.get_text(declaration.name));
+    self.check_value(&mut declaration.initializer, declaration.var_type, context);
+  }
+
+  fn check_assignment(&mut self, assignment: &mut Assignment) {
+    match self.lookup(assignment.name) {
+      Some(variable_type) => {
+        let context = format!("assignment to '{}'", self.get_text(assignment.name));
+        self.check_value(&mut assignment.value, variable_type, context);
+      },
+      None => { self.check_expression(&mut assignment.value); },
+    }
+  }
+
+  fn check_condition(&mut self, condition: &mut Expression, context: &str) {
+    self.check_value(condition, Type::Boolean, context.to_string());
+  }
+
+  // checks that the expression has the expected type, widening it if necessary
+  fn check_value(&mut self, expression: &mut Expression, expected: Type, context: String) {
+    match self.check_expression(expression) {
+      Some(actual) => {
+        if actual == expected {
+          self.check_implicit_cast(expression, expected);
+          return;
+        }
+
+        if actual.is_widening_conversion(expected) {
+          widen(expression, expected);
+        } else if expected.is_widening_conversion(actual) {
+          let msg = format!(
+            "Implicit narrowing conversion from {} to {} in {}. Use an explicit cast",
+            actual, expected, context);
+          self.register_error(msg, &expression.pos);
+        } else {
+          self.type_mismatch(context, expected, actual, &expression.pos);
+        }
+      },
+      None => { /* error already reported */ },
+    }
+  }
+
+  // warns on an explicit cast of a value that would be widened to the
+  // expected type anyway
+  fn check_implicit_cast(&mut self, expression: &Expression, expected: Type) {
+    let source = match expression.kind {
+      ExpressionKind::Cast(ref inner, _) => inner.expr_type,
+      _ => None,
+    };
+    match source {
+      Some(source) if source.is_widening_conversion(expected) => {
+        self.register_warning(
+          format!("Redundant cast: {} is converted to {} implicitly", source, expected), &expression.pos);
+      },
+      _ => { },
+    }
+  }
+
+  fn check_function_call(&mut self, call: &mut FunctionCall) -> Option<Type> {
+    let signature = match self.functions.get(call.name) {
+      Some(signature) => signature.clone(),
+      None => {
+        if builtins::is_builtin(self.get_text(call.name)) {
+          return self.check_builtin_call(call);
+        }
+
+        // reported by name resolution; still check the arguments
+        for argument in call.arguments.iter_mut() {
+          self.check_expression(argument);
+        }
+        return None;
+      }
+    };
+
+    if signature.parameters.len() != call.arguments.len() {
+      let msg = format!("Function '{}' declared at {} expects {} argument(s), but {} were given",
+        self.get_text(call.name), signature.pos, signature.parameters.len(), call.arguments.len());
+      self.register_error(msg, &call.pos);
+    }
+
+    let mut index = 0;
+    for argument in call.arguments.iter_mut() {
+      if index < signature.parameters.len() {
+        let parameter = &signature.parameters[index];
+        let context = format!("argument {} ('{}') of call to '{}' declared at {}",
+          index + 1, self.get_text(parameter.name), self.get_text(call.name), signature.pos);
+        self.check_value(argument, parameter.param_type, context);
+      } else {
+        self.check_expression(argument);
+      }
+      index += 1;
+    }
+
+    Some(signature.return_type)
+  }
+
+  fn check_builtin_call(&mut self, call: &mut FunctionCall) -> Option<Type> {
+    // print is the only builtin
+    if call.arguments.len() != 1 {
+      let msg = format!("Builtin function '{}' expects 1 argument(s), but {} were given",
+        self.get_text(call.name), call.arguments.len());
+      self.register_error(msg, &call.pos);
+    }
+
+    for argument in call.arguments.iter_mut() {
+      match self.check_expression(argument) {
+        Some(Type::Void) => {
+          let msg = format!("Can not print a value of type {}", Type::Void);
+          self.register_error(msg, &argument.pos);
+        },
+        _ => { },
+      }
+    }
+
+    Some(Type::Void)
+  }
+
+  // computes the type of the expression and stores it in the node.
+  // None means that the type could not be determined due to an earlier error
+  fn check_expression(&mut self, expression: &mut Expression) -> Option<Type> {
+    let pos = expression.pos;
+    let expr_type = match expression.kind {
+      ExpressionKind::Literal(literal) => Some(literal_type(literal)),
+      ExpressionKind::Variable(name) => self.lookup(name),
+      ExpressionKind::Binary(operator, ref mut left, ref mut right) => {
+        let left_type = self.check_expression(&mut **left);
+        let right_type = self.check_expression(&mut **right);
+        match (left_type, right_type) {
+          (Some(mut left_type), Some(mut right_type)) => {
+            // promote the narrower operand
+            if left_type.is_widening_conversion(right_type) {
+              widen(&mut **left, right_type);
+              left_type = right_type;
+            } else if right_type.is_widening_conversion(left_type) {
+              widen(&mut **right, left_type);
+              right_type = left_type;
+            }
+            self.check_binary_operator(operator, left_type, right_type, &pos)
+          },
+          _ => None,
+        }
+      },
+      ExpressionKind::Call(ref mut call) => self.check_function_call(call),
+      ExpressionKind::Cast(ref mut inner, target) => {
+        match self.check_expression(&mut **inner) {
+          Some(source) => self.check_cast(source, target, &pos),
+          None => None,
+        }
+      },
+    };
+
+    expression.expr_type = expr_type;
+    expr_type
+  }
+
+  fn check_cast(&mut self, source: Type, target: Type, pos: &Position) -> Option<Type> {
+    if source == target {
+      self.register_warning(
+        format!("Redundant cast: expression already has type {}", target), pos);
+      Some(target)
+    } else if source.is_numeric() && target.is_numeric() {
+      Some(target)
+    } else {
+      let msg = format!("Can not cast value of type {} to {}", source, target);
+      self.register_error(msg, pos);
+      None
+    }
+  }
+
+  fn check_binary_operator(&mut self, operator: BinaryOperator, left: Type, right: Type,
+    pos: &Position) -> Option<Type> {
+
+    let result = match operator {
+      BinaryOperator::Plus => {
+        if left == right && (left.is_numeric() || left == Type::String) {
+          Some(left)
+        } else {
+          None
+        }
+      },
+      BinaryOperator::Minus | BinaryOperator::Multiply | BinaryOperator::Divide => {
+        if left == right && left.is_numeric() {
+          Some(left)
+        } else {
+          None
+        }
+      },
+      BinaryOperator::Lesser | BinaryOperator::Greater | BinaryOperator::LesserOrEq |
+      BinaryOperator::GreaterOrEq => {
+        if left == right && left.is_numeric() {
+          Some(Type::Boolean)
+        } else {
+          None
+        }
+      },
+      BinaryOperator::Equals => {
+        if left == right && left != Type::Void {
+          Some(Type::Boolean)
+        } else {
+          None
+        }
+      },
+    };
+
+    if result.is_none() {
+      let msg = format!("Operator '{}' can not be applied to operands of type {} and {}",
+        operator, left, right);
+      self.register_error(msg, pos);
+    }
+
+    result
+  }
+
+  fn lookup(&self, name: usize) -> Option<Type> {
+    match self.locals.lookup(name) {
+      Some(variable_type) => return Some(variable_type),
+      None => { },
+    }
+
+    match self.globals.get(&name) {
+      Some(variable_type) => Some(*variable_type),
+      None => None,
+    }
+  }
+
+  fn type_mismatch(&mut self, context: String, expected: Type, actual: Type, pos: &Position) {
+    let msg = format!("Type mismatch in {}: expected {}, found {}", context, expected, actual);
+    self.register_error(msg, pos);
+  }
+
+  fn get_text(&self, index: usize) -> &'a str {
+    let text_table: &'a Vec<String> = self.text_table;
+    text_table[index].as_slice()
+  }
+
+  fn register_error(&mut self, msg: String, pos: &Position) {
+    self.errors.push(format!("Error at {}: {}", pos, msg));
+  }
+
+  fn register_warning(&mut self, msg: String, pos: &Position) {
+    self.warnings.push(format!("Warning at {}: {}", pos, msg));
+  }
+}
+
+// wraps an already checked expression into an implicit conversion
+fn widen(expression: &mut Expression, target: Type) {
+  let pos = expression.pos;
+  let placeholder = Expression::new(ExpressionKind::Literal(Literal::Integer(0)), pos);
+  let inner = mem::replace(expression, placeholder);
+
+  *expression = Expression {
+    kind: ExpressionKind::Cast(Box::new(inner), target),
+    expr_type: Some(target),
+    pos: pos,
+  };
+}
+
+fn literal_type(literal: Literal) -> Type {
+  match literal {
+    Literal::Integer(..) => Type::Integer,
+    Literal::Float(..) => Type::Float,
+    Literal::Double(..) => Type::Double,
+    Literal::Boolean(..) => Type::Boolean,
+    Literal::Text(..) => Type::String,
+  }
+}
+
+// conservative check; loops are never considered to return, as their body
+// might not be executed
+fn always_returns(block: &Block) -> bool {
+  block.statements.iter().any(|statement| statement_always_returns(statement))
+}
+
+fn statement_always_returns(statement: &Statement) -> bool {
+  match *statement {
+    Statement::Return(..) => true,
+    Statement::Block(ref block) => always_returns(block),
+    Statement::If(ref if_statement) => {
+      match if_statement.else_block {
+        Some(ref else_block) => {
+          always_returns(&if_statement.block) &&
+          if_statement.else_ifs.iter().all(|else_if| always_returns(&else_if.block)) &&
+          always_returns(else_block)
+        },
+        None => false,
+      }
+    },
+    _ => false,
+  }
+}
@@ -0,0 +1,203 @@
+use std::iter;
+use bytecode::*;
+use vm::jit::Kind;
+
+/*
+  Finds the kind of every value on the stack and in the locals of a chunk
+  for the kinds its parameters were called with, by walking the bytecode
+  from the first instruction and merging the states where paths join.
+  Locals other than the parameters are unknown until they are stored, and
+  become unknown again where paths with different kinds meet, as the
+  compiler may reuse the slot of a variable for another one. Reading an
+  unknown local makes the chunk unsupported.
+
+  Compiled code only calls compiled code, so the functions a chunk calls
+  are analyzed with it, for the kinds of the arguments of the call, and so
+  on. If one of them is unsupported, all are. A call needs the kind the
+  callee returns, which is only known once a call of it has returned. Until
+  then the analysis is deferred. A chunk that never reaches a return, for
+  example because it loops forever, has no return kind and is unsupported.
+*/
+
+#[derive(Show, Clone, PartialEq)]
+pub enum Failure {
+  // the return kind of a callee is not known yet
+  Deferred,
+  Unsupported(String),
+}
+
+pub struct Analysis {
+  pub function: usize,
+  pub parameter_kinds: Vec<Kind>,
+  // offset -> kinds on the stack before the instruction, None if the
+  // instruction can not be reached
+  pub stacks: Vec<Option<Vec<Kind>>>,
+  pub return_kind: Kind,
+  // called functions and the kinds of their arguments
+  calls: Vec<(usize, Vec<Kind>)>,
+}
+
+#[derive(Clone, PartialEq)]
+struct State {
+  stack: Vec<Kind>,
+  locals: Vec<Option<Kind>>,
+}
+
+// a function pops its arguments with ret, which takes a 16 bit byte count
+pub static MAX_PARAMETERS: usize = 1024;
+
+// analyzes the function and the functions it calls, directly or not. The
+// function comes first
+pub fn analyze(module: &Module, function: usize, parameter_kinds: &[Kind],
+  return_kinds: &[Option<Kind>]) -> Result<Vec<Analysis>, Failure> {
+
+  let mut analyses: Vec<Analysis> = vec![];
+  let mut pending = vec![(function, parameter_kinds.to_vec())];
+  while let Some((callee, kinds)) = pending.pop() {
+    if analyses.iter().any(|analysis| analysis.function == callee) {
+      continue;
+    }
+    let analysis = match analyze_function(module, callee, kinds, return_kinds) {
+      Ok(analysis) => analysis,
+      Err(Failure::Unsupported(reason)) if callee != function => return Err(Failure::Unsupported(
+        format!("called function '{}' is not supported: {}", module.functions[callee].name, reason))),
+      Err(failure) => return Err(failure),
+    };
+    pending.push_all(analysis.calls.as_slice());
+    analyses.push(analysis);
+  }
+  Ok(analyses)
+}
+
+fn analyze_function(module: &Module, function: usize, parameter_kinds: Vec<Kind>,
+  return_kinds: &[Option<Kind>]) -> Result<Analysis, Failure> {
+
+  let chunk = &module.functions[function];
+  if chunk.arity > MAX_PARAMETERS {
+    return Err(Failure::Unsupported(format!("functions with more than {} parameters are not supported",
+      MAX_PARAMETERS)));
+  }
+  let mut locals: Vec<Option<Kind>> = iter::repeat(None).take(chunk.local_count).collect();
+  for (slot, kind) in parameter_kinds.iter().enumerate() {
+    locals[slot] = Some(*kind);
+  }
+
+  let mut states: Vec<Option<State>> = iter::repeat(None).take(chunk.code.len()).collect();
+  states[0] = Some(State { stack: vec![], locals: locals });
+  let mut worklist = vec![0];
+  let mut return_kind = None;
+  let mut calls = vec![];
+
+  while !worklist.is_empty() {
+    let offset = worklist.pop().unwrap();
+    let mut state = states[offset].clone().unwrap();
+    let opcode = chunk.code[offset];
+    let (mnemonic, operand_size) = opcode_info(opcode).unwrap();
+    let next = offset + 1 + operand_size;
+    let mut successors = vec![next];
+
+    match opcode {
+      CONST => {
+        match chunk.constants[chunk.read_u16(offset + 1)] {
+          Constant::Integer(..) => state.stack.push(Kind::Integer),
+          Constant::Float(..) | Constant::Double(..) =>
+            return Err(Failure::Unsupported("floating point constants are not supported".to_string())),
+          Constant::Text(..) => return Err(Failure::Unsupported("strings are not supported".to_string())),
+        }
+      },
+      TRUE | FALSE => state.stack.push(Kind::Boolean),
+      LOAD_LOCAL => {
+        match state.locals[chunk.read_u16(offset + 1)] {
+          Some(kind) => state.stack.push(kind),
+          None => return Err(Failure::Unsupported(format!("local read at offset {} has no known kind", offset))),
+        }
+      },
+      STORE_LOCAL => {
+        let kind = state.stack.pop().unwrap();
+        state.locals[chunk.read_u16(offset + 1)] = Some(kind);
+      },
+      IADD | ISUB | IMUL | IDIV => {
+        state.stack.pop();
+        state.stack.pop();
+        state.stack.push(Kind::Integer);
+      },
+      IEQ | ILT | IGT | ILE | IGE | BEQ => {
+        state.stack.pop();
+        state.stack.pop();
+        state.stack.push(Kind::Boolean);
+      },
+      JUMP => successors = vec![chunk.read_u16(offset + 1)],
+      JUMP_IF_FALSE => {
+        state.stack.pop();
+        successors.push(chunk.read_u16(offset + 1));
+      },
+      CALL => {
+        let callee = chunk.read_u16(offset + 1);
+        let arity = module.functions[callee].arity;
+        let height = state.stack.len();
+        calls.push((callee, state.stack.slice_from(height - arity).to_vec()));
+        state.stack.truncate(height - arity);
+        match return_kinds[callee] {
+          Some(kind) => state.stack.push(kind),
+          None => return Err(Failure::Deferred),
+        }
+      },
+      TAIL_CALL => {
+        // the callee returns what this function returns
+        let callee = chunk.read_u16(offset + 1);
+        let arity = module.functions[callee].arity;
+        let height = state.stack.len();
+        calls.push((callee, state.stack.slice_from(height - arity).to_vec()));
+        successors = vec![];
+      },
+      RETURN | RETURN_VOID => {
+        let kind = if opcode == RETURN { state.stack.pop().unwrap() } else { Kind::Void };
+        match return_kind {
+          Some(previous) if previous != kind =>
+            return Err(Failure::Unsupported("returns of different kinds".to_string())),
+          _ => return_kind = Some(kind),
+        }
+        successors = vec![];
+      },
+      POP => { state.stack.pop(); },
+      PRINT => {
+        state.stack.pop();
+        state.stack.push(Kind::Void);
+      },
+      _ => return Err(Failure::Unsupported(format!("{} is not supported", mnemonic))),
+    }
+
+    for successor in successors.into_iter() {
+      let merged = match states[successor] {
+        None => state.clone(),
+        Some(ref previous) => try!(merge(previous, &state)),
+      };
+      if states[successor].as_ref() != Some(&merged) {
+        states[successor] = Some(merged);
+        worklist.push(successor);
+      }
+    }
+  }
+
+  let return_kind = match return_kind {
+    Some(kind) => kind,
+    None => return Err(Failure::Unsupported("no return is reachable".to_string())),
+  };
+  Ok(Analysis {
+    function: function,
+    parameter_kinds: parameter_kinds,
+    stacks: states.into_iter().map(|state| state.map(|state| state.stack)).collect(),
+    return_kind: return_kind,
+    calls: calls,
+  })
+}
+
+fn merge(first: &State, second: &State) -> Result<State, Failure> {
+  if first.stack != second.stack {
+    return Err(Failure::Unsupported("stack kinds differ where paths join".to_string()));
+  }
+  let locals = first.locals.iter().zip(second.locals.iter())
+    .map(|(first, second)| if first == second { *first } else { None })
+    .collect();
+  Ok(State { stack: first.stack.clone(), locals: locals })
+}
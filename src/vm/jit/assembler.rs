@@ -0,0 +1,315 @@
+/*
+  Encoder for the few x86-64 instructions the just-in-time compiler emits.
+  Jumps refer to labels, which may be bound after the jump; their 32 bit
+  displacements are filled in by finish. Arithmetic on the values of the
+  language uses the 32 bit forms of the instructions, so integers wrap
+  around like in the virtual machine.
+*/
+
+#[derive(Show, Copy, PartialEq)]
+pub enum Register {
+  Rax = 0,
+  Rcx = 1,
+  Rdx = 2,
+  Rbx = 3,
+  Rsp = 4,
+  Rbp = 5,
+  Rsi = 6,
+  Rdi = 7,
+  R8 = 8,
+  R9 = 9,
+  R12 = 12,
+}
+
+// condition codes of jcc and setcc
+#[derive(Show, Copy, PartialEq)]
+pub enum Condition {
+  Equal = 0x4,
+  NotEqual = 0x5,
+  Less = 0xC,
+  GreaterEqual = 0xD,
+  LessEqual = 0xE,
+  Greater = 0xF,
+}
+
+#[derive(Show, Copy, PartialEq)]
+pub struct Label(usize);
+
+pub struct Assembler {
+  code: Vec<u8>,
+  // label -> its offset in the code once bound
+  labels: Vec<Option<usize>>,
+  // offsets of 32 bit displacements and the labels they jump to
+  fixups: Vec<(usize, Label)>,
+}
+
+impl Assembler {
+  pub fn new() -> Assembler {
+    Assembler { code: vec![], labels: vec![], fixups: vec![] }
+  }
+
+  pub fn new_label(&mut self) -> Label {
+    self.labels.push(None);
+    Label(self.labels.len() - 1)
+  }
+
+  pub fn bind(&mut self, label: Label) {
+    let Label(index) = label;
+    self.labels[index] = Some(self.code.len());
+  }
+
+  // offset of the next instruction
+  pub fn offset(&self) -> usize {
+    self.code.len()
+  }
+
+  pub fn finish(mut self) -> Vec<u8> {
+    for &(offset, Label(index)) in self.fixups.iter() {
+      let target = match self.labels[index] {
+        Some(target) => target,
+        None => panic!("Internal compiler error: jump to unbound label"),
+      };
+      let displacement = (target as i64 - (offset + 4) as i64) as i32;
+      for byte in range(0, 4) {
+        self.code[offset + byte] = (displacement >> (8 * byte)) as u8;
+      }
+    }
+    self.code
+  }
+
+  pub fn push(&mut self, register: Register) {
+    self.rex(false, 0, register as u8);
+    self.code.push(0x50 + (register as u8 & 7));
+  }
+
+  pub fn pop(&mut self, register: Register) {
+    self.rex(false, 0, register as u8);
+    self.code.push(0x58 + (register as u8 & 7));
+  }
+
+  // mov destination, source
+  pub fn mov(&mut self, destination: Register, source: Register) {
+    self.rex(true, source as u8, destination as u8);
+    self.code.push(0x89);
+    self.modrm(3, source as u8, destination as u8);
+  }
+
+  // mov destination, immediate
+  pub fn mov_immediate(&mut self, destination: Register, value: i64) {
+    self.rex(true, 0, destination as u8);
+    self.code.push(0xB8 + (destination as u8 & 7));
+    self.immediate(value, 8);
+  }
+
+  // mov destination, [base + displacement]
+  pub fn load(&mut self, destination: Register, base: Register, displacement: i32) {
+    self.rex(true, destination as u8, base as u8);
+    self.code.push(0x8B);
+    self.memory(destination as u8, base, displacement);
+  }
+
+  // mov [base + displacement], source
+  pub fn store(&mut self, base: Register, displacement: i32, source: Register) {
+    self.rex(true, source as u8, base as u8);
+    self.code.push(0x89);
+    self.memory(source as u8, base, displacement);
+  }
+
+  // lea destination, [base + displacement]
+  pub fn lea(&mut self, destination: Register, base: Register, displacement: i32) {
+    self.rex(true, destination as u8, base as u8);
+    self.code.push(0x8D);
+    self.memory(destination as u8, base, displacement);
+  }
+
+  // cmp qword [base], 0
+  pub fn compare_memory_with_zero(&mut self, base: Register) {
+    self.rex(true, 0, base as u8);
+    self.code.push(0x83);
+    self.memory(7, base, 0);
+    self.code.push(0);
+  }
+
+  // cmp qword [base + displacement], immediate
+  pub fn compare_memory(&mut self, base: Register, displacement: i32, value: i32) {
+    self.rex(true, 0, base as u8);
+    self.code.push(0x81);
+    self.memory(7, base, displacement);
+    self.immediate(value as i64, 4);
+  }
+
+  // add qword [base + displacement], immediate
+  pub fn add_memory(&mut self, base: Register, displacement: i32, value: i32) {
+    self.rex(true, 0, base as u8);
+    self.code.push(0x81);
+    self.memory(0, base, displacement);
+    self.immediate(value as i64, 4);
+  }
+
+  // mov qword [base + displacement], immediate sign extended to 64 bits
+  pub fn store_immediate(&mut self, base: Register, displacement: i32, value: i32) {
+    self.rex(true, 0, base as u8);
+    self.code.push(0xC7);
+    self.memory(0, base, displacement);
+    self.immediate(value as i64, 4);
+  }
+
+  pub fn add_32(&mut self, destination: Register, source: Register) {
+    self.alu_32(0x01, destination, source);
+  }
+
+  pub fn sub_32(&mut self, destination: Register, source: Register) {
+    self.alu_32(0x29, destination, source);
+  }
+
+  pub fn cmp_32(&mut self, left: Register, right: Register) {
+    self.alu_32(0x39, left, right);
+  }
+
+  pub fn test_32(&mut self, left: Register, right: Register) {
+    self.alu_32(0x85, left, right);
+  }
+
+  pub fn imul_32(&mut self, destination: Register, source: Register) {
+    self.rex(false, destination as u8, source as u8);
+    self.code.push_all(&[0x0F, 0xAF]);
+    self.modrm(3, destination as u8, source as u8);
+  }
+
+  // cmp register, immediate with an 8 bit immediate sign extended to 32 bits
+  pub fn cmp_32_immediate(&mut self, register: Register, value: i8) {
+    self.rex(false, 0, register as u8);
+    self.code.push(0x83);
+    self.modrm(3, 7, register as u8);
+    self.code.push(value as u8);
+  }
+
+  // sign extends edx:eax from eax
+  pub fn cdq(&mut self) {
+    self.code.push(0x99);
+  }
+
+  // divides edx:eax, leaving the quotient in eax
+  pub fn idiv_32(&mut self, divisor: Register) {
+    self.rex(false, 0, divisor as u8);
+    self.code.push(0xF7);
+    self.modrm(3, 7, divisor as u8);
+  }
+
+  pub fn neg_32(&mut self, register: Register) {
+    self.rex(false, 0, register as u8);
+    self.code.push(0xF7);
+    self.modrm(3, 3, register as u8);
+  }
+
+  // movsxd destination, source: sign extends the low 32 bits of the source
+  pub fn sign_extend(&mut self, destination: Register, source: Register) {
+    self.rex(true, destination as u8, source as u8);
+    self.code.push(0x63);
+    self.modrm(3, destination as u8, source as u8);
+  }
+
+  // sets the register to 1 if the condition holds and 0 otherwise
+  pub fn set(&mut self, condition: Condition, register: Register) {
+    // a REX prefix selects the low byte of rsi and rdi instead of dh and bh
+    self.code.push(0x40 | (register as u8 >> 3));
+    self.code.push_all(&[0x0F, 0x90 + condition as u8]);
+    self.modrm(3, 0, register as u8);
+    // movzx register, low byte of register
+    self.rex(false, register as u8, register as u8);
+    self.code.push_all(&[0x0F, 0xB6]);
+    self.modrm(3, register as u8, register as u8);
+  }
+
+  pub fn add_rsp(&mut self, value: i32) {
+    self.code.push_all(&[0x48, 0x81]);
+    self.modrm(3, 0, Register::Rsp as u8);
+    self.immediate(value as i64, 4);
+  }
+
+  pub fn sub_rsp(&mut self, value: i32) {
+    self.code.push_all(&[0x48, 0x81]);
+    self.modrm(3, 5, Register::Rsp as u8);
+    self.immediate(value as i64, 4);
+  }
+
+  // rounds rsp down to a multiple of 16
+  pub fn align_rsp(&mut self) {
+    self.code.push_all(&[0x48, 0x83]);
+    self.modrm(3, 4, Register::Rsp as u8);
+    self.code.push(0xF0);
+  }
+
+  // call register
+  pub fn call(&mut self, target: Register) {
+    self.rex(false, 0, target as u8);
+    self.code.push(0xFF);
+    self.modrm(3, 2, target as u8);
+  }
+
+  // call label
+  pub fn call_label(&mut self, label: Label) {
+    self.code.push(0xE8);
+    self.displacement(label);
+  }
+
+  pub fn jmp(&mut self, label: Label) {
+    self.code.push(0xE9);
+    self.displacement(label);
+  }
+
+  pub fn jump_if(&mut self, condition: Condition, label: Label) {
+    self.code.push_all(&[0x0F, 0x80 + condition as u8]);
+    self.displacement(label);
+  }
+
+  pub fn ret(&mut self) {
+    self.code.push(0xC3);
+  }
+
+  // returns and pops the given number of bytes of arguments
+  pub fn ret_popping(&mut self, bytes: u16) {
+    self.code.push(0xC2);
+    self.immediate(bytes as i64, 2);
+  }
+
+  fn alu_32(&mut self, opcode: u8, destination: Register, source: Register) {
+    self.rex(false, source as u8, destination as u8);
+    self.code.push(opcode);
+    self.modrm(3, source as u8, destination as u8);
+  }
+
+  // REX prefix for the register in the reg field and the one in the r/m
+  // field, left out if not needed
+  fn rex(&mut self, wide: bool, reg: u8, rm: u8) {
+    let prefix = 0x40 | (if wide { 0x8 } else { 0 }) | ((reg >> 3) << 2) | (rm >> 3);
+    if prefix != 0x40 {
+      self.code.push(prefix);
+    }
+  }
+
+  fn modrm(&mut self, mode: u8, reg: u8, rm: u8) {
+    self.code.push((mode << 6) | ((reg & 7) << 3) | (rm & 7));
+  }
+
+  // [base + displacement] with a 32 bit displacement. rsp and r12 as base
+  // need a SIB byte
+  fn memory(&mut self, reg: u8, base: Register, displacement: i32) {
+    self.modrm(2, reg, base as u8);
+    if base as u8 & 7 == 4 {
+      self.code.push(0x24);
+    }
+    self.immediate(displacement as i64, 4);
+  }
+
+  fn immediate(&mut self, value: i64, size: usize) {
+    for byte in range(0, size) {
+      self.code.push((value >> (8 * byte)) as u8);
+    }
+  }
+
+  fn displacement(&mut self, label: Label) {
+    self.fixups.push((self.code.len(), label));
+    self.immediate(0, 4);
+  }
+}
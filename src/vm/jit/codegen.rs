@@ -0,0 +1,293 @@
+use bytecode::*;
+use interpreter::MAX_CALL_DEPTH;
+use vm::jit::Error;
+use vm::jit::print_helper;
+use vm::jit::CONTEXT_ERROR;
+use vm::jit::CONTEXT_FUNCTION;
+use vm::jit::CONTEXT_OFFSET;
+use vm::jit::CONTEXT_DEPTH;
+use vm::jit::analysis::Analysis;
+use vm::jit::assembler::Assembler;
+use vm::jit::assembler::Condition;
+use vm::jit::assembler::Label;
+use vm::jit::assembler::Register::*;
+
+/*
+  Translates analyzed chunks into x86-64 machine code, instruction by
+  instruction. The value stack of a chunk becomes the machine stack: every
+  value takes eight bytes, integers sign extended and booleans as 0 or 1.
+
+  Every chunk gets an entry for the virtual machine, which follows the
+  System V calling convention:
+    rdi   address of the arguments, the first one at the lowest address
+    rsi   address of the context of the call, kept in r12
+    rax   returned value
+  The entry pushes the arguments and calls the body of the chunk. Bodies
+  call each other directly: the caller pushes the arguments, the last one
+  on top, and the callee pops them when it returns, leaving its result in
+  rax. A body copies its parameters into its locals, which live in its
+  frame and are addressed through rbx. A tail call moves the arguments over
+  the ones of the body and its return address and jumps to the callee, so
+  the callee returns to the caller of the body.
+
+  The context counts the frames being executed, so that a call fails at
+  the same depth as in the virtual machine. A failing instruction writes
+  the error and its function and offset into the context and returns; every
+  caller checks the first word of the context after a call and returns as
+  well. Printing goes through a helper, which takes the context first.
+
+  After the prologue of a body the stack pointer is aligned to 16 bytes, so
+  a helper call is aligned if the number of values on the stack is even and
+  needs another eight bytes otherwise. The analysis knows that number at
+  every instruction.
+*/
+
+// returns the code and the offset of the entry of every analyzed chunk
+pub fn generate(module: &Module, analyses: &[Analysis]) -> (Vec<u8>, Vec<usize>) {
+  let mut assembler = Assembler::new();
+  let bodies: Vec<Label> = analyses.iter().map(|_| assembler.new_label()).collect();
+  let mut entries = vec![];
+  for (analysis, body) in analyses.iter().zip(bodies.iter()) {
+    entries.push(assembler.offset());
+    generate_entry(&mut assembler, module.functions[analysis.function].arity, *body);
+  }
+  for (analysis, body) in analyses.iter().zip(bodies.iter()) {
+    assembler.bind(*body);
+    generate_body(&mut assembler, module, analysis, analyses, bodies.as_slice());
+  }
+  (assembler.finish(), entries)
+}
+
+fn generate_entry(assembler: &mut Assembler, arity: usize, body: Label) {
+  assembler.push(Rbp);
+  assembler.mov(Rbp, Rsp);
+  assembler.push(R12);
+  assembler.mov(R12, Rsi);
+  for index in range(0, arity) {
+    assembler.load(Rax, Rdi, 8 * index as i32);
+    assembler.push(Rax);
+  }
+  assembler.call_label(body);
+  assembler.lea(Rsp, Rbp, -8);
+  assembler.pop(R12);
+  assembler.pop(Rbp);
+  assembler.ret();
+}
+
+fn generate_body(assembler: &mut Assembler, module: &Module, analysis: &Analysis, analyses: &[Analysis],
+  bodies: &[Label]) {
+
+  let function = analysis.function;
+  let chunk = &module.functions[function];
+  let labels: Vec<Label> = range(0, chunk.code.len()).map(|_| assembler.new_label()).collect();
+  let epilogue = assembler.new_label();
+
+  assembler.push(Rbp);
+  assembler.mov(Rbp, Rsp);
+  assembler.push(Rbx);
+  if chunk.local_count > 0 {
+    assembler.sub_rsp(8 * chunk.local_count as i32);
+  }
+  assembler.align_rsp();
+  assembler.mov(Rbx, Rsp);
+  // the last argument is next to the return address
+  for slot in range(0, chunk.arity) {
+    assembler.load(Rax, Rbp, 16 + 8 * (chunk.arity - 1 - slot) as i32);
+    assembler.store(Rbx, 8 * slot as i32, Rax);
+  }
+
+  let mut offset = 0;
+  while offset < chunk.code.len() {
+    let opcode = chunk.code[offset];
+    let (_, operand_size) = opcode_info(opcode).unwrap();
+    let stack = match analysis.stacks[offset] {
+      Some(ref stack) => stack,
+      None => {
+        offset += 1 + operand_size;
+        continue;
+      },
+    };
+    assembler.bind(labels[offset]);
+    let operand = if operand_size == 2 { chunk.read_u16(offset + 1) } else { 0 };
+
+    match opcode {
+      CONST => {
+        match chunk.constants[operand] {
+          Constant::Integer(value) => assembler.mov_immediate(Rax, value as i64),
+          _ => panic!("Internal compiler error: unsupported constant in compiled chunk"),
+        }
+        assembler.push(Rax);
+      },
+      TRUE | FALSE => {
+        assembler.mov_immediate(Rax, if opcode == TRUE { 1 } else { 0 });
+        assembler.push(Rax);
+      },
+      LOAD_LOCAL => {
+        assembler.load(Rax, Rbx, 8 * operand as i32);
+        assembler.push(Rax);
+      },
+      STORE_LOCAL => {
+        assembler.pop(Rax);
+        assembler.store(Rbx, 8 * operand as i32, Rax);
+      },
+      IADD | ISUB | IMUL => {
+        assembler.pop(Rcx);
+        assembler.pop(Rax);
+        match opcode {
+          IADD => assembler.add_32(Rax, Rcx),
+          ISUB => assembler.sub_32(Rax, Rcx),
+          _ => assembler.imul_32(Rax, Rcx),
+        }
+        assembler.sign_extend(Rax, Rax);
+        assembler.push(Rax);
+      },
+      IDIV => {
+        let divide = assembler.new_label();
+        let done = assembler.new_label();
+        assembler.pop(Rcx);
+        assembler.pop(Rax);
+        assembler.test_32(Rcx, Rcx);
+        assembler.jump_if(Condition::NotEqual, divide);
+        fail(assembler, Error::DivisionByZero, function, offset, epilogue);
+        assembler.bind(divide);
+        // the minimum integer divided by -1 overflows idiv, it wraps around
+        // to itself
+        let regular = assembler.new_label();
+        assembler.cmp_32_immediate(Rcx, -1);
+        assembler.jump_if(Condition::NotEqual, regular);
+        assembler.neg_32(Rax);
+        assembler.jmp(done);
+        assembler.bind(regular);
+        assembler.cdq();
+        assembler.idiv_32(Rcx);
+        assembler.bind(done);
+        assembler.sign_extend(Rax, Rax);
+        assembler.push(Rax);
+      },
+      IEQ | ILT | IGT | ILE | IGE | BEQ => {
+        let condition = match opcode {
+          ILT => Condition::Less,
+          IGT => Condition::Greater,
+          ILE => Condition::LessEqual,
+          IGE => Condition::GreaterEqual,
+          _ => Condition::Equal,
+        };
+        assembler.pop(Rcx);
+        assembler.pop(Rax);
+        assembler.cmp_32(Rax, Rcx);
+        assembler.set(condition, Rax);
+        assembler.push(Rax);
+      },
+      JUMP => assembler.jmp(labels[operand]),
+      JUMP_IF_FALSE => {
+        assembler.pop(Rax);
+        assembler.test_32(Rax, Rax);
+        assembler.jump_if(Condition::Equal, labels[operand]);
+      },
+      CALL => {
+        let call = assembler.new_label();
+        assembler.compare_memory(R12, CONTEXT_DEPTH, MAX_CALL_DEPTH as i32);
+        assembler.jump_if(Condition::Less, call);
+        fail(assembler, Error::CallDepth, function, offset, epilogue);
+        assembler.bind(call);
+        // the callee pops the arguments
+        assembler.add_memory(R12, CONTEXT_DEPTH, 1);
+        assembler.call_label(body_of(analyses, bodies, operand));
+        assembler.add_memory(R12, CONTEXT_DEPTH, -1);
+        assembler.compare_memory_with_zero(R12);
+        assembler.jump_if(Condition::NotEqual, epilogue);
+        assembler.push(Rax);
+      },
+      TAIL_CALL => {
+        let arity = module.functions[operand].arity;
+        tail_call(assembler, chunk.arity, arity, body_of(analyses, bodies, operand));
+      },
+      RETURN => {
+        assembler.pop(Rax);
+        assembler.jmp(epilogue);
+      },
+      RETURN_VOID => {
+        assembler.mov_immediate(Rax, 0);
+        assembler.jmp(epilogue);
+      },
+      POP => assembler.add_rsp(8),
+      PRINT => {
+        let kind = stack[stack.len() - 1];
+        assembler.pop(Rdx);
+        assembler.mov_immediate(Rsi, kind as i64);
+        assembler.mov_immediate(Rcx, function as i64);
+        assembler.mov_immediate(R8, offset as i64);
+        call_helper_function(assembler, stack.len() - 1, print_helper as i64, epilogue);
+        // print leaves void on the stack
+        assembler.mov_immediate(Rax, 0);
+        assembler.push(Rax);
+      },
+      _ => panic!("Internal compiler error: unsupported opcode {} in compiled chunk", opcode),
+    }
+    offset += 1 + operand_size;
+  }
+
+  assembler.bind(epilogue);
+  assembler.lea(Rsp, Rbp, -8);
+  assembler.pop(Rbx);
+  assembler.pop(Rbp);
+  if chunk.arity > 0 {
+    assembler.ret_popping(8 * chunk.arity as u16);
+  } else {
+    assembler.ret();
+  }
+}
+
+// replaces the frame of the body with the arguments on top of the stack
+// and jumps to the callee. The arguments end where the ones of the body
+// end, the return address goes below them
+fn tail_call(assembler: &mut Assembler, parameters: usize, arguments: usize, callee: Label) {
+  let end = 16 + 8 * parameters as i32;
+  assembler.load(Rcx, Rbp, 8);
+  assembler.load(Rdx, Rbp, 0);
+  assembler.load(Rsi, Rbp, -8);
+  // the arguments move to higher addresses, so the top one moves last
+  for index in range(0, arguments).rev() {
+    assembler.load(Rax, Rsp, 8 * index as i32);
+    assembler.store(Rbp, end - 8 * (arguments - index) as i32, Rax);
+  }
+  assembler.lea(Rsp, Rbp, end - 8 * arguments as i32 - 8);
+  assembler.store(Rsp, 0, Rcx);
+  assembler.mov(Rbp, Rdx);
+  assembler.mov(Rbx, Rsi);
+  assembler.jmp(callee);
+}
+
+// the label of the body of a function, which the analysis included
+fn body_of(analyses: &[Analysis], bodies: &[Label], function: usize) -> Label {
+  match analyses.iter().position(|analysis| analysis.function == function) {
+    Some(index) => bodies[index],
+    None => panic!("Internal compiler error: called function {} was not analyzed", function),
+  }
+}
+
+// calls a helper with the context as first argument, the other arguments
+// are already in their registers. Returns from the function if the helper
+// failed
+fn call_helper_function(assembler: &mut Assembler, height: usize, helper: i64, epilogue: Label) {
+  assembler.mov(Rdi, R12);
+  assembler.mov_immediate(Rax, helper);
+  if height % 2 == 1 {
+    assembler.sub_rsp(8);
+  }
+  assembler.call(Rax);
+  if height % 2 == 1 {
+    assembler.add_rsp(8);
+  }
+  assembler.compare_memory_with_zero(R12);
+  assembler.jump_if(Condition::NotEqual, epilogue);
+}
+
+// stores the error of the instruction at the offset in the context and
+// returns from the function
+fn fail(assembler: &mut Assembler, error: Error, function: usize, offset: usize, epilogue: Label) {
+  assembler.store_immediate(R12, CONTEXT_FUNCTION, function as i32);
+  assembler.store_immediate(R12, CONTEXT_OFFSET, offset as i32);
+  assembler.store_immediate(R12, CONTEXT_ERROR, error as i32);
+  assembler.jmp(epilogue);
+}
@@ -0,0 +1,333 @@
+use std::io::IoError;
+use std::io::Writer;
+use std::mem;
+use std::os::MapOption;
+use std::os::MemoryMap;
+use std::ptr;
+use bytecode::Module;
+use interpreter::Value;
+use interpreter::MAX_CALL_DEPTH;
+use vm::jit::analysis::Analysis;
+use vm::jit::analysis::Failure;
+
+pub mod analysis;
+pub mod assembler;
+pub mod codegen;
+
+/*
+  Just-in-time compilation of hot functions into x86-64 machine code. The
+  virtual machine reports every call to the compiler, which counts them
+  and compiles a function once its count reaches the threshold. From then
+  on calls with arguments of the kinds it was compiled for run the machine
+  code instead of the bytecode.
+
+  Only integers and booleans are compiled. The language is statically
+  typed, so the kinds of the arguments of one call are the kinds of every
+  call, and the kind of one returned value is the kind the function always
+  returns; the compiler remembers both from the calls the virtual machine
+  runs. A function using floating point numbers, strings or globals stays
+  interpreted, as does one calling such a function or a function that has
+  not returned yet. The latter is tried again whenever the return kind of
+  another function becomes known.
+
+  Machine code never calls back into the virtual machine: the functions a
+  compiled function calls are compiled with it, into the same code, and
+  count as compiled from then on. All the code can reach is the context of
+  the call, which holds the output to print to, the call depth and the
+  runtime error the code failed with, if any. The virtual machine turns the
+  error into the same message it reports itself. The code is written into a
+  mapping that is made executable, and no longer writable, before it runs.
+  On other targets than x86-64 Unix nothing is compiled.
+*/
+
+// kind of a value compiled code can hold
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Kind {
+  Integer = 0,
+  Boolean = 1,
+  Void = 2,
+}
+
+impl Kind {
+  pub fn of(value: &Value) -> Option<Kind> {
+    match *value {
+      Value::Integer(..) => Some(Kind::Integer),
+      Value::Boolean(..) => Some(Kind::Boolean),
+      Value::Void => Some(Kind::Void),
+      Value::Float(..) | Value::Double(..) | Value::Text(..) => None,
+    }
+  }
+}
+
+// calls a function needs before it is compiled
+pub static DEFAULT_THRESHOLD: usize = 100;
+
+pub struct Jit {
+  threshold: usize,
+  profiles: Vec<Profile>,
+  // number of functions with a known return kind
+  known_returns: usize,
+  // keeps the compiled code mapped
+  code: Vec<MemoryMap>,
+}
+
+struct Profile {
+  calls: usize,
+  parameter_kinds: Option<Vec<Kind>>,
+  return_kind: Option<Kind>,
+  state: State,
+}
+
+enum State {
+  Interpreted,
+  // the compilation was deferred when this many return kinds were known
+  Deferred(usize),
+  Compiled(Entry),
+  Unsupported(String),
+}
+
+// how to call compiled code
+#[derive(Copy)]
+pub struct Entry {
+  code: extern "C" fn(*const i64, *mut Context) -> i64,
+  return_kind: Kind,
+}
+
+// runtime errors of compiled code
+#[derive(Show, Clone, Copy, PartialEq)]
+pub enum Error {
+  DivisionByZero = 1,
+  CallDepth = 2,
+  Output = 3,
+}
+
+// state of a call of compiled code. The machine code reads and writes the
+// first four words, at the offsets below
+#[repr(C)]
+pub struct Context<'a, 'b: 'a> {
+  // the error the code failed with, 0 while it runs
+  error: u64,
+  // function and offset of the failing instruction
+  function: u64,
+  offset: u64,
+  // number of frames being executed
+  depth: u64,
+  output: &'a mut (Writer + 'b),
+  // why printing failed
+  output_error: Option<IoError>,
+}
+
+pub static CONTEXT_ERROR: i32 = 0;
+pub static CONTEXT_FUNCTION: i32 = 8;
+pub static CONTEXT_OFFSET: i32 = 16;
+pub static CONTEXT_DEPTH: i32 = 24;
+
+// a runtime error of compiled code and the instruction it happened at
+pub struct RuntimeError {
+  pub message: String,
+  pub function: usize,
+  pub offset: usize,
+}
+
+impl Jit {
+  pub fn new(threshold: usize) -> Jit {
+    Jit { threshold: threshold, profiles: vec![], known_returns: 0, code: vec![] }
+  }
+
+  pub fn is_compiled(&self, function: usize) -> bool {
+    match self.profiles.get(function) {
+      Some(&Profile { state: State::Compiled(..), .. }) => true,
+      _ => false,
+    }
+  }
+
+  // why the function can not be compiled, None if it may still be
+  pub fn unsupported_reason(&self, function: usize) -> Option<&str> {
+    match self.profiles.get(function) {
+      Some(&Profile { state: State::Unsupported(ref reason), .. }) => Some(reason.as_slice()),
+      _ => None,
+    }
+  }
+
+  // counts a call of the function with the given arguments and returns its
+  // compiled code if the call can run it
+  pub fn enter(&mut self, module: &Module, function: usize, arguments: &[Value]) -> Option<Entry> {
+    if self.profiles.is_empty() {
+      self.profiles = range(0, module.functions.len()).map(|_| Profile {
+        calls: 0,
+        parameter_kinds: None,
+        return_kind: None,
+        state: State::Interpreted,
+      }).collect();
+    }
+
+    let kinds: Vec<Option<Kind>> = arguments.iter().map(|argument| Kind::of(argument)).collect();
+    if kinds.iter().any(|kind| kind.is_none()) {
+      self.profiles[function].state = State::Unsupported("parameters of unsupported kinds".to_string());
+      return None;
+    }
+    let kinds: Vec<Kind> = kinds.into_iter().map(|kind| kind.unwrap()).collect();
+    let known_returns = self.known_returns;
+    let threshold = self.threshold;
+    let attempt = {
+      let profile = &mut self.profiles[function];
+      profile.calls += 1;
+      if profile.parameter_kinds.is_none() {
+        profile.parameter_kinds = Some(kinds.clone());
+      }
+      match profile.state {
+        State::Interpreted => profile.calls >= threshold,
+        State::Deferred(known) => known < known_returns,
+        State::Compiled(..) | State::Unsupported(..) => false,
+      }
+    };
+    if !attempt {
+      return self.entry(function, kinds.as_slice());
+    }
+
+    let return_kinds: Vec<Option<Kind>> = self.profiles.iter().map(|profile| profile.return_kind).collect();
+    let state = match analysis::analyze(module, function, kinds.as_slice(), return_kinds.as_slice()) {
+      Ok(analyses) => match compile(module, analyses.as_slice()) {
+        Some((memory, entries)) => {
+          // the called functions are compiled as well
+          for (analysis, entry) in analyses.iter().zip(entries.into_iter()) {
+            let profile = &mut self.profiles[analysis.function];
+            profile.parameter_kinds = Some(analysis.parameter_kinds.clone());
+            profile.state = State::Compiled(entry);
+          }
+          self.code.push(memory);
+          return self.entry(function, kinds.as_slice());
+        },
+        None => State::Unsupported("executable memory is not available".to_string()),
+      },
+      Err(Failure::Deferred) => State::Deferred(known_returns),
+      Err(Failure::Unsupported(reason)) => State::Unsupported(reason),
+    };
+    self.profiles[function].state = state;
+    None
+  }
+
+  // remembers the kind of a value returned by the function
+  pub fn record_return(&mut self, function: usize, value: &Value) {
+    match self.profiles.get_mut(function) {
+      Some(profile) if profile.return_kind.is_none() => {
+        match Kind::of(value) {
+          Some(kind) => {
+            profile.return_kind = Some(kind);
+            self.known_returns += 1;
+          },
+          None => { },
+        }
+      },
+      _ => { },
+    }
+  }
+
+  fn entry(&self, function: usize, kinds: &[Kind]) -> Option<Entry> {
+    let profile = &self.profiles[function];
+    let compiled_for = profile.parameter_kinds.as_ref().map(|compiled| compiled.as_slice());
+    match profile.state {
+      State::Compiled(entry) if compiled_for == Some(kinds) => Some(entry),
+      _ => None,
+    }
+  }
+}
+
+impl Entry {
+  // runs the code with the arguments, counting its frames from the given
+  // depth on. The output is all the code can reach while it runs
+  pub fn run<'b>(&self, arguments: &[Value], depth: usize, output: &mut (Writer + 'b))
+    -> Result<Value, RuntimeError> {
+
+    let arguments: Vec<i64> = arguments.iter().map(|argument| encode(argument)).collect();
+    let mut context = Context {
+      error: 0,
+      function: 0,
+      offset: 0,
+      depth: depth as u64,
+      output: output,
+      output_error: None,
+    };
+    let result = (self.code)(arguments.as_ptr(), &mut context);
+    if context.error == 0 {
+      return Ok(decode(self.return_kind, result));
+    }
+
+    let message = match context.output_error {
+      Some(ref error) => format!("Failed to write output: {}", error),
+      None if context.error == Error::CallDepth as u64 =>
+        format!("Maximum call depth of {} exceeded", MAX_CALL_DEPTH),
+      None => "Division by zero".to_string(),
+    };
+    Err(RuntimeError { message: message, function: context.function as usize, offset: context.offset as usize })
+  }
+}
+
+fn encode(value: &Value) -> i64 {
+  match *value {
+    Value::Integer(value) => value as i64,
+    Value::Boolean(value) => if value { 1 } else { 0 },
+    _ => 0,
+  }
+}
+
+fn decode(kind: Kind, value: i64) -> Value {
+  match kind {
+    Kind::Integer => Value::Integer(value as i32),
+    Kind::Boolean => Value::Boolean(value != 0),
+    Kind::Void => Value::Void,
+  }
+}
+
+// the mapped code of the analyzed functions and their entries
+#[cfg(all(target_arch = "x86_64", unix))]
+fn compile(module: &Module, analyses: &[Analysis]) -> Option<(MemoryMap, Vec<Entry>)> {
+  let (bytes, offsets) = codegen::generate(module, analyses);
+  let memory = match MemoryMap::new(bytes.len(), &[MapOption::MapReadable, MapOption::MapWritable]) {
+    Ok(memory) => memory,
+    Err(..) => return None,
+  };
+  unsafe {
+    ptr::copy_nonoverlapping_memory(memory.data(), bytes.as_ptr(), bytes.len());
+    if mprotect(memory.data(), memory.len(), PROT_READ | PROT_EXEC) != 0 {
+      return None;
+    }
+  }
+  let entries = analyses.iter().zip(offsets.iter()).map(|(analysis, offset)| Entry {
+    code: unsafe { mem::transmute(memory.data().offset(*offset as isize)) },
+    return_kind: analysis.return_kind,
+  }).collect();
+  Some((memory, entries))
+}
+
+#[cfg(not(all(target_arch = "x86_64", unix)))]
+fn compile(_module: &Module, _analyses: &[Analysis]) -> Option<(MemoryMap, Vec<Entry>)> {
+  None
+}
+
+#[cfg(all(target_arch = "x86_64", unix))]
+static PROT_READ: i32 = 1;
+#[cfg(all(target_arch = "x86_64", unix))]
+static PROT_EXEC: i32 = 4;
+
+#[cfg(all(target_arch = "x86_64", unix))]
+extern {
+  fn mprotect(address: *mut u8, length: usize, protection: i32) -> i32;
+}
+
+// prints a value for compiled code. Only touches the context, and reports
+// a failed write through it instead of panicking, which must not unwind
+// into the machine code
+pub extern "C" fn print_helper(context: *mut Context, kind: u64, value: i64, function: u64, offset: u64) {
+  let context = unsafe { &mut *context };
+  let kind = if kind == Kind::Boolean as u64 { Kind::Boolean } else { Kind::Integer };
+  match writeln!(context.output, "{}", decode(kind, value)) {
+    Ok(()) => { },
+    Err(error) => {
+      context.output_error = Some(error);
+      context.function = function;
+      context.offset = offset;
+      context.error = Error::Output as u64;
+    },
+  }
+}
@@ -0,0 +1,393 @@
+use std::i32;
+use std::io::Writer;
+use std::iter;
+use ast::Position;
+use bytecode::*;
+use interpreter::Value;
+use interpreter::MAX_CALL_DEPTH;
+use vm::jit::Jit;
+
+pub mod jit;
+
+/*
+  Stack based virtual machine executing a bytecode module. Runs the global
+  initializers first and then the 'main' function. Local variables live on the
+  value stack; a call frame points to the slot of its first parameter.
+
+  The module must either come from the bytecode compiler or have passed the
+  verifier; operands, jump targets and stack heights are not checked here.
+
+  Runtime behaviour matches the tree-walking interpreter: integer arithmetic
+  wraps around, integer division by zero and too deep recursion are runtime
+  errors reported with the source position of the failing instruction. As
+  in the interpreter, a tail call replaces the frame of the caller with the
+  one of the callee and does not count towards the call depth.
+
+  With a just-in-time compiler, calls of hot functions run machine code
+  instead, see jit/mod.rs. Compiled code only calls compiled code, and only
+  reaches the output of the machine while it runs.
+*/
+
+pub fn execute(module: &Module, output: &mut Writer) -> Result<(), String> {
+  let mut vm = Vm::new(module, output, None);
+  vm.execute()
+}
+
+// executes the module, compiling the functions hot enough for the compiler
+pub fn execute_with_jit(module: &Module, output: &mut Writer, jit: &mut Jit) -> Result<(), String> {
+  let mut vm = Vm::new(module, output, Some(jit));
+  vm.execute()
+}
+
+struct Frame {
+  function: usize,
+  ip: usize,
+  // stack index of the first local
+  base: usize,
+}
+
+struct Vm<'a> {
+  module: &'a Module,
+  output: &'a mut (Writer + 'a),
+  globals: Vec<Value>,
+  stack: Vec<Value>,
+  // callers of the frame being executed
+  frames: Vec<Frame>,
+  // number of frames being executed
+  depth: usize,
+  jit: Option<&'a mut Jit>,
+}
+
+impl<'a> Vm<'a> {
+  fn new(module: &'a Module, output: &'a mut (Writer + 'a), jit: Option<&'a mut Jit>) -> Vm<'a> {
+    Vm {
+      module: module,
+      output: output,
+      globals: iter::repeat(Value::Void).take(module.global_count).collect(),
+      stack: vec![],
+      frames: vec![],
+      depth: 0,
+      jit: jit,
+    }
+  }
+
+  fn execute(&mut self) -> Result<(), String> {
+    let module = self.module;
+    let init = self.new_frame(module.init);
+    try!(self.run(init));
+
+    match module.main {
+      Some(main) => {
+        let chunk = &module.functions[main];
+        if chunk.arity != 0 {
+          return Err(runtime_error("Function 'main' must not take parameters", &chunk.pos));
+        }
+        let frame = self.new_frame(main);
+        self.run(frame).map(|_| ())
+      },
+      None => Err("Runtime error: No 'main' function found".to_string()),
+    }
+  }
+
+  // pushes the locals of the function; arguments are already on the stack
+  fn new_frame(&mut self, function: usize) -> Frame {
+    let module = self.module;
+    let chunk = &module.functions[function];
+    let base = self.stack.len() - chunk.arity;
+    for _ in range(chunk.arity, chunk.local_count) {
+      self.stack.push(Value::Void);
+    }
+    self.depth += 1;
+    Frame { function: function, ip: 0, base: base }
+  }
+
+  // executes until the given frame returns and returns its result
+  fn run(&mut self, mut frame: Frame) -> Result<Value, String> {
+    let module = self.module;
+    // callers below the frame belong to an outer run
+    let bottom = self.frames.len();
+    loop {
+      let chunk = &module.functions[frame.function];
+      let offset = frame.ip;
+      let opcode = chunk.code[offset];
+      frame.ip += 1;
+
+      match opcode {
+        CONST => {
+          let index = chunk.read_u16(frame.ip);
+          frame.ip += 2;
+          let value = match chunk.constants[index] {
+            Constant::Integer(value) => Value::Integer(value),
+            Constant::Float(value) => Value::Float(value),
+            Constant::Double(value) => Value::Double(value),
+            Constant::Text(index) => Value::Text(module.text_table[index].clone()),
+          };
+          self.stack.push(value);
+        },
+        TRUE => self.stack.push(Value::Boolean(true)),
+        FALSE => self.stack.push(Value::Boolean(false)),
+        LOAD_LOCAL => {
+          let slot = chunk.read_u16(frame.ip);
+          frame.ip += 2;
+          let value = self.stack[frame.base + slot].clone();
+          self.stack.push(value);
+        },
+        STORE_LOCAL => {
+          let slot = chunk.read_u16(frame.ip);
+          frame.ip += 2;
+          let value = self.pop();
+          self.stack[frame.base + slot] = value;
+        },
+        LOAD_GLOBAL => {
+          let index = chunk.read_u16(frame.ip);
+          frame.ip += 2;
+          let value = self.globals[index].clone();
+          self.stack.push(value);
+        },
+        STORE_GLOBAL => {
+          let index = chunk.read_u16(frame.ip);
+          frame.ip += 2;
+          let value = self.pop();
+          self.globals[index] = value;
+        },
+
+        IADD => { let (a, b) = self.pop_integers(); self.stack.push(Value::Integer(a + b)); },
+        ISUB => { let (a, b) = self.pop_integers(); self.stack.push(Value::Integer(a - b)); },
+        IMUL => { let (a, b) = self.pop_integers(); self.stack.push(Value::Integer(a * b)); },
+        IDIV => {
+          let (a, b) = self.pop_integers();
+          if b == 0 {
+            return Err(runtime_error("Division by zero", &chunk.position_at(offset)));
+          }
+          let result = if a == i32::MIN && b == -1 { i32::MIN } else { a / b };
+          self.stack.push(Value::Integer(result));
+        },
+        FADD => { let (a, b) = self.pop_floats(); self.stack.push(Value::Float(a + b)); },
+        FSUB => { let (a, b) = self.pop_floats(); self.stack.push(Value::Float(a - b)); },
+        FMUL => { let (a, b) = self.pop_floats(); self.stack.push(Value::Float(a * b)); },
+        FDIV => { let (a, b) = self.pop_floats(); self.stack.push(Value::Float(a / b)); },
+        DADD => { let (a, b) = self.pop_doubles(); self.stack.push(Value::Double(a + b)); },
+        DSUB => { let (a, b) = self.pop_doubles(); self.stack.push(Value::Double(a - b)); },
+        DMUL => { let (a, b) = self.pop_doubles(); self.stack.push(Value::Double(a * b)); },
+        DDIV => { let (a, b) = self.pop_doubles(); self.stack.push(Value::Double(a / b)); },
+        SCONCAT => {
+          let (a, b) = self.pop_texts();
+          self.stack.push(Value::Text(a + b.as_slice()));
+        },
+
+        IEQ => { let (a, b) = self.pop_integers(); self.stack.push(Value::Boolean(a == b)); },
+        ILT => { let (a, b) = self.pop_integers(); self.stack.push(Value::Boolean(a < b)); },
+        IGT => { let (a, b) = self.pop_integers(); self.stack.push(Value::Boolean(a > b)); },
+        ILE => { let (a, b) = self.pop_integers(); self.stack.push(Value::Boolean(a <= b)); },
+        IGE => { let (a, b) = self.pop_integers(); self.stack.push(Value::Boolean(a >= b)); },
+        FEQ => { let (a, b) = self.pop_floats(); self.stack.push(Value::Boolean(a == b)); },
+        FLT => { let (a, b) = self.pop_floats(); self.stack.push(Value::Boolean(a < b)); },
+        FGT => { let (a, b) = self.pop_floats(); self.stack.push(Value::Boolean(a > b)); },
+        FLE => { let (a, b) = self.pop_floats(); self.stack.push(Value::Boolean(a <= b)); },
+        FGE => { let (a, b) = self.pop_floats(); self.stack.push(Value::Boolean(a >= b)); },
+        DEQ => { let (a, b) = self.pop_doubles(); self.stack.push(Value::Boolean(a == b)); },
+        DLT => { let (a, b) = self.pop_doubles(); self.stack.push(Value::Boolean(a < b)); },
+        DGT => { let (a, b) = self.pop_doubles(); self.stack.push(Value::Boolean(a > b)); },
+        DLE => { let (a, b) = self.pop_doubles(); self.stack.push(Value::Boolean(a <= b)); },
+        DGE => { let (a, b) = self.pop_doubles(); self.stack.push(Value::Boolean(a >= b)); },
+        BEQ => {
+          let b = self.pop_boolean();
+          let a = self.pop_boolean();
+          self.stack.push(Value::Boolean(a == b));
+        },
+        SEQ => { let (a, b) = self.pop_texts(); self.stack.push(Value::Boolean(a == b)); },
+
+        I2F => { let a = self.pop_integer(); self.stack.push(Value::Float(a as f32)); },
+        I2D => { let a = self.pop_integer(); self.stack.push(Value::Double(a as f64)); },
+        F2I => { let a = self.pop_float(); self.stack.push(Value::Integer(a as i32)); },
+        F2D => { let a = self.pop_float(); self.stack.push(Value::Double(a as f64)); },
+        D2I => { let a = self.pop_double(); self.stack.push(Value::Integer(a as i32)); },
+        D2F => { let a = self.pop_double(); self.stack.push(Value::Float(a as f32)); },
+
+        JUMP => frame.ip = chunk.read_u16(frame.ip),
+        JUMP_IF_FALSE => {
+          let target = chunk.read_u16(frame.ip);
+          frame.ip += 2;
+          if !self.pop_boolean() {
+            frame.ip = target;
+          }
+        },
+        CALL => {
+          let function = chunk.read_u16(frame.ip);
+          frame.ip += 2;
+          try!(self.check_call_depth(frame.function, offset));
+          match try!(self.call_compiled(function)) {
+            Some(result) => self.stack.push(result),
+            None => {
+              let callee = self.new_frame(function);
+              self.frames.push(frame);
+              frame = callee;
+            },
+          }
+        },
+        TAIL_CALL => {
+          let function = chunk.read_u16(frame.ip);
+          // the arguments take the place of the locals of the frame
+          let start = self.stack.len() - module.functions[function].arity;
+          let arguments = self.stack.slice_from(start).to_vec();
+          self.stack.truncate(frame.base);
+          self.stack.push_all(arguments.as_slice());
+          self.depth -= 1;
+          match try!(self.call_compiled(function)) {
+            Some(result) => {
+              self.record_return(frame.function, &result);
+              if self.frames.len() == bottom {
+                return Ok(result);
+              }
+              frame = self.frames.pop().unwrap();
+              self.stack.push(result);
+            },
+            None => frame = self.new_frame(function),
+          }
+        },
+        RETURN | RETURN_VOID => {
+          let result = if opcode == RETURN { self.pop() } else { Value::Void };
+          self.stack.truncate(frame.base);
+          self.depth -= 1;
+          self.record_return(frame.function, &result);
+          if self.frames.len() == bottom {
+            return Ok(result);
+          }
+          frame = self.frames.pop().unwrap();
+          self.stack.push(result);
+        },
+        POP => { self.pop(); },
+        PRINT => {
+          let value = self.pop();
+          try!(self.print(value, frame.function, offset));
+          self.stack.push(Value::Void);
+        },
+        _ => panic!("Internal compiler error: invalid opcode {} at offset {} in function '{}'",
+          opcode, offset, chunk.name),
+      }
+    }
+  }
+
+  fn check_call_depth(&self, caller: usize, offset: usize) -> Result<(), String> {
+    if self.depth >= MAX_CALL_DEPTH {
+      let msg = format!("Maximum call depth of {} exceeded", MAX_CALL_DEPTH);
+      return Err(self.error_at(msg.as_slice(), caller, offset));
+    }
+    Ok(())
+  }
+
+  // runs the compiled code of the function if the compiler has any for the
+  // arguments on the stack. None if the function has to be interpreted
+  fn call_compiled(&mut self, function: usize) -> Result<Option<Value>, String> {
+    let module = self.module;
+    let start = self.stack.len() - module.functions[function].arity;
+    let entry = match self.jit {
+      Some(ref mut jit) => jit.enter(module, function, self.stack.slice_from(start)),
+      None => None,
+    };
+    let entry = match entry {
+      Some(entry) => entry,
+      None => return Ok(None),
+    };
+
+    let arguments = self.stack.slice_from(start).to_vec();
+    self.stack.truncate(start);
+    // the compiled frame counts on top of the frames being executed
+    let result = entry.run(arguments.as_slice(), self.depth + 1, &mut *self.output);
+    let result = match result {
+      Ok(result) => result,
+      Err(error) => return Err(self.error_at(error.message.as_slice(), error.function, error.offset)),
+    };
+    self.record_return(function, &result);
+    Ok(Some(result))
+  }
+
+  fn record_return(&mut self, function: usize, result: &Value) {
+    match self.jit {
+      Some(ref mut jit) => jit.record_return(function, result),
+      None => { },
+    }
+  }
+
+  fn print(&mut self, value: Value, function: usize, offset: usize) -> Result<(), String> {
+    match writeln!(self.output, "{}", value) {
+      Ok(..) => Ok(()),
+      Err(err) => {
+        let msg = format!("Failed to write output: {}", err);
+        Err(self.error_at(msg.as_slice(), function, offset))
+      }
+    }
+  }
+
+  // runtime error at the instruction at the offset of the function
+  fn error_at(&self, msg: &str, function: usize, offset: usize) -> String {
+    runtime_error(msg, &self.module.functions[function].position_at(offset))
+  }
+
+  fn pop(&mut self) -> Value {
+    match self.stack.pop() {
+      Some(value) => value,
+      None => panic!("Internal compiler error: value stack underflow"),
+    }
+  }
+
+  fn pop_integer(&mut self) -> i32 {
+    match self.pop() {
+      Value::Integer(value) => value,
+      value => panic!("Internal compiler error: expected int on stack, found {:?}", value),
+    }
+  }
+
+  fn pop_float(&mut self) -> f32 {
+    match self.pop() {
+      Value::Float(value) => value,
+      value => panic!("Internal compiler error: expected float on stack, found {:?}", value),
+    }
+  }
+
+  fn pop_double(&mut self) -> f64 {
+    match self.pop() {
+      Value::Double(value) => value,
+      value => panic!("Internal compiler error: expected double on stack, found {:?}", value),
+    }
+  }
+
+  fn pop_boolean(&mut self) -> bool {
+    match self.pop() {
+      Value::Boolean(value) => value,
+      value => panic!("Internal compiler error: expected bool on stack, found {:?}", value),
+    }
+  }
+
+  fn pop_text(&mut self) -> String {
+    match self.pop() {
+      Value::Text(value) => value,
+      value => panic!("Internal compiler error: expected string on stack, found {:?}", value),
+    }
+  }
+
+  // operands of binary instructions, left operand first
+  fn pop_integers(&mut self) -> (i32, i32) {
+    let b = self.pop_integer();
+    (self.pop_integer(), b)
+  }
+
+  fn pop_floats(&mut self) -> (f32, f32) {
+    let b = self.pop_float();
+    (self.pop_float(), b)
+  }
+
+  fn pop_doubles(&mut self) -> (f64, f64) {
+    let b = self.pop_double();
+    (self.pop_double(), b)
+  }
+
+  fn pop_texts(&mut self) -> (String, String) {
+    let b = self.pop_text();
+    (self.pop_text(), b)
+  }
+}
+
+fn runtime_error(msg: &str, pos: &Position) -> String {
+  format!("Runtime error at {}: {}", pos, msg)
+}
@@ -0,0 +1,135 @@
+extern crate compiler;
+
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::type_checker::check;
+use compiler::bytecode::*;
+use compiler::bytecode::compiler::compile;
+use compiler::bytecode::disassembler::disassemble;
+use compiler::bytecode::disassembler::disassemble_chunk;
+
+fn compile_source(source: &str) -> Module {
+  compile_checked(source).unwrap()
+}
+
+fn compile_checked(source: &str) -> Result<Module, Vec<String>> {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(check(&mut program).is_ok());
+  compile(&program)
+}
+
+#[test]
+fn compiler_creates_chunk_per_function_and_global_initializer() {
+  let module = compile_source("let g:int = 1;\nfn foo(a:int, b:int) { let c:int = a; }\nfn main() { }");
+  assert_eq!(3, module.functions.len());
+  assert_eq!(Some(1), module.main);
+  assert_eq!(2, module.init);
+  assert_eq!(1, module.global_count);
+  assert_eq!("foo", module.functions[0].name.as_slice());
+  assert_eq!(2, module.functions[0].arity);
+  assert_eq!(3, module.functions[0].local_count);
+}
+
+#[test]
+fn compiler_shares_constant_pool_entries() {
+  let module = compile_source("fn main() { let a:int = 7 + 7; let s:string = \"x\"; print(s + \"x\"); }");
+  let chunk = &module.functions[0];
+  assert_eq!(2, chunk.constants.len());
+  match chunk.constants[1] {
+    Constant::Text(index) => assert_eq!("x", module.text_table[index].as_slice()),
+    _ => assert!(false),
+  }
+}
+
+#[test]
+fn compiler_emits_typed_instructions() {
+  let module = compile_source("fn main() { let d:double = 1 + 2.0; }");
+  let chunk = &module.functions[0];
+  assert_eq!(vec![CONST, 0, 0, I2D, CONST, 0, 1, DADD, STORE_LOCAL, 0, 0, RETURN_VOID], chunk.code);
+}
+
+#[test]
+fn compiler_records_source_positions() {
+  let module = compile_source("fn main() {\n let a:int = 1;\n let b:int = a / 2; }");
+  let chunk = &module.functions[0];
+  let divide = chunk.code.iter().position(|op| *op == IDIV).unwrap();
+  let pos = chunk.position_at(divide);
+  assert_eq!(3, pos.line);
+  assert_eq!(16, pos.pos_at_line);
+}
+
+#[test]
+fn disassembler_lists_instructions_with_positions_and_operands() {
+  let module = compile_source("fn add(a:int, b:int) : int { return a + b; }");
+  let listing = disassemble_chunk(&module, &module.functions[0]);
+  let expected = "== fn add (arity 2, locals 2) at 1:1 ==\n\
+                  0000     1:37  LOAD_LOCAL     0\n\
+                  0003     1:41  LOAD_LOCAL     1\n\
+                  0006     1:39  IADD\n\
+                  0007     1:30  RETURN\n\
+                  0008     1:28  RETURN_VOID\n";
+  assert_eq!(expected, listing.as_slice());
+}
+
+#[test]
+fn disassembler_resolves_constants_jumps_and_calls() {
+  let module = compile_source("fn foo() { }\nfn main() {\n if (true) { print(\"a\\n\"); }\n foo(); }");
+  let listing = disassemble(&module);
+  assert!(listing.contains("== fn main (arity 0, locals 0) at 2:1 =="));
+  assert!(listing.contains("JUMP_IF_FALSE  -> 0012"));
+  assert!(listing.contains("CONST          0 (string \"a\\n\")"));
+  assert!(listing.contains("CALL           0 (foo)"));
+  assert!(listing.contains("== fn <globals>"));
+}
+
+#[test]
+fn compiler_emits_tail_calls_for_returned_calls() {
+  let module = compile_source("fn f(n:int) : int { if (n == 0) { return 0; } return f(n - 1); }\n\
+    fn g(n:int) : int { return f(n) + 1; }");
+  assert!(disassemble_chunk(&module, &module.functions[0]).contains("TAIL_CALL      0 (f)"));
+  let listing = disassemble_chunk(&module, &module.functions[1]);
+  assert!(listing.contains("CALL           0 (f)"));
+  assert!(!listing.contains("TAIL_CALL"));
+}
+
+#[test]
+fn disassembler_marks_invalid_opcodes() {
+  let mut module = compile_source("fn main() { }");
+  module.functions[0].code.insert(0, 200);
+  let listing = disassemble_chunk(&module, &module.functions[0]);
+  assert!(listing.contains("0000     1:11  <invalid opcode 200>"));
+}
+
+#[test]
+fn compiler_errors_on_too_many_constants() {
+  let mut source = "fn main() {\n let a:int = 0;\n".to_string();
+  for value in range(1, 70000) {
+    source.push_str(format!(" a = {};\n", value).as_slice());
+  }
+  source.push_str("}");
+  match compile_checked(source.as_slice()) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("Error at 1:1"));
+      assert!(errors[0].contains("Too many constants in function 'main'"));
+    },
+  }
+}
+
+#[test]
+fn compiler_errors_on_jump_past_operand_range() {
+  let mut source = "fn main() {\n let a:int = 0;\n if (a == 0) {\n".to_string();
+  for _ in range(0, 12000) {
+    source.push_str(" a = 1;\n");
+  }
+  source.push_str(" }\n}");
+  match compile_checked(source.as_slice()) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("Function 'main' is too large"));
+    },
+  }
+}
@@ -0,0 +1,201 @@
+extern crate compiler;
+
+use std::io::MemWriter;
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::type_checker::check;
+use compiler::bytecode::Module;
+use compiler::bytecode::Constant;
+use compiler::bytecode::compiler::compile;
+use compiler::bytecode::file::write;
+use compiler::bytecode::file::read;
+use compiler::bytecode::file::is_bytecode_file;
+use compiler::vm::execute;
+
+// uses every construct accepted by the parser
+static EVERY_CONSTRUCT: &'static str = "const LIMIT:int = 3 * 2;\n\
+  let scale:double = 1.5;\n\
+  let label:string = \"total:\\t\";\n\
+  fn add(a:int, b:float) : double { return a + b as double; }\n\
+  fn plus(a:int, b:float) : double { return add(a, b); }\n\
+  fn nothing() { for (;;) { return; } }\n\
+  fn main() {\n\
+    let sum:double = 0.0;\n\
+    let f:float = 2.5f;\n\
+    let d:double = 2.5d;\n\
+    let flag:bool = false;\n\
+    for (let i:int = 0; i < LIMIT; i = i + 1) {\n\
+      if (i == 1) { sum = sum + plus(i, f); }\n\
+      elif (i >= 4 == true) { sum = sum - 1 * scale; }\n\
+      else { { sum = sum + d / 2; } ; }\n\
+    }\n\
+    if (sum > 100.0) { flag = true; }\n\
+    nothing();\n\
+    print(label + \"x\"); print(sum); print(flag); print(f as int); }";
+
+fn compile_source(source: &str) -> Module {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(check(&mut program).is_ok());
+  compile(&program).unwrap()
+}
+
+fn output_of(module: &Module) -> String {
+  let mut output = MemWriter::new();
+  assert!(execute(module, &mut output).is_ok());
+  String::from_utf8(output.get_ref().to_vec()).unwrap()
+}
+
+#[test]
+fn bytecode_file_round_trips_every_construct() {
+  let module = compile_source(EVERY_CONSTRUCT);
+  let bytes = write(&module);
+  assert!(is_bytecode_file(bytes.as_slice()));
+
+  match read(bytes.as_slice()) {
+    Ok(loaded) => {
+      assert!(module == loaded);
+      assert_eq!(output_of(&module), output_of(&loaded));
+    },
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn bytecode_file_round_trips_module_without_main() {
+  let module = compile_source("fn foo() { }");
+  match read(write(&module).as_slice()) {
+    Ok(loaded) => {
+      assert_eq!(None, loaded.main);
+      assert!(module == loaded);
+    },
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn bytecode_file_loader_rejects_every_truncation() {
+  let bytes = write(&compile_source(EVERY_CONSTRUCT));
+  for length in range(0, bytes.len()) {
+    match read(bytes.slice_to(length)) {
+      Ok(..) => assert!(false),
+      Err(error) => assert!(error.contains("Truncated")),
+    }
+  }
+}
+
+#[test]
+fn bytecode_file_loader_rejects_bad_magic_number() {
+  let mut bytes = write(&compile_source("fn main() { }"));
+  bytes[0] = 0;
+  assert!(!is_bytecode_file(bytes.as_slice()));
+  match read(bytes.as_slice()) {
+    Ok(..) => assert!(false),
+    Err(error) => assert!(error.contains("magic number")),
+  }
+}
+
+#[test]
+fn bytecode_file_loader_rejects_unknown_version() {
+  let mut bytes = write(&compile_source("fn main() { }"));
+  bytes[5] = 99;
+  match read(bytes.as_slice()) {
+    Ok(..) => assert!(false),
+    Err(error) => assert!(error.contains("version 99")),
+  }
+}
+
+#[test]
+fn bytecode_file_loader_rejects_trailing_data() {
+  let mut bytes = write(&compile_source("fn main() { }"));
+  bytes.push(0);
+  match read(bytes.as_slice()) {
+    Ok(..) => assert!(false),
+    Err(error) => assert!(error.contains("after the last function")),
+  }
+}
+
+#[test]
+fn bytecode_file_loader_rejects_out_of_range_function_indices() {
+  let mut bytes = write(&compile_source("fn main() { }"));
+  // init function index follows the header and the global count
+  bytes[13] = 7;
+  match read(bytes.as_slice()) {
+    Ok(..) => assert!(false),
+    Err(error) => {
+      assert!(error.contains("byte 10"));
+      assert!(error.contains("init function index 7"));
+    }
+  }
+}
+
+#[test]
+fn bytecode_file_loader_rejects_invalid_constants() {
+  let module = compile_source("fn main() { print(\"a\"); }");
+  let bytes = write(&module);
+  let index = match module.functions[0].constants[0] {
+    Constant::Text(index) => index as u8,
+    _ => panic!("expected string constant"),
+  };
+  // constant count of main followed by the string constant
+  let pattern = [0, 0, 0, 1, 3, 0, 0, 0, index];
+  let tag_offset = find(bytes.as_slice(), &pattern) + 4;
+
+  let mut bad_tag = bytes.clone();
+  bad_tag[tag_offset] = 9;
+  match read(bad_tag.as_slice()) {
+    Ok(..) => assert!(false),
+    Err(error) => {
+      assert!(error.contains(format!("byte {}", tag_offset).as_slice()));
+      assert!(error.contains("unknown constant tag 9"));
+    }
+  }
+
+  let mut bad_index = bytes.clone();
+  bad_index[tag_offset + 4] = 200;
+  match read(bad_index.as_slice()) {
+    Ok(..) => assert!(false),
+    Err(error) => assert!(error.contains("refers to string 200")),
+  }
+}
+
+#[test]
+fn bytecode_file_loader_rejects_unaddressable_counts() {
+  let bytes = write(&compile_source("fn main() { }"));
+
+  // the global count follows the header
+  let mut globals = bytes.clone();
+  for offset in range(6, 10) {
+    globals[offset] = 0xFF;
+  }
+  match read(globals.as_slice()) {
+    Ok(..) => assert!(false),
+    Err(error) => {
+      assert!(error.contains("byte 10"));
+      assert!(error.contains("4294967295 globals, but at most 65536 can be addressed"));
+    }
+  }
+
+  // name of main followed by its arity, unlike the string in the string
+  // table, and its local count
+  let local_count_offset = find(bytes.as_slice(), &[0, 0, 0, 4, 0x6D, 0x61, 0x69, 0x6E, 0, 0, 0, 0]) + 12;
+  let mut locals = bytes.clone();
+  locals[local_count_offset + 1] = 0x01;
+  locals[local_count_offset + 3] = 0x01;
+  match read(locals.as_slice()) {
+    Ok(..) => assert!(false),
+    Err(error) => {
+      assert!(error.contains(format!("byte {}", local_count_offset + 4).as_slice()));
+      assert!(error.contains("function 'main' has 65537 locals, but at most 65536 can be addressed"));
+    }
+  }
+}
+
+fn find(bytes: &[u8], pattern: &[u8]) -> usize {
+  for start in range(0, bytes.len() - pattern.len()) {
+    if bytes.slice(start, start + pattern.len()) == pattern {
+      return start;
+    }
+  }
+  panic!("pattern not found");
+}
@@ -0,0 +1,83 @@
+extern crate compiler;
+
+use std::io::TempDir;
+use std::io::process::Command;
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::backend::c::generate;
+
+mod support;
+
+fn generate_source(source: &str) -> String {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  generate(&program)
+}
+
+// compiles the generated C with the system compiler and runs it. Returns the
+// output and whether the program succeeded
+fn compile_and_run(source: &str) -> (String, bool) {
+  let dir = TempDir::new("c_backend").unwrap();
+  let c_path = support::write_file(&dir, "program.c", generate_source(source).as_slice());
+  let executable = dir.path().join("program");
+  support::build(Command::new("cc").arg("-std=c99").arg("-o").arg(&executable).arg(&c_path), "cc");
+  support::run(&mut Command::new(&executable), "program")
+}
+
+fn assert_output(source: &str, expected: &str) {
+  support::assert_output(compile_and_run(source), expected);
+}
+
+#[test]
+fn c_backend_declares_functions_before_use() {
+  let c = generate_source("fn main() { print(add(1, 2)); }\nfn add(a:int, b:int) : int { return a + b; }");
+  let prototype = c.find_str("static int32_t fn_add(int32_t, int32_t);").unwrap();
+  let definition = c.find_str("static void fn_main(void) {").unwrap();
+  assert!(prototype < definition);
+}
+
+#[test]
+fn c_backend_prints_every_value_type() {
+  assert_output("fn main() { print(42); print(2.5f); print(0.1); print(true); print(\"a\\t\\\"b\\\"\"); }",
+    "42\n2.5\n0.1\ntrue\na\t\"b\"\n");
+}
+
+#[test]
+fn c_backend_wraps_integer_arithmetic() {
+  assert_output("fn main() { let a:int = 2147483647; print(a + 1); print(-7 / 2); print(3 * 4 - 5); }",
+    "-2147483648\n-3\n7\n");
+}
+
+#[test]
+fn c_backend_translates_control_flow() {
+  let source = "fn classify(a:int) : string {\n if (a < 0) { return \"negative\"; } elif (a == 0) { return \"zero\"; } else { return \"positive\"; } }\nfn main() { for (let i:int = -1; i <= 1; i = i + 1) { print(classify(i)); } for (;;) { return; } }";
+  assert_output(source, "negative\nzero\npositive\n");
+}
+
+#[test]
+fn c_backend_supports_recursion_and_globals() {
+  let source = "const BASE:int = 10;\nlet calls:int = BASE - 10;\nfn fib(n:int) : int { calls = calls + 1; if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }\nfn main() { print(fib(15)); print(calls); }";
+  assert_output(source, "610\n1973\n");
+}
+
+#[test]
+fn c_backend_handles_strings_and_conversions() {
+  let source = "fn main() { let s:string = \"ab\" + \"cd\"; print(s); print(s == \"abcd\"); print(7 / 2.0); print(7.9 as int); print(1 as float / 4); }";
+  assert_output(source, "abcd\ntrue\n3.5\n7\n0.25\n");
+}
+
+#[test]
+fn c_backend_keeps_outer_variable_visible_in_shadowing_initializer() {
+  assert_output("fn main() { let a:int = 1; { let a:int = a + 1; print(a); } print(a); }", "2\n1\n");
+}
+
+#[test]
+fn c_backend_reports_division_by_zero() {
+  let (output, success) = compile_and_run("fn main() {\n let a:int = 0;\n print(10 / a); }");
+  assert!(!success);
+  assert_eq!("Runtime error at 3:11: Division by zero\n", output.as_slice());
+}
@@ -0,0 +1,96 @@
+extern crate compiler;
+
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::call_graph::CallGraph;
+use compiler::ast::Program;
+
+fn check_source(source: &str) -> Program {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  program
+}
+
+#[test]
+fn call_graph_finds_calls_in_nested_statements_and_arguments() {
+  let program = check_source("fn one() : int { return 1; }\nfn two() : int { return 2; }\n\
+    fn limit() : int { return 3; }\nfn show(a:int) { print(a); }\n\
+    fn main() {\n for (let i:int = one(); i < limit(); i = i + one()) {\n  if (i == two()) { show(i + two()); }\n }\n}");
+  let graph = CallGraph::new(&program);
+  let one = graph.function_index("one").unwrap();
+  let two = graph.function_index("two").unwrap();
+  let limit = graph.function_index("limit").unwrap();
+  let show = graph.function_index("show").unwrap();
+  let main = graph.function_index("main").unwrap();
+  assert_eq!([one, limit, two, show].as_slice(), graph.callees(main));
+  assert_eq!(2, graph.call_sites(one));
+  assert_eq!(2, graph.call_sites(two));
+  // print is a built-in function
+  assert!(graph.callees(show).is_empty());
+  assert!(graph.unreachable_functions().is_empty());
+}
+
+#[test]
+fn call_graph_reports_functions_unreachable_from_main() {
+  let program = check_source("fn used() { }\nfn unused() { helper(); }\nfn helper() { }\n\
+    fn lonely() { lonely(); }\nfn main() { used(); }");
+  let graph = CallGraph::new(&program);
+  assert_eq!(vec![1, 2, 3], graph.unreachable_functions());
+  assert!(graph.is_reachable(0));
+  assert_eq!(vec!["Warning at 2:1: Function 'unused' is never called from 'main'".to_string(),
+    "Warning at 3:1: Function 'helper' is never called from 'main'".to_string(),
+    "Warning at 4:1: Function 'lonely' is never called from 'main'".to_string()],
+    compiler::call_graph::check(&program));
+}
+
+#[test]
+fn call_graph_without_main_reports_nothing() {
+  let program = check_source("fn a() { b(); }\nfn b() { }");
+  assert_eq!(vec![0, 1], CallGraph::new(&program).unreachable_functions());
+  assert!(compiler::call_graph::check(&program).is_empty());
+}
+
+#[test]
+fn call_graph_finds_direct_and_mutual_recursion() {
+  let program = check_source("fn down(n:int) { if (n > 0) { down(n - 1); } }\n\
+    fn even(n:int) : bool { if (n == 0) { return true; } return odd(n - 1); }\n\
+    fn odd(n:int) : bool { if (n == 0) { return false; } return even(n - 1); }\n\
+    fn main() { down(3); print(even(4)); }");
+  let graph = CallGraph::new(&program);
+  let down = graph.function_index("down").unwrap();
+  let even = graph.function_index("even").unwrap();
+  let odd = graph.function_index("odd").unwrap();
+  let main = graph.function_index("main").unwrap();
+  assert!(graph.is_directly_recursive(down));
+  assert!(graph.mutually_recursive(down).is_empty());
+  assert!(!graph.is_directly_recursive(even));
+  assert!(graph.is_recursive(even));
+  assert_eq!(vec![odd], graph.mutually_recursive(even));
+  assert_eq!(vec![even], graph.mutually_recursive(odd));
+  assert!(!graph.is_recursive(main));
+
+  let order = graph.bottom_up_order();
+  let position = |function: usize| order.iter().position(|other| *other == function).unwrap();
+  assert!(position(down) < position(main));
+  assert!(position(odd) < position(main));
+}
+
+#[test]
+fn call_graph_is_written_as_dot() {
+  let program = check_source("fn loop(n:int) { if (n > 0) { loop(n - 1); } }\nfn dead() { loop(1); }\n\
+    fn main() { loop(2); loop(3); }");
+  let expected = "digraph calls {
+  \"loop\" [peripheries=2];
+  \"dead\" [style=dashed];
+  \"main\";
+  \"loop\" -> \"loop\";
+  \"dead\" -> \"loop\";
+  \"main\" -> \"loop\";
+}
+";
+  assert_eq!(expected, CallGraph::new(&program).to_dot().as_slice());
+}
@@ -0,0 +1,184 @@
+extern crate compiler;
+
+use std::collections::HashMap;
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::constant_evaluator::Constant;
+use compiler::constant_evaluator::evaluate;
+use compiler::constant_evaluator::fold;
+use compiler::ast::Program;
+use compiler::ast::Statement;
+use compiler::ast::Expression;
+use compiler::ast::ExpressionKind;
+use compiler::ast::Literal;
+
+fn check_source(source: &str) -> Program {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  program
+}
+
+fn fold_source(source: &str) -> Result<Program, Vec<String>> {
+  let mut program = check_source(source);
+  try!(fold(&mut program));
+  Ok(program)
+}
+
+// the initializer of the first statement of the first function
+fn first_initializer(program: &Program) -> &Expression {
+  match program.functions[0].body.statements[0] {
+    Statement::VariableDeclaration(ref declaration) => &declaration.initializer,
+    _ => panic!("Variable declaration expected"),
+  }
+}
+
+#[test]
+fn evaluator_computes_global_constant_initializers() {
+  let program = check_source("const MINUTE:int = 60;\nconst DAY:int = MINUTE * 60 * 24;\n\
+    const HALF:double = 1 / 2.0;\nconst OK:bool = DAY > 1000 == true;\nconst NAME:string = \"a\" + \"b\";\nfn main() { }");
+  let mut constants = HashMap::new();
+  let mut values = vec![];
+  for global in program.globals.iter() {
+    let value = evaluate(&global.initializer, &program.text_table, &constants).unwrap().unwrap();
+    constants.insert(global.name, value.clone());
+    values.push(value);
+  }
+  assert_eq!(vec![Constant::Integer(60), Constant::Integer(86400), Constant::Double(0.5),
+    Constant::Boolean(true), Constant::Text("ab".to_string())], values);
+}
+
+#[test]
+fn evaluator_does_not_evaluate_variables_and_calls() {
+  let program = check_source("fn one() : int { return 1; }\nfn main() { let a:int = 2 * one(); let b:int = a + 1; }");
+  let constants = HashMap::new();
+  assert_eq!(Ok(None), evaluate(first_initializer(&program), &program.text_table, &constants));
+}
+
+#[test]
+fn evaluator_errors_on_division_by_zero() {
+  let program = check_source("fn main() {\n let a:int = 1 + 10 / (5 - 5); }");
+  let constants = HashMap::new();
+  assert_eq!(Err("Error at 2:21: Division by zero in constant expression".to_string()),
+    evaluate(first_initializer(&program), &program.text_table, &constants));
+}
+
+#[test]
+fn folding_replaces_constant_subtrees_with_literals() {
+  let program = fold_source("const DAY:int = 60 * 60 * 24;\n\
+    fn main() { let a:int = DAY * 7 - 1; let b:float = 0.5f * 3 as float; let c:double = 7.9; print(a * (2 + 3)); }").unwrap();
+  assert_eq!(ExpressionKind::Literal(Literal::Integer(86400)), program.globals[0].initializer.kind);
+  assert_eq!(ExpressionKind::Literal(Literal::Integer(604799)), first_initializer(&program).kind);
+  match program.functions[0].body.statements[1] {
+    Statement::VariableDeclaration(ref declaration) =>
+      assert_eq!(ExpressionKind::Literal(Literal::Float(1.5)), declaration.initializer.kind),
+    _ => assert!(false),
+  }
+
+  // only the constant operand of the multiplication is folded
+  match program.functions[0].body.statements[3] {
+    Statement::FunctionCall(ref call) => match call.arguments[0].kind {
+      ExpressionKind::Binary(_, ref left, ref right) => {
+        match left.kind {
+          ExpressionKind::Variable(name) => assert_eq!("a", program.get_text(name)),
+          _ => assert!(false),
+        }
+        assert_eq!(ExpressionKind::Literal(Literal::Integer(5)), right.kind);
+      },
+      _ => assert!(false),
+    },
+    _ => assert!(false),
+  }
+}
+
+#[test]
+fn folding_adds_concatenated_strings_to_text_table() {
+  let program = fold_source("const GREETING:string = \"hello\";\nfn main() { print(GREETING + \", \" + \"world\"); }").unwrap();
+  match program.functions[0].body.statements[0] {
+    Statement::FunctionCall(ref call) => match call.arguments[0].kind {
+      ExpressionKind::Literal(Literal::Text(index)) => assert_eq!("hello, world", program.get_text(index)),
+      _ => assert!(false),
+    },
+    _ => assert!(false),
+  }
+}
+
+#[test]
+fn folding_does_not_replace_locals_shadowing_constants() {
+  let program = fold_source("const A:int = 2;\nfn main() { let b:int = A + 1; { let A:int = 5; b = A * 2; } }").unwrap();
+  assert_eq!(ExpressionKind::Literal(Literal::Integer(3)), first_initializer(&program).kind);
+  match program.functions[0].body.statements[1] {
+    Statement::Block(ref block) => match block.statements[1] {
+      Statement::Assignment(ref assignment) => match assignment.value.kind {
+        ExpressionKind::Binary(..) => { },
+        _ => assert!(false),
+      },
+      _ => assert!(false),
+    },
+    _ => assert!(false),
+  }
+}
+
+#[test]
+fn folding_keeps_results_that_are_not_finite() {
+  let program = fold_source("fn main() { let a:double = 1.0 / 0.0; }").unwrap();
+  match first_initializer(&program).kind {
+    ExpressionKind::Binary(..) => { },
+    _ => assert!(false),
+  }
+}
+
+#[test]
+fn folding_errors_on_division_by_zero_with_position() {
+  match fold_source("const ZERO:int = 0;\nfn main() {\n print(5 / ZERO); }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("3:10"));
+      assert!(errors[0].contains("Division by zero"));
+    }
+  }
+}
+
+#[test]
+fn folding_errors_on_integer_overflow() {
+  match fold_source("const MAX:int = 2147483647;\nconst NEXT:int = MAX + 1;\nfn main() {\n let a:int = 65536 * 65536;\n let b:int = 30000000000.0 as int; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(3, errors.len());
+      assert!(errors[0].contains("2:22"));
+      assert!(errors[0].contains("Integer overflow"));
+      assert!(errors[1].contains("4:20"));
+      assert!(errors[2].contains("5:28"));
+    }
+  }
+}
+
+#[test]
+fn folding_warns_about_errors_in_dead_branches() {
+  let mut program = check_source("fn main() {\n let x:int = 0;\n if (false) {\n x = 1 / 0; }\n else if (true) { x = 2; }\n\
+    else { x = 2147483647 + 1; }\n print(x); }");
+  match fold(&mut program) {
+    Ok(warnings) => {
+      assert_eq!(2, warnings.len());
+      assert!(warnings[0].starts_with("Warning at 4:8: Division by zero"));
+      assert!(warnings[1].starts_with("Warning at 6:24: Integer overflow"));
+    },
+    Err(..) => assert!(false),
+  }
+
+  // the division is left for the runtime check
+  match program.functions[0].body.statements[1] {
+    Statement::If(ref if_statement) => match if_statement.block.statements[0] {
+      Statement::Assignment(ref assignment) => match assignment.value.kind {
+        ExpressionKind::Binary(..) => { },
+        _ => assert!(false),
+      },
+      _ => assert!(false),
+    },
+    _ => assert!(false),
+  }
+}
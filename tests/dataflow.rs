@@ -0,0 +1,105 @@
+extern crate compiler;
+
+use compiler::ir::*;
+use compiler::ir::dataflow::Analysis;
+use compiler::ir::dataflow::Direction;
+use compiler::ir::dataflow::block_facts;
+use compiler::ir::dataflow::solve;
+use compiler::ir::reaching_definitions::Definition;
+use compiler::ir::reaching_definitions::ReachingDefinitions;
+
+fn parse_ir(text: &str) -> Module {
+  match compiler::ir::parser::parse(text) {
+    Ok(module) => module,
+    Err(errors) => panic!("Parsing failed: {:?}", errors),
+  }
+}
+
+// whether a value was printed on every path to a point
+struct PrintedOnEveryPath;
+
+impl Analysis<bool> for PrintedOnEveryPath {
+  fn direction(&self) -> Direction {
+    Direction::Forward
+  }
+
+  fn boundary(&self) -> bool {
+    false
+  }
+
+  fn initial(&self) -> bool {
+    true
+  }
+
+  fn meet(&self, fact: &mut bool, other: &bool) {
+    *fact = *fact && *other;
+  }
+
+  fn transfer(&self, _block: BlockId, _index: usize, instruction: &Instruction, fact: &mut bool) {
+    match *instruction {
+      Instruction::Print(..) => *fact = true,
+      _ => { },
+    }
+  }
+}
+
+static LOOP: &'static str = "fn @f(%0: int) : int {
+bb0:
+  %1: int = copy 0
+  %2: int = copy 0
+  jmp bb1
+bb1:
+  %3: bool = lt %2, %0
+  br %3, bb2, bb3
+bb2:
+  %1: int = add %1, %2
+  %2: int = add %2, 1
+  jmp bb1
+bb3:
+  ret %1
+}
+";
+
+#[test]
+fn solver_meets_facts_of_reachable_predecessors() {
+  let module = parse_ir("fn @f(%0: bool) : void {
+bb0:
+  br %0, bb1, bb2
+bb1:
+  print 1
+  jmp bb3
+bb2:
+  jmp bb3
+bb3:
+  print 2
+  ret
+bb4:
+  jmp bb2
+}
+");
+  let function = &module.functions[0];
+  let solution = solve(function, &PrintedOnEveryPath);
+  assert_eq!(vec![false, false, false, false, true], solution.start);
+  assert_eq!(vec![false, true, false, true, true], solution.end);
+  assert_eq!(vec![false, true], block_facts(function, &PrintedOnEveryPath, &solution, 3));
+}
+
+#[test]
+fn reaching_definitions_of_loop() {
+  let module = parse_ir(LOOP);
+  let reaching = ReachingDefinitions::new(&module.functions[0]);
+  assert_eq!(vec![Definition::Parameter(0)], reaching.at_start(0));
+  assert_eq!(vec![Definition::Parameter(0), Definition::Instruction(0, 0), Definition::Instruction(0, 1),
+    Definition::Instruction(1, 0), Definition::Instruction(2, 0), Definition::Instruction(2, 1)], reaching.at_start(1));
+  assert_eq!(vec![Definition::Instruction(0, 0), Definition::Instruction(2, 0)], reaching.reaching(3, 0, 1));
+}
+
+#[test]
+fn reaching_definitions_are_killed_by_assignments() {
+  let module = parse_ir(LOOP);
+  let reaching = ReachingDefinitions::new(&module.functions[0]);
+  assert_eq!(vec![Definition::Instruction(0, 1), Definition::Instruction(2, 1)], reaching.reaching(2, 0, 2));
+  assert_eq!(vec![Definition::Instruction(2, 0)], reaching.reaching(2, 1, 1));
+  assert_eq!(vec![Definition::Parameter(0), Definition::Instruction(1, 0), Definition::Instruction(2, 0),
+    Definition::Instruction(2, 1)], reaching.at_end(2));
+}
@@ -0,0 +1,183 @@
+@.rt_int_format = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+@.rt_string_format = private unnamed_addr constant [4 x i8] c"%s\0A\00"
+@.rt_float_format = private unnamed_addr constant [5 x i8] c"%.*g\00"
+@.rt_division_format = private unnamed_addr constant [42 x i8] c"Runtime error at %d:%d: Division by zero\0A\00"
+@.rt_memory_message = private unnamed_addr constant [30 x i8] c"Runtime error: Out of memory\0A\00"
+@.rt_true = private unnamed_addr constant [5 x i8] c"true\00"
+@.rt_false = private unnamed_addr constant [6 x i8] c"false\00"
+
+declare i32 @printf(ptr, ...)
+declare i32 @snprintf(ptr, i64, ptr, ...)
+declare double @strtod(ptr, ptr)
+declare float @strtof(ptr, ptr)
+declare i64 @strlen(ptr)
+declare i32 @strcmp(ptr, ptr)
+declare ptr @malloc(i64)
+declare ptr @memcpy(ptr, ptr, i64)
+declare void @exit(i32)
+
+define internal i32 @rt_idiv(i32 %a, i32 %b, i32 %line, i32 %pos) {
+entry:
+  %zero = icmp eq i32 %b, 0
+  br i1 %zero, label %division_by_zero, label %check_overflow
+division_by_zero:
+  call i32 (ptr, ...) @printf(ptr @.rt_division_format, i32 %line, i32 %pos)
+  call void @exit(i32 1)
+  unreachable
+check_overflow:
+  %minimum = icmp eq i32 %a, -2147483648
+  %minus_one = icmp eq i32 %b, -1
+  %overflow = and i1 %minimum, %minus_one
+  br i1 %overflow, label %wrap, label %divide
+wrap:
+  ret i32 -2147483648
+divide:
+  %result = sdiv i32 %a, %b
+  ret i32 %result
+}
+
+define internal ptr @rt_concat(ptr %a, ptr %b) {
+entry:
+  %a_length = call i64 @strlen(ptr %a)
+  %b_length = call i64 @strlen(ptr %b)
+  %length = add i64 %a_length, %b_length
+  %size = add i64 %length, 1
+  %result = call ptr @malloc(i64 %size)
+  %failed = icmp eq ptr %result, null
+  br i1 %failed, label %out_of_memory, label %copy
+out_of_memory:
+  call i32 (ptr, ...) @printf(ptr @.rt_memory_message)
+  call void @exit(i32 1)
+  unreachable
+copy:
+  call ptr @memcpy(ptr %result, ptr %a, i64 %a_length)
+  %tail = getelementptr i8, ptr %result, i64 %a_length
+  %b_size = add i64 %b_length, 1
+  call ptr @memcpy(ptr %tail, ptr %b, i64 %b_size)
+  ret ptr %result
+}
+
+define internal i1 @rt_string_eq(ptr %a, ptr %b) {
+entry:
+  %order = call i32 @strcmp(ptr %a, ptr %b)
+  %equal = icmp eq i32 %order, 0
+  ret i1 %equal
+}
+
+define internal void @rt_print_int(i32 %value) {
+entry:
+  call i32 (ptr, ...) @printf(ptr @.rt_int_format, i32 %value)
+  ret void
+}
+
+define internal void @rt_print_bool(i1 %value) {
+entry:
+  %text = select i1 %value, ptr @.rt_true, ptr @.rt_false
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %text)
+  ret void
+}
+
+define internal void @rt_print_string(ptr %value) {
+entry:
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %value)
+  ret void
+}
+
+; shortest representation that reads back as the same value
+define internal void @rt_print_double(double %value) {
+entry:
+  %buffer = alloca [32 x i8]
+  br label %format
+format:
+  %precision = phi i32 [ 1, %entry ], [ %next, %retry ]
+  call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buffer, i64 32, ptr @.rt_float_format, i32 %precision, double %value)
+  %parsed = call double @strtod(ptr %buffer, ptr null)
+  %same = fcmp oeq double %parsed, %value
+  %exhausted = icmp sge i32 %precision, 17
+  %done = or i1 %same, %exhausted
+  br i1 %done, label %print, label %retry
+retry:
+  %next = add i32 %precision, 1
+  br label %format
+print:
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %buffer)
+  ret void
+}
+
+define internal void @rt_print_float(float %value) {
+entry:
+  %buffer = alloca [32 x i8]
+  %extended = fpext float %value to double
+  br label %format
+format:
+  %precision = phi i32 [ 1, %entry ], [ %next, %retry ]
+  call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buffer, i64 32, ptr @.rt_float_format, i32 %precision, double %extended)
+  %parsed = call float @strtof(ptr %buffer, ptr null)
+  %same = fcmp oeq float %parsed, %value
+  %exhausted = icmp sge i32 %precision, 9
+  %done = or i1 %same, %exhausted
+  br i1 %done, label %print, label %retry
+retry:
+  %next = add i32 %precision, 1
+  br label %format
+print:
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %buffer)
+  ret void
+}
+
+define internal void @rt_init_globals() {
+entry:
+  ret void
+}
+
+define internal double @fn_mix(i32 %p_a, float %p_b, double %p_c) {
+entry:
+  %v_a_0 = alloca i32
+  %v_b_1 = alloca float
+  %v_c_2 = alloca double
+  %v_sum_3 = alloca i32
+  %v_quotient_4 = alloca i32
+  store i32 %p_a, ptr %v_a_0
+  store float %p_b, ptr %v_b_1
+  store double %p_c, ptr %v_c_2
+  %t0 = load i32, ptr %v_a_0
+  %t1 = mul i32 %t0, 2
+  %t2 = sub i32 %t1, 1
+  store i32 %t2, ptr %v_sum_3
+  %t3 = load i32, ptr %v_sum_3
+  %t4 = load i32, ptr %v_a_0
+  %t5 = call i32 @rt_idiv(i32 %t3, i32 %t4, i32 3, i32 26)
+  store i32 %t5, ptr %v_quotient_4
+  %t6 = load i32, ptr %v_quotient_4
+  %t7 = sitofp i32 %t6 to float
+  %t8 = load float, ptr %v_b_1
+  %t9 = fadd float %t7, %t8
+  %t10 = fpext float %t9 to double
+  %t11 = load double, ptr %v_c_2
+  %t12 = fmul double %t10, %t11
+  %t13 = fdiv double %t12, 0x4010000000000000
+  ret double %t13
+}
+
+define internal void @fn_main() {
+entry:
+  %t0 = call double @fn_mix(i32 3, float 0x3FE0000000000000, double 0x4000000000000000)
+  call void @rt_print_double(double %t0)
+  %t1 = sitofp i32 7 to float
+  %t2 = fdiv float %t1, 0x4000000000000000
+  call void @rt_print_float(float %t2)
+  %t3 = fptosi double 0x4007333333333333 to i32
+  call void @rt_print_int(i32 %t3)
+  %t4 = fcmp olt float 0x3FF8000000000000, 0x4000000000000000
+  call void @rt_print_bool(i1 %t4)
+  %t5 = icmp sge i32 3, 4
+  call void @rt_print_bool(i1 %t5)
+  ret void
+}
+
+define i32 @main() {
+entry:
+  call void @rt_init_globals()
+  call void @fn_main()
+  ret i32 0
+}
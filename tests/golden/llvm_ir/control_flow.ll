@@ -0,0 +1,199 @@
+@.rt_int_format = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+@.rt_string_format = private unnamed_addr constant [4 x i8] c"%s\0A\00"
+@.rt_float_format = private unnamed_addr constant [5 x i8] c"%.*g\00"
+@.rt_division_format = private unnamed_addr constant [42 x i8] c"Runtime error at %d:%d: Division by zero\0A\00"
+@.rt_memory_message = private unnamed_addr constant [30 x i8] c"Runtime error: Out of memory\0A\00"
+@.rt_true = private unnamed_addr constant [5 x i8] c"true\00"
+@.rt_false = private unnamed_addr constant [6 x i8] c"false\00"
+
+declare i32 @printf(ptr, ...)
+declare i32 @snprintf(ptr, i64, ptr, ...)
+declare double @strtod(ptr, ptr)
+declare float @strtof(ptr, ptr)
+declare i64 @strlen(ptr)
+declare i32 @strcmp(ptr, ptr)
+declare ptr @malloc(i64)
+declare ptr @memcpy(ptr, ptr, i64)
+declare void @exit(i32)
+
+define internal i32 @rt_idiv(i32 %a, i32 %b, i32 %line, i32 %pos) {
+entry:
+  %zero = icmp eq i32 %b, 0
+  br i1 %zero, label %division_by_zero, label %check_overflow
+division_by_zero:
+  call i32 (ptr, ...) @printf(ptr @.rt_division_format, i32 %line, i32 %pos)
+  call void @exit(i32 1)
+  unreachable
+check_overflow:
+  %minimum = icmp eq i32 %a, -2147483648
+  %minus_one = icmp eq i32 %b, -1
+  %overflow = and i1 %minimum, %minus_one
+  br i1 %overflow, label %wrap, label %divide
+wrap:
+  ret i32 -2147483648
+divide:
+  %result = sdiv i32 %a, %b
+  ret i32 %result
+}
+
+define internal ptr @rt_concat(ptr %a, ptr %b) {
+entry:
+  %a_length = call i64 @strlen(ptr %a)
+  %b_length = call i64 @strlen(ptr %b)
+  %length = add i64 %a_length, %b_length
+  %size = add i64 %length, 1
+  %result = call ptr @malloc(i64 %size)
+  %failed = icmp eq ptr %result, null
+  br i1 %failed, label %out_of_memory, label %copy
+out_of_memory:
+  call i32 (ptr, ...) @printf(ptr @.rt_memory_message)
+  call void @exit(i32 1)
+  unreachable
+copy:
+  call ptr @memcpy(ptr %result, ptr %a, i64 %a_length)
+  %tail = getelementptr i8, ptr %result, i64 %a_length
+  %b_size = add i64 %b_length, 1
+  call ptr @memcpy(ptr %tail, ptr %b, i64 %b_size)
+  ret ptr %result
+}
+
+define internal i1 @rt_string_eq(ptr %a, ptr %b) {
+entry:
+  %order = call i32 @strcmp(ptr %a, ptr %b)
+  %equal = icmp eq i32 %order, 0
+  ret i1 %equal
+}
+
+define internal void @rt_print_int(i32 %value) {
+entry:
+  call i32 (ptr, ...) @printf(ptr @.rt_int_format, i32 %value)
+  ret void
+}
+
+define internal void @rt_print_bool(i1 %value) {
+entry:
+  %text = select i1 %value, ptr @.rt_true, ptr @.rt_false
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %text)
+  ret void
+}
+
+define internal void @rt_print_string(ptr %value) {
+entry:
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %value)
+  ret void
+}
+
+; shortest representation that reads back as the same value
+define internal void @rt_print_double(double %value) {
+entry:
+  %buffer = alloca [32 x i8]
+  br label %format
+format:
+  %precision = phi i32 [ 1, %entry ], [ %next, %retry ]
+  call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buffer, i64 32, ptr @.rt_float_format, i32 %precision, double %value)
+  %parsed = call double @strtod(ptr %buffer, ptr null)
+  %same = fcmp oeq double %parsed, %value
+  %exhausted = icmp sge i32 %precision, 17
+  %done = or i1 %same, %exhausted
+  br i1 %done, label %print, label %retry
+retry:
+  %next = add i32 %precision, 1
+  br label %format
+print:
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %buffer)
+  ret void
+}
+
+define internal void @rt_print_float(float %value) {
+entry:
+  %buffer = alloca [32 x i8]
+  %extended = fpext float %value to double
+  br label %format
+format:
+  %precision = phi i32 [ 1, %entry ], [ %next, %retry ]
+  call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buffer, i64 32, ptr @.rt_float_format, i32 %precision, double %extended)
+  %parsed = call float @strtof(ptr %buffer, ptr null)
+  %same = fcmp oeq float %parsed, %value
+  %exhausted = icmp sge i32 %precision, 9
+  %done = or i1 %same, %exhausted
+  br i1 %done, label %print, label %retry
+retry:
+  %next = add i32 %precision, 1
+  br label %format
+print:
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %buffer)
+  ret void
+}
+
+@.str.0 = private unnamed_addr constant [5 x i8] c"zero\00"
+
+define internal void @rt_init_globals() {
+entry:
+  ret void
+}
+
+define internal i32 @fn_sign(i32 %p_a) {
+entry:
+  %v_a_0 = alloca i32
+  store i32 %p_a, ptr %v_a_0
+  %t0 = load i32, ptr %v_a_0
+  %t1 = icmp slt i32 %t0, 0
+  br i1 %t1, label %if.then.0, label %if.else.0
+if.then.0:
+  ret i32 -1
+dead.1:
+  br label %if.end.0
+if.else.0:
+  %t2 = load i32, ptr %v_a_0
+  %t3 = icmp eq i32 %t2, 0
+  br i1 %t3, label %if.then.2, label %if.else.2
+if.then.2:
+  ret i32 0
+dead.3:
+  br label %if.end.0
+if.else.2:
+  ret i32 1
+if.end.0:
+  unreachable
+}
+
+define internal void @fn_main() {
+entry:
+  %v_i_0 = alloca i32
+  store i32 -1, ptr %v_i_0
+  br label %for.cond.0
+for.cond.0:
+  %t0 = load i32, ptr %v_i_0
+  %t1 = icmp sle i32 %t0, 1
+  br i1 %t1, label %for.body.0, label %for.end.0
+for.body.0:
+  %t2 = load i32, ptr %v_i_0
+  %t3 = call i32 @fn_sign(i32 %t2)
+  %t4 = icmp eq i32 %t3, 0
+  br i1 %t4, label %if.then.1, label %if.end.1
+if.then.1:
+  call void @rt_print_string(ptr @.str.0)
+  br label %if.end.1
+if.end.1:
+  %t5 = load i32, ptr %v_i_0
+  %t6 = add i32 %t5, 1
+  store i32 %t6, ptr %v_i_0
+  br label %for.cond.0
+for.end.0:
+  br label %for.cond.2
+for.cond.2:
+  br label %for.body.2
+for.body.2:
+  ret void
+dead.3:
+  br label %for.cond.2
+for.end.2:
+  ret void
+}
+
+define i32 @main() {
+entry:
+  call void @rt_init_globals()
+  call void @fn_main()
+  ret i32 0
+}
@@ -0,0 +1,178 @@
+@.rt_int_format = private unnamed_addr constant [4 x i8] c"%d\0A\00"
+@.rt_string_format = private unnamed_addr constant [4 x i8] c"%s\0A\00"
+@.rt_float_format = private unnamed_addr constant [5 x i8] c"%.*g\00"
+@.rt_division_format = private unnamed_addr constant [42 x i8] c"Runtime error at %d:%d: Division by zero\0A\00"
+@.rt_memory_message = private unnamed_addr constant [30 x i8] c"Runtime error: Out of memory\0A\00"
+@.rt_true = private unnamed_addr constant [5 x i8] c"true\00"
+@.rt_false = private unnamed_addr constant [6 x i8] c"false\00"
+
+declare i32 @printf(ptr, ...)
+declare i32 @snprintf(ptr, i64, ptr, ...)
+declare double @strtod(ptr, ptr)
+declare float @strtof(ptr, ptr)
+declare i64 @strlen(ptr)
+declare i32 @strcmp(ptr, ptr)
+declare ptr @malloc(i64)
+declare ptr @memcpy(ptr, ptr, i64)
+declare void @exit(i32)
+
+define internal i32 @rt_idiv(i32 %a, i32 %b, i32 %line, i32 %pos) {
+entry:
+  %zero = icmp eq i32 %b, 0
+  br i1 %zero, label %division_by_zero, label %check_overflow
+division_by_zero:
+  call i32 (ptr, ...) @printf(ptr @.rt_division_format, i32 %line, i32 %pos)
+  call void @exit(i32 1)
+  unreachable
+check_overflow:
+  %minimum = icmp eq i32 %a, -2147483648
+  %minus_one = icmp eq i32 %b, -1
+  %overflow = and i1 %minimum, %minus_one
+  br i1 %overflow, label %wrap, label %divide
+wrap:
+  ret i32 -2147483648
+divide:
+  %result = sdiv i32 %a, %b
+  ret i32 %result
+}
+
+define internal ptr @rt_concat(ptr %a, ptr %b) {
+entry:
+  %a_length = call i64 @strlen(ptr %a)
+  %b_length = call i64 @strlen(ptr %b)
+  %length = add i64 %a_length, %b_length
+  %size = add i64 %length, 1
+  %result = call ptr @malloc(i64 %size)
+  %failed = icmp eq ptr %result, null
+  br i1 %failed, label %out_of_memory, label %copy
+out_of_memory:
+  call i32 (ptr, ...) @printf(ptr @.rt_memory_message)
+  call void @exit(i32 1)
+  unreachable
+copy:
+  call ptr @memcpy(ptr %result, ptr %a, i64 %a_length)
+  %tail = getelementptr i8, ptr %result, i64 %a_length
+  %b_size = add i64 %b_length, 1
+  call ptr @memcpy(ptr %tail, ptr %b, i64 %b_size)
+  ret ptr %result
+}
+
+define internal i1 @rt_string_eq(ptr %a, ptr %b) {
+entry:
+  %order = call i32 @strcmp(ptr %a, ptr %b)
+  %equal = icmp eq i32 %order, 0
+  ret i1 %equal
+}
+
+define internal void @rt_print_int(i32 %value) {
+entry:
+  call i32 (ptr, ...) @printf(ptr @.rt_int_format, i32 %value)
+  ret void
+}
+
+define internal void @rt_print_bool(i1 %value) {
+entry:
+  %text = select i1 %value, ptr @.rt_true, ptr @.rt_false
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %text)
+  ret void
+}
+
+define internal void @rt_print_string(ptr %value) {
+entry:
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %value)
+  ret void
+}
+
+; shortest representation that reads back as the same value
+define internal void @rt_print_double(double %value) {
+entry:
+  %buffer = alloca [32 x i8]
+  br label %format
+format:
+  %precision = phi i32 [ 1, %entry ], [ %next, %retry ]
+  call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buffer, i64 32, ptr @.rt_float_format, i32 %precision, double %value)
+  %parsed = call double @strtod(ptr %buffer, ptr null)
+  %same = fcmp oeq double %parsed, %value
+  %exhausted = icmp sge i32 %precision, 17
+  %done = or i1 %same, %exhausted
+  br i1 %done, label %print, label %retry
+retry:
+  %next = add i32 %precision, 1
+  br label %format
+print:
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %buffer)
+  ret void
+}
+
+define internal void @rt_print_float(float %value) {
+entry:
+  %buffer = alloca [32 x i8]
+  %extended = fpext float %value to double
+  br label %format
+format:
+  %precision = phi i32 [ 1, %entry ], [ %next, %retry ]
+  call i32 (ptr, i64, ptr, ...) @snprintf(ptr %buffer, i64 32, ptr @.rt_float_format, i32 %precision, double %extended)
+  %parsed = call float @strtof(ptr %buffer, ptr null)
+  %same = fcmp oeq float %parsed, %value
+  %exhausted = icmp sge i32 %precision, 9
+  %done = or i1 %same, %exhausted
+  br i1 %done, label %print, label %retry
+retry:
+  %next = add i32 %precision, 1
+  br label %format
+print:
+  call i32 (ptr, ...) @printf(ptr @.rt_string_format, ptr %buffer)
+  ret void
+}
+
+@g_GREETING = internal global ptr zeroinitializer
+@g_count = internal global i32 zeroinitializer
+
+@.str.0 = private unnamed_addr constant [6 x i8] c"hello\00"
+@.str.1 = private unnamed_addr constant [3 x i8] c", \00"
+@.str.2 = private unnamed_addr constant [2 x i8] c"\0A\00"
+@.str.3 = private unnamed_addr constant [6 x i8] c"world\00"
+@.str.4 = private unnamed_addr constant [6 x i8] c"again\00"
+@.str.5 = private unnamed_addr constant [14 x i8] c"hello, again\0A\00"
+
+define internal void @rt_init_globals() {
+entry:
+  store ptr @.str.0, ptr @g_GREETING
+  store i32 0, ptr @g_count
+  ret void
+}
+
+define internal ptr @fn_greet(ptr %p_name) {
+entry:
+  %v_name_0 = alloca ptr
+  store ptr %p_name, ptr %v_name_0
+  %t0 = load i32, ptr @g_count
+  %t1 = add i32 %t0, 1
+  store i32 %t1, ptr @g_count
+  %t2 = load ptr, ptr @g_GREETING
+  %t3 = call ptr @rt_concat(ptr %t2, ptr @.str.1)
+  %t4 = load ptr, ptr %v_name_0
+  %t5 = call ptr @rt_concat(ptr %t3, ptr %t4)
+  %t6 = call ptr @rt_concat(ptr %t5, ptr @.str.2)
+  ret ptr %t6
+}
+
+define internal void @fn_main() {
+entry:
+  %t0 = call ptr @fn_greet(ptr @.str.3)
+  call void @rt_print_string(ptr %t0)
+  %t1 = call ptr @fn_greet(ptr @.str.4)
+  %t2 = call i1 @rt_string_eq(ptr %t1, ptr @.str.5)
+  call void @rt_print_bool(i1 %t2)
+  %t3 = load i32, ptr @g_count
+  call void @rt_print_int(i32 %t3)
+  call void @rt_print_bool(i1 true)
+  ret void
+}
+
+define i32 @main() {
+entry:
+  call void @rt_init_globals()
+  call void @fn_main()
+  ret i32 0
+}
@@ -0,0 +1,138 @@
+extern crate compiler;
+
+use std::io::MemWriter;
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::interpreter::run;
+
+fn run_source(source: &str) -> Result<String, String> {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+
+  let mut output = MemWriter::new();
+  try!(run(&program, &mut output));
+  Ok(String::from_utf8(output.get_ref().to_vec()).unwrap())
+}
+
+#[test]
+fn interpreter_prints_every_literal_type() {
+  match run_source("fn main() {\n print(42);\n print(2.5f);\n print(0.5);\n print(true);\n print(\"hello\\tworld\"); }") {
+    Ok(output) => assert_eq!("42\n2.5\n0.5\ntrue\nhello\tworld\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn interpreter_evaluates_arithmetic_with_precedence() {
+  match run_source("fn main() { print(1 + 2 * 3 - 8 / 4); print((1 + 2) * 3); print(-7 / 2); }") {
+    Ok(output) => assert_eq!("5\n9\n-3\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn interpreter_concatenates_and_compares_strings() {
+  match run_source("fn main() { let s:string = \"ab\" + \"cd\"; print(s); print(s == \"abcd\"); }") {
+    Ok(output) => assert_eq!("abcd\ntrue\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn interpreter_applies_numeric_conversions() {
+  match run_source("fn main() { let d:double = 7; print(d / 2); print(7.9 as int); }") {
+    Ok(output) => assert_eq!("3.5\n7\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn interpreter_executes_if_elif_else() {
+  let source = "fn classify(a:int) {\n if (a < 0) { print(\"negative\"); } elif (a == 0) { print(\"zero\"); } else { print(\"positive\"); } }\nfn main() { classify(-1); classify(0); classify(1); }";
+  match run_source(source) {
+    Ok(output) => assert_eq!("negative\nzero\npositive\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn interpreter_executes_for_loops() {
+  match run_source("fn main() { let sum:int = 0; for (let i:int = 1; i <= 10; i = i + 1) { sum = sum + i; } print(sum); }") {
+    Ok(output) => assert_eq!("55\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn interpreter_returns_from_inside_loop() {
+  match run_source("fn first_above(limit:int) : int { for (let i:int = 0;; i = i + 1) { if (i * i > limit) { return i; } } return -1; }\nfn main() { print(first_above(50)); }") {
+    Ok(output) => assert_eq!("8\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn interpreter_supports_recursion() {
+  match run_source("fn fib(n:int) : int { if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }\nfn main() { print(fib(15)); }") {
+    Ok(output) => assert_eq!("610\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn interpreter_respects_block_scopes() {
+  match run_source("fn main() { let a:int = 1; { let a:int = 2; print(a); a = 3; } print(a); }") {
+    Ok(output) => assert_eq!("2\n1\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn interpreter_initializes_and_updates_globals() {
+  match run_source("const BASE:int = 10;\nlet counter:int = BASE * 2;\nfn bump() { counter = counter + 1; }\nfn main() { bump(); bump(); print(counter); }") {
+    Ok(output) => assert_eq!("22\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn interpreter_reports_division_by_zero_with_position() {
+  match run_source("fn main() {\n let a:int = 0;\n print(10 / a); }") {
+    Ok(..) => assert!(false),
+    Err(error) => {
+      assert!(error.contains("3:11"));
+      assert!(error.contains("Division by zero"));
+    }
+  }
+}
+
+#[test]
+fn interpreter_reports_too_deep_recursion() {
+  match run_source("fn forever(a:int) { forever(a + 1); }\nfn main() { forever(0); }") {
+    Ok(..) => assert!(false),
+    Err(error) => assert!(error.contains("1:21")),
+  }
+}
+
+#[test]
+fn interpreter_runs_deep_tail_recursion_in_constant_frames() {
+  match run_source("fn count(n:int, total:int) : int { if (n == 0) { return total; } return count(n - 1, total + n); }\n\
+    fn even(n:int) : bool { if (n == 0) { return true; } return odd(n - 1); }\n\
+    fn odd(n:int) : bool { if (n == 0) { return false; } return even(n - 1); }\n\
+    fn main() { print(count(100000, 0)); print(even(100001)); }") {
+    Ok(output) => assert_eq!("705082704\nfalse\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn interpreter_errors_on_missing_main() {
+  match run_source("fn foo() { }") {
+    Ok(..) => assert!(false),
+    Err(error) => assert!(error.contains("main")),
+  }
+}
@@ -0,0 +1,237 @@
+extern crate compiler;
+
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::ast::Position;
+use compiler::ast::Type;
+use compiler::ir::*;
+use compiler::ir::lowering::lower;
+use compiler::ir::printer::print;
+use compiler::ir::printer::print_function;
+
+fn lower_source(source: &str) -> Module {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  lower(&program)
+}
+
+fn parse_ir(text: &str) -> Module {
+  match compiler::ir::parser::parse(text) {
+    Ok(module) => module,
+    Err(errors) => panic!("Parsing failed: {:?}", errors),
+  }
+}
+
+fn assert_parse_error(text: &str, expected: &str) {
+  match compiler::ir::parser::parse(text) {
+    Ok(..) => panic!("Parsing succeeded, expected '{}'", expected),
+    Err(errors) => assert_eq!(expected, errors[0].as_slice()),
+  }
+}
+
+#[test]
+fn lowering_creates_function_per_declaration_and_global_initializer() {
+  let module = lower_source("let g:int = 1;\nfn foo(a:int, b:double) { }\nfn main() { }");
+  assert_eq!(3, module.functions.len());
+  assert_eq!(Some(1), module.main);
+  assert_eq!(2, module.init);
+  assert_eq!(vec![Global { name: "g".to_string(), global_type: Type::Integer }], module.globals);
+  assert_eq!(vec![0, 1], module.functions[0].parameters);
+  assert_eq!(vec![Type::Integer, Type::Double], module.functions[0].registers);
+  assert_eq!("fn @<globals>() : void {\nbb0:\n  store @g, 1\n  ret\n}\n",
+    print_function(&module, &module.functions[2]).as_slice());
+}
+
+#[test]
+fn lowering_translates_expressions_into_three_address_instructions() {
+  let module = lower_source("let g:int = 1;\nfn f(a:int) : double {\n let b:int = a * 2 + g / a; g = b; return b as double / 2.0; }");
+  let expected = "fn @f(%0: int) : double {
+bb0:
+  %1: int = mul %0, 2
+  %2: int = load @g
+  %3: int = div %2, %0 at 3:24
+  %4: int = add %1, %3
+  %5: int = copy %4
+  store @g, %5
+  %6: double = convert %5
+  %7: double = div %6, 2.0
+  ret %7
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+}
+
+#[test]
+fn lowering_builds_loop_header_body_and_exit_blocks() {
+  let module = lower_source("fn main() { for (let i:int = 0; i < 3; i = i + 1) { print(i); } print(\"done\"); }");
+  let expected = "fn @main() : void {
+bb0:
+  %0: int = copy 0
+  jmp bb1
+bb1:
+  %1: bool = lt %0, 3
+  br %1, bb2, bb3
+bb2:
+  print %0
+  %2: int = add %0, 1
+  %0: int = copy %2
+  jmp bb1
+bb3:
+  print \"done\"
+  ret
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+}
+
+#[test]
+fn lowering_chains_elif_conditions() {
+  let module = lower_source("fn sign(a:int) : int { let s:int = 0; if (a < 0) { s = -1; } elif (a > 0) { s = 1; } else { print(a); } return s; }");
+  let expected = "fn @sign(%0: int) : int {
+bb0:
+  %1: int = copy 0
+  %2: bool = lt %0, 0
+  br %2, bb1, bb2
+bb1:
+  %1: int = copy -1
+  jmp bb5
+bb2:
+  %3: bool = gt %0, 0
+  br %3, bb3, bb4
+bb3:
+  %1: int = copy 1
+  jmp bb5
+bb4:
+  print %0
+  jmp bb5
+bb5:
+  ret %1
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+}
+
+#[test]
+fn lowering_removes_unreachable_blocks() {
+  let module = lower_source("fn f(a:bool) : int { if (a) { return 1; } else { return 2; } print(3); }\nfn g() { for (;;) { } print(1); }");
+  let f = &module.functions[0];
+  assert_eq!(3, f.blocks.len());
+  assert_eq!(Terminator::Return(Some(Operand::Integer(1))), f.blocks[1].terminator);
+  assert_eq!(Terminator::Return(Some(Operand::Integer(2))), f.blocks[2].terminator);
+
+  let g = &module.functions[1];
+  assert_eq!(vec![Terminator::Jump(1), Terminator::Jump(2), Terminator::Jump(1)],
+    g.blocks.iter().map(|block| block.terminator.clone()).collect::<Vec<Terminator>>());
+}
+
+#[test]
+fn lowering_returns_at_end_of_void_function() {
+  let module = lower_source("fn f(a:bool) { if (a) { return; } print(1); }");
+  let blocks = &module.functions[0].blocks;
+  assert_eq!(4, blocks.len());
+  assert_eq!(Terminator::Return(None), blocks[1].terminator);
+  assert_eq!(vec![Instruction::Print(Operand::Integer(1))], blocks[3].instructions);
+  assert_eq!(Terminator::Return(None), blocks[3].terminator);
+}
+
+#[test]
+fn lowering_keeps_shadowed_variables_in_separate_registers() {
+  let module = lower_source("fn main() { let a:int = 1; { let a:int = a + 1; print(a); } print(a); }");
+  let instructions = &module.functions[0].blocks[0].instructions;
+  assert_eq!(Instruction::Binary(1, compiler::ast::BinaryOperator::Plus, Operand::Register(0), Operand::Integer(1)),
+    instructions[1]);
+  assert_eq!(Instruction::Print(Operand::Register(2)), instructions[3]);
+  assert_eq!(Instruction::Print(Operand::Register(0)), instructions[4]);
+}
+
+#[test]
+fn printed_module_parses_into_same_module() {
+  let sources = [
+    "const BASE:int = 10;\nlet calls:int = BASE - 10;\nfn fib(n:int) : int { calls = calls + 1; if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }\nfn main() { print(fib(15)); print(calls); }",
+    "fn main() { let s:string = \"a\\t\\\"b\\\"\\\\\" + \"c\\n\"; print(s == \"x\"); print(7 / 2.0); print(7.9 as int); print(1 as float / 4); print(0.1f); }",
+    "fn f(a:int, b:float, c:double, d:bool) { for (;;) { if (d) { return; } } }\nfn main() { f(1, 2.5f, 0.1, true); }",
+  ];
+  for source in sources.iter() {
+    let module = lower_source(*source);
+    let text = print(&module);
+    let parsed = parse_ir(text.as_slice());
+    assert_eq!(text, print(&parsed));
+    assert_eq!(module, parsed);
+  }
+}
+
+#[test]
+fn parser_reads_hand_written_functions() {
+  let module = parse_ir("global @count: int
+
+// returns the absolute value
+fn @abs(%0: int) : int {
+bb0:
+  %1: bool = lt %0, 0
+  br %1, bb1, bb2
+bb1:
+  %2: int = sub 0, %0
+  ret %2
+bb2:
+  ret %0   // already positive
+}
+
+fn @main() : void {
+bb0:
+  %0: int = call @abs(-5)
+  %1: int = div %0, 0 at 3:7
+  store @count, %1
+  print 2.5f
+  print 1e300
+  print \"\\x01\"
+  call @main()
+  ret
+}
+");
+  assert_eq!(3, module.functions.len());
+  assert_eq!(Some(1), module.main);
+  assert_eq!(2, module.init);
+  assert_eq!(vec![Type::Integer, Type::Boolean, Type::Integer], module.functions[0].registers);
+  assert_eq!(Terminator::Branch(Operand::Register(1), 1, 2), module.functions[0].blocks[0].terminator);
+
+  let main = &module.functions[1].blocks[0].instructions;
+  assert_eq!(Instruction::Call(Some(0), 0, vec![Operand::Integer(-5)]), main[0]);
+  assert_eq!(Instruction::Divide(1, Operand::Register(0), Operand::Integer(0), Position::new(3, 7)), main[1]);
+  assert_eq!(Instruction::StoreGlobal(0, Operand::Register(1)), main[2]);
+  assert_eq!(Instruction::Print(Operand::Float(2.5)), main[3]);
+  assert_eq!(Instruction::Print(Operand::Double(1e300)), main[4]);
+  assert_eq!(Instruction::Print(Operand::Text("\x01".to_string())), main[5]);
+  assert_eq!(Instruction::Call(None, 1, vec![]), main[6]);
+}
+
+#[test]
+fn parser_reports_syntax_errors_with_position() {
+  assert_parse_error("fn @f() : void {\nbb0:\n  %0: int = copy\n  ret\n}", "Error at 3:17: Expected operand");
+  assert_parse_error("fn @f() : void {\nbb0:\n  %0: int = move 1\n  ret\n}", "Error at 3:13: Unknown instruction 'move'");
+  assert_parse_error("fn @f() : void {\nbb0:\n  ret 1 2\n}", "Error at 3:9: Unexpected input at end of line");
+  assert_parse_error("global @g: number", "Error at 1:12: Expected type");
+}
+
+#[test]
+fn parser_checks_blocks() {
+  assert_parse_error("fn @f() : void {\nbb0:\n  jmp bb2\nbb1:\n  ret\n}", "Error at 3:7: Block bb2 does not exist");
+  assert_parse_error("fn @f() : void {\nbb0:\n  print 1\nbb1:\n  ret\n}", "Error at 4:1: Block bb0 has no terminator");
+  assert_parse_error("fn @f() : void {\nbb1:\n  ret\n}", "Error at 4:1: Blocks of function '@f' are not numbered bb0 to bb0");
+  assert_parse_error("fn @f() : void {\nbb0:\n  ret\n  ret\n}", "Error at 4:3: Expected block label");
+  assert_parse_error("fn @f() : void {\nbb0:\n  ret\n", "Error at 4:1: Function '@f' is not closed with '}'");
+}
+
+#[test]
+fn parser_checks_registers_functions_and_globals() {
+  assert_parse_error("fn @f() : void {\nbb0:\n  print %3\n  ret\n}", "Error at 3:9: Register %3 is never defined");
+  assert_parse_error("fn @f() : void {\nbb0:\n  %0: int = copy 1\n  %0: bool = copy true\n  ret\n}",
+    "Error at 4:3: Register %0 is defined as bool, but was defined as int before");
+  assert_parse_error("fn @f() : void {\nbb0:\n  call @g()\n  ret\n}", "Error at 3:8: Function '@g' is not defined");
+  assert_parse_error("fn @f(%0: int) : void {\nbb0:\n  call @f()\n  ret\n}",
+    "Error at 3:3: Call passes 0 argument(s), but the function has 1 parameter(s)");
+  assert_parse_error("fn @f() : void {\nbb0:\n  store @g, 1\n  ret\n}", "Error at 3:9: Global '@g' is not defined");
+}
@@ -0,0 +1,121 @@
+extern crate compiler;
+
+use std::io::TempDir;
+use std::io::process::Command;
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::backend::javascript::generate;
+
+mod support;
+
+fn generate_source(source: &str) -> String {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  generate(&program, source)
+}
+
+// translated program without the runtime
+fn translate(source: &str) -> String {
+  let javascript = generate_source(source);
+  let end = javascript.as_slice().find_str("\n// runtime\n").unwrap();
+  javascript.as_slice().slice_to(end).to_string()
+}
+
+// runs the generated code with node. Returns the output and whether the
+// program succeeded
+fn run(source: &str) -> (String, bool) {
+  let dir = TempDir::new("javascript_backend").unwrap();
+  let path = support::write_file(&dir, "program.js", generate_source(source).as_slice());
+  support::run(Command::new("node").arg(&path), "node")
+}
+
+fn assert_output(source: &str, expected: &str) {
+  support::assert_output(run(source), expected);
+}
+
+#[test]
+fn javascript_backend_keeps_comments_and_line_structure() {
+  let source = "// counts down\nfn main() {\n\tlet i:int = 3; // start\n\n  for (; i > 0; i = i - 1)\n  {\n    print(i);\n  }\n}\n";
+  let expected = "// counts down\nfunction main() {\n\tlet i = 3; // start\n\n  for (; i > 0; i = (i - 1 | 0))\n  {\n    $print(i);\n  }\n}\n";
+  assert_eq!(expected, translate(source).as_slice());
+}
+
+#[test]
+fn javascript_backend_translates_if_elif_else() {
+  let source = "fn sign(a:int) : int {\n  if (a < 0) { return -1; }\n  elif (a == 0) { return 0; } else {\n    return 1;\n  }\n}";
+  let expected = "function sign(a) {\n  if (a < 0) { return -1; }\n  else if (a === 0) { return 0; } else {\n    return 1;\n  }\n}\n";
+  assert_eq!(expected, translate(source).as_slice());
+}
+
+#[test]
+fn javascript_backend_ignores_braces_and_comment_markers_in_strings() {
+  let source = "fn main() { print(\"{ // }\"); // done\n}";
+  assert_eq!("function main() { $print(\"{ // }\"); // done\n}\n", translate(source).as_slice());
+}
+
+#[test]
+fn javascript_backend_emulates_int_and_float_arithmetic() {
+  let javascript = translate("fn f(a:int, b:float, c:double) : double {\n a = a + a * a - a / a; b = b * 0.1f; return c / 2.0 + b as double; }");
+  assert!(javascript.contains("a = ((a + Math.imul(a, a) | 0) - $idiv(a, a, 2, 20) | 0);"));
+  assert!(javascript.contains("b = Math.fround(b * Math.fround(0.1));"));
+  assert!(javascript.contains("return c / 2 + b;"));
+}
+
+#[test]
+fn javascript_backend_renames_reserved_and_shadowing_names() {
+  let javascript = translate("let delete:int = 1;\nfn var(this:int) : int { let delete:int = delete + this; { let delete:int = delete; } return delete; }");
+  assert!(javascript.contains("let delete$ = 1;"));
+  assert!(javascript.contains("function var$(this$) { let delete$$1 = (delete$ + this$ | 0); { let delete$$2 = delete$$1; } return delete$$1; }"));
+}
+
+#[test]
+fn javascript_backend_calls_main_after_runtime() {
+  assert!(generate_source("fn main() { }").as_slice().ends_with("\nmain();\n"));
+  assert!(!generate_source("fn helper() { }").as_slice().contains("helper();"));
+}
+
+#[test]
+fn javascript_backend_prints_every_value_type() {
+  assert_output("fn main() { print(42); print(2.5f); print(0.1); print(true); print(\"a\\t\\\"b\\\"\"); }",
+    "42\n2.5\n0.1\ntrue\na\t\"b\"\n");
+}
+
+#[test]
+fn javascript_backend_wraps_integer_arithmetic() {
+  let source = "fn main() { let a:int = 2147483647; print(a + 1); print(a * a); print(-7 / 2); print(3 * 4 - 5);\n let min:int = -2147483647 - 1; print(min / -1); print(-7.9 as int); }";
+  assert_output(source, "-2147483648\n1\n-3\n7\n-2147483648\n-7\n");
+}
+
+#[test]
+fn javascript_backend_formats_floats_like_printf() {
+  let source = "fn main() { print(1.0f / 3.0f); print(1.0 / 3.0); print(0.1f + 0.2f); print(0.00001234); print(1234567890123456789.0); print(-0.0); print(1.0 / 0.0); }";
+  assert_output(source, "0.33333334\n0.3333333333333333\n0.3\n1.234e-05\n1.2345678901234568e+18\n-0\ninf\n");
+}
+
+#[test]
+fn javascript_backend_supports_recursion_and_globals() {
+  let source = "const BASE:int = 10;\nlet calls:int = BASE - 10;\nfn fib(n:int) : int { calls = calls + 1; if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }\nfn main() { print(fib(15)); print(calls); }";
+  assert_output(source, "610\n1973\n");
+}
+
+#[test]
+fn javascript_backend_translates_control_flow() {
+  let source = "fn classify(a:int) : string {\n if (a < 0) { return \"negative\"; } elif (a == 0) { return \"zero\"; } else { return \"positive\"; } }\nfn main() { for (let i:int = -1; i <= 1; i = i + 1) { print(classify(i)); } for (;;) { return; } }";
+  assert_output(source, "negative\nzero\npositive\n");
+}
+
+#[test]
+fn javascript_backend_keeps_outer_variable_visible_in_shadowing_initializer() {
+  assert_output("fn main() { let a:int = 1; { let a:int = a + 1; print(a); } print(a); }", "2\n1\n");
+}
+
+#[test]
+fn javascript_backend_reports_division_by_zero() {
+  let (output, success) = run("fn main() {\n let a:int = 0;\n print(10 / a); }");
+  assert!(!success);
+  assert_eq!("Runtime error at 3:11: Division by zero\n", output.as_slice());
+}
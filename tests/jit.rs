@@ -0,0 +1,170 @@
+extern crate compiler;
+
+use std::io::IoError;
+use std::io::IoErrorKind;
+use std::io::IoResult;
+use std::io::MemWriter;
+use std::iter;
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::bytecode::Module;
+use compiler::bytecode::compiler::compile;
+use compiler::vm::execute;
+use compiler::vm::execute_with_jit;
+use compiler::vm::jit::Jit;
+use compiler::vm::jit::analysis::Failure;
+use compiler::vm::jit::analysis::analyze;
+
+fn compile_source(source: &str) -> Module {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  compile(&program).unwrap()
+}
+
+fn function_index(module: &Module, name: &str) -> usize {
+  module.functions.iter().position(|chunk| chunk.name.as_slice() == name).unwrap()
+}
+
+fn run_module(module: &Module) -> Result<String, String> {
+  let mut output = MemWriter::new();
+  try!(execute(module, &mut output));
+  Ok(String::from_utf8(output.get_ref().to_vec()).unwrap())
+}
+
+fn run_module_with_jit(module: &Module, jit: &mut Jit) -> Result<String, String> {
+  let mut output = MemWriter::new();
+  try!(execute_with_jit(module, &mut output, jit));
+  Ok(String::from_utf8(output.get_ref().to_vec()).unwrap())
+}
+
+// runs the module with and without the compiler, which must give the same
+// result, and returns the compiler
+fn assert_same_result(module: &Module, threshold: usize) -> Jit {
+  let mut jit = Jit::new(threshold);
+  assert_eq!(run_module(module), run_module_with_jit(module, &mut jit));
+  jit
+}
+
+#[test]
+fn jit_compiles_hot_recursive_function() {
+  let module = compile_source("fn fib(n:int) : int { if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }\n\
+    fn main() { print(fib(20)); }");
+  let jit = assert_same_result(&module, 10);
+  assert!(jit.is_compiled(function_index(&module, "fib")));
+  assert!(!jit.is_compiled(function_index(&module, "main")));
+  assert_eq!(Ok("6765\n".to_string()), run_module(&module));
+}
+
+#[test]
+fn jit_matches_virtual_machine_on_integers_and_booleans() {
+  let module = compile_source("fn even(n:int) : bool { if (n == 0) { return true; } return odd(n - 1); }\n\
+    fn odd(n:int) : bool { if (n == 0) { return false; } return even(n - 1); }\n\
+    fn pick(flag:bool, a:int, b:int) : int { if (flag == true) { return a; } return b; }\n\
+    fn divide(a:int, b:int) : int { return a / b; }\n\
+    fn report(a:int) { print(a); print(a > 0); }\n\
+    fn main() {\n\
+     for (let i:int = -5; i <= 5; i = i + 1) {\n\
+      print(even(i * i));\n\
+      print(pick(i < 0, i * 2147483647, divide(i, 3)));\n\
+      print(divide(-2147483647 - 1, -1));\n\
+      report(i - 2);\n\
+     }\n\
+    }");
+  let jit = assert_same_result(&module, 2);
+  for name in vec!["even", "odd", "pick", "divide", "report"].into_iter() {
+    assert!(jit.is_compiled(function_index(&module, name)), "{} is not compiled", name);
+  }
+}
+
+#[test]
+fn jit_reports_runtime_errors_of_compiled_code() {
+  let module = compile_source("fn divide(a:int, b:int) : int { return a / b; }\n\
+    fn main() { for (let i:int = 3; i >= 0; i = i - 1) { print(divide(12, i)); } }");
+  let jit = assert_same_result(&module, 1);
+  assert!(jit.is_compiled(function_index(&module, "divide")));
+  match run_module(&module) {
+    Err(error) => assert!(error.contains("Division by zero")),
+    Ok(..) => assert!(false),
+  }
+}
+
+#[test]
+fn jit_keeps_maximum_call_depth() {
+  let module = compile_source("fn deep(n:int) : int { if (n == 0) { return 0; } return deep(n - 1) + 1; }\n\
+    fn main() { print(deep(10)); print(deep(5000)); }");
+  let jit = assert_same_result(&module, 5);
+  assert!(jit.is_compiled(function_index(&module, "deep")));
+  match run_module(&module) {
+    Err(error) => assert!(error.contains("Maximum call depth")),
+    Ok(..) => assert!(false),
+  }
+}
+
+#[test]
+fn jit_leaves_unsupported_functions_to_virtual_machine() {
+  let module = compile_source("fn half(a:double) : double { return a / 2.0; }\n\
+    fn greet(name:string) { print(\"hello \" + name); }\n\
+    fn count(n:int) : int { return n + 1; }\n\
+    fn main() { for (let i:int = 0; i < 5; i = i + 1) { print(half(i as double)); greet(\"you\"); print(count(i)); } }");
+  let jit = assert_same_result(&module, 1);
+  assert!(!jit.is_compiled(function_index(&module, "half")));
+  assert!(!jit.is_compiled(function_index(&module, "greet")));
+  assert!(jit.is_compiled(function_index(&module, "count")));
+  assert!(jit.unsupported_reason(function_index(&module, "half")).is_some());
+  assert_eq!(None, jit.unsupported_reason(function_index(&module, "count")));
+}
+
+// output that can not be written
+struct FullDisk;
+
+impl Writer for FullDisk {
+  fn write(&mut self, _buf: &[u8]) -> IoResult<()> {
+    Err(IoError { kind: IoErrorKind::OtherIoError, desc: "disk full", detail: None })
+  }
+}
+
+#[test]
+fn jit_reports_failed_output_of_compiled_code() {
+  let module = compile_source("fn report(a:int) { print(a); }\nfn main() { report(1); report(2); }");
+  let mut jit = Jit::new(1);
+  let error = execute_with_jit(&module, &mut FullDisk, &mut jit);
+  assert!(jit.is_compiled(function_index(&module, "report")));
+  assert_eq!(execute(&module, &mut FullDisk), error);
+  match error {
+    Err(error) => assert!(error.contains("Failed to write output")),
+    Ok(..) => assert!(false),
+  }
+}
+
+#[test]
+fn jit_compiles_called_functions_with_caller() {
+  let module = compile_source("fn square(a:int) : int { return a * a; }\n\
+    fn sum(n:int) : int { let total:int = 0; for (let i:int = 1; i <= n; i = i + 1) { total = total + square(i); } return total; }\n\
+    fn main() { print(square(3)); for (let i:int = 0; i < 3; i = i + 1) { print(sum(i * 10)); } }");
+  let jit = assert_same_result(&module, 2);
+  assert!(jit.is_compiled(function_index(&module, "sum")));
+  assert!(jit.is_compiled(function_index(&module, "square")));
+}
+
+#[test]
+fn jit_does_not_compile_function_without_reachable_return() {
+  let module = compile_source("fn spin() { for (;;) { } }\nfn main() { spin(); }");
+  let return_kinds: Vec<_> = iter::repeat(None).take(module.functions.len()).collect();
+  match analyze(&module, function_index(&module, "spin"), &[], return_kinds.as_slice()) {
+    Err(Failure::Unsupported(reason)) => assert_eq!("no return is reachable", reason.as_slice()),
+    _ => assert!(false),
+  }
+}
+
+#[test]
+fn jit_runs_deep_tail_recursion_in_compiled_code() {
+  let module = compile_source("fn count(n:int, total:int) : int { if (n == 0) { return total; } return count(n - 1, total + n); }\n\
+    fn main() { for (let i:int = 0; i < 3; i = i + 1) { print(count(i, 0)); }\n print(count(100000, 0)); }");
+  let jit = assert_same_result(&module, 2);
+  assert!(jit.is_compiled(function_index(&module, "count")));
+  assert_eq!(Ok("0\n1\n3\n705082704\n".to_string()), run_module(&module));
+}
@@ -570,6 +570,28 @@ fn keywords_are_tokenized_correctly() {
   }
 }
 
+#[test]
+fn const_keyword_is_tokenized_correctly() {
+  match tokenize("const") {
+    Ok(mut tokens) => {
+      assert_eq!(1, tokens.token_count());
+      assert!(generic_helper(&mut tokens, TokenType::Const));
+    },
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn as_keyword_is_tokenized_correctly() {
+  match tokenize("as") {
+    Ok(mut tokens) => {
+      assert_eq!(1, tokens.token_count());
+      assert!(generic_helper(&mut tokens, TokenType::As));
+    },
+    Err(..) => assert!(false),
+  }
+}
+
 #[test]
 fn comments_are_ignored_correctly() {
   let string="ident_1// This is comment\nident2";
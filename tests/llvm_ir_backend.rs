@@ -0,0 +1,80 @@
+extern crate compiler;
+
+use std::io::File;
+use std::os;
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::backend::llvm::generate;
+
+fn generate_source(source: &str) -> String {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  generate(&program)
+}
+
+// compares the IR generated for tests/golden/llvm_ir/<name>.src with <name>.ll.
+// With UPDATE_GOLDEN set in the environment the golden file is rewritten instead
+fn assert_golden(name: &str) {
+  let directory = Path::new("tests/golden/llvm_ir");
+  let source = File::open(&directory.join(format!("{}.src", name))).read_to_string().unwrap();
+  let generated = generate_source(source.as_slice());
+  let golden_path = directory.join(format!("{}.ll", name));
+
+  if os::getenv("UPDATE_GOLDEN").is_some() {
+    File::create(&golden_path).write_str(generated.as_slice()).unwrap();
+    return;
+  }
+
+  let expected = File::open(&golden_path).read_to_string().unwrap();
+  for (index, (expected_line, line)) in expected.lines().zip(generated.lines()).enumerate() {
+    if expected_line != line {
+      panic!("{}.ll differs at line {}:\nexpected: {}\n   found: {}", name, index + 1, expected_line, line);
+    }
+  }
+  assert_eq!(expected.lines().count(), generated.lines().count());
+}
+
+#[test]
+fn llvm_ir_backend_matches_golden_arithmetic() {
+  assert_golden("arithmetic");
+}
+
+#[test]
+fn llvm_ir_backend_matches_golden_control_flow() {
+  assert_golden("control_flow");
+}
+
+#[test]
+fn llvm_ir_backend_matches_golden_globals_and_strings() {
+  assert_golden("globals_and_strings");
+}
+
+#[test]
+fn llvm_ir_backend_places_allocas_in_entry_block() {
+  let ir = generate_source("fn f(a:bool) { for (let i:int = 0; i < 2; i = i + 1) { let d:double = 1.0; } }");
+  assert!(ir.contains("define internal void @fn_f(i1 %p_a) {\nentry:\n  %v_a_0 = alloca i1\n  %v_i_1 = alloca i32\n  %v_d_2 = alloca double\n  store i1 %p_a, ptr %v_a_0\n"));
+  assert!(ir.contains("for.body.0:\n  store double 0x3FF0000000000000, ptr %v_d_2\n"));
+}
+
+#[test]
+fn llvm_ir_backend_maps_operators_to_typed_instructions() {
+  let ir = generate_source("fn f(a:int, b:float, c:double) {\n let x:bool = a < 1; x = b > 1.0f; x = c <= 1.0; x = a == a; x = b >= b;\n a = a - a / a; b = b * b; c = c + c; }");
+  assert!(ir.contains("icmp slt i32 %t0, 1\n"));
+  assert!(ir.contains("fcmp ogt float %t2, 0x3FF0000000000000\n"));
+  assert!(ir.contains("fcmp ole double %t4, 0x3FF0000000000000\n"));
+  assert!(ir.contains("icmp eq i32 %t6, %t7\n"));
+  assert!(ir.contains("fcmp oge float %t9, %t10\n"));
+  assert!(ir.contains("call i32 @rt_idiv(i32 %t13, i32 %t14, i32 3, i32 12)\n  %t16 = sub i32 %t12, %t15\n"));
+  assert!(ir.contains("fmul float %t17, %t18\n"));
+  assert!(ir.contains("fadd double %t20, %t21\n"));
+}
+
+#[test]
+fn llvm_ir_backend_starts_new_block_after_return() {
+  let ir = generate_source("fn f() : int { return 1; print(2); }");
+  assert!(ir.contains("  ret i32 1\ndead.0:\n  call void @rt_print_int(i32 2)\n  unreachable\n}\n"));
+}
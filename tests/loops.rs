@@ -0,0 +1,376 @@
+extern crate compiler;
+
+use std::io::MemWriter;
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::ir::*;
+use compiler::ir::lowering::lower;
+use compiler::ir::printer::print_function;
+use compiler::ir::verifier::verify;
+use compiler::ir::verifier::Form;
+use compiler::ir::loops::Loop;
+use compiler::ir::loops::find_loops;
+use compiler::ir::loops::preheader;
+use compiler::ir::loops::insert_preheader;
+use compiler::ir::optimizer::*;
+use compiler::ir::optimizer::loop_invariants::LoopInvariantCodeMotion;
+use compiler::ir::optimizer::strength_reduction::StrengthReduction;
+use compiler::ir::optimizer::unrolling::LoopUnrolling;
+use compiler::ir::ssa::to_ssa;
+
+fn lower_source(source: &str) -> Module {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  lower(&program)
+}
+
+fn parse_ir(text: &str) -> Module {
+  match compiler::ir::parser::parse(text) {
+    Ok(module) => module,
+    Err(errors) => panic!("Parsing failed: {:?}", errors),
+  }
+}
+
+// runs a single pass over a module in SSA form and returns its first function
+fn run_pass(pass: Box<Pass>, text: &str) -> String {
+  let mut module = parse_ir(text);
+  assert_eq!(Ok(()), verify(&module, Form::Ssa));
+  let mut manager = PassManager::new();
+  manager.add(pass);
+  assert!(manager.run(&mut module));
+  print_function(&module, &module.functions[0])
+}
+
+fn run_module(module: &Module) -> Result<String, String> {
+  let mut output = MemWriter::new();
+  try!(compiler::ir::interpreter::run(module, &mut output));
+  Ok(String::from_utf8(output.get_ref().to_vec()).unwrap())
+}
+
+// the output of the program, which must not change with the optimization level
+fn run_optimized(source: &str) -> Result<String, String> {
+  let unoptimized = run_module(&lower_source(source));
+  for level in vec![Level::O1, Level::O2].into_iter() {
+    let mut module = lower_source(source);
+    optimize(&mut module, level);
+    assert_eq!(Ok(()), verify(&module, Form::Normal));
+    assert_eq!(unoptimized, run_module(&module));
+  }
+  unoptimized
+}
+
+#[test]
+fn natural_loops_of_nested_for_loops() {
+  let mut module = lower_source("fn main() { for (let i:int = 0; i < 3; i = i + 1) { \
+    for (let j:int = 0; j < i; j = j + 1) { if (j == 1) { return; } print(j); } } }");
+  to_ssa(&mut module);
+  let loops = find_loops(&module.functions[0]);
+  assert_eq!(vec![
+    Loop { header: 3, blocks: vec![3, 4, 6, 7], latches: vec![7], exits: vec![5, 8], parent: Some(1) },
+    Loop { header: 1, blocks: vec![1, 2, 3, 4, 6, 7, 8], latches: vec![8], exits: vec![5, 9], parent: None },
+  ], loops);
+  assert_eq!(Some(2), preheader(&module.functions[0], &loops[0]));
+  assert_eq!(Some(0), preheader(&module.functions[0], &loops[1]));
+}
+
+#[test]
+fn preheaders_merge_the_values_entering_the_loop() {
+  let mut module = parse_ir("fn @f(%0: bool) : int {
+bb0:
+  br %0, bb1, bb2
+bb1:
+  jmp bb3
+bb2:
+  jmp bb3
+bb3:
+  %1: int = phi [bb1: 1], [bb2: 2], [bb4: %3]
+  %2: int = phi [bb1: 0], [bb2: 0], [bb4: %4]
+  %5: bool = lt %1, 10
+  br %5, bb4, bb5
+bb4:
+  %3: int = add %1, 1
+  %4: int = add %2, %1
+  jmp bb3
+bb5:
+  ret %2
+}
+");
+  let natural_loop = find_loops(&module.functions[0]).remove(0);
+  assert_eq!(None, preheader(&module.functions[0], &natural_loop));
+  assert_eq!(Some(6), insert_preheader(&mut module.functions[0], &natural_loop));
+  assert_eq!(Ok(()), verify(&module, Form::Ssa));
+  let natural_loop = find_loops(&module.functions[0]).remove(0);
+  assert_eq!(Some(6), preheader(&module.functions[0], &natural_loop));
+  let expected = "fn @f(%0: bool) : int {
+bb0:
+  br %0, bb1, bb2
+bb1:
+  jmp bb6
+bb2:
+  jmp bb6
+bb3:
+  %1: int = phi [bb4: %3], [bb6: %6]
+  %2: int = phi [bb4: %4], [bb6: 0]
+  %5: bool = lt %1, 10
+  br %5, bb4, bb5
+bb4:
+  %3: int = add %1, 1
+  %4: int = add %2, %1
+  jmp bb3
+bb5:
+  ret %2
+bb6:
+  %6: int = phi [bb1: 1], [bb2: 2]
+  jmp bb3
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+}
+
+#[test]
+fn loop_invariant_code_motion_hoists_into_the_preheader() {
+  // the division by %1 stays, it may fail when the loop does not run
+  let expected = "fn @f(%0: int, %1: int) : int {
+bb0:
+  %5: int = mul %1, 3
+  %6: int = add %5, 1
+  %10: int = div %0, 2 at 1:1
+  %11: int = add %6, %10
+  jmp bb1
+bb1:
+  %2: int = phi [bb0: 0], [bb2: %8]
+  %3: int = phi [bb0: 0], [bb2: %9]
+  %4: bool = lt %3, %0
+  br %4, bb2, bb3
+bb2:
+  %7: int = div %0, %1 at 1:1
+  %12: int = add %11, %7
+  %8: int = add %2, %12
+  %9: int = add %3, 1
+  jmp bb1
+bb3:
+  ret %2
+}
+";
+  assert_eq!(expected, run_pass(Box::new(LoopInvariantCodeMotion), "fn @f(%0: int, %1: int) : int {
+bb0:
+  jmp bb1
+bb1:
+  %2: int = phi [bb0: 0], [bb2: %8]
+  %3: int = phi [bb0: 0], [bb2: %9]
+  %4: bool = lt %3, %0
+  br %4, bb2, bb3
+bb2:
+  %5: int = mul %1, 3
+  %6: int = add %5, 1
+  %7: int = div %0, %1 at 1:1
+  %10: int = div %0, 2 at 1:1
+  %11: int = add %6, %10
+  %12: int = add %11, %7
+  %8: int = add %2, %12
+  %9: int = add %3, 1
+  jmp bb1
+bb3:
+  ret %2
+}
+").as_slice());
+}
+
+#[test]
+fn loop_invariant_code_motion_keeps_loads_of_stored_globals() {
+  let expected = "fn @f(%0: int) : int {
+bb0:
+  %3: int = load @a
+  jmp bb1
+bb1:
+  %1: int = phi [bb0: 0], [bb2: %5]
+  %2: bool = lt %1, %0
+  br %2, bb2, bb3
+bb2:
+  %4: int = load @b
+  store @b, %3
+  %5: int = add %1, %4
+  jmp bb1
+bb3:
+  ret %1
+}
+";
+  assert_eq!(expected, run_pass(Box::new(LoopInvariantCodeMotion), "global @a: int
+global @b: int
+
+fn @f(%0: int) : int {
+bb0:
+  jmp bb1
+bb1:
+  %1: int = phi [bb0: 0], [bb2: %5]
+  %2: bool = lt %1, %0
+  br %2, bb2, bb3
+bb2:
+  %3: int = load @a
+  %4: int = load @b
+  store @b, %3
+  %5: int = add %1, %4
+  jmp bb1
+bb3:
+  ret %1
+}
+").as_slice());
+}
+
+#[test]
+fn strength_reduction_replaces_multiplications_of_induction_variables() {
+  let expected = "fn @f(%0: int, %1: int) : int {
+bb0:
+  %10: int = mul %1, 8
+  %13: int = mul %1, 3
+  jmp bb1
+bb1:
+  %11: int = phi [bb0: %10], [bb2: %12]
+  %14: int = phi [bb0: %13], [bb2: %15]
+  %2: int = phi [bb0: 0], [bb2: %7]
+  %3: int = phi [bb0: %1], [bb2: %8]
+  %4: bool = lt %3, %0
+  br %4, bb2, bb3
+bb2:
+  %5: int = copy %11
+  %6: int = copy %14
+  %9: int = add %5, %6
+  %7: int = add %2, %9
+  %8: int = sub %3, 2
+  %12: int = add %11, -16
+  %15: int = add %14, -6
+  jmp bb1
+bb3:
+  ret %2
+}
+";
+  assert_eq!(expected, run_pass(Box::new(StrengthReduction), "fn @f(%0: int, %1: int) : int {
+bb0:
+  jmp bb1
+bb1:
+  %2: int = phi [bb0: 0], [bb2: %7]
+  %3: int = phi [bb0: %1], [bb2: %8]
+  %4: bool = lt %3, %0
+  br %4, bb2, bb3
+bb2:
+  %5: int = mul %3, 8
+  %6: int = mul 3, %3
+  %9: int = add %5, %6
+  %7: int = add %2, %9
+  %8: int = sub %3, 2
+  jmp bb1
+bb3:
+  ret %2
+}
+").as_slice());
+}
+
+#[test]
+fn unrolling_copies_loops_with_constant_trip_counts() {
+  // three iterations, the header is left at the end
+  let expected = "fn @f(%0: int) : int {
+bb0:
+  jmp bb3
+bb1:
+  %1: int = phi [bb8: %19]
+  %2: int = phi [bb8: %20]
+  %3: bool = le %2, 3
+  jmp bb2
+bb2:
+  ret %1
+bb3:
+  %6: int = copy %0
+  %7: int = copy 1
+  %8: bool = le %7, 3
+  jmp bb4
+bb4:
+  %9: int = mul %6, %7
+  %10: int = add %7, 1
+  jmp bb5
+bb5:
+  %11: int = copy %9
+  %12: int = copy %10
+  %13: bool = le %12, 3
+  jmp bb6
+bb6:
+  %14: int = mul %11, %12
+  %15: int = add %12, 1
+  jmp bb7
+bb7:
+  %16: int = copy %14
+  %17: int = copy %15
+  %18: bool = le %17, 3
+  jmp bb8
+bb8:
+  %19: int = mul %16, %17
+  %20: int = add %17, 1
+  jmp bb1
+}
+";
+  assert_eq!(expected, run_pass(Box::new(LoopUnrolling), "fn @f(%0: int) : int {
+bb0:
+  jmp bb1
+bb1:
+  %1: int = phi [bb0: %0], [bb2: %4]
+  %2: int = phi [bb0: 1], [bb2: %5]
+  %3: bool = le %2, 3
+  br %3, bb2, bb3
+bb2:
+  %4: int = mul %1, %2
+  %5: int = add %2, 1
+  jmp bb1
+bb3:
+  ret %1
+}
+").as_slice());
+}
+
+#[test]
+fn unrolling_keeps_long_loops() {
+  let mut module = parse_ir("fn @f(%0: int) : int {
+bb0:
+  jmp bb1
+bb1:
+  %1: int = phi [bb0: %0], [bb2: %4]
+  %2: int = phi [bb0: 0], [bb2: %5]
+  %3: bool = lt %2, 9
+  br %3, bb2, bb3
+bb2:
+  %4: int = mul %1, %2
+  %5: int = add %2, 1
+  jmp bb1
+bb3:
+  ret %1
+}
+");
+  let mut manager = PassManager::new();
+  manager.add(Box::new(LoopUnrolling));
+  assert!(!manager.run(&mut module));
+}
+
+#[test]
+fn loop_optimizations_keep_the_output_of_programs() {
+  assert_eq!(Ok("500\n14\n5\n4\n3\n15\n14\n13\n25\n24\n23\n".to_string()), run_optimized("fn main() { let n:int = 10; let s:int = 0; \
+    for (let i:int = 0; i < n; i = i + 1) { let k:int = n * 3 + 2; s = s + i * 4 + k; } print(s); \
+    let t:int = 0; for (let i:int = 0; i < 4; i = i + 1) { t = t + i * i; } print(t); \
+    for (let i:int = 0; i < 3; i = i + 1) { for (let j:int = 5; j > 2; j = j - 1) { print(i * 10 + j); } } }"));
+  assert_eq!(Ok("3\n-1\n276\n45\n".to_string()), run_optimized("let g:int = 7;\n\
+    fn find(n:int) : int { for (let i:int = 0; i < n; i = i + 1) { if (i * 3 > g) { return i; } } return -1; }\n\
+    fn sum(n:int, m:int) : int { let s:int = 0; for (let i:int = 2; i < n; i = i + 2) { \
+    for (let j:int = 0; j < 3; j = j + 1) { s = s + i * m + j * 5 + g / 2; } } return s; }\n\
+    fn bump() { g = g + 1; }\n\
+    fn calls() : int { let s:int = 0; for (let i:int = 0; i < 5; i = i + 1) { s = s + g; bump(); } return s; }\n\
+    fn main() { print(find(10)); print(find(2)); print(sum(9, 3)); print(calls()); }"));
+  assert_eq!(Ok("2.5\n2.5\n2.5\n15.625\n".to_string()), run_optimized("fn main() { let d:double = 1.0; \
+    for (let i:int = 0; i < 3; i = i + 1) { d = d * 2.5; let f:float = 0.5f * 5.0f; print(f); } print(d); }"));
+}
+
+#[test]
+fn loop_optimizations_keep_run_time_errors() {
+  assert_eq!(Err("Runtime error at 1:87: Division by zero".to_string()), run_optimized("fn main() { let z:int = 0; \
+    for (let i:int = 0; i < 3; i = i + 1) { print(i); print(10 / z); } }"));
+}
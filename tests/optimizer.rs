@@ -0,0 +1,539 @@
+extern crate compiler;
+
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::ir::*;
+use compiler::ir::lowering::lower;
+use compiler::ir::printer::print;
+use compiler::ir::printer::print_function;
+use compiler::ir::verifier::verify;
+use compiler::ir::verifier::Form;
+use compiler::ir::optimizer::*;
+use compiler::ir::optimizer::constant_propagation::ConstantPropagation;
+use compiler::ir::optimizer::copy_propagation::CopyPropagation;
+use compiler::ir::optimizer::dead_code::DeadCodeElimination;
+use compiler::ir::optimizer::unreachable_blocks::UnreachableBlockElimination;
+use compiler::ir::optimizer::common_subexpressions::CommonSubexpressionElimination;
+use compiler::ir::optimizer::inlining::Inlining;
+use compiler::ir::optimizer::tail_calls::TailCallElimination;
+use compiler::ir::call_graph::CallGraph;
+use compiler::ir::ssa::to_ssa;
+
+fn lower_source(source: &str) -> Module {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  lower(&program)
+}
+
+fn parse_ir(text: &str) -> Module {
+  match compiler::ir::parser::parse(text) {
+    Ok(module) => module,
+    Err(errors) => panic!("Parsing failed: {:?}", errors),
+  }
+}
+
+// runs a single pass over a module in SSA form and returns its first function
+fn run_pass(pass: Box<Pass>, text: &str) -> String {
+  let mut module = parse_ir(text);
+  assert_eq!(Ok(()), verify(&module, Form::Ssa));
+  let mut manager = PassManager::new();
+  manager.add(pass);
+  manager.run(&mut module);
+  print_function(&module, &module.functions[0])
+}
+
+// runs a single module pass over a module in SSA form and returns its first function
+fn run_module_pass(pass: Box<ModulePass>, text: &str) -> String {
+  let mut module = parse_ir(text);
+  assert_eq!(Ok(()), verify(&module, Form::Ssa));
+  let mut manager = PassManager::new();
+  manager.add_module_pass(pass);
+  manager.run(&mut module);
+  print_function(&module, &module.functions[0])
+}
+
+#[test]
+fn constant_propagation_folds_operations_and_branches() {
+  let text = "fn @f() : int {
+bb0:
+  %0: int = add 2, 3
+  %1: int = mul %0, %0
+  %2: bool = lt %1, 20
+  br %2, bb1, bb2
+bb1:
+  print %1
+  jmp bb3
+bb2:
+  %3: int = sub %1, 1
+  jmp bb3
+bb3:
+  %4: int = phi [bb1: %1], [bb2: %3]
+  ret %4
+}
+";
+  let expected = "fn @f() : int {
+bb0:
+  jmp bb1
+bb1:
+  jmp bb2
+bb2:
+  ret 24
+}
+";
+  assert_eq!(expected, run_pass(Box::new(ConstantPropagation), text).as_slice());
+}
+
+#[test]
+fn constant_propagation_ignores_values_from_branches_never_taken() {
+  // %1 stays 1 around the loop, as the branch assigning 2 never runs
+  let text = "fn @f(%0: int) : void {
+bb0:
+  jmp bb1
+bb1:
+  %1: int = phi [bb0: 1], [bb4: %4]
+  %2: int = phi [bb0: 0], [bb4: %5]
+  %3: bool = eq %1, 1
+  br %3, bb2, bb3
+bb2:
+  jmp bb4
+bb3:
+  jmp bb4
+bb4:
+  %4: int = phi [bb2: 1], [bb3: 2]
+  %5: int = add %2, %4
+  print %5
+  %6: bool = lt %5, %0
+  br %6, bb1, bb5
+bb5:
+  print %1
+  ret
+}
+";
+  let expected = "fn @f(%0: int) : void {
+bb0:
+  jmp bb1
+bb1:
+  %2: int = phi [bb0: 0], [bb3: %5]
+  jmp bb2
+bb2:
+  jmp bb3
+bb3:
+  %5: int = add %2, 1
+  print %5
+  %6: bool = lt %5, %0
+  br %6, bb1, bb4
+bb4:
+  print 1
+  ret
+}
+";
+  assert_eq!(expected, run_pass(Box::new(ConstantPropagation), text).as_slice());
+}
+
+#[test]
+fn constant_propagation_keeps_division_by_zero_for_run_time() {
+  let text = "fn @f(%0: float) : void {
+bb0:
+  %1: int = div -2147483648, -1 at 1:10
+  print %1
+  %2: int = div 7, 0 at 2:10
+  print %2
+  %3: float = mul 0.0f, -1.0f
+  %4: bool = eq %3, 0.0f
+  print %4
+  %5: int = convert 3000000000.0
+  print %5
+  ret
+}
+";
+  let expected = "fn @f(%0: float) : void {
+bb0:
+  print -2147483648
+  %2: int = div 7, 0 at 2:10
+  print %2
+  print true
+  %5: int = convert 3000000000.0
+  print %5
+  ret
+}
+";
+  assert_eq!(expected, run_pass(Box::new(ConstantPropagation), text).as_slice());
+}
+
+#[test]
+fn copy_propagation_removes_copies_and_trivial_phis() {
+  let text = "fn @f(%0: int, %1: bool) : int {
+bb0:
+  %2: int = copy %0
+  %3: int = copy %2
+  jmp bb1
+bb1:
+  %4: int = phi [bb0: %3], [bb2: %5]
+  br %1, bb2, bb3
+bb2:
+  %5: int = phi [bb1: %4]
+  jmp bb1
+bb3:
+  %6: int = add %4, %3
+  ret %6
+}
+";
+  let expected = "fn @f(%0: int, %1: bool) : int {
+bb0:
+  jmp bb1
+bb1:
+  br %1, bb2, bb3
+bb2:
+  jmp bb1
+bb3:
+  %6: int = add %0, %0
+  ret %6
+}
+";
+  assert_eq!(expected, run_pass(Box::new(CopyPropagation), text).as_slice());
+}
+
+#[test]
+fn dead_code_elimination_keeps_effects() {
+  // %5 and %6 only feed each other around the loop
+  let text = "global @g: int
+
+fn @f(%0: int, %1: bool) : void {
+bb0:
+  %2: int = add %0, 1
+  %3: int = div %0, %0 at 3:5
+  %4: int = div %0, 2 at 4:5
+  %7: int = call @h()
+  %8: int = load @g
+  %9: int = mul %2, 2
+  store @g, %9
+  jmp bb1
+bb1:
+  %5: int = phi [bb0: 0], [bb1: %6]
+  %6: int = add %5, 1
+  br %1, bb1, bb2
+bb2:
+  ret
+}
+
+fn @h() : int {
+bb0:
+  ret 1
+}
+";
+  let expected = "fn @f(%0: int, %1: bool) : void {
+bb0:
+  %2: int = add %0, 1
+  %3: int = div %0, %0 at 3:5
+  %7: int = call @h()
+  %9: int = mul %2, 2
+  store @g, %9
+  jmp bb1
+bb1:
+  br %1, bb1, bb2
+bb2:
+  ret
+}
+";
+  assert_eq!(expected, run_pass(Box::new(DeadCodeElimination), text).as_slice());
+}
+
+#[test]
+fn unreachable_block_elimination_follows_constant_branches() {
+  let text = "fn @f(%0: bool) : int {
+bb0:
+  br false, bb1, bb2
+bb1:
+  jmp bb3
+bb2:
+  br %0, bb3, bb3
+bb3:
+  %1: int = phi [bb1: 1], [bb2: 2]
+  ret %1
+}
+";
+  let expected = "fn @f(%0: bool) : int {
+bb0:
+  jmp bb1
+bb1:
+  jmp bb2
+bb2:
+  %1: int = phi [bb1: 2]
+  ret %1
+}
+";
+  assert_eq!(expected, run_pass(Box::new(UnreachableBlockElimination), text).as_slice());
+}
+
+#[test]
+fn common_subexpression_elimination_reuses_dominating_results() {
+  let text = "fn @f(%0: int, %1: int, %2: bool, %3: string) : void {
+bb0:
+  %4: int = add %0, %1
+  %5: int = add %1, %0
+  %6: int = sub %1, %0
+  %7: string = add %3, \"a\"
+  %8: string = add \"a\", %3
+  %9: double = convert %0
+  %10: float = convert %0
+  %11: double = convert %0
+  %12: int = div %0, %1 at 1:1
+  br %2, bb1, bb2
+bb1:
+  %13: int = div %0, %1 at 2:1
+  %14: int = sub %0, %1
+  print %14
+  jmp bb3
+bb2:
+  %15: int = sub %0, %1
+  print %15
+  jmp bb3
+bb3:
+  %16: float = mul %10, 0.0f
+  %17: float = mul %10, -0.0f
+  %18: float = mul 0.0f, %10
+  ret
+}
+";
+  let expected = "fn @f(%0: int, %1: int, %2: bool, %3: string) : void {
+bb0:
+  %4: int = add %0, %1
+  %5: int = copy %4
+  %6: int = sub %1, %0
+  %7: string = add %3, \"a\"
+  %8: string = add \"a\", %3
+  %9: double = convert %0
+  %10: float = convert %0
+  %11: double = copy %9
+  %12: int = div %0, %1 at 1:1
+  br %2, bb1, bb2
+bb1:
+  %13: int = copy %12
+  %14: int = sub %0, %1
+  print %14
+  jmp bb3
+bb2:
+  %15: int = sub %0, %1
+  print %15
+  jmp bb3
+bb3:
+  %16: float = mul %10, 0.0f
+  %17: float = mul %10, -0.0f
+  %18: float = copy %16
+  ret
+}
+";
+  assert_eq!(expected, run_pass(Box::new(CommonSubexpressionElimination), text).as_slice());
+}
+
+#[test]
+fn call_graph_orders_callees_first_and_finds_recursion() {
+  let module = lower_source("fn leaf() : int { return 1; }\n\
+    fn even(n:int) : bool { if (n == 0) { return true; } return odd(n - 1); }\n\
+    fn odd(n:int) : bool { if (n == 0) { return false; } return even(n - 1); }\n\
+    fn down(n:int) { if (n > 0) { down(n - leaf()); } }\n\
+    fn main() { print(even(leaf() + leaf())); down(3); }");
+  let graph = CallGraph::new(&module);
+  let leaf = module.function_index("leaf").unwrap();
+  let even = module.function_index("even").unwrap();
+  let odd = module.function_index("odd").unwrap();
+  let down = module.function_index("down").unwrap();
+  let main = module.function_index("main").unwrap();
+  assert_eq!([leaf, even, down].as_slice(), graph.callees(main));
+  assert_eq!(3, graph.call_sites(leaf));
+  assert_eq!(0, graph.call_sites(main));
+  assert!(!graph.is_recursive(leaf));
+  assert!(graph.is_recursive(even));
+  assert!(graph.is_recursive(down));
+  assert!(graph.in_same_cycle(even, odd));
+  assert!(!graph.in_same_cycle(down, leaf));
+  assert!(!graph.in_same_cycle(main, main));
+
+  let order = graph.bottom_up_order();
+  let position = |function: usize| order.iter().position(|other| *other == function).unwrap();
+  assert_eq!(module.functions.len(), order.len());
+  assert!(position(leaf) < position(down));
+  assert!(position(even) < position(main));
+  assert!(position(odd) < position(main));
+}
+
+#[test]
+fn inlining_replaces_calls_of_cheap_functions() {
+  let text = "fn @main(%0: int) : void {
+bb0:
+  %1: int = call @twice(%0)
+  %2: int = call @recursive(%1)
+  call @show(%2)
+  print %1
+  ret
+}
+
+fn @twice(%0: int) : int {
+bb0:
+  %1: bool = lt %0, 0
+  br %1, bb1, bb2
+bb1:
+  %2: int = div %0, 0 at 7:12
+  ret %2
+bb2:
+  %3: int = add %0, %0
+  ret %3
+}
+
+fn @recursive(%0: int) : int {
+bb0:
+  %1: int = call @recursive(%0)
+  ret %1
+}
+
+fn @show(%0: int) : void {
+bb0:
+  print %0
+  ret
+}
+";
+  // the division keeps the position it has in @twice
+  let expected = "fn @main(%0: int) : void {
+bb0:
+  jmp bb2
+bb1:
+  %1: int = phi [bb3: %4], [bb4: %5]
+  %2: int = call @recursive(%1)
+  jmp bb6
+bb2:
+  %3: bool = lt %0, 0
+  br %3, bb3, bb4
+bb3:
+  %4: int = div %0, 0 at 7:12
+  jmp bb1
+bb4:
+  %5: int = add %0, %0
+  jmp bb1
+bb5:
+  print %1
+  ret
+bb6:
+  print %2
+  jmp bb5
+}
+";
+  assert_eq!(expected, run_module_pass(Box::new(Inlining), text).as_slice());
+}
+
+#[test]
+fn tail_call_elimination_turns_self_calls_into_loops() {
+  let mut module = lower_source("fn fact(n:int, acc:int) : int { if (n <= 1) { return acc; } \
+    return fact(n - 1, acc * n); }\nfn main() { print(fact(5, 1)); }");
+  to_ssa(&mut module);
+  let mut manager = PassManager::new();
+  manager.add_module_pass(Box::new(TailCallElimination));
+  assert!(manager.run(&mut module));
+  let expected = "fn @fact(%0: int, %1: int) : int {
+bb0:
+  jmp bb4
+bb1:
+  ret %7
+bb2:
+  jmp bb3
+bb3:
+  %3: int = sub %6, 1
+  %4: int = mul %7, %6
+  jmp bb4
+bb4:
+  %6: int = phi [bb0: %0], [bb3: %3]
+  %7: int = phi [bb0: %1], [bb3: %4]
+  %2: bool = le %6, 1
+  br %2, bb1, bb2
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+}
+
+#[test]
+fn pass_managers_for_levels() {
+  let empty: Vec<&'static str> = vec![];
+  assert_eq!(empty, PassManager::for_level(Level::O0).pass_names());
+  assert_eq!(vec!["tail-calls", "constant-propagation", "copy-propagation", "dead-code",
+    "unreachable-blocks"], PassManager::for_level(Level::O1).pass_names());
+  assert_eq!(vec!["inlining", "tail-calls", "constant-propagation", "cse", "copy-propagation",
+    "unrolling", "licm", "strength-reduction", "dead-code", "unreachable-blocks"],
+    PassManager::for_level(Level::O2).pass_names());
+}
+
+#[test]
+fn optimization_levels_of_program() {
+  let source = "fn main() { let n:int = 4; let s:int = 0; \
+    for (let i:int = 0; i < n * 10; i = i + 1) { s = s + i * n + i * 4; if (n > 5) { print(s); } } print(s); }";
+  let mut module = lower_source(source);
+  let unoptimized = print(&module);
+  optimize(&mut module, Level::O0);
+  assert_eq!(unoptimized, print(&module));
+
+  let mut module = lower_source(source);
+  optimize(&mut module, Level::O1);
+  assert_eq!(Ok(()), verify(&module, Form::Normal));
+  let expected = "fn @main() : void {
+bb0:
+  %11: int = copy 0
+  %12: int = copy 0
+  jmp bb1
+bb1:
+  %4: bool = lt %12, 40
+  br %4, bb2, bb5
+bb2:
+  %5: int = mul %12, 4
+  %6: int = add %11, %5
+  %7: int = mul %12, 4
+  %8: int = add %6, %7
+  jmp bb3
+bb3:
+  jmp bb4
+bb4:
+  %10: int = add %12, 1
+  %11: int = copy %8
+  %12: int = copy %10
+  jmp bb1
+bb5:
+  print %11
+  ret
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+
+  let mut module = lower_source(source);
+  optimize(&mut module, Level::O2);
+  assert_eq!(Ok(()), verify(&module, Form::Normal));
+  // i * n became i * 4, is computed once and strength reduced to an addition
+  let expected = "fn @main() : void {
+bb0:
+  %15: int = copy 0
+  %11: int = copy 0
+  %12: int = copy 0
+  jmp bb1
+bb1:
+  %4: bool = lt %12, 40
+  br %4, bb2, bb5
+bb2:
+  %6: int = add %11, %15
+  %8: int = add %6, %15
+  jmp bb3
+bb3:
+  jmp bb4
+bb4:
+  %10: int = add %12, 1
+  %16: int = add %15, 4
+  %15: int = copy %16
+  %11: int = copy %8
+  %12: int = copy %10
+  jmp bb1
+bb5:
+  print %11
+  ret
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+}
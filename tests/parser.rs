@@ -856,3 +856,120 @@ fn parser_detects_multiple_errors_correctly_on_if_elif_else_statement() {
   }
 
 }
+
+#[test]
+fn parser_accepts_global_variable_and_constant_declarations() {
+  let tokens = tokenize("let a:int = 5;\nconst B:double = 2.5*2;\nfn foo() { a = 4; }").unwrap();
+  match parse(tokens) {
+    Ok(program) => {
+      assert_eq!(2, program.globals.len());
+      assert!(!program.globals[0].is_constant);
+      assert!(program.globals[1].is_constant);
+      assert_eq!(1, program.functions.len());
+    },
+    Err(..) => assert!(false)
+  }
+}
+
+#[test]
+fn parser_errors_on_global_declaration_with_missing_semicolon() {
+  let tokens = tokenize("const a:int = 5\nfn foo() { }").unwrap();
+  match parse(tokens) {
+    Ok(..) => assert!(false),
+    Err(err) => {
+      assert_eq!(1, err.len());
+      assert!(err[0].contains("2:1"));
+    }
+  }
+}
+
+#[test]
+fn parser_continues_after_invalid_global_declaration() {
+  let tokens = tokenize("const a = 5;\nlet b:int = 4;\nfn foo() {\n let c; }").unwrap();
+  match parse(tokens) {
+    Ok(..) => assert!(false),
+    Err(err) => {
+      assert_eq!(2, err.len());
+      assert!(err[0].contains("1:9"));
+      assert!(err[1].contains("4:7"));
+    }
+  }
+}
+
+#[test]
+fn parser_builds_syntax_tree_for_function() {
+  let tokens = tokenize("fn foo(a:int, b:double) : bool { let c:int = a + 2*3; bar(c); }").unwrap();
+  match parse(tokens) {
+    Ok(program) => {
+      assert_eq!(1, program.functions.len());
+      let function = &program.functions[0];
+      assert_eq!("foo", program.get_text(function.name));
+      assert_eq!(2, function.parameters.len());
+      assert_eq!(2, function.body.statements.len());
+    },
+    Err(..) => assert!(false)
+  }
+}
+
+#[test]
+fn parser_accepts_return_statements() {
+  let tokens = tokenize("fn foo() : int { return 4 + 5; }\nfn bar() { return; }").unwrap();
+  match parse(tokens) {
+    Ok(..) => assert!(true),
+    Err(..) => assert!(false)
+  }
+}
+
+#[test]
+fn parser_errors_on_return_statement_without_semicolon() {
+  let tokens = tokenize("fn foo() : int {\n return 4 }").unwrap();
+  match parse(tokens) {
+    Ok(..) => assert!(false),
+    Err(err) => {
+      assert_eq!(1, err.len());
+      assert!(err[0].contains("2:11"));
+    }
+  }
+}
+
+#[test]
+fn parser_accepts_function_calls_in_expressions() {
+  let tokens = tokenize("fn foo() { let a:int = bar(1, baz()) * 2 + bar(3, 4); }").unwrap();
+  match parse(tokens) {
+    Ok(..) => assert!(true),
+    Err(..) => assert!(false)
+  }
+}
+
+#[test]
+fn parser_errors_on_invalid_function_call_in_expression() {
+  let tokens = tokenize("fn foo() {\n let a:int = bar(1,) * 2; }").unwrap();
+  match parse(tokens) {
+    Ok(..) => assert!(false),
+    Err(err) => {
+      assert_eq!(1, err.len());
+      assert!(err[0].contains("2:20"));
+    }
+  }
+}
+
+#[test]
+fn parser_accepts_cast_expressions() {
+  let tokens = tokenize("fn foo() { let a:double = 1 as double * 2.0 + (3 + 4) as double as double; }").unwrap();
+  match parse(tokens) {
+    Ok(..) => assert!(true),
+    Err(..) => assert!(false)
+  }
+}
+
+#[test]
+fn parser_errors_on_cast_without_type() {
+  let tokens = tokenize("fn foo() {\n let a:int = 1 as 2; }").unwrap();
+  match parse(tokens) {
+    Ok(..) => assert!(false),
+    Err(err) => {
+      assert_eq!(1, err.len());
+      assert!(err[0].contains("2:19"));
+    }
+  }
+}
@@ -0,0 +1,556 @@
+extern crate compiler;
+
+use std::collections::HashMap;
+use std::io::MemWriter;
+use std::iter;
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::ast::BinaryOperator;
+use compiler::ast::Position;
+use compiler::ast::Type;
+use compiler::interpreter::Value;
+use compiler::interpreter::binary_operation;
+use compiler::interpreter::convert;
+use compiler::ir::*;
+use compiler::ir::lowering::lower;
+use compiler::ir::liveness::analyze;
+use compiler::ir::optimizer::Level;
+use compiler::ir::optimizer::optimize;
+use compiler::backend::x86_64::register_allocator::*;
+
+fn lower_source(source: &str) -> Module {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  lower(&program)
+}
+
+fn parse_ir(text: &str) -> Module {
+  match compiler::ir::parser::parse(text) {
+    Ok(module) => module,
+    Err(errors) => panic!("Parsing failed: {:?}", errors),
+  }
+}
+
+fn target(general: &[(&'static str, bool)], float: &[&'static str]) -> Target {
+  let mut registers = vec![];
+  for &(name, callee_saved) in general.iter() {
+    registers.push(PhysicalRegister { name: name, class: Class::General, callee_saved: callee_saved });
+  }
+  for name in float.iter() {
+    registers.push(PhysicalRegister { name: *name, class: Class::Float, callee_saved: false });
+  }
+  Target { registers: registers }
+}
+
+fn is_clobbering(function: &Function, instruction: &Instruction) -> bool {
+  match *instruction {
+    Instruction::Call(..) | Instruction::Print(..) => true,
+    Instruction::Binary(_, _, ref left, _) => left.get_type(function) == Type::String,
+    _ => false,
+  }
+}
+
+// checks that values live at the same time never share a register, that
+// registers hold values of their class and that no value stays in a
+// caller-saved register over a call
+fn check_allocation(function: &Function, target: &Target, allocation: &Allocation) {
+  let mut clobbers = vec![];
+  for (block, range) in allocation.blocks.iter().enumerate() {
+    match *range {
+      Some((from, _)) => {
+        for (index, instruction) in function.blocks[block].instructions.iter().enumerate() {
+          if is_clobbering(function, instruction) {
+            clobbers.push(from + 2 * index + 1);
+          }
+        }
+      },
+      None => { },
+    }
+  }
+
+  let mut register_segments = vec![];
+  for (register, segments) in allocation.segments.iter().enumerate() {
+    for pair in segments.windows(2) {
+      assert!(pair[0].to <= pair[1].from);
+    }
+    for segment in segments.iter() {
+      assert!(segment.from < segment.to);
+      match segment.location {
+        Location::Register(name) => {
+          let physical = target.registers.iter().find(|physical| physical.name == name).unwrap();
+          assert_eq!(Class::of(function.registers[register]), physical.class);
+          if !physical.callee_saved {
+            assert!(clobbers.iter().all(|clobber| *clobber < segment.from || *clobber >= segment.to));
+          }
+          register_segments.push((register, *segment, name));
+        },
+        Location::Stack(slot) => assert!(slot < allocation.spill_slots),
+      }
+    }
+  }
+
+  for &(register, segment, name) in register_segments.iter() {
+    for &(other, other_segment, other_name) in register_segments.iter() {
+      if register != other && name == other_name {
+        assert!(segment.to <= other_segment.from || other_segment.to <= segment.from);
+      }
+    }
+  }
+}
+
+// executes a module with the values in the locations the allocator gave
+// them, clobbering the caller-saved registers at calls
+struct Simulator<'a> {
+  module: &'a Module,
+  target: &'a Target,
+  allocations: &'a Vec<Allocation>,
+  globals: Vec<Value>,
+  output: String,
+}
+
+struct Frame {
+  registers: HashMap<&'static str, Value>,
+  stack: Vec<Value>,
+}
+
+impl Frame {
+  fn read(&self, location: Location) -> Value {
+    match location {
+      Location::Register(name) => match self.registers.get(&name) {
+        Some(value) => value.clone(),
+        None => Value::Void,
+      },
+      Location::Stack(slot) => self.stack[slot].clone(),
+    }
+  }
+
+  fn write(&mut self, location: Location, value: Value) {
+    match location {
+      Location::Register(name) => { self.registers.insert(name, value); },
+      Location::Stack(slot) => self.stack[slot] = value,
+    }
+  }
+
+  // the copies happen at the same time
+  fn copy(&mut self, moves: Vec<(Register, Location, Location)>) {
+    let values: Vec<Value> = moves.iter().map(|&(_, from, _)| self.read(from)).collect();
+    for (&(_, _, to), value) in moves.iter().zip(values.into_iter()) {
+      self.write(to, value);
+    }
+  }
+}
+
+impl<'a> Simulator<'a> {
+  fn run(module: &'a Module, target: &'a Target, allocations: &'a Vec<Allocation>) -> String {
+    let mut simulator = Simulator {
+      module: module,
+      target: target,
+      allocations: allocations,
+      globals: iter::repeat(Value::Void).take(module.globals.len()).collect(),
+      output: String::new(),
+    };
+    simulator.call(module.init, vec![]);
+    simulator.call(module.main.unwrap(), vec![]);
+    simulator.output
+  }
+
+  fn call(&mut self, index: usize, arguments: Vec<Value>) -> Value {
+    let module = self.module;
+    let allocations = self.allocations;
+    let function = &module.functions[index];
+    let allocation = &allocations[index];
+    let mut frame = Frame {
+      registers: HashMap::new(),
+      stack: iter::repeat(Value::Void).take(allocation.spill_slots).collect(),
+    };
+    for (parameter, argument) in function.parameters.iter().zip(arguments.into_iter()) {
+      match allocation.location(*parameter, 0) {
+        Some(location) => frame.write(location, argument),
+        None => { },
+      }
+    }
+
+    let mut block = 0;
+    loop {
+      let mut position = allocation.blocks[block].unwrap().0;
+      for instruction in function.blocks[block].instructions.iter() {
+        frame.copy(allocation.moves_at(position));
+        let result = {
+          let read = |operand: &Operand| value(&frame, allocation, operand, position);
+          match *instruction {
+            Instruction::Copy(_, ref operand) => Some(read(operand)),
+            Instruction::Binary(_, operator, ref left, ref right) =>
+              Some(binary_operation(operator, read(left), read(right), &Position::new(0, 0)).unwrap()),
+            Instruction::Divide(_, ref left, ref right, ref pos) =>
+              Some(binary_operation(BinaryOperator::Divide, read(left), read(right), pos).unwrap()),
+            Instruction::Convert(destination, ref operand) =>
+              Some(convert(read(operand), function.registers[destination])),
+            Instruction::Call(_, callee, ref arguments) => {
+              let arguments = arguments.iter().map(|argument| read(argument)).collect();
+              Some(self.call(callee, arguments))
+            },
+            Instruction::Print(ref operand) => {
+              self.output.push_str(format!("{}\n", read(operand)).as_slice());
+              None
+            },
+            Instruction::LoadGlobal(_, global) => Some(self.globals[global].clone()),
+            Instruction::StoreGlobal(global, ref operand) => {
+              self.globals[global] = read(operand);
+              None
+            },
+            Instruction::Phi(..) => panic!("Phi in allocated function"),
+          }
+        };
+
+        let clobbering = is_clobbering(function, instruction);
+        if clobbering {
+          for register in self.target.registers.iter().filter(|register| !register.callee_saved) {
+            frame.registers.insert(register.name, Value::Void);
+          }
+        }
+        match (instruction.destination(), result) {
+          (Some(destination), Some(result)) => {
+            let definition = if clobbering { position + 2 } else { position + 1 };
+            frame.write(allocation.location(destination, definition).unwrap(), result);
+          },
+          _ => { },
+        }
+        position += 2;
+      }
+
+      frame.copy(allocation.moves_at(position));
+      let next = match function.blocks[block].terminator {
+        Terminator::Jump(target) => target,
+        Terminator::Branch(ref condition, if_true, if_false) => {
+          match value(&frame, allocation, condition, position) {
+            Value::Boolean(true) => if_true,
+            Value::Boolean(false) => if_false,
+            other => panic!("Branch on {:?}", other),
+          }
+        },
+        Terminator::Return(Some(ref operand)) => return value(&frame, allocation, operand, position),
+        Terminator::Return(None) => return Value::Void,
+        Terminator::Unreachable => panic!("Unreachable end reached"),
+      };
+      frame.copy(allocation.edge_moves(block, next));
+      block = next;
+    }
+  }
+}
+
+fn value(frame: &Frame, allocation: &Allocation, operand: &Operand, position: usize) -> Value {
+  match *operand {
+    Operand::Register(register) => match allocation.location(register, position) {
+      Some(location) => frame.read(location),
+      None => panic!("%{} has no location at {}", register, position),
+    },
+    Operand::Integer(value) => Value::Integer(value),
+    Operand::Float(value) => Value::Float(value),
+    Operand::Double(value) => Value::Double(value),
+    Operand::Boolean(value) => Value::Boolean(value),
+    Operand::Text(ref value) => Value::Text(value.clone()),
+  }
+}
+
+// allocates every function, checks the allocations and compares the output
+// of the allocated module with the output of the intermediate representation
+fn assert_same_output(module: &Module, target: &Target) {
+  let allocations: Vec<Allocation> = module.functions.iter().map(|function| {
+    let allocation = allocate(function, target);
+    check_allocation(function, target, &allocation);
+    allocation
+  }).collect();
+
+  let mut expected = MemWriter::new();
+  compiler::ir::interpreter::run(module, &mut expected).unwrap();
+  assert_eq!(String::from_utf8(expected.get_ref().to_vec()).unwrap(),
+    Simulator::run(module, target, &allocations));
+}
+
+// a function keeping count values of both classes live at the same time,
+// over a call if requested
+fn pressure_module(count: usize, call: bool) -> Module {
+  let mut text = "fn @id(%0: int) : int {\nbb0:\n  ret %0\n}\n\n\
+    fn @f(%0: int, %1: double) : int {\nbb0:\n".to_string();
+  let mut next = 2;
+  let mut integers = vec![];
+  let mut doubles = vec![];
+  for index in range(0, count) {
+    text.push_str(format!("  %{}: int = mul %0, {}\n", next, index + 2).as_slice());
+    integers.push(next);
+    text.push_str(format!("  %{}: double = add %1, {}.5\n", next + 1, index).as_slice());
+    doubles.push(next + 1);
+    next += 2;
+  }
+  if call {
+    text.push_str(format!("  %{}: int = call @id(%0)\n", next).as_slice());
+    integers.push(next);
+    next += 1;
+  }
+  let mut integer_sum = integers[0];
+  for register in integers.iter().skip(1) {
+    text.push_str(format!("  %{}: int = add %{}, %{}\n", next, integer_sum, register).as_slice());
+    integer_sum = next;
+    next += 1;
+  }
+  let mut double_sum = doubles[0];
+  for register in doubles.iter().skip(1) {
+    text.push_str(format!("  %{}: double = add %{}, %{}\n", next, double_sum, register).as_slice());
+    double_sum = next;
+    next += 1;
+  }
+  text.push_str(format!("  %{}: int = convert %{}\n", next, double_sum).as_slice());
+  text.push_str(format!("  %{}: int = add %{}, %{}\n", next + 1, integer_sum, next).as_slice());
+  text.push_str(format!("  ret %{}\n}}\n\nfn @main() : void {{\nbb0:\n\
+    %0: int = call @f(3, 0.25)\n  print %0\n  ret\n}}\n", next + 1).as_slice());
+  parse_ir(text.as_slice())
+}
+
+#[test]
+fn liveness_of_loop() {
+  let module = parse_ir("fn @f(%0: int) : int {
+bb0:
+  %1: int = copy 0
+  %2: int = copy 0
+  jmp bb1
+bb1:
+  %3: bool = lt %2, %0
+  br %3, bb2, bb3
+bb2:
+  %1: int = add %1, %2
+  %2: int = add %2, 1
+  jmp bb1
+bb3:
+  ret %1
+}
+");
+  let liveness = analyze(&module.functions[0]);
+  assert_eq!(vec![0], liveness.live_in_registers(0));
+  assert_eq!(vec![0, 1, 2], liveness.live_out_registers(0));
+  assert_eq!(vec![0, 1, 2], liveness.live_in_registers(1));
+  assert_eq!(vec![0, 1, 2], liveness.live_in_registers(2));
+  assert_eq!(vec![1], liveness.live_in_registers(3));
+  assert_eq!(Vec::<Register>::new(), liveness.live_out_registers(3));
+}
+
+#[test]
+fn liveness_reads_phi_operands_at_end_of_predecessors() {
+  let module = parse_ir("fn @f(%0: bool, %1: int) : int {
+bb0:
+  br %0, bb1, bb2
+bb1:
+  %2: int = add %1, 1
+  jmp bb3
+bb2:
+  jmp bb3
+bb3:
+  %3: int = phi [bb1: %2], [bb2: %1]
+  ret %3
+}
+");
+  let liveness = analyze(&module.functions[0]);
+  assert_eq!(vec![2], liveness.live_out_registers(1));
+  assert_eq!(vec![1], liveness.live_out_registers(2));
+  assert_eq!(Vec::<Register>::new(), liveness.live_in_registers(3));
+  assert_eq!(vec![0, 1], liveness.live_in_registers(0));
+}
+
+#[test]
+fn allocation_keeps_loop_values_in_registers_over_lifetime_holes() {
+  let module = parse_ir("fn @f(%0: int) : int {
+bb0:
+  %1: int = copy 0
+  %2: int = copy 0
+  jmp bb1
+bb1:
+  %3: bool = lt %2, %0
+  br %3, bb2, bb3
+bb2:
+  %1: int = add %1, %2
+  %2: int = add %2, 1
+  jmp bb1
+bb3:
+  ret %1
+}
+");
+  let function = &module.functions[0];
+  let allocation = allocate(function, &Target::x86_64());
+  assert_eq!("fn @f(%0: int) : int {
+bb0:
+   0  %1: int = copy 0
+   2  %2: int = copy 0
+   4  jmp bb1
+bb1:
+   6  %3: bool = lt %2, %0
+   8  br %3, bb2, bb3
+bb3:
+  10  ret %1
+bb2:
+  12  %1: int = add %1, %2
+  14  %2: int = add %2, 1
+  16  jmp bb1
+}
+%0: int -> rdi [0, 10), rdi [12, 18)
+%1: int -> rsi [1, 11), rsi [12, 18)
+%2: int -> rcx [3, 10), rcx [12, 18)
+%3: bool -> r8 [7, 9)
+spill slots: 0
+callee-saved: none
+", dump(&module, function, &allocation).as_slice());
+  assert!(allocation.edge_moves(2, 1).is_empty());
+}
+
+#[test]
+fn allocation_splits_and_spills_intervals_under_pressure() {
+  let module = parse_ir("fn @f(%0: int) : int {
+bb0:
+  %1: int = add %0, 1
+  %2: int = add %0, 2
+  %3: int = add %0, 3
+  %4: int = add %1, %2
+  %5: int = add %4, %3
+  ret %5
+}
+");
+  let function = &module.functions[0];
+  let two = target(&[("rdi", false), ("rsi", false)], &[]);
+  let allocation = allocate(function, &two);
+  assert_eq!("fn @f(%0: int) : int {
+bb0:
+   0  %1: int = add %0, 1
+   2  %2: int = add %0, 2
+   4  %3: int = add %0, 3
+   6  %4: int = add %1, %2
+   8  %5: int = add %4, %3
+  10  ret %5
+}
+%0: int -> rdi [0, 5)
+%1: int -> rsi [1, 2), stack 0 [2, 6), rdi [6, 7)
+%2: int -> rsi [3, 7)
+%3: int -> rdi [5, 6), stack 1 [6, 8), rsi [8, 9)
+%4: int -> rdi [7, 9)
+%5: int -> rdi [9, 11)
+spill slots: 2
+callee-saved: none
+", dump(&module, function, &allocation).as_slice());
+
+  assert_eq!(vec![(1, Location::Register("rsi"), Location::Stack(0))], allocation.moves_at(2));
+  assert_eq!(vec![(1, Location::Stack(0), Location::Register("rdi")),
+    (3, Location::Register("rdi"), Location::Stack(1))], allocation.moves_at(6));
+  assert_eq!(Some(Location::Stack(1)), allocation.location(3, 7));
+  check_allocation(function, &two, &allocation);
+}
+
+#[test]
+fn allocation_keeps_values_live_over_calls_in_callee_saved_registers() {
+  let module = parse_ir("fn @g(%0: int) : int {
+bb0:
+  ret %0
+}
+
+fn @f(%0: int) : int {
+bb0:
+  %1: int = call @g(%0)
+  %2: int = add %1, %0
+  ret %2
+}
+");
+  let function = &module.functions[1];
+  let allocation = allocate(function, &Target::x86_64());
+  assert_eq!("fn @f(%0: int) : int {
+bb0:
+   0  %1: int = call @g(%0)
+   2  %2: int = add %1, %0
+   4  ret %2
+}
+%0: int -> rbx [0, 3)
+%1: int -> rdi [2, 3)
+%2: int -> rdi [3, 5)
+spill slots: 0
+callee-saved: rbx
+", dump(&module, function, &allocation).as_slice());
+}
+
+#[test]
+fn allocation_spills_values_live_over_calls_without_callee_saved_registers() {
+  let module = parse_ir("fn @g() : void {
+bb0:
+  ret
+}
+
+fn @f(%0: double) : double {
+bb0:
+  %1: double = add %0, 1.0
+  call @g()
+  %2: double = mul %1, %0
+  ret %2
+}
+");
+  let function = &module.functions[1];
+  let allocation = allocate(function, &Target::x86_64());
+  assert_eq!(Some(Location::Register("xmm0")), allocation.location(0, 0));
+  assert_eq!(Some(Location::Stack(1)), allocation.location(0, 3));
+  assert_eq!(Some(Location::Stack(0)), allocation.location(1, 3));
+  assert_eq!(Some(Location::Register("xmm0")), allocation.location(0, 4));
+  assert_eq!(2, allocation.spill_slots);
+  assert_eq!(Vec::<&'static str>::new(), allocation.callee_saved);
+  check_allocation(function, &Target::x86_64(), &allocation);
+}
+
+#[test]
+fn allocation_under_high_pressure_keeps_classes_apart() {
+  for &call in [false, true].iter() {
+    let module = pressure_module(24, call);
+    let target = Target::x86_64();
+    let allocation = allocate(&module.functions[1], &target);
+    check_allocation(&module.functions[1], &target, &allocation);
+    assert!(allocation.spill_slots > 0);
+    assert_same_output(&module, &target);
+  }
+}
+
+#[test]
+fn allocation_with_few_registers_keeps_program_output() {
+  let targets = vec![
+    Target::x86_64(),
+    target(&[("rdi", false), ("rsi", false), ("rbx", true)], &["xmm0", "xmm1"]),
+    target(&[("rbx", true)], &["xmm0"]),
+    target(&[("rdi", false)], &[]),
+  ];
+  for module in vec![pressure_module(6, true), pressure_module(1, false)].iter() {
+    for target in targets.iter() {
+      assert_same_output(module, target);
+    }
+  }
+}
+
+#[test]
+fn allocation_of_lowered_programs_keeps_program_output() {
+  let source = "let counter:int = 0;\n\
+    fn bump(n:int) : int { counter = counter + n; return counter; }\n\
+    fn scale(x:double, y:float) : double { return x * 2.0 + y as double; }\n\
+    fn main() { let a:int = 1; let b:int = 2; let c:int = 3; let d:int = 4; let e:int = 5; \
+    let f:int = 6; let g:int = 7; let h:int = 8; let x:double = 1.5; let y:double = 2.5; \
+    let z:float = 0.5f; let w:float = 1.25f; let s:string = \"s\"; \
+    for (let i:int = 0; i < 4; i = i + 1) { a = a + b * i; b = b + c - d; c = c * 2 - e; \
+    d = d + bump(f + g); e = e + h / (i + 1); f = f - a; x = scale(x, z) - y; y = y + x / 4.0; \
+    z = z * w; w = w + 0.25f; s = s + \"x\"; if (a > b) { g = g + bump(1); } else { h = h + g; } } \
+    print(a); print(b); print(c); print(d); print(e); print(f); print(g); print(h); \
+    print(x); print(y); print(z); print(w); print(s); print(s == \"sxxxx\"); print(counter); }";
+  let targets = vec![
+    Target::x86_64(),
+    target(&[("rdi", false), ("rsi", false), ("rbx", true)], &["xmm0", "xmm1"]),
+    target(&[("rdi", false)], &["xmm0"]),
+  ];
+  for level in vec![Level::O0, Level::O2].into_iter() {
+    let mut module = lower_source(source);
+    optimize(&mut module, level);
+    for target in targets.iter() {
+      assert_same_output(&module, target);
+    }
+  }
+}
@@ -0,0 +1,233 @@
+extern crate compiler;
+
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+
+fn resolve_source(source: &str) -> Result<Vec<String>, Vec<String>> {
+  let tokens = tokenize(source).unwrap();
+  let program = parse(tokens).unwrap();
+  resolve(&program)
+}
+
+#[test]
+fn resolver_accepts_globals_with_constant_initializers() {
+  match resolve_source("const A:int = 60*60;\nconst B:int = A*24;\nlet c:int = B + 1;\nfn foo() { c = A; }") {
+    Ok(warnings) => assert_eq!(0, warnings.len()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn resolver_errors_on_global_initializer_referring_to_variable() {
+  match resolve_source("let a:int = 5;\nlet b:int = 2*a;\nfn foo() { }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:15"));
+    }
+  }
+}
+
+#[test]
+fn resolver_errors_on_global_initializer_referring_to_later_constant() {
+  match resolve_source("const A:int = B;\nconst B:int = 5;\nfn foo() { }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("1:15"));
+    }
+  }
+}
+
+#[test]
+fn resolver_errors_on_assignment_to_constant() {
+  match resolve_source("const A:int = 5;\nfn foo() {\n A = 4; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("3:2"));
+      assert!(errors[0].contains("1:1"));
+    }
+  }
+}
+
+#[test]
+fn resolver_allows_assignment_to_local_shadowing_constant() {
+  match resolve_source("const A:int = 5;\nfn foo() {\n let A:int = 3; A = 4; }") {
+    Ok(warnings) => assert_eq!(1, warnings.len()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn resolver_warns_when_local_shadows_global() {
+  match resolve_source("let a:int = 5;\nfn foo(a:int) {\n { let a:int = 4; } }") {
+    Ok(warnings) => {
+      assert_eq!(2, warnings.len());
+      assert!(warnings[0].contains("Warning at 2:8"));
+      assert!(warnings[1].contains("Warning at 3:4"));
+    },
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn resolver_accepts_variables_in_enclosing_scopes() {
+  match resolve_source("fn foo(a:int) {\n let b:int = a;\n { let c:int = a + b; c = 5; }\n for (let i:int = 0; i < b; i = i + 1) { b = i; }\n if (a == b) { a = 1; } elif (b == 2) { b = a; } else { bar(a); } }\nfn bar(x:int) { }") {
+    Ok(warnings) => assert_eq!(0, warnings.len()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn resolver_errors_on_undeclared_variable_in_expression() {
+  match resolve_source("fn foo() {\n let a:int = b + 1; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:14"));
+      assert!(errors[0].contains("'b'"));
+    }
+  }
+}
+
+#[test]
+fn resolver_errors_on_assignment_to_undeclared_variable() {
+  match resolve_source("fn foo() {\n x = 1; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:2"));
+    }
+  }
+}
+
+#[test]
+fn resolver_errors_on_variable_used_outside_its_block() {
+  match resolve_source("fn foo() {\n { let a:int = 1; }\n a = 2; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("3:2"));
+    }
+  }
+}
+
+#[test]
+fn resolver_errors_on_for_loop_variable_used_after_loop() {
+  match resolve_source("fn foo() {\n for (let i:int = 0; i < 10; i = i + 1) { }\n i = 2; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("3:2"));
+    }
+  }
+}
+
+#[test]
+fn resolver_errors_on_variable_used_in_its_own_initializer() {
+  match resolve_source("fn foo() {\n let a:int = a; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:14"));
+    }
+  }
+}
+
+#[test]
+fn resolver_errors_on_undeclared_function() {
+  match resolve_source("fn foo() {\n bar(1); }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:2"));
+      assert!(errors[0].contains("'bar'"));
+    }
+  }
+}
+
+#[test]
+fn resolver_accepts_call_to_function_declared_later() {
+  match resolve_source("fn foo() { bar(); }\nfn bar() { foo(); }") {
+    Ok(..) => assert!(true),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn resolver_errors_on_duplicate_declaration_in_same_scope_with_both_locations() {
+  match resolve_source("fn foo() {\n let a:int = 1;\n let a:int = 2; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("3:2"));
+      assert!(errors[0].contains("2:2"));
+    }
+  }
+}
+
+#[test]
+fn resolver_allows_shadowing_in_nested_scope() {
+  match resolve_source("fn foo() {\n let a:int = 1;\n { let a:int = 2; } }") {
+    Ok(warnings) => assert_eq!(0, warnings.len()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn resolver_errors_on_local_redeclaring_parameter() {
+  match resolve_source("fn foo(a:int) {\n let a:int = 2; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:2"));
+      assert!(errors[0].contains("1:8"));
+    }
+  }
+}
+
+#[test]
+fn resolver_errors_on_duplicate_parameter_names() {
+  match resolve_source("fn foo(a:int, b:int, a:double) { }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("1:22"));
+      assert!(errors[0].contains("1:8"));
+    }
+  }
+}
+
+#[test]
+fn resolver_errors_on_duplicate_function_names() {
+  match resolve_source("fn foo() { }\nfn bar() { }\nfn foo(a:int) { }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("3:1"));
+      assert!(errors[0].contains("1:1"));
+    }
+  }
+}
+
+#[test]
+fn resolver_errors_on_duplicate_global_names() {
+  match resolve_source("let a:int = 1;\nconst a:int = 2;\nfn foo() { }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:1"));
+      assert!(errors[0].contains("1:1"));
+    }
+  }
+}
+
+#[test]
+fn resolver_accepts_builtin_print() {
+  match resolve_source("fn main() { print(1); }") {
+    Ok(..) => assert!(true),
+    Err(..) => assert!(false),
+  }
+}
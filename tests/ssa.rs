@@ -0,0 +1,463 @@
+extern crate compiler;
+
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::ir::*;
+use compiler::ir::lowering::lower;
+use compiler::ir::printer::print;
+use compiler::ir::printer::print_function;
+use compiler::ir::dominators::DominatorTree;
+use compiler::ir::ssa::construct;
+use compiler::ir::ssa::destruct;
+use compiler::ir::ssa::to_ssa;
+use compiler::ir::ssa::from_ssa;
+use compiler::ir::verifier::verify;
+use compiler::ir::verifier::Form;
+
+fn lower_source(source: &str) -> Module {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  lower(&program)
+}
+
+fn parse_ir(text: &str) -> Module {
+  match compiler::ir::parser::parse(text) {
+    Ok(module) => module,
+    Err(errors) => panic!("Parsing failed: {:?}", errors),
+  }
+}
+
+fn assert_verify_error(text: &str, form: Form, expected: &str) {
+  match verify(&parse_ir(text), form) {
+    Ok(()) => panic!("Verification succeeded, expected '{}'", expected),
+    Err(errors) => assert_eq!(expected, errors[0].as_slice()),
+  }
+}
+
+#[test]
+fn dominator_tree_of_loop_with_branch() {
+  let module = parse_ir("fn @f(%0: bool) : void {
+bb0:
+  jmp bb1
+bb1:
+  br %0, bb2, bb3
+bb2:
+  br %0, bb1, bb4
+bb3:
+  jmp bb4
+bb4:
+  ret
+bb5:
+  jmp bb4
+}
+");
+  let function = &module.functions[0];
+  let tree = DominatorTree::new(function);
+  assert_eq!(vec![None, Some(0), Some(1), Some(1), Some(1), None],
+    range(0, 6).map(|block| tree.immediate_dominator(block)).collect::<Vec<Option<BlockId>>>());
+  assert_eq!([2, 3, 4].as_slice(), tree.children(1));
+  assert!(tree.dominates(1, 4));
+  assert!(tree.dominates(2, 2));
+  assert!(!tree.strictly_dominates(2, 2));
+  assert!(!tree.dominates(2, 4));
+  assert!(!tree.dominates(0, 5));
+  assert!(!tree.is_reachable(5));
+
+  let frontiers = tree.frontiers(function);
+  assert_eq!(vec![vec![], vec![1], vec![1, 4], vec![4], vec![], vec![]], frontiers);
+}
+
+#[test]
+fn construction_merges_elif_arms_with_phi() {
+  let mut module = lower_source("fn sign(a:int) : int { let s:int = 0; if (a < 0) { s = -1; } elif (a > 0) { s = 1; } else { print(a); } return s; }");
+  to_ssa(&mut module);
+  let expected = "fn @sign(%0: int) : int {
+bb0:
+  %1: int = copy 0
+  %2: bool = lt %0, 0
+  br %2, bb1, bb2
+bb1:
+  %4: int = copy -1
+  jmp bb5
+bb2:
+  %3: bool = gt %0, 0
+  br %3, bb3, bb4
+bb3:
+  %5: int = copy 1
+  jmp bb5
+bb4:
+  print %0
+  jmp bb5
+bb5:
+  %6: int = phi [bb1: %4], [bb3: %5], [bb4: %1]
+  ret %6
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+}
+
+#[test]
+fn construction_places_phis_in_loop_header() {
+  let mut module = lower_source("fn sum(n:int) : int { let s:int = 0; for (let i:int = 1; i <= n; i = i + 1) { s = s + i; } return s; }");
+  to_ssa(&mut module);
+  let expected = "fn @sum(%0: int) : int {
+bb0:
+  %1: int = copy 0
+  %2: int = copy 1
+  jmp bb1
+bb1:
+  %6: int = phi [bb0: %1], [bb2: %8]
+  %7: int = phi [bb0: %2], [bb2: %9]
+  %3: bool = le %7, %0
+  br %3, bb2, bb3
+bb2:
+  %4: int = add %6, %7
+  %8: int = copy %4
+  %5: int = add %7, 1
+  %9: int = copy %5
+  jmp bb1
+bb3:
+  ret %6
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+}
+
+#[test]
+fn construction_only_places_phis_where_register_is_live() {
+  // %1 is dead after the if, %0 is a parameter assigned in one arm
+  let mut module = parse_ir("fn @f(%0: int, %2: bool) : int {
+bb0:
+  %1: int = copy 1
+  br %2, bb1, bb2
+bb1:
+  %1: int = copy 2
+  %0: int = copy %1
+  jmp bb2
+bb2:
+  ret %0
+}
+");
+  construct(&mut module.functions[0]);
+  let expected = "fn @f(%0: int, %2: bool) : int {
+bb0:
+  %1: int = copy 1
+  br %2, bb1, bb2
+bb1:
+  %3: int = copy 2
+  %4: int = copy %3
+  jmp bb2
+bb2:
+  %5: int = phi [bb0: %0], [bb1: %4]
+  ret %5
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+  assert!(verify(&module, Form::Ssa).is_ok());
+}
+
+#[test]
+fn construction_uses_zero_value_on_paths_without_assignment() {
+  let mut module = parse_ir("fn @f(%0: bool) : string {
+bb0:
+  br %0, bb1, bb2
+bb1:
+  %1: string = copy \"a\"
+  jmp bb2
+bb2:
+  %1: string = add %1, \"b\"
+  ret %1
+}
+");
+  construct(&mut module.functions[0]);
+  assert_eq!(Instruction::Phi(2, vec![(0, Operand::Text(String::new())), (1, Operand::Register(1))]),
+    module.functions[0].blocks[2].instructions[0]);
+  assert!(verify(&module, Form::Ssa).is_ok());
+}
+
+#[test]
+fn ssa_form_of_programs_verifies_and_round_trips() {
+  let sources = [
+    "let total:int = 0;\nfn find(n:int, k:int) : int { for (let i:int = 0; i < n; i = i + 1) { let j:int = i * i; if (j > k) { return i; } total = total + j; } return -1; }\nfn main() { print(find(10, 20)); print(total); }",
+    "fn nested(n:int) : double { let acc:double = 0.0; for (let i:int = 0; i < n; i = i + 1) { for (let j:int = 0; j < i; j = j + 1) { if (j == 2) { acc = acc + 1.5; } elif (j == 3) { acc = acc * 2.0; n = n - 1; } } } return acc; }\nfn main() { print(nested(8)); }",
+    "fn main() { let s:string = \"\"; let f:bool = false; for (let i:int = 0; i < 4; i = i + 1) { if (i == 2) { s = s + \"two\"; } else { s = s + \"x\"; } f = f == (i > 2); } print(s); print(f); }",
+  ];
+  for source in sources.iter() {
+    let mut module = lower_source(*source);
+    assert!(verify(&module, Form::Normal).is_ok());
+    to_ssa(&mut module);
+    let text = print(&module);
+    assert_eq!(module, parse_ir(text.as_slice()));
+
+    from_ssa(&mut module);
+    for block in module.functions.iter().flat_map(|function| function.blocks.iter()) {
+      assert!(!block.instructions.iter().any(|instruction| instruction.is_phi()));
+    }
+  }
+}
+
+#[test]
+fn destruction_copies_at_end_of_predecessors() {
+  let mut module = lower_source("fn sum(n:int) : int { let s:int = 0; for (let i:int = 1; i <= n; i = i + 1) { s = s + i; } return s; }");
+  to_ssa(&mut module);
+  from_ssa(&mut module);
+  let expected = "fn @sum(%0: int) : int {
+bb0:
+  %1: int = copy 0
+  %2: int = copy 1
+  %6: int = copy %1
+  %7: int = copy %2
+  jmp bb1
+bb1:
+  %3: bool = le %7, %0
+  br %3, bb2, bb3
+bb2:
+  %4: int = add %6, %7
+  %8: int = copy %4
+  %5: int = add %7, 1
+  %9: int = copy %5
+  %6: int = copy %8
+  %7: int = copy %9
+  jmp bb1
+bb3:
+  ret %6
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+}
+
+#[test]
+fn destruction_sequentializes_cyclic_copies_with_temporary() {
+  // the phis rotate %0, %1 and %2 and swap %3 and %4 on the back edge
+  let mut module = parse_ir("fn @f(%5: bool) : void {
+bb0:
+  jmp bb1
+bb1:
+  %0: int = phi [bb0: 1], [bb1: %1]
+  %1: int = phi [bb0: 2], [bb1: %2]
+  %2: int = phi [bb0: 3], [bb1: %0]
+  %3: int = phi [bb0: 4], [bb1: %4]
+  %4: int = phi [bb0: 5], [bb1: %3]
+  %6: int = phi [bb0: 6], [bb1: %6]
+  print %0
+  jmp bb1
+}
+");
+  assert!(verify(&module, Form::Ssa).is_ok());
+  destruct(&mut module.functions[0]);
+  let expected = "fn @f(%5: bool) : void {
+bb0:
+  %0: int = copy 1
+  %1: int = copy 2
+  %2: int = copy 3
+  %3: int = copy 4
+  %4: int = copy 5
+  %6: int = copy 6
+  jmp bb1
+bb1:
+  print %0
+  %7: int = copy %1
+  %1: int = copy %2
+  %2: int = copy %0
+  %0: int = copy %7
+  %8: int = copy %4
+  %4: int = copy %3
+  %3: int = copy %8
+  jmp bb1
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+  assert!(verify(&module, Form::Normal).is_ok());
+}
+
+#[test]
+fn destruction_splits_edges_leaving_branches() {
+  // without splitting, the copy for bb2 would change %0 on the way to bb3 as well
+  let mut module = parse_ir("fn @f(%0: int) : int {
+bb0:
+  jmp bb1
+bb1:
+  %1: int = phi [bb0: %0], [bb1: %2]
+  %2: int = sub %1, 1
+  %3: bool = gt %2, 0
+  br %3, bb1, bb2
+bb2:
+  ret %1
+}
+");
+  destruct(&mut module.functions[0]);
+  let expected = "fn @f(%0: int) : int {
+bb0:
+  %1: int = copy %0
+  jmp bb1
+bb1:
+  %2: int = sub %1, 1
+  %3: bool = gt %2, 0
+  br %3, bb3, bb2
+bb2:
+  ret %1
+bb3:
+  %1: int = copy %2
+  jmp bb1
+}
+";
+  assert_eq!(expected, print_function(&module, &module.functions[0]).as_slice());
+  assert!(verify(&module, Form::Normal).is_ok());
+}
+
+#[test]
+fn phis_are_printed_and_parsed() {
+  let text = "fn @f(%0: bool) : int {
+bb0:
+  br %0, bb1, bb2
+bb1:
+  jmp bb2
+bb2:
+  %1: int = phi [bb0: 1], [bb1: -2]
+  ret %1
+}
+";
+  let module = parse_ir(text);
+  assert_eq!(Instruction::Phi(1, vec![(0, Operand::Integer(1)), (1, Operand::Integer(-2))]),
+    module.functions[0].blocks[2].instructions[0]);
+  assert!(print(&module).as_slice().starts_with(text));
+
+  match compiler::ir::parser::parse("fn @f() : int {\nbb0:\n  %1: int = phi [bb4: 1]\n  ret %1\n}") {
+    Ok(..) => panic!("Parsing succeeded"),
+    Err(errors) => assert_eq!("Error at 3:18: Block bb4 does not exist", errors[0].as_slice()),
+  }
+}
+
+#[test]
+fn verifier_checks_dominance_in_ssa_form() {
+  assert_verify_error("fn @f(%0: bool) : int {
+bb0:
+  br %0, bb1, bb2
+bb1:
+  %1: int = copy 1
+  jmp bb2
+bb2:
+  ret %1
+}", Form::Ssa, "Verification error in function '@f' at bb2, terminator: definition of register %1 does not dominate its use");
+
+  assert_verify_error("fn @f() : int {
+bb0:
+  %0: int = add %1, 1
+  %1: int = copy 2
+  ret %0
+}", Form::Ssa, "Verification error in function '@f' at bb0, instruction 0: definition of register %1 does not dominate its use");
+
+  assert_verify_error("fn @f(%0: int) : void {
+bb0:
+  %0: int = copy 1
+  ret
+}", Form::Ssa, "Verification error in function '@f' at bb0, instruction 0: register %0 is assigned more than once");
+}
+
+#[test]
+fn verifier_checks_phis() {
+  assert_verify_error("fn @f(%0: bool) : int {
+bb0:
+  br %0, bb1, bb2
+bb1:
+  jmp bb2
+bb2:
+  %1: int = phi [bb1: 1]
+  ret %1
+}", Form::Ssa, "Verification error in function '@f' at bb2, instruction 0: phi has no operand for predecessor bb0");
+
+  assert_verify_error("fn @f() : int {
+bb0:
+  jmp bb1
+bb1:
+  print 1
+  %1: int = phi [bb0: 1]
+  ret %1
+}", Form::Ssa, "Verification error in function '@f' at bb1, instruction 1: phi follows other instructions");
+
+  assert_verify_error("fn @f() : int {
+bb0:
+  jmp bb1
+bb1:
+  %1: int = phi [bb0: true]
+  ret %1
+}", Form::Ssa, "Verification error in function '@f' at bb1, instruction 0: operand true has type bool, expected int");
+
+  assert_verify_error("fn @f() : int {
+bb0:
+  jmp bb1
+bb1:
+  %1: int = phi [bb0: 1]
+  ret %1
+}", Form::Normal, "Verification error in function '@f' at bb1, instruction 0: phi outside of SSA form");
+}
+
+#[test]
+fn verifier_checks_assignment_on_every_path_in_normal_form() {
+  let text = "fn @f(%0: bool) : int {
+bb0:
+  br %0, bb1, bb2
+bb1:
+  %1: int = copy 1
+  jmp bb2
+bb2:
+  ret %1
+}";
+  assert_verify_error(text, Form::Normal,
+    "Verification error in function '@f' at bb2, terminator: register %1 may be used before it is assigned");
+
+  // no assignment reaches the use at all
+  assert_verify_error("fn @f() : int {
+bb0:
+  %0: int = add %0, 1
+  ret %0
+}", Form::Normal, "Verification error in function '@f' at bb0, instruction 0: register %0 is used before it is assigned");
+
+  let module = parse_ir("fn @f(%0: bool) : int {
+bb0:
+  %1: int = copy 0
+  jmp bb1
+bb1:
+  br %0, bb2, bb3
+bb2:
+  %1: int = add %1, 1
+  jmp bb1
+bb3:
+  ret %1
+}");
+  assert!(verify(&module, Form::Normal).is_ok());
+}
+
+#[test]
+fn verifier_checks_types_and_signatures() {
+  assert_verify_error("fn @g(%0: int) : int {
+bb0:
+  ret %0
+}
+
+fn @f() : void {
+bb0:
+  %0: double = call @g(1.5)
+  ret
+}", Form::Normal, "Verification error in function '@f' at bb0, instruction 0: operand 1.5 has type double, expected int");
+
+  assert_verify_error("fn @f() : int {
+bb0:
+  %0: bool = add 1, 2
+  ret 1
+}", Form::Normal, "Verification error in function '@f' at bb0, instruction 0: result has type int, but the destination has type bool");
+
+  assert_verify_error("fn @f() : int {
+bb0:
+  br 1, bb0, bb0
+}", Form::Normal, "Verification error in function '@f' at bb0, terminator: operand 1 has type int, expected bool");
+
+  assert_verify_error("fn @f() : int {
+bb0:
+  ret
+}", Form::Normal, "Verification error in function '@f' at bb0, terminator: function returns no value, expected int");
+}
@@ -0,0 +1,46 @@
+#![allow(dead_code)]
+
+use std::io::File;
+use std::io::TempDir;
+use std::io::process::Command;
+use std::io::process::ProcessOutput;
+
+/*
+  Helpers shared by the tests that build and run generated code with tools
+  of the system. A missing tool fails the test instead of skipping it, so a
+  machine without the toolchain can not report the backends as working.
+*/
+
+// writes the contents into a file of the directory and returns its path
+pub fn write_file(dir: &TempDir, name: &str, contents: &str) -> Path {
+  let path = dir.path().join(name);
+  File::create(&path).write_str(contents).unwrap();
+  path
+}
+
+// runs a tool that has to succeed, like a compiler or an assembler
+pub fn build(command: &mut Command, tool: &str) {
+  let result = run_tool(command, tool);
+  if !result.status.success() {
+    panic!("{} failed: {}", tool, String::from_utf8(result.error).unwrap());
+  }
+}
+
+// runs a program and returns its output and whether it succeeded
+pub fn run(command: &mut Command, tool: &str) -> (String, bool) {
+  let result = run_tool(command, tool);
+  (String::from_utf8(result.output).unwrap(), result.status.success())
+}
+
+pub fn assert_output(result: (String, bool), expected: &str) {
+  let (output, success) = result;
+  assert!(success);
+  assert_eq!(expected, output.as_slice());
+}
+
+fn run_tool(command: &mut Command, tool: &str) -> ProcessOutput {
+  match command.output() {
+    Ok(result) => result,
+    Err(err) => panic!("Can not run '{}', which these tests need installed: {}", tool, err),
+  }
+}
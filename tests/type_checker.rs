@@ -0,0 +1,406 @@
+extern crate compiler;
+
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::type_checker::check;
+use compiler::signatures::SignatureTable;
+use compiler::ast::Statement;
+use compiler::ast::Type;
+use compiler::ast::ExpressionKind;
+
+fn check_source(source: &str) -> Result<Vec<String>, Vec<String>> {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  check(&mut program)
+}
+
+#[test]
+fn type_checker_accepts_correctly_typed_program() {
+  match check_source("const LIMIT:int = 10;\nfn add(a:int, b:int) : int { return a + b; }\nfn main() {\n let x:int = add(1, LIMIT);\n let y:double = 2.5 * 4.0;\n let s:string = \"a\" + \"b\";\n let f:float = 1.5f;\n if (x < 5 == true) { x = 3; } elif (y >= 2.0) { y = 1.0; } else { s = \"c\"; }\n for (let i:int = 0; i < x; i = i + 1) { add(i, i); } }") {
+    Ok(warnings) => assert_eq!(0, warnings.len()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_annotates_expression_types() {
+  let tokens = tokenize("fn main() { let b:bool = 1 + 2 < 4; }").unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(check(&mut program).is_ok());
+
+  match program.functions[0].body.statements[0] {
+    Statement::VariableDeclaration(ref declaration) =>
+      assert_eq!(Type::Boolean, declaration.initializer.get_type()),
+    _ => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_errors_on_initializer_type_mismatch() {
+  match check_source("fn main() {\n let x:int = \"hello\"; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:14"));
+      assert!(errors[0].contains("expected int, found string"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_errors_on_global_initializer_type_mismatch() {
+  match check_source("const A:bool = 5;\nfn main() { }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("1:16"));
+      assert!(errors[0].contains("expected bool, found int"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_errors_on_assignment_type_mismatch() {
+  match check_source("let g:double = 1.0;\nfn main() {\n g = true; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("3:6"));
+      assert!(errors[0].contains("expected double, found bool"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_uses_innermost_declaration_type() {
+  match check_source("fn main() {\n let a:int = 1;\n { let a:string = \"x\"; a = \"y\"; }\n a = 2; }") {
+    Ok(..) => assert!(true),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_errors_on_argument_type_mismatch() {
+  match check_source("fn foo(a:int, b:string) { }\nfn main() {\n foo(1, 2); }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("3:9"));
+      assert!(errors[0].contains("argument 2"));
+      assert!(errors[0].contains("expected string, found int"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_errors_on_non_boolean_conditions() {
+  match check_source("fn main() {\n if (1) { } elif (2.0) { }\n for (;\"a\";) { } }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(3, errors.len());
+      assert!(errors[0].contains("2:6"));
+      assert!(errors[0].contains("expected bool, found int"));
+      assert!(errors[1].contains("2:19"));
+      assert!(errors[1].contains("expected bool, found double"));
+      assert!(errors[2].contains("3:8"));
+      assert!(errors[2].contains("expected bool, found string"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_errors_on_incompatible_arithmetic_operands() {
+  match check_source("fn main() {\n let a:int = 1 + true; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:16"));
+      assert!(errors[0].contains("int and bool"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_errors_on_string_subtraction() {
+  match check_source("fn main() {\n let a:string = \"a\" - \"b\"; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => assert_eq!(1, errors.len()),
+  }
+}
+
+#[test]
+fn type_checker_errors_on_incompatible_comparison_operands() {
+  match check_source("fn main() {\n let a:bool = true < false;\n let b:bool = 1 == \"a\"; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(2, errors.len());
+      assert!(errors[0].contains("2:20"));
+      assert!(errors[1].contains("3:17"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_does_not_report_errors_caused_by_earlier_errors() {
+  match check_source("fn main() {\n let a:int = (1 + true) * 2 + 3; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => assert_eq!(1, errors.len()),
+  }
+}
+
+#[test]
+fn type_checker_errors_on_return_type_mismatch() {
+  match check_source("fn foo() : int {\n return 1.5; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:9"));
+      assert!(errors[0].contains("expected int, found double"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_errors_on_missing_return_value() {
+  match check_source("fn foo() : int {\n return; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:2"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_errors_on_value_returned_from_void_function() {
+  match check_source("fn foo() {\n return 1; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:9"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_errors_on_function_that_may_not_return() {
+  match check_source("fn foo(a:int) : int {\n if (a == 1) { return 1; } elif (a == 2) { return 2; } }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("1:1"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_accepts_function_returning_on_every_branch() {
+  match check_source("fn foo(a:int) : int {\n if (a == 1) { return 1; } elif (a == 2) { return 2; } else { { return 3; } } }") {
+    Ok(..) => assert!(true),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_errors_on_void_function_used_as_value() {
+  match check_source("fn foo() { }\nfn main() {\n let a:int = foo(); }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("expected int, found void"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_errors_on_void_variable() {
+  match check_source("fn foo() { }\nfn main() {\n let a:void = foo(); }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("3:2"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_widens_integer_initializers_and_assignments() {
+  match check_source("let g:double = 1;\nfn main() {\n let f:float = 2;\n let d:double = f;\n g = 3; }") {
+    Ok(warnings) => assert_eq!(0, warnings.len()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_widens_arguments_and_return_values() {
+  match check_source("fn half(a:double) : double { return a / 2; }\nfn main() {\n half(1);\n half(1.5f); }") {
+    Ok(..) => assert!(true),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_inserts_implicit_conversion_node() {
+  let tokens = tokenize("fn main() { let d:double = 1; }").unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(check(&mut program).is_ok());
+
+  match program.functions[0].body.statements[0] {
+    Statement::VariableDeclaration(ref declaration) => {
+      assert_eq!(Type::Double, declaration.initializer.get_type());
+      match declaration.initializer.kind {
+        ExpressionKind::Cast(ref inner, Type::Double) => assert_eq!(Type::Integer, inner.get_type()),
+        _ => assert!(false),
+      }
+    },
+    _ => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_promotes_mixed_binary_operands() {
+  let tokens = tokenize("fn main() { let b:bool = 1 + 2.5f < 3.0; }").unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(check(&mut program).is_ok());
+
+  match program.functions[0].body.statements[0] {
+    Statement::VariableDeclaration(ref declaration) => {
+      match declaration.initializer.kind {
+        ExpressionKind::Binary(_, ref left, ref right) => {
+          assert_eq!(Type::Double, left.get_type());
+          assert_eq!(Type::Double, right.get_type());
+        },
+        _ => assert!(false),
+      }
+    },
+    _ => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_errors_on_implicit_narrowing() {
+  match check_source("fn foo(a:float) { }\nfn main() {\n let a:int = 1.5;\n foo(2.0); }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(2, errors.len());
+      assert!(errors[0].contains("3:14"));
+      assert!(errors[0].contains("narrowing conversion from double to int"));
+      assert!(errors[1].contains("4:6"));
+      assert!(errors[1].contains("narrowing conversion from double to float"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_accepts_explicit_narrowing_cast() {
+  match check_source("fn main() {\n let a:int = 1.5 as int;\n let f:float = (2.0 * a) as float; }") {
+    Ok(warnings) => assert_eq!(0, warnings.len()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_warns_on_redundant_cast() {
+  match check_source("fn main() {\n let a:int = 1 as int; }") {
+    Ok(warnings) => {
+      assert_eq!(1, warnings.len());
+      assert!(warnings[0].contains("2:16"));
+    },
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_warns_on_cast_the_checker_would_insert() {
+  match check_source("fn scale(a:float) { }\nfn main() {\n let x:int = 2;\n scale(x as float);\n let d:double = x as double; }") {
+    Ok(warnings) => {
+      assert_eq!(2, warnings.len());
+      assert!(warnings[0].contains("4:10"));
+      assert!(warnings[0].contains("int is converted to float implicitly"));
+      assert!(warnings[1].contains("5:19"));
+    },
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_does_not_warn_on_cast_changing_the_operation() {
+  match check_source("fn main() {\n let x:int = 3;\n let d:double = x as double / 2;\n let n:int = 1.5 as int; }") {
+    Ok(warnings) => assert_eq!(0, warnings.len()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_errors_on_non_numeric_cast() {
+  match check_source("fn main() {\n let a:int = true as int;\n let b:string = 1 as string; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(2, errors.len());
+      assert!(errors[0].contains("2:19"));
+      assert!(errors[0].contains("bool to int"));
+      assert!(errors[1].contains("3:19"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_reports_callee_declaration_on_wrong_argument_count() {
+  match check_source("fn main() {\n foo(1);\n let a:int = foo(1, 2, 3); }\n\nfn foo(a:int, b:int) : int { return a + b; }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(2, errors.len());
+      assert!(errors[0].contains("2:2"));
+      assert!(errors[0].contains("declared at 5:1"));
+      assert!(errors[0].contains("expects 2 argument(s), but 1 were given"));
+      assert!(errors[1].contains("3:14"));
+      assert!(errors[1].contains("declared at 5:1"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_reports_callee_declaration_on_wrong_argument_type() {
+  match check_source("fn main() {\n bar(\"x\", true); }\nfn bar(count:int, flag:bool) { }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("2:6"));
+      assert!(errors[0].contains("argument 1 ('count')"));
+      assert!(errors[0].contains("declared at 3:1"));
+    }
+  }
+}
+
+#[test]
+fn type_checker_uses_first_declaration_of_duplicate_function() {
+  let tokens = tokenize("fn foo(a:int) { }\nfn foo() { }\nfn main() { foo(1); }").unwrap();
+  let mut program = parse(tokens).unwrap();
+  let signatures = SignatureTable::from_program(&program);
+  assert_eq!(2, signatures.len());
+  match signatures.get(program.functions[0].name) {
+    Some(signature) => assert_eq!(1, signature.parameters.len()),
+    None => assert!(false),
+  }
+  assert!(check(&mut program).is_ok());
+}
+
+#[test]
+fn type_checker_accepts_print_of_any_value() {
+  match check_source("fn main() { print(1); print(2.0); print(true); print(\"a\" + \"b\"); }") {
+    Ok(..) => assert!(true),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn type_checker_errors_on_invalid_print_calls() {
+  match check_source("fn foo() { }\nfn main() {\n print(1, 2);\n print(foo()); }") {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(2, errors.len());
+      assert!(errors[0].contains("3:2"));
+      assert!(errors[1].contains("4:8"));
+    }
+  }
+}
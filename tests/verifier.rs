@@ -0,0 +1,260 @@
+extern crate compiler;
+
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::type_checker::check;
+use compiler::ast::Position;
+use compiler::bytecode::*;
+use compiler::bytecode::compiler::compile;
+use compiler::bytecode::verifier::verify;
+
+fn compile_source(source: &str) -> Module {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(check(&mut program).is_ok());
+  compile(&program).unwrap()
+}
+
+// module with a single function 'f' used as both init and main
+fn module_with_code(code: Vec<u8>, constants: Vec<Constant>, local_count: usize) -> Module {
+  let mut chunk = Chunk::new("f".to_string(), 0, Position::new(1, 1));
+  chunk.code = code;
+  chunk.constants = constants;
+  chunk.local_count = local_count;
+  Module {
+    functions: vec![chunk],
+    global_count: 1,
+    init: 0,
+    main: Some(0),
+    text_table: vec!["text".to_string()],
+  }
+}
+
+fn verify_code(code: Vec<u8>) -> Result<(), Vec<String>> {
+  verify(&module_with_code(code, vec![Constant::Integer(1)], 1))
+}
+
+#[test]
+fn verifier_accepts_compiled_programs() {
+  let module = compile_source("let g:int = 2;\nfn fib(n:int) : int { if (n < 2) { return n; } elif (n == 2) { return 1; } else { return fib(n - 1) + fib(n - 2); } }\nfn main() {\n for (let i:int = 0; i < 10; i = i + 1) { print(fib(i) * g); }\n for (;;) { return; } }");
+  assert!(verify(&module).is_ok());
+}
+
+#[test]
+fn verifier_infers_return_kinds_through_tail_calls() {
+  let module = compile_source("fn even(n:int) : bool { if (n == 0) { return true; } return odd(n - 1); }\n\
+    fn odd(n:int) : bool { if (n == 0) { return false; } return even(n - 1); }\nfn main() { print(even(10)); }");
+  assert!(verify(&module).is_ok());
+
+  // f returns an int or what g returns, a bool
+  let mut f = Chunk::new("f".to_string(), 0, Position::new(1, 1));
+  f.code = vec![TRUE, JUMP_IF_FALSE, 0, 7, TAIL_CALL, 0, 1, CONST, 0, 0, RETURN];
+  f.constants = vec![Constant::Integer(1)];
+  let mut g = Chunk::new("g".to_string(), 0, Position::new(2, 1));
+  g.code = vec![TRUE, RETURN];
+  let module = Module { functions: vec![f, g], global_count: 0, init: 0, main: None, text_table: vec![] };
+  match verify(&module) {
+    Ok(..) => assert!(false),
+    Err(errors) => assert!(errors[0].contains("function returns")),
+  }
+}
+
+#[test]
+fn verifier_accepts_straight_line_code() {
+  assert!(verify_code(vec![CONST, 0, 0, STORE_LOCAL, 0, 0, LOAD_LOCAL, 0, 0, STORE_GLOBAL, 0, 0, RETURN_VOID]).is_ok());
+}
+
+#[test]
+fn verifier_rejects_invalid_opcode() {
+  match verify_code(vec![CONST, 0, 0, 250, RETURN_VOID]) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("function 'f' at offset 0003"));
+      assert!(errors[0].contains("invalid opcode 250"));
+    }
+  }
+}
+
+#[test]
+fn verifier_rejects_truncated_operand() {
+  match verify_code(vec![TRUE, POP, CONST, 0]) {
+    Ok(..) => assert!(false),
+    Err(errors) => assert!(errors[0].contains("operand of CONST is truncated")),
+  }
+}
+
+#[test]
+fn verifier_rejects_out_of_range_indices() {
+  match verify_code(vec![CONST, 0, 1, POP, LOAD_LOCAL, 0, 1, POP, LOAD_GLOBAL, 0, 1, POP, CALL, 0, 5, POP, RETURN_VOID]) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(4, errors.len());
+      assert!(errors[0].contains("constant index 1"));
+      assert!(errors[1].contains("local slot 1"));
+      assert!(errors[2].contains("global index 1"));
+      assert!(errors[3].contains("function index 5"));
+    }
+  }
+}
+
+#[test]
+fn verifier_rejects_invalid_text_constant() {
+  match verify(&module_with_code(vec![CONST, 0, 0, POP, RETURN_VOID], vec![Constant::Text(3)], 0)) {
+    Ok(..) => assert!(false),
+    Err(errors) => assert!(errors[0].contains("text 3")),
+  }
+}
+
+#[test]
+fn verifier_rejects_jumps_outside_instructions() {
+  match verify_code(vec![CONST, 0, 0, JUMP, 0, 1, JUMP, 0, 40]) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(2, errors.len());
+      assert!(errors[0].contains("at offset 0003: jump target 0001"));
+      assert!(errors[1].contains("jump target 0040"));
+    }
+  }
+}
+
+#[test]
+fn verifier_rejects_stack_underflow() {
+  match verify_code(vec![TRUE, IADD, POP, RETURN_VOID]) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("at offset 0001: instruction needs 2 value(s), but the stack holds 1"));
+    }
+  }
+}
+
+#[test]
+fn verifier_rejects_inconsistent_stack_height_at_join_point() {
+  // the 'true' branch pushes an extra value before joining
+  match verify_code(vec![TRUE, JUMP_IF_FALSE, 0, 8, CONST, 0, 0, FALSE, RETURN_VOID]) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("at offset 0008"));
+      assert!(errors[0].contains("stack height"));
+    }
+  }
+}
+
+#[test]
+fn verifier_rejects_call_without_enough_arguments() {
+  let mut module = compile_source("fn add(a:int, b:int) : int { return a + b; }\nfn main() { print(add(1, 2)); }");
+  // drop the first argument of the call
+  module.functions[1].code.remove(0);
+  module.functions[1].code.remove(0);
+  module.functions[1].code.remove(0);
+  match verify(&module) {
+    Ok(..) => assert!(false),
+    Err(errors) => assert!(errors[0].contains("instruction needs 2 value(s), but the stack holds 1")),
+  }
+}
+
+#[test]
+fn verifier_rejects_running_past_end_of_code() {
+  match verify_code(vec![TRUE, POP]) {
+    Ok(..) => assert!(false),
+    Err(errors) => assert!(errors[0].contains("at offset 0001: execution runs past the end of the code")),
+  }
+}
+
+#[test]
+fn verifier_rejects_operands_of_wrong_kind() {
+  match verify_code(vec![TRUE, TRUE, IADD, POP, RETURN_VOID]) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("at offset 0002: IADD expects int, but the stack holds bool"));
+    }
+  }
+}
+
+#[test]
+fn verifier_rejects_condition_of_wrong_kind() {
+  match verify_code(vec![CONST, 0, 0, JUMP_IF_FALSE, 0, 6, RETURN_VOID]) {
+    Ok(..) => assert!(false),
+    Err(errors) => assert!(errors[0].contains("JUMP_IF_FALSE expects bool, but the stack holds int")),
+  }
+}
+
+#[test]
+fn verifier_rejects_local_read_before_it_is_stored() {
+  match verify_code(vec![LOAD_LOCAL, 0, 0, POP, RETURN_VOID]) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("at offset 0000: local slot 0 may be read before it is stored"));
+    }
+  }
+
+  // stored on one path only
+  match verify_code(vec![TRUE, JUMP_IF_FALSE, 0, 10, CONST, 0, 0, STORE_LOCAL, 0, 0, LOAD_LOCAL, 0, 0, POP, RETURN_VOID]) {
+    Ok(..) => assert!(false),
+    Err(errors) => assert!(errors[0].contains("at offset 0010: local slot 0 may be read before it is stored")),
+  }
+}
+
+#[test]
+fn verifier_rejects_global_read_before_its_initializer() {
+  match verify_code(vec![LOAD_GLOBAL, 0, 0, STORE_GLOBAL, 0, 0, RETURN_VOID]) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("at offset 0000: global 0 is read before it is initialized"));
+    }
+  }
+
+  // the initializer of a calls a function reading b
+  let module = compile_source("let a:int = f();\nlet b:int = 2;\nfn f() : int { return b; }\nfn main() { print(a); }");
+  match verify(&module) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("function '<globals>'"));
+      assert!(errors[0].contains("call of 'f' reads global 1 before it is initialized"));
+    }
+  }
+}
+
+#[test]
+fn verifier_rejects_global_never_initialized() {
+  let mut init = Chunk::new("init".to_string(), 0, Position::new(1, 1));
+  init.code = vec![RETURN_VOID];
+  let mut main = Chunk::new("main".to_string(), 0, Position::new(1, 1));
+  main.code = vec![LOAD_GLOBAL, 0, 0, PRINT, POP, RETURN_VOID];
+  let module = Module {
+    functions: vec![main, init],
+    global_count: 1,
+    init: 1,
+    main: Some(0),
+    text_table: vec![],
+  };
+  match verify(&module) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("function 'main' at offset 0000: global 0 is read before it is initialized"));
+    }
+  }
+}
+
+#[test]
+fn verifier_rejects_arguments_of_different_kinds() {
+  let mut module = compile_source("fn twice(a:int) : int { return a + a; }\nfn main() { print(twice(1)); }");
+  // pass true instead of the constant
+  module.functions[1].code.remove(0);
+  module.functions[1].code.remove(0);
+  module.functions[1].code[0] = TRUE;
+  match verify(&module) {
+    Ok(..) => assert!(false),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert!(errors[0].contains("function 'twice' at offset 0006: IADD expects int, but the stack holds bool"));
+    }
+  }
+}
@@ -0,0 +1,159 @@
+extern crate compiler;
+
+use std::cmp;
+use std::i64;
+use std::io::MemWriter;
+use std::time::Duration;
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::ast::Program;
+use compiler::bytecode::compiler::compile;
+use compiler::vm::execute;
+use compiler::interpreter;
+
+fn checked_program(source: &str) -> Program {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  program
+}
+
+fn run_source(source: &str) -> Result<String, String> {
+  let module = compile(&checked_program(source)).unwrap();
+  let mut output = MemWriter::new();
+  try!(execute(&module, &mut output));
+  Ok(String::from_utf8(output.get_ref().to_vec()).unwrap())
+}
+
+fn interpret_source(source: &str) -> Result<String, String> {
+  let program = checked_program(source);
+  let mut output = MemWriter::new();
+  try!(interpreter::run(&program, &mut output));
+  Ok(String::from_utf8(output.get_ref().to_vec()).unwrap())
+}
+
+#[test]
+fn vm_prints_every_literal_type() {
+  match run_source("fn main() {\n print(42);\n print(2.5f);\n print(0.5);\n print(true);\n print(\"hello\"); }") {
+    Ok(output) => assert_eq!("42\n2.5\n0.5\ntrue\nhello\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn vm_evaluates_typed_arithmetic_and_comparisons() {
+  match run_source("fn main() { print(1 + 2 * 3 - 8 / 4); print(1.5f * 2.0f); print(7 / 2.0); print(2 <= 2); print(1.0 > 2.0); print(\"a\" + \"b\" == \"ab\"); print(true == false); }") {
+    Ok(output) => assert_eq!("5\n3\n3.5\ntrue\nfalse\ntrue\nfalse\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn vm_applies_explicit_conversions() {
+  match run_source("fn main() { print(7.9 as int); print(3 as float / 2.0f); }") {
+    Ok(output) => assert_eq!("7\n1.5\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn vm_executes_control_flow() {
+  let source = "fn classify(a:int) : string {\n if (a < 0) { return \"negative\"; } elif (a == 0) { return \"zero\"; } else { return \"positive\"; } }\nfn main() { for (let i:int = -1; i <= 1; i = i + 1) { print(classify(i)); } }";
+  match run_source(source) {
+    Ok(output) => assert_eq!("negative\nzero\npositive\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn vm_keeps_shadowed_locals_separate() {
+  match run_source("fn main() { let a:int = 1; { let a:int = 2; print(a); a = 3; } print(a); }") {
+    Ok(output) => assert_eq!("2\n1\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn vm_initializes_and_updates_globals() {
+  match run_source("const BASE:int = 10;\nlet counter:int = BASE * 2;\nfn bump() { counter = counter + 1; }\nfn main() { bump(); bump(); print(counter); }") {
+    Ok(output) => assert_eq!("22\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn vm_supports_recursion() {
+  match run_source("fn fib(n:int) : int { if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }\nfn main() { print(fib(20)); }") {
+    Ok(output) => assert_eq!("6765\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn vm_reports_division_by_zero_with_position() {
+  match run_source("fn main() {\n let a:int = 0;\n print(10 / a); }") {
+    Ok(..) => assert!(false),
+    Err(error) => {
+      assert!(error.contains("3:11"));
+      assert!(error.contains("Division by zero"));
+    }
+  }
+}
+
+#[test]
+fn vm_reports_too_deep_recursion() {
+  match run_source("fn forever(a:int) { forever(a + 1); }\nfn main() { forever(0); }") {
+    Ok(..) => assert!(false),
+    Err(error) => assert!(error.contains("1:21")),
+  }
+}
+
+#[test]
+fn vm_runs_deep_tail_recursion_in_constant_frames() {
+  match run_source("fn count(n:int, total:int) : int { if (n == 0) { return total; } return count(n - 1, total + n); }\n\
+    fn even(n:int) : bool { if (n == 0) { return true; } return odd(n - 1); }\n\
+    fn odd(n:int) : bool { if (n == 0) { return false; } return even(n - 1); }\n\
+    fn main() { print(count(100000, 0)); print(even(100001)); }") {
+    Ok(output) => assert_eq!("705082704\nfalse\n", output.as_slice()),
+    Err(..) => assert!(false),
+  }
+}
+
+#[test]
+fn vm_output_matches_interpreter() {
+  let source = "let total:double = 0.0;\nfn add(value:double) { total = total + value; }\nfn main() {\n for (let i:int = 0; i < 5; i = i + 1) { if (i == 2) { add(0.5); } else { add(i); } }\n print(total);\n print(\"done\"); }";
+  assert_eq!(interpret_source(source), run_source(source));
+}
+
+#[test]
+fn vm_keeps_negative_zero_constants_apart() {
+  let source = "fn main() { print(1.0 / 0.0); print(1.0 / -0.0); print(1.0f / 0.0f); print(1.0f / -0.0f); }";
+  assert_eq!(Ok("inf\n-inf\ninf\n-inf\n".to_string()), run_source(source));
+  assert_eq!(interpret_source(source), run_source(source));
+}
+
+// the best of a few runs of each is compared, so a slow run does not decide
+#[test]
+fn vm_is_several_times_faster_than_interpreter() {
+  let program = checked_program("fn fib(n:int) : int { if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }\n\
+    fn main() { print(fib(20)); }");
+  let module = compile(&program).unwrap();
+  let mut interpreter_time = i64::MAX;
+  let mut vm_time = i64::MAX;
+  for _ in range(0, 5) {
+    interpreter_time = cmp::min(interpreter_time, nanoseconds(|| {
+      assert!(interpreter::run(&program, &mut MemWriter::new()).is_ok());
+    }));
+    vm_time = cmp::min(vm_time, nanoseconds(|| {
+      assert!(execute(&module, &mut MemWriter::new()).is_ok());
+    }));
+  }
+  assert!(vm_time * 3 <= interpreter_time);
+}
+
+fn nanoseconds<F: FnOnce()>(function: F) -> i64 {
+  Duration::span(function).num_nanoseconds().unwrap()
+}
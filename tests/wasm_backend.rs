@@ -0,0 +1,204 @@
+extern crate compiler;
+
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::backend::wasm::*;
+use compiler::backend::wasm::compiler::compile;
+use compiler::backend::wasm::text::print;
+use compiler::backend::wasm::binary::encode;
+use compiler::backend::wasm::validator::validate;
+
+fn compile_source(source: &str) -> Module {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  compile(&program)
+}
+
+// module with a single function of the given type and body
+fn single_function_module(parameters: Vec<ValueType>, results: Vec<ValueType>,
+                          body: Vec<Instruction>) -> Module {
+  Module {
+    types: vec![FunctionType { parameters: parameters, results: results }],
+    imports: vec![],
+    functions: vec![Function { name: "f".to_string(), type_index: 0, locals: vec![], body: body }],
+    globals: vec![],
+    memory_pages: 1,
+    exports: vec![],
+    data: vec![],
+  }
+}
+
+fn assert_invalid(bytes: &[u8], expected: &str) {
+  match validate(bytes) {
+    Ok(()) => panic!("Validation succeeded, expected '{}'", expected),
+    Err(errors) => {
+      assert_eq!(1, errors.len());
+      assert_eq!(expected, errors[0].as_slice());
+    },
+  }
+}
+
+#[test]
+fn wasm_backend_output_validates() {
+  let sources = [
+    "fn main() { print(42); print(2.5f); print(0.1); print(true); print(\"a\\t\\\"b\\\"\"); }",
+    "fn main() { let a:int = 2147483647; print(a + 1); print(-7 / 2); let b:float = 1.5f; print(b < 2.0f); }",
+    "fn classify(a:int) : string {\n if (a < 0) { return \"negative\"; } elif (a == 0) { return \"zero\"; } else { return \"positive\"; } }\nfn main() { for (let i:int = -1; i <= 1; i = i + 1) { print(classify(i)); } for (;;) { return; } }",
+    "const BASE:int = 10;\nlet calls:int = BASE - 10;\nfn fib(n:int) : int { calls = calls + 1; if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }\nfn main() { print(fib(15)); print(calls); }",
+    "fn main() { let s:string = \"ab\" + \"cd\"; print(s == \"abcd\"); print(7 / 2.0); print(7.9 as int); print(1 as float / 4); }",
+    "fn twice(x:double) : double { return x * 2.0; }\nfn main() { twice(1.0); let a:int = 1; { let a:int = a + 1; print(a); } }",
+  ];
+  for source in sources.iter() {
+    assert_eq!(Ok(()), validate(encode(&compile_source(*source)).as_slice()));
+  }
+}
+
+#[test]
+fn wasm_backend_maps_types_to_value_types() {
+  let text = print(&compile_source(
+    "fn add(a:int, b:float, c:double, d:bool) : double { if (d) { return c; } return a as double + b as double; }\nfn main() { print(add(1, 2.5f, 0.5, false)); }"));
+  assert!(text.contains("(func $fn_add (type 8) (param i32 f32 f64 i32) (result f64)\n    local.get 3\n    if\n      local.get 2\n      return\n    end\n"));
+  assert!(text.contains("    local.get 0\n    f64.convert_i32_s\n    local.get 1\n    f64.promote_f32\n    f64.add\n"));
+  assert!(text.contains("    f32.const 0x1.4p+1 (;2.5;)\n    f64.const 0x1p-1 (;0.5;)\n"));
+  assert!(text.contains("  (import \"env\" \"print_double\" (func $print_double (type 2)))\n"));
+  assert!(text.contains("  (export \"main\" (func $main))\n  (export \"memory\" (memory 0))\n"));
+}
+
+#[test]
+fn wasm_backend_translates_for_into_block_and_loop() {
+  let text = print(&compile_source("fn main() { for (let i:int = 0; i < 3; i = i + 1) { print(i); } }"));
+  assert!(text.contains("    block\n      loop\n        local.get 0\n        i32.const 3\n        i32.lt_s\n        i32.eqz\n        br_if 1\n"));
+  assert!(text.contains("        local.set 0\n        br 0\n      end\n    end\n"));
+}
+
+#[test]
+fn wasm_backend_stores_string_literals_in_data_segment() {
+  let module = compile_source("fn main() { print(\"hi\"); }");
+  assert_eq!(1, module.data.len());
+  assert_eq!(vec![2, 0, 0, 0, b'h', b'i'], module.data[0].bytes);
+  assert!(print(&module).contains("  (data (i32.const 8) \"\\02\\00\\00\\00hi\")\n"));
+}
+
+#[test]
+fn wasm_backend_writes_binary_header() {
+  let bytes = encode(&compile_source("fn main() { }"));
+  let header: &[u8] = &[0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+  assert_eq!(header, bytes.slice_to(8));
+  // the type section comes first
+  assert_eq!(1, bytes[8]);
+}
+
+#[test]
+fn wasm_validator_accepts_well_typed_function() {
+  let module = single_function_module(vec![ValueType::I32], vec![ValueType::F64], vec![
+    Instruction::LocalGet(0),
+    Instruction::If,
+    Instruction::F64Const(1.0),
+    Instruction::Return,
+    Instruction::End,
+    Instruction::Block,
+    Instruction::Loop,
+    Instruction::LocalGet(0),
+    Instruction::BrIf(1),
+    Instruction::Br(0),
+    Instruction::End,
+    Instruction::End,
+    Instruction::F64Const(0.0),
+  ]);
+  assert_eq!(Ok(()), validate(encode(&module).as_slice()));
+}
+
+#[test]
+fn wasm_validator_rejects_bad_magic_number() {
+  let mut bytes = encode(&compile_source("fn main() { }"));
+  bytes[1] = b'b';
+  assert_invalid(bytes.as_slice(), "Invalid WebAssembly module at byte 0: bad magic number");
+}
+
+#[test]
+fn wasm_validator_rejects_truncated_module() {
+  let bytes = encode(&compile_source("fn main() { }"));
+  assert_invalid(bytes.slice_to(10),
+    "Invalid WebAssembly module at byte 8: section 1 has size 46, but only 0 bytes remain");
+  assert_invalid(bytes.slice_to(6),
+    "Truncated WebAssembly module: expected 4 byte(s) of version at byte 4, but only 2 remain");
+}
+
+#[test]
+fn wasm_validator_rejects_operand_type_mismatch() {
+  let module = single_function_module(vec![], vec![ValueType::I32], vec![
+    Instruction::I32Const(1),
+    Instruction::F64Const(2.0),
+    Instruction::Numeric(I32_ADD),
+  ]);
+  assert_invalid(encode(&module).as_slice(),
+    "Validation error in function 0 at byte 40: i32.add: expected operand of type i32, found f64");
+}
+
+#[test]
+fn wasm_validator_rejects_missing_result() {
+  let module = single_function_module(vec![], vec![ValueType::F32], vec![]);
+  assert_invalid(encode(&module).as_slice(),
+    "Validation error in function 0 at byte 29: end: operand stack is empty");
+}
+
+#[test]
+fn wasm_validator_rejects_extra_values_at_end_of_block() {
+  let module = single_function_module(vec![], vec![], vec![
+    Instruction::Block,
+    Instruction::I32Const(1),
+    Instruction::End,
+  ]);
+  assert_invalid(encode(&module).as_slice(),
+    "Validation error in function 0 at byte 32: end: block leaves 1 extra value(s) on the stack");
+}
+
+#[test]
+fn wasm_validator_rejects_branch_out_of_range() {
+  let module = single_function_module(vec![], vec![], vec![
+    Instruction::Block,
+    Instruction::Br(2),
+    Instruction::End,
+  ]);
+  assert_invalid(encode(&module).as_slice(),
+    "Validation error in function 0 at byte 30: br: branch depth 2 exceeds the 2 enclosing block(s)");
+}
+
+#[test]
+fn wasm_validator_rejects_unknown_function_and_local() {
+  let module = single_function_module(vec![ValueType::I32], vec![], vec![
+    Instruction::Call(1),
+  ]);
+  assert_invalid(encode(&module).as_slice(),
+    "Validation error in function 0 at byte 29: call: function 1 does not exist");
+
+  let module = single_function_module(vec![ValueType::I32], vec![], vec![
+    Instruction::LocalGet(1),
+    Instruction::Drop,
+  ]);
+  assert_invalid(encode(&module).as_slice(),
+    "Validation error in function 0 at byte 29: local.get: local 1 does not exist");
+}
+
+#[test]
+fn wasm_validator_rejects_assignment_to_immutable_global() {
+  let mut module = single_function_module(vec![], vec![], vec![
+    Instruction::I32Const(1),
+    Instruction::GlobalSet(0),
+  ]);
+  module.globals.push(Global {
+    name: "g".to_string(),
+    value_type: ValueType::I32,
+    mutable: false,
+    init: Instruction::I32Const(0),
+  });
+  let bytes = encode(&module);
+  match validate(bytes.as_slice()) {
+    Ok(()) => panic!("Validation succeeded"),
+    Err(errors) => assert!(errors[0].as_slice().ends_with("global.set: global 0 is immutable")),
+  }
+}
@@ -0,0 +1,184 @@
+extern crate compiler;
+
+use std::io::TempDir;
+use std::io::process::Command;
+use compiler::ast::Program;
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use compiler::resolver::resolve;
+use compiler::type_checker::check;
+use compiler::ir::lowering::lower;
+use compiler::ir::optimizer::optimize;
+use compiler::ir::optimizer::Level;
+use compiler::backend::x86_64::generate;
+use compiler::backend::x86_64::generate_module;
+
+mod support;
+
+fn checked_program(source: &str) -> Program {
+  let tokens = tokenize(source).unwrap();
+  let mut program = parse(tokens).unwrap();
+  assert!(resolve(&program).is_ok());
+  assert!(check(&mut program).is_ok());
+  program
+}
+
+fn generate_source(source: &str) -> String {
+  generate(&checked_program(source))
+}
+
+fn generate_optimized(source: &str, level: Level) -> String {
+  let mut module = lower(&checked_program(source));
+  optimize(&mut module, level);
+  generate_module(&module)
+}
+
+fn assemble_and_run(source: &str) -> (String, bool) {
+  run_assembly(generate_source(source).as_slice())
+}
+
+// assembles and links the code with the system toolchain and runs it.
+// Returns the output and whether the program succeeded
+fn run_assembly(assembly: &str) -> (String, bool) {
+  let dir = TempDir::new("x86_64_backend").unwrap();
+  let assembly_path = support::write_file(&dir, "program.s", assembly);
+  let executable = dir.path().join("program");
+  support::build(Command::new("cc").arg("-o").arg(&executable).arg(&assembly_path), "cc");
+  support::run(&mut Command::new(&executable), "program")
+}
+
+fn assert_output(source: &str, expected: &str) {
+  support::assert_output(assemble_and_run(source), expected);
+}
+
+// the instructions of a function up to its return
+fn function_body(assembly: &str, name: &str) -> String {
+  let label = format!("\n{}:\n", name);
+  let start = assembly.find_str(label.as_slice()).unwrap();
+  let body = assembly.slice_from(start);
+  body.slice_to(body.find_str("  ret\n").unwrap()).to_string()
+}
+
+#[test]
+fn x86_64_backend_emits_prologue_and_epilogue_for_every_function() {
+  let assembly = generate_source("fn main() { let a:int = 1; print(a); }\nfn helper() { }");
+  assert!(assembly.contains("fn_main:\n  push rbp\n  mov rbp, rsp\n"));
+  assert!(assembly.contains("fn_helper:\n  push rbp\n  mov rbp, rsp\n"));
+  assert!(assembly.contains("rt_init_globals:\n  push rbp\n  mov rbp, rsp\n"));
+  assert!(assembly.contains("  .globl main\nmain:\n"));
+  assert!(assembly.contains("  pop rbp\n  ret\n"));
+}
+
+#[test]
+fn x86_64_backend_passes_arguments_in_registers() {
+  let assembly = generate_source("fn f(a:int, b:double, c:string) { print(c); }\nfn main() { f(1, 2.0, \"c\"); }");
+  assert!(assembly.contains("  mov edi, 1\n"));
+  assert!(assembly.contains("  movsd xmm0, qword ptr [rip + "));
+  assert!(assembly.contains("  lea rsi, [rip + "));
+  assert!(assembly.contains("call fn_f"));
+  // the parameter is not stored in the frame
+  assert!(!function_body(assembly.as_slice(), "fn_f").contains("[rbp"));
+}
+
+#[test]
+fn x86_64_backend_keeps_values_in_registers() {
+  let source = "fn sum(n:int) : int { let total:int = 0; for (let i:int = 0; i < n; i = i + 1) { total = total + i; } return total; }\nfn main() { print(sum(10)); }";
+  let body = function_body(generate_source(source).as_slice(), "fn_sum");
+  assert!(!body.contains("[rbp"));
+  assert!(!body.contains("sub rsp"));
+  assert!(!body.contains("push rbx"));
+  assert_output(source, "45\n");
+}
+
+#[test]
+fn x86_64_backend_keeps_values_live_across_calls_in_callee_saved_registers() {
+  let source = "fn id(x:int) : int { return x; }\nfn main() { let a:int = 40; let b:int = id(2); print(a + b); }";
+  let body = function_body(generate_source(source).as_slice(), "fn_main");
+  assert!(body.contains("fn_main:\n  push rbp\n  mov rbp, rsp\n  push rbx\n"));
+  assert!(body.contains("  pop rbx\n  pop rbp\n"));
+  assert!(!body.contains("[rbp -"));
+  assert_output(source, "42\n");
+}
+
+#[test]
+fn x86_64_backend_spills_values_without_a_free_register() {
+  // no xmm register is preserved across calls
+  let source = "fn id(x:double) : double { return x; }\nfn main() { let a:double = 1.5; let b:double = id(2.0); print(a + b); }";
+  let body = function_body(generate_source(source).as_slice(), "fn_main");
+  assert!(body.contains("  sub rsp, 16\n"));
+  assert!(body.contains("qword ptr [rbp - 8]"));
+  assert_output(source, "3.5\n");
+}
+
+#[test]
+fn x86_64_backend_prints_every_value_type() {
+  assert_output("fn main() { print(42); print(2.5f); print(0.1); print(true); print(\"a\\t\\\"b\\\"\"); }",
+    "42\n2.5\n0.1\ntrue\na\t\"b\"\n");
+}
+
+#[test]
+fn x86_64_backend_wraps_integer_arithmetic() {
+  let source = "fn main() { let a:int = 2147483647; print(a + 1); print(-7 / 2); print(3 * 4 - 5);\n let min:int = -2147483647 - 1; print(min / -1); }";
+  assert_output(source, "-2147483648\n-3\n7\n-2147483648\n");
+}
+
+#[test]
+fn x86_64_backend_computes_with_floats_and_doubles() {
+  let source = "fn main() { let a:float = 1.5f; let b:double = 0.25; print(a * 2.0f - 1.0f); print(b / 2.0 + a); print(a < 2.0f); print(b >= 0.5); print(b == 0.25); }";
+  assert_output(source, "2\n1.625\ntrue\nfalse\ntrue\n");
+}
+
+#[test]
+fn x86_64_backend_translates_control_flow() {
+  let source = "fn classify(a:int) : string {\n if (a < 0) { return \"negative\"; } elif (a == 0) { return \"zero\"; } else { return \"positive\"; } }\nfn main() { for (let i:int = -1; i <= 1; i = i + 1) { print(classify(i)); } for (;;) { return; } }";
+  assert_output(source, "negative\nzero\npositive\n");
+}
+
+#[test]
+fn x86_64_backend_supports_recursion_and_globals() {
+  let source = "const BASE:int = 10;\nlet calls:int = BASE - 10;\nfn fib(n:int) : int { calls = calls + 1; if (n < 2) { return n; } return fib(n - 1) + fib(n - 2); }\nfn main() { print(fib(15)); print(calls); }";
+  assert_output(source, "610\n1973\n");
+}
+
+#[test]
+fn x86_64_backend_passes_extra_arguments_on_the_stack() {
+  let source = "fn sum(a:int, b:double, c:int, d:float, e:int, f:int, g:int, h:int, i:string, k:double, l:double, m:double, n:double, o:double, p:double, q:double, r:double) : double {\n print(a + c + e + f + g + h); print(i); print(d); return b + k + l + m + n + o + p + q + r; }\nfn main() { print(1 + sum(1, 0.5, 2, 1.5f, 3, 4, 5, 6, \"seven\", 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0) as int); }";
+  assert_output(source, "21\nseven\n1.5\n37\n");
+}
+
+#[test]
+fn x86_64_backend_keeps_stack_aligned_for_nested_calls() {
+  let source = "fn half(x:double) : double { print(x); return x / 2.0; }\nfn shout(a:string) : string { return a + \"!\"; }\nfn main() { print(1.0 + (2.0 * half(2.5))); print(\"a\" + (\"b\" + shout(\"c\"))); }";
+  assert_output(source, "2.5\n3.5\nabc!\n");
+}
+
+#[test]
+fn x86_64_backend_handles_strings_and_conversions() {
+  let source = "fn main() { let s:string = \"ab\" + \"cd\"; print(s); print(s == \"abcd\"); print(7 / 2.0); print(7.9 as int); print(1 as float / 4); }";
+  assert_output(source, "abcd\ntrue\n3.5\n7\n0.25\n");
+}
+
+#[test]
+fn x86_64_backend_keeps_outer_variable_visible_in_shadowing_initializer() {
+  assert_output("fn main() { let a:int = 1; { let a:int = a + 1; print(a); } print(a); }", "2\n1\n");
+}
+
+#[test]
+fn x86_64_backend_reports_division_by_zero() {
+  let (output, success) = assemble_and_run("fn main() {\n let a:int = 0;\n print(10 / a); }");
+  assert!(!success);
+  assert_eq!("Runtime error at 3:11: Division by zero\n", output.as_slice());
+}
+
+#[test]
+fn x86_64_backend_translates_optimized_programs() {
+  let source = "fn square(x:int) : int { return x * x; }\nfn count(n:int, total:int) : int { if (n == 0) { return total; } return count(n - 1, total + n); }\nfn main() { print(square(2) + 3); print(count(100000, 0)); }";
+  for level in [Level::O0, Level::O1, Level::O2].iter() {
+    support::assert_output(run_assembly(generate_optimized(source, *level).as_slice()), "7\n705082704\n");
+  }
+
+  let assembly = generate_optimized(source, Level::O2);
+  // the call of square is inlined and folded, the self call of count is a loop
+  assert!(function_body(assembly.as_slice(), "fn_main").contains("  mov edi, 7\n"));
+  assert!(!function_body(assembly.as_slice(), "fn_count").contains("call fn_count"));
+}